@@ -1,6 +1,12 @@
+use std::convert::Infallible;
 use std::fmt;
+use std::str::FromStr;
 use wasm_interface::Interface;
 
+pub mod detect;
+pub mod emscripten;
+pub mod exports;
+
 /// The ABI is a hint to WebAssembly runtimes about what additional imports to insert.
 /// It currently is only used for validation (in the validation subcommand).  The default value is `None`.
 #[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
@@ -24,12 +30,18 @@ impl Abi {
     pub fn is_none(&self) -> bool {
         return self == &Abi::None;
     }
-    pub fn from_str(name: &str) -> Self {
-        match name.to_lowercase().as_ref() {
+}
+
+impl FromStr for Abi {
+    type Err = Infallible;
+
+    /// Unknown names are `Abi::None`
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok(match name.to_lowercase().as_ref() {
             "emscripten" => Abi::Emscripten,
             "wasi" => Abi::Wasi,
             _ => Abi::None,
-        }
+        })
     }
 }
 
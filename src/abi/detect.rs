@@ -0,0 +1,185 @@
+//! Classifying modules by the ABI that their imports need
+
+use crate::abi::emscripten;
+use crate::abi::Abi;
+use std::collections::BTreeSet;
+use std::fmt;
+use wasmparser::{ModuleReader, SectionCode};
+
+/// The import namespaces of the versions of WASI
+pub const WASI_NAMESPACES: &[&str] = &["wasi_unstable", "wasi_snapshot_preview1"];
+
+/// How sure the detection is about the ABI of a module
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Confidence {
+    High,
+    Low,
+}
+
+impl fmt::Display for Confidence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Confidence::High => write!(f, "high"),
+            Confidence::Low => write!(f, "low"),
+        }
+    }
+}
+
+/// The ABI detected for a module, with notes explaining the decision
+#[derive(Clone, Debug, PartialEq)]
+pub struct AbiDetection {
+    pub abi: Abi,
+    pub confidence: Confidence,
+    pub notes: Vec<String>,
+}
+
+/// Classify a module as WASI, Emscripten or none from the namespaces of its imports
+pub fn detect_abi(wasm: &[u8]) -> Result<AbiDetection, String> {
+    let mut namespaces = BTreeSet::new();
+    let mut exports_start = false;
+    let mut reader = ModuleReader::new(wasm).map_err(|e| e.message().to_string())?;
+    while !reader.eof() {
+        let section = reader.read().map_err(|e| e.message().to_string())?;
+        match section.code {
+            SectionCode::Import => {
+                let imports = section
+                    .get_import_section_reader()
+                    .map_err(|e| e.message().to_string())?;
+                for import in imports {
+                    let import = import.map_err(|e| e.message().to_string())?;
+                    namespaces.insert(import.module.to_string());
+                }
+            }
+            SectionCode::Export => {
+                let exports = section
+                    .get_export_section_reader()
+                    .map_err(|e| e.message().to_string())?;
+                for export in exports {
+                    let export = export.map_err(|e| e.message().to_string())?;
+                    if export.field == "_start" {
+                        exports_start = true;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let emscripten_module = emscripten::inspect_module(wasm)?;
+    Ok(classify(&namespaces, exports_start, emscripten_module))
+}
+
+fn classify(
+    namespaces: &BTreeSet<String>,
+    exports_start: bool,
+    emscripten_module: Option<emscripten::EmscriptenModule>,
+) -> AbiDetection {
+    let mut notes = vec![];
+    let wasi_namespaces: Vec<&str> = WASI_NAMESPACES
+        .iter()
+        .cloned()
+        .filter(|namespace| namespaces.contains(*namespace))
+        .collect();
+    let unknown_namespaces: Vec<&str> = namespaces
+        .iter()
+        .map(String::as_str)
+        .filter(|namespace| !WASI_NAMESPACES.contains(namespace) && *namespace != "env")
+        .filter(|namespace| !emscripten::EMSCRIPTEN_IMPORT_MODULES.contains(namespace))
+        .collect();
+    if !unknown_namespaces.is_empty() {
+        notes.push(format!(
+            "imports from {} which no known ABI provides",
+            unknown_namespaces.join(", ")
+        ));
+    }
+
+    let (abi, confidence) = match (wasi_namespaces.is_empty(), emscripten_module) {
+        (false, emscripten_module) => {
+            notes.push(format!("imports from {}", wasi_namespaces.join(", ")));
+            if wasi_namespaces.contains(&"wasi_unstable") {
+                notes.push("uses the older `wasi_unstable` version of WASI".to_owned());
+            }
+            if !exports_start {
+                notes.push("does not export `_start`, so it can't be run as a command".to_owned());
+            }
+            match emscripten_module {
+                Some(emscripten_module) if !emscripten_module.runs_standalone() => {
+                    notes.push(format!(
+                        "was generated by Emscripten and needs its JavaScript glue code for {}",
+                        emscripten_module.js_glue_imports.join(", ")
+                    ));
+                    (Abi::Emscripten, Confidence::Low)
+                }
+                Some(_) => {
+                    notes.push("was generated by Emscripten as a standalone module".to_owned());
+                    (Abi::Wasi, Confidence::High)
+                }
+                None => (Abi::Wasi, Confidence::High),
+            }
+        }
+        (true, Some(emscripten_module)) => {
+            notes.push("imports functions provided by Emscripten's runtime".to_owned());
+            if let Some(version) = emscripten_module.version.as_ref() {
+                notes.push(format!("records version {} of the Emscripten ABI", version));
+            }
+            if !emscripten_module.runs_standalone() {
+                notes.push(format!(
+                    "needs Emscripten's JavaScript glue code for {}",
+                    emscripten_module.js_glue_imports.join(", ")
+                ));
+            }
+            (Abi::Emscripten, Confidence::High)
+        }
+        (true, None) => {
+            if namespaces.is_empty() {
+                notes.push("has no imports".to_owned());
+                (Abi::None, Confidence::High)
+            } else {
+                notes.push("does not import anything from WASI or Emscripten".to_owned());
+                (Abi::None, Confidence::Low)
+            }
+        }
+    };
+    AbiDetection {
+        abi,
+        confidence,
+        notes,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn namespaces(names: &[&str]) -> BTreeSet<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn classify_modules() {
+        let wasi = classify(&namespaces(&["wasi_snapshot_preview1"]), true, None);
+        assert_eq!(wasi.abi, Abi::Wasi);
+        assert_eq!(wasi.confidence, Confidence::High);
+
+        let emscripten = classify(
+            &namespaces(&["env", "global"]),
+            false,
+            Some(emscripten::EmscriptenModule::default()),
+        );
+        assert_eq!(emscripten.abi, Abi::Emscripten);
+        assert_eq!(emscripten.confidence, Confidence::High);
+
+        let none = classify(&namespaces(&[]), false, None);
+        assert_eq!(none.abi, Abi::None);
+        assert_eq!(none.confidence, Confidence::High);
+
+        let unknown = classify(&namespaces(&["env", "custom"]), false, None);
+        assert_eq!(unknown.abi, Abi::None);
+        assert_eq!(unknown.confidence, Confidence::Low);
+    }
+
+    #[test]
+    fn detect_empty_module() {
+        let wasm = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+        assert_eq!(detect_abi(&wasm).unwrap().abi, Abi::None);
+    }
+}
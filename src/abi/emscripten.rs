@@ -0,0 +1,155 @@
+//! Recognizing modules generated by Emscripten from their imports
+
+use wasmparser::{BinaryReader, ModuleReader, SectionCode};
+
+/// The import modules that only Emscripten's runtime provides
+pub const EMSCRIPTEN_IMPORT_MODULES: &[&str] = &["global", "asm2wasm"];
+
+/// Prefixes of the `env` imports that Emscripten's runtime provides
+const EMSCRIPTEN_IMPORT_PREFIXES: &[&str] = &[
+    "emscripten_",
+    "_emscripten_",
+    "__syscall",
+    "___syscall",
+    "___setErrNo",
+    "abortStackOverflow",
+    "nullFunc_",
+    "invoke_",
+    "_embind_",
+];
+
+/// `env` globals that Emscripten's runtime provides
+const EMSCRIPTEN_IMPORT_GLOBALS: &[&str] = &[
+    "DYNAMICTOP_PTR",
+    "STACKTOP",
+    "STACK_MAX",
+    "tempDoublePtr",
+    "__memory_base",
+    "__table_base",
+];
+
+/// Prefixes of the imports that are implemented in the JavaScript glue code generated by
+/// Emscripten. Modules using these can only run in a JavaScript host.
+const JS_GLUE_IMPORT_PREFIXES: &[&str] = &[
+    "invoke_",
+    "emscripten_asm_const",
+    "emscripten_run_script",
+    "_embind_",
+    "_emval_",
+    "emscripten_set_",
+    "emscripten_webgl_",
+];
+
+/// The key in the `interfaces` of a module that records the version of the Emscripten ABI
+pub const EMSCRIPTEN_INTERFACE_NAME: &str = "emscripten";
+
+/// The custom section where older versions of Emscripten record the version of their ABI
+const EMSCRIPTEN_METADATA_SECTION: &str = "emscripten_metadata";
+
+/// What was found in a module generated by Emscripten
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EmscriptenModule {
+    /// The version of the Emscripten ABI, when the module records it
+    pub version: Option<String>,
+    /// The imports that need Emscripten's JavaScript glue code
+    pub js_glue_imports: Vec<String>,
+}
+
+impl EmscriptenModule {
+    /// Modules that don't need the JavaScript glue code can run in Wasmer
+    pub fn runs_standalone(&self) -> bool {
+        self.js_glue_imports.is_empty()
+    }
+}
+
+/// Check if an import is provided by Emscripten's runtime
+pub fn is_emscripten_import(module: &str, field: &str) -> bool {
+    if EMSCRIPTEN_IMPORT_MODULES.contains(&module) {
+        return true;
+    }
+    module == "env"
+        && (EMSCRIPTEN_IMPORT_GLOBALS.contains(&field)
+            || EMSCRIPTEN_IMPORT_PREFIXES
+                .iter()
+                .any(|prefix| field.starts_with(prefix)))
+}
+
+/// Check if an import is implemented by Emscripten's JavaScript glue code
+pub fn is_js_glue_import(module: &str, field: &str) -> bool {
+    module == "env"
+        && JS_GLUE_IMPORT_PREFIXES
+            .iter()
+            .any(|prefix| field.starts_with(prefix))
+}
+
+/// Inspect a module, returning `None` if it was not generated by Emscripten
+pub fn inspect_module(wasm: &[u8]) -> Result<Option<EmscriptenModule>, String> {
+    let mut reader = ModuleReader::new(wasm).map_err(|e| e.message().to_string())?;
+    let mut is_emscripten = false;
+    let mut emscripten_module = EmscriptenModule::default();
+    while !reader.eof() {
+        let section = reader.read().map_err(|e| e.message().to_string())?;
+        match section.code {
+            SectionCode::Import => {
+                let imports = section
+                    .get_import_section_reader()
+                    .map_err(|e| e.message().to_string())?;
+                for import in imports {
+                    let import = import.map_err(|e| e.message().to_string())?;
+                    if is_emscripten_import(import.module, import.field) {
+                        is_emscripten = true;
+                    }
+                    if is_js_glue_import(import.module, import.field) {
+                        emscripten_module
+                            .js_glue_imports
+                            .push(format!("{}.{}", import.module, import.field));
+                    }
+                }
+            }
+            SectionCode::Custom { name, .. } if name == EMSCRIPTEN_METADATA_SECTION => {
+                is_emscripten = true;
+                emscripten_module.version = read_metadata_version(section.get_binary_reader());
+            }
+            _ => {}
+        }
+    }
+    Ok(if is_emscripten {
+        Some(emscripten_module)
+    } else {
+        None
+    })
+}
+
+/// The metadata starts with its own version followed by the version of the ABI
+fn read_metadata_version(mut reader: BinaryReader) -> Option<String> {
+    let _metadata_major = reader.read_var_u32().ok()?;
+    let _metadata_minor = reader.read_var_u32().ok()?;
+    let abi_major = reader.read_var_u32().ok()?;
+    let abi_minor = reader.read_var_u32().ok()?;
+    Some(format!("{}.{}.0", abi_major, abi_minor))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn recognize_emscripten_imports() {
+        assert!(is_emscripten_import("env", "___syscall140"));
+        assert!(is_emscripten_import("env", "DYNAMICTOP_PTR"));
+        assert!(is_emscripten_import("global", "NaN"));
+        assert!(!is_emscripten_import("env", "abort"));
+        assert!(!is_emscripten_import("wasi_unstable", "fd_write"));
+
+        assert!(is_js_glue_import("env", "invoke_vii"));
+        assert!(is_js_glue_import("env", "emscripten_asm_const_iii"));
+        assert!(!is_js_glue_import("env", "___syscall140"));
+    }
+
+    #[test]
+    fn modules_without_emscripten_imports() {
+        // an empty module
+        let wasm = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+        assert_eq!(inspect_module(&wasm), Ok(None));
+    }
+}
@@ -0,0 +1,307 @@
+//! Listing the exports and imports of a module, with the WASI syscalls it imports annotated
+
+use crate::abi::detect::WASI_NAMESPACES;
+use std::collections::BTreeMap;
+use std::fmt;
+use wasmparser::{ExternalKind, FuncType, ImportSectionEntryType, ModuleReader, SectionCode, Type};
+
+/// What the syscalls of WASI do, the same in `wasi_unstable` and `wasi_snapshot_preview1`
+const WASI_SYSCALLS: &[(&str, &str)] = &[
+    ("args_get", "reads the command line arguments"),
+    (
+        "args_sizes_get",
+        "reads the size of the command line arguments",
+    ),
+    ("environ_get", "reads the environment variables"),
+    (
+        "environ_sizes_get",
+        "reads the size of the environment variables",
+    ),
+    ("clock_res_get", "reads the resolution of a clock"),
+    ("clock_time_get", "reads the time of a clock"),
+    ("fd_advise", "advises on the use of a file"),
+    ("fd_allocate", "allocates space in a file"),
+    ("fd_close", "closes a file descriptor"),
+    ("fd_datasync", "syncs the data of a file to disk"),
+    (
+        "fd_fdstat_get",
+        "reads the flags and rights of a file descriptor",
+    ),
+    (
+        "fd_fdstat_set_flags",
+        "changes the flags of a file descriptor",
+    ),
+    ("fd_fdstat_set_rights", "drops rights of a file descriptor"),
+    ("fd_filestat_get", "reads the metadata of a file"),
+    ("fd_filestat_set_size", "truncates or extends a file"),
+    ("fd_filestat_set_times", "changes the timestamps of a file"),
+    ("fd_pread", "reads a file at an offset"),
+    ("fd_prestat_get", "lists the preopened directories"),
+    (
+        "fd_prestat_dir_name",
+        "reads the name of a preopened directory",
+    ),
+    ("fd_pwrite", "writes a file at an offset"),
+    ("fd_read", "reads from a file descriptor"),
+    ("fd_readdir", "lists a directory"),
+    ("fd_renumber", "renumbers a file descriptor"),
+    ("fd_seek", "moves the offset of a file descriptor"),
+    ("fd_sync", "syncs a file to disk"),
+    ("fd_tell", "reads the offset of a file descriptor"),
+    ("fd_write", "writes to a file descriptor"),
+    ("path_create_directory", "creates a directory"),
+    ("path_filestat_get", "reads the metadata of a path"),
+    (
+        "path_filestat_set_times",
+        "changes the timestamps of a path",
+    ),
+    ("path_link", "creates a hard link"),
+    ("path_open", "opens a file or directory"),
+    ("path_readlink", "reads a symlink"),
+    ("path_remove_directory", "removes a directory"),
+    ("path_rename", "renames a file or directory"),
+    ("path_symlink", "creates a symlink"),
+    ("path_unlink_file", "removes a file"),
+    ("poll_oneoff", "waits for clocks and file descriptors"),
+    ("proc_exit", "exits the process"),
+    ("proc_raise", "sends a signal to the process"),
+    ("sched_yield", "yields the CPU"),
+    ("random_get", "reads random bytes"),
+    ("sock_accept", "accepts a connection on a socket"),
+    ("sock_recv", "receives from a socket"),
+    ("sock_send", "sends to a socket"),
+    ("sock_shutdown", "shuts down a socket"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemKind {
+    Func,
+    Table,
+    Memory,
+    Global,
+}
+
+impl fmt::Display for ItemKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ItemKind::Func => write!(f, "func"),
+            ItemKind::Table => write!(f, "table"),
+            ItemKind::Memory => write!(f, "memory"),
+            ItemKind::Global => write!(f, "global"),
+        }
+    }
+}
+
+/// An export or import of a module
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Item {
+    pub name: String,
+    pub kind: ItemKind,
+    /// The signature of functions, like `(i32, i32) -> i32`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// What an imported WASI syscall does, or a note that WASI has no such syscall
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wasi: Option<String>,
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {}{}",
+            self.kind,
+            self.name,
+            self.signature.as_deref().unwrap_or_default()
+        )
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct ModuleItems {
+    pub exports: Vec<Item>,
+    /// The imports grouped by the namespace they are imported from
+    pub imports: BTreeMap<String, Vec<Item>>,
+}
+
+impl ModuleItems {
+    pub fn import_count(&self) -> usize {
+        self.imports.values().map(Vec::len).sum()
+    }
+}
+
+/// Whether an import namespace is one of the versions of WASI
+pub fn is_wasi_namespace(namespace: &str) -> bool {
+    WASI_NAMESPACES.contains(&namespace)
+}
+
+fn wasi_annotation(name: &str) -> String {
+    match WASI_SYSCALLS.iter().find(|(syscall, _)| *syscall == name) {
+        Some((_, description)) => description.to_string(),
+        None => "not a WASI syscall, no WASI runtime provides it".to_string(),
+    }
+}
+
+fn type_name(ty: &Type) -> &'static str {
+    match ty {
+        Type::I32 => "i32",
+        Type::I64 => "i64",
+        Type::F32 => "f32",
+        Type::F64 => "f64",
+        Type::V128 => "v128",
+        Type::AnyFunc => "funcref",
+        Type::AnyRef | Type::NullRef => "anyref",
+        _ => "?",
+    }
+}
+
+fn signature(func_type: Option<&FuncType>) -> Option<String> {
+    let func_type = func_type?;
+    let join = |types: &[Type]| types.iter().map(type_name).collect::<Vec<_>>().join(", ");
+    Some(match func_type.returns.len() {
+        0 => format!("({})", join(&func_type.params)),
+        1 => format!(
+            "({}) -> {}",
+            join(&func_type.params),
+            join(&func_type.returns)
+        ),
+        _ => format!(
+            "({}) -> ({})",
+            join(&func_type.params),
+            join(&func_type.returns)
+        ),
+    })
+}
+
+/// The exports and imports of a module, in the order the module declares them
+pub fn list_items(wasm: &[u8]) -> Result<ModuleItems, String> {
+    let message = |e: wasmparser::BinaryReaderError| e.message().to_string();
+    let mut types: Vec<FuncType> = vec![];
+    // the type of every function, imported ones first as in the function index space
+    let mut function_types: Vec<u32> = vec![];
+    let mut exports = vec![];
+    let mut items = ModuleItems::default();
+    let mut reader = ModuleReader::new(wasm).map_err(message)?;
+    while !reader.eof() {
+        let section = reader.read().map_err(message)?;
+        match section.code {
+            SectionCode::Type => {
+                for func_type in section.get_type_section_reader().map_err(message)? {
+                    types.push(func_type.map_err(message)?);
+                }
+            }
+            SectionCode::Import => {
+                for import in section.get_import_section_reader().map_err(message)? {
+                    let import = import.map_err(message)?;
+                    let (kind, signature) = match import.ty {
+                        ImportSectionEntryType::Function(type_index) => {
+                            function_types.push(type_index);
+                            (ItemKind::Func, signature(types.get(type_index as usize)))
+                        }
+                        ImportSectionEntryType::Table(_) => (ItemKind::Table, None),
+                        ImportSectionEntryType::Memory(_) => (ItemKind::Memory, None),
+                        ImportSectionEntryType::Global(_) => (ItemKind::Global, None),
+                    };
+                    let wasi = if is_wasi_namespace(import.module) && kind == ItemKind::Func {
+                        Some(wasi_annotation(import.field))
+                    } else {
+                        None
+                    };
+                    items
+                        .imports
+                        .entry(import.module.to_string())
+                        .or_default()
+                        .push(Item {
+                            name: import.field.to_string(),
+                            kind,
+                            signature,
+                            wasi,
+                        });
+                }
+            }
+            SectionCode::Function => {
+                for type_index in section.get_function_section_reader().map_err(message)? {
+                    function_types.push(type_index.map_err(message)?);
+                }
+            }
+            SectionCode::Export => {
+                for export in section.get_export_section_reader().map_err(message)? {
+                    let export = export.map_err(message)?;
+                    exports.push((export.field.to_string(), export.kind, export.index));
+                }
+            }
+            _ => {}
+        }
+    }
+    // the function section comes before the export section, so all the types are known now
+    for (name, kind, index) in exports {
+        let (kind, signature) = match kind {
+            ExternalKind::Function => (
+                ItemKind::Func,
+                function_types
+                    .get(index as usize)
+                    .and_then(|type_index| signature(types.get(*type_index as usize))),
+            ),
+            ExternalKind::Table => (ItemKind::Table, None),
+            ExternalKind::Memory => (ItemKind::Memory, None),
+            ExternalKind::Global => (ItemKind::Global, None),
+        };
+        items.exports.push(Item {
+            name,
+            kind,
+            signature,
+            wasi: None,
+        });
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exports_and_imports_are_listed() {
+        #[rustfmt::skip]
+        let wasm = [
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+            // types: (i32, i32, i32, i32) -> i32 and () -> ()
+            0x01, 0x0c, 0x02, 0x60, 0x04, 0x7f, 0x7f, 0x7f, 0x7f, 0x01, 0x7f, 0x60, 0x00, 0x00,
+            // imports: wasi_unstable.fd_write, wasi_unstable.fd_wrote and env.memory
+            0x02, 0x41, 0x03,
+            0x0d, b'w', b'a', b's', b'i', b'_', b'u', b'n', b's', b't', b'a', b'b', b'l', b'e',
+            0x08, b'f', b'd', b'_', b'w', b'r', b'i', b't', b'e', 0x00, 0x00,
+            0x0d, b'w', b'a', b's', b'i', b'_', b'u', b'n', b's', b't', b'a', b'b', b'l', b'e',
+            0x08, b'f', b'd', b'_', b'w', b'r', b'o', b't', b'e', 0x00, 0x01,
+            0x03, b'e', b'n', b'v', 0x06, b'm', b'e', b'm', b'o', b'r', b'y', 0x02, 0x00, 0x01,
+            // functions: one of type () -> ()
+            0x03, 0x02, 0x01, 0x01,
+            // exports: _start, the function after the two imported ones
+            0x07, 0x0a, 0x01, 0x06, b'_', b's', b't', b'a', b'r', b't', 0x00, 0x02,
+            // code
+            0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b,
+        ];
+        let items = list_items(&wasm).unwrap();
+        assert_eq!(items.exports.len(), 1);
+        assert_eq!(items.exports[0].to_string(), "func _start()");
+        assert_eq!(items.import_count(), 3);
+
+        let wasi = &items.imports["wasi_unstable"];
+        assert_eq!(
+            wasi[0].to_string(),
+            "func fd_write(i32, i32, i32, i32) -> i32"
+        );
+        assert_eq!(wasi[0].wasi.as_deref(), Some("writes to a file descriptor"));
+        assert!(wasi[1]
+            .wasi
+            .as_deref()
+            .unwrap()
+            .starts_with("not a WASI syscall"));
+        let env = &items.imports["env"];
+        assert_eq!(env[0].kind, ItemKind::Memory);
+        assert_eq!(env[0].wasi, None);
+
+        assert!(list_items(b"\0asm").is_err());
+    }
+}
@@ -0,0 +1,181 @@
+//! Aliases for long commands, defined in the `[alias]` section of the manifest of the project or
+//! of the config. An alias given as the subcommand is replaced by the arguments it stands for
+//! before the command line is parsed, so `t = "run test -- --verbose"` makes `wapm t` run
+//! `wapm run test -- --verbose`. Aliases can refer to other aliases but never shadow the
+//! subcommands of wapm.
+
+use crate::config::Config;
+use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alias {
+    pub command: String,
+    /// Where the alias is defined: the manifest or the config
+    pub source: &'static str,
+}
+
+#[derive(Debug, Fail, PartialEq)]
+pub enum AliasError {
+    #[fail(display = "The aliases refer to each other in a cycle: {}", _0)]
+    Cycle(String),
+    #[fail(display = "The alias `{}` has an unterminated quote", _0)]
+    UnterminatedQuote(String),
+    #[fail(display = "The alias `{}` is empty", _0)]
+    Empty(String),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Aliases {
+    aliases: BTreeMap<String, Alias>,
+}
+
+impl Aliases {
+    /// The aliases of the config, with those of the manifest in `directory` over them
+    pub fn load(directory: &Path) -> Self {
+        let mut aliases = Aliases::default();
+        if let Ok(config) = Config::from_file() {
+            aliases.extend(config.alias, "config");
+        }
+        if let Some(manifest_aliases) = Manifest::find_in_directory(directory)
+            .ok()
+            .and_then(|manifest| manifest.alias)
+        {
+            aliases.extend(manifest_aliases, MANIFEST_FILE_NAME);
+        }
+        aliases
+    }
+
+    fn extend(&mut self, aliases: BTreeMap<String, String>, source: &'static str) {
+        for (name, command) in aliases {
+            self.aliases.insert(name, Alias { command, source });
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Alias)> {
+        self.aliases.iter()
+    }
+
+    /// Expand the alias given as the subcommand in `args`, which start with the program name.
+    /// Names for which `is_subcommand` holds are left alone.
+    pub fn expand(
+        &self,
+        args: &[String],
+        is_subcommand: impl Fn(&str) -> bool,
+    ) -> Result<Vec<String>, AliasError> {
+        let mut args = args.to_vec();
+        let mut expanded: Vec<String> = vec![];
+        while let Some(name) = args.get(1).cloned() {
+            if name.starts_with('-') || is_subcommand(&name) {
+                break;
+            }
+            let alias = match self.aliases.get(&name) {
+                Some(alias) => alias,
+                None => break,
+            };
+            if expanded.contains(&name) {
+                expanded.push(name);
+                return Err(AliasError::Cycle(expanded.join(" -> ")));
+            }
+            let words = split_words(&alias.command)
+                .ok_or_else(|| AliasError::UnterminatedQuote(name.clone()))?;
+            if words.is_empty() {
+                return Err(AliasError::Empty(name));
+            }
+            args.splice(1..2, words);
+            expanded.push(name);
+        }
+        Ok(args)
+    }
+}
+
+/// Split a command into words at whitespace, keeping quoted text together. `None` if a quote
+/// is not closed.
+fn split_words(command: &str) -> Option<Vec<String>> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in command.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote.is_some() {
+        return None;
+    }
+    words.extend(word);
+    Some(words)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn aliases(aliases: &[(&str, &str)]) -> Aliases {
+        let mut result = Aliases::default();
+        result.extend(
+            aliases
+                .iter()
+                .map(|(name, command)| (name.to_string(), command.to_string()))
+                .collect(),
+            "config",
+        );
+        result
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn aliases_are_expanded() {
+        let aliases = aliases(&[
+            ("t", "run test -- --verbose"),
+            ("tt", "t --quiet"),
+            ("greet", "run hello 'big world'"),
+            ("install", "run other"),
+        ]);
+        let is_subcommand = |name: &str| ["run", "install"].contains(&name);
+        assert_eq!(
+            aliases.expand(&args(&["wapm", "tt", "-x"]), is_subcommand),
+            Ok(args(&[
+                "wapm",
+                "run",
+                "test",
+                "--",
+                "--verbose",
+                "--quiet",
+                "-x"
+            ]))
+        );
+        assert_eq!(
+            aliases.expand(&args(&["wapm", "greet"]), is_subcommand),
+            Ok(args(&["wapm", "run", "hello", "big world"]))
+        );
+        assert_eq!(
+            aliases.expand(&args(&["wapm", "install", "t"]), is_subcommand),
+            Ok(args(&["wapm", "install", "t"]))
+        );
+    }
+
+    #[test]
+    fn alias_cycles_are_detected() {
+        let aliases = aliases(&[("a", "b --flag"), ("b", "a"), ("c", "run 'x")]);
+        assert_eq!(
+            aliases.expand(&args(&["wapm", "a"]), |_| false),
+            Err(AliasError::Cycle("a -> b -> a".to_string()))
+        );
+        assert_eq!(
+            aliases.expand(&args(&["wapm", "c"]), |_| false),
+            Err(AliasError::UnterminatedQuote("c".to_string()))
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! Allowlists of the only packages installs may fetch, for regulated environments where every
+//! dependency must be vetted.
+//!
+//! An allowlist lists exact versions with the SHA-256 checksums of their archives:
+//!
+//! ```toml
+//! [[package]]
+//! name = "_/sqlite"
+//! version = "0.1.1"
+//! sha256 = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+//! ```
+//!
+//! `wapm lock export-allowlist` writes one for the packages of a vetted lockfile and signs it
+//! with the active personal key into `<allowlist>.minisig`. Installs use the allowlist from the
+//! `WAPM_ALLOWLIST` environment variable or the `install.allowlist` config key, which is only
+//! trusted when its signature matches the `install.allowlist-key` config key. Resolving to a
+//! version that isn't listed fails before anything is downloaded, and archives with another
+//! checksum fail the install.
+
+use crate::config::Config;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const ALLOWLIST_ENV_VAR: &str = "WAPM_ALLOWLIST";
+
+/// The extension of the signature next to an allowlist
+pub const SIGNATURE_EXTENSION: &str = "minisig";
+
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Allowlist {
+    #[serde(rename = "package", default)]
+    pub packages: Vec<AllowedPackage>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct AllowedPackage {
+    pub name: String,
+    pub version: String,
+    /// The checksum of the archive of the package
+    pub sha256: String,
+}
+
+#[derive(Debug, Fail)]
+pub enum AllowlistError {
+    #[fail(display = "Could not read the allowlist {}: {}", _0, _1)]
+    CouldNotRead(String, String),
+    #[fail(display = "Could not parse the allowlist {}: {}", _0, _1)]
+    CouldNotParse(String, String),
+    #[fail(
+        display = "The allowlist {} can't be trusted without the key it is signed with, set it with `wapm config set install.allowlist-key <public key>`",
+        _0
+    )]
+    MissingKey(String),
+    #[fail(display = "The signature of the allowlist {} is invalid: {}", _0, _1)]
+    InvalidSignature(String, String),
+}
+
+impl Allowlist {
+    /// The allowlist from the environment or the config, if there is one, after checking its
+    /// signature
+    pub fn load() -> Result<Option<Self>, AllowlistError> {
+        let config = Config::from_file().ok();
+        let path = env::var(ALLOWLIST_ENV_VAR)
+            .ok()
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                config
+                    .as_ref()
+                    .and_then(|config| config.install.allowlist.clone())
+            });
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let display = path.display().to_string();
+        let public_key = config
+            .and_then(|config| config.install.allowlist_key)
+            .ok_or_else(|| AllowlistError::MissingKey(display.clone()))?;
+        Self::from_signed_file(&path, &public_key).map(Some)
+    }
+
+    /// Read an allowlist, checking that it is signed with the given minisign public key
+    pub fn from_signed_file(path: &Path, public_key: &str) -> Result<Self, AllowlistError> {
+        let display = path.display().to_string();
+        let source = fs::read_to_string(path)
+            .map_err(|e| AllowlistError::CouldNotRead(display.clone(), e.to_string()))?;
+        let signature = fs::read_to_string(signature_path(path))
+            .map_err(|e| AllowlistError::InvalidSignature(display.clone(), e.to_string()))?;
+        verify_signature(&source, &signature, public_key)
+            .map_err(|e| AllowlistError::InvalidSignature(display.clone(), e))?;
+        Self::parse(&source).map_err(|e| AllowlistError::CouldNotParse(display, e))
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        toml::from_str(source).map_err(|e| e.to_string())
+    }
+
+    fn find(&self, name: &str, version: &str) -> Option<&AllowedPackage> {
+        self.packages
+            .iter()
+            .find(|package| package.name == name && package.version == version)
+    }
+
+    /// Whether a version of a package may be installed
+    pub fn allows(&self, name: &str, version: &str) -> bool {
+        self.find(name, version).is_some()
+    }
+
+    /// The checksum the archive of a version of a package must have
+    pub fn sha256(&self, name: &str, version: &str) -> Option<&str> {
+        self.find(name, version)
+            .map(|package| package.sha256.as_str())
+    }
+}
+
+/// Where the signature of an allowlist is kept
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(SIGNATURE_EXTENSION);
+    path.with_file_name(file_name)
+}
+
+fn verify_signature(source: &str, signature: &str, public_key: &str) -> Result<(), String> {
+    let public_key =
+        minisign::PublicKey::from_base64(public_key.trim()).map_err(|e| e.to_string())?;
+    let signature = minisign::SignatureBox::from_string(signature).map_err(|e| e.to_string())?;
+    minisign::verify(
+        &public_key,
+        &signature,
+        io::Cursor::new(source.as_bytes()),
+        true,
+        false,
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn allowlists_list_versions_and_checksums() {
+        let source = r#"
+[[package]]
+name = "_/sqlite"
+version = "0.1.1"
+sha256 = "abc"
+"#;
+        let allowlist = Allowlist::parse(source).unwrap();
+        assert!(allowlist.allows("_/sqlite", "0.1.1"));
+        assert!(!allowlist.allows("_/sqlite", "0.2.0"));
+        assert_eq!(allowlist.sha256("_/sqlite", "0.1.1"), Some("abc"));
+
+        assert_eq!(
+            signature_path(Path::new("dir/allowlist.toml")),
+            PathBuf::from("dir/allowlist.toml.minisig")
+        );
+    }
+}
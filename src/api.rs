@@ -0,0 +1,519 @@
+//! A local HTTP API over the library, for editors, GUIs and build systems that would otherwise
+//! parse the output of the CLI. `wapm api` serves it:
+//!
+//! - `GET /v1/installed?directory=<path>` or `?global=true`: the packages and commands of a
+//!   lockfile
+//! - `GET /v1/resolve?package=<name>&version=<version>`: a version of a package in the registry,
+//!   the last one without `version`
+//! - `GET /v1/search?q=<query>`: packages of the registry
+//! - `POST /v1/run` with `{"command": ..., "args": [...], "directory": ...}`: run a command and
+//!   collect its output
+//!
+//! Every request needs an `Authorization: Bearer <token>` header with the token of the token file,
+//! which is created readable only by its owner the first time the server starts, so other users
+//! of the machine can't drive it. Responses are JSON, errors are `{"error": "..."}`. At most
+//! `MAX_CONNECTIONS` requests are answered at once, the others get a 503.
+
+use crate::commands::search_packages;
+use crate::config::Config;
+use crate::credentials::to_hex;
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::graphql::VERSION;
+use crate::registry;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const TOKEN_FILE_NAME: &str = "api_token";
+/// The size of the request line and headers
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// The number of connections answered at once, each has its own thread
+const MAX_CONNECTIONS: usize = 32;
+
+#[derive(Debug, Fail)]
+pub enum ApiError {
+    #[fail(display = "Malformed request: {}", _0)]
+    BadRequest(String),
+    #[fail(display = "The request is larger than the limit of {} bytes", _0)]
+    TooLarge(usize),
+    #[fail(
+        display = "The token file {} can be read by other users, make it readable only by its owner with `chmod 600 {}`",
+        _0, _0
+    )]
+    InsecureTokenFile(String),
+    #[fail(
+        display = "Could not generate a token from the randomness of the operating system: {}",
+        _0
+    )]
+    NoRandomness(String),
+}
+
+/// An HTTP request, with lowercase header names
+#[derive(Debug, Default, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: BTreeMap<String, String>,
+    pub headers: BTreeMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Self { status: 200, body }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message }),
+        }
+    }
+
+    fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let body = self.body.to_string();
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            reason_phrase(self.status),
+            body.len(),
+            body
+        )?;
+        writer.flush()
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Where the token is kept when no token file is given
+pub fn default_token_path() -> Result<PathBuf, failure::Error> {
+    Ok(Config::get_folder()?.join(TOKEN_FILE_NAME))
+}
+
+/// Read the token of the token file, creating the file with a new token if it doesn't exist
+pub fn load_or_create_token(path: &Path) -> Result<String, failure::Error> {
+    if let Ok(token) = fs::read_to_string(path) {
+        let token = token.trim();
+        if !token.is_empty() {
+            check_token_permissions(path)?;
+            return Ok(token.to_string());
+        }
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let token = generate_token()?;
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(token.as_bytes())?;
+    Ok(token)
+}
+
+/// The token lets whoever has it run commands, so a token file others can read is refused
+#[cfg(unix)]
+fn check_token_permissions(path: &Path) -> Result<(), ApiError> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = fs::metadata(path)
+        .map(|metadata| metadata.permissions().mode())
+        .unwrap_or(0);
+    if mode & 0o077 != 0 {
+        return Err(ApiError::InsecureTokenFile(path.display().to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_token_permissions(_path: &Path) -> Result<(), ApiError> {
+    Ok(())
+}
+
+/// A token from the randomness of the operating system
+fn generate_token() -> Result<String, ApiError> {
+    let mut bytes = [0; 32];
+    OsRng
+        .try_fill_bytes(&mut bytes)
+        .map_err(|e| ApiError::NoRandomness(e.to_string()))?;
+    Ok(to_hex(&bytes))
+}
+
+/// Frees the place of a connection when its thread is done
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Serve the API until the process is stopped, a connection at a time per thread
+pub fn serve(listener: TcpListener, token: String) -> Result<(), failure::Error> {
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                debug!("Could not accept an API connection: {}", e);
+                continue;
+            }
+        };
+        if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            let busy = Response::error(503, "Too many connections, try again later");
+            if let Err(e) = busy.write_to(&stream) {
+                debug!("Could not answer an API request: {}", e);
+            }
+            continue;
+        }
+        let slot = ConnectionSlot(connections.clone());
+        let token = token.clone();
+        thread::spawn(move || {
+            let _slot = slot;
+            if let Err(e) = handle_connection(stream, &token) {
+                debug!("Could not answer an API request: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: TcpStream, token: &str) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let response = match read_request(BufReader::new(&stream)) {
+        Ok(request) => route(&request, token),
+        Err(e @ ApiError::TooLarge(_)) => Response::error(413, &e.to_string()),
+        Err(e) => Response::error(400, &e.to_string()),
+    };
+    response.write_to(&stream)
+}
+
+/// Read an HTTP/1.1 request
+pub fn read_request<R: BufRead>(mut reader: R) -> Result<Request, ApiError> {
+    let bad_request = |e: &str| ApiError::BadRequest(e.to_string());
+    let mut head_size = 0;
+    let mut read_line = |reader: &mut R| -> Result<String, ApiError> {
+        let mut line = String::new();
+        reader
+            .by_ref()
+            .take((MAX_HEAD_SIZE - head_size + 1) as u64)
+            .read_line(&mut line)
+            .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+        head_size += line.len();
+        if head_size > MAX_HEAD_SIZE {
+            return Err(ApiError::TooLarge(MAX_HEAD_SIZE));
+        }
+        Ok(line.trim_end_matches(&['\r', '\n'][..]).to_string())
+    };
+
+    let request_line = read_line(&mut reader)?;
+    let mut parts = request_line.split(' ');
+    let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/1.") => {
+            (method.to_string(), target)
+        }
+        _ => return Err(bad_request("invalid request line")),
+    };
+    let url = url::Url::parse(&format!("http://localhost{}", target))
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    let mut request = Request {
+        method,
+        path: url.path().to_string(),
+        query: url.query_pairs().into_owned().collect(),
+        ..Request::default()
+    };
+    loop {
+        let line = read_line(&mut reader)?;
+        if line.is_empty() {
+            break;
+        }
+        let colon = line
+            .find(':')
+            .ok_or_else(|| bad_request("invalid header"))?;
+        request.headers.insert(
+            line[..colon].trim().to_lowercase(),
+            line[colon + 1..].trim().to_string(),
+        );
+    }
+    let content_length = match request.headers.get("content-length") {
+        Some(length) => length
+            .parse::<usize>()
+            .map_err(|_| bad_request("invalid Content-Length"))?,
+        None => 0,
+    };
+    if content_length > MAX_BODY_SIZE {
+        return Err(ApiError::TooLarge(MAX_BODY_SIZE));
+    }
+    request.body = vec![0; content_length];
+    reader
+        .read_exact(&mut request.body)
+        .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+    Ok(request)
+}
+
+/// Compare tokens in a time that doesn't depend on where they differ
+fn tokens_match(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// Answer a request
+pub fn route(request: &Request, token: &str) -> Response {
+    let authorized = request
+        .headers
+        .get("authorization")
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .is_some_and(|given| tokens_match(given.trim(), token));
+    if !authorized {
+        return Response::error(401, "missing or invalid API token");
+    }
+    let result = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/v1/health") => Ok(json!({ "version": VERSION })),
+        ("GET", "/v1/installed") => installed(request),
+        ("GET", "/v1/resolve") => resolve(request),
+        ("GET", "/v1/search") => search(request),
+        ("POST", "/v1/run") => run(request),
+        (_, "/v1/health")
+        | (_, "/v1/installed")
+        | (_, "/v1/resolve")
+        | (_, "/v1/search")
+        | (_, "/v1/run") => return Response::error(405, "method not allowed"),
+        _ => return Response::error(404, "not found"),
+    };
+    match result {
+        Ok(body) => Response::ok(body),
+        Err(RouteError::BadRequest(message)) => Response::error(400, &message),
+        Err(RouteError::NotFound(message)) => Response::error(404, &message),
+        Err(RouteError::Failed(e)) => Response::error(500, &e.to_string()),
+    }
+}
+
+enum RouteError {
+    BadRequest(String),
+    NotFound(String),
+    Failed(failure::Error),
+}
+
+impl<E: Into<failure::Error>> From<E> for RouteError {
+    fn from(e: E) -> Self {
+        RouteError::Failed(e.into())
+    }
+}
+
+fn required_parameter<'a>(request: &'a Request, name: &str) -> Result<&'a str, RouteError> {
+    request
+        .query
+        .get(name)
+        .map(String::as_str)
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| RouteError::BadRequest(format!("the \"{}\" parameter is required", name)))
+}
+
+fn installed(request: &Request) -> Result<Value, RouteError> {
+    let directory = if request.query.get("global").map(String::as_str) == Some("true") {
+        Config::get_globals_directory()?
+    } else {
+        match request.query.get("directory") {
+            Some(directory) => PathBuf::from(directory),
+            None => env::current_dir()?,
+        }
+    };
+    let lockfile = match LockfileResult::find_in_directory(&directory) {
+        LockfileResult::Lockfile(lockfile) => lockfile,
+        LockfileResult::NoLockfile => {
+            return Ok(json!({
+                "directory": directory,
+                "packages": [],
+                "commands": [],
+            }))
+        }
+        LockfileResult::LockfileError(e) => return Err(RouteError::Failed(e.into())),
+    };
+    let packages: Vec<Value> = lockfile
+        .modules
+        .iter()
+        .flat_map(|(name, versions)| {
+            versions
+                .keys()
+                .map(move |version| json!({ "name": name, "version": version.to_string() }))
+        })
+        .collect();
+    let commands: Vec<_> = lockfile.commands.values().collect();
+    Ok(json!({
+        "directory": directory,
+        "packages": packages,
+        "commands": commands,
+    }))
+}
+
+fn resolve(request: &Request) -> Result<Value, RouteError> {
+    let name = required_parameter(request, "package")?;
+    let version = request.query.get("version").map(String::as_str);
+    let package_version = registry::backend()?
+        .package_version(name, version)?
+        .ok_or_else(|| {
+            RouteError::NotFound(format!(
+                "{}{} was not found in the registry",
+                name,
+                version.map(|v| format!("@{}", v)).unwrap_or_default()
+            ))
+        })?;
+    Ok(json!({
+        "name": package_version.name,
+        "version": package_version.version,
+        "download_url": package_version.download_url,
+        "license": package_version.license,
+        "size": package_version.size,
+        "published_at": package_version.published_at.map(|date| date.to_rfc3339()),
+        "commands": package_version.commands,
+    }))
+}
+
+fn search(request: &Request) -> Result<Value, RouteError> {
+    let query = required_parameter(request, "q")?;
+    Ok(serde_json::to_value(search_packages(query)?)?)
+}
+
+#[derive(Deserialize)]
+struct RunRequest {
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    directory: Option<PathBuf>,
+}
+
+/// Run a command with `wapm run` in a child process, so its output can be collected and a
+/// crashing command doesn't take the server down
+fn run(request: &Request) -> Result<Value, RouteError> {
+    let run_request: RunRequest = serde_json::from_slice(&request.body)
+        .map_err(|e| RouteError::BadRequest(format!("invalid run request: {}", e)))?;
+    let directory = match run_request.directory {
+        Some(directory) => directory,
+        None => env::current_dir()?,
+    };
+    let output = Command::new(env::current_exe()?)
+        .arg("run")
+        .arg(&run_request.command)
+        .arg("--")
+        .args(&run_request.args)
+        .current_dir(&directory)
+        .output()?;
+    Ok(json!({
+        "exit_code": output.status.code(),
+        "stdout": String::from_utf8_lossy(&output.stdout),
+        "stderr": String::from_utf8_lossy(&output.stderr),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_requests() {
+        let raw = "POST /v1/run?x=1%202 HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer abc\r\nContent-Length: 2\r\n\r\n{}";
+        let request = read_request(raw.as_bytes()).unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/v1/run");
+        assert_eq!(request.query["x"], "1 2");
+        assert_eq!(request.headers["authorization"], "Bearer abc");
+        assert_eq!(request.body, b"{}");
+
+        assert!(matches!(
+            read_request("nonsense\r\n\r\n".as_bytes()),
+            Err(ApiError::BadRequest(_))
+        ));
+        let large = format!(
+            "GET / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        );
+        assert!(matches!(
+            read_request(large.as_bytes()),
+            Err(ApiError::TooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn requests_need_the_token() {
+        let mut request = Request {
+            method: "GET".to_string(),
+            path: "/v1/health".to_string(),
+            ..Request::default()
+        };
+        assert_eq!(route(&request, "secret").status, 401);
+        request
+            .headers
+            .insert("authorization".to_string(), "Bearer wrong!".to_string());
+        assert_eq!(route(&request, "secret").status, 401);
+        request
+            .headers
+            .insert("authorization".to_string(), "Bearer secret".to_string());
+        assert_eq!(route(&request, "secret").status, 200);
+        request.path = "/v1/unknown".to_string();
+        assert_eq!(route(&request, "secret").status, 404);
+        request.path = "/v1/search".to_string();
+        assert_eq!(route(&request, "secret").status, 400);
+        request.method = "DELETE".to_string();
+        assert_eq!(route(&request, "secret").status, 405);
+    }
+
+    #[test]
+    fn tokens_are_created_once() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("api_token");
+        let token = load_or_create_token(&path).unwrap();
+        assert_eq!(token.len(), 64);
+        assert_eq!(load_or_create_token(&path).unwrap(), token);
+        assert_ne!(generate_token().unwrap(), token);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(matches!(
+                load_or_create_token(&path)
+                    .unwrap_err()
+                    .downcast::<ApiError>(),
+                Ok(ApiError::InsecureTokenFile(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Registry tokens scoped to some packages, for CI jobs that should only publish what they build.
+//!
+//! A scope is a package name like `namespace/package`, all the packages of a namespace like
+//! `namespace/*`, or `*`. Tokens without scopes can publish every package of their user. The
+//! tokens are managed with `wapm token` on registries whose API has them, and the scopes of the
+//! token in use are checked before a publish so a token that can't publish the package fails
+//! before anything is built or uploaded.
+
+use crate::config::{Config, RegistryBackendKind};
+use crate::graphql::{execute_raw_query, GraphQLError};
+use serde_json::json;
+
+const CURRENT_TOKEN_QUERY: &str = "query CurrentApiTokenQuery {
+  viewer {
+    currentApiToken {
+      scopes
+    }
+  }
+}";
+
+const LIST_TOKENS_QUERY: &str = "query ApiTokensQuery {
+  viewer {
+    apiTokens {
+      id
+      identifier
+      scopes
+      createdAt
+      lastUsedAt
+    }
+  }
+}";
+
+const CREATE_TOKEN_MUTATION: &str =
+    "mutation CreateApiTokenMutation($identifier: String, $scopes: [String!]) {
+  createApiToken(input: {identifier: $identifier, scopes: $scopes}) {
+    token
+    apiToken {
+      id
+      identifier
+      scopes
+      createdAt
+      lastUsedAt
+    }
+  }
+}";
+
+const REVOKE_TOKEN_MUTATION: &str = "mutation RevokeApiTokenMutation($tokenId: String!) {
+  revokeApiToken(input: {tokenId: $tokenId}) {
+    success
+  }
+}";
+
+#[derive(Debug, Fail)]
+pub enum ApiTokenError {
+    #[fail(display = "The registry {} does not support scoped tokens", _0)]
+    NotSupported(String),
+    #[fail(display = "You need to be logged in to manage tokens, run `wapm login`")]
+    NotLoggedIn,
+    #[fail(
+        display = "Invalid token scope \"{}\", expected a package like `namespace/package`, `namespace/*` or `*`",
+        _0
+    )]
+    InvalidScope(String),
+    #[fail(
+        display = "The registry token can only publish {}, not {}. Create a token that covers it with `wapm token create --scope {}`, or log in with `wapm login`",
+        _1, _0, _0
+    )]
+    ScopeDoesNotCoverPackage(String, String),
+    #[fail(display = "No token with id {}", _0)]
+    UnknownToken(String),
+}
+
+/// A token of the logged in user, without its secret value
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiToken {
+    pub id: String,
+    pub identifier: Option<String>,
+    pub scopes: Option<Vec<String>>,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CurrentToken {
+    scopes: Option<Vec<String>>,
+}
+
+/// Check that a scope is a package name, a namespace followed by `/*`, or `*`
+pub fn validate_scope(scope: &str) -> Result<(), ApiTokenError> {
+    let invalid = || ApiTokenError::InvalidScope(scope.to_string());
+    if scope == "*" {
+        return Ok(());
+    }
+    let (namespace, package) = scope.split_once('/').ok_or_else(invalid)?;
+    let valid_part = |part: &str| {
+        !part.is_empty() && !part.contains(|c: char| c == '/' || c == '*' || c.is_whitespace())
+    };
+    if !valid_part(namespace) || !(package == "*" || valid_part(package)) {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+/// Whether a scope lets a token publish the package
+pub fn scope_covers(scope: &str, package_name: &str) -> bool {
+    if scope == "*" || scope == package_name {
+        return true;
+    }
+    match scope.strip_suffix("/*") {
+        Some(namespace) => package_name
+            .split_once('/')
+            .is_some_and(|(package_namespace, _)| package_namespace == namespace),
+        None => false,
+    }
+}
+
+/// Errors of registries whose API has no tokens
+fn unsupported(error: failure::Error, registry_url: &str) -> failure::Error {
+    let not_in_schema = error
+        .downcast_ref::<GraphQLError>()
+        .is_some_and(|e| e.to_string().to_lowercase().contains("cannot query field"));
+    if not_in_schema {
+        ApiTokenError::NotSupported(registry_url.to_string()).into()
+    } else {
+        error
+    }
+}
+
+/// Fail if the token in use has scopes and none of them covers the package. Registries without
+/// scoped tokens are left to accept or reject the publish themselves.
+pub fn check_publish_scope(package_name: &str) -> Result<(), failure::Error> {
+    let config = Config::from_file()?;
+    if config.registry.backend_kind() != RegistryBackendKind::Graphql
+        || config.registry.token.is_none()
+    {
+        return Ok(());
+    }
+    let response: serde_json::Value =
+        match execute_raw_query(CURRENT_TOKEN_QUERY, "CurrentApiTokenQuery", &json!({})) {
+            Ok(response) => response,
+            Err(e) => {
+                debug!("Could not look up the scopes of the token: {}", e);
+                return Ok(());
+            }
+        };
+    let current: Option<CurrentToken> =
+        serde_json::from_value(response["viewer"]["currentApiToken"].clone())?;
+    let scopes = match current.and_then(|current| current.scopes) {
+        Some(scopes) if !scopes.is_empty() => scopes,
+        _ => return Ok(()),
+    };
+    if scopes.iter().any(|scope| scope_covers(scope, package_name)) {
+        return Ok(());
+    }
+    Err(ApiTokenError::ScopeDoesNotCoverPackage(package_name.to_string(), scopes.join(", ")).into())
+}
+
+fn logged_in_config() -> Result<Config, failure::Error> {
+    let config = Config::from_file()?;
+    if config.registry.token.is_none() {
+        return Err(ApiTokenError::NotLoggedIn.into());
+    }
+    Ok(config)
+}
+
+/// The tokens of the logged in user
+pub fn list_tokens() -> Result<Vec<ApiToken>, failure::Error> {
+    let config = logged_in_config()?;
+    let response: serde_json::Value =
+        execute_raw_query(LIST_TOKENS_QUERY, "ApiTokensQuery", &json!({}))
+            .map_err(|e| unsupported(e, &config.registry.url))?;
+    let tokens: Option<Vec<ApiToken>> =
+        serde_json::from_value(response["viewer"]["apiTokens"].clone())?;
+    Ok(tokens.unwrap_or_default())
+}
+
+/// Create a token limited to the scopes, returning it with its secret value
+pub fn create_token(
+    identifier: Option<String>,
+    scopes: Vec<String>,
+) -> Result<(ApiToken, String), failure::Error> {
+    for scope in scopes.iter() {
+        validate_scope(scope)?;
+    }
+    let config = logged_in_config()?;
+    let variables = json!({ "identifier": identifier, "scopes": scopes });
+    let response: serde_json::Value =
+        execute_raw_query(CREATE_TOKEN_MUTATION, "CreateApiTokenMutation", &variables)
+            .map_err(|e| unsupported(e, &config.registry.url))?;
+    let created = &response["createApiToken"];
+    let token: ApiToken = serde_json::from_value(created["apiToken"].clone())?;
+    let value = created["token"]
+        .as_str()
+        .ok_or_else(|| format_err!("The registry did not return the new token"))?;
+    Ok((token, value.to_string()))
+}
+
+/// Revoke a token of the logged in user by its id
+pub fn revoke_token(id: &str) -> Result<(), failure::Error> {
+    let config = logged_in_config()?;
+    let response: serde_json::Value = execute_raw_query(
+        REVOKE_TOKEN_MUTATION,
+        "RevokeApiTokenMutation",
+        &json!({ "tokenId": id }),
+    )
+    .map_err(|e| unsupported(e, &config.registry.url))?;
+    if response["revokeApiToken"]["success"].as_bool() != Some(true) {
+        return Err(ApiTokenError::UnknownToken(id.to_string()).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scopes_cover_packages_and_namespaces() {
+        assert!(scope_covers("*", "syrus/pkg"));
+        assert!(scope_covers("syrus/pkg", "syrus/pkg"));
+        assert!(scope_covers("syrus/*", "syrus/pkg"));
+        assert!(!scope_covers("syrus/*", "syrusakbary/pkg"));
+        assert!(!scope_covers("syrus/other", "syrus/pkg"));
+
+        for scope in &["*", "syrus/*", "syrus/pkg"] {
+            assert!(validate_scope(scope).is_ok(), "{}", scope);
+        }
+        for scope in &[
+            "syrus",
+            "syrus/",
+            "/pkg",
+            "syrus/pkg/extra",
+            "*/pkg",
+            "a b/c",
+        ] {
+            assert!(validate_scope(scope).is_err(), "{}", scope);
+        }
+    }
+}
@@ -0,0 +1,480 @@
+//! Extracting package archives without trusting them. Archives come from the registry, GitHub
+//! releases, bundles and `wapm validate`, and a crafted one must not be able to write outside of
+//! the directory it is extracted to or fill the disk:
+//!
+//! - paths with `..` or that are absolute are rejected
+//! - symlinks must point inside the directory, hard links, devices and fifos are rejected
+//! - the number of files, the size of each file and the size of the whole archive are limited,
+//!   also while decompressing, so a small archive that decompresses to gigabytes is stopped early
+//!
+//! File names are written in the NFC unicode form, and on Windows paths may be longer than 260
+//! characters.
+
+use crate::portable_paths::{long_path, nfc_name};
+use flate2::read::GzDecoder;
+use std::cell::Cell;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use tar::{Archive, EntryType};
+
+const KIB: u64 = 1024;
+const MIB: u64 = 1024 * KIB;
+const GIB: u64 = 1024 * MIB;
+
+/// How much an archive may extract to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExtractionLimits {
+    /// The number of entries, including directories and symlinks
+    pub max_files: u64,
+    /// The size of a single file
+    pub max_file_size: u64,
+    /// The size of all the files together
+    pub max_total_size: u64,
+    /// The size of the decompressed tar stream, headers and padding included
+    pub max_decompressed_size: u64,
+}
+
+impl Default for ExtractionLimits {
+    fn default() -> Self {
+        Self {
+            max_files: 100_000,
+            max_file_size: GIB,
+            max_total_size: 2 * GIB,
+            max_decompressed_size: 3 * GIB,
+        }
+    }
+}
+
+/// What an archive extracted to
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ExtractionSummary {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Fail)]
+pub enum ArchiveError {
+    #[fail(
+        display = "The archive has a path leaving the package directory: {}",
+        _0
+    )]
+    PathTraversal(String),
+    #[fail(display = "The archive has an absolute path: {}", _0)]
+    AbsolutePath(String),
+    #[fail(
+        display = "The archive has a link pointing outside of the package directory: {} -> {}",
+        _0, _1
+    )]
+    LinkEscapes(String, String),
+    #[fail(
+        display = "The archive has an unsupported entry {} of type {:?}",
+        _0, _1
+    )]
+    UnsupportedEntry(String, EntryType),
+    #[fail(display = "The archive has more than {} files", _0)]
+    TooManyFiles(u64),
+    #[fail(
+        display = "The file {} in the archive is larger than the limit of {} bytes",
+        _0, _1
+    )]
+    FileTooLarge(String, u64),
+    #[fail(
+        display = "The archive extracts to more than the limit of {} bytes",
+        _0
+    )]
+    TooLarge(u64),
+    #[fail(display = "Could not extract the archive: {}", _0)]
+    Io(String),
+}
+
+/// Decompress a `.tar.gz` archive and extract it to `destination`, which is created if needed
+pub fn unpack<R: Read>(
+    compressed: R,
+    destination: &Path,
+    limits: &ExtractionLimits,
+) -> Result<ExtractionSummary, ArchiveError> {
+    let exceeded = Rc::new(Cell::new(false));
+    let reader = LimitedReader {
+        inner: GzDecoder::new(compressed),
+        remaining: limits.max_decompressed_size,
+        exceeded: exceeded.clone(),
+    };
+    let io_error = |e: io::Error| {
+        if exceeded.get() {
+            ArchiveError::TooLarge(limits.max_decompressed_size)
+        } else {
+            ArchiveError::Io(e.to_string())
+        }
+    };
+
+    let destination = long_path(destination);
+    fs::create_dir_all(&destination).map_err(io_error)?;
+    // canonical paths on Windows have the long path prefix, so the entries can be long too
+    let root = destination.canonicalize().map_err(io_error)?;
+    let mut archive = Archive::new(reader);
+    let mut summary = ExtractionSummary::default();
+    for entry in archive.entries().map_err(io_error)? {
+        let mut entry = entry.map_err(io_error)?;
+        let path = entry.path().map_err(io_error)?.to_path_buf();
+        let display = path.to_string_lossy().to_string();
+        let relative = match relative_path(&path, &display)? {
+            Some(relative) => relative,
+            // `./`, the archive root
+            None => continue,
+        };
+
+        let entry_type = entry.header().entry_type();
+        match entry_type {
+            EntryType::Regular | EntryType::Continuous | EntryType::Directory => {}
+            EntryType::Symlink => {}
+            EntryType::XGlobalHeader => continue,
+            other => return Err(ArchiveError::UnsupportedEntry(display, other)),
+        }
+
+        summary.files += 1;
+        if summary.files > limits.max_files {
+            return Err(ArchiveError::TooManyFiles(limits.max_files));
+        }
+        let size = entry.header().entry_size().map_err(io_error)?;
+        if size > limits.max_file_size {
+            return Err(ArchiveError::FileTooLarge(display, limits.max_file_size));
+        }
+        summary.bytes += size;
+        if summary.bytes > limits.max_total_size {
+            return Err(ArchiveError::TooLarge(limits.max_total_size));
+        }
+
+        let parent = create_parent_dirs(&root, &relative).map_err(io_error)?;
+        let target = match relative.file_name() {
+            Some(name) => parent.join(name),
+            None => continue,
+        };
+        if entry_type == EntryType::Symlink {
+            let link = entry
+                .link_name()
+                .map_err(io_error)?
+                .map(|link| link.to_path_buf())
+                .unwrap_or_default();
+            if !link_stays_inside(&root, &parent, &link) {
+                return Err(ArchiveError::LinkEscapes(
+                    display,
+                    link.to_string_lossy().to_string(),
+                ));
+            }
+        }
+        // never write through a link left by an earlier entry or a previous install
+        if let Ok(metadata) = fs::symlink_metadata(&target) {
+            if metadata.file_type().is_symlink() {
+                fs::remove_file(&target).map_err(io_error)?;
+            }
+        }
+        entry.unpack(&target).map_err(io_error)?;
+    }
+    if exceeded.get() {
+        return Err(ArchiveError::TooLarge(limits.max_decompressed_size));
+    }
+    Ok(summary)
+}
+
+/// The path of an entry without `.` components and with NFC names, or `None` if nothing is left
+fn relative_path(path: &Path, display: &str) -> Result<Option<PathBuf>, ArchiveError> {
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => relative.push(nfc_name(part)),
+            Component::CurDir => {}
+            Component::ParentDir => return Err(ArchiveError::PathTraversal(display.to_string())),
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(ArchiveError::AbsolutePath(display.to_string()))
+            }
+        }
+    }
+    if relative.as_os_str().is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(relative))
+    }
+}
+
+/// Create the directories leading to an entry one at a time, checking that none of them is a
+/// link leaving `root`. Returns the resolved parent directory of the entry.
+fn create_parent_dirs(root: &Path, relative: &Path) -> io::Result<PathBuf> {
+    let mut parent = root.to_path_buf();
+    if let Some(directories) = relative.parent() {
+        for directory in directories.components() {
+            parent.push(directory);
+            if fs::symlink_metadata(&parent).is_err() {
+                fs::create_dir(&parent)?;
+            }
+            parent = parent.canonicalize()?;
+            if !parent.starts_with(root) || !parent.is_dir() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{} is not a directory of the package", relative.display()),
+                ));
+            }
+        }
+    }
+    Ok(parent)
+}
+
+/// Whether a link in `parent` pointing to `link` resolves inside `root`. `..` is only allowed
+/// at the start of the link, as a `..` after a directory that is itself a link would go back
+/// from wherever that link points.
+fn link_stays_inside(root: &Path, parent: &Path, link: &Path) -> bool {
+    let mut resolved = parent.to_path_buf();
+    let mut leading = true;
+    for component in link.components() {
+        match component {
+            Component::ParentDir if leading => {
+                if !resolved.pop() {
+                    return false;
+                }
+            }
+            Component::CurDir => {}
+            Component::Normal(part) => {
+                leading = false;
+                resolved.push(part);
+            }
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    !link.as_os_str().is_empty() && resolved.starts_with(root)
+}
+
+/// Stops reading, and records that it did, once more than `remaining` bytes come out of `inner`
+struct LimitedReader<R> {
+    inner: R,
+    remaining: u64,
+    exceeded: Rc<Cell<bool>>,
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // read one byte past the limit to know whether there is more
+        let max = (self.remaining + 1).min(buf.len() as u64) as usize;
+        let read = self.inner.read(&mut buf[..max])?;
+        if read as u64 > self.remaining {
+            self.exceeded.set(true);
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the archive decompresses to more than the limit",
+            ));
+        }
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use tar::{Builder, Header};
+
+    /// A header whose path is written as it is, as `set_path` refuses the malicious ones
+    fn raw_header(path: &str, entry_type: EntryType, size: u64) -> Header {
+        let mut header = Header::new_gnu();
+        header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+        header.set_entry_type(entry_type);
+        header.set_size(size);
+        header.set_mode(0o644);
+        header.set_cksum();
+        header
+    }
+
+    fn symlink_header(path: &str, link: &str) -> Header {
+        let mut header = raw_header(path, EntryType::Symlink, 0);
+        header.set_link_name(link).unwrap();
+        header.set_cksum();
+        header
+    }
+
+    fn archive(entries: Vec<(Header, &[u8])>) -> Vec<u8> {
+        let mut builder = Builder::new(GzEncoder::new(vec![], Compression::default()));
+        for (header, data) in entries {
+            builder.append(&header, data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    fn extract(
+        archive: &[u8],
+        limits: &ExtractionLimits,
+    ) -> (
+        tempfile::TempDir,
+        PathBuf,
+        Result<ExtractionSummary, ArchiveError>,
+    ) {
+        let dir = tempfile::TempDir::new().unwrap();
+        let destination = dir.path().join("package");
+        let result = unpack(archive, &destination, limits);
+        (dir, destination, result)
+    }
+
+    #[test]
+    fn well_formed_archives_are_extracted() {
+        let data = archive(vec![
+            (raw_header("./", EntryType::Directory, 0), &b""[..]),
+            (raw_header("wapm.toml", EntryType::Regular, 5), b"hello"),
+            (raw_header("lib/", EntryType::Directory, 0), b""),
+            (raw_header("lib/a.wasm", EntryType::Regular, 3), b"abc"),
+            (symlink_header("lib/b.wasm", "a.wasm"), b""),
+            (symlink_header("lib/up", "../wapm.toml"), b""),
+        ]);
+        let (_dir, destination, result) = extract(&data, &ExtractionLimits::default());
+        assert_eq!(result.unwrap(), ExtractionSummary { files: 5, bytes: 8 });
+        assert_eq!(fs::read(destination.join("wapm.toml")).unwrap(), b"hello");
+        if cfg!(unix) {
+            assert_eq!(fs::read(destination.join("lib/b.wasm")).unwrap(), b"abc");
+            assert_eq!(fs::read(destination.join("lib/up")).unwrap(), b"hello");
+        }
+    }
+
+    #[test]
+    fn names_are_normalized_and_may_be_long() {
+        let long_path = format!("{}/file.txt", "d".repeat(150));
+        let mut builder = Builder::new(GzEncoder::new(vec![], Compression::default()));
+        for path in ["cafe\u{301}.txt", long_path.as_str()] {
+            let mut header = Header::new_gnu();
+            header.set_size(2);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, &b"ok"[..]).unwrap();
+        }
+        let data = builder.into_inner().unwrap().finish().unwrap();
+        let (_dir, destination, result) = extract(&data, &ExtractionLimits::default());
+        result.unwrap();
+        let names: Vec<String> = fs::read_dir(&destination)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        assert!(names.contains(&"caf\u{e9}.txt".to_string()));
+        assert!(!names.contains(&"cafe\u{301}.txt".to_string()));
+        assert_eq!(fs::read(destination.join(&long_path)).unwrap(), b"ok");
+    }
+
+    #[test]
+    fn paths_leaving_the_destination_are_rejected() {
+        let data = archive(vec![(
+            raw_header("../evil", EntryType::Regular, 4),
+            &b"evil"[..],
+        )]);
+        let (dir, _, result) = extract(&data, &ExtractionLimits::default());
+        match result {
+            Err(ArchiveError::PathTraversal(path)) => assert_eq!(path, "../evil"),
+            other => panic!("expected a path traversal error, got {:?}", other),
+        }
+        assert!(!dir.path().join("evil").exists());
+
+        let data = archive(vec![(
+            raw_header("/tmp/evil", EntryType::Regular, 4),
+            &b"evil"[..],
+        )]);
+        let (_dir, _, result) = extract(&data, &ExtractionLimits::default());
+        match result {
+            Err(ArchiveError::AbsolutePath(path)) => assert_eq!(path, "/tmp/evil"),
+            other => panic!("expected an absolute path error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn links_leaving_the_destination_are_rejected() {
+        for link in &["../outside", "/etc", "sub/../../outside"] {
+            let data = archive(vec![(symlink_header("link", link), &b""[..])]);
+            let (_dir, _, result) = extract(&data, &ExtractionLimits::default());
+            match result {
+                Err(ArchiveError::LinkEscapes(_, target)) => assert_eq!(target, *link),
+                other => panic!("expected {} to be rejected, got {:?}", link, other),
+            }
+        }
+
+        if cfg!(unix) {
+            // `alias/up` is `up` in the package directory, so `..` leaves it
+            let data = archive(vec![
+                (symlink_header("alias", "."), &b""[..]),
+                (symlink_header("alias/up", ".."), b""),
+            ]);
+            let (dir, _, result) = extract(&data, &ExtractionLimits::default());
+            assert!(matches!(result, Err(ArchiveError::LinkEscapes(..))));
+            assert!(fs::symlink_metadata(dir.path().join("package/up")).is_err());
+        }
+    }
+
+    #[test]
+    fn devices_and_hard_links_are_rejected() {
+        for entry_type in &[
+            EntryType::Char,
+            EntryType::Block,
+            EntryType::Fifo,
+            EntryType::Link,
+        ] {
+            let data = archive(vec![(raw_header("device", *entry_type, 0), &b""[..])]);
+            let (_dir, destination, result) = extract(&data, &ExtractionLimits::default());
+            match result {
+                Err(ArchiveError::UnsupportedEntry(path, found)) => {
+                    assert_eq!(path, "device");
+                    assert_eq!(found, *entry_type);
+                }
+                other => panic!("expected {:?} to be rejected, got {:?}", entry_type, other),
+            }
+            assert!(!destination.join("device").exists());
+        }
+    }
+
+    #[test]
+    fn oversized_archives_are_rejected() {
+        let zeros = vec![0; 64 * KIB as usize];
+        let data = archive(vec![
+            (
+                raw_header("a", EntryType::Regular, zeros.len() as u64),
+                &zeros[..],
+            ),
+            (
+                raw_header("b", EntryType::Regular, zeros.len() as u64),
+                &zeros[..],
+            ),
+        ]);
+        // the zeros compress to almost nothing
+        assert!(data.len() < 4 * KIB as usize);
+        let limits = ExtractionLimits::default();
+
+        let (_dir, _, result) = extract(
+            &data,
+            &ExtractionLimits {
+                max_file_size: 32 * KIB,
+                ..limits
+            },
+        );
+        assert!(matches!(result, Err(ArchiveError::FileTooLarge(path, _)) if path == "a"));
+
+        let (_dir, _, result) = extract(
+            &data,
+            &ExtractionLimits {
+                max_total_size: 100 * KIB,
+                ..limits
+            },
+        );
+        assert!(matches!(result, Err(ArchiveError::TooLarge(limit)) if limit == 100 * KIB));
+
+        let (_dir, _, result) = extract(
+            &data,
+            &ExtractionLimits {
+                max_decompressed_size: 16 * KIB,
+                ..limits
+            },
+        );
+        assert!(matches!(result, Err(ArchiveError::TooLarge(limit)) if limit == 16 * KIB));
+
+        let (_dir, _, result) = extract(
+            &data,
+            &ExtractionLimits {
+                max_files: 1,
+                ..limits
+            },
+        );
+        assert!(matches!(result, Err(ArchiveError::TooManyFiles(1))));
+    }
+}
@@ -0,0 +1,210 @@
+//! Scanning the archives of packages after they are downloaded and before they are extracted, for
+//! organizations that must scan everything they install.
+//!
+//! `install.scanner` is a command, like a virus scanner, run by the shell with the path of the
+//! archive as its last argument. The install is blocked when it exits with a non-zero code.
+//! `install.scan-heuristics` turns on built-in checks that block archives that decompress to far
+//! more than their size, or whose contents look encrypted or packed.
+
+use crate::config::Config;
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::Command;
+
+/// The archive being scanned, also given as the last argument of the scanner
+pub const SCAN_ARCHIVE_ENV_VAR: &str = "WAPM_SCAN_ARCHIVE";
+/// The `name@version` of the package being scanned
+pub const SCAN_PACKAGE_ENV_VAR: &str = "WAPM_SCAN_PACKAGE";
+
+/// Archives decompressing to more than this many times their size are blocked
+const MAX_COMPRESSION_RATIO: u64 = 200;
+/// Contents with more bits of entropy per byte than this look encrypted or packed
+const MAX_ENTROPY: f64 = 7.9;
+/// Smaller contents don't have enough bytes for their entropy to mean anything
+const MIN_BYTES_FOR_ENTROPY: u64 = 64 * 1024;
+
+#[derive(Debug, Fail)]
+pub enum ScanError {
+    #[fail(display = "Could not run the scanner `{}`: {}", _0, _1)]
+    CouldNotRunScanner(String, String),
+    #[fail(display = "The scanner `{}` rejected the archive ({}). {}", _0, _1, _2)]
+    Rejected(String, String, String),
+    #[fail(display = "The archive looks suspicious: {}", _0)]
+    Anomaly(String),
+}
+
+/// Run the scanner and the heuristics set in the config on the archive of a package
+pub fn scan(archive_path: &Path, package: &str) -> Result<(), ScanError> {
+    let install = match Config::from_file() {
+        Ok(config) => config.install,
+        Err(_) => return Ok(()),
+    };
+    if let Some(scanner) = install.scanner.as_deref().filter(|s| !s.is_empty()) {
+        run_scanner(scanner, archive_path, package)?;
+    }
+    if install.scan_heuristics() {
+        let anomalies = match File::open(archive_path).and_then(|file| {
+            let compressed = file.metadata()?.len();
+            anomalies(file, compressed)
+        }) {
+            Ok(anomalies) => anomalies,
+            // webc containers are not gzipped, their contents are checked when converted
+            Err(e) => {
+                debug!("Could not check the archive of {}: {}", package, e);
+                vec![]
+            }
+        };
+        if !anomalies.is_empty() {
+            return Err(ScanError::Anomaly(anomalies.join(", ")));
+        }
+    }
+    Ok(())
+}
+
+fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell
+            .arg("/C")
+            .arg(format!("{} \"%{}%\"", command, SCAN_ARCHIVE_ENV_VAR));
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell
+            .arg("-c")
+            .arg(format!("{} \"${}\"", command, SCAN_ARCHIVE_ENV_VAR));
+        shell
+    }
+}
+
+fn run_scanner(scanner: &str, archive_path: &Path, package: &str) -> Result<(), ScanError> {
+    debug!("Scanning the archive of {} with `{}`", package, scanner);
+    let output = shell_command(scanner)
+        .env(SCAN_ARCHIVE_ENV_VAR, archive_path)
+        .env(SCAN_PACKAGE_ENV_VAR, package)
+        .output()
+        .map_err(|e| ScanError::CouldNotRunScanner(scanner.to_string(), e.to_string()))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let status = match output.status.code() {
+        Some(code) => format!("exit code {}", code),
+        None => "killed by a signal".to_string(),
+    };
+    let mut report = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        if !report.is_empty() {
+            report.push('\n');
+        }
+        report.push_str(stderr.trim());
+    }
+    Err(ScanError::Rejected(scanner.to_string(), status, report))
+}
+
+/// Shannon entropy in bits per byte of the bytes counted in the histogram
+fn entropy(histogram: &[u64; 256], total: u64) -> f64 {
+    histogram
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// What looks wrong with a gzipped archive of `compressed` bytes
+fn anomalies<R: Read>(archive: R, compressed: u64) -> io::Result<Vec<String>> {
+    let max_bytes = compressed.max(1) * MAX_COMPRESSION_RATIO;
+    let mut decoder = GzDecoder::new(archive);
+    let mut histogram = [0u64; 256];
+    let mut total = 0u64;
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = decoder.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        for byte in &buffer[..read] {
+            histogram[*byte as usize] += 1;
+        }
+        total += read as u64;
+        if total > max_bytes {
+            return Ok(vec![format!(
+                "it decompresses to more than {} times its size",
+                MAX_COMPRESSION_RATIO
+            )]);
+        }
+    }
+    let mut anomalies = vec![];
+    if total >= MIN_BYTES_FOR_ENTROPY {
+        let entropy = entropy(&histogram, total);
+        if entropy > MAX_ENTROPY {
+            anomalies.push(format!(
+                "its contents have {:.2} bits of entropy per byte, like encrypted or packed data",
+                entropy
+            ));
+        }
+    }
+    Ok(anomalies)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn check(data: &[u8]) -> Vec<String> {
+        let archive = gzip(data);
+        anomalies(&archive[..], archive.len() as u64).unwrap()
+    }
+
+    #[test]
+    fn heuristics_flag_bombs_and_random_looking_contents() {
+        let text: String = (0..8000)
+            .map(|i| format!("(func $f{} (result i32) i32.const {})\n", i, i * 7919))
+            .collect();
+        assert!(check(text.as_bytes()).is_empty());
+
+        assert_eq!(check(&vec![0; 10 * 1024 * 1024]).len(), 1);
+
+        // a xorshift generator stands in for encrypted data
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let random: Vec<u8> = (0..256 * 1024)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 24) as u8
+            })
+            .collect();
+        assert!(check(&random)[0].contains("entropy"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scanners_block_on_failure() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let archive = tmp_dir.path().join("package.tar.gz");
+        std::fs::write(&archive, gzip(b"package")).unwrap();
+        assert!(run_scanner("test -f", &archive, "_/pkg@1.0.0").is_ok());
+        match run_scanner("echo infected; exit 3; true", &archive, "_/pkg@1.0.0") {
+            Err(ScanError::Rejected(_, status, report)) => {
+                assert_eq!(status, "exit code 3");
+                assert_eq!(report, "infected");
+            }
+            other => panic!("unexpected scan result {:?}", other),
+        }
+    }
+}
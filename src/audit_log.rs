@@ -0,0 +1,180 @@
+//! A local, append-only log of the changes this machine made on registries: publishes, logins,
+//! logouts and token operations, whether they succeeded or not.
+//!
+//! Every action is one JSON line in `audit.log` in the wapm folder, so the log survives partial
+//! writes and can be read with `wapm audit-log` or any JSON tool when reconstructing an incident.
+//! Failing to write the log only warns, it never fails the action itself.
+
+use crate::config::Config;
+use chrono::{DateTime, Utc};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const AUDIT_LOG_FILE_NAME: &str = "audit.log";
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Publish,
+    Login,
+    Logout,
+    TokenCreate,
+    TokenRevoke,
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Action::Publish => write!(f, "publish"),
+            Action::Login => write!(f, "login"),
+            Action::Logout => write!(f, "logout"),
+            Action::TokenCreate => write!(f, "token create"),
+            Action::TokenRevoke => write!(f, "token revoke"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct Entry {
+    pub timestamp: DateTime<Utc>,
+    pub action: Action,
+    pub registry: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// What else the action was about, like the id of a revoked token or the user logging in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    /// The error of a failed action, `None` when it succeeded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Entry {
+    /// The package and version, like `user/package@1.0.0`
+    pub fn target(&self) -> Option<String> {
+        let package = self.package.as_ref()?;
+        Some(match &self.version {
+            Some(version) => format!("{}@{}", package, version),
+            None => package.clone(),
+        })
+    }
+}
+
+fn audit_log_path() -> Result<PathBuf, failure::Error> {
+    Ok(Config::get_folder()?.join(AUDIT_LOG_FILE_NAME))
+}
+
+fn append(path: &Path, entry: &Entry) -> Result<(), failure::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    // a single write keeps lines whole when several wapm processes log at once
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())?;
+    Ok(())
+}
+
+fn read(path: &Path) -> Result<Vec<Entry>, failure::Error> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                debug!("Skipping an unreadable line of the audit log: {}", e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Log an action on the registry of the config with its result
+pub fn record<T>(
+    action: Action,
+    package: Option<&str>,
+    version: Option<&str>,
+    subject: Option<&str>,
+    result: &Result<T, failure::Error>,
+) {
+    let logged = Config::from_file()
+        .map_err(failure::Error::from)
+        .and_then(|config| {
+            let entry = Entry {
+                timestamp: Utc::now(),
+                action,
+                registry: config.registry.url,
+                package: package.map(str::to_string),
+                version: version.map(str::to_string),
+                subject: subject.map(str::to_string),
+                error: result.as_ref().err().map(|e| e.to_string()),
+            };
+            append(&audit_log_path()?, &entry)
+        });
+    if let Err(e) = logged {
+        warn!("Could not write the {} to the audit log: {}", action, e);
+    }
+}
+
+/// The logged actions, oldest first
+pub fn entries() -> Result<Vec<Entry>, failure::Error> {
+    read(&audit_log_path()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn entries_are_appended_and_read_back() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join(AUDIT_LOG_FILE_NAME);
+        assert!(read(&path).unwrap().is_empty());
+
+        let published = Entry {
+            timestamp: Utc::now(),
+            action: Action::Publish,
+            registry: "https://registry.wapm.io".to_string(),
+            package: Some("user/package".to_string()),
+            version: Some("1.0.0".to_string()),
+            subject: None,
+            error: None,
+        };
+        append(&path, &published).unwrap();
+        let revoked = Entry {
+            action: Action::TokenRevoke,
+            package: None,
+            version: None,
+            subject: Some("VG9rZW46MQ==".to_string()),
+            error: Some("Token not found".to_string()),
+            ..published
+        };
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"truncated\n")
+            .unwrap();
+        append(&path, &revoked).unwrap();
+
+        let entries = read(&path).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].target().as_deref(), Some("user/package@1.0.0"));
+        assert_eq!(entries[1], revoked);
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .contains("\"action\":\"token-revoke\""));
+    }
+}
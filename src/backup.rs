@@ -0,0 +1,309 @@
+//! Backups carry the state of wapm to a new machine.
+//!
+//! A backup is a gzipped tar archive with:
+//!
+//! - `backup.json`, always the first entry, listing the registries logged in to, the globally
+//!   installed packages and the trusted public keys
+//! - `wapm.toml`, the global config. Registry tokens are taken out unless the backup was made
+//!   with `--include-secrets`.
+//!
+//! Global packages are listed by name and version only: restoring downloads them again instead of
+//! copying binaries that may not suit the new machine.
+
+use crate::config::GLOBAL_CONFIG_FILE_NAME;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use tar::{Archive, Builder, Header};
+
+const BACKUP_METADATA_NAME: &str = "backup.json";
+const BACKUP_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Fail)]
+pub enum BackupError {
+    #[fail(display = "{} is not a wapm backup: {}", _0, _1)]
+    InvalidBackup(String, String),
+    #[fail(
+        display = "The backup {} has format version {}, this version of wapm reads version {}",
+        _0, _1, _2
+    )]
+    UnsupportedFormat(String, u32, u32),
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct BackupMetadata {
+    pub format_version: u32,
+    pub created_at: DateTime<Utc>,
+    /// Whether the config in the backup kept its registry tokens
+    pub includes_secrets: bool,
+    pub credentials: Vec<Credential>,
+    pub global_packages: Vec<GlobalPackage>,
+    pub trusted_keys: Vec<TrustedKey>,
+}
+
+/// A registry of the config and whether it had a token, without the token itself
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct Credential {
+    /// The profile the registry belongs to, `None` for `[registry]`
+    pub profile: Option<String>,
+    pub url: String,
+    pub logged_in: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct GlobalPackage {
+    pub name: String,
+    pub version: String,
+    /// Whether this is the default version rather than one installed side by side
+    pub default: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+pub struct TrustedKey {
+    pub user_name: String,
+    pub public_key_id: String,
+    pub public_key_value: String,
+}
+
+/// The registry tables of a config: `[registry]` and the registries of the profiles
+fn registries_mut(config: &mut toml::Value) -> Vec<(Option<String>, &mut toml::value::Table)> {
+    let table = match config.as_table_mut() {
+        Some(table) => table,
+        None => return vec![],
+    };
+    let mut registries = vec![];
+    let mut profiles = None;
+    for (key, value) in table.iter_mut() {
+        match key.as_str() {
+            "registry" => {
+                if let Some(registry) = value.as_table_mut() {
+                    registries.push((None, registry));
+                }
+            }
+            "profiles" => profiles = value.as_table_mut(),
+            _ => (),
+        }
+    }
+    for (name, profile) in profiles.into_iter().flatten() {
+        if let Some(registry) = profile
+            .get_mut("registry")
+            .and_then(toml::Value::as_table_mut)
+        {
+            registries.push((Some(name.clone()), registry));
+        }
+    }
+    registries
+}
+
+/// The registries of the config and whether they have a token, taking the tokens out of the
+/// config unless `keep_secrets` is set
+pub fn credentials(config: &mut toml::Value, keep_secrets: bool) -> Vec<Credential> {
+    registries_mut(config)
+        .into_iter()
+        .map(|(profile, registry)| {
+            let logged_in = if keep_secrets {
+                registry.contains_key("token")
+            } else {
+                registry.remove("token").is_some()
+            };
+            Credential {
+                profile,
+                url: registry
+                    .get("url")
+                    .and_then(toml::Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                logged_in,
+            }
+        })
+        .collect()
+}
+
+/// Keep the tokens of the current config for the registries of a restored config that has none,
+/// so restoring a backup without secrets does not log out of registries with the same url
+pub fn keep_current_tokens(restored: &mut toml::Value, current: &mut toml::Value) {
+    let tokens: Vec<(String, toml::Value)> = registries_mut(current)
+        .into_iter()
+        .filter_map(|(_, registry)| {
+            let url = registry.get("url")?.as_str()?.to_string();
+            Some((url, registry.get("token")?.clone()))
+        })
+        .collect();
+    for (_, registry) in registries_mut(restored) {
+        if registry.contains_key("token") {
+            continue;
+        }
+        let url = registry.get("url").and_then(toml::Value::as_str);
+        if let Some((_, token)) = tokens.iter().find(|(u, _)| Some(u.as_str()) == url) {
+            registry.insert("token".to_string(), token.clone());
+        }
+    }
+}
+
+impl BackupMetadata {
+    pub fn new(
+        includes_secrets: bool,
+        credentials: Vec<Credential>,
+        global_packages: Vec<GlobalPackage>,
+        trusted_keys: Vec<TrustedKey>,
+    ) -> Self {
+        BackupMetadata {
+            format_version: BACKUP_FORMAT_VERSION,
+            created_at: Utc::now(),
+            includes_secrets,
+            credentials,
+            global_packages,
+            trusted_keys,
+        }
+    }
+}
+
+/// Write a backup with the metadata and the global config, if there is one
+pub fn write_backup<W: Write>(
+    writer: W,
+    metadata: &BackupMetadata,
+    config_source: Option<&str>,
+) -> Result<(), failure::Error> {
+    let mut builder = Builder::new(GzEncoder::new(writer, Compression::default()));
+    append_file(
+        &mut builder,
+        BACKUP_METADATA_NAME,
+        &serde_json::to_vec_pretty(metadata)?,
+    )?;
+    if let Some(config_source) = config_source {
+        append_file(
+            &mut builder,
+            GLOBAL_CONFIG_FILE_NAME,
+            config_source.as_bytes(),
+        )?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Read the metadata and the global config of a backup
+pub fn read_backup(path: &Path) -> Result<(BackupMetadata, Option<String>), failure::Error> {
+    let invalid =
+        |reason: &str| BackupError::InvalidBackup(path.display().to_string(), reason.into());
+    let mut archive = Archive::new(GzDecoder::new(File::open(path)?));
+    let mut metadata = None;
+    let mut config_source = None;
+    for entry in archive.entries().map_err(|e| invalid(&e.to_string()))? {
+        let mut entry = entry.map_err(|e| invalid(&e.to_string()))?;
+        let entry_path = entry.path()?.to_string_lossy().to_string();
+        let mut source = String::new();
+        entry.read_to_string(&mut source)?;
+        if entry_path == BACKUP_METADATA_NAME {
+            let found: BackupMetadata =
+                serde_json::from_str(&source).map_err(|e| invalid(&e.to_string()))?;
+            if found.format_version != BACKUP_FORMAT_VERSION {
+                return Err(BackupError::UnsupportedFormat(
+                    path.display().to_string(),
+                    found.format_version,
+                    BACKUP_FORMAT_VERSION,
+                )
+                .into());
+            }
+            metadata = Some(found);
+        } else if entry_path == GLOBAL_CONFIG_FILE_NAME {
+            config_source = Some(source);
+        }
+    }
+    let metadata = metadata.ok_or_else(|| invalid("it has no backup.json"))?;
+    Ok((metadata, config_source))
+}
+
+fn append_file<W: Write>(builder: &mut Builder<W>, path: &str, data: &[u8]) -> io::Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o600);
+    header.set_cksum();
+    builder.append_data(&mut header, path, data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[registry]
+url = "https://registry.wapm.io"
+token = "production-token"
+
+[profiles.staging.registry]
+url = "https://staging.wapm.io"
+token = "staging-token"
+
+[profiles.local.registry]
+url = "file:///srv/registry"
+"#;
+
+    #[test]
+    fn backups_leave_out_tokens_unless_asked() {
+        let mut config: toml::Value = toml::from_str(CONFIG).unwrap();
+        let backed_up = credentials(&mut config, false);
+        assert!(!toml::to_string(&config).unwrap().contains("token"));
+        let logged_in: Vec<(Option<&str>, bool)> = backed_up
+            .iter()
+            .map(|c| (c.profile.as_deref(), c.logged_in))
+            .collect();
+        assert_eq!(
+            logged_in,
+            [
+                (None, true),
+                (Some("local"), false),
+                (Some("staging"), true)
+            ]
+        );
+
+        let mut with_secrets: toml::Value = toml::from_str(CONFIG).unwrap();
+        assert_eq!(credentials(&mut with_secrets, true), backed_up);
+        assert!(toml::to_string(&with_secrets)
+            .unwrap()
+            .contains("staging-token"));
+
+        // restoring keeps the tokens the new machine already has for the same registries
+        let mut current: toml::Value =
+            toml::from_str("[registry]\nurl = \"https://registry.wapm.io\"\ntoken = \"new\"\n")
+                .unwrap();
+        keep_current_tokens(&mut config, &mut current);
+        assert_eq!(config["registry"]["token"].as_str(), Some("new"));
+        assert!(config["profiles"]["staging"]["registry"]
+            .get("token")
+            .is_none());
+    }
+
+    #[test]
+    fn backups_can_be_read_back() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("backup.tar.gz");
+        let metadata = BackupMetadata::new(
+            false,
+            vec![],
+            vec![GlobalPackage {
+                name: "_/cowsay".to_string(),
+                version: "0.2.0".to_string(),
+                default: true,
+            }],
+            vec![TrustedKey {
+                user_name: "syrus".to_string(),
+                public_key_id: "ABCD".to_string(),
+                public_key_value: "RWS...".to_string(),
+            }],
+        );
+        write_backup(
+            File::create(&path).unwrap(),
+            &metadata,
+            Some("wax_cooldown = 1\n"),
+        )
+        .unwrap();
+
+        let (read, config_source) = read_backup(&path).unwrap();
+        assert_eq!(read, metadata);
+        assert_eq!(config_source.as_deref(), Some("wax_cooldown = 1\n"));
+    }
+}
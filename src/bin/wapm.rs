@@ -1,15 +1,28 @@
+use std::time::Instant;
 use std::{env, path};
-use structopt::{clap::AppSettings, StructOpt};
+use structopt::{
+    clap::{AppSettings, ErrorKind},
+    StructOpt,
+};
+use wapm_cli::data::toolchain;
 #[cfg(feature = "update-notifications")]
 use wapm_cli::update_notifier;
-use wapm_cli::{commands, logging};
+use wapm_cli::{alias, commands, desktop_notify, diagnostics, error_codes, history, logging};
 
 #[derive(StructOpt, Debug)]
-#[structopt(global_settings = &[AppSettings::VersionlessSubcommands, AppSettings::ColorAuto, AppSettings::ColoredHelp])]
+#[structopt(
+    global_settings = &[AppSettings::VersionlessSubcommands, AppSettings::ColorAuto, AppSettings::ColoredHelp],
+    after_help = "GLOBAL OPTIONS (before the subcommand):
+    --profile <name>       Use the settings of a profile from the config, or set WAPM_PROFILE
+    --trace-http <file>    Record the HTTP requests to the registry in a file, without credentials,
+                           or set WAPM_TRACE_HTTP
+    --deny <categories>    Fail on the warnings of these comma separated categories, or on all of
+                           them with `--deny warnings`, or set WAPM_DENY"
+)]
 enum Command {
     #[structopt(name = "whoami")]
     /// Prints the current user (if authed) in the stdout
-    WhoAmI,
+    WhoAmI(commands::WhoAmIOpt),
 
     #[structopt(name = "login")]
     /// Logins into wapm, saving the token locally for future commands
@@ -19,6 +32,10 @@ enum Command {
     /// Remove the token for the registry
     Logout,
 
+    #[structopt(name = "setup")]
+    /// Set up the registry, the login, telemetry and the PATH the first time wapm is used
+    Setup(commands::SetupOpt),
+
     #[structopt(name = "config")]
     /// Config related subcommands
     Config(commands::ConfigOpt),
@@ -27,10 +44,34 @@ enum Command {
     /// Install a package
     Install(commands::InstallOpt),
 
+    #[structopt(name = "apply")]
+    /// Install the packages of a plan written by `wapm install --plan`
+    Apply(commands::ApplyOpt),
+
     #[structopt(name = "publish")]
     /// Publish a package
     Publish(commands::PublishOpt),
 
+    #[structopt(name = "push")]
+    /// Push the package to an OCI registry, like `wapm push oci://ghcr.io/user/pkg`
+    Push(commands::PushOpt),
+
+    #[structopt(name = "containerize")]
+    /// Write a container image that runs a command of the package
+    Containerize(commands::ContainerizeOpt),
+
+    #[structopt(name = "export")]
+    /// Export a command for a wasm serverless platform: Spin, Fastly Compute or Cloudflare Workers
+    Export(commands::ExportOpt),
+
+    #[structopt(name = "docs")]
+    /// Build a static documentation site for the package
+    Docs(commands::DocsOpt),
+
+    #[structopt(name = "notify")]
+    /// Send the publish webhook for the package in the current directory
+    Notify(commands::NotifyOpt),
+
     #[structopt(
         name = "run",
         settings = &[AppSettings::TrailingVarArg, AppSettings::AllowLeadingHyphen],
@@ -51,10 +92,18 @@ enum Command {
     #[structopt(name = "validate")]
     Validate(commands::ValidateOpt),
 
+    #[structopt(name = "migrate")]
+    /// Rewrite a manifest written for an older version of wapm to the current schema
+    Migrate(commands::MigrateOpt),
+
     #[structopt(name = "completions")]
     /// Generate autocompletion scripts for your shell
     Completions(commands::CompletionOpt),
 
+    #[structopt(name = "complete-commands")]
+    /// List the installed commands with their descriptions, for the completion scripts
+    CompleteCommands(commands::CompleteCommandsOpt),
+
     #[structopt(name = "init")]
     /// Set up current directory for use with wapm
     Init(commands::InitOpt),
@@ -63,19 +112,142 @@ enum Command {
     /// List the currently installed packages and their commands
     List(commands::ListOpt),
 
+    #[structopt(name = "lock")]
+    /// Resolve merge conflicts in the lockfile
+    Lock(commands::LockOpt),
+
+    #[structopt(name = "index")]
+    /// Manage the local snapshot of the registry's package index
+    Index(commands::IndexOpt),
+
+    #[structopt(name = "interface")]
+    /// Manage the interfaces used by the modules of the package
+    Interface(commands::InterfaceOpt),
+
     #[cfg(feature = "packagesigning")]
     #[structopt(name = "keys")]
     /// Manage minisign keys for verifying packages
     Keys(commands::KeyOpt),
 
+    #[structopt(name = "token")]
+    /// Create, list and revoke registry tokens that can only publish some packages
+    Token(commands::TokenOpt),
+
+    #[structopt(name = "audit-log")]
+    /// List the publishes, logins and token operations this machine made on registries
+    AuditLog(commands::AuditLogOpt),
+
+    #[structopt(name = "exception")]
+    /// Record and list the integrity exceptions that let single dependencies through a signature
+    /// or policy check
+    Exception(commands::ExceptionOpt),
+
     #[structopt(name = "uninstall")]
     /// Uninstall a package
     Uninstall(commands::UninstallOpt),
 
+    #[structopt(name = "default")]
+    /// Choose the default version of a package installed globally in several versions
+    Default(commands::DefaultOpt),
+
+    #[structopt(name = "attributions")]
+    /// Collect the licenses and notices of the dependencies into a THIRD_PARTY file
+    Attributions(commands::AttributionsOpt),
+
+    #[structopt(name = "bundle")]
+    /// Pack the dependencies into one file, or install them from it without network access
+    Bundle(commands::BundleOpt),
+
+    #[structopt(name = "foreach")]
+    /// Run a wapm command in many projects at once and report how it went in each
+    Foreach(commands::ForeachOpt),
+
+    #[structopt(name = "generate")]
+    /// Generate Makefile or justfile targets for the commands of the manifest
+    Generate(commands::GenerateOpt),
+
+    #[structopt(name = "backup")]
+    /// Save the global config, global installs and trusted keys into one file for a new machine
+    Backup(commands::BackupOpt),
+
+    #[structopt(name = "restore")]
+    /// Restore a backup made with `wapm backup`, installing the global packages again
+    Restore(commands::RestoreOpt),
+
+    #[structopt(name = "which")]
+    /// Show the module a command runs, and with --verbose its package and sandbox profile
+    Which(commands::WhichOpt),
+
+    #[structopt(name = "convert")]
+    /// Convert a package between a webc container, a .tar.gz archive and a directory
+    Convert(commands::ConvertOpt),
+
+    #[structopt(name = "api")]
+    /// Serve a local HTTP API for editors and other tools
+    Api(commands::ApiOpt),
+
+    #[structopt(name = "alias")]
+    /// Show the aliases defined for long commands in wapm.toml and the config
+    Alias(commands::AliasOpt),
+
+    #[structopt(name = "history")]
+    /// List the changes made to the dependencies of the project
+    History(commands::HistoryOpt),
+
+    #[structopt(name = "undo")]
+    /// Revert the last change made to the dependencies of the project
+    Undo(commands::UndoOpt),
+
+    #[cfg(feature = "browse")]
+    #[structopt(name = "browse")]
+    /// Search the registry, read about packages and install them in a terminal UI
+    Browse(commands::BrowseOpt),
+
+    #[structopt(name = "readme")]
+    /// Show the readme of a package from the registry
+    Readme(commands::ReadmeOpt),
+
+    #[structopt(name = "open")]
+    /// Open the registry page, homepage, repository or documentation of a package in the browser
+    Open(commands::OpenOpt),
+
     #[structopt(name = "bin")]
     /// Get the .bin dir path
     Bin(commands::BinOpt),
 
+    #[structopt(name = "clean")]
+    /// Remove installed packages and generated files
+    Clean(commands::CleanOpt),
+
+    #[structopt(name = "du")]
+    /// Show how much disk space installed packages use
+    Du(commands::DuOpt),
+
+    #[structopt(name = "rehash")]
+    /// Write the shims of the global commands again, and remove those of removed commands
+    Rehash(commands::RehashOpt),
+
+    #[structopt(name = "doctor")]
+    /// Check that wapm and the pinned toolchain are set up, and explain how to fix problems
+    Doctor(commands::DoctorOpt),
+
+    #[structopt(name = "env")]
+    /// Show the environment variables and paths used by wapm
+    Env(commands::EnvOpt),
+
+    #[structopt(name = "detect-abi")]
+    /// Detect the ABI of a wasm module from its imports
+    DetectAbi(commands::DetectAbiOpt),
+
+    #[structopt(name = "exports")]
+    /// List the exports and imports of a module or installed package, with its WASI syscalls
+    /// annotated: wapm exports <pkg or file>
+    Exports(commands::ExportsOpt),
+
+    #[structopt(name = "explain")]
+    /// Explain an error code and how to fix the error
+    Explain(commands::ExplainOpt),
+
     #[cfg(feature = "update-notifications")]
     #[structopt(name = "run-background-update-check")]
     /// Run the background updater explicitly
@@ -89,10 +261,84 @@ enum Command {
     /// Remove packages from the manifest
     Remove(commands::RemoveOpt),
 
+    #[structopt(name = "outdated")]
+    /// List the dependencies that have newer versions in the registry
+    Outdated(commands::OutdatedOpt),
+
+    #[structopt(name = "upgrade", alias = "update")]
+    /// Upgrade dependencies to their latest versions in the manifest and the lockfile
+    Upgrade(commands::UpgradeOpt),
+
+    #[structopt(name = "check-updates")]
+    /// Report the versions available for every dependency without changing anything, with
+    /// --json for bots
+    CheckUpdates(commands::CheckUpdatesOpt),
+
+    #[structopt(name = "diff")]
+    /// Compare the files and manifests of two versions of a package: wapm diff <pkg@ver> [<pkg@ver>]
+    Diff(commands::DiffOpt),
+
+    #[structopt(name = "inspect")]
+    /// List the files, hashes, manifest, sizes and signature of a package archive without
+    /// installing it: wapm inspect <archive|pkg@ver>
+    Inspect(commands::InspectOpt),
+
+    #[structopt(name = "exec")]
+    /// Run a command of a specific installed package: wapm exec --package <pkg> -- <cmd> [args]
+    Exec(commands::ExecOpt),
+
     /// Execute a command, installing it temporarily if necessary
     Execute(commands::ExecuteOpt),
 }
 
+/// The global options, given before the subcommand, and the environment variables they set
+const GLOBAL_OPTIONS: &[(&str, &str)] = &[
+    ("--profile", "WAPM_PROFILE"),
+    ("--trace-http", "WAPM_TRACE_HTTP"),
+    ("--deny", diagnostics::DENY_ENV_VAR),
+];
+
+/// Take the global options out of the arguments and set their environment variables: the
+/// config and the HTTP clients are set up in many places, which all read them from there
+fn take_global_options(args: &mut Vec<String>) {
+    while let Some((env_var, value)) = take_global_option(args) {
+        env::set_var(env_var, value);
+    }
+}
+
+fn take_global_option(args: &mut Vec<String>) -> Option<(&'static str, String)> {
+    let mut option = args.get(1)?.splitn(2, '=');
+    let name = option.next()?;
+    let inline_value = option.next().map(str::to_string);
+    let (_, env_var) = GLOBAL_OPTIONS.iter().find(|(option, _)| *option == name)?;
+    let value = match inline_value {
+        Some(value) => value,
+        None if args.len() > 2 => args.remove(2),
+        None => return None,
+    };
+    args.remove(1);
+    Some((env_var, value))
+}
+
+/// Replace an alias given as the subcommand with the command it stands for
+fn expand_aliases(args: &[String]) -> Result<Vec<String>, failure::Error> {
+    let aliases = alias::Aliases::load(&env::current_dir()?);
+    if aliases.iter().next().is_none() {
+        return Ok(args.to_vec());
+    }
+    // anything clap doesn't reject as an unknown subcommand is a subcommand of wapm
+    let is_subcommand = |name: &str| match Command::clap().get_matches_from_safe(["wapm", name]) {
+        Ok(_) => true,
+        Err(e) => !matches!(
+            e.kind,
+            ErrorKind::UnknownArgument
+                | ErrorKind::InvalidSubcommand
+                | ErrorKind::UnrecognizedSubcommand
+        ),
+    };
+    Ok(aliases.expand(args, is_subcommand)?)
+}
+
 fn main() {
     let is_atty = atty::is(atty::Stream::Stdout);
     if let Err(e) = logging::set_up_logging(is_atty) {
@@ -111,12 +357,40 @@ fn main() {
         }
     };
 
+    let mut cli_args: Vec<String> = env::args().collect();
+    take_global_options(&mut cli_args);
+    if let Err(e) = diagnostics::validate_deny_option() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
     let prog_name = path::PathBuf::from(
-        env::args()
-            .next()
+        cli_args
+            .first()
             .expect("Fatal error could not find any arguments!"),
     );
-    let maybe_subcommand_name = env::args().skip(1).next();
+    if prog_name.file_stem().is_some_and(|stem| stem != "wax") {
+        match expand_aliases(&cli_args) {
+            Ok(expanded) => cli_args = expanded,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    // `wapm run <command> --help` shows the help the manifest declares for the command
+    if let [_, run, command, help] = &cli_args[..] {
+        if run == "run" && (help == "--help" || help == "-h") {
+            if let Some(help) = commands::command_help(command) {
+                print!("{}", help);
+                return;
+            }
+        }
+    }
+    let maybe_subcommand_name = cli_args.get(1).cloned();
+    let command_line = std::iter::once("wapm")
+        .chain(cli_args.iter().skip(1).map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ");
     let prog_name = prog_name
         .file_name()
         .expect("Could not parse argv[0] as a path")
@@ -124,14 +398,14 @@ fn main() {
 
     let args = if prog_name == "wax" {
         Command::Execute(commands::ExecuteOpt::ExecArgs(
-            env::args().skip(1).collect(),
+            cli_args.iter().skip(1).cloned().collect(),
         ))
     } else if maybe_subcommand_name == Some("execute".to_string()) {
         Command::Execute(commands::ExecuteOpt::ExecArgs(
-            env::args().skip(2).collect(),
+            cli_args.iter().skip(2).cloned().collect(),
         ))
     } else {
-        Command::from_args()
+        Command::from_iter(cli_args)
     };
 
     #[cfg(feature = "update-notifications")]
@@ -151,41 +425,152 @@ fn main() {
         _ => false,
     };
 
-    let result = match args {
-        Command::WhoAmI => commands::whoami(),
+    // Commands that work on a project check the toolchain it pins
+    let toolchain_check = match args {
+        Command::Install(_)
+        | Command::Add(_)
+        | Command::Remove(_)
+        | Command::Upgrade(_)
+        | Command::Run(_)
+        | Command::Exec(_)
+        | Command::Publish(_)
+        | Command::Push(_)
+        | Command::Validate(_)
+        | Command::List(_)
+        | Command::Uninstall(_) => {
+            env::current_dir()
+                .map_err(failure::Error::from)
+                .and_then(|dir| {
+                    // manifests of older versions of wapm are offered to be migrated first
+                    commands::offer_migration(&dir)?;
+                    toolchain::check_toolchain_pins(&dir).map_err(failure::Error::from)
+                })
+        }
+        _ => Ok(()),
+    };
+
+    // Long installs and publishes notify the desktop when they finish
+    let notified_operation = match args {
+        Command::Install(_) => Some("install"),
+        Command::Publish(_) => Some("publish"),
+        _ => None,
+    };
+    let started = Instant::now();
+
+    // Commands that change the dependencies of the project are journaled for `wapm undo`
+    let journaled = match args {
+        Command::Install(_)
+        | Command::Add(_)
+        | Command::Remove(_)
+        | Command::Upgrade(_)
+        | Command::Apply(_)
+        | Command::Uninstall(_) => env::current_dir()
+            .ok()
+            .map(|dir| (history::Snapshot::take(&dir), dir)),
+        _ => None,
+    };
+
+    let result = toolchain_check.and_then(|()| match args {
+        Command::WhoAmI(options) => commands::whoami(options),
         Command::Login => commands::login(),
         Command::Logout => commands::logout(),
+        Command::Setup(options) => commands::setup(options),
         Command::Config(config_options) => commands::config(config_options),
         Command::Install(install_options) => commands::install(install_options),
         Command::Add(add_options) => commands::add(add_options),
         Command::Remove(remove_options) => commands::remove(remove_options),
+        Command::Outdated(outdated_options) => commands::outdated(outdated_options),
+        Command::Upgrade(upgrade_options) => commands::upgrade(upgrade_options),
+        Command::CheckUpdates(check_updates_options) => {
+            commands::check_updates(check_updates_options)
+        }
         Command::Publish(publish_options) => commands::publish(publish_options),
+        Command::Push(push_options) => commands::push(push_options),
+        Command::Containerize(containerize_options) => commands::containerize(containerize_options),
+        Command::Export(export_options) => commands::export(export_options),
+        Command::Docs(docs_options) => commands::docs(docs_options),
+        Command::Notify(notify_options) => commands::notify(notify_options),
         Command::Run(run_options) => commands::run(run_options),
+        Command::Diff(diff_options) => commands::diff(diff_options),
+        Command::Inspect(inspect_options) => commands::inspect(inspect_options),
+        Command::Exec(exec_options) => commands::exec(exec_options),
         Command::Execute(execute_options) => commands::execute(execute_options),
         Command::Search(search_options) => commands::search(search_options),
         #[cfg(feature = "package")]
         Command::Package(package_options) => commands::package(package_options),
         Command::Validate(validate_options) => commands::validate(validate_options),
+        Command::Migrate(migrate_options) => commands::migrate(migrate_options),
         Command::Init(init_options) => commands::init(init_options),
         Command::List(list_options) => commands::list(list_options),
+        Command::Lock(lock_options) => commands::lock(lock_options),
+        Command::Index(index_options) => commands::index(index_options),
+        Command::Interface(interface_options) => commands::interface(interface_options),
         #[cfg(feature = "packagesigning")]
         Command::Keys(key_options) => commands::keys(key_options),
+        Command::Token(token_options) => commands::token(token_options),
+        Command::AuditLog(audit_log_options) => commands::audit_log(audit_log_options),
+        Command::Exception(exception_options) => commands::exception(exception_options),
         Command::Completions(completion_options) => {
-            Command::clap().gen_completions_to(
-                "wapm",
-                completion_options.shell,
-                &mut ::std::io::stdout(),
+            let mut script = vec![];
+            Command::clap().gen_completions_to("wapm", completion_options.shell, &mut script);
+            print!(
+                "{}",
+                commands::add_run_command_completions(
+                    completion_options.shell,
+                    String::from_utf8_lossy(&script).to_string()
+                )
             );
             Ok(())
         }
+        Command::CompleteCommands(complete_options) => {
+            commands::complete_commands(complete_options)
+        }
         Command::Uninstall(uninstall_options) => commands::uninstall(uninstall_options),
+        Command::Default(default_options) => commands::default(default_options),
+        Command::Attributions(attributions_options) => commands::attributions(attributions_options),
+        Command::Bundle(bundle_options) => commands::bundle(bundle_options),
+        Command::Foreach(foreach_options) => commands::foreach(foreach_options),
+        Command::Generate(generate_options) => commands::generate(generate_options),
+        Command::Backup(backup_options) => commands::backup(backup_options),
+        Command::Restore(restore_options) => commands::restore(restore_options),
+        Command::Apply(apply_options) => commands::apply(apply_options),
+        Command::Which(which_options) => commands::which(which_options),
+        Command::Convert(convert_options) => commands::convert(convert_options),
+        Command::Api(api_options) => commands::api(api_options),
+        Command::Alias(alias_options) => commands::alias(alias_options),
+        Command::History(history_options) => commands::history(history_options),
+        Command::Undo(undo_options) => commands::undo(undo_options),
+        #[cfg(feature = "browse")]
+        Command::Browse(browse_options) => commands::browse(browse_options),
+        Command::Readme(readme_options) => commands::readme(readme_options),
+        Command::Open(open_options) => commands::open(open_options),
         Command::Bin(bin_options) => commands::bin(bin_options),
+        Command::Clean(clean_options) => commands::clean(clean_options),
+        Command::Du(du_options) => commands::du(du_options),
+        Command::Env(env_options) => commands::env(env_options),
+        Command::Rehash(options) => commands::rehash(options),
+        Command::Doctor(doctor_options) => commands::doctor(doctor_options),
+        Command::DetectAbi(detect_abi_options) => commands::detect_abi(detect_abi_options),
+        Command::Exports(exports_options) => commands::exports(exports_options),
+        Command::Explain(explain_options) => commands::explain(explain_options),
         #[cfg(feature = "update-notifications")]
         Command::BackgroundUpdateCheck => {
             update_notifier::run_subprocess_check();
             Ok(())
         }
-    };
+    });
+
+    if let (Some((before, dir)), Ok(())) = (journaled, &result) {
+        if let Err(e) = history::record(&dir, &command_line, before) {
+            eprintln!("Warning: could not record the change in the history: {}", e);
+        }
+    }
+    // denied warnings fail the command once it is done, so what it changed is still journaled
+    let result = result.and_then(|()| diagnostics::check_denied().map_err(failure::Error::from));
+
+    if let Some(operation) = notified_operation {
+        desktop_notify::notify_if_slow(operation, started.elapsed(), &result);
+    }
 
     // Exit the program, flushing stdout, stderr
     // and show pending notifications (if any)
@@ -196,7 +581,16 @@ fn main() {
     }
 
     if let Err(e) = &result {
-        eprintln!("Error: {}", e);
+        match error_codes::code_for_error(e) {
+            Some(code) => {
+                eprintln!("Error[{}]: {}", code, e);
+                eprintln!(
+                    "For more information about this error, try `wapm explain {}`",
+                    code
+                );
+            }
+            None => eprintln!("Error: {}", e),
+        }
     }
 
     #[cfg(feature = "update-notifications")]
@@ -206,11 +600,12 @@ fn main() {
         }
     }
 
-    if result.is_err() {
+    if let Err(e) = &result {
+        let exit_code = error_codes::exit_code_for_error(e);
         #[cfg(feature = "telemetry")]
         {
             drop(_guard);
         };
-        std::process::exit(-1);
+        std::process::exit(exit_code);
     }
 }
@@ -0,0 +1,463 @@
+//! Bundles carry a project's dependencies to machines without network access.
+//!
+//! A bundle is a gzipped tar archive with:
+//!
+//! - `bundle.json`, always the first entry, listing the packages with the sha256 checksum and
+//!   size of their archives
+//! - the project's `wapm.lock`, and its `wapm.toml` if it has one
+//! - `archives/<namespace>/<package>@<version>.tar.gz`, the package archives as published
+//!
+//! Installing a bundle checks every archive against its checksum before extracting any, and
+//! skips packages that are already installed, so an interrupted install can be resumed.
+
+use crate::archive::{self, ExtractionLimits};
+use crate::data::lock::LOCKFILE_NAME;
+use crate::data::manifest::MANIFEST_FILE_NAME;
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::registry;
+use crate::util::{create_package_dir, get_package_namespace_and_name, sha256_hex, validate_name};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use semver::Version;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use tar::{Archive, Builder, EntryType, Header};
+
+const BUNDLE_METADATA_NAME: &str = "bundle.json";
+const BUNDLE_FORMAT_VERSION: u32 = 1;
+const ARCHIVES_DIR: &str = "archives";
+
+#[derive(Debug, Fail)]
+pub enum BundleError {
+    #[fail(display = "No wapm.lock found in {}, run `wapm install` first", _0)]
+    NoLockfile(String),
+    #[fail(display = "Could not download {}@{} from {}: {}", _0, _1, _2, _3)]
+    CouldNotDownload(String, String, String, String),
+    #[fail(display = "{} is not a wapm bundle: {}", _0, _1)]
+    InvalidBundle(String, String),
+    #[fail(
+        display = "The bundle was made with format version {}, this version of wapm reads version {}",
+        _0, _1
+    )]
+    UnsupportedFormatVersion(u32, u32),
+    #[fail(
+        display = "The archive of {}@{} in the bundle is corrupted: expected the checksum {}, found {}",
+        _0, _1, _2, _3
+    )]
+    ChecksumMismatch(String, String, String, String),
+    #[fail(display = "The bundle has no archive for {}@{}", _0, _1)]
+    MissingArchive(String, String),
+}
+
+/// The table of contents of a bundle
+#[derive(Debug, Deserialize, Serialize)]
+struct BundleMetadata {
+    format_version: u32,
+    packages: Vec<BundledPackage>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct BundledPackage {
+    name: String,
+    version: String,
+    /// The path of the archive in the bundle
+    archive: String,
+    sha256: String,
+    size: u64,
+}
+
+/// What installing a bundle did
+#[derive(Debug, Default)]
+pub struct BundleInstallSummary {
+    pub installed: Vec<String>,
+    pub already_installed: Vec<String>,
+}
+
+/// Bundle the lockfile, the manifest and the archives of the registry packages of the project
+/// in `directory`. Returns the number of bundled packages.
+pub fn create_bundle(directory: &Path, output: &Path) -> Result<usize, failure::Error> {
+    let lockfile = match LockfileResult::find_in_directory(directory) {
+        LockfileResult::Lockfile(lockfile) => lockfile,
+        LockfileResult::LockfileError(e) => return Err(e.into()),
+        LockfileResult::NoLockfile => {
+            return Err(BundleError::NoLockfile(directory.display().to_string()).into())
+        }
+    };
+    let lockfile_source = fs::read_to_string(directory.join(LOCKFILE_NAME))?;
+
+    let client = registry::http_client()?;
+    let mut archives: Vec<(BundledPackage, Vec<u8>)> = vec![];
+    for (name, versions) in lockfile.modules.iter() {
+        for (version, modules) in versions.iter() {
+            // every module of a package version is resolved from the same archive
+            let module = match modules.values().next() {
+                Some(module) => module,
+                None => continue,
+            };
+            if !module.resolved_source.starts_with("registry+") {
+                if module.resolved != "local" {
+                    warn!(
+                        "{}@{} was not installed from a registry and is not bundled",
+                        name, version
+                    );
+                }
+                continue;
+            }
+            let version = version.to_string();
+            let data = download_archive(&client, &module.resolved).map_err(|e| {
+                BundleError::CouldNotDownload(
+                    name.clone(),
+                    version.clone(),
+                    module.resolved.clone(),
+                    e.to_string(),
+                )
+            })?;
+            let (namespace, package_name) = get_package_namespace_and_name(name)?;
+            archives.push((
+                BundledPackage {
+                    name: name.clone(),
+                    archive: format!(
+                        "{}/{}/{}@{}.tar.gz",
+                        ARCHIVES_DIR, namespace, package_name, version
+                    ),
+                    version,
+                    sha256: sha256_hex(&data),
+                    size: data.len() as u64,
+                },
+                data,
+            ));
+        }
+    }
+
+    let (packages, archive_data): (Vec<_>, Vec<_>) = archives.into_iter().unzip();
+    let metadata = BundleMetadata {
+        format_version: BUNDLE_FORMAT_VERSION,
+        packages,
+    };
+    let package_count = metadata.packages.len();
+    let mut builder = Builder::new(GzEncoder::new(
+        File::create(output)?,
+        Compression::default(),
+    ));
+    append_file(
+        &mut builder,
+        BUNDLE_METADATA_NAME,
+        &serde_json::to_vec_pretty(&metadata)?,
+    )?;
+    append_file(&mut builder, LOCKFILE_NAME, lockfile_source.as_bytes())?;
+    let manifest_path = directory.join(MANIFEST_FILE_NAME);
+    if manifest_path.is_file() {
+        append_file(&mut builder, MANIFEST_FILE_NAME, &fs::read(manifest_path)?)?;
+    }
+    for (package, data) in metadata.packages.iter().zip(archive_data) {
+        append_file(&mut builder, &package.archive, &data)?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(package_count)
+}
+
+fn download_archive(
+    client: &reqwest::blocking::Client,
+    url: &str,
+) -> Result<Vec<u8>, failure::Error> {
+    if let Some(path) = registry::file_url_path(url) {
+        return Ok(fs::read(path)?);
+    }
+    let mut response = client
+        .get(&registry::fetch_url(url)?)
+        .send()?
+        .error_for_status()?;
+    let mut data = vec![];
+    response.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn append_file<W: Write>(builder: &mut Builder<W>, path: &str, data: &[u8]) -> io::Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, path, data)
+}
+
+/// Restore the lockfile, the manifest if the project has none, and the packages of a bundle
+/// into `directory`. Only the archives of packages that aren't installed yet are extracted.
+///
+/// The bundle is read twice: first to check the checksums of all the archives, then to extract
+/// them. Each package is extracted next to its final directory and moved into place, and the
+/// lockfile is written last, so a failed install leaves the project as it was apart from the
+/// packages that were completely installed.
+pub fn install_bundle(
+    bundle_path: &Path,
+    directory: &Path,
+) -> Result<BundleInstallSummary, failure::Error> {
+    let invalid =
+        |reason: &str| BundleError::InvalidBundle(bundle_path.display().to_string(), reason.into());
+    let mut archive = Archive::new(GzDecoder::new(File::open(bundle_path)?));
+    let mut entries = archive.entries()?;
+    let metadata = read_metadata(&mut entries, &invalid)?;
+    let packages: BTreeMap<&str, &BundledPackage> = metadata
+        .packages
+        .iter()
+        .map(|package| (package.archive.as_str(), package))
+        .collect();
+
+    let mut lockfile = None;
+    let mut manifest = None;
+    let mut checked = BTreeSet::new();
+    for entry in entries {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().to_string();
+        if path == LOCKFILE_NAME {
+            lockfile = Some(read_regular_file(&mut entry, &invalid)?);
+        } else if path == MANIFEST_FILE_NAME {
+            manifest = Some(read_regular_file(&mut entry, &invalid)?);
+        } else if let Some(package) = packages.get(path.as_str()) {
+            read_checked_archive(&mut entry, package)?;
+            checked.insert(path);
+        } else {
+            debug!("Skipping unknown bundle entry {}", path);
+        }
+    }
+    let lockfile = lockfile.ok_or_else(|| invalid("it has no wapm.lock"))?;
+    if let Some(missing) = metadata
+        .packages
+        .iter()
+        .find(|package| !checked.contains(&package.archive))
+    {
+        return Err(
+            BundleError::MissingArchive(missing.name.clone(), missing.version.clone()).into(),
+        );
+    }
+
+    let mut summary = BundleInstallSummary::default();
+    let mut archive = Archive::new(GzDecoder::new(File::open(bundle_path)?));
+    let mut entries = archive.entries()?;
+    read_metadata(&mut entries, &invalid)?;
+    for entry in entries {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().to_string();
+        let package = match packages.get(path.as_str()) {
+            Some(package) => package,
+            None => continue,
+        };
+        let label = format!("{}@{}", package.name, package.version);
+        let package_dir = package_dir(directory, package).map_err(|reason| invalid(&reason))?;
+        if package_dir.join(MANIFEST_FILE_NAME).is_file() {
+            summary.already_installed.push(label);
+            continue;
+        }
+        // the bundle could have changed since it was checked
+        let data = read_checked_archive(&mut entry, package)?;
+        let parent = package_dir.parent().unwrap_or(directory);
+        let extracted = tempfile::Builder::new()
+            .prefix(".bundle-")
+            .tempdir_in(parent)?;
+        archive::unpack(&data[..], extracted.path(), &ExtractionLimits::default())?;
+        // the directory is left empty by `package_dir`, or partly filled by an older wapm
+        fs::remove_dir_all(&package_dir)?;
+        fs::rename(extracted.path(), &package_dir)?;
+        summary.installed.push(label);
+    }
+
+    let manifest_path = directory.join(MANIFEST_FILE_NAME);
+    match manifest {
+        Some(_) if manifest_path.exists() => {
+            debug!("Keeping the existing manifest of the project")
+        }
+        Some(contents) => fs::write(manifest_path, contents)?,
+        None => {}
+    }
+    fs::write(directory.join(LOCKFILE_NAME), lockfile)?;
+    Ok(summary)
+}
+
+/// Read `bundle.json`, the first entry of a bundle
+fn read_metadata<R: Read>(
+    entries: &mut tar::Entries<R>,
+    invalid: &dyn Fn(&str) -> BundleError,
+) -> Result<BundleMetadata, failure::Error> {
+    let mut entry = entries.next().ok_or_else(|| invalid("it is empty"))??;
+    if entry.path()?.to_string_lossy() != BUNDLE_METADATA_NAME {
+        return Err(invalid("it does not start with bundle.json").into());
+    }
+    let mut source = String::new();
+    entry.read_to_string(&mut source)?;
+    let metadata: BundleMetadata =
+        serde_json::from_str(&source).map_err(|e| invalid(&e.to_string()))?;
+    if metadata.format_version != BUNDLE_FORMAT_VERSION {
+        return Err(BundleError::UnsupportedFormatVersion(
+            metadata.format_version,
+            BUNDLE_FORMAT_VERSION,
+        )
+        .into());
+    }
+    Ok(metadata)
+}
+
+/// Read the archive of a package, checking it against the checksum of `bundle.json`
+fn read_checked_archive<R: Read>(
+    entry: &mut tar::Entry<R>,
+    package: &BundledPackage,
+) -> Result<Vec<u8>, failure::Error> {
+    let mut data = Vec::with_capacity(package.size as usize);
+    entry.read_to_end(&mut data)?;
+    let sha256 = sha256_hex(&data);
+    if sha256 != package.sha256 {
+        return Err(BundleError::ChecksumMismatch(
+            package.name.clone(),
+            package.version.clone(),
+            package.sha256.clone(),
+            sha256,
+        )
+        .into());
+    }
+    Ok(data)
+}
+
+/// The lockfile and manifest are written by hand instead of unpacked, so a link in their place
+/// can't make the install write outside of the project
+fn read_regular_file<R: Read>(
+    entry: &mut tar::Entry<R>,
+    invalid: &dyn Fn(&str) -> BundleError,
+) -> Result<Vec<u8>, failure::Error> {
+    if entry.header().entry_type() != EntryType::Regular {
+        let path = entry.path()?.to_string_lossy().to_string();
+        return Err(invalid(&format!("{} is not a regular file", path)).into());
+    }
+    let mut contents = vec![];
+    entry.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// The directory of a bundled package, refusing the names of `bundle.json` that aren't package
+/// names since they become paths
+fn package_dir(directory: &Path, package: &BundledPackage) -> Result<PathBuf, String> {
+    let (namespace, package_name) =
+        get_package_namespace_and_name(&package.name).map_err(|e| e.to_string())?;
+    for name in [namespace, package_name].iter() {
+        validate_name(name)
+            .map_err(|e| format!("invalid package name \"{}\": {}", package.name, e))?;
+    }
+    Version::parse(&package.version).map_err(|e| {
+        format!(
+            "invalid version \"{}\" of {}: {}",
+            package.version, package.name, e
+        )
+    })?;
+    create_package_dir(
+        directory,
+        namespace,
+        &format!("{}@{}", package_name, package.version),
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bundles_are_checked_and_installed() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let package_archive = {
+            let mut builder = Builder::new(GzEncoder::new(vec![], Compression::default()));
+            append_file(
+                &mut builder,
+                MANIFEST_FILE_NAME,
+                b"[package]\nname = \"_/sqlite\"\nversion = \"0.1.1\"\ndescription = \"\"\n",
+            )
+            .unwrap();
+            builder.into_inner().unwrap().finish().unwrap()
+        };
+        let write_bundle = |path: &Path, name: &str, sha256: &str, link_lockfile: bool| {
+            let metadata = BundleMetadata {
+                format_version: BUNDLE_FORMAT_VERSION,
+                packages: vec![BundledPackage {
+                    name: name.to_string(),
+                    version: "0.1.1".to_string(),
+                    archive: "archives/_/sqlite@0.1.1.tar.gz".to_string(),
+                    sha256: sha256.to_string(),
+                    size: package_archive.len() as u64,
+                }],
+            };
+            let mut builder = Builder::new(GzEncoder::new(
+                File::create(path).unwrap(),
+                Compression::default(),
+            ));
+            append_file(
+                &mut builder,
+                BUNDLE_METADATA_NAME,
+                &serde_json::to_vec(&metadata).unwrap(),
+            )
+            .unwrap();
+            if link_lockfile {
+                let mut header = Header::new_gnu();
+                header.set_entry_type(EntryType::Symlink);
+                header.set_size(0);
+                builder
+                    .append_link(&mut header, LOCKFILE_NAME, "/etc/passwd")
+                    .unwrap();
+            } else {
+                append_file(&mut builder, LOCKFILE_NAME, b"").unwrap();
+            }
+            append_file(
+                &mut builder,
+                &metadata.packages[0].archive,
+                &package_archive,
+            )
+            .unwrap();
+            builder.into_inner().unwrap().finish().unwrap();
+        };
+
+        let corrupted = tmp_dir.path().join("corrupted.wapmbundle");
+        write_bundle(&corrupted, "_/sqlite", "0000", false);
+        let project = tmp_dir.path().join("corrupted");
+        fs::create_dir(&project).unwrap();
+        fs::write(project.join(LOCKFILE_NAME), "old lockfile").unwrap();
+        assert!(install_bundle(&corrupted, &project).is_err());
+        assert_eq!(
+            fs::read_to_string(project.join(LOCKFILE_NAME)).unwrap(),
+            "old lockfile"
+        );
+        assert!(!project.join("wapm_packages").exists());
+
+        let sha256 = sha256_hex(&package_archive);
+        let linked = tmp_dir.path().join("linked.wapmbundle");
+        write_bundle(&linked, "_/sqlite", &sha256, true);
+        let project = tmp_dir.path().join("linked");
+        fs::create_dir(&project).unwrap();
+        assert!(install_bundle(&linked, &project).is_err());
+        assert!(fs::symlink_metadata(project.join(LOCKFILE_NAME)).is_err());
+
+        let escaping = tmp_dir.path().join("escaping.wapmbundle");
+        write_bundle(&escaping, "../sqlite", &sha256, false);
+        assert!(install_bundle(&escaping, &project).is_err());
+        assert!(!project.join("sqlite@0.1.1").exists());
+
+        let bundle = tmp_dir.path().join("out.wapmbundle");
+        write_bundle(&bundle, "_/sqlite", &sha256, false);
+        let project = tmp_dir.path().join("project");
+        fs::create_dir(&project).unwrap();
+        let summary = install_bundle(&bundle, &project).unwrap();
+        assert_eq!(summary.installed, vec!["_/sqlite@0.1.1"]);
+        assert!(project
+            .join("wapm_packages/_/sqlite@0.1.1")
+            .join(MANIFEST_FILE_NAME)
+            .is_file());
+        let summary = install_bundle(&bundle, &project).unwrap();
+        assert_eq!(summary.already_installed, vec!["_/sqlite@0.1.1"]);
+
+        // a package extracted partway by an interrupted install is extracted again
+        let package_dir = project.join("wapm_packages/_/sqlite@0.1.1");
+        fs::remove_file(package_dir.join(MANIFEST_FILE_NAME)).unwrap();
+        fs::write(package_dir.join("partial"), "").unwrap();
+        let summary = install_bundle(&bundle, &project).unwrap();
+        assert_eq!(summary.installed, vec!["_/sqlite@0.1.1"]);
+        assert!(package_dir.join(MANIFEST_FILE_NAME).is_file());
+        assert!(!package_dir.join("partial").exists());
+    }
+}
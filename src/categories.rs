@@ -0,0 +1,188 @@
+//! Keywords and categories of packages, which `wapm search --keyword` and `--category` filter on.
+//!
+//! Keywords are free form, within the limits the registry indexes. Categories come from the
+//! taxonomy of the registry, so they are checked against it before a publish. Registries without
+//! a taxonomy accept any category.
+
+use crate::config::{Config, RegistryBackendKind};
+use crate::data::manifest::Package;
+use crate::graphql::execute_raw_query;
+use serde_json::json;
+
+const CATEGORIES_QUERY: &str = "query CategoriesQuery {
+  categories {
+    slug
+  }
+}";
+
+/// The most keywords a package can have
+pub const MAX_KEYWORDS: usize = 5;
+const MAX_KEYWORD_LENGTH: usize = 20;
+
+#[derive(Debug, Fail)]
+pub enum CategoryError {
+    #[fail(
+        display = "The package has {} keywords, the registry indexes at most {}",
+        _0, _1
+    )]
+    TooManyKeywords(usize, usize),
+    #[fail(
+        display = "Invalid keyword \"{}\", keywords have up to {} letters, digits, `-` or `_` and start with a letter",
+        _0, _1
+    )]
+    InvalidKeyword(String, usize),
+    #[fail(
+        display = "Unknown category \"{}\", the categories of the registry are: {}",
+        _0, _1
+    )]
+    UnknownCategory(String, String),
+}
+
+#[derive(Debug, Deserialize)]
+struct Category {
+    slug: String,
+}
+
+fn check_keyword(keyword: &str) -> Result<(), CategoryError> {
+    let valid = keyword.len() <= MAX_KEYWORD_LENGTH
+        && keyword
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic())
+        && keyword
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(CategoryError::InvalidKeyword(
+            keyword.to_string(),
+            MAX_KEYWORD_LENGTH,
+        ))
+    }
+}
+
+/// Check the keywords of a package and that its categories are in `taxonomy`
+fn check(package: &Package, taxonomy: Option<&[String]>) -> Result<(), CategoryError> {
+    if package.keywords.len() > MAX_KEYWORDS {
+        return Err(CategoryError::TooManyKeywords(
+            package.keywords.len(),
+            MAX_KEYWORDS,
+        ));
+    }
+    for keyword in package.keywords.iter() {
+        check_keyword(keyword)?;
+    }
+    if let Some(taxonomy) = taxonomy {
+        if let Some(unknown) = package
+            .categories
+            .iter()
+            .find(|category| !taxonomy.contains(category))
+        {
+            return Err(CategoryError::UnknownCategory(
+                unknown.clone(),
+                taxonomy.join(", "),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The category slugs of the registry, or `None` when the registry has no taxonomy
+fn registry_taxonomy() -> Result<Option<Vec<String>>, failure::Error> {
+    let config = Config::from_file()?;
+    if config.registry.backend_kind() != RegistryBackendKind::Graphql {
+        return Ok(None);
+    }
+    let response: serde_json::Value =
+        match execute_raw_query(CATEGORIES_QUERY, "CategoriesQuery", &json!({})) {
+            Ok(response) => response,
+            Err(e) => {
+                debug!("Could not fetch the categories of the registry: {}", e);
+                return Ok(None);
+            }
+        };
+    let categories: Option<Vec<Category>> = serde_json::from_value(response["categories"].clone())?;
+    Ok(categories.map(|categories| categories.into_iter().map(|c| c.slug).collect()))
+}
+
+/// Check the keywords and categories of a package before it is published
+pub fn check_package(package: &Package) -> Result<(), failure::Error> {
+    let taxonomy = if package.categories.is_empty() {
+        None
+    } else {
+        registry_taxonomy()?
+    };
+    check(package, taxonomy.as_deref())?;
+    Ok(())
+}
+
+/// Whether a package has all the keywords and categories searched for, ignoring case
+pub fn matches(
+    keywords: &[String],
+    categories: &[String],
+    wanted_keywords: &[String],
+    wanted_categories: &[String],
+) -> bool {
+    let has_all = |values: &[String], wanted: &[String]| {
+        wanted.iter().all(|wanted| {
+            values
+                .iter()
+                .any(|value| value.eq_ignore_ascii_case(wanted))
+        })
+    };
+    has_all(keywords, wanted_keywords) && has_all(categories, wanted_categories)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::manifest::Manifest;
+
+    #[test]
+    fn keywords_and_categories_are_checked() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+[package]
+name = "user/resize"
+version = "1.0.0"
+description = "Resize images"
+keywords = ["image", "resize"]
+categories = ["cli", "multimedia"]
+"#,
+        )
+        .unwrap();
+        let mut package = manifest.package;
+        let taxonomy = vec!["cli".to_string(), "multimedia".to_string()];
+        assert!(check(&package, Some(&taxonomy)).is_ok());
+        assert!(check(&package, None).is_ok());
+        assert!(matches!(
+            check(&package, Some(&taxonomy[..1])),
+            Err(CategoryError::UnknownCategory(category, _)) if category == "multimedia"
+        ));
+
+        package.keywords.push("not a keyword".to_string());
+        assert!(matches!(
+            check(&package, None),
+            Err(CategoryError::InvalidKeyword(..))
+        ));
+        package.keywords = (0..6).map(|i| format!("k{}", i)).collect();
+        assert!(matches!(
+            check(&package, None),
+            Err(CategoryError::TooManyKeywords(6, MAX_KEYWORDS))
+        ));
+
+        let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        let keywords = strings(&["image", "resize"]);
+        let categories = strings(&["cli"]);
+        assert!(matches(
+            &keywords,
+            &categories,
+            &strings(&["Image"]),
+            &strings(&["cli"])
+        ));
+        assert!(matches(&keywords, &categories, &[], &[]));
+        assert!(!matches(&keywords, &categories, &strings(&["video"]), &[]));
+        assert!(!matches(&keywords, &categories, &[], &strings(&["web"])));
+    }
+}
@@ -1,10 +1,12 @@
 //! Code pertaining to the `add` subcommand: it adds dependencies to
 //! the manifest without installing
 
-use crate::graphql::execute_query;
-use graphql_client::*;
-
 use crate::data::manifest::Manifest;
+use crate::integrity_exceptions::IntegrityExceptions;
+use crate::moved_packages;
+use crate::policy::Policy;
+use crate::registry;
+use chrono::Utc;
 use structopt::StructOpt;
 
 /// Options for the `add` subcommand
@@ -13,14 +15,6 @@ pub struct AddOpt {
     packages: Vec<String>,
 }
 
-#[derive(GraphQLQuery)]
-#[graphql(
-    schema_path = "graphql/schema.graphql",
-    query_path = "graphql/queries/get_package_version.graphql",
-    response_derives = "Debug"
-)]
-struct GetPackageVersionQuery;
-
 #[derive(Debug, Fail)]
 enum AddError {
     #[fail(display = "There were problems adding packages")]
@@ -36,32 +30,60 @@ enum AddError {
 /// Run the add command
 pub fn add(options: AddOpt) -> Result<(), failure::Error> {
     let mut error = false;
-    let mut manifest: Manifest = {
-        let cur_dir = std::env::current_dir()?;
-        Manifest::find_in_directory(cur_dir).map_err(|_| AddError::NoManifest)?
-    };
+    let cur_dir = std::env::current_dir()?;
+    let mut manifest: Manifest =
+        Manifest::find_in_directory(&cur_dir).map_err(|_| AddError::NoManifest)?;
 
     if options.packages.is_empty() {
         return Err(AddError::ArgumentsRequired.into());
     }
+    let policy = Policy::load()?;
+    let exceptions = IntegrityExceptions::load(&cur_dir)?;
+    let requested: Vec<(String, Option<String>)> = options
+        .packages
+        .into_iter()
+        .map(|package_str| {
+            if package_str.contains('@') {
+                let mut p = package_str.split('@');
+                let package_name = p.next().unwrap();
+                let package_version = p.next().unwrap();
+                (package_name.to_string(), Some(package_version.to_string()))
+            } else {
+                (package_str, None)
+            }
+        })
+        .collect();
 
-    for (package_name, maybe_version) in options.packages.into_iter().map(|package_str| {
-        if package_str.contains('@') {
-            let mut p = package_str.split('@');
-            let package_name = p.next().unwrap();
-            let package_version = p.next().unwrap();
-            (package_name.to_string(), Some(package_version.to_string()))
-        } else {
-            (package_str, None)
-        }
-    }) {
-        let q = GetPackageVersionQuery::build_query(get_package_version_query::Variables {
-            name: package_name.clone(),
-            version: maybe_version.clone(),
-        });
-        let response: get_package_version_query::ResponseData = execute_query(&q)?;
+    // look up all the packages in one request, their versions say which packages moved
+    if let Err(e) = registry::backend()?.prefetch_package_versions(&requested) {
+        debug!("Could not look up the packages in one request: {}", e);
+    }
+    let names: Vec<String> = requested.iter().map(|(name, _)| name.clone()).collect();
+    let requested_versions = registry::backend()?.package_versions(&names)?;
 
-        if let Some(pv) = response.package_version {
+    for (package_name, maybe_version) in requested {
+        let package_name =
+            moved_packages::follow_moved_package(&package_name, &requested_versions)?;
+        let package_version =
+            registry::backend()?.package_version(&package_name, maybe_version.as_deref())?;
+
+        if let Some(pv) = package_version {
+            if let Some(policy) = &policy {
+                // only the list of all versions has the metadata that policies check
+                let package_versions =
+                    registry::backend()?.package_versions(std::slice::from_ref(&package_name))?;
+                let package_version = package_versions
+                    .iter()
+                    .find(|version| version.version == pv.version)
+                    .unwrap_or(&pv);
+                if let Some(violation) =
+                    policy.check_with_exceptions(package_version, Utc::now(), &exceptions)
+                {
+                    error = true;
+                    error!("The install policy does not allow {}", violation);
+                    continue;
+                }
+            }
             info!("Adding {}@{}", &package_name, &pv.version);
             manifest.add_dependency(package_name, pv.version);
         } else {
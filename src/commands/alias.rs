@@ -0,0 +1,29 @@
+//! Code pertaining to the `alias` subcommand: it shows the aliases defined for long commands
+
+use crate::alias::Aliases;
+use std::env;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub enum AliasOpt {
+    #[structopt(name = "list")]
+    /// List the aliases of the project and of the config
+    List,
+}
+
+pub fn alias(options: AliasOpt) -> Result<(), failure::Error> {
+    match options {
+        AliasOpt::List => {
+            let aliases = Aliases::load(&env::current_dir()?);
+            let mut any = false;
+            for (name, alias) in aliases.iter() {
+                println!("{} = {:?} ({})", name, alias.command, alias.source);
+                any = true;
+            }
+            if !any {
+                println!("No aliases are defined. Add them to the [alias] section of wapm.toml");
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,39 @@
+//! Code pertaining to the `api` subcommand: it serves the local HTTP API of `crate::api`
+
+use crate::api;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ApiOpt {
+    /// The address to listen on. Port 0 picks a free port
+    #[structopt(long = "listen", default_value = "127.0.0.1:0")]
+    listen: String,
+    /// The file with the token requests must send, created with a new token if it doesn't
+    /// exist. Defaults to `api_token` in the wapm directory
+    #[structopt(long = "token-file", parse(from_os_str))]
+    token_file: Option<PathBuf>,
+}
+
+pub fn api(options: ApiOpt) -> Result<(), failure::Error> {
+    let token_path = match options.token_file {
+        Some(path) => path,
+        None => api::default_token_path()?,
+    };
+    let token = api::load_or_create_token(&token_path)?;
+    let listener = TcpListener::bind(&options.listen)?;
+    let address = listener.local_addr()?;
+    if !address.ip().is_loopback() {
+        warn!(
+            "The API is reachable from other machines on {}, anyone with the token can run commands",
+            address
+        );
+    }
+    println!(
+        "wapm API listening on http://{} (token in {})",
+        address,
+        token_path.display()
+    );
+    api::serve(listener, token)
+}
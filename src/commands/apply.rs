@@ -0,0 +1,25 @@
+//! Code pertaining to the `apply` subcommand: it runs the install of a plan written by
+//! `wapm install --plan`, refusing to install anything other than what the plan lists
+
+use crate::commands::install;
+use crate::dataflow::install_plan::{self, PlanFile};
+use std::env;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ApplyOpt {
+    /// The plan written by `wapm install --plan`
+    #[structopt(parse(from_os_str))]
+    plan: PathBuf,
+}
+
+pub fn apply(options: ApplyOpt) -> Result<(), failure::Error> {
+    let plan = PlanFile::load(&options.plan)?;
+    plan.check_project()?;
+    if !plan.request.global {
+        env::set_current_dir(&plan.directory)?;
+    }
+    install_plan::set_applied_plan(plan.plan.packages);
+    install::install_request(&plan.request)
+}
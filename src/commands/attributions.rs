@@ -0,0 +1,191 @@
+//! Subcommand for collecting the licenses and notices of the dependencies into one file, for
+//! redistributing apps built from wapm packages
+
+use crate::data::manifest::Manifest;
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::util::{get_package_namespace_and_name, get_packages_dir};
+use semver::Version;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::{env, io};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct AttributionsOpt {
+    /// The file to write the attributions to
+    #[structopt(
+        long = "output",
+        short = "o",
+        default_value = "THIRD_PARTY",
+        parse(from_os_str)
+    )]
+    output: PathBuf,
+}
+
+#[derive(Debug, Fail)]
+enum AttributionsError {
+    #[fail(display = "No wapm.lock found in {}, run `wapm install` first", _0)]
+    NoLockfile(String),
+}
+
+/// Files with these name prefixes are collected from the packages
+const ATTRIBUTION_FILE_PREFIXES: &[&str] = &["LICENSE", "LICENCE", "COPYING", "NOTICE"];
+
+const SEPARATOR: &str =
+    "================================================================================";
+
+/// The license and attribution files of a package
+struct Attribution {
+    name: String,
+    version: Version,
+    license: Option<String>,
+    files: Vec<(String, String)>,
+}
+
+pub fn attributions(options: AttributionsOpt) -> Result<(), failure::Error> {
+    let current_dir = env::current_dir()?;
+    let lockfile = match LockfileResult::find_in_directory(&current_dir) {
+        LockfileResult::Lockfile(lockfile) => lockfile,
+        LockfileResult::LockfileError(e) => return Err(e.into()),
+        LockfileResult::NoLockfile => {
+            return Err(AttributionsError::NoLockfile(current_dir.display().to_string()).into())
+        }
+    };
+    // the package of the project itself is not a third party
+    let own_package = Manifest::find_in_directory(&current_dir)
+        .ok()
+        .map(|manifest| manifest.package.name);
+    let packages_dir = get_packages_dir(&current_dir);
+
+    let mut attributions = vec![];
+    for (name, versions) in lockfile.modules.iter() {
+        if Some(name) == own_package.as_ref() {
+            continue;
+        }
+        for version in versions.keys() {
+            let (namespace, package_name) = get_package_namespace_and_name(name)?;
+            let package_dir = packages_dir
+                .join(namespace)
+                .join(format!("{}@{}", package_name, version));
+            attributions.push(collect_attribution(name, version, &package_dir)?);
+        }
+    }
+
+    let without_files: Vec<String> = attributions
+        .iter()
+        .filter(|attribution| attribution.files.is_empty())
+        .map(|attribution| format!("{}@{}", attribution.name, attribution.version))
+        .collect();
+    if !without_files.is_empty() {
+        warn!(
+            "No license or notice files found for: {}",
+            without_files.join(", ")
+        );
+    }
+    fs::write(&options.output, render(&attributions))?;
+    println!(
+        "Wrote the attributions of {} packages to {}",
+        attributions.len(),
+        options.output.display()
+    );
+    Ok(())
+}
+
+fn collect_attribution(
+    name: &str,
+    version: &Version,
+    package_dir: &Path,
+) -> Result<Attribution, io::Error> {
+    let license = Manifest::find_in_directory(package_dir)
+        .ok()
+        .and_then(|manifest| manifest.package.license);
+    let mut files = vec![];
+    if package_dir.is_dir() {
+        for entry in fs::read_dir(package_dir)? {
+            let path = entry?.path();
+            let file_name = match path.file_name() {
+                Some(file_name) => file_name.to_string_lossy().to_string(),
+                None => continue,
+            };
+            let upper_case_name = file_name.to_uppercase();
+            if path.is_file()
+                && ATTRIBUTION_FILE_PREFIXES
+                    .iter()
+                    .any(|prefix| upper_case_name.starts_with(prefix))
+            {
+                files.push((file_name, fs::read_to_string(&path)?));
+            }
+        }
+    }
+    files.sort();
+    Ok(Attribution {
+        name: name.to_string(),
+        version: version.clone(),
+        license,
+        files,
+    })
+}
+
+fn render(attributions: &[Attribution]) -> String {
+    let mut out = String::new();
+    out.push_str("This file lists the third party packages used by this project and their licenses and notices.\n");
+    for attribution in attributions {
+        // writing to a String can't fail
+        let _ = writeln!(out, "\n{}", SEPARATOR);
+        let _ = writeln!(out, "{} {}", attribution.name, attribution.version);
+        if let Some(license) = &attribution.license {
+            let _ = writeln!(out, "License: {}", license);
+        }
+        let _ = writeln!(out, "{}", SEPARATOR);
+        if attribution.files.is_empty() {
+            out.push_str("\nNo license or notice files were found in this package.\n");
+        }
+        for (file_name, contents) in attribution.files.iter() {
+            let _ = writeln!(out, "\n{}:\n", file_name);
+            out.push_str(contents.trim_end());
+            out.push('\n');
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn collect_license_and_notice_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let package_dir = dir.path();
+        fs::write(
+            package_dir.join("wapm.toml"),
+            "[package]\nname = \"_/lib\"\nversion = \"1.0.0\"\ndescription = \"\"\nlicense = \"MIT\"\n",
+        )
+        .unwrap();
+        fs::write(package_dir.join("LICENSE-MIT"), "MIT License\n").unwrap();
+        fs::write(package_dir.join("Notice.txt"), "Notice\n").unwrap();
+        fs::write(package_dir.join("README.md"), "Readme\n").unwrap();
+
+        let attribution =
+            collect_attribution("_/lib", &Version::new(1, 0, 0), package_dir).unwrap();
+        assert_eq!(attribution.license.as_deref(), Some("MIT"));
+        let file_names: Vec<&str> = attribution
+            .files
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(file_names, vec!["LICENSE-MIT", "Notice.txt"]);
+
+        let missing = collect_attribution(
+            "_/missing",
+            &Version::new(0, 1, 0),
+            &package_dir.join("missing"),
+        )
+        .unwrap();
+        let rendered = render(&[attribution, missing]);
+        assert!(rendered.contains("_/lib 1.0.0\nLicense: MIT\n"));
+        assert!(rendered.contains("LICENSE-MIT:\n\nMIT License\n"));
+        assert!(rendered.contains("No license or notice files were found in this package."));
+    }
+}
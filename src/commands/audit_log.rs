@@ -0,0 +1,59 @@
+//! Code pertaining to the `audit-log` subcommand: it lists the publishes, logins and token
+//! operations this machine made on registries
+
+use crate::audit_log;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct AuditLogOpt {
+    /// Only show the latest actions
+    #[structopt(short = "n", long = "limit")]
+    limit: Option<usize>,
+    /// Only show the actions on this package
+    #[structopt(long = "package")]
+    package: Option<String>,
+    /// Only show the actions that failed
+    #[structopt(long = "failed")]
+    failed: bool,
+    /// Print the entries as JSON lines, as they are saved
+    #[structopt(long = "json")]
+    json: bool,
+}
+
+pub fn audit_log(options: AuditLogOpt) -> Result<(), failure::Error> {
+    let entries: Vec<_> = audit_log::entries()?
+        .into_iter()
+        .filter(|entry| options.package.is_none() || entry.package == options.package)
+        .filter(|entry| !options.failed || entry.error.is_some())
+        .collect();
+    if entries.is_empty() && !options.json {
+        println!("No actions were logged");
+        return Ok(());
+    }
+    let skipped = options
+        .limit
+        .map_or(0, |limit| entries.len().saturating_sub(limit));
+    for entry in entries.iter().skip(skipped) {
+        if options.json {
+            println!("{}", serde_json::to_string(entry)?);
+            continue;
+        }
+        let target = entry
+            .target()
+            .or_else(|| entry.subject.clone())
+            .unwrap_or_else(|| "-".to_string());
+        let result = match &entry.error {
+            Some(error) => format!("failed: {}", error),
+            None => "ok".to_string(),
+        };
+        println!(
+            "{}\t{}\t{}\t{}\t{}",
+            entry.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+            entry.action,
+            target,
+            entry.registry,
+            result
+        );
+    }
+    Ok(())
+}
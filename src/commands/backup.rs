@@ -0,0 +1,233 @@
+//! Code pertaining to the `backup` and `restore` subcommands: they carry the global config, the
+//! registries logged in to, the global installs and the trusted keys to a new machine
+
+use crate::backup::{self, BackupMetadata, Credential, GlobalPackage, TrustedKey};
+use crate::commands::{install, InstallOpt};
+use crate::config::Config;
+use crate::database;
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::global_versions;
+use crate::keys::{self, WapmPublicKeyError};
+use crate::util;
+use semver::Version;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct BackupOpt {
+    /// The backup file to write
+    #[structopt(parse(from_os_str), default_value = "wapm-backup.tar.gz")]
+    output: PathBuf,
+    /// Keep the registry tokens in the backup. Anyone with the file can then publish as you.
+    #[structopt(long = "include-secrets")]
+    include_secrets: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RestoreOpt {
+    /// The backup file to restore
+    #[structopt(parse(from_os_str))]
+    backup: PathBuf,
+    /// Replace the global config without asking
+    #[structopt(short = "y", long = "yes")]
+    yes: bool,
+    /// Restore the config and the keys without installing the global packages again
+    #[structopt(long = "skip-packages")]
+    skip_packages: bool,
+}
+
+/// The packages installed globally, the default versions first
+fn global_packages(globals_directory: &Path) -> Vec<GlobalPackage> {
+    let lockfile = match LockfileResult::find_in_directory(globals_directory) {
+        LockfileResult::Lockfile(lockfile) => lockfile,
+        _ => return vec![],
+    };
+    let mut packages = vec![];
+    for (name, versions) in lockfile.modules.iter() {
+        for version in versions.keys() {
+            packages.push(GlobalPackage {
+                name: name.clone(),
+                version: version.to_string(),
+                default: true,
+            });
+        }
+    }
+    for name in lockfile.modules.keys() {
+        for version in global_versions::side_by_side_versions(globals_directory, name) {
+            packages.push(GlobalPackage {
+                name: name.clone(),
+                version: version.to_string(),
+                default: false,
+            });
+        }
+    }
+    packages
+}
+
+pub fn backup(options: BackupOpt) -> Result<(), failure::Error> {
+    let config_source = fs::read_to_string(Config::get_file_location()?).ok();
+    let (config_source, credentials) = match config_source {
+        Some(source) => {
+            let mut config: toml::Value = toml::from_str(&source)?;
+            let credentials = backup::credentials(&mut config, options.include_secrets);
+            let source = if options.include_secrets {
+                source
+            } else {
+                toml::to_string(&config)?
+            };
+            (Some(source), credentials)
+        }
+        None => (None, vec![]),
+    };
+    let global_packages = global_packages(&Config::get_globals_directory()?);
+    let key_db = database::open_db()?;
+    let trusted_keys: Vec<TrustedKey> = keys::get_wapm_public_keys_from_database(&key_db)?
+        .into_iter()
+        .map(|key| TrustedKey {
+            user_name: key.user_name,
+            public_key_id: key.public_key_id,
+            public_key_value: key.public_key_value,
+        })
+        .collect();
+
+    let metadata = BackupMetadata::new(
+        options.include_secrets,
+        credentials,
+        global_packages,
+        trusted_keys,
+    );
+    backup::write_backup(
+        File::create(&options.output)?,
+        &metadata,
+        config_source.as_deref(),
+    )?;
+    println!(
+        "Backed up the config, {} global package(s) and {} trusted key(s) to {}",
+        metadata.global_packages.len(),
+        metadata.trusted_keys.len(),
+        options.output.display()
+    );
+    if options.include_secrets {
+        warn!(
+            "{} contains registry tokens, keep it somewhere safe",
+            options.output.display()
+        );
+    }
+    Ok(())
+}
+
+pub fn restore(options: RestoreOpt) -> Result<(), failure::Error> {
+    let (metadata, config_source) = backup::read_backup(&options.backup)?;
+    if let Some(config_source) = config_source {
+        let mut restored = restore_config(config_source, metadata.includes_secrets, options.yes)?;
+        warn_logged_out(&metadata.credentials, &mut restored);
+    }
+
+    let mut key_db = database::open_db()?;
+    let mut imported = 0;
+    for key in metadata.trusted_keys {
+        match keys::import_public_key(
+            &mut key_db,
+            &key.public_key_id,
+            &key.public_key_value,
+            key.user_name,
+        ) {
+            Ok(()) => imported += 1,
+            Err(e) if e.downcast_ref::<WapmPublicKeyError>().is_some() => {
+                debug!("{}", e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    println!("Imported {} trusted key(s)", imported);
+
+    if options.skip_packages || metadata.global_packages.is_empty() {
+        return Ok(());
+    }
+    let (defaults, side_by_side): (Vec<_>, Vec<_>) = metadata
+        .global_packages
+        .into_iter()
+        .partition(|package| package.default);
+    if !defaults.is_empty() {
+        let mut args = vec![
+            "install".to_string(),
+            "--global".to_string(),
+            "--force-yes".to_string(),
+        ];
+        args.extend(
+            defaults
+                .iter()
+                .map(|package| format!("{}@{}", package.name, package.version)),
+        );
+        install(InstallOpt::from_iter_safe(args)?)?;
+    }
+    let globals_directory = Config::get_globals_directory()?;
+    for package in side_by_side.iter() {
+        let version = Version::parse(&package.version)?;
+        global_versions::install_side_by_side(&globals_directory, &package.name, &version, true)?;
+    }
+    println!(
+        "Installed {} global package(s) from the registry",
+        defaults.len() + side_by_side.len()
+    );
+    Ok(())
+}
+
+/// Write the config of the backup as the global config, asking first if it replaces another one.
+/// Returns the global config as it is afterwards.
+fn restore_config(
+    source: String,
+    includes_secrets: bool,
+    yes: bool,
+) -> Result<toml::Value, failure::Error> {
+    let mut restored: toml::Value = toml::from_str(&source)?;
+    // fail before writing anything if this version of wapm can't read the config
+    toml::from_str::<Config>(&source)?;
+    let path = Config::get_file_location()?;
+    let source = match fs::read_to_string(&path) {
+        Ok(current_source) if current_source != source => {
+            if !yes
+                && !util::prompt_user_for_yes(&format!(
+                    "Replace the global config {} with the one of the backup?",
+                    path.display()
+                ))?
+            {
+                return Ok(toml::from_str(&current_source)?);
+            }
+            match toml::from_str::<toml::Value>(&current_source) {
+                Ok(mut current) if !includes_secrets => {
+                    backup::keep_current_tokens(&mut restored, &mut current);
+                    toml::to_string(&restored)?
+                }
+                _ => source,
+            }
+        }
+        _ => source,
+    };
+    fs::create_dir_all(Config::get_folder()?)?;
+    fs::write(&path, source)?;
+    println!("Restored the global config to {}", path.display());
+    Ok(restored)
+}
+
+/// Point to `wapm login` for the registries that had a token when the backup was made but have
+/// none in the global config
+fn warn_logged_out(backed_up: &[Credential], config: &mut toml::Value) {
+    let current = backup::credentials(config, true);
+    for credential in backed_up.iter().filter(|credential| credential.logged_in) {
+        let still_logged_in = current
+            .iter()
+            .any(|c| c.profile == credential.profile && c.url == credential.url && c.logged_in);
+        if still_logged_in {
+            continue;
+        }
+        match &credential.profile {
+            None => warn!("Run `wapm login` to log in to {} again", credential.url),
+            Some(profile) => warn!(
+                "Run `wapm --profile {} login` to log in to {} again",
+                profile, credential.url
+            ),
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::config::Config;
-use crate::data::manifest::PACKAGES_DIR_NAME;
 use crate::dataflow::bin_script::BIN_DIR_NAME;
+use crate::util::get_packages_dir;
 use std::env;
 use structopt::StructOpt;
 
@@ -18,11 +18,11 @@ pub enum BinError {
 }
 
 pub fn bin(options: BinOpt) -> Result<(), failure::Error> {
-    let mut root_dir = match options.global {
+    let project_dir = match options.global {
         true => Config::get_globals_directory()?,
         false => env::current_dir()?,
     };
-    root_dir.push(PACKAGES_DIR_NAME);
+    let mut root_dir = get_packages_dir(&project_dir);
 
     // for wapm bin -g, display the global path even if it does not exist
     // otherwise error if the bin directory does not exist in the local directory
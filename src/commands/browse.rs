@@ -0,0 +1,368 @@
+//! Code pertaining to the `browse` subcommand: a terminal UI to search the registry, read about
+//! packages and pick packages to install
+
+use crate::commands::{install, package_details, search_packages, InstallOpt, SearchResult};
+use crate::data::manifest::Manifest;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::BTreeSet;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct BrowseOpt {
+    /// Search for this right away
+    query: Option<String>,
+    /// Install the chosen packages globally
+    #[structopt(short = "g", long = "global")]
+    global: bool,
+}
+
+#[derive(Debug, Fail)]
+enum BrowseError {
+    #[fail(display = "`wapm browse` needs an interactive terminal, use `wapm search` instead")]
+    NotATerminal,
+}
+
+const HELP: &str = "enter: search/details  space: mark  i: install marked  /: search  q: quit";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Focus {
+    Query,
+    Results,
+    Details,
+}
+
+/// What the event loop has to do after a key press
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Action {
+    None,
+    Search,
+    LoadDetails,
+    Install,
+    Quit,
+}
+
+struct App {
+    query: String,
+    focus: Focus,
+    results: Vec<SearchResult>,
+    list: ListState,
+    /// The names of the packages to install
+    marked: BTreeSet<String>,
+    details: Option<String>,
+    scroll: u16,
+    status: String,
+}
+
+impl App {
+    fn new(query: String) -> Self {
+        App {
+            query,
+            focus: Focus::Query,
+            results: vec![],
+            list: ListState::default(),
+            marked: BTreeSet::new(),
+            details: None,
+            scroll: 0,
+            status: HELP.to_string(),
+        }
+    }
+
+    fn selected(&self) -> Option<&SearchResult> {
+        self.list
+            .selected()
+            .and_then(|index| self.results.get(index))
+    }
+
+    fn handle_key(&mut self, key: KeyCode) -> Action {
+        match (self.focus, key) {
+            (Focus::Query, KeyCode::Char(c)) => self.query.push(c),
+            (Focus::Query, KeyCode::Backspace) => {
+                self.query.pop();
+            }
+            (Focus::Query, KeyCode::Enter) if !self.query.trim().is_empty() => {
+                return Action::Search
+            }
+            (Focus::Query, KeyCode::Down) | (Focus::Query, KeyCode::Tab)
+                if !self.results.is_empty() =>
+            {
+                self.focus = Focus::Results
+            }
+            (Focus::Query, KeyCode::Esc) => return Action::Quit,
+            (_, KeyCode::Char('q')) | (Focus::Results, KeyCode::Esc) => return Action::Quit,
+            (_, KeyCode::Char('/')) => self.focus = Focus::Query,
+            (_, KeyCode::Char(' ')) => {
+                if let Some(name) = self.selected().map(|result| result.name.clone()) {
+                    if !self.marked.remove(&name) {
+                        self.marked.insert(name);
+                    }
+                }
+            }
+            (_, KeyCode::Char('i')) => {
+                if self.marked.is_empty() {
+                    if let Some(name) = self.selected().map(|result| result.name.clone()) {
+                        self.marked.insert(name);
+                    }
+                }
+                if !self.marked.is_empty() {
+                    return Action::Install;
+                }
+            }
+            (Focus::Results, KeyCode::Up) | (Focus::Results, KeyCode::Char('k')) => {
+                self.list.select_previous()
+            }
+            (Focus::Results, KeyCode::Down) | (Focus::Results, KeyCode::Char('j')) => {
+                self.list.select_next()
+            }
+            (Focus::Results, KeyCode::Enter) if self.selected().is_some() => {
+                return Action::LoadDetails
+            }
+            (Focus::Details, KeyCode::Up) | (Focus::Details, KeyCode::Char('k')) => {
+                self.scroll = self.scroll.saturating_sub(1)
+            }
+            (Focus::Details, KeyCode::Down) | (Focus::Details, KeyCode::Char('j')) => {
+                self.scroll = self.scroll.saturating_add(1)
+            }
+            (Focus::Details, KeyCode::Esc)
+            | (Focus::Details, KeyCode::Backspace)
+            | (Focus::Details, KeyCode::Left) => self.focus = Focus::Results,
+            _ => {}
+        }
+        Action::None
+    }
+
+    fn search(&mut self) {
+        match search_packages(self.query.trim()) {
+            Ok(results) => {
+                self.status = format!("{} packages found. {}", results.len(), HELP);
+                self.results = results;
+                self.list
+                    .select(Some(0).filter(|_| !self.results.is_empty()));
+                self.details = None;
+                self.focus = if self.results.is_empty() {
+                    Focus::Query
+                } else {
+                    Focus::Results
+                };
+            }
+            Err(e) => self.status = format!("Search failed: {}", e),
+        }
+    }
+
+    fn load_details(&mut self) {
+        let name = match self.selected() {
+            Some(result) => result.name.clone(),
+            None => return,
+        };
+        match describe_package(&name) {
+            Ok(details) => {
+                self.details = Some(details);
+                self.scroll = 0;
+                self.focus = Focus::Details;
+            }
+            Err(e) => self.status = format!("Could not load {}: {}", name, e),
+        }
+    }
+}
+
+/// The details of the last version of a package and its readme
+fn describe_package(name: &str) -> Result<String, failure::Error> {
+    let details = match package_details(name, None)? {
+        Some(details) => details,
+        None => return Ok(format!("{} has no published versions", name)),
+    };
+    let mut lines = vec![
+        format!("{}@{}", name, details.version),
+        details.description,
+        String::new(),
+    ];
+    let fields = [
+        ("License", details.license),
+        ("Homepage", details.homepage),
+        ("Repository", details.repository),
+    ];
+    for (field, value) in fields.iter() {
+        if let Some(value) = value {
+            lines.push(format!("{}: {}", field, value));
+        }
+    }
+    if let Ok(manifest) = toml::from_str::<Manifest>(&details.manifest) {
+        let commands: Vec<String> = manifest
+            .command
+            .unwrap_or_default()
+            .into_iter()
+            .map(|command| command.name)
+            .collect();
+        if !commands.is_empty() {
+            lines.push(format!("Commands: {}", commands.join(", ")));
+        }
+    }
+    if let Some(readme) = details.readme {
+        lines.push(String::new());
+        lines.push(readme);
+    }
+    Ok(lines.join("\n"))
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .split(frame.area());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
+        .split(rows[1]);
+    let current_focus = app.focus;
+    let focused = |focus: Focus| {
+        if current_focus == focus {
+            Style::default().add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        }
+    };
+
+    let query = Paragraph::new(app.query.as_str()).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Search")
+            .border_style(focused(Focus::Query)),
+    );
+    frame.render_widget(query, rows[0]);
+
+    let items: Vec<ListItem> = app
+        .results
+        .iter()
+        .map(|result| {
+            let mark = if app.marked.contains(&result.name) {
+                "[x]"
+            } else {
+                "[ ]"
+            };
+            ListItem::new(Line::from(format!(
+                "{} {}@{}",
+                mark, result.name, result.version
+            )))
+        })
+        .collect();
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Packages")
+                .border_style(focused(Focus::Results)),
+        )
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, columns[0], &mut app.list);
+
+    let details = match (&app.details, app.selected()) {
+        (Some(details), _) => details.clone(),
+        (None, Some(result)) => format!(
+            "{}@{}\n{}\n\nPress enter for the details and readme",
+            result.name, result.version, result.description
+        ),
+        (None, None) => String::new(),
+    };
+    let details = Paragraph::new(details)
+        .wrap(Wrap { trim: false })
+        .scroll((app.scroll, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Details")
+                .border_style(focused(Focus::Details)),
+        );
+    frame.render_widget(details, columns[1]);
+
+    frame.render_widget(Paragraph::new(app.status.as_str()), rows[2]);
+}
+
+/// Run the UI until the user quits, returning whether the marked packages are to be installed
+fn run(terminal: &mut DefaultTerminal, app: &mut App) -> Result<bool, failure::Error> {
+    loop {
+        terminal.draw(|frame| draw(frame, app))?;
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        match app.handle_key(key.code) {
+            Action::None => {}
+            Action::Search => {
+                app.status = format!("Searching for \"{}\"...", app.query.trim());
+                terminal.draw(|frame| draw(frame, app))?;
+                app.search();
+            }
+            Action::LoadDetails => app.load_details(),
+            Action::Install => return Ok(true),
+            Action::Quit => return Ok(false),
+        }
+    }
+}
+
+pub fn browse(options: BrowseOpt) -> Result<(), failure::Error> {
+    if !atty::is(atty::Stream::Stdin) || !atty::is(atty::Stream::Stdout) {
+        return Err(BrowseError::NotATerminal.into());
+    }
+    let mut app = App::new(options.query.unwrap_or_default());
+    let mut terminal = ratatui::try_init()?;
+    if !app.query.is_empty() {
+        app.search();
+    }
+    let result = run(&mut terminal, &mut app);
+    ratatui::restore();
+    if !result? {
+        return Ok(());
+    }
+
+    let mut args = vec!["install".to_string()];
+    if options.global {
+        args.push("--global".to_string());
+    }
+    args.extend(app.marked);
+    install(InstallOpt::from_iter_safe(args)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn result(name: &str) -> SearchResult {
+        SearchResult {
+            name: name.to_string(),
+            description: String::new(),
+            version: "1.0.0".to_string(),
+            created_at: String::new(),
+            keywords: vec![],
+            categories: vec![],
+        }
+    }
+
+    #[test]
+    fn keys_move_between_the_query_and_the_results() {
+        let mut app = App::new(String::new());
+        assert_eq!(app.handle_key(KeyCode::Enter), Action::None);
+        app.handle_key(KeyCode::Char('s'));
+        app.handle_key(KeyCode::Char('q'));
+        app.handle_key(KeyCode::Char('l'));
+        assert_eq!(app.query, "sql");
+        assert_eq!(app.handle_key(KeyCode::Enter), Action::Search);
+
+        app.results = vec![result("_/sqlite"), result("_/sqlite-ext")];
+        app.list.select(Some(0));
+        app.focus = Focus::Results;
+        app.handle_key(KeyCode::Down);
+        app.handle_key(KeyCode::Char(' '));
+        assert!(app.marked.contains("_/sqlite-ext"));
+        assert_eq!(app.handle_key(KeyCode::Enter), Action::LoadDetails);
+        assert_eq!(app.handle_key(KeyCode::Char('i')), Action::Install);
+        assert_eq!(app.handle_key(KeyCode::Char('q')), Action::Quit);
+    }
+}
@@ -0,0 +1,69 @@
+//! Code pertaining to the `bundle` subcommand: it packs a project's dependencies into one file
+//! and installs them from it on machines without network access
+
+use crate::bundle::{create_bundle, install_bundle};
+use crate::dataflow;
+use std::env;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub enum BundleOpt {
+    #[structopt(name = "create")]
+    /// Pack the lockfile, the manifest and the archives of all the dependencies into one file
+    Create(CreateBundleOpt),
+
+    #[structopt(name = "install")]
+    /// Install the dependencies of the project from a bundle, without network access
+    Install(InstallBundleOpt),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct CreateBundleOpt {
+    /// The bundle file to write, e.g. `out.wapmbundle`
+    #[structopt(parse(from_os_str))]
+    output: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct InstallBundleOpt {
+    /// The bundle file to install from
+    #[structopt(parse(from_os_str))]
+    bundle: PathBuf,
+}
+
+#[derive(Debug, Fail)]
+enum BundleCommandError {
+    #[fail(display = "Failed to set up the installed packages. {}", _0)]
+    CannotRegenLockfile(dataflow::Error),
+}
+
+pub fn bundle(options: BundleOpt) -> Result<(), failure::Error> {
+    let current_dir = env::current_dir()?;
+    match options {
+        BundleOpt::Create(CreateBundleOpt { output }) => {
+            let package_count = create_bundle(&current_dir, &output)?;
+            println!(
+                "Bundled {} package(s) into {}",
+                package_count,
+                output.display()
+            );
+        }
+        BundleOpt::Install(InstallBundleOpt { bundle }) => {
+            let summary = install_bundle(&bundle, &current_dir)?;
+            // the lockfile matches the restored packages, so nothing is fetched
+            dataflow::update(vec![], vec![], &current_dir)
+                .map_err(BundleCommandError::CannotRegenLockfile)?;
+            for package in summary.installed.iter() {
+                info!("Installed {} from the bundle", package);
+            }
+            println!(
+                "Installed {} package(s) from {}, {} already installed",
+                summary.installed.len(),
+                bundle.display(),
+                summary.already_installed.len()
+            );
+        }
+    }
+    Ok(())
+}
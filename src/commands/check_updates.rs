@@ -0,0 +1,182 @@
+//! Code pertaining to the `check-updates` subcommand: a read-only report of the versions
+//! available for every dependency, with a stable JSON output for bots that open upgrade pull
+//! requests. Upgrading is left to `wapm upgrade`.
+
+use crate::data::manifest::Manifest;
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::min_age;
+use crate::outdated::{dependency_versions, format_rows, OutdatedDependency};
+use crate::registry;
+use chrono::Duration;
+use std::env;
+use structopt::StructOpt;
+
+/// The version of the JSON output, bumped on changes that could break its readers
+const SCHEMA_VERSION: u32 = 1;
+
+#[derive(StructOpt, Debug)]
+pub struct CheckUpdatesOpt {
+    /// Print the report as JSON
+    #[structopt(long = "json")]
+    json: bool,
+    /// Hold back versions published more recently than this, e.g. `3d`.
+    /// Overrides the `install.min-age` config key
+    #[structopt(long = "min-age", parse(try_from_str = min_age::parse_min_age))]
+    min_age: Option<Duration>,
+}
+
+#[derive(Debug, Fail)]
+enum CheckUpdatesError {
+    #[fail(
+        display = "Could not find a manifest in the current directory, try running `wapm init`"
+    )]
+    NoManifest,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    schema_version: u32,
+    dependencies: Vec<DependencyReport>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct DependencyReport {
+    name: String,
+    /// The version requirement in the manifest, or of the pin of the dependency
+    requirement: String,
+    /// The version in the lockfile
+    current: Option<String>,
+    /// The newest version matching the requirement
+    latest_satisfying: Option<String>,
+    /// The newest version, `None` if the registry has no versions of the dependency
+    latest: Option<String>,
+    /// A newer version published too recently for the minimum package age
+    held_back: Option<String>,
+    pinned: bool,
+    /// Whether a version newer than the current one matches the requirement
+    update_in_range: bool,
+    /// Whether a version newer than the current one exists
+    update_available: bool,
+}
+
+impl DependencyReport {
+    fn new(
+        name: &str,
+        requirement: &str,
+        pinned: bool,
+        versions: Option<&OutdatedDependency>,
+    ) -> Self {
+        let newer_than_current = |version: &semver::Version| {
+            versions
+                .and_then(|versions| versions.current.as_ref())
+                .is_none_or(|current| version > current)
+        };
+        DependencyReport {
+            name: name.to_string(),
+            requirement: versions
+                .map(|versions| versions.requirement.clone())
+                .unwrap_or_else(|| requirement.to_string()),
+            current: versions
+                .and_then(|versions| versions.current.as_ref())
+                .map(ToString::to_string),
+            latest_satisfying: versions
+                .and_then(|versions| versions.wanted.as_ref())
+                .map(ToString::to_string),
+            latest: versions.map(|versions| versions.latest.to_string()),
+            held_back: versions
+                .and_then(|versions| versions.held_back.as_ref())
+                .map(ToString::to_string),
+            pinned,
+            update_in_range: versions
+                .and_then(|versions| versions.wanted.as_ref())
+                .is_some_and(newer_than_current),
+            update_available: versions.is_some_and(|versions| newer_than_current(&versions.latest)),
+        }
+    }
+}
+
+pub fn check_updates(options: CheckUpdatesOpt) -> Result<(), failure::Error> {
+    let current_dir = env::current_dir()?;
+    let manifest =
+        Manifest::find_in_directory(&current_dir).map_err(|_| CheckUpdatesError::NoManifest)?;
+    let lockfile = match LockfileResult::find_in_directory(&current_dir) {
+        LockfileResult::Lockfile(lockfile) => Some(lockfile),
+        _ => None,
+    };
+    let mut names: Vec<String> = manifest
+        .dependencies
+        .iter()
+        .flatten()
+        .map(|(name, _)| name.clone())
+        .collect();
+    names.sort();
+    let registry_versions = registry::backend()?.package_versions(&names)?;
+    let min_age = options.min_age.or_else(min_age::min_age);
+    let versions = dependency_versions(&manifest, lockfile.as_ref(), &registry_versions, min_age);
+
+    if !options.json {
+        let outdated: Vec<OutdatedDependency> = versions
+            .into_iter()
+            .filter(OutdatedDependency::is_outdated)
+            .collect();
+        if outdated.is_empty() {
+            println!("All dependencies are up to date");
+        } else {
+            for row in format_rows(&outdated) {
+                println!("{}", row);
+            }
+        }
+        return Ok(());
+    }
+
+    let dependencies = names
+        .iter()
+        .map(|name| {
+            let requirement = manifest
+                .dependencies
+                .as_ref()
+                .and_then(|dependencies| dependencies.get(name))
+                .map(String::as_str)
+                .unwrap_or_default();
+            let pinned = manifest
+                .pins
+                .as_ref()
+                .is_some_and(|pins| pins.contains_key(name));
+            let versions = versions.iter().find(|versions| versions.name == *name);
+            DependencyReport::new(name, requirement, pinned, versions)
+        })
+        .collect();
+    let report = Report {
+        schema_version: SCHEMA_VERSION,
+        dependencies,
+    };
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use semver::Version;
+
+    #[test]
+    fn reports_tell_updates_in_and_out_of_range() {
+        let versions = OutdatedDependency {
+            name: "_/sqlite".to_string(),
+            requirement: "^0.1.0".to_string(),
+            current: Some(Version::parse("0.1.0").unwrap()),
+            wanted: Some(Version::parse("0.1.0").unwrap()),
+            latest: Version::parse("0.2.0").unwrap(),
+            held_back: None,
+        };
+        let report = DependencyReport::new("_/sqlite", "0.1.0", false, Some(&versions));
+        assert!(!report.update_in_range);
+        assert!(report.update_available);
+        assert_eq!(report.latest.as_deref(), Some("0.2.0"));
+
+        let missing = DependencyReport::new("_/gone", "1.0.0", true, None);
+        assert_eq!(missing.requirement, "1.0.0");
+        assert!(missing.latest.is_none());
+        assert!(!missing.update_available);
+    }
+}
@@ -0,0 +1,109 @@
+//! Code pertaining to the `clean` subcommand: it removes installed packages and generated
+//! files so they can be reinstalled from scratch
+//!
+//! The command shims are in `wapm_packages/.bin` and go with the packages. The only temporary
+//! file of a project is the half written lockfile of an interrupted install, archives are
+//! downloaded to temporary directories of the system that are removed once extracted.
+
+use crate::config::Config;
+use crate::data::lock::{LOCKFILE_NAME, LOCKFILE_TEMP_NAME};
+use crate::data::wax_index::WaxIndex;
+use crate::util::{format_size, get_dir_size, get_packages_dir};
+use crate::wasm_store;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct CleanOpt {
+    /// Remove the globally installed packages and the temporary packages used by `wax`
+    #[structopt(short = "g", long = "global")]
+    global: bool,
+    /// List what would be removed without removing anything
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+}
+
+#[derive(Debug, Fail)]
+enum CleanError {
+    #[fail(display = "Could not remove \"{}\": {}", _0, _1)]
+    CannotRemove(String, String),
+}
+
+pub fn clean(options: CleanOpt) -> Result<(), failure::Error> {
+    let paths = if options.global {
+        let globals_directory = Config::get_globals_directory()?;
+        let mut paths = vec![
+            get_packages_dir(&globals_directory),
+            globals_directory.join(LOCKFILE_NAME),
+            globals_directory.join(LOCKFILE_TEMP_NAME),
+        ];
+        if let Ok(wax_index) = WaxIndex::open() {
+            paths.push(wax_index.base_path().to_path_buf());
+        }
+        paths
+    } else {
+        // the lockfile is kept so that `wapm install` restores the same packages
+        let current_dir = env::current_dir()?;
+        vec![
+            get_packages_dir(&current_dir),
+            current_dir.join(LOCKFILE_TEMP_NAME),
+        ]
+    };
+    let paths: Vec<PathBuf> = paths.into_iter().filter(|path| path.exists()).collect();
+
+    let mut total_size = 0;
+    for path in paths.iter() {
+        let size = get_dir_size(path);
+        total_size += size;
+        if options.dry_run {
+            println!("Would remove {} ({})", path.display(), format_size(size));
+        } else {
+            remove_path(path)?;
+            println!("Removed {} ({})", path.display(), format_size(size));
+        }
+    }
+
+    // modules in the store are shared, they can only go once no package links to them
+    let store = Config::get_wasm_store_directory()?;
+    let pruned = wasm_store::prune(&store, options.dry_run).map_err(|e| {
+        CleanError::CannotRemove(store.to_string_lossy().to_string(), e.to_string())
+    })?;
+    if pruned.modules > 0 {
+        total_size += pruned.bytes;
+        let verb = if options.dry_run {
+            "Would remove"
+        } else {
+            "Removed"
+        };
+        println!(
+            "{} {} unused module(s) from {} ({})",
+            verb,
+            pruned.modules,
+            store.display(),
+            format_size(pruned.bytes)
+        );
+    }
+
+    if paths.is_empty() && pruned.modules == 0 {
+        println!("Nothing to clean");
+        return Ok(());
+    }
+
+    if options.dry_run {
+        println!("{} would be reclaimed", format_size(total_size));
+    } else {
+        println!("{} reclaimed", format_size(total_size));
+    }
+    Ok(())
+}
+
+fn remove_path(path: &Path) -> Result<(), CleanError> {
+    let result = if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    result.map_err(|e| CleanError::CannotRemove(path.to_string_lossy().to_string(), e.to_string()))
+}
@@ -1,3 +1,7 @@
+use crate::config::Config;
+use crate::dataflow::lockfile_packages::LockfileResult;
+use std::collections::BTreeMap;
+use std::env;
 use structopt::{clap::AppSettings, clap::Shell, StructOpt};
 
 #[derive(StructOpt, Debug)]
@@ -7,3 +11,73 @@ pub struct CompletionOpt {
     #[structopt(name = "SHELL", hidden = true, parse(try_from_str))]
     pub shell: Shell,
 }
+
+#[derive(StructOpt, Debug)]
+#[structopt(setting = AppSettings::Hidden)]
+pub struct CompleteCommandsOpt {
+    /// Print `name:description` lines for zsh instead of tab separated ones for fish
+    #[structopt(long = "zsh")]
+    zsh: bool,
+}
+
+/// Completes `wapm run` with the installed commands, calling `wapm complete-commands`
+const FISH_RUN_COMMANDS: &str = r#"complete -c wapm -n "__fish_seen_subcommand_from run; and not __fish_seen_subcommand_from (wapm complete-commands 2>/dev/null | string split -f1 \t)" -f -a "(wapm complete-commands 2>/dev/null)"
+"#;
+
+const ZSH_RUN_COMMANDS: &str = r#"(( $+functions[_wapm_run_commands] )) ||
+_wapm_run_commands() {
+    local -a commands
+    commands=("${(@f)$(wapm complete-commands --zsh 2>/dev/null)}")
+    _describe -t commands 'installed command' commands "$@"
+}
+
+"#;
+
+/// Make the completion script of a shell complete the commands of `wapm run` with their
+/// descriptions, for the shells that show descriptions
+pub fn add_run_command_completions(shell: Shell, script: String) -> String {
+    match shell {
+        Shell::Fish => script + FISH_RUN_COMMANDS,
+        Shell::Zsh => {
+            let run_section = match script.find("\n(run)\n") {
+                Some(index) => index,
+                None => return script,
+            };
+            let (before, after) = script.split_at(run_section);
+            let after = after.replacen(
+                "':command -- Command name:_files'",
+                "':command -- Command name:_wapm_run_commands'",
+                1,
+            );
+            let script = format!("{}{}", before, after);
+            match script.rfind("_wapm \"$@\"") {
+                Some(call) => format!("{}{}{}", &script[..call], ZSH_RUN_COMMANDS, &script[call..]),
+                None => script,
+            }
+        }
+        _ => script,
+    }
+}
+
+/// Print the commands installed in the current directory and globally, with their descriptions
+pub fn complete_commands(options: CompleteCommandsOpt) -> Result<(), failure::Error> {
+    let mut directories = vec![env::current_dir()?];
+    directories.extend(Config::get_globals_directory().ok());
+    let mut commands = BTreeMap::new();
+    for directory in directories {
+        if let LockfileResult::Lockfile(lockfile) = LockfileResult::find_in_directory(directory) {
+            for (name, command) in lockfile.commands {
+                commands.entry(name).or_insert(command.description);
+            }
+        }
+    }
+    for (name, description) in commands {
+        let description = description.unwrap_or_default();
+        if options.zsh {
+            println!("{}:{}", name.replace(':', "\\:"), description);
+        } else {
+            println!("{}\t{}", name, description);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+//! Code pertaining to the `containerize` subcommand: it writes a container image that runs a
+//! command of the package in the current directory
+
+use crate::commands::run::resolve_env_vars;
+use crate::container_image::{self, ImageSpec};
+use crate::data::manifest::Manifest;
+use crate::oci::{self, OciReference};
+use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ContainerizeOpt {
+    /// The command to run in the container, required when the package has several commands
+    #[structopt(long = "command", short = "c")]
+    command: Option<String>,
+    /// A runtime executable to bundle in the image, which must run on linux without the
+    /// libraries of the base image, like a statically linked `wasmer`
+    #[structopt(long = "runtime", parse(from_os_str))]
+    runtime: Option<PathBuf>,
+    /// The image to build on, like `oci://ghcr.io/user/wasmer-base:latest`, which has the runtime
+    /// when none is bundled. Without it, the image only has the files of the package and the
+    /// bundled runtime.
+    #[structopt(long = "base", parse(try_from_str = OciReference::parse))]
+    base: Option<OciReference>,
+    /// An environment variable of the container, given as `KEY=VALUE`, or as `KEY` to take the
+    /// value of the current environment
+    #[structopt(long = "env", number_of_values = 1)]
+    env_vars: Vec<String>,
+    /// The architecture of the image, like `amd64` or `arm64`. Defaults to the one of this machine
+    #[structopt(long = "arch")]
+    architecture: Option<String>,
+    /// The name and tag of the image, `<package name>:<version>` by default
+    #[structopt(long = "tag", short = "t")]
+    tag: Option<String>,
+    /// The image archive to write, `<package>-<version>.oci.tar` by default
+    #[structopt(long = "output", short = "o", parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Fail)]
+enum ContainerizeError {
+    #[fail(display = "The package {} has no commands to run in a container", _0)]
+    NoCommands(String),
+    #[fail(
+        display = "The package has several commands, choose one with `--command`: {}",
+        _0
+    )]
+    SeveralCommands(String),
+    #[fail(
+        display = "The package has no command \"{}\", its commands are: {}",
+        _0, _1
+    )]
+    UnknownCommand(String, String),
+    #[fail(
+        display = "The image needs a runtime: bundle one with `--runtime`, or give a `--base` image that has `wasmer`"
+    )]
+    NoRuntime,
+}
+
+pub fn containerize(options: ContainerizeOpt) -> Result<(), failure::Error> {
+    if options.runtime.is_none() && options.base.is_none() {
+        return Err(ContainerizeError::NoRuntime.into());
+    }
+    let current_dir = env::current_dir()?;
+    let manifest = Manifest::find_in_directory(&current_dir)?;
+    let package = &manifest.package;
+    let commands = manifest.command.as_deref().unwrap_or_default();
+    let names = || {
+        commands
+            .iter()
+            .map(|command| command.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let command = match (&options.command, commands) {
+        (_, []) => return Err(ContainerizeError::NoCommands(package.name.clone()).into()),
+        (Some(name), _) => commands
+            .iter()
+            .find(|command| &command.name == name)
+            .ok_or_else(|| ContainerizeError::UnknownCommand(name.clone(), names()))?,
+        (None, [command]) => command,
+        (None, _) => return Err(ContainerizeError::SeveralCommands(names()).into()),
+    };
+    let env_vars = resolve_env_vars(&options.env_vars)?;
+    let architecture = options
+        .architecture
+        .as_deref()
+        .unwrap_or_else(|| container_image::host_architecture());
+    let spec = ImageSpec {
+        manifest: &manifest,
+        command,
+        runtime: options.runtime.as_deref(),
+        env_vars: &env_vars,
+        architecture,
+    };
+
+    let base = match &options.base {
+        Some(base) => {
+            info!("Pulling the base image {}", base);
+            Some(oci::pull_image(base, architecture)?)
+        }
+        None => None,
+    };
+    let image = container_image::build_image(&spec, base)?;
+    let tag = options
+        .tag
+        .clone()
+        .unwrap_or_else(|| format!("{}:{}", package.name.to_lowercase(), package.version));
+    let output = options.output.clone().unwrap_or_else(|| {
+        PathBuf::from(format!(
+            "{}-{}.oci.tar",
+            package.name.replace('/', "-"),
+            package.version
+        ))
+    });
+    container_image::write_image(BufWriter::new(File::create(&output)?), &image, &tag)?;
+    println!(
+        "Wrote the image {} running `{}` to {}, load it with `docker load -i {}`",
+        tag,
+        command.name,
+        output.display(),
+        output.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,105 @@
+//! Code pertaining to the `convert` subcommand: it converts packages between webc containers,
+//! `.tar.gz` archives and package directories
+
+use crate::archive::{self, ExtractionLimits};
+use crate::commands::publish::build_package_tar;
+use crate::data::manifest::Manifest;
+use crate::webc::{self, WebcPackage};
+use std::fs;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ConvertOpt {
+    /// The package to convert: a `.webc` container, a `.tar.gz` archive or a package directory
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+    /// Where to write the package. Paths ending in `.webc` get a webc container, paths ending in
+    /// `.tar.gz` or `.tgz` an archive, and other paths a package directory
+    #[structopt(parse(from_os_str))]
+    output: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Webc,
+    Archive,
+    Directory,
+}
+
+impl Format {
+    fn of(path: &Path) -> Self {
+        let name = path.to_string_lossy();
+        if name.ends_with(".webc") {
+            Format::Webc
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Format::Archive
+        } else {
+            Format::Directory
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+enum ConvertError {
+    #[fail(
+        display = "{} and {} are in the same format, nothing to convert",
+        _0, _1
+    )]
+    SameFormat(String, String),
+    #[fail(display = "{} is not a package: it is not a file or a directory", _0)]
+    NotAPackage(String),
+    #[fail(display = "{} already exists", _0)]
+    OutputExists(String),
+}
+
+pub fn convert(options: ConvertOpt) -> Result<(), failure::Error> {
+    let input_format = if options.input.is_dir() {
+        Format::Directory
+    } else if options.input.is_file() {
+        Format::of(&options.input)
+    } else {
+        return Err(ConvertError::NotAPackage(options.input.display().to_string()).into());
+    };
+    let output_format = Format::of(&options.output);
+    if input_format == output_format {
+        return Err(ConvertError::SameFormat(
+            options.input.display().to_string(),
+            options.output.display().to_string(),
+        )
+        .into());
+    }
+    if options.output.exists() {
+        return Err(ConvertError::OutputExists(options.output.display().to_string()).into());
+    }
+
+    // every conversion goes through the uncompressed tarball of the package
+    let tar_data = match input_format {
+        Format::Webc => WebcPackage::parse(&fs::read(&options.input)?)?.to_tar()?,
+        Format::Archive => webc::gunzip(&fs::read(&options.input)?)?,
+        Format::Directory => {
+            let manifest = Manifest::find_in_directory(&options.input)?;
+            build_package_tar(&options.input, &manifest)?.tar_data
+        }
+    };
+    match output_format {
+        Format::Webc => fs::write(
+            &options.output,
+            WebcPackage::from_tar(&tar_data)?.to_bytes()?,
+        )?,
+        Format::Archive => fs::write(&options.output, webc::gzip(&tar_data)?)?,
+        Format::Directory => {
+            archive::unpack(
+                &webc::gzip(&tar_data)?[..],
+                &options.output,
+                &ExtractionLimits::default(),
+            )?;
+        }
+    }
+    println!(
+        "Converted {} to {}",
+        options.input.display(),
+        options.output.display()
+    );
+    Ok(())
+}
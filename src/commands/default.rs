@@ -0,0 +1,47 @@
+use crate::config::Config;
+use crate::global_versions;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct DefaultOpt {
+    /// The package and the version to make the default, e.g. `pkg@2.0.0`. Without a version the
+    /// installed versions of the package are listed.
+    package: String,
+}
+
+#[derive(Debug, Fail)]
+pub enum DefaultError {
+    #[fail(display = "Package {} is not installed globally", _0)]
+    NotInstalled(String),
+}
+
+pub fn default(options: DefaultOpt) -> Result<(), failure::Error> {
+    let globals_directory = Config::get_globals_directory()?;
+    let mut parts = options.package.splitn(2, '@');
+    let name = parts.next().unwrap_or_default();
+    match parts.next() {
+        Some(version) => {
+            let version = global_versions::resolve_version(name, version)?;
+            if global_versions::default_version(&globals_directory, name) == Some(version.clone()) {
+                println!("{}@{} is already the default version", name, version);
+                return Ok(());
+            }
+            global_versions::set_default_version(&globals_directory, name, &version)?;
+            println!("{}@{} is now the default version", name, version);
+        }
+        None => {
+            let default = global_versions::default_version(&globals_directory, name);
+            let side_by_side = global_versions::side_by_side_versions(&globals_directory, name);
+            if default.is_none() && side_by_side.is_empty() {
+                return Err(DefaultError::NotInstalled(name.to_string()).into());
+            }
+            if let Some(default) = default {
+                println!("{}@{} (default)", name, default);
+            }
+            for version in side_by_side {
+                println!("{}@{}", name, version);
+            }
+        }
+    }
+    Ok(())
+}
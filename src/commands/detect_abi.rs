@@ -0,0 +1,37 @@
+//! Subcommand for detecting the ABI of a wasm module from its imports
+
+use crate::abi::detect::detect_abi;
+use std::fs;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct DetectAbiOpt {
+    /// The wasm module to inspect
+    #[structopt(parse(from_os_str))]
+    module: PathBuf,
+}
+
+#[derive(Debug, Fail)]
+enum DetectAbiError {
+    #[fail(display = "Could not read \"{}\": {}", _0, _1)]
+    CannotRead(String, String),
+    #[fail(display = "\"{}\" is not a valid wasm module: {}", _0, _1)]
+    InvalidWasm(String, String),
+}
+
+pub fn detect_abi_command(options: DetectAbiOpt) -> Result<(), failure::Error> {
+    let module_path = options.module.to_string_lossy().to_string();
+    let wasm = fs::read(&options.module)
+        .map_err(|e| DetectAbiError::CannotRead(module_path.clone(), e.to_string()))?;
+    let detection =
+        detect_abi(&wasm).map_err(|e| DetectAbiError::InvalidWasm(module_path.clone(), e))?;
+    println!(
+        "ABI: {} ({} confidence)",
+        detection.abi, detection.confidence
+    );
+    for note in detection.notes.iter() {
+        println!(" - the module {}", note);
+    }
+    Ok(())
+}
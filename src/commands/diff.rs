@@ -0,0 +1,383 @@
+//! Code pertaining to the `diff` subcommand: it compares the files and the manifests of two
+//! versions of a package, or of a published version and the package in the current directory
+
+use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
+use crate::dataflow::installed_packages::{Install, RegistryInstaller};
+use crate::dataflow::WapmPackageKey;
+use crate::integrity_exceptions::IntegrityExceptions;
+use crate::registry;
+use crate::util::{format_size, sha256_hex};
+use semver::Version;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::{env, iter};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct DiffOpt {
+    /// The version to compare from, e.g. `_/sqlite@0.1.0`
+    from: String,
+    /// The version to compare to. The package in the current directory if not given
+    to: Option<String>,
+}
+
+#[derive(Debug, Fail)]
+enum DiffError {
+    #[fail(
+        display = "Expected a package version like `_/sqlite@0.1.0`, found \"{}\"",
+        _0
+    )]
+    InvalidPackageVersion(String),
+    #[fail(
+        display = "Version {} of package {} was not found in the registry",
+        _1, _0
+    )]
+    VersionNotFound(String, String),
+    #[fail(display = "Could not download {}@{}: {}", _0, _1, _2)]
+    CouldNotDownload(String, String, String),
+    #[fail(
+        display = "Could not find a manifest in the current directory to compare {} to: {}",
+        _0, _1
+    )]
+    NoManifest(String, String),
+}
+
+/// The size and the checksum of a file of a package
+#[derive(Clone, Debug, PartialEq)]
+struct FileInfo {
+    size: u64,
+    sha256: String,
+}
+
+impl FileInfo {
+    fn short_hash(&self) -> &str {
+        &self.sha256[..12]
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Change<T> {
+    Added(T),
+    Removed(T),
+    Changed(T, T),
+}
+
+/// The differences between two maps, ordered by key
+fn diff_maps<T: Clone + PartialEq>(
+    old: &BTreeMap<String, T>,
+    new: &BTreeMap<String, T>,
+) -> Vec<(String, Change<T>)> {
+    let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter()
+        .filter_map(|key| {
+            let change = match (old.get(key), new.get(key)) {
+                (None, Some(value)) => Change::Added(value.clone()),
+                (Some(value), None) => Change::Removed(value.clone()),
+                (Some(old), Some(new)) if old != new => Change::Changed(old.clone(), new.clone()),
+                _ => return None,
+            };
+            Some((key.clone(), change))
+        })
+        .collect()
+}
+
+/// A package version to compare, with the directory its files are in
+struct DiffSide {
+    label: String,
+    manifest: Manifest,
+    files: BTreeMap<String, FileInfo>,
+    // keeps a downloaded package around until the comparison is done
+    _temp_dir: Option<tempfile::TempDir>,
+}
+
+pub fn diff(options: DiffOpt) -> Result<(), failure::Error> {
+    let from = download_version(&options.from)?;
+    let to = match options.to {
+        Some(to) => download_version(&to)?,
+        None => local_package(&options.from)?,
+    };
+
+    println!("Comparing {} and {}", from.label, to.label);
+    let file_changes = diff_maps(&from.files, &to.files);
+    let manifest_changes = diff_manifests(&from.manifest, &to.manifest);
+    if file_changes.is_empty() && manifest_changes.is_empty() {
+        println!("No differences found");
+        return Ok(());
+    }
+    if !file_changes.is_empty() {
+        println!("\nFiles:");
+        for (path, change) in file_changes {
+            match change {
+                Change::Added(file) => println!(
+                    "  + {} ({}, {})",
+                    path,
+                    format_size(file.size),
+                    file.short_hash()
+                ),
+                Change::Removed(file) => println!(
+                    "  - {} ({}, {})",
+                    path,
+                    format_size(file.size),
+                    file.short_hash()
+                ),
+                Change::Changed(old, new) => println!(
+                    "  ~ {} ({} -> {}, {} -> {})",
+                    path,
+                    format_size(old.size),
+                    format_size(new.size),
+                    old.short_hash(),
+                    new.short_hash()
+                ),
+            }
+        }
+    }
+    if !manifest_changes.is_empty() {
+        println!("\nManifest:");
+        for line in manifest_changes {
+            println!("  {}", line);
+        }
+    }
+    Ok(())
+}
+
+fn parse_package_version(package: &str) -> Result<(String, Version), DiffError> {
+    let invalid = || DiffError::InvalidPackageVersion(package.to_string());
+    let mut parts = package.splitn(2, '@');
+    let name = parts
+        .next()
+        .filter(|name| !name.is_empty())
+        .ok_or_else(invalid)?;
+    let version = parts
+        .next()
+        .and_then(|version| Version::parse(version).ok())
+        .ok_or_else(invalid)?;
+    Ok((name.to_string(), version))
+}
+
+/// Download and extract a version of a package from the registry
+fn download_version(package: &str) -> Result<DiffSide, failure::Error> {
+    let (name, version) = parse_package_version(package)?;
+    let version_string = version.to_string();
+    let package_version = registry::backend()?
+        .package_version(&name, Some(&version_string))?
+        .ok_or_else(|| DiffError::VersionNotFound(name.clone(), version_string.clone()))?;
+    let temp_dir = tempfile::TempDir::new()?;
+    let key = WapmPackageKey {
+        name: Cow::Owned(name.clone()),
+        version,
+    };
+    let (_, package_dir, _) = RegistryInstaller::install_package(
+        temp_dir.path(),
+        key,
+        &package_version.download_url,
+        package_version.signature,
+        false,
+        &IntegrityExceptions::load(&env::current_dir()?)?,
+    )
+    .map_err(|e| {
+        DiffError::CouldNotDownload(name.clone(), version_string.clone(), e.to_string())
+    })?;
+    let manifest = Manifest::find_in_directory(&package_dir)?;
+    let mut files = BTreeMap::new();
+    collect_files(&package_dir, &package_dir, &mut files)?;
+    Ok(DiffSide {
+        label: format!("{}@{}", name, version_string),
+        manifest,
+        files,
+        _temp_dir: Some(temp_dir),
+    })
+}
+
+/// The files of the package in the current directory that `wapm publish` would include
+fn local_package(compared_to: &str) -> Result<DiffSide, failure::Error> {
+    let current_dir = env::current_dir()?;
+    let manifest = Manifest::find_in_directory(&current_dir)
+        .map_err(|e| DiffError::NoManifest(compared_to.to_string(), e.to_string()))?;
+    let base_dir = manifest.base_directory_path.clone();
+    let package = &manifest.package;
+    let license_file = package
+        .license_file
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("LICENSE"));
+    let paths = iter::once(PathBuf::from(MANIFEST_FILE_NAME))
+        .chain(package.all_readmes().map(Path::to_path_buf))
+        .chain(iter::once(license_file))
+        .chain(
+            manifest
+                .module
+                .iter()
+                .flatten()
+                .map(|module| module.source.clone()),
+        )
+        .chain(manifest.fs.iter().flatten().map(|(_, path)| path.clone()));
+    let mut files = BTreeMap::new();
+    for path in paths {
+        let path = base_dir.join(path);
+        if path.exists() {
+            collect_files(&base_dir, &path, &mut files)?;
+        }
+    }
+    Ok(DiffSide {
+        label: format!("the package in {}", current_dir.display()),
+        manifest,
+        files,
+        _temp_dir: None,
+    })
+}
+
+/// Add the file at `path`, or the files in it if it's a directory, keyed by their path relative
+/// to `root`
+fn collect_files(
+    root: &Path,
+    path: &Path,
+    files: &mut BTreeMap<String, FileInfo>,
+) -> io::Result<()> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            collect_files(root, &entry?.path(), files)?;
+        }
+        return Ok(());
+    }
+    let contents = fs::read(path)?;
+    let relative_path = path.strip_prefix(root).unwrap_or(path);
+    files.insert(
+        relative_path.to_string_lossy().replace('\\', "/"),
+        FileInfo {
+            size: contents.len() as u64,
+            sha256: sha256_hex(&contents),
+        },
+    );
+    Ok(())
+}
+
+/// Describe the differences between the commands, dependencies and modules of two manifests
+fn diff_manifests(old: &Manifest, new: &Manifest) -> Vec<String> {
+    let commands = |manifest: &Manifest| -> BTreeMap<String, String> {
+        manifest
+            .command
+            .iter()
+            .flatten()
+            .map(|command| {
+                let description = match &command.main_args {
+                    Some(main_args) => format!("module {}, args {}", command.module, main_args),
+                    None => format!("module {}", command.module),
+                };
+                (command.name.clone(), description)
+            })
+            .collect()
+    };
+    let dependencies = |manifest: &Manifest| -> BTreeMap<String, String> {
+        manifest
+            .dependencies
+            .iter()
+            .flatten()
+            .map(|(name, version)| (name.clone(), version.clone()))
+            .collect()
+    };
+    let modules = |manifest: &Manifest| -> BTreeMap<String, String> {
+        manifest
+            .module
+            .iter()
+            .flatten()
+            .map(|module| (module.name.clone(), format!("ABI {}", module.abi)))
+            .collect()
+    };
+
+    let mut lines = vec![];
+    describe_changes(
+        "command",
+        diff_maps(&commands(old), &commands(new)),
+        &mut lines,
+    );
+    describe_changes(
+        "dependency",
+        diff_maps(&dependencies(old), &dependencies(new)),
+        &mut lines,
+    );
+    describe_changes(
+        "module",
+        diff_maps(&modules(old), &modules(new)),
+        &mut lines,
+    );
+    lines
+}
+
+fn describe_changes<T: Display>(
+    kind: &str,
+    changes: Vec<(String, Change<T>)>,
+    lines: &mut Vec<String>,
+) {
+    for (name, change) in changes {
+        lines.push(match change {
+            Change::Added(value) => format!("+ {} {} ({})", kind, name, value),
+            Change::Removed(value) => format!("- {} {} ({})", kind, name, value),
+            Change::Changed(old, new) => format!("~ {} {}: {} -> {}", kind, name, old, new),
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn manifest_changes_are_described() {
+        let manifest = |source: &str| -> Manifest { toml::from_str(source).unwrap() };
+        let old = manifest(
+            r#"
+[package]
+name = "_/sqlite"
+version = "0.1.0"
+description = ""
+
+[dependencies]
+"_/libc" = "^1.0"
+
+[[module]]
+name = "sqlite"
+source = "sqlite.wasm"
+abi = "emscripten"
+
+[[command]]
+name = "sqlite"
+module = "sqlite"
+"#,
+        );
+        let new = manifest(
+            r#"
+[package]
+name = "_/sqlite"
+version = "0.1.1"
+description = ""
+
+[dependencies]
+"_/libc" = "^2.0"
+
+[[module]]
+name = "sqlite"
+source = "sqlite.wasm"
+abi = "wasi"
+
+[[command]]
+name = "sqlite3"
+module = "sqlite"
+"#,
+        );
+        assert_eq!(
+            diff_manifests(&old, &new),
+            vec![
+                "- command sqlite (module sqlite)",
+                "+ command sqlite3 (module sqlite)",
+                "~ dependency _/libc: ^1.0 -> ^2.0",
+                "~ module sqlite: ABI emscripten -> ABI wasi",
+            ]
+        );
+        assert!(parse_package_version("_/sqlite").is_err());
+    }
+}
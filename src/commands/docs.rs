@@ -0,0 +1,73 @@
+//! Code pertaining to the `docs` subcommand: it builds a static HTML site documenting the package
+//! in the current directory
+
+use crate::data::manifest::Manifest;
+use crate::docs_site;
+use crate::registry;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub enum DocsOpt {
+    #[structopt(name = "build")]
+    /// Build a static HTML site with the readme, commands, interfaces and versions of the package
+    Build(DocsBuildOpt),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct DocsBuildOpt {
+    /// The directory to write the site to
+    #[structopt(
+        long = "output",
+        short = "o",
+        parse(from_os_str),
+        default_value = "wapm-docs"
+    )]
+    output: PathBuf,
+    /// Don't fetch the published versions of the package from the registry
+    #[structopt(long = "offline")]
+    offline: bool,
+}
+
+pub fn docs(options: DocsOpt) -> Result<(), failure::Error> {
+    match options {
+        DocsOpt::Build(options) => build(options),
+    }
+}
+
+fn build(options: DocsBuildOpt) -> Result<(), failure::Error> {
+    let current_dir = env::current_dir()?;
+    let manifest = Manifest::find_in_directory(&current_dir)?;
+    let versions = if options.offline {
+        None
+    } else {
+        let name = manifest.package.name.clone();
+        match registry::backend().and_then(|backend| backend.package_versions(&[name])) {
+            Ok(versions) => Some(versions),
+            Err(e) => {
+                warn!(
+                    "Could not fetch the versions of {} from the registry, the site has no version history: {}",
+                    manifest.package.name, e
+                );
+                None
+            }
+        }
+    };
+    let files = docs_site::build_site(&manifest, versions.as_deref())?;
+    for file in files.iter() {
+        let path = options.output.join(&file.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, &file.contents)?;
+    }
+    println!(
+        "Built the documentation of {}@{} in {}",
+        manifest.package.name,
+        manifest.package.version,
+        options.output.join("index.html").display()
+    );
+    Ok(())
+}
@@ -0,0 +1,144 @@
+//! Subcommand for checking that wapm is set up correctly and explaining how to fix problems
+
+use crate::config::Config;
+use crate::constants::WAPM_RUNTIME_ENV_KEY;
+use crate::data::toolchain::{self, PinMismatch, ToolchainPins};
+use crate::dataflow::bin_script::BIN_DIR_NAME;
+use crate::shims;
+use crate::util::{get_packages_dir, get_runtime_with_args};
+use crate::wasm_store;
+use std::env;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct DoctorOpt {}
+
+#[derive(Debug, Fail)]
+pub enum DoctorError {
+    #[fail(display = "Found {} problem(s)", _0)]
+    ProblemsFound(usize),
+}
+
+pub fn doctor(_options: DoctorOpt) -> Result<(), failure::Error> {
+    let mut problems = 0;
+    let (runtime, _) = get_runtime_with_args();
+    let wapm_version = toolchain::wapm_version();
+    let runtime_version = toolchain::installed_version(&runtime);
+
+    println!("wapm: {}", wapm_version);
+    match &runtime_version {
+        Some(version) => println!("{}: {}", runtime, version),
+        None => {
+            problems += 1;
+            println!("{}: not found", runtime);
+            println!(
+                "  fix: install {} or set {} to the runtime to use",
+                runtime, WAPM_RUNTIME_ENV_KEY
+            );
+        }
+    }
+
+    match Config::from_file() {
+        Ok(_) => println!("config: ok"),
+        Err(e) => {
+            problems += 1;
+            println!("config: {}", e);
+            println!(
+                "  fix: correct or remove {}",
+                Config::get_file_location()?.display()
+            );
+        }
+    }
+
+    let global_bin_dir = get_packages_dir(&Config::get_globals_directory()?).join(BIN_DIR_NAME);
+    let path_contains_bin_dir = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).any(|dir| dir == global_bin_dir))
+        .unwrap_or(false);
+    if path_contains_bin_dir {
+        println!("PATH: ok");
+    } else {
+        problems += 1;
+        println!(
+            "PATH: the global bin dir {} is missing",
+            global_bin_dir.display()
+        );
+        println!("  fix: add it with `eval \"$(wapm env --shell bash)\"`");
+    }
+
+    match ToolchainPins::find_for_directory(&env::current_dir()?)? {
+        Some(pins) => {
+            let mismatches = pins.mismatches(&wapm_version, &runtime);
+            if mismatches.is_empty() {
+                println!("toolchain pins ({}): ok", pins.path.display());
+            } else {
+                println!("toolchain pins ({}):", pins.path.display());
+                for mismatch in mismatches.iter() {
+                    problems += 1;
+                    println!("  {}", mismatch);
+                    println!("  fix: {}", fix_for_mismatch(mismatch));
+                }
+            }
+        }
+        None => println!("toolchain pins: none"),
+    }
+
+    let globals_directory = Config::get_globals_directory()?;
+    let drift = shims::check(&globals_directory);
+    if drift.is_empty() {
+        println!("shims: ok");
+    } else {
+        println!(
+            "shims: {} missing, {} outdated, {} of removed commands",
+            drift.missing.len(),
+            drift.outdated.len(),
+            drift.stale.len()
+        );
+        match shims::rehash(&globals_directory) {
+            Ok(_) => println!("  fixed: ran `wapm rehash`"),
+            Err(e) => {
+                problems += 1;
+                println!("  `wapm rehash` failed: {}", e);
+            }
+        }
+    }
+
+    let store = Config::get_wasm_store_directory()?;
+    match wasm_store::damaged_blobs(&store) {
+        Ok(damaged) if damaged.is_empty() => println!("module store: ok"),
+        Ok(damaged) => {
+            problems += 1;
+            println!("module store: {} damaged module(s)", damaged.len());
+            for blob in damaged.iter() {
+                println!("  {}", blob.display());
+            }
+            println!(
+                "  fix: every package linking to them is affected, remove them with their packages \
+                 (`wapm clean` and `wapm clean --global`) and install again"
+            );
+        }
+        Err(e) => {
+            problems += 1;
+            println!("module store: {}", e);
+        }
+    }
+
+    if problems > 0 {
+        return Err(DoctorError::ProblemsFound(problems).into());
+    }
+    println!("No problems found");
+    Ok(())
+}
+
+fn fix_for_mismatch(mismatch: &PinMismatch) -> String {
+    if mismatch.tool == "wapm" {
+        format!(
+            "install a matching version of wapm, e.g. `cargo install wapm-cli --version \"{}\"`",
+            mismatch.required
+        )
+    } else {
+        format!(
+            "install a version of {} matching {}, or set {} to a runtime that matches",
+            mismatch.tool, mismatch.required, WAPM_RUNTIME_ENV_KEY
+        )
+    }
+}
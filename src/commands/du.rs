@@ -0,0 +1,115 @@
+//! Subcommand for reporting how much disk space installed packages use
+
+use crate::config::Config;
+use crate::dataflow::bin_script::BIN_DIR_NAME;
+use crate::dataflow::hoisted_packages::is_linked_package_dir;
+use crate::util::{format_size, get_dir_size, get_packages_dir};
+use prettytable::{format, Table};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::{env, fs};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct DuOpt {
+    /// Only report the globally installed packages
+    #[structopt(short = "g", long = "global")]
+    global: bool,
+}
+
+/// An installed package and how much space it takes up
+struct PackageUsage {
+    location: &'static str,
+    name: String,
+    version: String,
+    size: u64,
+}
+
+pub fn du(options: DuOpt) -> Result<(), failure::Error> {
+    let mut usages = vec![];
+    if !options.global {
+        let packages_dir = get_packages_dir(&env::current_dir()?);
+        usages.extend(get_package_usages(&packages_dir, "local"));
+    }
+    let packages_dir = get_packages_dir(&Config::get_globals_directory()?);
+    usages.extend(get_package_usages(&packages_dir, "global"));
+
+    if usages.is_empty() {
+        println!("No packages found");
+        return Ok(());
+    }
+    usages.sort_by_key(|usage| Reverse(usage.size));
+
+    // packages that are installed in more than one version
+    let mut versions: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for usage in usages.iter() {
+        versions
+            .entry(usage.name.as_str())
+            .or_default()
+            .insert(usage.version.as_str());
+    }
+    let has_duplicates = versions.values().any(|versions| versions.len() > 1);
+
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.add_row(row!["SIZE", "PACKAGE", "VERSION", "LOCATION"]);
+    for usage in usages.iter() {
+        let version = if versions[usage.name.as_str()].len() > 1 {
+            format!("{} *", usage.version)
+        } else {
+            usage.version.clone()
+        };
+        table.add_row(row![
+            format_size(usage.size),
+            usage.name,
+            version,
+            usage.location,
+        ]);
+    }
+    print!("{}", table);
+    let total_size: u64 = usages.iter().map(|usage| usage.size).sum();
+    println!("Total: {}", format_size(total_size));
+    if has_duplicates {
+        println!("* multiple versions of this package are installed");
+    }
+    Ok(())
+}
+
+/// Find the packages in a packages directory, which are laid out as `namespace/name@version`
+fn get_package_usages(packages_dir: &Path, location: &'static str) -> Vec<PackageUsage> {
+    let mut usages = vec![];
+    let namespace_dirs = match fs::read_dir(packages_dir) {
+        Ok(namespace_dirs) => namespace_dirs,
+        Err(_) => return usages,
+    };
+    for namespace_dir in namespace_dirs.filter_map(|entry| entry.ok()) {
+        let namespace = namespace_dir.file_name().to_string_lossy().to_string();
+        if namespace == BIN_DIR_NAME || !namespace_dir.path().is_dir() {
+            continue;
+        }
+        let package_dirs = match fs::read_dir(namespace_dir.path()) {
+            Ok(package_dirs) => package_dirs,
+            Err(_) => continue,
+        };
+        for package_dir in package_dirs.filter_map(|entry| entry.ok()) {
+            // hoisted packages are counted in the workspace root
+            if is_linked_package_dir(&package_dir.path()) {
+                continue;
+            }
+            let package_dir_name = package_dir.file_name().to_string_lossy().to_string();
+            let mut split = package_dir_name.rsplitn(2, '@');
+            let (version, name) = match (split.next(), split.next()) {
+                (Some(version), Some(name)) => (version, name),
+                _ => continue,
+            };
+            usages.push(PackageUsage {
+                location,
+                name: format!("{}/{}", namespace, name),
+                version: version.to_string(),
+                size: get_dir_size(&package_dir.path()),
+            });
+        }
+    }
+    usages
+}
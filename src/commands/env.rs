@@ -0,0 +1,176 @@
+//! Subcommand for printing the environment variables and paths used by wapm
+
+use crate::config::{Config, GLOBAL_CONFIG_FOLDER_ENV_VAR};
+use crate::constants::WAPM_RUNTIME_ENV_KEY;
+use crate::dataflow::bin_script::BIN_DIR_NAME;
+use crate::util::{get_packages_dir, get_runtime_with_args};
+use prettytable::{format, Table};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use structopt::StructOpt;
+
+/// The environment variable wasmer reads the location of its compilation cache from
+const WASMER_CACHE_DIR_ENV_VAR: &str = "WASMER_CACHE_DIR";
+
+#[derive(StructOpt, Debug)]
+pub struct EnvOpt {
+    /// Print commands that set up the environment for a shell: bash, fish or powershell.
+    /// For example, `eval "$(wapm env --shell bash)"` adds the global bin directory to PATH
+    #[structopt(long = "shell")]
+    shell: Option<Shell>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Shell {
+    Bash,
+    Fish,
+    Powershell,
+}
+
+#[derive(Debug, Fail)]
+pub enum EnvError {
+    #[fail(
+        display = "Unknown shell \"{}\", the supported shells are bash, fish and powershell",
+        _0
+    )]
+    UnknownShell(String),
+}
+
+impl FromStr for Shell {
+    type Err = EnvError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" | "sh" | "zsh" => Ok(Shell::Bash),
+            "fish" => Ok(Shell::Fish),
+            "powershell" | "pwsh" => Ok(Shell::Powershell),
+            _ => Err(EnvError::UnknownShell(s.to_owned())),
+        }
+    }
+}
+
+impl Shell {
+    /// The shell the user logs in with, from `SHELL`
+    pub(crate) fn of_user() -> Option<Self> {
+        let shell = PathBuf::from(env::var_os("SHELL")?);
+        shell.file_name()?.to_str()?.parse().ok()
+    }
+
+    /// The startup file of the shell in the home directory, like `.bashrc`
+    pub(crate) fn profile_file(self) -> Option<PathBuf> {
+        let home = dirs::home_dir()?;
+        let is_zsh = env::var("SHELL").is_ok_and(|shell| shell.ends_with("zsh"));
+        match self {
+            Shell::Bash if is_zsh => Some(home.join(".zshrc")),
+            Shell::Bash => Some(home.join(".bashrc")),
+            Shell::Fish => Some(home.join(".config").join("fish").join("config.fish")),
+            Shell::Powershell => None,
+        }
+    }
+
+    fn set_variable(self, name: &str, value: &Path) -> String {
+        let value = value.to_string_lossy();
+        match self {
+            Shell::Bash => format!("export {}=\"{}\"", name, value),
+            Shell::Fish => format!("set -gx {} \"{}\"", name, value),
+            Shell::Powershell => format!("$env:{} = \"{}\"", name, value),
+        }
+    }
+
+    fn prepend_to_path(self, directory: &Path) -> String {
+        let directory = directory.to_string_lossy();
+        match self {
+            Shell::Bash => format!("export PATH=\"{}:$PATH\"", directory),
+            Shell::Fish => format!("set -gx PATH \"{}\" $PATH", directory),
+            Shell::Powershell => format!("$env:PATH = \"{};\" + $env:PATH", directory),
+        }
+    }
+}
+
+/// The lines that set up the environment for a shell, one per line
+pub(crate) fn shell_setup(shell: Shell) -> Result<String, failure::Error> {
+    let wasmer_dir = Config::get_folder()?;
+    let global_bin_dir = get_packages_dir(&Config::get_globals_directory()?).join(BIN_DIR_NAME);
+    Ok(format!(
+        "{}\n{}\n",
+        shell.set_variable(GLOBAL_CONFIG_FOLDER_ENV_VAR, &wasmer_dir),
+        shell.prepend_to_path(&global_bin_dir)
+    ))
+}
+
+pub fn env(options: EnvOpt) -> Result<(), failure::Error> {
+    if let Some(shell) = options.shell {
+        print!("{}", shell_setup(shell)?);
+        return Ok(());
+    }
+    let wasmer_dir = Config::get_folder()?;
+    let global_bin_dir = get_packages_dir(&Config::get_globals_directory()?).join(BIN_DIR_NAME);
+
+    let config = Config::from_file()?;
+    let (runtime, runtime_args) = get_runtime_with_args();
+    let cache_dir = env::var(WASMER_CACHE_DIR_ENV_VAR)
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| wasmer_dir.join("cache"));
+    let local_bin_dir = get_packages_dir(&env::current_dir()?).join(BIN_DIR_NAME);
+
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_CLEAN);
+    table.add_row(row![
+        GLOBAL_CONFIG_FOLDER_ENV_VAR,
+        wasmer_dir.to_string_lossy()
+    ]);
+    table.add_row(row![
+        WAPM_RUNTIME_ENV_KEY,
+        std::iter::once(runtime)
+            .chain(runtime_args)
+            .collect::<Vec<_>>()
+            .join(" ")
+    ]);
+    table.add_row(row![WASMER_CACHE_DIR_ENV_VAR, cache_dir.to_string_lossy()]);
+    table.add_row(row!["Registry", config.registry.url]);
+    table.add_row(row![
+        "Config file",
+        Config::get_file_location()?.to_string_lossy()
+    ]);
+    table.add_row(row![
+        "Global packages",
+        Config::get_globals_directory()?.to_string_lossy()
+    ]);
+    table.add_row(row!["Global bin dir", global_bin_dir.to_string_lossy()]);
+    if local_bin_dir.exists() {
+        table.add_row(row!["Local bin dir", local_bin_dir.to_string_lossy()]);
+    }
+    print!("{}", table);
+
+    let path_contains_bin_dir = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).any(|dir| dir == global_bin_dir))
+        .unwrap_or(false);
+    if !path_contains_bin_dir {
+        println!(
+            "\nThe global bin dir is not in PATH. Add it with `eval \"$(wapm env --shell bash)\"`"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shell_export_lines() {
+        let dir = Path::new("/home/user/.wasmer/globals/wapm_packages/.bin");
+        assert_eq!(
+            Shell::from_str("bash").unwrap().prepend_to_path(dir),
+            "export PATH=\"/home/user/.wasmer/globals/wapm_packages/.bin:$PATH\""
+        );
+        assert_eq!(
+            Shell::Fish.set_variable("WASMER_DIR", Path::new("/home/user/.wasmer")),
+            "set -gx WASMER_DIR \"/home/user/.wasmer\""
+        );
+        assert!(Shell::from_str("cmd").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+//! Code pertaining to the `exception` subcommand: it records and lists the integrity exceptions
+//! that let single dependencies through a signature or policy check
+
+use crate::integrity_exceptions::{self, Check, IntegrityException, IntegrityExceptions};
+use chrono::{Local, NaiveDate};
+use std::env;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub enum ExceptionOpt {
+    #[structopt(name = "add")]
+    /// Record an exception for a version of a package: wapm exception add <pkg@ver> --check
+    /// <signature|policy> --reason <reason> --approver <approver>
+    Add(AddExceptionOpt),
+
+    #[structopt(name = "list")]
+    /// List the exceptions of the project, with the ones that expired
+    List,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct AddExceptionOpt {
+    /// The exact version of the package, like `_/sqlite@0.1.1`
+    package: String,
+    /// The check the package may fail: `signature` or `policy`
+    #[structopt(long = "check")]
+    check: Check,
+    /// Why the package can be trusted anyway
+    #[structopt(long = "reason")]
+    reason: String,
+    /// Who approved the exception
+    #[structopt(long = "approver")]
+    approver: String,
+    /// The day the exception stops applying, like `2021-06-30`
+    #[structopt(long = "expires")]
+    expires: Option<NaiveDate>,
+}
+
+#[derive(Debug, Fail)]
+enum ExceptionError {
+    #[fail(
+        display = "Exceptions are for an exact version of a package, like `_/sqlite@0.1.1`, not \"{}\"",
+        _0
+    )]
+    MissingVersion(String),
+}
+
+pub fn exception(options: ExceptionOpt) -> Result<(), failure::Error> {
+    let current_dir = env::current_dir()?;
+    let path = integrity_exceptions::exceptions_path(&current_dir);
+    match options {
+        ExceptionOpt::Add(options) => {
+            let (package, version) = options
+                .package
+                .split_once('@')
+                .ok_or_else(|| ExceptionError::MissingVersion(options.package.clone()))?;
+            let exception = IntegrityException {
+                package: package.to_string(),
+                version: version.to_string(),
+                check: options.check,
+                reason: options.reason,
+                approver: options.approver,
+                expires: options.expires,
+            };
+            integrity_exceptions::add(&path, &exception)?;
+            println!(
+                "Recorded a {} exception for {} in {}, have it reviewed with the rest of the project",
+                exception.check,
+                options.package,
+                path.display()
+            );
+        }
+        ExceptionOpt::List => {
+            let exceptions = IntegrityExceptions::load(&current_dir)?;
+            if exceptions.exceptions.is_empty() {
+                println!("No integrity exceptions in {}", path.display());
+            }
+            let today = Local::now().naive_local().date();
+            for exception in exceptions.exceptions.iter() {
+                let expires = match exception.expires {
+                    Some(expires) if exception.is_expired(today) => {
+                        format!("expired on {}", expires)
+                    }
+                    Some(expires) => format!("expires on {}", expires),
+                    None => "never expires".to_string(),
+                };
+                println!(
+                    "{}@{}\t{}\tapproved by {}\t{}\t{}",
+                    exception.package,
+                    exception.version,
+                    exception.check,
+                    exception.approver,
+                    expires,
+                    exception.reason
+                );
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use crate::commands::run::resolve_env_vars;
+use crate::data::lock::is_lockfile_out_of_date;
+use crate::dataflow;
+use crate::dataflow::find_command_result::{self, get_command_from_package};
+use std::env;
+use std::ffi::OsString;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ExecOpt {
+    /// The package providing the command, optionally with a version like `pkg@1.2.3`
+    #[structopt(long = "package", short = "p")]
+    package: String,
+    /// WASI pre-opened directory
+    #[structopt(long = "dir", number_of_values = 1)]
+    pre_opened_directories: Vec<String>,
+    /// Prevent the current directory from being pre-opened by default
+    #[structopt(long = "no-default-preopen")]
+    no_default_preopen: bool,
+    /// Environment variable for the command, `KEY=VALUE`, or `KEY` to pass the variable of the
+    /// current environment
+    #[structopt(long = "env", number_of_values = 1)]
+    env_vars: Vec<String>,
+    /// Command name
+    command: String,
+    /// Application arguments
+    #[structopt(multiple = true, parse(from_os_str))]
+    args: Vec<OsString>,
+}
+
+#[derive(Debug, Fail)]
+enum ExecError {
+    #[fail(display = "Failed to run command \"{}\". {}", _0, _1)]
+    CannotRegenLockfile(String, dataflow::Error),
+}
+
+pub fn exec(options: ExecOpt) -> Result<(), failure::Error> {
+    let current_dir = env::current_dir()?;
+    match is_lockfile_out_of_date(&current_dir) {
+        Ok(false) => {}
+        _ => dataflow::update(vec![], vec![], &current_dir)
+            .map(|_| ())
+            .map_err(|e| ExecError::CannotRegenLockfile(options.command.clone(), e))?,
+    }
+
+    let env_vars = resolve_env_vars(&options.env_vars)?;
+    let mut pre_opened_directories = options.pre_opened_directories.clone();
+    if !options.no_default_preopen {
+        pre_opened_directories.push(".".to_string());
+    }
+
+    let find_command_result::Command {
+        source,
+        manifest_dir,
+        args: _,
+        module_name,
+        directory,
+        prehashed_cache_key,
+    } = get_command_from_package(&options.package, &options.command)?;
+    let manifest_dir = directory.join(manifest_dir);
+
+    crate::commands::run::do_run(
+        directory,
+        source,
+        manifest_dir,
+        &options.command,
+        &module_name,
+        &pre_opened_directories,
+        &[],
+        &env_vars,
+        &options.args,
+        prehashed_cache_key,
+        None,
+        None,
+    )
+}
@@ -11,6 +11,7 @@ use crate::dataflow::resolved_packages::ResolvedPackages;
 use crate::dataflow::retained_lockfile_packages::RetainedLockfilePackages;
 use crate::dataflow::WapmPackageKey;
 use crate::graphql::{execute_query, DateTime};
+use crate::integrity_exceptions::IntegrityExceptions;
 //use crate::keys;
 use crate::util;
 
@@ -298,8 +299,12 @@ pub fn execute(opt: ExecuteOpt) -> Result<(), failure::Error> {
                 command_name,
                 &module_name,
                 &opt.pre_opened_directories,
+                &[],
+                &[],
                 &opt.args,
                 prehashed_cache_key,
+                None,
+                None,
             )?;
             return Ok(());
         }
@@ -338,8 +343,7 @@ pub fn execute(opt: ExecuteOpt) -> Result<(), failure::Error> {
             .expect("critical internal logic error in `wapm execute`");
         let package_version_str = format!("{}@{}", &package_name, &version);
         let location = wax_index.base_path().join(&package_version_str);
-        if !location
-            .join("wapm_packages")
+        if !util::get_packages_dir(&location)
             .join(&package_version_str)
             .join("wapm.toml")
             .exists()
@@ -399,8 +403,7 @@ pub fn execute(opt: ExecuteOpt) -> Result<(), failure::Error> {
             let package_version_str = format!("{}@{}", &package_name, &version);
             let location = wax_index.base_path().join(&package_version_str);
             if registry_version > version
-                || !location
-                    .join("wapm_packages")
+                || !util::get_packages_dir(&location)
                     .join(&package_version_str)
                     .join("wapm.toml")
                     .exists()
@@ -472,6 +475,7 @@ pub fn execute(opt: ExecuteOpt) -> Result<(), failure::Error> {
                           })*/
                 ),
             )],
+            details: Default::default(),
         };
 
         // perform the install and generate the lockfile (like a simpler version of dataflow::update updating without a manifest)
@@ -482,6 +486,7 @@ pub fn execute(opt: ExecuteOpt) -> Result<(), failure::Error> {
             &install_loc,
             resolved_packages,
             !opt.verify_signature,
+            &IntegrityExceptions::load(&install_loc)?,
         )?;
         let added_lockfile_data = LockfilePackages::from_installed_packages(&installed_packages)
             .map_err(|e| ExecuteError::InstallationError(e.to_string()))?;
@@ -491,7 +496,7 @@ pub fn execute(opt: ExecuteOpt) -> Result<(), failure::Error> {
         let final_lockfile_data =
             MergedLockfilePackages::merge(added_lockfile_data, retained_lockfile_packages);
         final_lockfile_data
-            .generate_lockfile(&install_loc)
+            .generate_lockfile(&install_loc, true)
             .map_err(|e| ExecuteError::InstallationError(e.to_string()))?;
 
         debug!("Wax package installed to {}", install_loc.to_string_lossy());
@@ -550,8 +555,12 @@ fn run(
                 command_name,
                 &module_name,
                 pre_opened_directories,
+                &[],
+                &[],
                 args,
                 prehashed_cache_key,
+                None,
+                None,
             );
         }
         FindCommandResult::Error(e) => return Err(e),
@@ -0,0 +1,38 @@
+//! Subcommand for explaining the codes printed with errors
+
+use crate::error_codes::{find_error_code, ERROR_CODES};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ExplainOpt {
+    /// The error code to explain, e.g. W0001. All codes are listed if it is omitted
+    code: Option<String>,
+}
+
+#[derive(Debug, Fail)]
+enum ExplainError {
+    #[fail(
+        display = "Unknown error code \"{}\". Run `wapm explain` to list the error codes",
+        _0
+    )]
+    UnknownErrorCode(String),
+}
+
+pub fn explain(options: ExplainOpt) -> Result<(), failure::Error> {
+    match options.code {
+        Some(code) => {
+            let error_code = match find_error_code(&code) {
+                Some(error_code) => error_code,
+                None => return Err(ExplainError::UnknownErrorCode(code).into()),
+            };
+            println!("{}: {}\n", error_code.code, error_code.title);
+            println!("{}", error_code.explanation);
+        }
+        None => {
+            for error_code in ERROR_CODES.iter() {
+                println!("{}  {}", error_code.code, error_code.title);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,103 @@
+//! Code pertaining to the `export` subcommand: it writes a command, from the current project or
+//! installed globally, in the layout of a wasm serverless platform
+
+use crate::commands::run::resolve_env_vars;
+use crate::data::lock::is_lockfile_out_of_date;
+use crate::dataflow;
+use crate::dataflow::find_command_result::get_command_from_anywhere;
+use crate::dataflow::manifest_packages::ManifestResult;
+use crate::serverless::{self, ExportedCommand, Platform};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ExportOpt {
+    /// The platform to export to: spin, fastly or workers
+    #[structopt(long = "target")]
+    target: Platform,
+    /// The command to export
+    command: String,
+    /// An environment variable of the command, given as `KEY=VALUE`, or as `KEY` to take the
+    /// value of the current environment
+    #[structopt(long = "env", number_of_values = 1)]
+    env_vars: Vec<String>,
+    /// The directory to write the export to, `<target>-<command>` by default
+    #[structopt(long = "output", short = "o", parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Fail)]
+enum ExportError {
+    #[fail(display = "Failed to export command \"{}\". {}", _0, _1)]
+    CannotRegenLockfile(String, dataflow::Error),
+}
+
+pub fn export(options: ExportOpt) -> Result<(), failure::Error> {
+    let current_dir = env::current_dir()?;
+    // the command is looked up in the lockfile, like `wapm run` does
+    if !matches!(is_lockfile_out_of_date(&current_dir), Ok(false)) {
+        dataflow::update(vec![], vec![], &current_dir)
+            .map_err(|e| ExportError::CannotRegenLockfile(options.command.clone(), e))?;
+    }
+    let found = get_command_from_anywhere(&options.command)?;
+    let package_dir = found.directory.join(&found.manifest_dir);
+    let mut exported = ExportedCommand {
+        name: options.command.clone(),
+        package_name: options.command.clone(),
+        version: "0.0.0".to_string(),
+        description: String::new(),
+        module: found.directory.join(&found.source),
+        args: found
+            .args
+            .as_deref()
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(str::to_string)
+            .collect(),
+        fs: BTreeMap::new(),
+        env_vars: vec![],
+    };
+    if let ManifestResult::Manifest(manifest) = ManifestResult::find_in_directory(&package_dir) {
+        let package = &manifest.package;
+        exported.package_name = package.name.clone();
+        exported.version = package.version.to_string();
+        exported.description = package.description.clone();
+        exported.fs = manifest
+            .fs
+            .iter()
+            .flatten()
+            .map(|(guest, host)| (guest.clone(), package_dir.join(host)))
+            .collect();
+        if let Some(flags) = &package.wasmer_extra_flags {
+            exported.env_vars = serverless::env_vars_of_flags(flags);
+        }
+    }
+    // the variables given on the command line win over those of the manifest
+    for env_var in resolve_env_vars(&options.env_vars)? {
+        let key = env_var.split('=').next().unwrap_or_default().to_string();
+        exported
+            .env_vars
+            .retain(|existing| existing.split('=').next() != Some(key.as_str()));
+        exported.env_vars.push(env_var);
+    }
+
+    let output = match options.output {
+        Some(output) => output,
+        None => PathBuf::from(format!("{}-{}", options.target, options.command)),
+    };
+    let files = serverless::export(&exported, options.target, &output)?;
+    println!(
+        "Exported `{}` from {}@{} for {} to {}",
+        exported.name,
+        exported.package_name,
+        exported.version,
+        options.target,
+        output.display()
+    );
+    for file in files {
+        println!("    {}", file.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,162 @@
+//! Code pertaining to the `exports` subcommand: it lists the exports and imports of a module or
+//! of the modules of an installed package, with the WASI syscalls they import annotated
+
+use crate::abi::exports::{is_wasi_namespace, list_items, ModuleItems};
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::util::get_packages_dir;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ExportsOpt {
+    /// A wasm module, or a package installed in the current project like `_/sqlite` or
+    /// `_/sqlite@0.1.0`
+    package: String,
+    /// Only list the module with this name, for packages with more than one
+    #[structopt(long = "module")]
+    module: Option<String>,
+    /// Print the exports and imports as JSON
+    #[structopt(long = "json")]
+    json: bool,
+}
+
+#[derive(Debug, Fail)]
+enum ExportsError {
+    #[fail(
+        display = "\"{}\" is neither a file nor a package installed in the current project",
+        _0
+    )]
+    NotInstalled(String),
+    #[fail(display = "Package {} has no module named {}", _0, _1)]
+    UnknownModule(String, String),
+    #[fail(display = "Could not read \"{}\": {}", _0, _1)]
+    CannotRead(String, String),
+    #[fail(display = "\"{}\" is not a valid wasm module: {}", _0, _1)]
+    InvalidWasm(String, String),
+}
+
+#[derive(Debug, Serialize)]
+struct ListedModule {
+    name: String,
+    path: PathBuf,
+    #[serde(flatten)]
+    items: ModuleItems,
+}
+
+pub fn exports(options: ExportsOpt) -> Result<(), failure::Error> {
+    let path = PathBuf::from(&options.package);
+    let modules = if path.is_file() {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        vec![(name, path)]
+    } else {
+        installed_modules(&options.package, options.module.as_deref())?
+    };
+
+    let mut listed = vec![];
+    for (name, path) in modules {
+        let display = path.display().to_string();
+        let wasm = fs::read(&path)
+            .map_err(|e| ExportsError::CannotRead(display.clone(), e.to_string()))?;
+        let items = list_items(&wasm).map_err(|e| ExportsError::InvalidWasm(display, e))?;
+        listed.push(ListedModule { name, path, items });
+    }
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&listed)?);
+        return Ok(());
+    }
+    for (i, module) in listed.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        print_module(module);
+    }
+    Ok(())
+}
+
+/// The names and paths of the modules of a package in the lockfile of the current project
+fn installed_modules(
+    package: &str,
+    module: Option<&str>,
+) -> Result<Vec<(String, PathBuf)>, failure::Error> {
+    let current_dir = env::current_dir()?;
+    let not_installed = || ExportsError::NotInstalled(package.to_string());
+    let lockfile = match LockfileResult::find_in_directory(&current_dir) {
+        LockfileResult::Lockfile(lockfile) => lockfile,
+        LockfileResult::LockfileError(e) => return Err(e.into()),
+        LockfileResult::NoLockfile => return Err(not_installed().into()),
+    };
+    let (name, version) = match package.split_once('@') {
+        Some((name, version)) => (name, Some(version)),
+        None => (package, None),
+    };
+    let versions = lockfile.modules.get(name).ok_or_else(not_installed)?;
+    let modules = match version {
+        Some(version) => versions
+            .iter()
+            .find(|(installed, _)| installed.to_string() == version)
+            .map(|(_, modules)| modules),
+        // the targets of a manifest can install several versions, list the highest one
+        None => versions.values().next_back(),
+    }
+    .ok_or_else(not_installed)?;
+
+    let packages_dir = get_packages_dir(&current_dir);
+    let paths: Vec<(String, PathBuf)> = modules
+        .values()
+        .filter(|lockfile_module| module.is_none_or(|module| module == lockfile_module.name))
+        .map(|lockfile_module| {
+            let package_path = if lockfile_module.resolved == "local" {
+                current_dir.clone()
+            } else {
+                packages_dir.join(&lockfile_module.package_path)
+            };
+            (
+                lockfile_module.name.clone(),
+                package_path.join(&lockfile_module.source),
+            )
+        })
+        .collect();
+    match module {
+        Some(module) if paths.is_empty() => {
+            Err(ExportsError::UnknownModule(name.to_string(), module.to_string()).into())
+        }
+        _ => Ok(paths),
+    }
+}
+
+fn print_module(module: &ListedModule) {
+    println!("{} ({})", module.name, module.path.display());
+
+    println!("Exports ({}):", module.items.exports.len());
+    for export in module.items.exports.iter() {
+        println!("  {}", export);
+    }
+
+    println!("Imports ({}):", module.items.import_count());
+    for (namespace, imports) in module.items.imports.iter() {
+        if is_wasi_namespace(namespace) {
+            println!("  {} (WASI):", namespace);
+        } else {
+            println!("  {}:", namespace);
+        }
+        let width = imports
+            .iter()
+            .map(|import| import.to_string().len())
+            .max()
+            .unwrap_or(0);
+        for import in imports {
+            match &import.wasi {
+                Some(wasi) => {
+                    println!("    {:width$}  {}", import.to_string(), wasi, width = width)
+                }
+                None => println!("    {}", import),
+            }
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Code pertaining to the `foreach` subcommand: it runs a wapm command in many projects at once,
+//! like `wapm foreach -- outdated`, and reports how it went in each of them
+
+use crate::data::manifest::{MANIFEST_FILE_NAME, PACKAGES_DIR_NAME};
+use rayon::prelude::*;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
+use structopt::StructOpt;
+
+/// Directories never searched for projects
+const SKIPPED_DIRECTORIES: &[&str] = &[PACKAGES_DIR_NAME, "node_modules", "target"];
+/// The number of lines of output shown for each project that failed
+const FAILURE_OUTPUT_LINES: usize = 10;
+
+#[derive(StructOpt, Debug)]
+pub struct ForeachOpt {
+    /// A project directory to run the command in, can be given several times. Without it, the
+    /// projects are found by searching the current directory recursively for `wapm.toml` files.
+    #[structopt(long = "dir", short = "d", parse(from_os_str), number_of_values = 1)]
+    directories: Vec<PathBuf>,
+    /// A file listing the project directories to run the command in, one per line
+    #[structopt(
+        long = "projects-file",
+        parse(from_os_str),
+        conflicts_with = "directories"
+    )]
+    projects_file: Option<PathBuf>,
+    /// How many projects to run the command in at the same time, the number of CPUs by default
+    #[structopt(long = "jobs", short = "j")]
+    jobs: Option<usize>,
+    /// Show the output of the command in every project, not only in those where it failed
+    #[structopt(long = "verbose", short = "v")]
+    verbose: bool,
+    /// Print the report as JSON
+    #[structopt(long = "json")]
+    json: bool,
+    /// The wapm command to run and its arguments, after `--`, e.g. `wapm foreach -- install`
+    #[structopt(raw(true), required = true)]
+    command: Vec<String>,
+}
+
+#[derive(Debug, Fail)]
+enum ForeachError {
+    #[fail(display = "No projects found, there is no wapm.toml under {}", _0)]
+    NoProjects(String),
+    #[fail(display = "`wapm foreach` can't run `foreach` itself")]
+    NestedForeach,
+    #[fail(display = "The command failed in {} of {} project(s)", _0, _1)]
+    ProjectsFailed(usize, usize),
+}
+
+/// How the command went in a project
+#[derive(Debug, Serialize)]
+struct ProjectReport {
+    directory: PathBuf,
+    success: bool,
+    exit_code: Option<i32>,
+    duration_ms: u64,
+    output: String,
+}
+
+/// The directories under `root` with a manifest, in a stable order. Hidden directories and the
+/// packages directories of the projects are not searched.
+fn find_projects(root: &Path) -> Vec<PathBuf> {
+    let mut projects = vec![];
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(directory) = pending.pop() {
+        if directory.join(MANIFEST_FILE_NAME).is_file() {
+            projects.push(directory.clone());
+        }
+        let entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+            if is_dir && !name.starts_with('.') && !SKIPPED_DIRECTORIES.contains(&name.as_str()) {
+                pending.push(entry.path());
+            }
+        }
+    }
+    projects.sort();
+    projects
+}
+
+fn read_projects_file(path: &Path) -> Result<Vec<PathBuf>, failure::Error> {
+    let base = path.parent().unwrap_or_else(|| Path::new("."));
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line))
+        .collect())
+}
+
+fn run_in(directory: &Path, command: &[String]) -> ProjectReport {
+    let start = Instant::now();
+    let output = env::current_exe().and_then(|wapm| {
+        Command::new(wapm)
+            .args(command)
+            .current_dir(directory)
+            .output()
+    });
+    let duration_ms = start.elapsed().as_millis() as u64;
+    match output {
+        Ok(output) => {
+            let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+            text.push_str(&String::from_utf8_lossy(&output.stderr));
+            ProjectReport {
+                directory: directory.to_path_buf(),
+                success: output.status.success(),
+                exit_code: output.status.code(),
+                duration_ms,
+                output: text,
+            }
+        }
+        Err(e) => ProjectReport {
+            directory: directory.to_path_buf(),
+            success: false,
+            exit_code: None,
+            duration_ms,
+            output: format!("Could not run wapm: {}", e),
+        },
+    }
+}
+
+fn print_report(reports: &[ProjectReport], verbose: bool) {
+    for report in reports {
+        let status = match (report.success, report.exit_code) {
+            (true, _) => "ok".to_string(),
+            (false, Some(code)) => format!("failed (exit code {})", code),
+            (false, None) => "failed".to_string(),
+        };
+        println!(
+            "{}: {} in {:.1}s",
+            report.directory.display(),
+            status,
+            report.duration_ms as f64 / 1000.0
+        );
+        let lines: Vec<&str> = report.output.lines().collect();
+        let shown = if verbose {
+            &lines[..]
+        } else if !report.success {
+            &lines[lines.len().saturating_sub(FAILURE_OUTPUT_LINES)..]
+        } else {
+            &[]
+        };
+        for line in shown {
+            println!("    {}", line);
+        }
+    }
+}
+
+pub fn foreach(options: ForeachOpt) -> Result<(), failure::Error> {
+    if options.command.first().map(String::as_str) == Some("foreach") {
+        return Err(ForeachError::NestedForeach.into());
+    }
+    let current_dir = env::current_dir()?;
+    let projects = if let Some(projects_file) = &options.projects_file {
+        read_projects_file(projects_file)?
+    } else if !options.directories.is_empty() {
+        options.directories.clone()
+    } else {
+        find_projects(&current_dir)
+    };
+    if projects.is_empty() {
+        return Err(ForeachError::NoProjects(current_dir.display().to_string()).into());
+    }
+
+    let mut pool = rayon::ThreadPoolBuilder::new();
+    if let Some(jobs) = options.jobs {
+        pool = pool.num_threads(jobs.max(1));
+    }
+    info!(
+        "Running `wapm {}` in {} project(s)",
+        options.command.join(" "),
+        projects.len()
+    );
+    let reports: Vec<ProjectReport> = pool.build()?.install(|| {
+        projects
+            .par_iter()
+            .map(|directory| run_in(directory, &options.command))
+            .collect()
+    });
+
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        print_report(&reports, options.verbose);
+    }
+    let failed = reports.iter().filter(|report| !report.success).count();
+    if failed > 0 {
+        return Err(ForeachError::ProjectsFailed(failed, reports.len()).into());
+    }
+    if !options.json {
+        println!("The command succeeded in all {} project(s)", reports.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn projects_are_found_recursively() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let root = tmp_dir.path();
+        for project in &[
+            "services/api",
+            "services/worker",
+            "services/api/wapm_packages/_/dep@1.0.0",
+            ".git/project",
+            "tools",
+        ] {
+            let directory = root.join(project);
+            fs::create_dir_all(&directory).unwrap();
+            if *project != "tools" {
+                fs::write(directory.join(MANIFEST_FILE_NAME), "").unwrap();
+            }
+        }
+        assert_eq!(
+            find_projects(root),
+            vec![root.join("services/api"), root.join("services/worker")]
+        );
+
+        let projects_file = root.join("projects.txt");
+        fs::write(&projects_file, "# services\nservices/api\n\ntools\n").unwrap();
+        assert_eq!(
+            read_projects_file(&projects_file).unwrap(),
+            vec![root.join("services/api"), root.join("tools")]
+        );
+    }
+}
@@ -0,0 +1,302 @@
+//! Code pertaining to the `generate` subcommand: it writes Makefile or justfile targets for the
+//! build command, the commands and the aliases of the manifest.
+//!
+//! The targets are written between two marker lines, so the rest of a hand-written file is kept
+//! and running the subcommand again only replaces them. `--check` fails when they are out of
+//! date, for CI to keep the task runner in sync with `wapm.toml`.
+
+use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+const BEGIN_MARKER: &str = "# BEGIN wapm generated targets";
+const END_MARKER: &str = "# END wapm generated targets";
+
+#[derive(StructOpt, Debug)]
+pub enum GenerateOpt {
+    #[structopt(name = "make")]
+    /// Write the targets to the Makefile
+    Make(GenerateTargetsOpt),
+
+    #[structopt(name = "just")]
+    /// Write the recipes to the justfile
+    Just(GenerateTargetsOpt),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct GenerateTargetsOpt {
+    /// Fail if the file does not have the targets the manifest would generate, without writing it
+    #[structopt(long = "check")]
+    check: bool,
+    /// The file to write instead of `Makefile` or `justfile`
+    #[structopt(long = "output", short = "o", parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Fail)]
+enum GenerateError {
+    #[fail(
+        display = "{} is out of date with {}, run `wapm generate {}` to update it",
+        _0, _1, _2
+    )]
+    OutOfDate(String, String, String),
+    #[fail(display = "{} has a \"{}\" line without a matching \"{}\"", _0, _1, _2)]
+    UnterminatedSection(String, String, String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Runner {
+    Make,
+    Just,
+}
+
+impl Runner {
+    fn name(self) -> &'static str {
+        match self {
+            Runner::Make => "make",
+            Runner::Just => "just",
+        }
+    }
+
+    fn file_name(self) -> &'static str {
+        match self {
+            Runner::Make => "Makefile",
+            Runner::Just => "justfile",
+        }
+    }
+}
+
+/// A target wrapping a command
+#[derive(Debug)]
+struct Target {
+    name: String,
+    description: String,
+    /// A shell command, or a wapm command when `wapm` is set
+    command: String,
+    wapm: bool,
+    /// Whether extra arguments are passed to the command
+    takes_args: bool,
+}
+
+/// The targets of the manifest: build, install, the aliases and a `run-<name>` for every command
+fn targets(manifest: &Manifest) -> Vec<Target> {
+    let mut targets = vec![];
+    if let Some(build) = &manifest.package.build {
+        targets.push(Target {
+            name: "build".to_string(),
+            description: "Build the modules of the package".to_string(),
+            command: build.command.clone(),
+            wapm: false,
+            takes_args: false,
+        });
+    }
+    targets.push(Target {
+        name: "install".to_string(),
+        description: "Install the dependencies".to_string(),
+        command: "install".to_string(),
+        wapm: true,
+        takes_args: false,
+    });
+    let aliases = manifest.alias.clone().unwrap_or_default();
+    for (name, command) in aliases.iter() {
+        targets.push(Target {
+            name: name.clone(),
+            description: format!("Run the `{}` alias: wapm {}", name, command),
+            command: name.clone(),
+            wapm: true,
+            takes_args: true,
+        });
+    }
+    for command in manifest.command.iter().flatten() {
+        let run = format!("run {} --", command.name);
+        targets.push(Target {
+            name: format!("run-{}", command.name),
+            description: format!("Run the `{}` command", command.name),
+            command: run.clone(),
+            wapm: true,
+            takes_args: true,
+        });
+        if command.name == "test" && !aliases.contains_key("test") {
+            targets.push(Target {
+                name: "test".to_string(),
+                description: "Run the `test` command".to_string(),
+                command: run,
+                wapm: true,
+                takes_args: true,
+            });
+        }
+    }
+    // an alias named like another target would define it twice
+    let mut names = HashSet::new();
+    targets.retain(|target| names.insert(target.name.clone()));
+    targets
+}
+
+fn render_make(targets: &[Target]) -> String {
+    let mut out = format!("{}, update them with `wapm generate make`\n", BEGIN_MARKER);
+    let names: Vec<&str> = targets.iter().map(|target| target.name.as_str()).collect();
+    out.push_str(&format!(".PHONY: {}\n", names.join(" ")));
+    for target in targets {
+        let mut command = if target.wapm {
+            format!("wapm {}", target.command)
+        } else {
+            // `$` starts a variable in a Makefile
+            target.command.replace('$', "$$")
+        };
+        if target.takes_args {
+            command.push_str(" $(ARGS)");
+        }
+        out.push_str(&format!(
+            "\n# {}\n{}:\n\t{}\n",
+            target.description, target.name, command
+        ));
+    }
+    out.push_str(END_MARKER);
+    out.push('\n');
+    out
+}
+
+fn render_just(targets: &[Target]) -> String {
+    let mut out = format!("{}, update them with `wapm generate just`\n", BEGIN_MARKER);
+    for target in targets {
+        let mut command = if target.wapm {
+            format!("wapm {}", target.command)
+        } else {
+            // `{{` starts an interpolation in a justfile
+            target.command.replace("{{", "{{{{")
+        };
+        let mut recipe = target.name.clone();
+        if target.takes_args {
+            command.push_str(" {{args}}");
+            recipe.push_str(" *args");
+        }
+        out.push_str(&format!(
+            "\n# {}\n{}:\n    {}\n",
+            target.description, recipe, command
+        ));
+    }
+    out.push_str(END_MARKER);
+    out.push('\n');
+    out
+}
+
+/// Replace the generated section of a file, or add it at the end of a file that has none
+fn splice(existing: &str, section: &str) -> Option<String> {
+    let begin = match existing.find(BEGIN_MARKER) {
+        Some(begin) => begin,
+        None if existing.is_empty() => return Some(section.to_string()),
+        None => {
+            let separator = if existing.ends_with('\n') {
+                "\n"
+            } else {
+                "\n\n"
+            };
+            return Some(format!("{}{}{}", existing, separator, section));
+        }
+    };
+    let end = existing[begin..].find(END_MARKER)? + begin;
+    let after = existing[end..]
+        .find('\n')
+        .map(|newline| end + newline + 1)
+        .unwrap_or_else(|| existing.len());
+    Some(format!(
+        "{}{}{}",
+        &existing[..begin],
+        section,
+        &existing[after..]
+    ))
+}
+
+pub fn generate(options: GenerateOpt) -> Result<(), failure::Error> {
+    let (runner, options) = match options {
+        GenerateOpt::Make(options) => (Runner::Make, options),
+        GenerateOpt::Just(options) => (Runner::Just, options),
+    };
+    let current_dir = env::current_dir()?;
+    let manifest = Manifest::find_in_directory(&current_dir)?;
+    let targets = targets(&manifest);
+    let section = match runner {
+        Runner::Make => render_make(&targets),
+        Runner::Just => render_just(&targets),
+    };
+    let path = options
+        .output
+        .unwrap_or_else(|| current_dir.join(runner.file_name()));
+    let existing = fs::read_to_string(&path).unwrap_or_default();
+    let generated = splice(&existing, &section).ok_or_else(|| {
+        GenerateError::UnterminatedSection(
+            path.display().to_string(),
+            BEGIN_MARKER.to_string(),
+            END_MARKER.to_string(),
+        )
+    })?;
+    if options.check {
+        if generated != existing {
+            return Err(GenerateError::OutOfDate(
+                path.display().to_string(),
+                MANIFEST_FILE_NAME.to_string(),
+                runner.name().to_string(),
+            )
+            .into());
+        }
+        println!("{} is up to date", path.display());
+        return Ok(());
+    }
+    if generated == existing {
+        println!("{} is up to date", path.display());
+        return Ok(());
+    }
+    fs::write(&path, generated)?;
+    println!("Wrote {} target(s) to {}", targets.len(), path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+[package]
+name = "user/tools"
+version = "0.1.0"
+description = ""
+build = { command = "cargo build --release && cp $OUT/tools.wasm ." }
+
+[alias]
+lint = "validate ."
+
+[[module]]
+name = "tools"
+source = "tools.wasm"
+abi = "wasi"
+
+[[command]]
+name = "test"
+module = "tools"
+"#;
+
+    #[test]
+    fn targets_are_spliced_into_hand_written_files() {
+        let manifest: Manifest = toml::from_str(MANIFEST).unwrap();
+        let targets = targets(&manifest);
+        let names: Vec<&str> = targets.iter().map(|target| target.name.as_str()).collect();
+        assert_eq!(names, ["build", "install", "lint", "run-test", "test"]);
+
+        let section = render_make(&targets);
+        assert!(section.contains("build:\n\tcargo build --release && cp $$OUT/tools.wasm .\n"));
+        assert!(section.contains("run-test:\n\twapm run test -- $(ARGS)\n"));
+        assert!(render_just(&targets).contains("lint *args:\n    wapm lint {{args}}\n"));
+
+        let hand_written = "deploy:\n\t./deploy.sh\n";
+        let generated = splice(hand_written, &section).unwrap();
+        assert!(generated.starts_with(hand_written));
+        assert_eq!(splice(&generated, &section).unwrap(), generated);
+
+        let outdated = splice(hand_written, &render_make(&targets[..2])).unwrap();
+        assert_eq!(splice(&outdated, &section).unwrap(), generated);
+        assert_eq!(splice(&format!("{}\n", BEGIN_MARKER), &section), None);
+    }
+}
@@ -0,0 +1,75 @@
+//! Code pertaining to the `history` and `undo` subcommands: they list the changes made to the
+//! dependencies of the project and revert the last one
+
+use crate::dataflow;
+use crate::history::{self, Snapshot};
+use std::env;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct HistoryOpt {
+    /// Only show the latest changes
+    #[structopt(short = "n", long = "limit")]
+    limit: Option<usize>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct UndoOpt {
+    /// Undo even if the manifest or the lockfile changed since the last operation
+    #[structopt(long = "force")]
+    force: bool,
+}
+
+#[derive(Debug, Fail)]
+enum UndoError {
+    #[fail(display = "There is nothing to undo")]
+    NothingToUndo,
+    #[fail(
+        display = "The manifest or the lockfile changed since `{}`. Run `wapm undo --force` to undo it anyway",
+        _0
+    )]
+    ChangedSince(String),
+}
+
+pub fn history(options: HistoryOpt) -> Result<(), failure::Error> {
+    let current_directory = env::current_dir()?;
+    let entries = history::entries(&current_directory)?;
+    if entries.is_empty() {
+        println!("No changes to the dependencies were recorded");
+        return Ok(());
+    }
+    let skipped = options
+        .limit
+        .map_or(0, |limit| entries.len().saturating_sub(limit));
+    for entry in entries.iter().skip(skipped).rev() {
+        println!(
+            "#{} {} {}",
+            entry.id,
+            entry.recorded_at.format("%Y-%m-%d %H:%M:%S"),
+            entry.operation
+        );
+        for change in entry.changes() {
+            println!("    {}", change);
+        }
+    }
+    Ok(())
+}
+
+pub fn undo(options: UndoOpt) -> Result<(), failure::Error> {
+    let current_directory = env::current_dir()?;
+    let entry = history::entries(&current_directory)?
+        .pop()
+        .ok_or(UndoError::NothingToUndo)?;
+    if !options.force && Snapshot::take(&current_directory) != entry.after {
+        return Err(UndoError::ChangedSince(entry.operation).into());
+    }
+
+    entry.before.restore(&current_directory)?;
+    // bring the installed packages in line with the restored lockfile
+    if entry.before.manifest.is_some() {
+        dataflow::update(vec![], vec![], &current_directory)?;
+    }
+    history::remove(&current_directory, &entry)?;
+    println!("Undid `{}`", entry.operation);
+    Ok(())
+}
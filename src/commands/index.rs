@@ -0,0 +1,32 @@
+//! Code pertaining to the `index` subcommand: it manages the local snapshot of the registry's
+//! package index
+
+use crate::config::Config;
+use crate::registry;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub enum IndexOpt {
+    #[structopt(name = "update")]
+    /// Download the latest snapshot of the registry's package index
+    Update,
+}
+
+pub fn index(options: IndexOpt) -> Result<(), failure::Error> {
+    let config = Config::from_file()?;
+    match options {
+        IndexOpt::Update => {
+            let url = registry::snapshot_url(&config);
+            let index = registry::update_snapshot(&url, &registry::snapshot_path(&config)?)?;
+            println!(
+                "Updated the index snapshot from {} ({} packages)",
+                url,
+                index.packages.len()
+            );
+            if !config.index.enabled {
+                println!("Resolve against it with `wapm config set index.enabled true`");
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,5 +1,6 @@
 use crate::init;
 use std::env;
+use std::path::PathBuf;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -7,16 +8,90 @@ pub struct InitOpt {
     /// Agree to all prompts. Useful for non-interactive uses
     #[structopt(long = "force-yes", short = "y")]
     force_yes: bool,
+    /// Set up a library package that exports interfaces instead of commands
+    #[structopt(long = "lib")]
+    lib: bool,
+    /// Set up an interface package, which publishes interface definitions instead of modules
+    #[structopt(long = "interface", conflicts_with_all = &["lib", "lang"])]
+    interface: bool,
+    /// Fill in the module source, ABI and build command for a language: rust, c, go, zig or
+    /// assemblyscript
+    #[structopt(long = "lang")]
+    lang: Option<String>,
+    /// Scaffold the package from a project template, with source stubs, a build command, a
+    /// placeholder module and a command: wasi-rust, wasi-c or emscripten
+    #[structopt(long = "template", conflicts_with_all = &["lib", "interface", "lang"])]
+    template: Option<String>,
+    /// Ask each question on its own line instead of using interactive widgets, for screen readers
+    /// and dumb terminals. Used automatically when TERM is `dumb` or not set
+    #[structopt(long = "no-fancy-prompts")]
+    no_fancy_prompts: bool,
+    /// Take the answers to the questions from a TOML file instead of asking, e.g. to scaffold
+    /// packages from a template
+    #[structopt(long = "answers", parse(from_os_str))]
+    answers: Option<PathBuf>,
+    /// The name of the package, instead of asking for it
+    #[structopt(long = "name")]
+    name: Option<String>,
+    /// The version of the package, instead of asking for it
+    #[structopt(long = "version")]
+    version: Option<String>,
+    /// The description of the package, instead of asking for it
+    #[structopt(long = "description")]
+    description: Option<String>,
+    /// The SPDX license of the package, instead of asking for it
+    #[structopt(long = "license")]
+    license: Option<String>,
+    /// A module of the package as `<name>:<path>` or `<name>:<path>:<abi>`, can be given several
+    /// times and replaces the other modules
+    #[structopt(long = "module", number_of_values = 1)]
+    modules: Vec<String>,
+    /// A command as `<name>:<module>`, can be given several times and replaces the other commands
+    #[structopt(long = "command", number_of_values = 1)]
+    commands: Vec<String>,
 }
 
 pub fn init(opt: InitOpt) -> Result<(), failure::Error> {
     let current_directory = env::current_dir()?;
-    init::init(current_directory, opt.force_yes)
+    init::init(
+        current_directory,
+        init::InitOptions {
+            force_yes: opt.force_yes,
+            lib: opt.lib,
+            interface: opt.interface,
+            lang: opt.lang,
+            template: opt.template,
+            no_fancy_prompts: opt.no_fancy_prompts,
+            answers: opt.answers,
+            flags: init::FlagAnswers {
+                name: opt.name,
+                version: opt.version,
+                description: opt.description,
+                license: opt.license,
+                modules: opt.modules,
+                commands: opt.commands,
+            },
+        },
+    )
 }
 
 #[cfg(feature = "integration_tests")]
 impl InitOpt {
     pub fn new(force_yes: bool) -> Self {
-        InitOpt { force_yes }
+        InitOpt {
+            force_yes,
+            lib: false,
+            interface: false,
+            lang: None,
+            template: None,
+            no_fancy_prompts: false,
+            answers: None,
+            name: None,
+            version: None,
+            description: None,
+            license: None,
+            modules: vec![],
+            commands: vec![],
+        }
     }
 }
@@ -0,0 +1,328 @@
+//! Code pertaining to the `inspect` subcommand: it shows the files, the manifest, the sizes and
+//! the signature of a package archive without installing it, from a local file or the registry
+
+use crate::data::manifest::MANIFEST_FILE_NAME;
+use crate::database;
+use crate::dataflow::installed_packages::{download_archive, verify_signature_on_package};
+use crate::dataflow::WapmPackageKey;
+use crate::keys::{self, WapmPackageSignature};
+use crate::registry;
+use crate::util::{format_size, sha256_hex};
+use crate::webc;
+use flate2::read::GzDecoder;
+use semver::Version;
+use std::borrow::Cow;
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+use structopt::StructOpt;
+use tar::EntryType;
+
+#[derive(StructOpt, Debug)]
+pub struct InspectOpt {
+    /// A package archive, or a package of the registry like `_/sqlite@0.1.0`. The latest version
+    /// when no version is given
+    package: String,
+    /// Print the inspection as JSON
+    #[structopt(long = "json")]
+    json: bool,
+}
+
+#[derive(Debug, Fail)]
+enum InspectError {
+    #[fail(
+        display = "\"{}\" is neither a file nor a package like `_/sqlite@0.1.0`",
+        _0
+    )]
+    InvalidPackage(String),
+    #[fail(display = "Package {} was not found in the registry", _0)]
+    NotFound(String),
+    #[fail(display = "Could not read the archive: {}", _0)]
+    InvalidArchive(String),
+}
+
+#[derive(Debug, Serialize)]
+struct ArchiveFile {
+    path: String,
+    size: u64,
+    /// The SHA-256 checksum of regular files
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    /// Where a symlink points to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    link: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+enum SignatureStatus {
+    /// The registry has no signature for the archive
+    Unsigned,
+    /// Local archives come without their signature
+    Unknown,
+    Valid {
+        public_key_id: String,
+        owner: String,
+        /// Whether the key is the one trusted locally for the owner
+        trusted: bool,
+    },
+    Invalid {
+        public_key_id: String,
+        owner: String,
+        error: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct Inspection {
+    source: String,
+    format: &'static str,
+    sha256: String,
+    compressed_size: u64,
+    uncompressed_size: u64,
+    files: Vec<ArchiveFile>,
+    manifest: Option<String>,
+    signature: SignatureStatus,
+}
+
+pub fn inspect(options: InspectOpt) -> Result<(), failure::Error> {
+    let path = PathBuf::from(&options.package);
+    let (source, data, signature) = if path.is_file() {
+        (options.package.clone(), fs::read(&path)?, None)
+    } else {
+        download(&options.package)?
+    };
+    let inspection = inspect_archive(source, data, signature)?;
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&inspection)?);
+    } else {
+        print_inspection(&inspection);
+    }
+    Ok(())
+}
+
+/// Download the archive of a registry package, with its signature if it has one
+fn download(package: &str) -> Result<(String, Vec<u8>, Option<SignatureStatus>), failure::Error> {
+    let invalid = || InspectError::InvalidPackage(package.to_string());
+    let (name, version) = match package.split_once('@') {
+        Some((name, version)) => (name, Some(Version::parse(version).map_err(|_| invalid())?)),
+        None => (package, None),
+    };
+    if !name.contains('/') {
+        return Err(invalid().into());
+    }
+    let version_string = version.as_ref().map(Version::to_string);
+    let package_version = registry::backend()?
+        .package_version(name, version_string.as_deref())?
+        .ok_or_else(|| InspectError::NotFound(package.to_string()))?;
+    let key = WapmPackageKey {
+        name: Cow::Owned(package_version.name.clone()),
+        version: Version::parse(&package_version.version)?,
+    };
+    let data = download_archive(&key, &package_version.download_url)?;
+    let signature = match package_version.signature {
+        Some(signature) => check_signature(&signature, &data),
+        None => SignatureStatus::Unsigned,
+    };
+    Ok((key.to_string(), data, Some(signature)))
+}
+
+/// Verify the signature of an archive with the key the registry gave, without trusting it
+fn check_signature(signature: &WapmPackageSignature, data: &[u8]) -> SignatureStatus {
+    let verified = verify_signature_on_package(
+        &signature.public_key,
+        &signature.signature_data,
+        &mut Cursor::new(data),
+    );
+    match verified {
+        Ok(()) => {
+            let trusted = database::open_db()
+                .and_then(|conn| keys::get_latest_public_key_for_user(&conn, &signature.owner))
+                .ok()
+                .flatten()
+                .is_some_and(|key| {
+                    key.public_key_id == signature.public_key_id
+                        && key.public_key_value == signature.public_key
+                });
+            SignatureStatus::Valid {
+                public_key_id: signature.public_key_id.clone(),
+                owner: signature.owner.clone(),
+                trusted,
+            }
+        }
+        Err(e) => SignatureStatus::Invalid {
+            public_key_id: signature.public_key_id.clone(),
+            owner: signature.owner.clone(),
+            error: e.to_string(),
+        },
+    }
+}
+
+fn inspect_archive(
+    source: String,
+    data: Vec<u8>,
+    signature: Option<SignatureStatus>,
+) -> Result<Inspection, failure::Error> {
+    let invalid = |e: &dyn std::fmt::Display| InspectError::InvalidArchive(e.to_string());
+    let sha256 = sha256_hex(&data);
+    let compressed_size = data.len() as u64;
+    let (format, compressed) = if webc::is_webc(&data) {
+        (
+            "webc",
+            webc::archive_from_download(data).map_err(|e| invalid(&e))?,
+        )
+    } else {
+        ("tar.gz", data)
+    };
+    let mut archive = tar::Archive::new(GzDecoder::new(&compressed[..]));
+    let mut files = vec![];
+    let mut manifest = None;
+    for entry in archive.entries().map_err(|e| invalid(&e))? {
+        let mut entry = entry.map_err(|e| invalid(&e))?;
+        let path = entry.path().map_err(|e| invalid(&e))?;
+        let path = path.to_string_lossy().trim_start_matches("./").to_string();
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Continuous => {
+                let mut contents = vec![];
+                entry.read_to_end(&mut contents).map_err(|e| invalid(&e))?;
+                if path == MANIFEST_FILE_NAME {
+                    manifest = Some(String::from_utf8_lossy(&contents).to_string());
+                }
+                files.push(ArchiveFile {
+                    path,
+                    size: contents.len() as u64,
+                    sha256: Some(sha256_hex(&contents)),
+                    link: None,
+                });
+            }
+            EntryType::Symlink => {
+                let link = entry.link_name().map_err(|e| invalid(&e))?;
+                files.push(ArchiveFile {
+                    path,
+                    size: 0,
+                    sha256: None,
+                    link: link.map(|link| link.to_string_lossy().to_string()),
+                });
+            }
+            _ => {}
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(Inspection {
+        source,
+        format,
+        sha256,
+        compressed_size,
+        uncompressed_size: files.iter().map(|file| file.size).sum(),
+        files,
+        manifest,
+        signature: signature.unwrap_or(SignatureStatus::Unknown),
+    })
+}
+
+fn print_inspection(inspection: &Inspection) {
+    println!("{} ({})", inspection.source, inspection.format);
+    println!(
+        "Size: {} compressed, {} uncompressed, {} files",
+        format_size(inspection.compressed_size),
+        format_size(inspection.uncompressed_size),
+        inspection.files.len()
+    );
+    println!("SHA-256: {}", inspection.sha256);
+    let signature = match &inspection.signature {
+        SignatureStatus::Unsigned => "unsigned".to_string(),
+        SignatureStatus::Unknown => "unknown, local archives come without their signature".into(),
+        SignatureStatus::Valid {
+            public_key_id,
+            owner,
+            trusted,
+        } => format!(
+            "valid, signed by {} with key {}{}",
+            owner,
+            public_key_id,
+            if *trusted {
+                ""
+            } else {
+                " which is not trusted locally yet"
+            }
+        ),
+        SignatureStatus::Invalid {
+            public_key_id,
+            owner,
+            error,
+        } => format!(
+            "INVALID, claimed to be signed by {} with key {}: {}",
+            owner, public_key_id, error
+        ),
+    };
+    println!("Signature: {}", signature);
+
+    println!("\nFiles:");
+    let width = inspection
+        .files
+        .iter()
+        .map(|file| file.path.len())
+        .max()
+        .unwrap_or(0);
+    for file in inspection.files.iter() {
+        match (&file.sha256, &file.link) {
+            (_, Some(link)) => println!("  {:width$}  -> {}", file.path, link, width = width),
+            (Some(sha256), None) => println!(
+                "  {:width$}  {:>9}  {}",
+                file.path,
+                format_size(file.size),
+                &sha256[..12],
+                width = width
+            ),
+            (None, None) => println!("  {}", file.path),
+        }
+    }
+
+    match &inspection.manifest {
+        Some(manifest) => {
+            println!("\nManifest:");
+            for line in manifest.lines() {
+                println!("  {}", line);
+            }
+        }
+        None => println!("\nThe archive has no {}", MANIFEST_FILE_NAME),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    #[test]
+    fn archives_are_inspected_without_extracting() {
+        let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+        for (path, contents) in [
+            ("wapm.toml", &b"[package]\nname = \"_/hello\"\n"[..]),
+            ("hello.wasm", b"\0asm"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, contents).unwrap();
+        }
+        let data = builder.into_inner().unwrap().finish().unwrap();
+        let compressed_size = data.len() as u64;
+
+        let inspection = inspect_archive("hello.tar.gz".to_string(), data, None).unwrap();
+        assert_eq!(inspection.format, "tar.gz");
+        assert_eq!(inspection.compressed_size, compressed_size);
+        assert_eq!(inspection.uncompressed_size, 31);
+        let paths: Vec<&str> = inspection.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, ["hello.wasm", "wapm.toml"]);
+        assert_eq!(
+            inspection.files[0].sha256.as_deref(),
+            Some(sha256_hex(b"\0asm").as_str())
+        );
+        assert!(inspection.manifest.unwrap().contains("_/hello"));
+        assert!(matches!(inspection.signature, SignatureStatus::Unknown));
+
+        assert!(inspect_archive("junk".to_string(), b"junk".to_vec(), None).is_err());
+    }
+}
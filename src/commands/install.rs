@@ -1,15 +1,29 @@
 //! Code pertaining to the `install` subcommand
 
-use crate::graphql::execute_query;
-
-use graphql_client::*;
-
 use crate::config::Config;
+use crate::data::manifest::{Manifest, PackageKind};
+use crate::data::workspace::Workspace;
 use crate::dataflow;
+use crate::dataflow::github_release::{GithubRelease, GITHUB_SOURCE_PREFIX};
+use crate::dataflow::install_plan::{InstallRequest, PlanExport};
+use crate::global_versions;
+use crate::i18n::{format_message, message};
+use crate::install_report::{self, InstallReport};
+use crate::logging;
+use crate::min_age;
+use crate::moved_packages;
+use crate::oci::{OciReference, OCI_SOURCE_PREFIX};
+use crate::package_size;
+use crate::progress::{self, ProgressEvent, ProgressFormat};
+use crate::registry;
+use crate::sandbox::{self, SandboxProfile};
 use crate::util;
+use chrono::Duration;
+use semver::Version;
 use std::borrow::Cow;
 use std::env;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 use structopt::StructOpt;
 
 /// Options for the `install` subcommand
@@ -19,9 +33,51 @@ pub struct InstallOpt {
     /// Install the package(s) globally
     #[structopt(short = "g", long = "global")]
     global: bool,
-    /// Agree to all prompts. Useful for non-interactive uses. (WARNING: this may cause undesired behavior)
-    #[structopt(long = "force-yes", short = "y")]
+    /// Agree to all prompts, including going ahead with the install without asking. Useful for
+    /// non-interactive uses. (WARNING: this may cause undesired behavior)
+    #[structopt(long = "force-yes", short = "y", alias = "yes")]
     force_yes: bool,
+    /// Don't create scripts in `wapm_packages/.bin` for the commands of installed packages
+    #[structopt(long = "no-bin", conflicts_with = "bin-only")]
+    no_bin: bool,
+    /// Only install application packages, skipping library packages that have no commands
+    #[structopt(long = "bin-only")]
+    bin_only: bool,
+    /// In a workspace, install a separate copy of every dependency into each member instead of
+    /// sharing dependencies used by multiple members
+    #[structopt(long = "no-hoist")]
+    no_hoist: bool,
+    /// Describe the installed packages, their modules, commands and interfaces in
+    /// `wapm_packages/.metadata.json` for editors and language servers
+    #[structopt(long = "editor-metadata")]
+    editor_metadata: bool,
+    /// Report progress as events in the given format, `json` writes a line of JSON per event
+    #[structopt(long = "progress")]
+    progress: Option<ProgressFormat>,
+    /// Write the progress events to a file or named pipe instead of stderr
+    #[structopt(long = "progress-output", parse(from_os_str), requires = "progress")]
+    progress_output: Option<PathBuf>,
+    /// Write a report of the changed packages, timings, downloads and warnings of the install,
+    /// as JSON if the path ends with `.json` and as text otherwise
+    #[structopt(long = "report", parse(from_os_str))]
+    report: Option<PathBuf>,
+    /// Don't pick versions published more recently than this for version ranges, e.g. `3d`.
+    /// Overrides the `install.min-age` config key
+    #[structopt(long = "min-age", parse(try_from_str = min_age::parse_min_age))]
+    min_age: Option<Duration>,
+    /// Fail on packages whose archive or extracted files are larger than this, e.g. `50MB`,
+    /// instead of warning about those larger than the `install.max-package-size` config key
+    #[structopt(long = "max-package-size", parse(try_from_str = package_size::parse_size))]
+    max_package_size: Option<u64>,
+    /// Write what the install would do, with the checksums of the archives it would download, to
+    /// a JSON file instead of installing anything. Apply it later with `wapm apply`
+    #[structopt(long = "plan", parse(from_os_str))]
+    plan: Option<PathBuf>,
+    /// Run the commands of the package(s) with a sandbox profile: `strict` only gives them the
+    /// current directory, `standard` also the locale and terminal environment variables, and
+    /// `none` removes the profile
+    #[structopt(long = "sandbox")]
+    sandbox: Option<SandboxProfile>,
 }
 
 #[derive(Debug, Fail)]
@@ -29,9 +85,6 @@ enum InstallError {
     #[fail(display = "Package not found in the registry: {}", name)]
     PackageNotFound { name: String },
 
-    #[fail(display = "No package versions available for package {}", name)]
-    NoVersionsAvailable { name: String },
-
     #[fail(display = "Failed to install packages. {}", _0)]
     CannotRegenLockFile(dataflow::Error),
 
@@ -45,16 +98,23 @@ enum InstallError {
     InvalidPackageIdentifier { name: String },
     #[fail(display = "Must supply package names to install command when using --global/-g flag.")]
     MustSupplyPackagesWithGlobalFlag,
+    #[fail(
+        display = "Must supply package names to install command when using the --bin-only flag."
+    )]
+    MustSupplyPackagesWithBinOnlyFlag,
+    #[fail(
+        display = "Must supply package names to install command when using the --sandbox flag."
+    )]
+    MustSupplyPackagesWithSandboxFlag,
+    #[fail(
+        display = "Could not read the manifest of package {} from the registry. {}",
+        name, error
+    )]
+    InvalidRegistryManifest { name: String, error: String },
+    #[fail(display = "Plans can't be made for installs of {}", _0)]
+    PlanNotSupported(&'static str),
 }
 
-#[derive(GraphQLQuery)]
-#[graphql(
-    schema_path = "graphql/schema.graphql",
-    query_path = "graphql/queries/get_package.graphql",
-    response_derives = "Debug"
-)]
-struct GetPackageQuery;
-
 mod global_flag {
     pub const GLOBAL_INSTALL: bool = true;
     pub const LOCAL_INSTALL: bool = false;
@@ -66,14 +126,102 @@ mod package_args {
     pub const SOME_PACKAGES: bool = false;
 }
 
+/// Run the install a plan was made for
+pub(crate) fn install_request(request: &InstallRequest) -> Result<(), failure::Error> {
+    install(InstallOpt {
+        packages: request.packages.clone(),
+        global: request.global,
+        // the plan was reviewed instead
+        force_yes: true,
+        no_bin: request.no_bin,
+        bin_only: request.bin_only,
+        no_hoist: false,
+        editor_metadata: false,
+        progress: None,
+        progress_output: None,
+        report: None,
+        min_age: None,
+        max_package_size: None,
+        plan: None,
+        sandbox: None,
+    })
+}
+
 /// Run the install command
 pub fn install(options: InstallOpt) -> Result<(), failure::Error> {
+    if let Some(ProgressFormat::Json) = options.progress {
+        progress::enable(options.progress_output.as_deref())?;
+    }
+    if let Some(min_age) = options.min_age {
+        min_age::set_min_age(min_age);
+    }
+    if let Some(max_package_size) = options.max_package_size {
+        package_size::set_max_package_size(max_package_size);
+    }
+    let report_path = options.report.clone();
+    let install_directory = if options.global {
+        Config::get_globals_directory()?
+    } else {
+        env::current_dir()?
+    };
+    let versions_before = install_report::installed_versions(&install_directory);
+    let started = Instant::now();
+
+    let result = install_packages(options);
+    progress::emit(match &result {
+        Ok(()) => ProgressEvent::Done,
+        Err(e) => ProgressEvent::Failed {
+            error: e.to_string(),
+        },
+    });
+    let stats = progress::stats();
+    if result.is_ok() && stats.modules_deduplicated > 0 {
+        println!(
+            "Saved {} by sharing {} module(s) identical to those of other packages",
+            util::format_size(stats.bytes_deduplicated),
+            stats.modules_deduplicated
+        );
+    }
+
+    if let Some(report_path) = report_path {
+        let report = InstallReport::new(
+            &versions_before,
+            &install_report::installed_versions(&install_directory),
+            &stats,
+            started.elapsed(),
+            logging::logged_warnings(),
+            result.as_ref().err().map(|e| e.to_string()),
+        );
+        let saved = report.save(&report_path);
+        // the install error is more important than an error writing the report
+        result?;
+        saved?;
+        return Ok(());
+    }
+    result
+}
+
+fn install_packages(options: InstallOpt) -> Result<(), failure::Error> {
     let current_directory = env::current_dir()?;
     let _value = util::set_wapm_should_accept_all_prompts(options.force_yes);
     debug_assert!(
         _value.is_some(),
         "this function should only be called once!"
     );
+    let update_options = dataflow::UpdateOptions {
+        create_bin_scripts: !options.no_bin,
+        write_editor_metadata: options.editor_metadata,
+        confirm_plan: true,
+        export_plan: options.plan.as_ref().map(|path| PlanExport {
+            path: path.clone(),
+            request: InstallRequest {
+                packages: options.packages.clone(),
+                global: options.global,
+                no_bin: options.no_bin,
+                bin_only: options.bin_only,
+            },
+        }),
+    };
 
     match (options.global, options.packages.is_empty()) {
         (global_flag::GLOBAL_INSTALL, package_args::NO_PACKAGES) => {
@@ -81,36 +229,96 @@ pub fn install(options: InstallOpt) -> Result<(), failure::Error> {
             return Err(InstallError::MustSupplyPackagesWithGlobalFlag.into());
         }
         (global_flag::LOCAL_INSTALL, package_args::NO_PACKAGES) => {
+            if options.bin_only {
+                return Err(InstallError::MustSupplyPackagesWithBinOnlyFlag.into());
+            }
+            if options.sandbox.is_some() {
+                return Err(InstallError::MustSupplyPackagesWithSandboxFlag.into());
+            }
+            if let Some(workspace) = Workspace::find_in_directory(&current_directory)? {
+                if update_options.export_plan.is_some() {
+                    return Err(InstallError::PlanNotSupported("workspaces").into());
+                }
+                // install the packages of all workspace members
+                dataflow::update_workspace(&workspace, !options.no_hoist, &update_options)
+                    .map_err(|err| InstallError::FailureInstallingPackages(err))?;
+                println!("{}", message("install.workspace_installed"));
+                return Ok(());
+            }
             // install all packages locally
             let added_packages = vec![];
-            dataflow::update(added_packages, vec![], &current_directory)
-                .map_err(|err| InstallError::FailureInstallingPackages(err))?;
-            println!("Packages installed to wapm_packages!");
+            dataflow::update_with_options(
+                added_packages,
+                vec![],
+                &current_directory,
+                &update_options,
+            )
+            .map_err(|err| InstallError::FailureInstallingPackages(err))?;
+            if let Some(export) = &update_options.export_plan {
+                print_plan_written(&export.path);
+                return Ok(());
+            }
+            println!("{}", message("install.packages_installed"));
         }
         (_, package_args::SOME_PACKAGES) => {
             let mut packages = vec![];
+            let mut moves = vec![];
+            let mut github_releases = vec![];
+            let mut oci_packages = vec![];
+            // packages given with a version, which can be installed side by side globally
+            let mut versioned = vec![];
+            prefetch_requested_packages(&options.packages);
+            let requested_versions = requested_package_versions(&options.packages)?;
             for name in options.packages {
+                if name.starts_with(GITHUB_SOURCE_PREFIX) {
+                    github_releases.push(GithubRelease::parse(&name)?);
+                    continue;
+                }
+                if name.starts_with(OCI_SOURCE_PREFIX) {
+                    oci_packages.push(OciReference::parse(&name)?);
+                    continue;
+                }
                 let name_with_version: Vec<&str> = name.split("@").collect();
 
                 match &name_with_version[..] {
                     [package_name, package_version] => {
+                        let package_name =
+                            &follow_moved_package(package_name, &requested_versions, &mut moves)?;
+                        if options.bin_only {
+                            let version_data = registry::backend()?
+                                .package_version(package_name, Some(package_version))?
+                                .ok_or(InstallError::PackageNotFound {
+                                    name: name.to_string(),
+                                })?;
+                            if is_library(package_name, version_data.manifest.as_deref())? {
+                                info!(
+                                    "{}",
+                                    format_message(
+                                        "install.skipping_library",
+                                        &[("package", &name)]
+                                    )
+                                );
+                                continue;
+                            }
+                        }
+                        versioned.push(package_name.to_string());
                         packages.push((package_name.to_string(), package_version.to_string()));
                     }
                     [name] => {
-                        let q = GetPackageQuery::build_query(get_package_query::Variables {
-                            name: name.to_string(),
-                        });
-                        let response: get_package_query::ResponseData = execute_query(&q)?;
-                        let package = response.package.ok_or(InstallError::PackageNotFound {
-                            name: name.to_string(),
-                        })?;
-                        let last_version =
-                            package
-                                .last_version
-                                .ok_or(InstallError::NoVersionsAvailable {
-                                    name: name.to_string(),
-                                })?;
-                        let package_name = package.name.clone();
+                        let name = &follow_moved_package(name, &requested_versions, &mut moves)?;
+                        let last_version = registry::backend()?
+                            .package_version(name, None)?
+                            .ok_or(InstallError::PackageNotFound {
+                                name: name.to_string(),
+                            })?;
+                        if options.bin_only && is_library(name, last_version.manifest.as_deref())? {
+                            info!(
+                                "{}",
+                                format_message("install.skipping_library", &[("package", &name)])
+                            );
+                            continue;
+                        }
+                        let package_name = last_version.name.clone();
                         let package_version = last_version.version.clone();
                         packages.push((package_name, package_version));
                     }
@@ -122,6 +330,26 @@ pub fn install(options: InstallOpt) -> Result<(), failure::Error> {
                 }
             }
 
+            let globals_directory = Config::get_globals_directory()?;
+            let (packages, side_by_side) = if options.global {
+                split_side_by_side(&globals_directory, packages, &versioned)?
+            } else {
+                (packages, vec![])
+            };
+            if update_options.export_plan.is_some() {
+                if !github_releases.is_empty() {
+                    return Err(InstallError::PlanNotSupported("GitHub releases").into());
+                }
+                if !oci_packages.is_empty() {
+                    return Err(InstallError::PlanNotSupported("OCI registries").into());
+                }
+                if !side_by_side.is_empty() {
+                    return Err(InstallError::PlanNotSupported(
+                        "versions side by side with the default version",
+                    )
+                    .into());
+                }
+            }
             let installed_packages: Vec<(&str, &str)> = packages
                 .iter()
                 .map(|(s1, s2)| (s1.as_str(), s2.as_str()))
@@ -130,26 +358,219 @@ pub fn install(options: InstallOpt) -> Result<(), failure::Error> {
             // the install directory will determine which wapm.lock we are updating. For now, we
             // look in the local directory, or the global install directory
             let install_directory: Cow<Path> = match options.global {
-                true => {
-                    let folder = Config::get_globals_directory()?;
-                    Cow::Owned(folder)
-                }
+                true => Cow::Borrowed(&globals_directory),
                 false => Cow::Borrowed(&current_directory),
             };
 
-            let changes_applied = dataflow::update(installed_packages, vec![], install_directory)
+            let mut changes_applied = false;
+            for release in github_releases.iter() {
+                changes_applied |= dataflow::update_with_github_release(
+                    &install_directory,
+                    release,
+                    &update_options,
+                )
                 .map_err(|err| InstallError::CannotRegenLockFile(err))?;
+            }
+            for reference in oci_packages.iter() {
+                changes_applied |= dataflow::update_with_oci_package(
+                    &install_directory,
+                    reference,
+                    &update_options,
+                )
+                .map_err(|err| InstallError::CannotRegenLockFile(err))?;
+            }
+            if !installed_packages.is_empty()
+                || (github_releases.is_empty()
+                    && oci_packages.is_empty()
+                    && side_by_side.is_empty())
+            {
+                changes_applied |= dataflow::update_with_options(
+                    installed_packages,
+                    vec![],
+                    &install_directory,
+                    &update_options,
+                )
+                .map_err(|err| InstallError::CannotRegenLockFile(err))?;
+            }
+            if let Some(export) = &update_options.export_plan {
+                print_plan_written(&export.path);
+                return Ok(());
+            }
+            if let Some(profile) = options.sandbox {
+                let names: Vec<String> = packages.iter().map(|(name, _)| name.clone()).collect();
+                sandbox::set_sandbox_profile(
+                    &install_directory,
+                    &names,
+                    profile,
+                    update_options.create_bin_scripts,
+                )?;
+            }
+            if options.global && update_options.create_bin_scripts {
+                for (name, _) in packages.iter() {
+                    global_versions::save_versioned_shims(
+                        &globals_directory,
+                        &globals_directory,
+                        name,
+                    )?;
+                }
+            }
+            for (name, version) in side_by_side.iter() {
+                global_versions::install_side_by_side(
+                    &globals_directory,
+                    name,
+                    version,
+                    update_options.create_bin_scripts,
+                )?;
+                if let Some(profile) = options.sandbox {
+                    let directory =
+                        global_versions::version_directory(&globals_directory, name, version);
+                    sandbox::set_sandbox_profile(
+                        &directory,
+                        std::slice::from_ref(name),
+                        profile,
+                        false,
+                    )?;
+                    if update_options.create_bin_scripts {
+                        global_versions::save_versioned_shims(
+                            &globals_directory,
+                            &directory,
+                            name,
+                        )?;
+                    }
+                }
+                let default = global_versions::default_version(&globals_directory, name)
+                    .map(|v| v.to_string())
+                    .unwrap_or_default();
+                println!(
+                    "{}",
+                    format_message(
+                        "install.installed_side_by_side",
+                        &[
+                            ("package", name),
+                            ("version", &version.to_string()),
+                            ("default", &default),
+                        ]
+                    )
+                );
+                changes_applied = true;
+            }
+            moved_packages::record_moved_packages(&install_directory, &moves)?;
 
             if changes_applied {
                 if options.global {
-                    println!("Global package installed successfully!");
+                    println!("{}", message("install.global_package_installed"));
                 } else {
-                    println!("Package installed successfully to wapm_packages!");
+                    println!("{}", message("install.package_installed"));
                 }
             } else {
-                println!("No packages to install")
+                println!("{}", message("install.nothing_to_install"))
             }
         }
     }
     Ok(())
 }
+
+fn print_plan_written(path: &Path) {
+    println!(
+        "{}",
+        format_message("install.plan_written", &[("path", &path.display())])
+    );
+}
+
+/// Versions of packages to install side by side with their default versions
+type SideBySideVersions = Vec<(String, Version)>;
+
+/// Split global installs into the packages to install as the default version and the versions
+/// to install side by side with it: those of packages listed more than once, and versioned
+/// packages already installed globally in another version. Packages given without a version
+/// keep upgrading the default version.
+fn split_side_by_side(
+    globals_directory: &Path,
+    packages: Vec<(String, String)>,
+    versioned: &[String],
+) -> Result<(Vec<(String, String)>, SideBySideVersions), failure::Error> {
+    let mut defaults: Vec<(String, String)> = vec![];
+    let mut side_by_side = vec![];
+    for (name, version) in packages {
+        let listed_before = defaults
+            .iter()
+            .any(|(default_name, _)| *default_name == name);
+        let installed = global_versions::default_version(globals_directory, &name);
+        if !listed_before && (installed.is_none() || !versioned.contains(&name)) {
+            defaults.push((name, version));
+            continue;
+        }
+        let version = global_versions::resolve_version(&name, &version)?;
+        if installed.as_ref() == Some(&version) {
+            // already the default version
+            continue;
+        }
+        side_by_side.push((name, version));
+    }
+    Ok((defaults, side_by_side))
+}
+
+/// The names of the requested registry packages, with the version they were requested with
+fn registry_packages(packages: &[String]) -> impl Iterator<Item = (String, Option<String>)> + '_ {
+    packages
+        .iter()
+        .filter(|package| {
+            !package.starts_with(GITHUB_SOURCE_PREFIX) && !package.starts_with(OCI_SOURCE_PREFIX)
+        })
+        .map(|package| {
+            let mut parts = package.splitn(2, '@');
+            let name = parts.next().unwrap_or_default().to_string();
+            (name, parts.next().map(str::to_string))
+        })
+}
+
+/// Look up the requested packages in one request, rather than one request per package
+fn prefetch_requested_packages(packages: &[String]) {
+    let lookups: Vec<(String, Option<String>)> = registry_packages(packages).collect();
+    let result =
+        registry::backend().and_then(|backend| backend.prefetch_package_versions(&lookups));
+    if let Err(e) = result {
+        debug!("Could not look up the packages in one request: {}", e);
+    }
+}
+
+/// The versions of all the requested packages, in one request like the resolver makes. They say
+/// which packages moved.
+fn requested_package_versions(
+    packages: &[String],
+) -> Result<Vec<registry::PackageVersion>, failure::Error> {
+    let names: Vec<String> = registry_packages(packages).map(|(name, _)| name).collect();
+    if names.is_empty() {
+        return Ok(vec![]);
+    }
+    registry::backend()?.package_versions(&names)
+}
+
+/// The name to install a package by, following a rename if the user agrees. Followed renames
+/// are added to `moves` as pairs of the old and new names.
+fn follow_moved_package(
+    name: &str,
+    versions: &[registry::PackageVersion],
+    moves: &mut Vec<(String, String)>,
+) -> Result<String, failure::Error> {
+    let new_name = moved_packages::follow_moved_package(name, versions)?;
+    if new_name != name {
+        moves.push((name.to_owned(), new_name.clone()));
+    }
+    Ok(new_name)
+}
+
+/// Check if the manifest of a package in the registry describes a library package
+fn is_library(name: &str, manifest: Option<&str>) -> Result<bool, InstallError> {
+    // registries that don't publish manifests can't tell libraries apart
+    let manifest = match manifest {
+        Some(manifest) => manifest,
+        None => return Ok(false),
+    };
+    let manifest: Manifest =
+        toml::from_str(manifest).map_err(|e| InstallError::InvalidRegistryManifest {
+            name: name.to_string(),
+            error: e.to_string(),
+        })?;
+    Ok(manifest.package_kind() == PackageKind::Library)
+}
@@ -0,0 +1,177 @@
+//! Code pertaining to the `interface` subcommand: it manages the interfaces that the modules of
+//! a package use, from the registry's interface catalog
+
+use crate::data::manifest::Manifest;
+use crate::database;
+use crate::dataflow::interfaces::{InterfaceFromServer, InterfaceListing};
+use crate::interfaces;
+use prettytable::{format, Table};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub enum InterfaceOpt {
+    #[structopt(name = "add")]
+    /// Make a module of the package use an interface: wapm interface add <name>[@<version>]
+    Add(AddInterfaceOpt),
+
+    #[structopt(name = "list")]
+    /// List the published versions of an interface
+    List(ListInterfaceOpt),
+
+    #[structopt(name = "fetch")]
+    /// Download the definition of an interface, e.g. for generating bindings
+    Fetch(FetchInterfaceOpt),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct AddInterfaceOpt {
+    /// The interface, with a version or else its last version
+    interface: String,
+    /// The module using the interface, needed when the package has more than one
+    #[structopt(long = "module")]
+    module: Option<String>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ListInterfaceOpt {
+    /// The name of the interface
+    name: String,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct FetchInterfaceOpt {
+    /// The interface, with a version or else its last version
+    interface: String,
+    /// Where to write the definition, `<name>-<version>.wasm_interface` by default
+    #[structopt(short = "o", long = "output", parse(from_os_str))]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Fail)]
+enum InterfaceError {
+    #[fail(
+        display = "Could not find a manifest in the current directory, try running `wapm init`"
+    )]
+    NoManifest,
+    #[fail(display = "The registry has no interface named {}", _0)]
+    UnknownInterface(String),
+    #[fail(display = "The interface {} has no published versions", _0)]
+    NoVersions(String),
+    #[fail(display = "The package has no modules to add the interface to")]
+    NoModules,
+    #[fail(
+        display = "The package has more than one module, pick one with --module: {}",
+        _0
+    )]
+    AmbiguousModule(String),
+    #[fail(display = "The package has no module named {}", _0)]
+    UnknownModule(String),
+}
+
+pub fn interface(options: InterfaceOpt) -> Result<(), failure::Error> {
+    match options {
+        InterfaceOpt::Add(options) => add(options),
+        InterfaceOpt::List(options) => list(options),
+        InterfaceOpt::Fetch(options) => fetch(options),
+    }
+}
+
+fn add(options: AddInterfaceOpt) -> Result<(), failure::Error> {
+    let mut manifest =
+        Manifest::find_in_directory(env::current_dir()?).map_err(|_| InterfaceError::NoManifest)?;
+    let (name, version) = resolve_interface(&options.interface)?;
+    // the definition is needed to validate the module when publishing
+    interfaces::ensure_interface(&mut database::open_db()?, &name, &version)?;
+
+    let modules = manifest
+        .module
+        .as_mut()
+        .filter(|modules| !modules.is_empty())
+        .ok_or(InterfaceError::NoModules)?;
+    let module = match &options.module {
+        Some(module_name) => modules
+            .iter_mut()
+            .find(|module| module.name == *module_name)
+            .ok_or_else(|| InterfaceError::UnknownModule(module_name.clone()))?,
+        None if modules.len() == 1 => &mut modules[0],
+        None => {
+            let names: Vec<&str> = modules.iter().map(|module| module.name.as_str()).collect();
+            return Err(InterfaceError::AmbiguousModule(names.join(", ")).into());
+        }
+    };
+    let previous = module
+        .interfaces
+        .get_or_insert_with(Default::default)
+        .insert(name.clone(), version.clone());
+    let module_name = module.name.clone();
+    manifest.save()?;
+    match previous {
+        Some(previous) if previous != version => println!(
+            "Module {} now uses {}@{} instead of {}",
+            module_name, name, version, previous
+        ),
+        _ => println!("Module {} uses {}@{}", module_name, name, version),
+    }
+    Ok(())
+}
+
+fn list(options: ListInterfaceOpt) -> Result<(), failure::Error> {
+    let listing = InterfaceListing::get(options.name.clone())?
+        .ok_or_else(|| InterfaceError::UnknownInterface(options.name.clone()))?;
+    println!("{}", listing.name);
+    if !listing.description.is_empty() {
+        println!("{}", listing.description);
+    }
+    if listing.versions.is_empty() {
+        println!("No published versions");
+        return Ok(());
+    }
+    println!();
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+    table.add_row(row!["VERSION", "PUBLISHED"]);
+    for (version, published) in listing.versions.iter() {
+        let version = if listing.last_version.as_ref() == Some(version) {
+            format!("{} (last)", version)
+        } else {
+            version.clone()
+        };
+        table.add_row(row![version, published]);
+    }
+    print!("{}", table);
+    Ok(())
+}
+
+fn fetch(options: FetchInterfaceOpt) -> Result<(), failure::Error> {
+    let (name, version) = resolve_interface(&options.interface)?;
+    let interface = InterfaceFromServer::get(name.clone(), version.clone())?;
+    let output = options
+        .output
+        .unwrap_or_else(|| PathBuf::from(format!("{}-{}.wasm_interface", name, version)));
+    fs::write(&output, interface.content)?;
+    println!(
+        "Wrote the definition of {}@{} to {}",
+        name,
+        version,
+        output.display()
+    );
+    Ok(())
+}
+
+/// The name and version of `name[@version]`, looking up the last version if none is given
+fn resolve_interface(interface: &str) -> Result<(String, String), failure::Error> {
+    let mut parts = interface.splitn(2, '@');
+    let name = parts.next().unwrap_or_default().to_string();
+    if let Some(version) = parts.next() {
+        return Ok((name, version.to_string()));
+    }
+    let listing = InterfaceListing::get(name.clone())?
+        .ok_or_else(|| InterfaceError::UnknownInterface(name.clone()))?;
+    let version = listing
+        .last_version
+        .ok_or_else(|| InterfaceError::NoVersions(name.clone()))?;
+    Ok((name, version))
+}
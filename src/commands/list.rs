@@ -2,7 +2,9 @@
 
 use crate::config;
 use crate::data::lock::lockfile::{CommandMap, ModuleMap};
+use crate::data::workspace::Workspace;
 use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::dataflow::workspace_lockfiles::member_lockfiles;
 use prettytable::{format, Table};
 use std::{env, fmt::Write as _};
 use structopt::StructOpt;
@@ -16,9 +18,22 @@ pub struct ListOpt {
     /// List both locally and globally installed packages
     #[structopt(short = "a", long = "all")]
     all: bool,
+
+    /// List the packages installed in every member of the workspace
+    #[structopt(long = "workspace", conflicts_with = "global", conflicts_with = "all")]
+    workspace: bool,
+}
+
+#[derive(Debug, Fail)]
+enum ListError {
+    #[fail(display = "The current directory is not in a workspace")]
+    NotInAWorkspace,
 }
 
 pub fn list(options: ListOpt) -> Result<(), failure::Error> {
+    if options.workspace {
+        return list_workspace();
+    }
     let mut local = false;
     let mut global = false;
     match (options.global, options.all) {
@@ -123,6 +138,38 @@ pub fn list(options: ListOpt) -> Result<(), failure::Error> {
     Ok(())
 }
 
+/// List the packages of each member of the workspace the current directory is in
+fn list_workspace() -> Result<(), failure::Error> {
+    let cwd = env::current_dir()?;
+    let workspace = Workspace::find_for_member(&cwd)?.ok_or(ListError::NotInAWorkspace)?;
+    let mut handle = String::new();
+    for (directory, lockfile) in member_lockfiles(&workspace) {
+        let member = directory
+            .strip_prefix(&workspace.root)
+            .unwrap_or(&directory)
+            .display()
+            .to_string();
+        match lockfile {
+            LockfileResult::Lockfile(lockfile) if !lockfile.modules.is_empty() => {
+                writeln!(handle, "{}:", member)?;
+                write!(handle, "{}", create_module_ascii_table(&lockfile.modules))?;
+            }
+            LockfileResult::Lockfile(_) | LockfileResult::NoLockfile => {
+                writeln!(handle, "{}: no packages", member)?;
+            }
+            LockfileResult::LockfileError(e) => {
+                return Err(format_err!(
+                    "Failed to read the lock file of workspace member {}: {}",
+                    member,
+                    e
+                ));
+            }
+        }
+    }
+    print!("{}", handle);
+    Ok(())
+}
+
 fn create_module_ascii_table(modules: &ModuleMap) -> String {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
@@ -140,13 +187,30 @@ fn create_module_ascii_table(modules: &ModuleMap) -> String {
 fn create_command_ascii_table(commands: &CommandMap) -> String {
     let mut table = Table::new();
     table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
-    table.add_row(row!["COMMAND", "PACKAGE", "VERSION"]);
+    // the descriptions of the manifests are only shown when there are some
+    let has_descriptions = commands
+        .values()
+        .any(|command| command.description.is_some());
+    if has_descriptions {
+        table.add_row(row!["COMMAND", "PACKAGE", "VERSION", "DESCRIPTION"]);
+    } else {
+        table.add_row(row!["COMMAND", "PACKAGE", "VERSION"]);
+    }
     for (command_name, command) in commands.iter() {
-        table.add_row(row![
-            command_name,
-            command.package_name,
-            command.package_version,
-        ]);
+        if has_descriptions {
+            table.add_row(row![
+                command_name,
+                command.package_name,
+                command.package_version,
+                command.description.as_deref().unwrap_or_default(),
+            ]);
+        } else {
+            table.add_row(row![
+                command_name,
+                command.package_name,
+                command.package_version,
+            ]);
+        }
     }
     format!("{}", table)
 }
@@ -0,0 +1,228 @@
+//! Code pertaining to the `lock` subcommand: it resolves merge conflicts in the lockfile and
+//! exports allowlists of its packages
+
+use crate::allowlist::{self, AllowedPackage, Allowlist};
+use crate::commands::publish::{sign_compressed_archive, SignArchiveResult};
+use crate::data::lock::merge::{merge_lockfiles, split_conflicts};
+use crate::data::lock::LOCKFILE_NAME;
+use crate::dataflow;
+use crate::dataflow::installed_packages;
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::dataflow::manifest_packages::ManifestResult;
+use crate::dataflow::WapmPackageKey;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub enum LockOpt {
+    #[structopt(name = "resolve")]
+    /// Resolve the merge conflicts of wapm.lock by merging the packages of both sides and
+    /// updating the result to match the manifest
+    Resolve,
+
+    #[structopt(name = "merge-driver")]
+    /// Merge lockfiles as a git merge driver, set up with
+    /// `git config merge.wapm.driver "wapm lock merge-driver %A %O %B"` and
+    /// `wapm.lock merge=wapm` in .gitattributes
+    MergeDriver(MergeDriverOpt),
+
+    #[structopt(name = "export-allowlist")]
+    /// Write the packages of the lockfile with the checksums of their archives to an allowlist
+    /// signed with the active personal key, the only packages installs may fetch when it is set
+    /// as `install.allowlist`
+    ExportAllowlist(ExportAllowlistOpt),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ExportAllowlistOpt {
+    /// Where to write the allowlist, its signature is written next to it with a `.minisig`
+    /// extension
+    #[structopt(
+        short = "o",
+        long = "output",
+        parse(from_os_str),
+        default_value = "wapm-allowlist.toml"
+    )]
+    output: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct MergeDriverOpt {
+    /// Our version of the lockfile, which is replaced by the merged lockfile (%A)
+    #[structopt(parse(from_os_str))]
+    current: PathBuf,
+    /// The version of the lockfile in the merge base (%O)
+    #[structopt(parse(from_os_str))]
+    base: PathBuf,
+    /// Their version of the lockfile (%B)
+    #[structopt(parse(from_os_str))]
+    other: PathBuf,
+}
+
+#[derive(Debug, Fail)]
+enum LockError {
+    #[fail(display = "Could not read the lockfile \"{}\". {}", _0, _1)]
+    CouldNotRead(String, String),
+    #[fail(display = "Could not parse the lockfile \"{}\". {}", _0, _1)]
+    CouldNotParse(String, String),
+    #[fail(display = "Resolve the conflicts of wapm.toml first. {}", _0)]
+    InvalidManifest(String),
+    #[fail(display = "Failed to update the lockfile. {}", _0)]
+    CannotRegenLockfile(dataflow::Error),
+    #[fail(
+        display = "There is no personal key to sign the allowlist with, generate one with `wapm keys generate`"
+    )]
+    NoSigningKey,
+}
+
+pub fn lock(options: LockOpt) -> Result<(), failure::Error> {
+    let current_dir = env::current_dir()?;
+    match options {
+        LockOpt::Resolve => resolve(&current_dir),
+        LockOpt::MergeDriver(MergeDriverOpt {
+            current,
+            base,
+            other,
+        }) => {
+            let ours = read_lockfile_source(&current)?;
+            let theirs = read_lockfile_source(&other)?;
+            // the lockfile may not exist in the merge base
+            let base = read_lockfile_source(&base)
+                .ok()
+                .filter(|source| !source.trim().is_empty())
+                .map(|source| parse_lockfile(&source, &base, &current_dir))
+                .transpose()?;
+            // the manifest can have conflicts of its own during the merge
+            let requirements = match ManifestResult::find_in_directory(&current_dir) {
+                ManifestResult::Manifest(manifest) => manifest.dependencies.unwrap_or_default(),
+                _ => HashMap::new(),
+            };
+            let merged = merge_lockfiles(
+                parse_lockfile(&ours, &current, &current_dir)?,
+                parse_lockfile(&theirs, &other, &current_dir)?,
+                base.as_ref(),
+                &requirements,
+            );
+            merged.save_to_file(&current)?;
+            Ok(())
+        }
+        LockOpt::ExportAllowlist(ExportAllowlistOpt { output }) => {
+            export_allowlist(&current_dir, &output)
+        }
+    }
+}
+
+fn export_allowlist(directory: &Path, output: &Path) -> Result<(), failure::Error> {
+    let lockfile_path = directory.join(LOCKFILE_NAME);
+    let source = read_lockfile_source(&lockfile_path)?;
+    let lockfile = parse_lockfile(&source, &lockfile_path, directory)?;
+    let mut allowlist = Allowlist::default();
+    for (name, versions) in lockfile.modules.iter() {
+        for (version, modules) in versions.iter() {
+            // only packages from the registry are checked against the allowlist
+            let module = match modules
+                .values()
+                .find(|module| module.resolved_source.starts_with("registry+"))
+            {
+                Some(module) => module,
+                None => continue,
+            };
+            let key = WapmPackageKey {
+                name: Cow::Borrowed(name),
+                version: version.clone(),
+            };
+            allowlist.packages.push(AllowedPackage {
+                name: name.clone(),
+                version: version.to_string(),
+                sha256: installed_packages::archive_sha256(&key, &module.resolved)?,
+            });
+        }
+    }
+    let source = format!(
+        "# The only packages installs may fetch, generated by `wapm lock export-allowlist`\n\n{}",
+        toml::to_string(&allowlist)?
+    );
+    fs::write(output, source)?;
+
+    let signature_path = allowlist::signature_path(output);
+    match sign_compressed_archive(&mut fs::File::open(output)?)? {
+        SignArchiveResult::Ok {
+            public_key_id,
+            signature,
+        } => {
+            fs::write(&signature_path, signature)?;
+            println!(
+                "Wrote the allowlist of {} package(s) to {}, signed with the key {} in {}",
+                allowlist.packages.len(),
+                output.display(),
+                public_key_id,
+                signature_path.display()
+            );
+            Ok(())
+        }
+        SignArchiveResult::NoKeyRegistered => Err(LockError::NoSigningKey.into()),
+    }
+}
+
+fn resolve(directory: &Path) -> Result<(), failure::Error> {
+    let lockfile_path = directory.join(LOCKFILE_NAME);
+    let source = read_lockfile_source(&lockfile_path)?;
+    let (ours, theirs) = match split_conflicts(&source)? {
+        Some(sides) => sides,
+        None => {
+            println!("The lockfile has no merge conflicts");
+            return Ok(());
+        }
+    };
+    let manifest = match ManifestResult::find_in_directory(directory) {
+        ManifestResult::Manifest(manifest) => Some(manifest),
+        ManifestResult::NoManifest => None,
+        ManifestResult::ManifestError(e) => {
+            return Err(LockError::InvalidManifest(e.to_string()).into())
+        }
+    };
+    let requirements = manifest
+        .as_ref()
+        .and_then(|manifest| manifest.dependencies.clone())
+        .unwrap_or_default();
+    let merged = merge_lockfiles(
+        parse_lockfile(&ours, &lockfile_path, directory)?,
+        parse_lockfile(&theirs, &lockfile_path, directory)?,
+        None,
+        &requirements,
+    );
+    merged.save(directory)?;
+    // re-resolve the merged packages against the merged manifest
+    if manifest.is_some() {
+        dataflow::update(vec![], vec![], directory).map_err(LockError::CannotRegenLockfile)?;
+    }
+    println!("Resolved the merge conflicts of the lockfile");
+    Ok(())
+}
+
+fn read_lockfile_source(path: &Path) -> Result<String, LockError> {
+    fs::read_to_string(path)
+        .map_err(|e| LockError::CouldNotRead(path.display().to_string(), e.to_string()))
+}
+
+fn parse_lockfile(
+    source: &str,
+    path: &Path,
+    directory: &Path,
+) -> Result<crate::data::lock::lockfile::Lockfile, LockError> {
+    match LockfileResult::from_source(source, directory) {
+        LockfileResult::Lockfile(lockfile) => Ok(lockfile),
+        LockfileResult::LockfileError(e) => Err(LockError::CouldNotParse(
+            path.display().to_string(),
+            e.to_string(),
+        )),
+        LockfileResult::NoLockfile => Err(LockError::CouldNotRead(
+            path.display().to_string(),
+            "The lockfile is empty".to_string(),
+        )),
+    }
+}
@@ -1,5 +1,8 @@
+use crate::audit_log::{self, Action};
 use crate::config::Config;
+use crate::credentials;
 use crate::graphql::execute_query;
+use crate::identity;
 use std::io::prelude::*;
 use std::io::{stdin, stdout};
 
@@ -28,7 +31,9 @@ pub fn login() -> Result<(), failure::Error> {
         username: username.to_string(),
         password: password.to_string(),
     });
-    let response: login_mutation::ResponseData = execute_query(&q)?;
+    let response: Result<login_mutation::ResponseData, _> = execute_query(&q);
+    audit_log::record(Action::Login, None, None, Some(username), &response);
+    let response = response?;
     let token = match response.token_auth {
         Some(token_auth) => token_auth.refresh_token,
         None => None,
@@ -36,8 +41,9 @@ pub fn login() -> Result<(), failure::Error> {
     if let Some(token) = token {
         // Save the token
         let mut config = Config::from_file()?;
-        config.registry.token = Some(token);
+        config.registry.token = Some(credentials::protect(&config, &token)?);
         config.save()?;
+        identity::remember(username)?;
     }
     Ok(())
 }
@@ -1,8 +1,12 @@
+use crate::audit_log::{self, Action};
 use crate::config::Config;
+use crate::identity;
 
 pub fn logout() -> Result<(), failure::Error> {
+    identity::forget()?;
     let mut config = Config::from_file()?;
     config.registry.token = None;
-    config.save()?;
-    Ok(())
+    let result = config.save();
+    audit_log::record(Action::Logout, None, None, None, &result);
+    result
 }
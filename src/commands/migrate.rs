@@ -0,0 +1,95 @@
+//! Code pertaining to the `migrate` subcommand: it rewrites a manifest written for an older
+//! version of wapm to the current schema, showing the changes as a diff first
+
+use crate::data::manifest::MANIFEST_FILE_NAME;
+use crate::data::manifest_migrations;
+use crate::util;
+use similar::TextDiff;
+use std::env;
+use std::fs;
+use std::path::Path;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct MigrateOpt {
+    /// Show the changes without writing them
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+    /// Write the changes without asking
+    #[structopt(short = "y", long = "yes")]
+    yes: bool,
+}
+
+#[derive(Debug, Fail)]
+enum MigrateError {
+    #[fail(
+        display = "Could not find a manifest in the current directory, try running `wapm init`"
+    )]
+    NoManifest,
+}
+
+/// The unified diff of the manifest before and after migrating
+fn manifest_diff(source: &str, migrated: &str) -> String {
+    TextDiff::from_lines(source, migrated)
+        .unified_diff()
+        .header(MANIFEST_FILE_NAME, MANIFEST_FILE_NAME)
+        .to_string()
+}
+
+pub fn migrate(options: MigrateOpt) -> Result<(), failure::Error> {
+    let manifest_path = env::current_dir()?.join(MANIFEST_FILE_NAME);
+    let source = fs::read_to_string(&manifest_path).map_err(|_| MigrateError::NoManifest)?;
+    let (migrated, changes) = manifest_migrations::migrate(&source)?;
+    if changes.is_empty() {
+        println!("{} is up to date", MANIFEST_FILE_NAME);
+        return Ok(());
+    }
+    for change in &changes {
+        println!("- {}", change);
+    }
+    println!();
+    print!("{}", manifest_diff(&source, &migrated));
+    if options.dry_run {
+        return Ok(());
+    }
+    if !options.yes && !util::prompt_user_for_yes(&format!("Update {}?", MANIFEST_FILE_NAME))? {
+        return Ok(());
+    }
+    fs::write(&manifest_path, migrated)?;
+    println!("Updated {}", MANIFEST_FILE_NAME);
+    Ok(())
+}
+
+/// Offer to migrate the manifest in the directory if it uses an older schema. Outside of a
+/// terminal this only warns, pointing to `wapm migrate`.
+pub fn offer_migration(directory: &Path) -> Result<(), failure::Error> {
+    let manifest_path = directory.join(MANIFEST_FILE_NAME);
+    let source = match fs::read_to_string(&manifest_path) {
+        Ok(source) => source,
+        Err(_) => return Ok(()),
+    };
+    let (migrated, changes) = match manifest_migrations::migrate(&source) {
+        Ok(migration) => migration,
+        // manifests that can't be parsed are reported by the command
+        Err(_) => return Ok(()),
+    };
+    if changes.is_empty() {
+        return Ok(());
+    }
+    if !atty::is(atty::Stream::Stdin) {
+        warn!(
+            "{} uses keys of an older version of wapm, run `wapm migrate` to update it",
+            MANIFEST_FILE_NAME
+        );
+        return Ok(());
+    }
+    println!(
+        "{} uses keys of an older version of wapm:\n",
+        MANIFEST_FILE_NAME
+    );
+    print!("{}", manifest_diff(&source, &migrated));
+    if util::prompt_user_for_yes(&format!("Update {} now?", MANIFEST_FILE_NAME))? {
+        fs::write(&manifest_path, migrated)?;
+    }
+    Ok(())
+}
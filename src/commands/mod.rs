@@ -1,39 +1,129 @@
 //! List of exported subcommands for use by wapm
 
 mod add;
+mod alias;
+mod api;
+mod apply;
+mod attributions;
+mod audit_log;
+mod backup;
 mod bin;
+#[cfg(feature = "browse")]
+mod browse;
+mod bundle;
+mod check_updates;
+mod clean;
 mod completions;
 mod config;
+mod containerize;
+mod convert;
+mod default;
+mod detect_abi;
+mod diff;
+mod docs;
+mod doctor;
+mod du;
+mod env;
+mod exception;
+mod exec;
 mod execute;
+mod explain;
+mod export;
+mod exports;
+mod foreach;
+mod generate;
+mod history;
+mod index;
 mod init;
+mod inspect;
 mod install;
+mod interface;
 mod keys;
 mod list;
+mod lock;
 mod login;
 mod logout;
+mod migrate;
+mod notify;
+pub(crate) mod open;
+mod outdated;
 mod publish;
+mod push;
+mod readme;
+mod rehash;
 mod remove;
 mod run;
 mod search;
+mod setup;
+mod token;
 mod uninstall;
+mod upgrade;
 mod validate;
+mod which;
 mod whoami;
 
 pub use self::add::{add, AddOpt};
+pub use self::alias::{alias, AliasOpt};
+pub use self::api::{api, ApiOpt};
+pub use self::apply::{apply, ApplyOpt};
+pub use self::attributions::{attributions, AttributionsOpt};
+pub use self::audit_log::{audit_log, AuditLogOpt};
+pub use self::backup::{backup, restore, BackupOpt, RestoreOpt};
 pub use self::bin::{bin, BinOpt};
-pub use self::completions::CompletionOpt;
+#[cfg(feature = "browse")]
+pub use self::browse::{browse, BrowseOpt};
+pub use self::bundle::{bundle, BundleOpt};
+pub use self::check_updates::{check_updates, CheckUpdatesOpt};
+pub use self::clean::{clean, CleanOpt};
+pub use self::completions::{
+    add_run_command_completions, complete_commands, CompleteCommandsOpt, CompletionOpt,
+};
 pub use self::config::{config, ConfigOpt};
+pub use self::containerize::{containerize, ContainerizeOpt};
+pub use self::convert::{convert, ConvertOpt};
+pub use self::default::{default, DefaultOpt};
+pub use self::detect_abi::{detect_abi_command as detect_abi, DetectAbiOpt};
+pub use self::diff::{diff, DiffOpt};
+pub use self::docs::{docs, DocsOpt};
+pub use self::doctor::{doctor, DoctorOpt};
+pub use self::du::{du, DuOpt};
+pub use self::env::{env, EnvOpt};
+pub use self::exception::{exception, ExceptionOpt};
+pub use self::exec::{exec, ExecOpt};
 pub use self::execute::{execute, ExecuteOpt};
+pub use self::explain::{explain, ExplainOpt};
+pub use self::export::{export, ExportOpt};
+pub use self::exports::{exports, ExportsOpt};
+pub use self::foreach::{foreach, ForeachOpt};
+pub use self::generate::{generate, GenerateOpt};
+pub use self::history::{history, undo, HistoryOpt, UndoOpt};
+pub use self::index::{index, IndexOpt};
 pub use self::init::{init, InitOpt};
+pub use self::inspect::{inspect, InspectOpt};
 pub use self::install::{install, InstallOpt};
+pub use self::interface::{interface, InterfaceOpt};
 pub use self::keys::{keys, KeyOpt};
 pub use self::list::{list, ListOpt};
+pub use self::lock::{lock, LockOpt};
 pub use self::login::login;
 pub use self::logout::logout;
+pub use self::migrate::{migrate, offer_migration, MigrateOpt};
+pub use self::notify::{notify, NotifyOpt};
+pub use self::open::{open, OpenOpt};
+pub use self::outdated::{outdated, OutdatedOpt};
 pub use self::publish::{publish, PublishOpt};
+pub use self::push::{push, PushOpt};
+pub use self::readme::{readme, ReadmeOpt};
+pub use self::rehash::{rehash, RehashOpt};
 pub use self::remove::{remove, RemoveOpt};
-pub use self::run::{run, RunOpt};
-pub use self::search::{search, SearchOpt};
+pub use self::run::{command_help, run, RunOpt};
+pub use self::search::{
+    package_details, search, search_packages, PackageDetails, SearchOpt, SearchResult,
+};
+pub use self::setup::{setup, SetupOpt};
+pub use self::token::{token, TokenOpt};
 pub use self::uninstall::{uninstall, UninstallOpt};
+pub use self::upgrade::{upgrade, UpgradeOpt};
 pub use self::validate::{validate, ValidateOpt};
-pub use self::whoami::whoami;
+pub use self::which::{which, WhichOpt};
+pub use self::whoami::{whoami, WhoAmIOpt};
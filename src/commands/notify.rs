@@ -0,0 +1,47 @@
+//! Code pertaining to the `notify` subcommand: it sends the publish webhook for the package in
+//! the current directory, e.g. to announce a release that was published without the webhook
+//! or to try out the webhook configuration
+
+use crate::data::manifest::Manifest;
+use crate::webhook::{self, Notification};
+use std::env;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct NotifyOpt {
+    /// The version to announce, defaults to the version in the manifest
+    #[structopt(long = "version")]
+    version: Option<String>,
+}
+
+#[derive(Debug, Fail)]
+enum NotifyError {
+    #[fail(
+        display = "Could not find a manifest in the current directory, try running `wapm init`"
+    )]
+    NoManifest,
+    #[fail(display = "No webhook is configured, set one with `wapm config set webhook.url <url>`")]
+    NoWebhook,
+}
+
+pub fn notify(options: NotifyOpt) -> Result<(), failure::Error> {
+    let current_dir = env::current_dir()?;
+    let manifest =
+        Manifest::find_in_directory(&current_dir).map_err(|_| NotifyError::NoManifest)?;
+    let version = options
+        .version
+        .unwrap_or_else(|| manifest.package.version.to_string());
+    let notification = Notification {
+        changelog: webhook::changelog_excerpt(&current_dir, &version),
+        package: manifest.package.name,
+        version,
+    };
+    if !webhook::notify(&notification)? {
+        return Err(NotifyError::NoWebhook.into());
+    }
+    println!(
+        "Sent the webhook for `{}@{}`",
+        notification.package, notification.version
+    );
+    Ok(())
+}
@@ -0,0 +1,200 @@
+//! Code pertaining to the `open` subcommand: it opens the registry page, homepage, repository or
+//! documentation of a package in the default browser
+
+use crate::config::Config;
+use crate::data::manifest::{Manifest, Package};
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::registry;
+use crate::util::{get_package_namespace_and_name, get_packages_dir};
+use std::env;
+use std::io;
+use std::process::{Command, Stdio};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct OpenOpt {
+    /// The name of the package, like `_/sqlite`
+    package: String,
+    /// Open the homepage of the package instead of its registry page
+    #[structopt(long = "homepage", conflicts_with_all = &["repo", "docs"])]
+    homepage: bool,
+    /// Open the repository of the package instead of its registry page
+    #[structopt(long = "repo", conflicts_with = "docs")]
+    repo: bool,
+    /// Open the documentation of the package instead of its registry page
+    #[structopt(long = "docs")]
+    docs: bool,
+    /// Print the URL instead of opening it
+    #[structopt(long = "print")]
+    print: bool,
+}
+
+#[derive(Debug, Fail)]
+pub(crate) enum OpenError {
+    #[fail(
+        display = "Package {} was not found in the project or the registry",
+        _0
+    )]
+    PackageNotFound(String),
+    #[fail(display = "Package {} has no {} URL in its manifest", _0, _1)]
+    NoUrl(String, &'static str),
+    #[fail(display = "The registry at {} has no package pages", _0)]
+    NoRegistryPage(String),
+    #[fail(display = "{} is not an http or https URL", _0)]
+    NotAWebUrl(String),
+    #[fail(display = "Could not open {} in the browser: {}", _0, _1)]
+    CouldNotOpen(String, String),
+}
+
+pub fn open(options: OpenOpt) -> Result<(), failure::Error> {
+    let url = if options.homepage || options.repo || options.docs {
+        let package = find_package(&options.package)?;
+        let (url, kind) = if options.homepage {
+            (package.homepage, "homepage")
+        } else if options.repo {
+            (package.repository, "repository")
+        } else {
+            (package.documentation, "documentation")
+        };
+        url.ok_or_else(|| OpenError::NoUrl(options.package.clone(), kind))?
+    } else {
+        registry_page(&Config::from_file()?.registry.url, &options.package)?
+    };
+    // the URLs come from the manifests of packages, and the opener would run anything it knows
+    // how to open
+    let url = web_url(&url)?;
+
+    if options.print {
+        println!("{}", url);
+        return Ok(());
+    }
+    open_in_browser(&url).map_err(|e| OpenError::CouldNotOpen(url.clone(), e.to_string()))?;
+    println!("Opened {}", url);
+    Ok(())
+}
+
+/// The `[package]` section of the package, from the installed copy when the project has one and
+/// from the registry otherwise
+fn find_package(name: &str) -> Result<Package, failure::Error> {
+    let current_dir = env::current_dir()?;
+    if let LockfileResult::Lockfile(lockfile) = LockfileResult::find_in_directory(&current_dir) {
+        if let Some(version) = lockfile
+            .modules
+            .get(name)
+            .and_then(|versions| versions.keys().max())
+        {
+            let (namespace, package_name) = get_package_namespace_and_name(name)?;
+            let package_dir = get_packages_dir(&current_dir)
+                .join(namespace)
+                .join(format!("{}@{}", package_name, version));
+            if let Ok(manifest) = Manifest::find_in_directory(&package_dir) {
+                return Ok(manifest.package);
+            }
+        }
+    }
+
+    let manifest = registry::backend()?
+        .package_version(name, None)?
+        .and_then(|version| version.manifest)
+        .ok_or_else(|| OpenError::PackageNotFound(name.to_string()))?;
+    let manifest: Manifest = toml::from_str(&manifest)?;
+    Ok(manifest.package)
+}
+
+/// The page of the package on the website of the registry, which is served from the registry
+/// host without its `registry.` prefix
+pub(crate) fn registry_page(registry_url: &str, package: &str) -> Result<String, OpenError> {
+    let url = url::Url::parse(registry_url)
+        .ok()
+        .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+        .ok_or_else(|| OpenError::NoRegistryPage(registry_url.to_string()))?;
+    let host = url.host_str().unwrap_or_default();
+    let host = host.strip_prefix("registry.").unwrap_or(host);
+    let port = url
+        .port()
+        .map(|port| format!(":{}", port))
+        .unwrap_or_default();
+    Ok(format!(
+        "{}://{}{}/package/{}",
+        url.scheme(),
+        host,
+        port,
+        package
+    ))
+}
+
+/// The URL, normalized, when it is an http or https URL
+fn web_url(url: &str) -> Result<String, OpenError> {
+    url::Url::parse(url)
+        .ok()
+        .filter(|url| url.scheme() == "http" || url.scheme() == "https")
+        .map(String::from)
+        .ok_or_else(|| OpenError::NotAWebUrl(url.to_string()))
+}
+
+#[cfg(target_os = "macos")]
+fn open_in_browser(url: &str) -> io::Result<()> {
+    spawn(Command::new("open").arg(url))
+}
+
+#[cfg(windows)]
+fn open_in_browser(url: &str) -> io::Result<()> {
+    // unlike `cmd /C start`, this doesn't parse the URL as a command line
+    spawn(
+        Command::new("rundll32")
+            .arg("url.dll,FileProtocolHandler")
+            .arg(url),
+    )
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn open_in_browser(url: &str) -> io::Result<()> {
+    spawn(Command::new("xdg-open").arg(url))
+}
+
+fn spawn(command: &mut Command) -> io::Result<()> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn registry_pages_are_on_the_website() {
+        assert_eq!(
+            registry_page("https://registry.wapm.io", "_/sqlite").unwrap(),
+            "https://wapm.io/package/_/sqlite"
+        );
+        assert_eq!(
+            registry_page("http://localhost:8080/graphql", "syrusakbary/cowsay").unwrap(),
+            "http://localhost:8080/package/syrusakbary/cowsay"
+        );
+        assert!(registry_page("file:///srv/registry", "_/sqlite").is_err());
+    }
+
+    #[test]
+    fn only_web_urls_are_opened() {
+        assert_eq!(
+            web_url("https://github.com/wasmerio/wapm-cli").unwrap(),
+            "https://github.com/wasmerio/wapm-cli"
+        );
+        assert_eq!(
+            web_url("http://example.com/a b&calc").unwrap(),
+            "http://example.com/a%20b&calc"
+        );
+        for url in &[
+            "file:///etc/passwd",
+            "javascript:alert(1)",
+            "smb://example.com/share",
+            "calc.exe",
+        ] {
+            assert!(matches!(web_url(url), Err(OpenError::NotAWebUrl(_))));
+        }
+    }
+}
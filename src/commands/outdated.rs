@@ -0,0 +1,81 @@
+//! Code pertaining to the `outdated` subcommand: it lists the dependencies that have newer
+//! versions in the registry
+
+use crate::data::manifest::Manifest;
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::min_age;
+use crate::outdated::{format_rows, outdated_dependencies};
+use crate::registry;
+use chrono::{Duration, Local};
+use std::env;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct OutdatedOpt {
+    /// Hold back versions published more recently than this, e.g. `3d`.
+    /// Overrides the `install.min-age` config key
+    #[structopt(long = "min-age", parse(try_from_str = min_age::parse_min_age))]
+    min_age: Option<Duration>,
+}
+
+#[derive(Debug, Fail)]
+enum OutdatedError {
+    #[fail(
+        display = "Could not find a manifest in the current directory, try running `wapm init`"
+    )]
+    NoManifest,
+}
+
+pub fn outdated(options: OutdatedOpt) -> Result<(), failure::Error> {
+    let current_dir = env::current_dir()?;
+    let manifest =
+        Manifest::find_in_directory(&current_dir).map_err(|_| OutdatedError::NoManifest)?;
+    let lockfile = match LockfileResult::find_in_directory(&current_dir) {
+        LockfileResult::Lockfile(lockfile) => Some(lockfile),
+        _ => None,
+    };
+    let names: Vec<String> = manifest
+        .dependencies
+        .iter()
+        .flatten()
+        .map(|(name, _)| name.clone())
+        .collect();
+    let registry_versions = registry::backend()?.package_versions(&names)?;
+    let min_age = options.min_age.or_else(min_age::min_age);
+    let outdated = outdated_dependencies(&manifest, lockfile.as_ref(), &registry_versions, min_age);
+    let today = Local::now().naive_local().date();
+    let mut pins: Vec<_> = manifest.pins.iter().flatten().collect();
+    pins.sort_by(|a, b| a.0.cmp(b.0));
+    if outdated.is_empty() {
+        println!("All dependencies are up to date");
+    } else {
+        for row in format_rows(&outdated) {
+            println!("{}", row);
+        }
+    }
+    if !pins.is_empty() {
+        println!("\nPins:");
+        for (name, pin) in pins {
+            if pin.is_expired(today) {
+                println!(
+                    "  {}, expired: check whether it is still needed",
+                    pin.describe(name)
+                );
+            } else {
+                println!("  {}", pin.describe(name));
+            }
+        }
+    }
+    if let Some(min_age) = min_age {
+        if outdated
+            .iter()
+            .any(|dependency| dependency.held_back.is_some())
+        {
+            println!(
+                "\nVersions under HELD BACK were published less than {} ago and are not installed until they are older",
+                min_age::format_min_age(min_age)
+            );
+        }
+    }
+    Ok(())
+}
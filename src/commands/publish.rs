@@ -1,10 +1,22 @@
 //! The publish command uploads the package specified in the Manifest (`wapm.toml`)
 //! to the wapm registry.
-use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
+use crate::api_tokens;
+use crate::audit_log::{self, Action};
+use crate::categories;
+use crate::config::{Config, RegistryBackendKind};
+use crate::data::manifest::{Manifest, PackageKind, SymlinkPolicy, MANIFEST_FILE_NAME};
 use crate::database;
-use crate::graphql::execute_query_modifier;
+use crate::diagnostics::{self, Warning};
+use crate::graphql::{execute_query_modifier, GraphQLError};
+use crate::identity::{self, Identity};
+use crate::ipfs;
 use crate::keys;
+use crate::portable_paths::{self, long_path};
+use crate::publish_outbox::{self, PreparedPublish, PreparedSignature};
+use crate::registry::{self, RegistryError, S3Backend};
 use crate::validate;
+use crate::webc::WebcPackage;
+use crate::webhook::{self, Notification};
 
 use flate2::{write::GzEncoder, Compression};
 use graphql_client::*;
@@ -16,11 +28,25 @@ use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
+const ARCHIVE_NAME: &str = "package.tar.gz";
+const WEBC_ARCHIVE_NAME: &str = "package.webc";
+
 #[derive(StructOpt, Debug)]
 pub struct PublishOpt {
     /// Run the publish logic without sending anything to the registry server
     #[structopt(long = "dry-run")]
     dry_run: bool,
+    /// Add the package archive to the IPFS node from the `ipfs.api-url` config and record its
+    /// CID, so it can be fetched from IPFS gateways
+    #[structopt(long = "ipfs-pin")]
+    ipfs_pin: bool,
+    /// Retry the uploads of earlier publishes that failed, without building the packages again
+    #[structopt(long = "resume")]
+    resume: bool,
+    /// Upload the package as a webc container instead of a .tar.gz archive, for registries that
+    /// serve webc
+    #[structopt(long = "webc")]
+    webc: bool,
 }
 
 #[derive(GraphQLQuery)]
@@ -48,18 +74,209 @@ fn normalize_path(cwd: &Path, path: &Path) -> PathBuf {
 }
 
 pub fn publish(publish_opts: PublishOpt) -> Result<(), failure::Error> {
-    let mut builder = Builder::new(Vec::new());
+    if publish_opts.resume {
+        return resume_publishes();
+    }
     let cwd = env::current_dir()?;
 
+    // typos in the manifest would otherwise be published silently
+    validate::validate_manifest_keys(&cwd, false)?;
     validate::validate_directory(cwd.clone())?;
 
     let manifest = Manifest::find_in_directory(&cwd)?;
 
-    let manifest_path_buf = cwd.join(MANIFEST_FILE_NAME);
-    builder.append_path_with_name(&manifest_path_buf, MANIFEST_FILE_NAME)?;
     let package = &manifest.package;
-    let modules = manifest.module.as_ref().ok_or(PublishError::NoModule)?;
+    if !publish_opts.dry_run {
+        check_identity()?;
+        // a token that can't publish the package fails before the package is built
+        api_tokens::check_publish_scope(&package.name)?;
+    }
+    categories::check_package(package)?;
     let manifest_string = toml::to_string(&manifest)?;
+    let PackageContents {
+        tar_data: tar_archive_data,
+        readme,
+        license_file,
+    } = build_package_tar(&cwd, &manifest)?;
+    portable_paths::check_archive(&tar_archive_data)?;
+    let archive_dir = tempfile::TempDir::new()?;
+    fs::create_dir(archive_dir.path().join("wapm_package"))?;
+    let archive_name = if publish_opts.webc {
+        WEBC_ARCHIVE_NAME
+    } else {
+        ARCHIVE_NAME
+    };
+    let archive_path = archive_dir.as_ref().join("wapm_package").join(archive_name);
+    if publish_opts.webc {
+        fs::write(
+            &archive_path,
+            WebcPackage::from_tar(&tar_archive_data)?.to_bytes()?,
+        )?;
+    } else {
+        let mut compressed_archive = fs::File::create(&archive_path).unwrap();
+        let mut gz_enc = GzEncoder::new(&mut compressed_archive, Compression::default());
+
+        gz_enc.write_all(&tar_archive_data).unwrap();
+        let _compressed_archive = gz_enc.finish().unwrap();
+    }
+    let mut compressed_archive_reader = fs::File::open(&archive_path)?;
+
+    let maybe_signature_data = match sign_compressed_archive(&mut compressed_archive_reader)? {
+        SignArchiveResult::Ok {
+            public_key_id,
+            signature,
+        } => {
+            info!(
+                "Package successfully signed with public key: \"{}\"!",
+                &public_key_id
+            );
+            Some(PreparedSignature {
+                public_key_id,
+                data: signature,
+            })
+        }
+        SignArchiveResult::NoKeyRegistered => {
+            // TODO: uncomment this when we actually want users to start using it
+            //warn!("Publishing package without a verifying signature. Consider registering a key pair with wapm");
+            None
+        }
+    };
+
+    // the archive can't contain its own CID, so it is only recorded in the registry's manifest
+    let manifest_string = if publish_opts.ipfs_pin && !publish_opts.dry_run {
+        let distribution = ipfs::pin_archive(&archive_path)?;
+        info!(
+            "Package archive pinned to IPFS with CID {}",
+            distribution.cid
+        );
+        let mut published_manifest = manifest.clone();
+        published_manifest.package.ipfs = Some(distribution);
+        toml::to_string(&published_manifest)?
+    } else {
+        manifest_string
+    };
+
+    assert!(archive_path.exists());
+    assert!(archive_path.is_file());
+    let prepared = PreparedPublish {
+        registry: Config::from_file()?.registry.url,
+        name: package.name.to_string(),
+        version: package.version.to_string(),
+        description: package.description.clone(),
+        manifest: manifest_string,
+        license: package.license.clone(),
+        license_file,
+        readme,
+        repository: package.repository.clone(),
+        homepage: package.homepage.clone(),
+        signature: maybe_signature_data,
+        idempotency_key: publish_outbox::idempotency_key(
+            &package.name,
+            &package.version.to_string(),
+            &fs::read(&archive_path)?,
+        ),
+        archive_name: archive_name.to_string(),
+    };
+    if !publish_opts.dry_run {
+        // a denied warning would fail the command once the package is already published
+        diagnostics::check_denied()?;
+        if let Err(e) = upload(&prepared, &archive_path) {
+            if !is_retryable(&e) {
+                return Err(e);
+            }
+            publish_outbox::save(
+                &Config::get_publish_outbox_directory()?,
+                &prepared,
+                &archive_path,
+            )?;
+            // keeping the cause lets an expired login exit with its own code
+            let message = e.to_string();
+            return Err(e.context(PublishError::SavedToOutbox(message)).into());
+        }
+    }
+
+    println!(
+        "Successfully published package `{}@{}`",
+        package.name, package.version
+    );
+    if !publish_opts.dry_run {
+        let version = package.version.to_string();
+        notify_webhook(Notification {
+            changelog: webhook::changelog_excerpt(&cwd, &version),
+            package: package.name.clone(),
+            version,
+        });
+    }
+
+    if publish_opts.dry_run {
+        info!(
+            "Publish succeeded, but package was not published because it was run in dry-run mode"
+        );
+    }
+    Ok(())
+}
+
+/// Fail before the package is built when nobody is logged in to the registry. When the registry
+/// can't be reached the publish goes on with the identity it last verified, and is saved for
+/// `wapm publish --resume` if the upload fails.
+fn check_identity() -> Result<(), failure::Error> {
+    if Config::from_file()?.registry.backend_kind() != RegistryBackendKind::Graphql {
+        return Ok(());
+    }
+    match identity::current(false) {
+        Ok(Identity::Verified { username, .. }) => info!("Publishing as {}", username),
+        Ok(Identity::Stale {
+            username,
+            verified_at,
+            error,
+        }) => warn!(
+            "Could not reach the registry, publishing as {} who was last verified on {}: {}",
+            username,
+            identity::format_verified_at(verified_at),
+            error
+        ),
+        Ok(Identity::LoggedOut) => return Err(PublishError::NotLoggedIn.into()),
+        Err(e) => debug!("Could not check who is logged in: {}", e),
+    }
+    Ok(())
+}
+
+/// The files of a package, as they are published
+pub(crate) struct PackageContents {
+    /// The uncompressed tarball of the package
+    pub tar_data: Vec<u8>,
+    pub readme: Option<String>,
+    pub license_file: Option<String>,
+}
+
+/// Bundle the manifest, modules, interfaces and filesystem of the package in `cwd` into a tarball
+pub(crate) fn build_package_tar(
+    cwd: &Path,
+    manifest: &Manifest,
+) -> Result<PackageContents, failure::Error> {
+    let mut builder = Builder::new(Vec::new());
+    let manifest_path_buf = cwd.join(MANIFEST_FILE_NAME);
+    let package = &manifest.package;
+    let is_interface_package = manifest.package_kind() == PackageKind::Interface;
+    if is_interface_package {
+        validate::check_interface_versions(manifest)?;
+    }
+    let modules = match manifest.module.as_ref() {
+        Some(modules) => modules.as_slice(),
+        None if is_interface_package => &[],
+        None => return Err(PublishError::NoModule.into()),
+    };
+    let manifest_string = toml::to_string(manifest)?;
+    if manifest.inherited_fields.is_empty() {
+        builder.append_path_with_name(&manifest_path_buf, MANIFEST_FILE_NAME)?;
+    } else {
+        // the published manifest can't refer to the workspace, so the inherited values are used
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest_string.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, MANIFEST_FILE_NAME, manifest_string.as_bytes())?;
+    }
 
     let readme = package.readme.as_ref().and_then(|readme_path| {
         let normalized_path = normalize_path(&manifest.base_directory_path, &readme_path);
@@ -68,6 +285,29 @@ pub fn publish(publish_opts: PublishOpt) -> Result<(), failure::Error> {
         }
         fs::read_to_string(normalized_path).ok()
     });
+    if readme.is_none() {
+        diagnostics::warn(
+            Warning::MissingReadme,
+            format_args!(
+                "{} has no readme, set `readme` in the `[package]` section of {}",
+                package.name, MANIFEST_FILE_NAME
+            ),
+        );
+    }
+    // the translations are bundled for `wapm readme` to show in the language of the user
+    for (language, localized) in package.localized.iter() {
+        if let Some(readme_path) = &localized.readme {
+            let normalized_path = normalize_path(&manifest.base_directory_path, readme_path);
+            builder
+                .append_path_with_name(long_path(&normalized_path), readme_path)
+                .map_err(|_| {
+                    PublishError::MissingLocalizedReadme(
+                        language.clone(),
+                        readme_path.to_string_lossy().to_string(),
+                    )
+                })?;
+        }
+    }
     let license_file = package.license_file.as_ref().and_then(|license_file_path| {
         let normalized_path = normalize_path(&manifest.base_directory_path, &license_file_path);
         if let Err(_) = builder.append_path(&normalized_path) {
@@ -91,15 +331,23 @@ pub fn publish(publish_opts: PublishOpt) -> Result<(), failure::Error> {
             .append_path(normalized_path)
             .map_err(|_| PublishError::ErrorBuildingPackage(module.name.clone()))?;
     }
+    for definition in manifest.interface.iter().flatten() {
+        let normalized_path = normalize_path(&manifest.base_directory_path, &definition.path);
+        builder
+            .append_path_with_name(long_path(&normalized_path), &definition.path)
+            .map_err(|_| PublishError::ErrorBuildingPackage(definition.name.clone()))?;
+    }
 
-    // bundle the package filesystem
-    for (_alias, path) in manifest.fs.unwrap_or_default().iter() {
-        let normalized_path = normalize_path(&cwd, &path);
+    // bundle the package filesystem, whose links were checked against the policy by validation
+    builder.follow_symlinks(package.symlinks.unwrap_or_default() != SymlinkPolicy::Preserve);
+    for (_alias, path) in manifest.fs.clone().unwrap_or_default().iter() {
+        let normalized_path = normalize_path(cwd, &path);
         let path_metadata = normalized_path.metadata().map_err(|_| {
             PublishError::MissingManifestFsPath(normalized_path.to_string_lossy().to_string())
         })?;
         if path_metadata.is_dir() {
-            builder.append_dir_all(path, &normalized_path)
+            // the files of deep directories can be over the 260 characters Windows allows
+            builder.append_dir_all(path, long_path(&normalized_path))
         } else {
             return Err(PublishError::PackageFileSystemEntryMustBeDirectory(
                 path.to_string_lossy().to_string(),
@@ -112,86 +360,145 @@ pub fn publish(publish_opts: PublishOpt) -> Result<(), failure::Error> {
     }
 
     builder.finish().ok();
-    let tar_archive_data = builder.into_inner().map_err(|_|
+    let tar_data = builder.into_inner().map_err(|_|
                                                         // TODO:
                                                         PublishError::NoModule)?;
-    let archive_name = "package.tar.gz".to_string();
-    let archive_dir = tempfile::TempDir::new()?;
-    fs::create_dir(archive_dir.path().join("wapm_package"))?;
-    let archive_path = archive_dir
-        .as_ref()
-        .join("wapm_package")
-        .join(&archive_name);
-    let mut compressed_archive = fs::File::create(&archive_path).unwrap();
-    let mut gz_enc = GzEncoder::new(&mut compressed_archive, Compression::default());
-
-    gz_enc.write_all(&tar_archive_data).unwrap();
-    let _compressed_archive = gz_enc.finish().unwrap();
-    let mut compressed_archive_reader = fs::File::open(&archive_path)?;
+    Ok(PackageContents {
+        tar_data,
+        readme,
+        license_file,
+    })
+}
 
-    let maybe_signature_data = match sign_compressed_archive(&mut compressed_archive_reader)? {
-        SignArchiveResult::Ok {
-            public_key_id,
-            signature,
-        } => {
-            info!(
-                "Package successfully signed with public key: \"{}\"!",
-                &public_key_id
-            );
-            Some(publish_package_mutation::InputSignature {
-                public_key_key_id: public_key_id,
-                data: signature,
-            })
-        }
-        SignArchiveResult::NoKeyRegistered => {
-            // TODO: uncomment this when we actually want users to start using it
-            //warn!("Publishing package without a verifying signature. Consider registering a key pair with wapm");
-            None
-        }
-    };
+/// Send a built package to the registry, logging the attempt in the audit log
+fn upload(prepared: &PreparedPublish, archive_path: &Path) -> Result<(), failure::Error> {
+    let result = send_package(prepared, archive_path);
+    audit_log::record(
+        Action::Publish,
+        Some(&prepared.name),
+        Some(&prepared.version),
+        None,
+        &result,
+    );
+    result
+}
 
+fn send_package(prepared: &PreparedPublish, archive_path: &Path) -> Result<(), failure::Error> {
+    let config = Config::from_file()?;
+    if config.registry.backend_kind() == RegistryBackendKind::S3 {
+        // S3 registries have no server, the bucket is written to directly
+        return S3Backend::from_registry(&config.registry)?.publish(
+            &prepared.name,
+            &prepared.version,
+            prepared.manifest.clone(),
+            archive_path,
+        );
+    }
     let q = PublishPackageMutation::build_query(publish_package_mutation::Variables {
-        name: package.name.to_string(),
-        version: package.version.to_string(),
-        description: package.description.clone(),
-        manifest: manifest_string,
-        license: package.license.clone(),
-        license_file,
-        readme,
-        repository: package.repository.clone(),
-        homepage: package.homepage.clone(),
-        file_name: Some(archive_name.clone()),
-        signature: maybe_signature_data,
+        name: prepared.name.clone(),
+        version: prepared.version.clone(),
+        description: prepared.description.clone(),
+        manifest: prepared.manifest.clone(),
+        license: prepared.license.clone(),
+        license_file: prepared.license_file.clone(),
+        readme: prepared.readme.clone(),
+        repository: prepared.repository.clone(),
+        homepage: prepared.homepage.clone(),
+        file_name: Some(prepared.archive_name.clone()),
+        signature: prepared.signature.as_ref().map(|signature| {
+            publish_package_mutation::InputSignature {
+                public_key_key_id: signature.public_key_id.clone(),
+                data: signature.data.clone(),
+            }
+        }),
+        client_mutation_id: Some(prepared.idempotency_key.clone()),
     });
-    assert!(archive_path.exists());
-    assert!(archive_path.is_file());
-    if !publish_opts.dry_run {
-        let _response: publish_package_mutation::ResponseData =
-            execute_query_modifier(&q, |f| f.file(archive_name, archive_path).unwrap()).map_err(
-                |e| {
-                    #[cfg(feature = "telemetry")]
-                    sentry::integrations::failure::capture_error(&e);
-                    e
-                },
-            )?;
+    let _response: publish_package_mutation::ResponseData = execute_query_modifier(&q, |f| {
+        f.file(prepared.archive_name.clone(), archive_path).unwrap()
+    })
+    .map_err(|e| {
+        #[cfg(feature = "telemetry")]
+        sentry::integrations::failure::capture_error(&e);
+        e
+    })?;
+    Ok(())
+}
+
+/// Announce a publish, the package is already published so failures are only warnings
+fn notify_webhook(notification: Notification) {
+    if let Err(e) = webhook::notify(&notification) {
+        warn!("Could not notify the publish webhook: {}", e);
     }
+}
 
-    println!(
-        "Successfully published package `{}@{}`",
-        package.name, package.version
-    );
+/// Failures that trying again later can fix, unlike the registry rejecting the package
+fn is_retryable(error: &failure::Error) -> bool {
+    if error.downcast_ref::<GraphQLError>().is_some() {
+        return false;
+    }
+    !matches!(
+        error.downcast_ref::<RegistryError>(),
+        Some(RegistryError::VersionAlreadyPublished(..))
+    )
+}
 
-    if publish_opts.dry_run {
-        info!(
-            "Publish succeeded, but package was not published because it was run in dry-run mode"
-        );
+/// Retry the uploads of the publishes in the outbox
+fn resume_publishes() -> Result<(), failure::Error> {
+    let registry_url = Config::from_file()?.registry.url;
+    let entries = publish_outbox::entries(&Config::get_publish_outbox_directory()?)?;
+    if entries.is_empty() {
+        println!("No publishes to resume");
+        return Ok(());
+    }
+    let mut failed = 0;
+    for entry in entries {
+        let name = entry.publish.name.clone();
+        let version = entry.publish.version.clone();
+        if entry.publish.registry != registry_url {
+            info!(
+                "Skipping `{}@{}` which is published to {}",
+                name, version, entry.publish.registry
+            );
+            continue;
+        }
+        // an earlier attempt may have reached the registry before failing
+        let already_published = registry::backend()
+            .and_then(|backend| backend.package_version(&name, Some(&version)))
+            .map(|package_version| package_version.is_some())
+            .unwrap_or(false);
+        if already_published {
+            info!("Package `{}@{}` was already published", name, version);
+            entry.remove()?;
+            continue;
+        }
+        match upload(&entry.publish, &entry.archive_path()) {
+            Ok(()) => {
+                println!("Successfully published package `{}@{}`", name, version);
+                entry.remove()?;
+                notify_webhook(Notification {
+                    package: name,
+                    version,
+                    changelog: None,
+                });
+            }
+            Err(e) => {
+                error!("Could not publish package `{}@{}`: {}", name, version, e);
+                if !is_retryable(&e) {
+                    entry.remove()?;
+                }
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(PublishError::ResumeFailed(failed).into());
     }
     Ok(())
 }
 
 #[derive(Debug, Fail)]
 enum PublishError {
-    #[fail(display = "Cannot publish without a module.")]
+    #[fail(display = "Cannot publish without a module or an interface definition.")]
     NoModule,
     #[fail(display = "Module \"{}\" must have a source that is a file.", _0)]
     SourceMustBeFile(String),
@@ -207,6 +514,17 @@ enum PublishError {
         _0
     )]
     PackageFileSystemEntryMustBeDirectory(String),
+    #[fail(
+        display = "{}\nThe package was saved, run `wapm publish --resume` to retry the upload.",
+        _0
+    )]
+    SavedToOutbox(String),
+    #[fail(display = "{} package(s) could not be published", _0)]
+    ResumeFailed(usize),
+    #[fail(display = "The \"{}\" readme \"{}\" does not exist.", _0, _1)]
+    MissingLocalizedReadme(String, String),
+    #[fail(display = "You need to be logged in to publish, run `wapm login`")]
+    NotLoggedIn,
 }
 
 #[derive(Debug)]
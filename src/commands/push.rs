@@ -0,0 +1,61 @@
+//! The push command uploads the package in the current directory to an OCI registry, as an
+//! artifact that `wapm install oci://...` can install.
+
+use crate::commands::publish::{build_package_tar, PackageContents};
+use crate::data::manifest::Manifest;
+use crate::oci::{self, OciReference};
+use crate::validate;
+use flate2::{write::GzEncoder, Compression};
+use std::env;
+use std::io::Write;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct PushOpt {
+    /// The repository to push to, like `oci://ghcr.io/user/pkg:1.0.0`. Without a tag, the
+    /// package is tagged with its version
+    #[structopt(parse(try_from_str = OciReference::parse))]
+    reference: OciReference,
+    /// Build the package archive without sending anything to the registry
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+}
+
+#[derive(Debug, Fail)]
+enum PushError {
+    #[fail(display = "Packages are pushed to a tag, not to the digest of {}", _0)]
+    DigestReference(String),
+}
+
+pub fn push(options: PushOpt) -> Result<(), failure::Error> {
+    if options.reference.digest.is_some() {
+        return Err(PushError::DigestReference(options.reference.to_string()).into());
+    }
+    let cwd = env::current_dir()?;
+    validate::validate_manifest_keys(&cwd, false)?;
+    validate::validate_directory(cwd.clone())?;
+    let manifest = Manifest::find_in_directory(&cwd)?;
+    let PackageContents { tar_data, .. } = build_package_tar(&cwd, &manifest)?;
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&tar_data)?;
+    let archive = encoder.finish()?;
+
+    let package = &manifest.package;
+    let reference = match &options.reference.tag {
+        Some(_) => options.reference.clone(),
+        None => options.reference.with_tag(&package.version.to_string()),
+    };
+    if options.dry_run {
+        info!(
+            "Push succeeded, but {}@{} was not pushed to {} because it was run in dry-run mode",
+            package.name, package.version, reference
+        );
+        return Ok(());
+    }
+    let digest = oci::push(&reference, &manifest, &archive)?;
+    println!(
+        "Successfully pushed package `{}@{}` to {} ({})",
+        package.name, package.version, reference, digest
+    );
+    Ok(())
+}
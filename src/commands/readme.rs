@@ -0,0 +1,99 @@
+//! Code pertaining to the `readme` subcommand: it shows the readme of a package from the registry
+//! without installing the package. A translation of the readme is shown when the package has one
+//! in the language of the user.
+
+use crate::commands::{package_details, PackageDetails};
+use crate::data::manifest::Manifest;
+use crate::dataflow::installed_packages::{Install, RegistryInstaller};
+use crate::dataflow::WapmPackageKey;
+use crate::i18n;
+use crate::integrity_exceptions::IntegrityExceptions;
+use crate::markdown;
+use crate::registry;
+use semver::Version;
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::path::Path;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ReadmeOpt {
+    /// The package, like `_/sqlite` or `_/sqlite@0.1.1` for a version other than the last one
+    package: String,
+    /// Print the markdown of the readme as it is
+    #[structopt(long = "raw")]
+    raw: bool,
+}
+
+#[derive(Debug, Fail)]
+enum ReadmeError {
+    #[fail(display = "Package {} was not found in the registry", _0)]
+    PackageNotFound(String),
+    #[fail(display = "{}@{} has no readme", _0, _1)]
+    NoReadme(String, String),
+}
+
+pub fn readme(options: ReadmeOpt) -> Result<(), failure::Error> {
+    let (name, version) = match options.package.find('@') {
+        Some(index) => (
+            &options.package[..index],
+            Some(&options.package[index + 1..]),
+        ),
+        None => (options.package.as_str(), None),
+    };
+    let details = package_details(name, version)?
+        .ok_or_else(|| ReadmeError::PackageNotFound(options.package.clone()))?;
+    let readme = match localized_readme(&details).or(details.readme) {
+        Some(readme) if !readme.trim().is_empty() => readme,
+        _ => return Err(ReadmeError::NoReadme(details.name, details.version).into()),
+    };
+    if options.raw {
+        print!("{}", readme);
+    } else {
+        println!(
+            "{}",
+            markdown::render(&readme, atty::is(atty::Stream::Stdout))
+        );
+    }
+    Ok(())
+}
+
+/// The readme of the package in the language of the user, when the published manifest has one
+fn localized_readme(details: &PackageDetails) -> Option<String> {
+    let manifest: Manifest = toml::from_str(&details.manifest).ok()?;
+    let language = i18n::language();
+    let path = manifest.package.localized.get(language)?.readme.clone()?;
+    match download_file(&details.name, &details.version, &path) {
+        Ok(readme) => Some(readme),
+        Err(e) => {
+            warn!(
+                "Could not fetch the \"{}\" readme of {}@{}, showing the English one: {}",
+                language, details.name, details.version, e
+            );
+            None
+        }
+    }
+}
+
+/// Read a file of a published package. Only the registry has the main readme, the translations
+/// are in the package archive.
+fn download_file(name: &str, version: &str, path: &Path) -> Result<String, failure::Error> {
+    let package_version = registry::backend()?
+        .package_version(name, Some(version))?
+        .ok_or_else(|| ReadmeError::PackageNotFound(format!("{}@{}", name, version)))?;
+    let temp_dir = tempfile::TempDir::new()?;
+    let key = WapmPackageKey {
+        name: Cow::Owned(name.to_string()),
+        version: Version::parse(version)?,
+    };
+    let (_, package_dir, _) = RegistryInstaller::install_package(
+        temp_dir.path(),
+        key,
+        &package_version.download_url,
+        package_version.signature,
+        false,
+        &IntegrityExceptions::load(&env::current_dir()?)?,
+    )?;
+    Ok(fs::read_to_string(package_dir.join(path))?)
+}
@@ -0,0 +1,31 @@
+//! Code pertaining to the `rehash` subcommand: it writes the shims of the global commands again,
+//! for when they were deleted or edited by hand
+
+use crate::config::Config;
+use crate::shims;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct RehashOpt {}
+
+pub fn rehash(_options: RehashOpt) -> Result<(), failure::Error> {
+    let globals_directory = Config::get_globals_directory()?;
+    let drift = shims::rehash(&globals_directory)?;
+    if drift.is_empty() {
+        println!("The shims of the global commands were up to date");
+        return Ok(());
+    }
+    for name in drift.missing.iter() {
+        println!("Restored the missing shim of {}", name);
+    }
+    for name in drift.outdated.iter() {
+        println!("Rewrote the outdated shim of {}", name);
+    }
+    for name in drift.stale.iter() {
+        println!(
+            "Removed the shim {} of a command that is not installed",
+            name
+        );
+    }
+    Ok(())
+}
@@ -1,15 +1,21 @@
-use crate::config::Config;
 use crate::constants::DEFAULT_RUNTIME;
-use crate::data::lock::is_lockfile_out_of_date;
+use crate::data::lock::{is_lockfile_out_of_date, LOCKFILE_NAME};
+use crate::data::manifest::Manifest;
 use crate::dataflow;
 use crate::dataflow::find_command_result;
 use crate::dataflow::find_command_result::get_command_from_anywhere;
 use crate::dataflow::manifest_packages::ManifestResult;
+use crate::sandbox::SandboxProfile;
 use crate::util::get_runtime_with_args;
+use chrono::Utc;
 use std::env;
 use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Instant;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -19,15 +25,100 @@ pub struct RunOpt {
     /// WASI pre-opened directory
     #[structopt(long = "dir", multiple = true, group = "wasi")]
     pre_opened_directories: Vec<String>,
+    /// Map a host directory to a guest path for this run, `GUEST:HOST`. Replaces a mapping of
+    /// the same guest path in the manifest
+    #[structopt(
+        long = "mapdir",
+        number_of_values = 1,
+        parse(try_from_str = parse_mapdir)
+    )]
+    mapped_directories: Vec<(String, String)>,
+    /// Environment variable for the command, `KEY=VALUE`, or `KEY` to pass the variable of the
+    /// current environment
+    #[structopt(long = "env", number_of_values = 1)]
+    env_vars: Vec<String>,
+    /// Record the arguments, stdout, stderr, exit code and timing of the run in a JSON file, e.g.
+    /// for bug reports. The output is still shown as it is written
+    #[structopt(long = "capture", parse(from_os_str))]
+    capture: Option<PathBuf>,
+    /// Run the command with a sandbox profile instead of the directories and flags its manifest
+    /// asks for: `strict`, `standard` or `none`
+    #[structopt(long = "sandbox")]
+    sandbox: Option<SandboxProfile>,
     /// Application arguments
     #[structopt(multiple = true, parse(from_os_str))]
     args: Vec<OsString>,
 }
 
+/// Parse a `GUEST:HOST` directory mapping. The guest path ends at the first `:`, so host paths
+/// like `C:\data` work.
+fn parse_mapdir(mapping: &str) -> Result<(String, String), String> {
+    let mut parts = mapping.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some(guest), Some(host)) if !guest.is_empty() && !host.is_empty() => {
+            Ok((guest.to_string(), host.to_string()))
+        }
+        _ => Err(format!(
+            "invalid directory mapping \"{}\", expected GUEST:HOST",
+            mapping
+        )),
+    }
+}
+
+/// The `KEY=VALUE` pairs of environment variables given as `KEY=VALUE` or as `KEY`, which takes
+/// the value from the current environment
+pub(crate) fn resolve_env_vars(env_vars: &[String]) -> Result<Vec<String>, RunError> {
+    env_vars
+        .iter()
+        .map(|env_var| {
+            if env_var.contains('=') {
+                return Ok(env_var.clone());
+            }
+            env::var(env_var)
+                .map(|value| format!("{}={}", env_var, value))
+                .map_err(|_| RunError::EnvVarNotSet(env_var.clone()))
+        })
+        .collect()
+}
+
+/// The help the manifest declares for a command, which `wapm run <command> --help` shows instead
+/// of running the command
+pub fn command_help(command_name: &str) -> Option<String> {
+    let current_dir = env::current_dir().ok()?;
+    let declared_in = |manifest: &Manifest| {
+        let name = command_name.split('@').next().unwrap_or(command_name);
+        manifest
+            .command
+            .iter()
+            .flatten()
+            .find(|command| command.name == name)
+            .and_then(|command| command.help())
+    };
+    let local_manifest = match ManifestResult::find_in_directory(&current_dir) {
+        ManifestResult::Manifest(manifest) => Some(manifest),
+        _ => None,
+    };
+    if let Some(manifest) = &local_manifest {
+        if let Some(help) = declared_in(manifest) {
+            return Some(help);
+        }
+        // commands are looked up in the lockfile, which is only written when running them
+        if !current_dir.join(LOCKFILE_NAME).exists() {
+            return None;
+        }
+    }
+    let found = get_command_from_anywhere(command_name).ok()?;
+    match ManifestResult::find_in_directory(found.directory.join(&found.manifest_dir)) {
+        ManifestResult::Manifest(manifest) => declared_in(&manifest),
+        _ => None,
+    }
+}
+
 pub fn run(run_options: RunOpt) -> Result<(), failure::Error> {
     let command_name = run_options.command.as_str();
     let args = &run_options.args;
     let current_dir = env::current_dir()?;
+    let env_vars = resolve_env_vars(&run_options.env_vars)?;
 
     // always update the local lockfile if the manifest has changed
     match is_lockfile_out_of_date(&current_dir) {
@@ -42,7 +133,7 @@ pub fn run(run_options: RunOpt) -> Result<(), failure::Error> {
         manifest_dir,
         args: _,
         module_name,
-        is_global,
+        directory: run_dir,
         prehashed_cache_key,
     } = match get_command_from_anywhere(command_name) {
         Err(find_command_result::Error::CommandNotFound(command)) => {
@@ -58,12 +149,6 @@ pub fn run(run_options: RunOpt) -> Result<(), failure::Error> {
         otherwise => otherwise?,
     };
 
-    let run_dir = if is_global {
-        Config::get_globals_directory().unwrap()
-    } else {
-        current_dir.clone()
-    };
-
     let manifest_dir = run_dir.join(manifest_dir);
 
     do_run(
@@ -73,11 +158,16 @@ pub fn run(run_options: RunOpt) -> Result<(), failure::Error> {
         command_name,
         &module_name,
         &run_options.pre_opened_directories,
+        &run_options.mapped_directories,
+        &env_vars,
         &args,
         prehashed_cache_key,
+        run_options.sandbox,
+        run_options.capture.as_deref(),
     )
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn do_run(
     run_dir: PathBuf,
     source_path_buf: PathBuf,
@@ -85,9 +175,14 @@ pub(crate) fn do_run(
     command_name: &str,
     module_name: &str,
     pre_opened_directories: &[String],
+    mapped_directories: &[(String, String)],
+    env_vars: &[String],
     args: &[OsString],
     prehashed_cache_key: Option<String>,
+    sandbox: Option<SandboxProfile>,
+    capture: Option<&Path>,
 ) -> Result<(), failure::Error> {
+    let sandbox = sandbox.unwrap_or(SandboxProfile::Unrestricted);
     debug!(
         "Running module located at {:?}",
         &run_dir.join(&source_path_buf)
@@ -103,24 +198,39 @@ pub(crate) fn do_run(
 
     let wasmer_extra_flags: Option<Vec<OsString>> =
         match ManifestResult::find_in_directory(&manifest_dir) {
-            ManifestResult::Manifest(manifest) => {
-                manifest
-                    .package
-                    .wasmer_extra_flags
-                    .clone()
-                    .map(|extra_flags| {
-                        extra_flags
-                            .split_whitespace()
-                            .map(|str| OsString::from(str))
-                            .collect()
-                    })
-            }
+            ManifestResult::Manifest(manifest) if sandbox.uses_manifest_flags() => manifest
+                .package
+                .wasmer_extra_flags
+                .clone()
+                .map(|extra_flags| {
+                    extra_flags
+                        .split_whitespace()
+                        .map(|str| OsString::from(str))
+                        .collect()
+                }),
             _ => None,
         };
 
     let mut wasi_preopened_dir_flags: Vec<OsString> = pre_opened_directories
         .iter()
         .map(|entry| OsString::from(format!("--dir={}", entry)))
+        .chain(
+            sandbox
+                .current_runtime_flags()
+                .into_iter()
+                .map(OsString::from),
+        )
+        // given last so they win over the variables of the sandbox profile
+        .chain(
+            mapped_directories
+                .iter()
+                .map(|(guest, host)| OsString::from(format!("--mapdir={}:{}", guest, host))),
+        )
+        .chain(
+            env_vars
+                .iter()
+                .map(|env_var| OsString::from(format!("--env={}", env_var))),
+        )
         .collect();
 
     let mut disable_command_rename = false;
@@ -129,9 +239,19 @@ pub(crate) fn do_run(
         ManifestResult::Manifest(manifest) => {
             disable_command_rename = manifest.package.disable_command_rename;
             manifest.package.rename_commands_to_raw_command_name;
-            if let Some(ref fs) = manifest.fs {
+            if let Some(fs) = manifest
+                .fs
+                .as_ref()
+                .filter(|_| sandbox.uses_manifest_flags())
+            {
                 // todo: normalize (rm `:` and newline, etc) these paths if we haven't yet
                 for (guest_path, host_path) in fs.iter() {
+                    if mapped_directories
+                        .iter()
+                        .any(|(guest, _)| guest == guest_path)
+                    {
+                        continue;
+                    }
                     wasi_preopened_dir_flags.push(OsString::from(format!(
                         "--mapdir={}:{}",
                         guest_path,
@@ -165,22 +285,131 @@ pub(crate) fn do_run(
         prehashed_cache_key,
     )?;
     debug!("Running command with args: {:?}", command_vec);
-    let mut child = Command::new(&runtime)
-        .args(&runtime_args)
-        .args(&command_vec)
-        .spawn()
-        .map_err(|e| -> failure::Error {
-            RunError::ProcessFailed {
-                runtime: runtime,
-                error: format!("{:?}", e),
-            }
-            .into()
-        })?;
+    let mut process = Command::new(&runtime);
+    process.args(&runtime_args).args(&command_vec);
+    if capture.is_some() {
+        process.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let mut child = process.spawn().map_err(|e| -> failure::Error {
+        RunError::ProcessFailed {
+            runtime: runtime.clone(),
+            error: format!("{:?}", e),
+        }
+        .into()
+    })?;
 
-    child.wait()?;
+    let capture = match capture {
+        Some(capture) => capture,
+        None => {
+            child.wait()?;
+            return Ok(());
+        }
+    };
+    let stdout = child.stdout.take().map(|stdout| tee(stdout, io::stdout));
+    let stderr = child.stderr.take().map(|stderr| tee(stderr, io::stderr));
+    let status = child.wait()?;
+    let output = |stream: Option<thread::JoinHandle<Vec<u8>>>| {
+        stream
+            .and_then(|stream| stream.join().ok())
+            .map(|output| String::from_utf8_lossy(&output).to_string())
+            .unwrap_or_default()
+    };
+    let run_capture = RunCapture {
+        command: command_name.to_string(),
+        runtime,
+        arguments: redact_env_values(
+            runtime_args.into_iter().chain(
+                command_vec
+                    .iter()
+                    .map(|arg| arg.to_string_lossy().to_string()),
+            ),
+        ),
+        directory: env::current_dir()?,
+        started_at: started_at.to_rfc3339(),
+        duration_ms: started.elapsed().as_millis() as u64,
+        exit_code: status.code(),
+        stdout: output(stdout),
+        stderr: output(stderr),
+    };
+    fs::write(capture, serde_json::to_string_pretty(&run_capture)? + "\n")?;
     Ok(())
 }
 
+/// What `wapm run --capture` records about a run
+#[derive(Debug, Serialize)]
+struct RunCapture {
+    command: String,
+    runtime: String,
+    /// The arguments the runtime was started with, without the values of environment variables
+    arguments: Vec<String>,
+    directory: PathBuf,
+    started_at: String,
+    duration_ms: u64,
+    /// None if the command was stopped by a signal
+    exit_code: Option<i32>,
+    stdout: String,
+    stderr: String,
+}
+
+/// The value written in place of the values of environment variables in captures
+const REDACTED: &str = "<redacted>";
+
+/// Replace the values of the `--env=KEY=VALUE` arguments of the runtime, which are often
+/// secrets read from the environment, so captures can be shared in bug reports. The arguments
+/// of the command after `--` are kept as they are.
+fn redact_env_values<I: IntoIterator<Item = String>>(arguments: I) -> Vec<String> {
+    let redact = |variable: &str| match variable.find('=') {
+        Some(index) => format!("{}={}", &variable[..index], REDACTED),
+        None => variable.to_string(),
+    };
+    let mut redacted = vec![];
+    let mut value_follows = false;
+    let mut arguments = arguments.into_iter();
+    while let Some(argument) = arguments.next() {
+        if argument == "--" {
+            redacted.push(argument);
+            redacted.extend(arguments.by_ref());
+            break;
+        }
+        if value_follows {
+            redacted.push(redact(&argument));
+            value_follows = false;
+        } else if let Some(variable) = argument.strip_prefix("--env=") {
+            redacted.push(format!("--env={}", redact(variable)));
+        } else {
+            value_follows = argument == "--env";
+            redacted.push(argument);
+        }
+    }
+    redacted
+}
+
+/// Copy the output of a child process to an output of this process as it comes, returning what
+/// was copied once the child closes it
+fn tee<R, W, F>(mut reader: R, output: F) -> thread::JoinHandle<Vec<u8>>
+where
+    R: Read + Send + 'static,
+    W: Write,
+    F: Fn() -> W + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut captured = vec![];
+        let mut buffer = [0; 8192];
+        while let Ok(read) = reader.read(&mut buffer) {
+            if read == 0 {
+                break;
+            }
+            let mut output = output();
+            output.write_all(&buffer[..read]).ok();
+            output.flush().ok();
+            captured.extend_from_slice(&buffer[..read]);
+        }
+        captured
+    })
+}
+
 fn create_run_command<P: AsRef<Path>, P2: AsRef<Path>>(
     args: &[OsString],
     wasmer_extra_flags: Option<Vec<OsString>>,
@@ -219,7 +448,7 @@ fn create_run_command<P: AsRef<Path>, P2: AsRef<Path>>(
 
 #[cfg(test)]
 mod test {
-    use crate::commands::run::create_run_command;
+    use crate::commands::run::{create_run_command, parse_mapdir, redact_env_values, tee};
     use crate::data::manifest::PACKAGES_DIR_NAME;
     use std::ffi::OsString;
     use std::fs;
@@ -251,10 +480,58 @@ mod test {
             create_run_command(&args, None, vec![], &dir, wasm_relative_path, None, None).unwrap();
         assert_eq!(expected_command, actual_command);
     }
+
+    #[test]
+    fn directory_mappings_are_parsed() {
+        assert_eq!(
+            parse_mapdir("/data:./fixtures"),
+            Ok(("/data".to_string(), "./fixtures".to_string()))
+        );
+        assert_eq!(
+            parse_mapdir("data:C:\\data"),
+            Ok(("data".to_string(), "C:\\data".to_string()))
+        );
+        assert!(parse_mapdir("/data").is_err());
+        assert!(parse_mapdir(":./fixtures").is_err());
+    }
+
+    #[test]
+    fn captures_do_not_contain_env_values() {
+        let arguments = vec![
+            "run",
+            "--env=API_TOKEN=secret",
+            "--env",
+            "PASSWORD=hunter2",
+            "--env=EMPTY",
+            "module.wasm",
+            "--",
+            "--env=ARG=kept",
+        ];
+        let redacted = redact_env_values(arguments.into_iter().map(str::to_string));
+        assert_eq!(
+            redacted,
+            vec![
+                "run",
+                "--env=API_TOKEN=<redacted>",
+                "--env",
+                "PASSWORD=<redacted>",
+                "--env=EMPTY",
+                "module.wasm",
+                "--",
+                "--env=ARG=kept",
+            ]
+        );
+    }
+
+    #[test]
+    fn captured_output_is_copied_through() {
+        let output = tee(std::io::Cursor::new(b"hello\n".to_vec()), std::io::sink);
+        assert_eq!(output.join().unwrap(), b"hello\n");
+    }
 }
 
 #[derive(Debug, Fail)]
-enum RunError {
+pub(crate) enum RunError {
     #[fail(display = "Failed to run command \"{}\". {}", _0, _1)]
     CannotRegenLockfile(String, dataflow::Error),
     #[fail(
@@ -264,4 +541,9 @@ enum RunError {
     SourceForCommandNotFound(String, String, String),
     #[fail(display = "Failed to run {}: {}", runtime, error)]
     ProcessFailed { runtime: String, error: String },
+    #[fail(
+        display = "Environment variable {} is not set, use `--env {}=VALUE` to give it a value",
+        _0, _0
+    )]
+    EnvVarNotSet(String),
 }
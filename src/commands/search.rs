@@ -1,19 +1,28 @@
 //! Code pertaining to the `search` subcommand, which queries the server about
 //! the specified package.
 
+use crate::categories;
 use crate::graphql::execute_query;
+use crate::i18n;
 
 use graphql_client::*;
 
 use prettytable::format;
 use prettytable::Table;
+use std::iter;
 use structopt::StructOpt;
 
 /// Options for the `search` subcommand
 #[derive(StructOpt, Debug)]
 pub struct SearchOpt {
-    #[structopt(parse(from_str))]
-    query: String,
+    #[structopt(parse(from_str), required_unless_one = &["category", "keyword"])]
+    query: Option<String>,
+    /// Only show packages in this category, can be given several times
+    #[structopt(long = "category", number_of_values = 1)]
+    category: Vec<String>,
+    /// Only show packages with this keyword, can be given several times
+    #[structopt(long = "keyword", number_of_values = 1)]
+    keyword: Vec<String>,
 }
 
 type DateTime = String;
@@ -26,16 +35,155 @@ type DateTime = String;
 )]
 struct SearchQuery;
 
-/// Run the search command
-pub fn search(options: SearchOpt) -> Result<(), failure::Error> {
-    let query = options.query;
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/queries/get_package_details.graphql",
+    response_derives = "Debug"
+)]
+struct GetPackageDetailsQuery;
+
+/// A package version found by a search
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub name: String,
+    pub description: String,
+    pub version: String,
+    pub created_at: String,
+    pub keywords: Vec<String>,
+    pub categories: Vec<String>,
+}
+
+/// Search the registry for package versions
+pub fn search_packages(query: &str) -> Result<Vec<SearchResult>, failure::Error> {
     let q = SearchQuery::build_query(search_query::Variables {
         query: query.to_string(),
     });
     let response: search_query::ResponseData = execute_query(&q)?;
+    Ok(response
+        .search
+        .edges
+        .into_iter()
+        .filter_map(|edge| match edge?.node {
+            Some(search_query::SearchQuerySearchEdgesNode::PackageVersion(version)) => {
+                let package = published_package(&version.manifest);
+                Some(SearchResult {
+                    name: version.package.display_name,
+                    description: localized_description(package.as_ref(), version.description),
+                    version: version.version,
+                    created_at: version.created_at,
+                    keywords: string_list(package.as_ref(), "keywords"),
+                    categories: string_list(package.as_ref(), "categories"),
+                })
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+/// What the registry knows about a published version of a package
+#[derive(Debug)]
+pub struct PackageDetails {
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    pub manifest: String,
+    pub license: Option<String>,
+    pub readme: Option<String>,
+    pub repository: Option<String>,
+    pub homepage: Option<String>,
+}
 
-    if response.search.edges.len() == 0 {
-        println!("No packages found for \"{}\"", query);
+/// Look up a version of a package in the registry, or its last version when no version is given
+pub fn package_details(
+    name: &str,
+    version: Option<&str>,
+) -> Result<Option<PackageDetails>, failure::Error> {
+    let q = GetPackageDetailsQuery::build_query(get_package_details_query::Variables {
+        name: name.to_string(),
+        version: version.map(str::to_string),
+    });
+    let response: get_package_details_query::ResponseData = execute_query(&q)?;
+    Ok(response.package_version.map(|version| PackageDetails {
+        name: name.to_string(),
+        version: version.version,
+        description: localized_description(
+            published_package(&version.manifest).as_ref(),
+            version.description,
+        ),
+        manifest: version.manifest,
+        license: version.license,
+        readme: version.readme,
+        repository: version.repository,
+        homepage: version.homepage,
+    }))
+}
+
+/// The `[package]` section of a published manifest
+fn published_package(manifest: &str) -> Option<toml::Value> {
+    toml::from_str::<toml::Value>(manifest)
+        .ok()?
+        .get("package")
+        .cloned()
+}
+
+/// The description of a published package in the language of the user, or else `description`
+fn localized_description(package: Option<&toml::Value>, description: String) -> String {
+    package
+        .and_then(|package| {
+            package
+                .get("localized")?
+                .get(i18n::language())?
+                .get("description")?
+                .as_str()
+                .map(str::to_string)
+        })
+        .unwrap_or(description)
+}
+
+/// A list of strings of a published package, like its keywords
+fn string_list(package: Option<&toml::Value>, key: &str) -> Vec<String> {
+    package
+        .and_then(|package| package.get(key)?.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|value| value.as_str().map(str::to_string))
+        .collect()
+}
+
+/// Run the search command
+pub fn search(options: SearchOpt) -> Result<(), failure::Error> {
+    // the registry only searches text, the keywords and categories are filtered here
+    let query = options.query.as_deref().unwrap_or_default();
+    let results: Vec<SearchResult> = search_packages(query)?
+        .into_iter()
+        .filter(|result| {
+            categories::matches(
+                &result.keywords,
+                &result.categories,
+                &options.keyword,
+                &options.category,
+            )
+        })
+        .collect();
+
+    if results.is_empty() {
+        let filters = options
+            .category
+            .iter()
+            .map(|category| format!("category \"{}\"", category))
+            .chain(
+                options
+                    .keyword
+                    .iter()
+                    .map(|keyword| format!("keyword \"{}\"", keyword)),
+            );
+        let criteria: Vec<String> = iter::once(query)
+            .filter(|query| !query.is_empty())
+            .map(|query| format!("\"{}\"", query))
+            .chain(filters)
+            .collect();
+        println!("No packages found for {}", criteria.join(", "));
         return Ok(());
     }
     let mut table = Table::new();
@@ -43,19 +191,13 @@ pub fn search(options: SearchOpt) -> Result<(), failure::Error> {
 
     // Add a row per time
     table.add_row(row!["NAME", "DESCRIPTION", "DATE", "VERSION"]);
-    for edge in response.search.edges.into_iter() {
-        let node = edge.unwrap().node;
-        match node {
-            Some(search_query::SearchQuerySearchEdgesNode::PackageVersion(version)) => {
-                table.add_row(row![
-                    version.package.display_name,
-                    version.description,
-                    version.created_at[..10],
-                    version.version
-                ]);
-            }
-            _ => {}
-        }
+    for result in results {
+        table.add_row(row![
+            result.name,
+            result.description,
+            result.created_at[..10],
+            result.version
+        ]);
     }
     table.printstd();
 
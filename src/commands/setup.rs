@@ -0,0 +1,183 @@
+//! Code pertaining to the `setup` subcommand: a one-time walk through the registry, the login,
+//! telemetry, the runtime and the PATH, ending with the checks of `wapm doctor`
+
+use crate::commands::env::{shell_setup, Shell};
+use crate::commands::{doctor, login, DoctorOpt};
+use crate::config::{self, Config};
+use crate::constants::WAPM_RUNTIME_ENV_KEY;
+use crate::data::toolchain;
+use crate::dataflow::bin_script::bin_directory;
+use crate::identity::{self, Identity};
+use crate::util::get_runtime_with_args;
+use dialoguer::{Confirmation, Input};
+use std::env;
+use std::fs;
+use std::io::Write;
+use structopt::StructOpt;
+
+/// Written above the lines `wapm setup` adds to a shell profile
+const PROFILE_MARKER: &str = "# added by `wapm setup`";
+
+#[derive(StructOpt, Debug)]
+pub struct SetupOpt {
+    /// Don't ask anything: use the options given and keep the current settings otherwise
+    #[structopt(long = "yes", short = "y")]
+    yes: bool,
+    /// The URL of the registry to use
+    #[structopt(long = "registry")]
+    registry: Option<String>,
+    /// Don't log in to the registry
+    #[structopt(long = "skip-login")]
+    skip_login: bool,
+    /// Whether to send crash reports, `true` or `false`
+    #[structopt(long = "telemetry")]
+    telemetry: Option<bool>,
+    /// Add the global bin directory to PATH in the profile of the shell without asking
+    #[structopt(long = "edit-profile")]
+    edit_profile: bool,
+}
+
+fn confirm(interactive: bool, text: &str, default: bool) -> Result<bool, failure::Error> {
+    if !interactive {
+        return Ok(default);
+    }
+    Ok(Confirmation::new()
+        .with_text(text)
+        .default(default)
+        .interact()?)
+}
+
+pub fn setup(options: SetupOpt) -> Result<(), failure::Error> {
+    let interactive = !options.yes && atty::is(atty::Stream::Stdin);
+    let mut config = Config::from_file()?;
+
+    let registry = match options.registry.clone() {
+        Some(registry) => registry,
+        None if interactive => Input::<String>::new()
+            .with_prompt("Registry")
+            .default(config.registry.url.clone())
+            .interact()?,
+        None => config.registry.url.clone(),
+    };
+    config::set(&mut config, "registry.url".to_string(), registry)?;
+    println!("registry: {}", config.registry.url);
+    set_telemetry(&mut config, &options, interactive)?;
+    config.save()?;
+
+    if !options.skip_login {
+        match identity::current(false) {
+            Ok(Identity::Verified { username, .. }) | Ok(Identity::Stale { username, .. }) => {
+                println!("login: logged in as {}", username)
+            }
+            _ if interactive && confirm(interactive, "Log in to the registry now?", true)? => {
+                login()?
+            }
+            _ => println!("login: not logged in, run `wapm login` to publish packages"),
+        }
+    }
+
+    let (runtime, _) = get_runtime_with_args();
+    match toolchain::installed_version(&runtime) {
+        Some(version) => println!("runtime: {} {}", runtime, version),
+        None => println!(
+            "runtime: {} was not found, install it from https://wasmer.io or set {} to the runtime to use",
+            runtime, WAPM_RUNTIME_ENV_KEY
+        ),
+    }
+
+    add_bin_directory_to_path(&options, interactive)?;
+
+    println!();
+    doctor(DoctorOpt {})
+}
+
+#[cfg(feature = "telemetry")]
+fn set_telemetry(
+    config: &mut Config,
+    options: &SetupOpt,
+    interactive: bool,
+) -> Result<(), failure::Error> {
+    let current = config.telemetry.enabled.parse::<bool>().unwrap_or(false);
+    let enabled = match options.telemetry {
+        Some(enabled) => enabled,
+        None => confirm(
+            interactive,
+            "Send crash reports to help improve wapm?",
+            current,
+        )?,
+    };
+    config::set(config, "telemetry.enabled".to_string(), enabled.to_string())?;
+    println!("telemetry: {}", if enabled { "on" } else { "off" });
+    Ok(())
+}
+
+#[cfg(not(feature = "telemetry"))]
+fn set_telemetry(
+    _config: &mut Config,
+    options: &SetupOpt,
+    _interactive: bool,
+) -> Result<(), failure::Error> {
+    if options.telemetry == Some(true) {
+        warn!("This wapm was built without telemetry, crash reports can't be sent");
+    }
+    println!("telemetry: off");
+    Ok(())
+}
+
+/// Offer to add the lines of `wapm env --shell` to the profile of the shell of the user
+fn add_bin_directory_to_path(options: &SetupOpt, interactive: bool) -> Result<(), failure::Error> {
+    let global_bin_dir = bin_directory(&Config::get_globals_directory()?);
+    let in_path = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).any(|dir| dir == global_bin_dir))
+        .unwrap_or(false);
+    if in_path {
+        println!("PATH: ok");
+        return Ok(());
+    }
+    let manual_fix = "add it with `eval \"$(wapm env --shell bash)\"` in the profile of your shell";
+    let (shell, profile) =
+        match Shell::of_user().and_then(|shell| Some((shell, shell.profile_file()?))) {
+            Some(found) => found,
+            None => {
+                println!("PATH: the global bin dir is missing, {}", manual_fix);
+                return Ok(());
+            }
+        };
+    let existing = fs::read_to_string(&profile).unwrap_or_default();
+    if existing.contains(PROFILE_MARKER) {
+        println!(
+            "PATH: {} sets it up, open a new terminal to use it",
+            profile.display()
+        );
+        return Ok(());
+    }
+    let question = format!("Add the global bin dir to PATH in {}?", profile.display());
+    if !options.edit_profile && !confirm(interactive, &question, false)? {
+        println!("PATH: the global bin dir is missing, {}", manual_fix);
+        return Ok(());
+    }
+    if let Some(parent) = profile.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&profile)?;
+    let separator = if existing.is_empty() || existing.ends_with('\n') {
+        ""
+    } else {
+        "\n"
+    };
+    write!(
+        file,
+        "{}\n{}\n{}",
+        separator,
+        PROFILE_MARKER,
+        shell_setup(shell)?
+    )?;
+    println!(
+        "PATH: added to {}, open a new terminal to use it",
+        profile.display()
+    );
+    Ok(())
+}
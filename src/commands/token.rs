@@ -0,0 +1,85 @@
+//! Code pertaining to the `token` subcommand: it creates, lists and revokes registry tokens that
+//! can only publish some packages
+
+use crate::api_tokens::{self, ApiToken};
+use crate::audit_log::{self, Action};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub enum TokenOpt {
+    #[structopt(name = "create")]
+    /// Create a token, limited to publishing some packages with `--scope`
+    Create(CreateTokenOpt),
+
+    #[structopt(name = "list")]
+    /// List your tokens and their scopes
+    List,
+
+    #[structopt(name = "revoke")]
+    /// Revoke a token so it can't be used anymore
+    Revoke(RevokeTokenOpt),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct CreateTokenOpt {
+    /// A name to recognize the token by, like the CI job using it
+    #[structopt(long = "name")]
+    name: Option<String>,
+    /// A package the token can publish: `namespace/package`, `namespace/*` or `*`. Can be given
+    /// several times, tokens without scopes can publish all your packages.
+    #[structopt(long = "scope", number_of_values = 1)]
+    scopes: Vec<String>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct RevokeTokenOpt {
+    /// The id of the token, as shown by `wapm token list`
+    id: String,
+}
+
+fn describe_scopes(token: &ApiToken) -> String {
+    match &token.scopes {
+        Some(scopes) if !scopes.is_empty() => scopes.join(", "),
+        _ => "all packages".to_string(),
+    }
+}
+
+pub fn token(options: TokenOpt) -> Result<(), failure::Error> {
+    match options {
+        TokenOpt::Create(CreateTokenOpt { name, scopes }) => {
+            let created = api_tokens::create_token(name.clone(), scopes);
+            let subject = match &created {
+                Ok((token, _)) => Some(token.id.as_str()),
+                Err(_) => name.as_deref(),
+            };
+            audit_log::record(Action::TokenCreate, None, None, subject, &created);
+            let (token, value) = created?;
+            println!("Created token {} for {}", token.id, describe_scopes(&token));
+            println!("{}", value);
+            println!("Save it now, it won't be shown again");
+        }
+        TokenOpt::List => {
+            let tokens = api_tokens::list_tokens()?;
+            if tokens.is_empty() {
+                println!("No tokens");
+            }
+            for token in tokens.iter() {
+                println!(
+                    "{}\t{}\t{}\tcreated {}, last used {}",
+                    token.id,
+                    token.identifier.as_deref().unwrap_or("-"),
+                    describe_scopes(token),
+                    token.created_at,
+                    token.last_used_at.as_deref().unwrap_or("never")
+                );
+            }
+        }
+        TokenOpt::Revoke(RevokeTokenOpt { id }) => {
+            let revoked = api_tokens::revoke_token(&id);
+            audit_log::record(Action::TokenRevoke, None, None, Some(&id), &revoked);
+            revoked?;
+            println!("Revoked token {}", id);
+        }
+    }
+    Ok(())
+}
@@ -1,5 +1,7 @@
 use crate::config::Config;
 use crate::dataflow;
+use crate::global_versions;
+use semver::Version;
 use std::env;
 use structopt::StructOpt;
 
@@ -24,6 +26,17 @@ pub fn uninstall(options: UninstallOpt) -> Result<(), failure::Error> {
     };
     let uninstalled_package_names = vec![options.package.as_str()];
 
+    // a version installed side by side with the default one can be uninstalled by its version
+    if options.global {
+        let mut parts = options.package.splitn(2, '@');
+        if let (Some(name), Some(Ok(version))) = (parts.next(), parts.next().map(Version::parse)) {
+            if global_versions::uninstall_side_by_side(&dir, name, &version)? {
+                info!("Package \"{}\" is uninstalled.", options.package);
+                return Ok(());
+            }
+        }
+    }
+
     // do not allow the "@" symbol to prevent mis-use of this command
     if options.package.contains('@') {
         return Err(Error::NoAtSignAllowed.into());
@@ -0,0 +1,175 @@
+//! Code pertaining to the `upgrade` subcommand: it bumps dependencies to their latest versions
+//! in the manifest and the lockfile
+
+use crate::commands::open::registry_page;
+use crate::config::Config;
+use crate::data::manifest::Manifest;
+use crate::dataflow;
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::min_age;
+use crate::outdated::{format_rows, outdated_dependencies, OutdatedDependency};
+use crate::registry;
+use crate::update_summary::{self, VersionBump};
+use crate::util::{get_package_namespace_and_name, get_packages_dir};
+use dialoguer::Checkboxes;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct UpgradeOpt {
+    /// The dependencies to upgrade, all outdated dependencies if none are given
+    packages: Vec<String>,
+    /// Choose the dependencies to upgrade from a list of the outdated dependencies
+    #[structopt(short = "i", long = "interactive", conflicts_with = "packages")]
+    interactive: bool,
+    /// Write a markdown summary of the upgrades to this file, for the description of a pull
+    /// request. Nothing is written if no dependency was upgraded
+    #[structopt(long = "write-summary", parse(from_os_str))]
+    write_summary: Option<PathBuf>,
+}
+
+#[derive(Debug, Fail)]
+enum UpgradeError {
+    #[fail(
+        display = "Could not find a manifest in the current directory, try running `wapm init`"
+    )]
+    NoManifest,
+    #[fail(display = "Package {} is not a dependency of this package", _0)]
+    NotADependency(String),
+    #[fail(display = "Failed to update the lockfile. {}", _0)]
+    CannotRegenLockfile(dataflow::Error),
+}
+
+pub fn upgrade(options: UpgradeOpt) -> Result<(), failure::Error> {
+    let current_dir = env::current_dir()?;
+    let mut manifest =
+        Manifest::find_in_directory(&current_dir).map_err(|_| UpgradeError::NoManifest)?;
+    for package in options.packages.iter() {
+        if !manifest
+            .dependencies
+            .iter()
+            .flatten()
+            .any(|(name, _)| name == package)
+        {
+            return Err(UpgradeError::NotADependency(package.clone()).into());
+        }
+    }
+    let lockfile = match LockfileResult::find_in_directory(&current_dir) {
+        LockfileResult::Lockfile(lockfile) => Some(lockfile),
+        _ => None,
+    };
+    let names: Vec<String> = manifest
+        .dependencies
+        .iter()
+        .flatten()
+        .map(|(name, _)| name.clone())
+        .collect();
+    let registry_versions = registry::backend()?.package_versions(&names)?;
+    let outdated: Vec<OutdatedDependency> = outdated_dependencies(
+        &manifest,
+        lockfile.as_ref(),
+        &registry_versions,
+        min_age::min_age(),
+    )
+    .into_iter()
+    .filter(|dependency| options.packages.is_empty() || options.packages.contains(&dependency.name))
+    .filter(OutdatedDependency::is_upgradable)
+    .filter(|dependency| {
+        match manifest
+            .pins
+            .as_ref()
+            .and_then(|pins| pins.get(&dependency.name))
+        {
+            Some(pin) => {
+                warn!(
+                    "Not upgrading {}, remove the pin to upgrade it",
+                    pin.describe(&dependency.name)
+                );
+                false
+            }
+            None => true,
+        }
+    })
+    .collect();
+    if outdated.is_empty() {
+        println!("All dependencies are up to date");
+        return Ok(());
+    }
+
+    let selected = if options.interactive {
+        let items = format_rows(&outdated);
+        let items_checked: Vec<(&str, bool)> = items[1..]
+            .iter()
+            .map(|item| (item.as_str(), false))
+            .collect();
+        let chosen = Checkboxes::new()
+            .with_prompt(&format!(
+                "Choose the dependencies to upgrade to their latest version\n  {}",
+                items[0]
+            ))
+            .items_checked(&items_checked)
+            .interact()?;
+        chosen.into_iter().map(|index| &outdated[index]).collect()
+    } else {
+        outdated.iter().collect::<Vec<_>>()
+    };
+    if selected.is_empty() {
+        println!("No dependencies upgraded");
+        return Ok(());
+    }
+
+    for dependency in selected.iter() {
+        manifest.update_dependency_version(&dependency.name, dependency.latest.to_string())?;
+    }
+    dataflow::update(vec![], vec![], &current_dir).map_err(UpgradeError::CannotRegenLockfile)?;
+    for dependency in selected.iter() {
+        println!(
+            "Upgraded {} from {} to {}",
+            dependency.name, dependency.requirement, dependency.latest
+        );
+    }
+    if let Some(summary_path) = options.write_summary {
+        let bumps: Vec<VersionBump> = selected
+            .iter()
+            .map(|dependency| version_bump(&current_dir, dependency))
+            .collect();
+        fs::write(&summary_path, update_summary::render(&bumps))?;
+        println!(
+            "Wrote the summary of the upgrades to {}",
+            summary_path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Describe an upgrade for the summary, with the changelog of the newly installed version
+fn version_bump(project_dir: &Path, dependency: &OutdatedDependency) -> VersionBump {
+    let page = Config::from_file()
+        .ok()
+        .and_then(|config| registry_page(&config.registry.url, &dependency.name).ok());
+    let changelog = get_package_namespace_and_name(&dependency.name)
+        .ok()
+        .and_then(|(namespace, name)| {
+            update_summary::read_changelog(
+                &get_packages_dir(project_dir)
+                    .join(namespace)
+                    .join(format!("{}@{}", name, dependency.latest)),
+            )
+        })
+        .and_then(|changelog| {
+            update_summary::changelog_excerpt(
+                &changelog,
+                dependency.current.as_ref(),
+                &dependency.latest,
+            )
+        });
+    VersionBump {
+        name: dependency.name.clone(),
+        from: dependency.current.clone(),
+        to: dependency.latest.clone(),
+        page,
+        changelog,
+    }
+}
@@ -1,53 +1,47 @@
+use crate::archive::{self, ExtractionLimits};
 use crate::validate::*;
-use flate2::read::GzDecoder;
-use std::{fs, io::Read, path::PathBuf};
+use std::{fs, path::PathBuf};
 use structopt::StructOpt;
-use tar::Archive;
 
 #[derive(StructOpt, Debug)]
 pub struct ValidateOpt {
     /// Directory or tar file to validate
     package: String,
+    /// Fail on manifest keys that wapm doesn't know, like typos, and on deprecated keys
+    #[structopt(long = "strict")]
+    strict: bool,
 }
 
 pub fn validate(validate_opts: ValidateOpt) -> Result<(), failure::Error> {
     let pkg_path = PathBuf::from(&validate_opts.package);
-    validate_manifest_and_modules(pkg_path)
+    validate_manifest_and_modules(pkg_path, validate_opts.strict)
 }
 
-pub fn validate_manifest_and_modules(pkg_path: PathBuf) -> Result<(), failure::Error> {
+pub fn validate_manifest_and_modules(
+    pkg_path: PathBuf,
+    strict: bool,
+) -> Result<(), failure::Error> {
     if pkg_path.is_dir() {
+        if strict {
+            validate_manifest_keys(&pkg_path, true)?;
+        }
         validate_directory(pkg_path)
     } else {
         //unzip then validate as dir
-        let mut compressed_archive_data = Vec::new();
-        let mut compressed_archive =
+        let compressed_archive =
             fs::File::open(&pkg_path).map_err(|_| ValidationError::MissingFile {
                 file: pkg_path.to_string_lossy().to_string(),
             })?;
-        compressed_archive
-            .read_to_end(&mut compressed_archive_data)
-            .map_err(|err| ValidationError::MiscCannotRead {
-                file: pkg_path.to_string_lossy().to_string(),
-                error: format!("{}", err),
-            })?;
-
-        let mut gz = GzDecoder::new(&compressed_archive_data[..]);
-        let mut archive_data = Vec::new();
-        gz.read_to_end(&mut archive_data)
-            .map_err(|e| format_err!("Failed to read archive data: {}", e.to_string()))?;
 
         let temp_out_dir = tempfile::TempDir::new()
             .map_err(|e| format_err!("Could not create temporary directory: {}", e.to_string()))?;
         let out_dir = temp_out_dir.path();
-        let mut archive = Archive::new(archive_data.as_slice());
-        // TODO: consider doing this entirely in memory with multiple passes
-        archive
-            .unpack(&out_dir)
-            .map_err(|err| ValidationError::CannotUnpackArchive {
+        archive::unpack(compressed_archive, out_dir, &ExtractionLimits::default()).map_err(
+            |err| ValidationError::CannotUnpackArchive {
                 file: pkg_path.to_string_lossy().to_string(),
                 error: format!("{}", err),
-            })?;
+            },
+        )?;
 
         let archive_path = {
             let mut ar_path = out_dir.to_path_buf();
@@ -60,6 +54,9 @@ pub fn validate_manifest_and_modules(pkg_path: PathBuf) -> Result<(), failure::E
             ar_path
         };
 
+        if strict {
+            validate_manifest_keys(&archive_path, true)?;
+        }
         validate_directory(archive_path)
     }
 }
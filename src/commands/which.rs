@@ -0,0 +1,60 @@
+//! Code pertaining to the `which` subcommand: it shows where a command comes from and, with
+//! `--verbose`, how it is run
+
+use crate::dataflow::bin_script::BIN_DIR_NAME;
+use crate::dataflow::find_command_result::get_command_from_anywhere;
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::sandbox::SandboxProfile;
+use crate::util::get_packages_dir;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct WhichOpt {
+    /// The command to look up
+    command: String,
+    /// Also show the package, lockfile, script and sandbox profile of the command and the
+    /// runtime flags the profile runs it with
+    #[structopt(short = "v", long = "verbose")]
+    verbose: bool,
+}
+
+pub fn which(options: WhichOpt) -> Result<(), failure::Error> {
+    let command = get_command_from_anywhere(&options.command)?;
+    let module_path = command.directory.join(&command.source);
+    println!("{}", module_path.display());
+    if !options.verbose {
+        return Ok(());
+    }
+
+    let lockfile_command = match LockfileResult::find_in_directory(&command.directory) {
+        LockfileResult::Lockfile(lockfile) => lockfile.get_command(&options.command).ok().cloned(),
+        _ => None,
+    };
+    if let Some(lockfile_command) = &lockfile_command {
+        println!(
+            "  package: {}@{}",
+            lockfile_command.package_name, lockfile_command.package_version
+        );
+    }
+    println!("  module: {}", command.module_name);
+    println!("  directory: {}", command.directory.display());
+    let script = get_packages_dir(&command.directory)
+        .join(BIN_DIR_NAME)
+        .join(&options.command);
+    if script.exists() {
+        println!("  script: {}", script.display());
+    }
+
+    let profile = match lockfile_command.and_then(|command| command.sandbox) {
+        Some(profile) => profile.parse().map_err(failure::err_msg)?,
+        None => SandboxProfile::Unrestricted,
+    };
+    println!("  sandbox: {} ({})", profile, profile.description());
+    if !profile.uses_manifest_flags() {
+        println!(
+            "  runtime flags: {}",
+            profile.current_runtime_flags().join(" ")
+        );
+    }
+    Ok(())
+}
@@ -1,7 +1,30 @@
-use crate::util;
+use crate::identity::{self, Identity};
+use structopt::StructOpt;
 
-pub fn whoami() -> Result<(), failure::Error> {
-    let username = util::get_username()?.unwrap_or("(not logged in)".to_string());
-    println!("{}", username);
+#[derive(StructOpt, Debug)]
+pub struct WhoAmIOpt {
+    /// Ask the registry again instead of trusting an identity it verified in the last hour
+    #[structopt(long = "refresh")]
+    refresh: bool,
+}
+
+pub fn whoami(options: WhoAmIOpt) -> Result<(), failure::Error> {
+    match identity::current(options.refresh)? {
+        Identity::Verified { username, .. } => println!("{}", username),
+        Identity::Stale {
+            username,
+            verified_at,
+            error,
+        } => {
+            warn!(
+                "Could not reach the registry, {} was last verified on {}: {}",
+                username,
+                identity::format_verified_at(verified_at),
+                error
+            );
+            println!("{} (offline)", username);
+        }
+        Identity::LoggedOut => println!("(not logged in)"),
+    }
     Ok(())
 }
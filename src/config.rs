@@ -1,3 +1,8 @@
+use crate::credentials;
+use crate::data::manifest::PACKAGES_DIR_NAME;
+use crate::min_age;
+use crate::package_size;
+use std::collections::BTreeMap;
 use std::env;
 use std::fs::File;
 use std::io::prelude::*;
@@ -8,6 +13,8 @@ pub static GLOBAL_CONFIG_FOLDER_NAME: &str = ".wasmer";
 pub static GLOBAL_WAX_INDEX_FILE_NAME: &str = ".wax_index.json";
 pub static GLOBAL_CONFIG_DATABASE_FILE_NAME: &str = "wapm.sqlite";
 pub static GLOBAL_CONFIG_FOLDER_ENV_VAR: &str = "WASMER_DIR";
+/// The profile to use, also set by the global `--profile` option
+pub static PROFILE_ENV_VAR: &str = "WAPM_PROFILE";
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
 pub struct Config {
@@ -16,6 +23,10 @@ pub struct Config {
     #[serde(default = "wax_default_cooldown")]
     pub wax_cooldown: i32,
 
+    /// The language of messages, e.g. `es`. Defaults to the language of `LANG`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+
     /// The registry that wapm will connect to.
     pub registry: Registry,
 
@@ -32,6 +43,62 @@ pub struct Config {
     /// The proxy to use when connecting to the Internet.
     #[serde(default)]
     pub proxy: Proxy,
+
+    /// Where packages get installed.
+    #[serde(default)]
+    pub install: Install,
+
+    /// Fetching packages from IPFS gateways, an experimental alternative to the registry.
+    #[serde(default)]
+    pub ipfs: Ipfs,
+
+    /// A webhook notified after packages are published.
+    #[serde(default)]
+    pub webhook: Webhook,
+
+    /// A local snapshot of the package index to resolve versions against.
+    #[serde(default)]
+    pub index: Index,
+
+    /// Desktop notifications when long installs and publishes finish.
+    #[serde(default)]
+    pub notify: Notify,
+
+    /// Encryption of the registry tokens saved in this file.
+    #[serde(default)]
+    pub credentials: Credentials,
+
+    /// Short names for long wapm commands, like `t = "run test -- --verbose"`. Aliases in the
+    /// manifest of the project take precedence.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub alias: BTreeMap<String, String>,
+
+    /// Named sets of settings, like a staging registry, used instead of the ones above with
+    /// `wapm --profile <name>` or `WAPM_PROFILE`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+
+    /// The profile in use, whose registry replaced `registry` when the config was loaded
+    #[serde(skip)]
+    pub active_profile: Option<String>,
+
+    /// The registry outside of the profile in use, which is saved back in its place
+    #[serde(skip)]
+    base_registry: Option<Registry>,
+}
+
+/// A `[profiles.<name>]` section of the config
+#[derive(Deserialize, Serialize, Debug, PartialEq, Default)]
+pub struct Profile {
+    /// The registry to use instead of `[registry]`, with its own token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub registry: Option<Registry>,
+    /// Where the package index snapshot and the module store are kept, instead of WASMER_DIR
+    #[serde(rename = "cache-dir", default, skip_serializing_if = "Option::is_none")]
+    pub cache_dir: Option<PathBuf>,
+    /// The install policy to use instead of `install.policy`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<PathBuf>,
 }
 
 /// The default cooldown for wax.
@@ -43,6 +110,92 @@ pub const fn wax_default_cooldown() -> i32 {
 pub struct Registry {
     pub url: String,
     pub token: Option<String>,
+    /// How to talk to the registry, GraphQL when not set. Registries with a `file://` url are
+    /// always static and registries with an `s3://` url are always S3 buckets.
+    pub backend: Option<RegistryBackendKind>,
+    /// The endpoint of an S3-compatible service hosting the bucket of an S3 registry
+    #[serde(rename = "s3-endpoint")]
+    pub s3_endpoint: Option<String>,
+    /// Send the hashes of GraphQL queries instead of the queries, for registries that support
+    /// persisted queries
+    #[serde(rename = "persisted-queries", default)]
+    pub persisted_queries: bool,
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistryBackendKind {
+    /// The GraphQL API of a wapm registry server
+    Graphql,
+    /// An `index.json` and package archives served from any web server or directory
+    Static,
+    /// A static registry in an S3-compatible bucket
+    S3,
+}
+
+impl std::str::FromStr for RegistryBackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "graphql" => Ok(RegistryBackendKind::Graphql),
+            "static" => Ok(RegistryBackendKind::Static),
+            "s3" => Ok(RegistryBackendKind::S3),
+            _ => Err(format!("unknown registry backend {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for RegistryBackendKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RegistryBackendKind::Graphql => write!(f, "graphql"),
+            RegistryBackendKind::Static => write!(f, "static"),
+            RegistryBackendKind::S3 => write!(f, "s3"),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Default)]
+pub struct Webhook {
+    /// The url to POST to after publishing. `$NAME` and `${NAME}` are replaced with the
+    /// environment variable, so secrets in the url don't need to be in the config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The kind of payload, a generic JSON object when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<WebhookFormat>,
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    Slack,
+    Discord,
+    Json,
+}
+
+impl std::str::FromStr for WebhookFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "slack" => Ok(WebhookFormat::Slack),
+            "discord" => Ok(WebhookFormat::Discord),
+            "json" => Ok(WebhookFormat::Json),
+            _ => Err(format!("unknown webhook format {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for WebhookFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            WebhookFormat::Slack => write!(f, "slack"),
+            WebhookFormat::Discord => write!(f, "discord"),
+            WebhookFormat::Json => write!(f, "json"),
+        }
+    }
 }
 
 #[cfg(feature = "telemetry")]
@@ -80,19 +233,214 @@ pub struct Proxy {
     pub url: Option<String>,
 }
 
+#[derive(Deserialize, Serialize, Debug, PartialEq, Default)]
+pub struct Index {
+    /// Resolve versions against a downloaded snapshot of the registry's index
+    #[serde(default)]
+    pub enabled: bool,
+    /// Where to download the snapshot from, `index.json.gz` in the registry by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// How old the snapshot may get before it is downloaded again, e.g. `12h`
+    #[serde(rename = "max-age", default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<String>,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Default)]
+pub struct Notify {
+    /// How long an install or publish must take for its completion to be notified, e.g. `30s`.
+    /// Nothing is notified when it is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Default)]
+pub struct Credentials {
+    /// How the registry tokens are encrypted, saved in plain text when not set. The key comes
+    /// from `WAPM_CREDENTIALS_KEY` when it is set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<CredentialEncryption>,
+    /// A known text encrypted like the tokens, to check the passphrase before encrypting a new
+    /// token with it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verifier: Option<String>,
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CredentialEncryption {
+    /// A key derived from a passphrase asked for when the token is needed
+    Passphrase,
+    /// A random key kept in the keychain of the operating system
+    Keychain,
+}
+
+impl std::str::FromStr for CredentialEncryption {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "passphrase" => Ok(CredentialEncryption::Passphrase),
+            "keychain" => Ok(CredentialEncryption::Keychain),
+            _ => Err(format!("unknown credentials encryption {}", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for CredentialEncryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CredentialEncryption::Passphrase => write!(f, "passphrase"),
+            CredentialEncryption::Keychain => write!(f, "keychain"),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Default)]
+pub struct Install {
+    /// The directory packages are installed into, relative to the project directory.
+    /// Overridden by `packages-dir` in a project's manifest.
+    #[serde(rename = "packages-dir")]
+    pub packages_dir: Option<PathBuf>,
+    /// A policy file restricting the packages that installs may fetch.
+    /// Overridden by the `WAPM_POLICY` environment variable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<PathBuf>,
+    /// How old versions must be to be picked for version ranges, e.g. `3d`.
+    /// Overridden by `wapm install --min-age`.
+    #[serde(rename = "min-age", default, skip_serializing_if = "Option::is_none")]
+    pub min_age: Option<String>,
+    /// Whether identical modules of installed packages are stored once, on by default.
+    #[serde(
+        rename = "dedup-modules",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub dedup_modules: Option<bool>,
+    /// The size above which packages are installed with a warning, e.g. `100MB`.
+    /// `wapm install --max-package-size` fails on larger packages instead.
+    #[serde(
+        rename = "max-package-size",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_package_size: Option<String>,
+    /// A signed allowlist of the only packages installs may fetch.
+    /// Overridden by the `WAPM_ALLOWLIST` environment variable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowlist: Option<PathBuf>,
+    /// The minisign public key the allowlist must be signed with
+    #[serde(
+        rename = "allowlist-key",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub allowlist_key: Option<String>,
+    /// The file of integrity exceptions, `integrity-exceptions.toml` next to the manifest by
+    /// default. Overridden by the `WAPM_INTEGRITY_EXCEPTIONS` environment variable.
+    #[serde(
+        rename = "integrity-exceptions",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub integrity_exceptions: Option<PathBuf>,
+    /// Whether lockfiles are always written in sorted order instead of keeping the order of
+    /// the existing lockfile, off by default.
+    #[serde(
+        rename = "sorted-lockfile",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sorted_lockfile: Option<bool>,
+    /// A command run on the archive of every downloaded package before it is extracted, like a
+    /// virus scanner. The install is blocked when it fails.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scanner: Option<String>,
+    /// Whether archives that decompress to far more than their size or look encrypted are
+    /// blocked, off by default.
+    #[serde(
+        rename = "scan-heuristics",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub scan_heuristics: Option<bool>,
+}
+
+impl Install {
+    pub fn dedup_modules(&self) -> bool {
+        self.dedup_modules.unwrap_or(true)
+    }
+
+    pub fn sorted_lockfile(&self) -> bool {
+        self.sorted_lockfile.unwrap_or(false)
+    }
+
+    pub fn scan_heuristics(&self) -> bool {
+        self.scan_heuristics.unwrap_or(false)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct Ipfs {
+    /// Try the gateways before downloading packages from the registry
+    #[serde(default)]
+    pub enabled: bool,
+    /// The gateways to fetch packages from, in order
+    #[serde(default = "ipfs_default_gateways")]
+    pub gateways: Vec<String>,
+    /// The HTTP API of the IPFS node that `wapm publish --ipfs-pin` pins packages with
+    #[serde(rename = "api-url", default = "ipfs_default_api_url")]
+    pub api_url: String,
+}
+
+fn ipfs_default_gateways() -> Vec<String> {
+    vec![
+        "https://ipfs.io".to_string(),
+        "https://cloudflare-ipfs.com".to_string(),
+    ]
+}
+
+fn ipfs_default_api_url() -> String {
+    "http://127.0.0.1:5001".to_string()
+}
+
+impl Default for Ipfs {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            gateways: ipfs_default_gateways(),
+            api_url: ipfs_default_api_url(),
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Config {
         Config {
             registry: Registry {
                 url: "https://registry.wapm.io".to_string(),
                 token: None,
+                backend: None,
+                s3_endpoint: None,
+                persisted_queries: false,
             },
             #[cfg(feature = "telemetry")]
             telemetry: Telemetry::default(),
             #[cfg(feature = "update-notifications")]
             update_notifications: UpdateNotifications::default(),
             proxy: Proxy::default(),
+            install: Install::default(),
+            ipfs: Ipfs::default(),
+            webhook: Webhook::default(),
+            index: Index::default(),
+            notify: Notify::default(),
+            credentials: Credentials::default(),
+            alias: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            active_profile: None,
+            base_registry: None,
             wax_cooldown: wax_default_cooldown(),
+            locale: None,
         }
     }
 }
@@ -115,7 +463,7 @@ impl Config {
         )
     }
 
-    fn get_file_location() -> Result<PathBuf, GlobalConfigError> {
+    pub fn get_file_location() -> Result<PathBuf, GlobalConfigError> {
         Ok(Self::get_folder()?.join(GLOBAL_CONFIG_FILE_NAME))
     }
 
@@ -137,9 +485,11 @@ impl Config {
                 let mut config_toml = String::new();
                 file.read_to_string(&mut config_toml)
                     .map_err(|e| GlobalConfigError::Io(e))?;
-                toml::from_str(&config_toml).map_err(|e| GlobalConfigError::Toml(e))
+                let config: Self =
+                    toml::from_str(&config_toml).map_err(|e| GlobalConfigError::Toml(e))?;
+                config.with_active_profile()
             }
-            Err(_e) => Ok(Self::default()),
+            Err(_e) => Self::default().with_active_profile(),
         }
     }
 
@@ -147,23 +497,139 @@ impl Config {
     #[cfg(feature = "integration_tests")]
     pub fn from_file() -> Result<Self, GlobalConfigError> {
         crate::integration_tests::data::RAW_CONFIG_DATA.with(|rcd| {
-            if let Some(ref config_toml) = *rcd.borrow() {
-                toml::from_str(&config_toml).map_err(|e| GlobalConfigError::Toml(e))
+            let config: Self = if let Some(ref config_toml) = *rcd.borrow() {
+                toml::from_str(&config_toml).map_err(|e| GlobalConfigError::Toml(e))?
             } else {
-                Ok(Self::default())
-            }
+                Self::default()
+            };
+            config.with_active_profile()
         })
     }
 
+    /// The name of the profile picked by `--profile` or `WAPM_PROFILE`
+    pub fn active_profile_name() -> Option<String> {
+        env::var(PROFILE_ENV_VAR)
+            .ok()
+            .filter(|profile| !profile.is_empty())
+    }
+
+    /// Use the settings of the active profile
+    fn with_active_profile(self) -> Result<Self, GlobalConfigError> {
+        match Self::active_profile_name() {
+            Some(name) => self.with_profile(name),
+            None => Ok(self),
+        }
+    }
+
+    fn with_profile(mut self, name: String) -> Result<Self, GlobalConfigError> {
+        if !self.profiles.contains_key(&name) {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            return Err(GlobalConfigError::UnknownProfile(name, known.join(", ")));
+        }
+        let profile = self.profiles.get_mut(&name).unwrap();
+        if let Some(registry) = profile.registry.take() {
+            self.base_registry = Some(std::mem::replace(&mut self.registry, registry));
+        }
+        self.active_profile = Some(name);
+        Ok(self)
+    }
+
+    fn active_profile(&self) -> Option<&Profile> {
+        self.active_profile
+            .as_ref()
+            .and_then(|name| self.profiles.get(name))
+    }
+
+    /// The install policy of the active profile, or else `install.policy`
+    pub fn policy_path(&self) -> Option<PathBuf> {
+        self.active_profile()
+            .and_then(|profile| profile.policy.clone())
+            .or_else(|| self.install.policy.clone())
+    }
+
+    /// Where downloaded data that can be fetched again is kept: the cache directory of the
+    /// active profile, or else WASMER_DIR
+    pub fn get_cache_directory() -> Result<PathBuf, GlobalConfigError> {
+        let cache_dir = Self::from_file()
+            .ok()
+            .and_then(|config| config.active_profile().and_then(|p| p.cache_dir.clone()));
+        match cache_dir {
+            Some(cache_dir) => Ok(cache_dir),
+            None => Self::get_folder(),
+        }
+    }
+
+    /// The saved registry tokens: of the registry in use, of the registry outside of the profile
+    /// in use and of the other profiles
+    pub(crate) fn tokens(&self) -> Vec<&String> {
+        let mut registries = vec![&self.registry];
+        registries.extend(self.base_registry.as_ref());
+        registries.extend(
+            self.profiles
+                .values()
+                .filter_map(|profile| profile.registry.as_ref()),
+        );
+        registries
+            .into_iter()
+            .filter_map(|registry| registry.token.as_ref())
+            .collect()
+    }
+
+    pub(crate) fn tokens_mut(&mut self) -> Vec<&mut String> {
+        let mut registries = vec![&mut self.registry];
+        registries.extend(self.base_registry.as_mut());
+        registries.extend(
+            self.profiles
+                .values_mut()
+                .filter_map(|profile| profile.registry.as_mut()),
+        );
+        registries
+            .into_iter()
+            .filter_map(|registry| registry.token.as_mut())
+            .collect()
+    }
+
+    /// The config as it is saved, with the registry of the active profile back in its profile
+    fn to_toml_string(&self) -> Result<String, failure::Error> {
+        let mut value = toml::Value::try_from(self)?;
+        if let (Some(name), Some(base_registry)) = (&self.active_profile, &self.base_registry) {
+            let registry = toml::Value::try_from(&self.registry)?;
+            if let Some(table) = value.as_table_mut() {
+                table.insert(
+                    "registry".to_string(),
+                    toml::Value::try_from(base_registry)?,
+                );
+                if let Some(profile) = table
+                    .get_mut("profiles")
+                    .and_then(|profiles| profiles.get_mut(name.as_str()))
+                    .and_then(toml::Value::as_table_mut)
+                {
+                    profile.insert("registry".to_string(), registry);
+                }
+            }
+        }
+        Ok(toml::to_string(&value)?)
+    }
+
     pub fn get_globals_directory() -> Result<PathBuf, GlobalConfigError> {
         Self::get_folder().map(|p| p.join("globals"))
     }
 
+    /// Where publishes that failed are kept until `wapm publish --resume` retries them
+    pub fn get_publish_outbox_directory() -> Result<PathBuf, GlobalConfigError> {
+        Self::get_folder().map(|p| p.join("publish_outbox"))
+    }
+
+    /// The content-addressed store that identical modules of installed packages are linked to
+    pub fn get_wasm_store_directory() -> Result<PathBuf, GlobalConfigError> {
+        Self::get_cache_directory().map(|p| p.join("store").join("wasm"))
+    }
+
     /// Save the config to a file
     #[cfg(not(feature = "integration_tests"))]
     pub fn save(self: &Self) -> Result<(), failure::Error> {
         let path = Self::get_file_location()?;
-        let config_serialized = toml::to_string(&self)?;
+        let config_serialized = self.to_toml_string()?;
         let mut file = File::create(path)?;
         file.write_all(config_serialized.as_bytes())?;
         Ok(())
@@ -172,7 +638,7 @@ impl Config {
     /// A mocked version of the standard function for integration tests
     #[cfg(feature = "integration_tests")]
     pub fn save(self: &Self) -> Result<(), failure::Error> {
-        let config_serialized = toml::to_string(&self)?;
+        let config_serialized = self.to_toml_string()?;
         crate::integration_tests::data::RAW_CONFIG_DATA.with(|rcd| {
             *rcd.borrow_mut() = Some(config_serialized);
         });
@@ -189,6 +655,16 @@ impl Config {
 }
 
 impl Registry {
+    pub fn backend_kind(&self) -> RegistryBackendKind {
+        if self.url.starts_with("file://") {
+            RegistryBackendKind::Static
+        } else if self.url.starts_with("s3://") {
+            RegistryBackendKind::S3
+        } else {
+            self.backend.unwrap_or(RegistryBackendKind::Graphql)
+        }
+    }
+
     pub fn get_graphql_url(self: &Self) -> String {
         let url = &self.url;
         if url.ends_with("/") {
@@ -209,6 +685,11 @@ pub enum GlobalConfigError {
         display = "While falling back to the default location for WASMER_DIR, could not resolve the user's home directory"
     )]
     CannotFindHomeDirectory,
+    #[fail(
+        display = "There is no profile named {} in the config, the profiles are: {}",
+        _0, _1
+    )]
+    UnknownProfile(String, String),
 }
 
 #[derive(Debug, Fail)]
@@ -229,7 +710,26 @@ pub fn set(config: &mut Config, key: String, value: String) -> Result<(), failur
             }
         }
         "registry.token" => {
-            config.registry.token = Some(value);
+            config.registry.token = Some(credentials::protect(config, &value)?);
+        }
+        "registry.backend" => {
+            config.registry.backend =
+                Some(value.parse().map_err(|_| ConfigError::CanNotParse {
+                    value: value.clone(),
+                    key: key.clone(),
+                })?);
+        }
+        "registry.s3-endpoint" => {
+            config.registry.s3_endpoint = Some(value);
+        }
+        "registry.persisted-queries" => {
+            config.registry.persisted_queries =
+                value
+                    .parse::<bool>()
+                    .map_err(|_| ConfigError::CanNotParse {
+                        value: value.clone(),
+                        key: key.clone(),
+                    })?;
         }
         #[cfg(feature = "telemetry")]
         "telemetry.enabled" => {
@@ -242,6 +742,190 @@ pub fn set(config: &mut Config, key: String, value: String) -> Result<(), failur
         "proxy.url" => {
             config.proxy.url = if value.is_empty() { None } else { Some(value) };
         }
+        "install.packages-dir" => {
+            config.install.packages_dir = if value.is_empty() {
+                None
+            } else {
+                Some(PathBuf::from(value))
+            };
+        }
+        "install.policy" => {
+            config.install.policy = if value.is_empty() {
+                None
+            } else {
+                Some(PathBuf::from(value))
+            };
+        }
+        "install.min-age" => {
+            config.install.min_age = if value.is_empty() {
+                None
+            } else {
+                min_age::parse_min_age(&value).map_err(|_| ConfigError::CanNotParse {
+                    value: value.clone(),
+                    key: key.clone(),
+                })?;
+                Some(value)
+            };
+        }
+        "install.max-package-size" => {
+            config.install.max_package_size = if value.is_empty() {
+                None
+            } else {
+                package_size::parse_size(&value).map_err(|_| ConfigError::CanNotParse {
+                    value: value.clone(),
+                    key: key.clone(),
+                })?;
+                Some(value)
+            };
+        }
+        "install.allowlist" => {
+            config.install.allowlist = if value.is_empty() {
+                None
+            } else {
+                Some(PathBuf::from(value))
+            };
+        }
+        "install.allowlist-key" => {
+            config.install.allowlist_key = if value.is_empty() { None } else { Some(value) };
+        }
+        "install.integrity-exceptions" => {
+            config.install.integrity_exceptions = if value.is_empty() {
+                None
+            } else {
+                Some(PathBuf::from(value))
+            };
+        }
+        "locale" => {
+            config.locale = if value.is_empty() { None } else { Some(value) };
+        }
+        "ipfs.enabled" => {
+            config.ipfs.enabled = value
+                .parse::<bool>()
+                .map_err(|_| ConfigError::CanNotParse {
+                    value: value.clone(),
+                    key: key.clone(),
+                })?;
+        }
+        "ipfs.gateways" => {
+            config.ipfs.gateways = value
+                .split(',')
+                .map(str::trim)
+                .filter(|gateway| !gateway.is_empty())
+                .map(str::to_owned)
+                .collect();
+        }
+        "ipfs.api-url" => {
+            config.ipfs.api_url = value;
+        }
+        "webhook.url" => {
+            config.webhook.url = if value.is_empty() { None } else { Some(value) };
+        }
+        "webhook.format" => {
+            config.webhook.format = if value.is_empty() {
+                None
+            } else {
+                Some(value.parse().map_err(|_| ConfigError::CanNotParse {
+                    value: value.clone(),
+                    key: key.clone(),
+                })?)
+            };
+        }
+        "install.dedup-modules" => {
+            config.install.dedup_modules = if value.is_empty() {
+                None
+            } else {
+                Some(
+                    value
+                        .parse::<bool>()
+                        .map_err(|_| ConfigError::CanNotParse {
+                            value: value.clone(),
+                            key: key.clone(),
+                        })?,
+                )
+            };
+        }
+        "install.sorted-lockfile" => {
+            config.install.sorted_lockfile = if value.is_empty() {
+                None
+            } else {
+                Some(
+                    value
+                        .parse::<bool>()
+                        .map_err(|_| ConfigError::CanNotParse {
+                            value: value.clone(),
+                            key: key.clone(),
+                        })?,
+                )
+            };
+        }
+        "install.scanner" => {
+            config.install.scanner = if value.is_empty() { None } else { Some(value) };
+        }
+        "install.scan-heuristics" => {
+            config.install.scan_heuristics = if value.is_empty() {
+                None
+            } else {
+                Some(
+                    value
+                        .parse::<bool>()
+                        .map_err(|_| ConfigError::CanNotParse {
+                            value: value.clone(),
+                            key: key.clone(),
+                        })?,
+                )
+            };
+        }
+        "index.enabled" => {
+            config.index.enabled = value
+                .parse::<bool>()
+                .map_err(|_| ConfigError::CanNotParse {
+                    value: value.clone(),
+                    key: key.clone(),
+                })?;
+        }
+        "index.url" => {
+            config.index.url = if value.is_empty() { None } else { Some(value) };
+        }
+        "index.max-age" => {
+            config.index.max_age = if value.is_empty() {
+                None
+            } else {
+                min_age::parse_min_age(&value).map_err(|_| ConfigError::CanNotParse {
+                    value: value.clone(),
+                    key: key.clone(),
+                })?;
+                Some(value)
+            };
+        }
+        "notify.threshold" => {
+            config.notify.threshold = if value.is_empty() {
+                None
+            } else {
+                min_age::parse_min_age(&value).map_err(|_| ConfigError::CanNotParse {
+                    value: value.clone(),
+                    key: key.clone(),
+                })?;
+                Some(value)
+            };
+        }
+        "credentials.encryption" => {
+            let encryption = match value.as_str() {
+                "" | "none" => None,
+                _ => Some(value.parse().map_err(|_| ConfigError::CanNotParse {
+                    value: value.clone(),
+                    key: key.clone(),
+                })?),
+            };
+            credentials::set_encryption(config, encryption)?;
+        }
+        _ if key.starts_with("alias.") => {
+            let name = key["alias.".len()..].to_string();
+            if value.is_empty() {
+                config.alias.remove(&name);
+            } else {
+                config.alias.insert(name, value);
+            }
+        }
         "wax.cooldown" => {
             let num = value.parse::<i32>().map_err(|_| ConfigError::CanNotParse {
                 value: value.clone(),
@@ -264,6 +948,9 @@ pub fn get(config: &mut Config, key: String) -> Result<String, failure::Error> {
             unimplemented!()
             // &(config.registry.token.as_ref().map_or("".to_string(), |n| n.to_string()).to_owned())
         }
+        "registry.backend" => config.registry.backend_kind().to_string(),
+        "registry.s3-endpoint" => config.registry.s3_endpoint.clone().unwrap_or_default(),
+        "registry.persisted-queries" => config.registry.persisted_queries.to_string(),
         #[cfg(feature = "telemetry")]
         "telemetry.enabled" => config.telemetry.enabled.clone(),
         #[cfg(feature = "update-notifications")]
@@ -275,7 +962,63 @@ pub fn get(config: &mut Config, key: String) -> Result<String, failure::Error> {
                 "No proxy configured".to_owned()
             }
         }
+        "install.packages-dir" => {
+            if let Some(packages_dir) = &config.install.packages_dir {
+                packages_dir.to_string_lossy().to_string()
+            } else {
+                PACKAGES_DIR_NAME.to_owned()
+            }
+        }
+        "install.policy" => config
+            .install
+            .policy
+            .as_ref()
+            .map(|policy| policy.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        "install.min-age" => config.install.min_age.clone().unwrap_or_default(),
+        "install.max-package-size" => config.install.max_package_size.clone().unwrap_or_default(),
+        "install.allowlist" => config
+            .install
+            .allowlist
+            .as_ref()
+            .map(|allowlist| allowlist.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        "install.allowlist-key" => config.install.allowlist_key.clone().unwrap_or_default(),
+        "install.integrity-exceptions" => config
+            .install
+            .integrity_exceptions
+            .as_ref()
+            .map(|path| path.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        "locale" => config.locale.clone().unwrap_or_default(),
+        "ipfs.enabled" => config.ipfs.enabled.to_string(),
+        "ipfs.gateways" => config.ipfs.gateways.join(","),
+        "ipfs.api-url" => config.ipfs.api_url.clone(),
+        "webhook.url" => config.webhook.url.clone().unwrap_or_default(),
+        "webhook.format" => config
+            .webhook
+            .format
+            .map(|format| format.to_string())
+            .unwrap_or_default(),
+        "install.dedup-modules" => config.install.dedup_modules().to_string(),
+        "install.sorted-lockfile" => config.install.sorted_lockfile().to_string(),
+        "install.scanner" => config.install.scanner.clone().unwrap_or_default(),
+        "install.scan-heuristics" => config.install.scan_heuristics().to_string(),
+        "index.enabled" => config.index.enabled.to_string(),
+        "index.url" => config.index.url.clone().unwrap_or_default(),
+        "index.max-age" => config.index.max_age.clone().unwrap_or_default(),
+        "notify.threshold" => config.notify.threshold.clone().unwrap_or_default(),
+        "credentials.encryption" => config
+            .credentials
+            .encryption
+            .map(|encryption| encryption.to_string())
+            .unwrap_or_default(),
         "wax.cooldown" => format!("{}", config.wax_cooldown),
+        _ if key.starts_with("alias.") => config
+            .alias
+            .get(&key["alias.".len()..])
+            .cloned()
+            .unwrap_or_default(),
         _ => {
             return Err(ConfigError::KeyNotFound { key }.into());
         }
@@ -334,4 +1077,40 @@ mod test {
         let config_result = Config::from_file();
         assert!(config_result.is_ok(), "Config not found.");
     }
+
+    #[test]
+    fn profiles_replace_the_registry() {
+        let config: Config = toml::from_str(
+            r#"
+[registry]
+url = "https://registry.wapm.io"
+token = "production-token"
+
+[profiles.staging]
+cache-dir = "/tmp/staging"
+
+[profiles.staging.registry]
+url = "https://registry.staging.wapm.io"
+"#,
+        )
+        .unwrap();
+        assert!(Config::default()
+            .with_profile("missing".to_string())
+            .is_err());
+
+        let mut config = config.with_profile("staging".to_string()).unwrap();
+        assert_eq!(config.registry.url, "https://registry.staging.wapm.io");
+        assert_eq!(config.registry.token, None);
+        assert_eq!(
+            config.active_profile().unwrap().cache_dir,
+            Some("/tmp/staging".into())
+        );
+
+        // logging in saves the token in the profile
+        config.registry.token = Some("staging-token".to_string());
+        let saved: Config = toml::from_str(&config.to_toml_string().unwrap()).unwrap();
+        assert_eq!(saved.registry.token.as_deref(), Some("production-token"));
+        let staging_registry = saved.profiles["staging"].registry.as_ref().unwrap();
+        assert_eq!(staging_registry.token.as_deref(), Some("staging-token"));
+    }
 }
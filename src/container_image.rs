@@ -0,0 +1,458 @@
+//! Container images that run a command of a package, so packages can be deployed to container
+//! platforms.
+//!
+//! The image adds one layer to its base image, or to an empty image, with the module of the
+//! command, the manifest and the `fs` directories of the package under `/app`. The entrypoint runs
+//! the module with the runtime bundled in the layer at `/usr/local/bin/wasmer`, or with the
+//! `wasmer` of the base image, mapping the `fs` directories like `wapm run` does.
+//!
+//! Images are written as a tar archive in the OCI image layout, which also has the
+//! `manifest.json` of `docker save` so both `docker load` and OCI tools like `skopeo` read it.
+
+use crate::constants::DEFAULT_RUNTIME;
+use crate::data::manifest::{Command, Manifest, Module, MANIFEST_FILE_NAME};
+use crate::oci::{
+    self, Descriptor, ImageManifest, PulledImage, CREATED_ANNOTATION, DESCRIPTION_ANNOTATION,
+    INDEX_MEDIA_TYPE, MANIFEST_MEDIA_TYPE, NAME_ANNOTATION, TITLE_ANNOTATION, VERSION_ANNOTATION,
+};
+use chrono::Utc;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Component, Path};
+use tar::{Builder, EntryType, Header};
+
+/// Where the files of the package are in the image
+pub const APP_DIR: &str = "/app";
+/// Where a bundled runtime is in the image
+pub const BUNDLED_RUNTIME_PATH: &str = "/usr/local/bin/wasmer";
+
+const LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+const CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+const DEFAULT_PATH: &str = "PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+#[derive(Debug, Fail)]
+pub enum ContainerImageError {
+    #[fail(display = "The base image has an invalid config: {}", _0)]
+    InvalidBaseConfig(String),
+    #[fail(
+        display = "The module \"{}\" of the command \"{}\" is not a module of this package",
+        _0, _1
+    )]
+    ModuleNotInPackage(String, String),
+}
+
+/// What goes in the image of a command
+pub struct ImageSpec<'a> {
+    pub manifest: &'a Manifest,
+    pub command: &'a Command,
+    /// A runtime executable to bundle, instead of using the one of the base image
+    pub runtime: Option<&'a Path>,
+    /// `KEY=VALUE` environment variables of the container, also given to the module
+    pub env_vars: &'a [String],
+    /// The architecture of the image when it has no base image, like `amd64`
+    pub architecture: &'a str,
+}
+
+/// An image ready to be written, with the blobs its manifest refers to
+pub struct Image {
+    pub manifest: ImageManifest,
+    pub config: Vec<u8>,
+    pub layers: Vec<Vec<u8>>,
+}
+
+/// The architecture of the current machine, as container images name it
+pub fn host_architecture() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" => "386",
+        "powerpc64" => "ppc64le",
+        other => other,
+    }
+}
+
+/// A path of the package as it is in the image, relative to the root
+fn in_app(path: &Path) -> String {
+    let mut in_app = APP_DIR.trim_start_matches('/').to_string();
+    for component in path.components() {
+        if let Component::Normal(part) = component {
+            in_app.push('/');
+            in_app.push_str(&part.to_string_lossy());
+        }
+    }
+    in_app
+}
+
+fn find_module<'a>(
+    manifest: &'a Manifest,
+    command: &Command,
+) -> Result<&'a Module, ContainerImageError> {
+    manifest
+        .module
+        .iter()
+        .flatten()
+        .find(|module| module.name == command.module && command.package.is_none())
+        .ok_or_else(|| {
+            ContainerImageError::ModuleNotInPackage(command.module.clone(), command.name.clone())
+        })
+}
+
+/// The entrypoint of the image, which runs the module like `wapm run` would
+pub fn entrypoint(spec: &ImageSpec) -> Result<Vec<String>, ContainerImageError> {
+    let module = find_module(spec.manifest, spec.command)?;
+    let runtime = match spec.runtime {
+        Some(_) => BUNDLED_RUNTIME_PATH,
+        None => DEFAULT_RUNTIME,
+    };
+    let mut entrypoint = vec![runtime.to_string(), format!("/{}", in_app(&module.source))];
+    if !spec.manifest.package.disable_command_rename {
+        entrypoint.push(format!("--command-name={}", spec.command.name));
+    }
+    let fs: BTreeMap<_, _> = spec.manifest.fs.iter().flatten().collect();
+    for (guest, host) in fs {
+        entrypoint.push(format!("--mapdir={}:/{}", guest, in_app(host)));
+    }
+    for env_var in spec.env_vars {
+        entrypoint.push(format!("--env={}", env_var));
+    }
+    if let Some(extra_flags) = &spec.manifest.package.wasmer_extra_flags {
+        entrypoint.extend(extra_flags.split_whitespace().map(str::to_string));
+    }
+    entrypoint.push("--".to_string());
+    Ok(entrypoint)
+}
+
+fn append_dir<W: Write>(builder: &mut Builder<W>, path: &str) -> io::Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Directory);
+    header.set_size(0);
+    header.set_mode(0o755);
+    header.set_cksum();
+    builder.append_data(&mut header, format!("{}/", path), io::empty())
+}
+
+/// The uncompressed layer with the files of the package and the bundled runtime
+fn build_layer(spec: &ImageSpec) -> Result<Vec<u8>, failure::Error> {
+    let manifest = spec.manifest;
+    let base = &manifest.base_directory_path;
+    let module = find_module(manifest, spec.command)?;
+    let mut builder = Builder::new(vec![]);
+    builder.follow_symlinks(true);
+    append_dir(&mut builder, &in_app(Path::new("")))?;
+    builder.append_path_with_name(
+        base.join(MANIFEST_FILE_NAME),
+        in_app(Path::new(MANIFEST_FILE_NAME)),
+    )?;
+    builder.append_path_with_name(base.join(&module.source), in_app(&module.source))?;
+    for host in manifest.fs.iter().flatten().map(|(_, host)| host) {
+        builder.append_dir_all(in_app(host), base.join(host))?;
+    }
+    if let Some(runtime) = spec.runtime {
+        for dir in &["usr", "usr/local", "usr/local/bin"] {
+            append_dir(&mut builder, dir)?;
+        }
+        let mut file = std::fs::File::open(runtime)?;
+        let mut header = Header::new_gnu();
+        header.set_metadata(&file.metadata()?);
+        header.set_mode(0o755);
+        builder.append_data(
+            &mut header,
+            BUNDLED_RUNTIME_PATH.trim_start_matches('/'),
+            &mut file,
+        )?;
+    }
+    Ok(builder.into_inner()?)
+}
+
+/// The image config: the config of the base image, or an empty one, running the entrypoint on
+/// top of the new layer
+fn image_config(
+    spec: &ImageSpec,
+    base_config: Option<&[u8]>,
+    entrypoint: Vec<String>,
+    layer_diff_id: String,
+) -> Result<Value, ContainerImageError> {
+    let created = Utc::now().to_rfc3339();
+    let mut config = match base_config {
+        Some(base_config) => serde_json::from_slice(base_config)
+            .map_err(|e| ContainerImageError::InvalidBaseConfig(e.to_string()))?,
+        None => json!({
+            "architecture": spec.architecture,
+            "os": "linux",
+            "config": { "Env": [DEFAULT_PATH] },
+            "rootfs": { "type": "layers", "diff_ids": [] },
+            "history": [],
+        }),
+    };
+    let invalid = |what: &str| ContainerImageError::InvalidBaseConfig(format!("no {}", what));
+    let object = config.as_object_mut().ok_or_else(|| invalid("object"))?;
+    object.insert("created".to_string(), json!(created));
+
+    let package = &spec.manifest.package;
+    let runtime_config = object
+        .entry("config")
+        .or_insert_with(|| json!({}))
+        .as_object_mut()
+        .ok_or_else(|| invalid("runtime config"))?;
+    let mut env: Vec<String> = runtime_config
+        .get("Env")
+        .and_then(Value::as_array)
+        .map(|env| {
+            env.iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    for env_var in spec.env_vars {
+        let key = env_var.split('=').next().unwrap_or_default();
+        env.retain(|existing| existing.split('=').next() != Some(key));
+        env.push(env_var.clone());
+    }
+    let cmd: Vec<&str> = spec
+        .command
+        .main_args
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect();
+    runtime_config.insert("Env".to_string(), json!(env));
+    runtime_config.insert("Entrypoint".to_string(), json!(entrypoint));
+    runtime_config.insert("Cmd".to_string(), json!(cmd));
+    runtime_config.insert("WorkingDir".to_string(), json!(APP_DIR));
+    let labels = runtime_config.entry("Labels").or_insert_with(|| json!({}));
+    if labels.is_null() {
+        *labels = json!({});
+    }
+    let labels = labels.as_object_mut().ok_or_else(|| invalid("labels"))?;
+    labels.insert(NAME_ANNOTATION.to_string(), json!(package.name));
+    labels.insert(TITLE_ANNOTATION.to_string(), json!(spec.command.name));
+    labels.insert(
+        VERSION_ANNOTATION.to_string(),
+        json!(package.version.to_string()),
+    );
+    labels.insert(
+        DESCRIPTION_ANNOTATION.to_string(),
+        json!(package.description),
+    );
+
+    object
+        .get_mut("rootfs")
+        .and_then(|rootfs| rootfs.get_mut("diff_ids"))
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| invalid("rootfs"))?
+        .push(json!(layer_diff_id));
+    let history = object.entry("history").or_insert_with(|| json!([]));
+    if let Some(history) = history.as_array_mut() {
+        history.push(json!({
+            "created": created,
+            "created_by": format!("wapm containerize {}@{}", package.name, package.version),
+        }));
+    }
+    Ok(config)
+}
+
+/// Build the image of a command, on top of a base image if there is one
+pub fn build_image(spec: &ImageSpec, base: Option<PulledImage>) -> Result<Image, failure::Error> {
+    let entrypoint = entrypoint(spec)?;
+    let layer = build_layer(spec)?;
+    let diff_id = oci::digest(&layer);
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(&layer)?;
+    let layer = encoder.finish()?;
+
+    let (base_config, mut descriptors, mut layers) = match base {
+        Some(base) => (Some(base.config), base.manifest.layers, base.layers),
+        None => (None, vec![], vec![]),
+    };
+    let config = image_config(spec, base_config.as_deref(), entrypoint, diff_id)?;
+    let config = serde_json::to_vec(&config)?;
+    descriptors.push(Descriptor::new(LAYER_MEDIA_TYPE, &layer));
+    layers.push(layer);
+
+    let package = &spec.manifest.package;
+    let mut annotations = BTreeMap::new();
+    annotations.insert(NAME_ANNOTATION.to_string(), package.name.clone());
+    annotations.insert(VERSION_ANNOTATION.to_string(), package.version.to_string());
+    annotations.insert(CREATED_ANNOTATION.to_string(), Utc::now().to_rfc3339());
+    Ok(Image {
+        manifest: ImageManifest {
+            schema_version: 2,
+            media_type: MANIFEST_MEDIA_TYPE.to_string(),
+            artifact_type: None,
+            config: Descriptor::new(CONFIG_MEDIA_TYPE, &config),
+            layers: descriptors,
+            annotations,
+        },
+        config,
+        layers,
+    })
+}
+
+fn blob_path(digest: &str) -> String {
+    format!("blobs/{}", digest.replacen(':', "/", 1))
+}
+
+fn append_file<W: Write>(builder: &mut Builder<W>, path: &str, data: &[u8]) -> io::Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, path, data)
+}
+
+/// Write the image as an OCI image layout archive, tagged with `tag` like `user/pkg:1.0.0`
+pub fn write_image<W: Write>(writer: W, image: &Image, tag: &str) -> Result<(), failure::Error> {
+    let manifest = serde_json::to_vec(&image.manifest)?;
+    let mut manifest_descriptor = Descriptor::new(MANIFEST_MEDIA_TYPE, &manifest);
+    manifest_descriptor
+        .annotations
+        .insert(REF_NAME_ANNOTATION.to_string(), tag.to_string());
+    manifest_descriptor
+        .annotations
+        .insert("io.containerd.image.name".to_string(), tag.to_string());
+    let index = json!({
+        "schemaVersion": 2,
+        "mediaType": INDEX_MEDIA_TYPE,
+        "manifests": [manifest_descriptor],
+    });
+    // the manifest `docker load` reads
+    let docker_manifest = json!([{
+        "Config": blob_path(&image.manifest.config.digest),
+        "RepoTags": [tag],
+        "Layers": image
+            .manifest
+            .layers
+            .iter()
+            .map(|layer| blob_path(&layer.digest))
+            .collect::<Vec<_>>(),
+    }]);
+
+    let mut builder = Builder::new(writer);
+    append_file(
+        &mut builder,
+        "oci-layout",
+        br#"{"imageLayoutVersion":"1.0.0"}"#,
+    )?;
+    append_file(&mut builder, "index.json", &serde_json::to_vec(&index)?)?;
+    append_file(
+        &mut builder,
+        "manifest.json",
+        &serde_json::to_vec(&docker_manifest)?,
+    )?;
+    let mut written = std::collections::HashSet::new();
+    let blobs = std::iter::once((&manifest_descriptor.digest, &manifest[..]))
+        .chain(std::iter::once((
+            &image.manifest.config.digest,
+            &image.config[..],
+        )))
+        .chain(
+            image
+                .manifest
+                .layers
+                .iter()
+                .map(|layer| &layer.digest)
+                .zip(image.layers.iter().map(Vec::as_slice)),
+        );
+    for (digest, data) in blobs {
+        if written.insert(digest.clone()) {
+            append_file(&mut builder, &blob_path(digest), data)?;
+        }
+    }
+    builder.into_inner()?.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::io::Read;
+
+    const MANIFEST: &str = r#"
+[package]
+name = "user/server"
+version = "1.2.0"
+description = "A server"
+
+[[module]]
+name = "server"
+source = "target/server.wasm"
+abi = "wasi"
+
+[[command]]
+name = "serve"
+module = "server"
+main_args = "--port 8080"
+
+[fs]
+"/static" = "public"
+"#;
+
+    #[test]
+    fn images_run_the_command_from_the_app_directory() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let dir = tmp_dir.path();
+        fs::create_dir_all(dir.join("target")).unwrap();
+        fs::create_dir_all(dir.join("public")).unwrap();
+        fs::write(dir.join("target/server.wasm"), b"\0asm").unwrap();
+        fs::write(dir.join("public/index.html"), b"<html>").unwrap();
+        fs::write(dir.join(MANIFEST_FILE_NAME), MANIFEST).unwrap();
+        let manifest = Manifest::find_in_directory(dir).unwrap();
+        let command = &manifest.command.as_ref().unwrap()[0];
+        let env_vars = ["RUST_LOG=info".to_string()];
+        let spec = ImageSpec {
+            manifest: &manifest,
+            command,
+            runtime: None,
+            env_vars: &env_vars,
+            architecture: "amd64",
+        };
+        assert_eq!(
+            entrypoint(&spec).unwrap(),
+            [
+                "wasmer",
+                "/app/target/server.wasm",
+                "--command-name=serve",
+                "--mapdir=/static:/app/public",
+                "--env=RUST_LOG=info",
+                "--",
+            ]
+        );
+
+        let image = build_image(&spec, None).unwrap();
+        let config: Value = serde_json::from_slice(&image.config).unwrap();
+        assert_eq!(config["config"]["Cmd"], json!(["--port", "8080"]));
+        assert_eq!(config["config"]["Env"][1], "RUST_LOG=info");
+        assert_eq!(config["rootfs"]["diff_ids"].as_array().unwrap().len(), 1);
+
+        let mut archive = vec![];
+        write_image(&mut archive, &image, "user/server:1.2.0").unwrap();
+        let mut entries = tar::Archive::new(&archive[..]);
+        let mut names = vec![];
+        let mut layer = vec![];
+        for entry in entries.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let name = entry.path().unwrap().to_string_lossy().to_string();
+            if name == blob_path(&image.manifest.layers[0].digest) {
+                entry.read_to_end(&mut layer).unwrap();
+            }
+            names.push(name);
+        }
+        assert_eq!(names[..3], ["oci-layout", "index.json", "manifest.json"]);
+        assert_eq!(names.len(), 6);
+
+        let mut layer = tar::Archive::new(flate2::read::GzDecoder::new(&layer[..]));
+        let files: Vec<String> = layer
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert!(files.contains(&"app/target/server.wasm".to_string()));
+        assert!(files.contains(&"app/public/index.html".to_string()));
+    }
+}
@@ -0,0 +1,399 @@
+//! Encryption at rest of the registry tokens saved in the config, for shared machines.
+//!
+//! With `credentials.encryption = "passphrase"` the tokens are encrypted with a key derived from
+//! a passphrase, asked for the first time a command needs the token. With `"keychain"` the
+//! passphrase is a random secret kept in the keychain of the operating system, through the
+//! `security` tool on macOS and `secret-tool` (libsecret) elsewhere. `WAPM_CREDENTIALS_KEY`
+//! gives the passphrase without asking, for CI jobs.
+//!
+//! Encrypted tokens are saved as `wapm-encrypted:v1:<hex>`, the hex being a random salt, a random
+//! nonce and the token encrypted with ChaCha20-Poly1305 under an Argon2 key of the passphrase.
+//! `credentials.verifier` is a known text encrypted the same way, which a passphrase must decrypt
+//! before a new token is encrypted with it.
+
+use crate::config::{Config, CredentialEncryption};
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use lazy_static::lazy_static;
+use std::env;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+/// The environment variable with the passphrase of the credentials
+pub const CREDENTIALS_KEY_ENV_VAR: &str = "WAPM_CREDENTIALS_KEY";
+
+const ENCRYPTED_PREFIX: &str = "wapm-encrypted:v1:";
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+/// The text encrypted in the verifier of the config
+const VERIFIER_TEXT: &str = "wapm-credentials";
+/// The service and account the secret is saved under in the keychain
+const KEYCHAIN_SERVICE: &str = "wapm-credentials";
+const KEYCHAIN_ACCOUNT: &str = "wapm";
+
+lazy_static! {
+    /// The passphrase, once it was asked for or read from the keychain
+    static ref SECRET: Mutex<Option<(CredentialEncryption, String)>> = Mutex::new(None);
+}
+
+#[derive(Debug, Fail)]
+pub enum CredentialsError {
+    #[fail(
+        display = "The registry token is encrypted, run wapm in a terminal to enter the passphrase or set {}",
+        _0
+    )]
+    PassphraseNeeded(&'static str),
+    #[fail(display = "The passphrases don't match")]
+    PassphraseMismatch,
+    #[fail(display = "The passphrase can't be empty")]
+    EmptyPassphrase,
+    #[fail(display = "Could not decrypt the registry token, the passphrase is wrong")]
+    WrongPassphrase,
+    #[fail(display = "The saved registry token is damaged, log in again with `wapm login`")]
+    Malformed,
+    #[fail(display = "The keychain can't be used: {}", _0)]
+    KeychainUnavailable(String),
+    #[fail(
+        display = "The key of the registry tokens is not in the keychain anymore, set `credentials.encryption` again and log in again"
+    )]
+    KeychainSecretMissing,
+    #[fail(display = "Could not encrypt the registry token: {}", _0)]
+    CouldNotEncrypt(String),
+}
+
+pub fn is_encrypted(token: &str) -> bool {
+    token.starts_with(ENCRYPTED_PREFIX)
+}
+
+pub(crate) fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn derive_key(secret: &str, salt: &[u8]) -> Result<Key, CredentialsError> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(secret.as_bytes(), salt, &mut key)
+        .map_err(|e| CredentialsError::CouldNotEncrypt(e.to_string()))?;
+    Ok(key)
+}
+
+fn encrypt(secret: &str, token: &str) -> Result<String, CredentialsError> {
+    let mut salt = [0; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    let cipher = ChaCha20Poly1305::new(&derive_key(secret, &salt)?);
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, token.as_bytes())
+        .map_err(|e| CredentialsError::CouldNotEncrypt(e.to_string()))?;
+    let mut data = salt.to_vec();
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&ciphertext);
+    Ok(format!("{}{}", ENCRYPTED_PREFIX, to_hex(&data)))
+}
+
+fn decrypt(secret: &str, token: &str) -> Result<String, CredentialsError> {
+    let data = from_hex(&token[ENCRYPTED_PREFIX.len()..])
+        .filter(|data| data.len() > SALT_LENGTH + NONCE_LENGTH)
+        .ok_or(CredentialsError::Malformed)?;
+    let (salt, rest) = data.split_at(SALT_LENGTH);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+    let cipher = ChaCha20Poly1305::new(&derive_key(secret, salt)?);
+    let token = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| CredentialsError::WrongPassphrase)?;
+    String::from_utf8(token).map_err(|_| CredentialsError::Malformed)
+}
+
+fn ask_passphrase(confirm: bool) -> Result<String, failure::Error> {
+    if !atty::is(atty::Stream::Stdin) {
+        return Err(CredentialsError::PassphraseNeeded(CREDENTIALS_KEY_ENV_VAR).into());
+    }
+    let prompt = if confirm {
+        "New passphrase of the registry tokens: "
+    } else {
+        "Passphrase of the registry tokens: "
+    };
+    let passphrase = rpassword::read_password_from_tty(Some(prompt))?;
+    if passphrase.is_empty() {
+        return Err(CredentialsError::EmptyPassphrase.into());
+    }
+    if confirm && rpassword::read_password_from_tty(Some("Repeat the passphrase: "))? != passphrase
+    {
+        return Err(CredentialsError::PassphraseMismatch.into());
+    }
+    Ok(passphrase)
+}
+
+/// The passphrase of an encryption, asked for or read once per run. A new passphrase is asked
+/// twice, and a new keychain secret is created.
+fn secret(encryption: CredentialEncryption, new: bool) -> Result<String, failure::Error> {
+    if let Some(secret) = env::var(CREDENTIALS_KEY_ENV_VAR)
+        .ok()
+        .filter(|secret| !secret.is_empty())
+    {
+        return Ok(secret);
+    }
+    let mut cached = SECRET.lock().unwrap();
+    if let Some((cached_encryption, secret)) = cached.as_ref() {
+        if *cached_encryption == encryption {
+            return Ok(secret.clone());
+        }
+    }
+    let secret = match encryption {
+        CredentialEncryption::Passphrase => ask_passphrase(new)?,
+        CredentialEncryption::Keychain if new => {
+            let mut random = [0; 32];
+            OsRng.fill_bytes(&mut random);
+            let secret = to_hex(&random);
+            keychain::write(&secret)?;
+            secret
+        }
+        CredentialEncryption::Keychain => {
+            keychain::read()?.ok_or(CredentialsError::KeychainSecretMissing)?
+        }
+    };
+    *cached = Some((encryption, secret.clone()));
+    Ok(secret)
+}
+
+fn forget_secret() {
+    *SECRET.lock().unwrap() = None;
+}
+
+/// Check the passphrase against the verifier of the config, or against its encrypted tokens
+/// when it was encrypted before there were verifiers
+fn check_secret(config: &Config, secret: &str) -> Result<(), CredentialsError> {
+    let encrypted = match &config.credentials.verifier {
+        Some(verifier) => Some(verifier),
+        None => config
+            .tokens()
+            .into_iter()
+            .find(|token| is_encrypted(token)),
+    };
+    match encrypted {
+        Some(encrypted) => decrypt(secret, encrypted).map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+/// The token as it is saved with the encryption of the config
+pub fn protect(config: &Config, token: &str) -> Result<String, failure::Error> {
+    match config.credentials.encryption {
+        Some(encryption) if !is_encrypted(token) => {
+            let secret = secret(encryption, false)?;
+            // a mistyped passphrase would encrypt the token under a key nothing else uses
+            check_secret(config, &secret).inspect_err(|_| forget_secret())?;
+            Ok(encrypt(&secret, token)?)
+        }
+        _ => Ok(token.to_string()),
+    }
+}
+
+fn reveal(encryption: Option<CredentialEncryption>, token: &str) -> Result<String, failure::Error> {
+    if !is_encrypted(token) {
+        return Ok(token.to_string());
+    }
+    // a token encrypted before the encryption was turned off still needs the passphrase
+    let encryption = encryption.unwrap_or(CredentialEncryption::Passphrase);
+    decrypt(&secret(encryption, false)?, token).map_err(|e| {
+        forget_secret();
+        e.into()
+    })
+}
+
+/// The config with the token of its registry decrypted, to send it. The config must not be saved.
+pub fn with_revealed_token(mut config: Config) -> Result<Config, failure::Error> {
+    if let Some(token) = config.registry.token.take() {
+        config.registry.token = Some(reveal(config.credentials.encryption, &token)?);
+    }
+    Ok(config)
+}
+
+/// Change how the tokens of the config are encrypted, encrypting them again
+pub fn set_encryption(
+    config: &mut Config,
+    encryption: Option<CredentialEncryption>,
+) -> Result<(), failure::Error> {
+    let current = config.credentials.encryption;
+    if current == encryption {
+        return Ok(());
+    }
+    let tokens = config
+        .tokens_mut()
+        .into_iter()
+        .map(|token| reveal(current, token))
+        .collect::<Result<Vec<String>, failure::Error>>()?;
+    forget_secret();
+    let new_secret = match encryption {
+        Some(encryption) => Some(secret(encryption, true)?),
+        None => None,
+    };
+    for (saved, token) in config.tokens_mut().into_iter().zip(tokens) {
+        *saved = match &new_secret {
+            Some(secret) => encrypt(secret, &token)?,
+            None => token,
+        };
+    }
+    config.credentials.verifier = match &new_secret {
+        Some(secret) => Some(encrypt(secret, VERIFIER_TEXT)?),
+        None => None,
+    };
+    config.credentials.encryption = encryption;
+    Ok(())
+}
+
+mod keychain {
+    use super::*;
+
+    fn unavailable(tool: &str, e: std::io::Error) -> CredentialsError {
+        CredentialsError::KeychainUnavailable(format!("could not run `{}`: {}", tool, e))
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn read() -> Result<Option<String>, failure::Error> {
+        let output = Command::new("security")
+            .args(["find-generic-password", "-s", KEYCHAIN_SERVICE])
+            .args(["-a", KEYCHAIN_ACCOUNT, "-w"])
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|e| unavailable("security", e))?;
+        Ok(secret_of_output(output))
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn write(secret: &str) -> Result<(), failure::Error> {
+        // the command is given on the standard input, so that the secret isn't in the arguments
+        // other users can see
+        let mut child = Command::new("security")
+            .arg("-i")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(|e| unavailable("security", e))?;
+        if let Some(stdin) = child.stdin.as_mut() {
+            writeln!(
+                stdin,
+                "add-generic-password -U -s {} -a {} -w {}",
+                KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT, secret
+            )?;
+        }
+        drop(child.stdin.take());
+        let status = child.wait()?;
+        check_status(status, "security")
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    pub fn read() -> Result<Option<String>, failure::Error> {
+        let output = Command::new("secret-tool")
+            .args(["lookup", "service", KEYCHAIN_SERVICE])
+            .args(["account", KEYCHAIN_ACCOUNT])
+            .stderr(Stdio::null())
+            .output()
+            .map_err(|e| unavailable("secret-tool", e))?;
+        Ok(secret_of_output(output))
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    pub fn write(secret: &str) -> Result<(), failure::Error> {
+        let mut child = Command::new("secret-tool")
+            .args(["store", "--label", "wapm registry tokens"])
+            .args(["service", KEYCHAIN_SERVICE, "account", KEYCHAIN_ACCOUNT])
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(|e| unavailable("secret-tool", e))?;
+        if let Some(stdin) = child.stdin.as_mut() {
+            stdin.write_all(secret.as_bytes())?;
+        }
+        let status = child.wait()?;
+        check_status(status, "secret-tool")
+    }
+
+    #[cfg(not(unix))]
+    pub fn read() -> Result<Option<String>, failure::Error> {
+        Err(CredentialsError::KeychainUnavailable(
+            "it is only supported on macOS and with libsecret, use a passphrase".to_string(),
+        )
+        .into())
+    }
+
+    #[cfg(not(unix))]
+    pub fn write(_secret: &str) -> Result<(), failure::Error> {
+        read().map(|_| ())
+    }
+
+    #[cfg(unix)]
+    fn secret_of_output(output: std::process::Output) -> Option<String> {
+        let secret = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        Some(secret).filter(|secret| output.status.success() && !secret.is_empty())
+    }
+
+    #[cfg(unix)]
+    fn check_status(status: std::process::ExitStatus, tool: &str) -> Result<(), failure::Error> {
+        if status.success() {
+            Ok(())
+        } else {
+            Err(CredentialsError::KeychainUnavailable(format!("`{}` failed", tool)).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tokens_round_trip_with_the_right_passphrase() {
+        let encrypted = encrypt("correct horse", "secret-token").unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.contains("secret-token"));
+        assert_eq!(
+            decrypt("correct horse", &encrypted).unwrap(),
+            "secret-token"
+        );
+        assert!(matches!(
+            decrypt("wrong horse", &encrypted),
+            Err(CredentialsError::WrongPassphrase)
+        ));
+        assert!(matches!(
+            decrypt("correct horse", "wapm-encrypted:v1:abc"),
+            Err(CredentialsError::Malformed)
+        ));
+        // the salt and nonce are random
+        assert_ne!(encrypt("correct horse", "secret-token").unwrap(), encrypted);
+        assert_eq!(reveal(None, "plain-token").unwrap(), "plain-token");
+    }
+
+    #[test]
+    fn passphrases_are_checked_before_encrypting_tokens() {
+        let mut config = Config::default();
+        assert!(check_secret(&config, "anything").is_ok());
+
+        config.registry.token = Some(encrypt("correct horse", "secret-token").unwrap());
+        assert!(check_secret(&config, "correct horse").is_ok());
+        assert!(matches!(
+            check_secret(&config, "wrong horse"),
+            Err(CredentialsError::WrongPassphrase)
+        ));
+
+        config.registry.token = None;
+        config.credentials.verifier = Some(encrypt("correct horse", VERIFIER_TEXT).unwrap());
+        assert!(check_secret(&config, "correct horse").is_ok());
+        assert!(matches!(
+            check_secret(&config, "wrong horse"),
+            Err(CredentialsError::WrongPassphrase)
+        ));
+    }
+}
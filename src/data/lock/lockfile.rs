@@ -1,3 +1,4 @@
+use crate::config::Config;
 use crate::data::lock::lockfile_command::LockfileCommand;
 use crate::data::lock::lockfile_module::{
     LockfileModule, LockfileModuleV2, LockfileModuleV3, LockfileModuleV4,
@@ -5,7 +6,7 @@ use crate::data::lock::lockfile_module::{
 use crate::data::lock::{LOCKFILE_HEADER, LOCKFILE_NAME};
 use semver::Version;
 use std::collections::BTreeMap;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io;
 use std::io::Write;
 use std::path::Path;
@@ -35,7 +36,7 @@ pub type ModuleMap = BTreeMap<String, BTreeMap<Version, BTreeMap<String, Lockfil
 pub type CommandMap = BTreeMap<String, LockfileCommand>;
 
 /// The latest Lockfile version
-#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub struct Lockfile {
     pub modules: ModuleMap, // PackageName -> VersionNumber -> ModuleName -> Module
     pub commands: CommandMap, // CommandName -> Command
@@ -48,11 +49,38 @@ pub type CommandMapV4 = CommandMap;
 impl<'a> Lockfile {
     /// Save the lockfile to the directory.
     pub fn save<P: AsRef<Path>>(&self, directory: P) -> Result<(), failure::Error> {
+        self.save_to_file(directory.as_ref().join(LOCKFILE_NAME))
+    }
+
+    /// Save the lockfile to a file with any name, e.g. for git merge drivers.
+    ///
+    /// The sections of an existing lockfile keep their order, so only the sections of the
+    /// packages that changed show up in diffs, unless the `install.sorted-lockfile` config key
+    /// asks for the sorted order. The file is replaced atomically and not touched at all when
+    /// nothing changed.
+    pub fn save_to_file<P: AsRef<Path>>(&self, lockfile_path: P) -> Result<(), failure::Error> {
+        let lockfile_path = lockfile_path.as_ref();
         let lockfile_string = toml::to_string(self)?;
         let lockfile_string = format!("{}\n{}", LOCKFILE_HEADER, lockfile_string);
-        let lockfile_path = directory.as_ref().join(LOCKFILE_NAME);
-        let mut file = File::create(&lockfile_path)?;
+        let existing = fs::read_to_string(lockfile_path).ok();
+        let sorted = Config::from_file()
+            .map(|config| config.install.sorted_lockfile())
+            .unwrap_or(false);
+        let lockfile_string = match existing.as_deref() {
+            Some(existing) if !sorted => keep_section_order(existing, &lockfile_string),
+            _ => lockfile_string,
+        };
+        if existing.as_deref() == Some(lockfile_string.as_str()) {
+            return Ok(());
+        }
+        let mut temp_name = lockfile_path.file_name().unwrap_or_default().to_owned();
+        temp_name.push(".tmp");
+        let temp_path = lockfile_path.with_file_name(temp_name);
+        let mut file = File::create(&temp_path)?;
         file.write_all(lockfile_string.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&temp_path, lockfile_path)?;
         Ok(())
     }
 
@@ -108,6 +136,70 @@ impl<'a> Lockfile {
     }
 }
 
+/// The text before the first table header of a TOML document and its tables, as pairs of the
+/// header line and the whole text of the table
+fn split_sections(source: &str) -> (&str, Vec<(&str, &str)>) {
+    let mut starts = vec![];
+    let mut offset = 0;
+    for line in source.split_inclusive('\n') {
+        if line.starts_with('[') {
+            starts.push(offset);
+        }
+        offset += line.len();
+    }
+    let preamble_end = starts.first().copied().unwrap_or(source.len());
+    let sections = starts
+        .iter()
+        .enumerate()
+        .map(|(index, &start)| {
+            let end = starts.get(index + 1).copied().unwrap_or(source.len());
+            let section = &source[start..end];
+            (section.lines().next().unwrap_or_default().trim(), section)
+        })
+        .collect();
+    (&source[..preamble_end], sections)
+}
+
+/// The new lockfile with its tables in the order they have in the existing lockfile. Tables
+/// that are new are put after the table that comes before them in the new lockfile.
+fn keep_section_order(existing: &str, new: &str) -> String {
+    let (_, existing_sections) = split_sections(existing);
+    let (preamble, new_sections) = split_sections(new);
+    let new_by_header: BTreeMap<&str, &str> = new_sections.iter().cloned().collect();
+    let mut order: Vec<&str> = existing_sections
+        .iter()
+        .map(|(header, _)| *header)
+        .filter(|header| new_by_header.contains_key(header))
+        .collect();
+    order.dedup();
+    for (index, (header, _)) in new_sections.iter().enumerate() {
+        if order.contains(header) {
+            continue;
+        }
+        let position = new_sections[..index]
+            .iter()
+            .rev()
+            .find_map(|(previous, _)| order.iter().position(|kept| kept == previous))
+            .map_or(0, |position| position + 1);
+        order.insert(position, header);
+    }
+    if order
+        .iter()
+        .eq(new_sections.iter().map(|(header, _)| header))
+    {
+        return new.to_string();
+    }
+    let mut lockfile = preamble.to_string();
+    for header in order {
+        let section = new_by_header[header];
+        lockfile.push_str(section.trim_end());
+        lockfile.push_str("\n\n");
+    }
+    lockfile.truncate(lockfile.trim_end().len());
+    lockfile.push('\n');
+    lockfile
+}
+
 #[derive(Debug, Fail)]
 pub enum LockfileError {
     #[fail(display = "Command not found: {}", _0)]
@@ -134,3 +226,20 @@ pub enum LockfileError {
     )]
     TomlParseError(toml::de::Error),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lockfile_tables_keep_their_order() {
+        let existing = "# header\n[b]\nx = 1\n\n[a]\nx = 1\n\n[d]\nx = 1\n";
+        let new = "# header\n[a]\nx = 2\n\n[b]\nx = 1\n\n[c]\nx = 1\n";
+        assert_eq!(
+            keep_section_order(existing, new),
+            "# header\n[b]\nx = 1\n\n[c]\nx = 1\n\n[a]\nx = 2\n"
+        );
+        // lockfiles already in order are written as they are
+        assert_eq!(keep_section_order(new, new), new);
+    }
+}
@@ -10,6 +10,12 @@ pub struct LockfileCommand {
     pub module: String,
     pub is_top_level_dependency: bool,
     pub main_args: Option<String>,
+    /// The sandbox profile the command is run with, see `wapm install --sandbox`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<String>,
+    /// The description of the command in the manifest that declares it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
 }
 
 impl<'a> LockfileCommand {
@@ -49,6 +55,8 @@ impl<'a> LockfileCommand {
             module: command.module.to_string(),
             main_args: command.main_args.clone(),
             is_top_level_dependency: true,
+            sandbox: None,
+            description: command.description.clone(),
         };
         Ok(lockfile_command)
     }
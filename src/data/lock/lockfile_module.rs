@@ -1,5 +1,5 @@
 use crate::abi::Abi;
-use crate::data::manifest::{Module, PACKAGES_DIR_NAME};
+use crate::data::manifest::Module;
 use crate::util;
 use semver::Version;
 use std::path::{Path, PathBuf};
@@ -50,6 +50,12 @@ pub struct LockfileModule {
     pub source: String,
     /// The hash of the wasm module cached here for faster startup time
     pub prehashed_module_key: Option<String>,
+    /// The condition of the manifest's `[target]` section that the package was installed for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub condition: Option<String>,
+    /// The old name the package was requested by, when it has been renamed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moved_from: Option<String>,
 }
 
 pub type LockfileModuleV4 = LockfileModule;
@@ -92,6 +98,8 @@ impl LockfileModule {
             abi: module.abi.clone(),
             prehashed_module_key: util::get_hashed_module_key(&path.join(&source)),
             source,
+            condition: None,
+            moved_from: None,
         };
         lockfile_module
     }
@@ -115,19 +123,18 @@ impl LockfileModule {
             abi: module.abi.clone(),
             source: module.source.to_string_lossy().to_string(),
             prehashed_module_key: util::get_hashed_module_key(&wasm_module_full_path),
+            condition: None,
+            moved_from: None,
         }
     }
 
     /// Returns the full, absolute path to the WASM module
-    pub fn get_canonical_source_path_from_lockfile_dir(
-        &self,
-        mut lockfile_dir: PathBuf,
-    ) -> PathBuf {
-        lockfile_dir.push(PACKAGES_DIR_NAME);
-        lockfile_dir.push(&self.package_path);
-        lockfile_dir.push(&self.source);
+    pub fn get_canonical_source_path_from_lockfile_dir(&self, lockfile_dir: PathBuf) -> PathBuf {
+        let mut source_path = util::get_packages_dir(&lockfile_dir);
+        source_path.push(&self.package_path);
+        source_path.push(&self.source);
 
-        lockfile_dir
+        source_path
     }
 
     /// Returns the Manifest path from the lockfile
@@ -138,17 +145,17 @@ impl LockfileModule {
     /// is not in the current directory and that we need to add `wapm_packages/...` to it.
     pub fn get_canonical_manifest_path_from_lockfile_dir(
         &self,
-        mut lockfile_dir: PathBuf,
+        lockfile_dir: PathBuf,
         local_dep: bool,
     ) -> PathBuf {
         if crate::config::Config::get_globals_directory().expect("Could not get globals direcotry")
             == lockfile_dir
             || local_dep
         {
-            lockfile_dir.push(PACKAGES_DIR_NAME);
-            lockfile_dir.push(&self.package_path);
+            let mut manifest_path = util::get_packages_dir(&lockfile_dir);
+            manifest_path.push(&self.package_path);
 
-            lockfile_dir
+            manifest_path
         } else {
             lockfile_dir
         }
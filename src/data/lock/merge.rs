@@ -0,0 +1,241 @@
+//! Merging lockfiles, for resolving the conflicts git merges leave in them
+//!
+//! Git can also merge lockfiles with wapm instead of line by line. Mark the lockfile in
+//! `.gitattributes`:
+//!
+//! ```text
+//! wapm.lock merge=wapm
+//! ```
+//!
+//! and set up the merge driver:
+//!
+//! ```text
+//! git config merge.wapm.name "wapm lockfile merge driver"
+//! git config merge.wapm.driver "wapm lock merge-driver %A %O %B"
+//! ```
+
+use crate::data::lock::lockfile::Lockfile;
+use semver::{Version, VersionReq};
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Debug, Fail)]
+pub enum MergeError {
+    #[fail(
+        display = "The conflict starting on line {} of the lockfile is not closed",
+        _0
+    )]
+    UnterminatedConflict(usize),
+}
+
+enum Section {
+    Common,
+    Ours,
+    Base,
+    Theirs,
+}
+
+/// Split the source of a lockfile with git conflict markers into our and their sides. Returns
+/// `None` if there are no conflicts.
+pub fn split_conflicts(source: &str) -> Result<Option<(String, String)>, MergeError> {
+    let mut ours = String::new();
+    let mut theirs = String::new();
+    let mut section = Section::Common;
+    let mut has_conflicts = false;
+    let mut conflict_start = 0;
+    for (index, line) in source.lines().enumerate() {
+        match section {
+            Section::Common if line.starts_with("<<<<<<<") => {
+                section = Section::Ours;
+                has_conflicts = true;
+                conflict_start = index + 1;
+            }
+            Section::Ours if line.starts_with("|||||||") => section = Section::Base,
+            Section::Ours | Section::Base if line.starts_with("=======") => {
+                section = Section::Theirs
+            }
+            Section::Theirs if line.starts_with(">>>>>>>") => section = Section::Common,
+            Section::Common => {
+                ours.push_str(line);
+                ours.push('\n');
+                theirs.push_str(line);
+                theirs.push('\n');
+            }
+            Section::Ours => {
+                ours.push_str(line);
+                ours.push('\n');
+            }
+            Section::Base => {}
+            Section::Theirs => {
+                theirs.push_str(line);
+                theirs.push('\n');
+            }
+        }
+    }
+    match section {
+        Section::Common if has_conflicts => Ok(Some((ours, theirs))),
+        Section::Common => Ok(None),
+        _ => Err(MergeError::UnterminatedConflict(conflict_start)),
+    }
+}
+
+/// Merge the packages of two lockfiles. When the sides have different versions of a package,
+/// the greatest version matching its requirement in `requirements` wins, or the greatest version
+/// if it has no requirement. Commands of packages that didn't win are dropped. With the lockfile
+/// of the merge base, packages removed on one side and unchanged on the other are removed.
+pub fn merge_lockfiles(
+    mut ours: Lockfile,
+    mut theirs: Lockfile,
+    base: Option<&Lockfile>,
+    requirements: &HashMap<String, String>,
+) -> Lockfile {
+    if let Some(base) = base {
+        for (name, base_versions) in base.modules.iter() {
+            let unchanged = |side: &Lockfile| {
+                side.modules
+                    .get(name)
+                    .map(|versions| versions.keys().eq(base_versions.keys()))
+                    .unwrap_or(false)
+            };
+            if !ours.modules.contains_key(name) && unchanged(&theirs) {
+                theirs.modules.remove(name);
+            } else if !theirs.modules.contains_key(name) && unchanged(&ours) {
+                ours.modules.remove(name);
+            }
+        }
+    }
+    let names: BTreeSet<String> = ours
+        .modules
+        .keys()
+        .chain(theirs.modules.keys())
+        .cloned()
+        .collect();
+    let mut merged = Lockfile {
+        modules: Default::default(),
+        commands: Default::default(),
+    };
+    for name in names {
+        let mut versions = ours.modules.remove(&name).unwrap_or_default();
+        for (version, modules) in theirs.modules.remove(&name).unwrap_or_default() {
+            versions.entry(version).or_insert(modules);
+        }
+        let requirement = requirements
+            .get(&name)
+            .and_then(|requirement| VersionReq::parse(requirement).ok());
+        let chosen: Option<Version> = versions
+            .keys()
+            .filter(|version| {
+                requirement
+                    .as_ref()
+                    .map(|requirement| requirement.matches(version))
+                    .unwrap_or(true)
+            })
+            .max()
+            .or_else(|| versions.keys().max())
+            .cloned();
+        if let Some(chosen) = chosen {
+            let modules = versions.remove(&chosen).unwrap_or_default();
+            merged
+                .modules
+                .entry(name)
+                .or_default()
+                .insert(chosen, modules);
+        }
+    }
+    for (command_name, command) in ours.commands.into_iter().chain(theirs.commands) {
+        let provided = merged
+            .modules
+            .get(&command.package_name)
+            .map(|versions| versions.contains_key(&command.package_version))
+            .unwrap_or(false);
+        if provided {
+            merged.commands.entry(command_name).or_insert(command);
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sqlite_lockfile(version: &str) -> Lockfile {
+        toml::from_str(&format!(
+            r#"
+[modules."_/sqlite"."{version}".sqlite]
+name = "sqlite"
+package_version = "{version}"
+package_name = "_/sqlite"
+package_path = "_/sqlite@{version}"
+resolved = "https://registry-cdn.wapm.dev/packages/_/sqlite/sqlite-{version}.tar.gz"
+resolved_source = "registry+sqlite"
+abi = "emscripten"
+source = "sqlite.wasm"
+[commands.sqlite]
+name = "sqlite"
+package_name = "_/sqlite"
+package_version = "{version}"
+module = "sqlite"
+is_top_level_dependency = true
+"#,
+            version = version
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn split_conflicted_lockfile() {
+        let source = "a\n<<<<<<< HEAD\nb\n||||||| base\nc\n=======\nd\n>>>>>>> branch\ne\n";
+        assert_eq!(
+            split_conflicts(source).unwrap(),
+            Some(("a\nb\ne\n".to_string(), "a\nd\ne\n".to_string()))
+        );
+        assert_eq!(split_conflicts("a\nb\n").unwrap(), None);
+        assert!(split_conflicts("a\n<<<<<<< HEAD\nb\n").is_err());
+    }
+
+    #[test]
+    fn merge_takes_the_greatest_matching_version() {
+        let no_requirements = HashMap::new();
+        let merged = merge_lockfiles(
+            sqlite_lockfile("0.1.1"),
+            sqlite_lockfile("0.2.0"),
+            None,
+            &no_requirements,
+        );
+        assert_eq!(merged, sqlite_lockfile("0.2.0"));
+
+        let mut requirements = HashMap::new();
+        requirements.insert("_/sqlite".to_string(), "0.1".to_string());
+        let merged = merge_lockfiles(
+            sqlite_lockfile("0.1.1"),
+            sqlite_lockfile("0.2.0"),
+            None,
+            &requirements,
+        );
+        assert_eq!(merged, sqlite_lockfile("0.1.1"));
+    }
+
+    #[test]
+    fn merge_keeps_removals() {
+        let empty = || Lockfile {
+            modules: Default::default(),
+            commands: Default::default(),
+        };
+        let base = sqlite_lockfile("0.1.1");
+        let merged = merge_lockfiles(
+            empty(),
+            sqlite_lockfile("0.1.1"),
+            Some(&base),
+            &HashMap::new(),
+        );
+        assert_eq!(merged, empty());
+        // the package was removed on one side but upgraded on the other
+        let merged = merge_lockfiles(
+            empty(),
+            sqlite_lockfile("0.2.0"),
+            Some(&base),
+            &HashMap::new(),
+        );
+        assert_eq!(merged, sqlite_lockfile("0.2.0"));
+    }
+}
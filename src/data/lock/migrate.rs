@@ -6,9 +6,9 @@
 use crate::data::lock::lockfile::{LockfileV2, LockfileV3, LockfileV4};
 use crate::data::lock::lockfile_command::LockfileCommand;
 use crate::data::lock::lockfile_module::{LockfileModuleV2, LockfileModuleV3, LockfileModuleV4};
-use crate::data::manifest::PACKAGES_DIR_NAME;
 use crate::dataflow::lockfile_packages::LockfileError;
 use crate::dataflow::normalize_global_namespace_package_name;
+use crate::util;
 
 use lazy_static::lazy_static;
 use regex::Regex;
@@ -129,7 +129,7 @@ pub fn convert_lockfilev2_to_v3(lockfile: LockfileV2) -> LockfileV3 {
 }
 
 pub fn convert_lockfilev3_to_v4(lockfile: LockfileV3, directory: &Path) -> LockfileV4 {
-    let dir_prefix = directory.join(PACKAGES_DIR_NAME);
+    let dir_prefix = util::get_packages_dir(directory);
 
     let mut modules: BTreeMap<String, _> = Default::default();
     for (k1, version_map) in lockfile.modules.into_iter() {
@@ -160,6 +160,8 @@ pub fn convert_lockfilev3_to_v4(lockfile: LockfileV3, directory: &Path) -> Lockf
                     },
                     package_path,
                     prehashed_module_key: module_data.prehashed_module_key,
+                    condition: None,
+                    moved_from: None,
                 };
                 name_map.insert(k3, module);
             }
@@ -1,9 +1,13 @@
 pub mod lockfile;
 pub mod lockfile_command;
 pub mod lockfile_module;
+pub mod merge;
 pub mod migrate;
 
 pub static LOCKFILE_NAME: &str = "wapm.lock";
+/// The lockfile is written here by `save_to_file` before it replaces the old one, interrupted
+/// installs can leave it behind
+pub static LOCKFILE_TEMP_NAME: &str = "wapm.lock.tmp";
 
 static LOCKFILE_HEADER: &str = r#"# Lockfile v4
 # This file is automatically generated by Wapm.
@@ -1,7 +1,12 @@
 //! The Manifest file is where the core metadata of a wapm package lives
 use crate::abi::Abi;
-use semver::Version;
+use crate::data::workspace::{inherit_workspace_package, inherited_field_marker};
+use crate::diagnostics::Level;
+use chrono::NaiveDate;
+use semver::{Version, VersionReq};
 use std::collections::hash_map::HashMap;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -15,6 +20,8 @@ pub struct Package {
     pub name: String,
     pub version: Version,
     pub description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authors: Option<Vec<String>>,
     pub license: Option<String>,
     /// The location of the license file, useful for non-standard licenses
     #[serde(rename = "license-file")]
@@ -22,8 +29,23 @@ pub struct Package {
     pub readme: Option<PathBuf>,
     pub repository: Option<String>,
     pub homepage: Option<String>,
+    /// Where the documentation of the package is published
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documentation: Option<String>,
+    /// Words `wapm search --keyword` finds the package with
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    /// Categories of the registry the package is in, for `wapm search --category`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    /// The new name of the package, when it has been renamed
+    #[serde(rename = "moved-to", skip_serializing_if = "Option::is_none")]
+    pub moved_to: Option<String>,
     #[serde(rename = "wasmer-extra-flags")]
     pub wasmer_extra_flags: Option<String>,
+    /// Where dependencies are installed, relative to the manifest. Defaults to `wapm_packages`
+    #[serde(rename = "packages-dir", skip_serializing_if = "Option::is_none")]
+    pub packages_dir: Option<PathBuf>,
     #[serde(
         rename = "disable-command-rename",
         default,
@@ -40,6 +62,157 @@ pub struct Package {
         skip_serializing_if = "std::ops::Not::not"
     )]
     pub rename_commands_to_raw_command_name: bool,
+    /// How to build the modules of the package
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub build: Option<Build>,
+    /// Where the package archive can be fetched from IPFS. Added by `wapm publish --ipfs-pin` to
+    /// the manifest sent to the registry
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ipfs: Option<IpfsDistribution>,
+    /// Translations of the description and readme, by language, like `[package.localized.es]`.
+    /// `description` and `readme` are the English ones.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub localized: BTreeMap<String, Localized>,
+    /// What happens to the symlinks in the directories of the package filesystem when it is
+    /// published, `rewrite` when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symlinks: Option<SymlinkPolicy>,
+}
+
+/// The `symlinks` policy of a package
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkPolicy {
+    /// Links fail the publish
+    Reject,
+    /// Links are replaced with the files they point to, inside the package directory
+    #[default]
+    Rewrite,
+    /// Links are kept, pointing inside the directory they are in
+    Preserve,
+}
+
+/// A `[package.localized.<language>]` section of the manifest
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Localized {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub readme: Option<PathBuf>,
+}
+
+impl Package {
+    /// The description in `language`, like `es`, or the English one when it isn't translated
+    pub fn description_in(&self, language: &str) -> &str {
+        self.localized
+            .get(language)
+            .and_then(|localized| localized.description.as_deref())
+            .unwrap_or(&self.description)
+    }
+
+    /// The readme in `language`, or the English one when it isn't translated
+    pub fn readme_in(&self, language: &str) -> Option<&Path> {
+        self.localized
+            .get(language)
+            .and_then(|localized| localized.readme.as_deref())
+            .or(self.readme.as_deref())
+    }
+
+    /// The readmes of every language, the English one first
+    pub fn all_readmes(&self) -> impl Iterator<Item = &Path> {
+        self.readme.as_deref().into_iter().chain(
+            self.localized
+                .values()
+                .filter_map(|localized| localized.readme.as_deref()),
+        )
+    }
+}
+
+/// The `[package.build]` section of the manifest
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Build {
+    /// The command that builds the modules of the package
+    pub command: String,
+}
+
+/// The `[package.ipfs]` section of a published manifest
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct IpfsDistribution {
+    /// The content identifier of the package archive
+    pub cid: String,
+    /// The SHA-256 checksum of the package archive, which content from gateways must match
+    pub sha256: String,
+}
+
+/// A `[target.'<condition>']` section of the manifest
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Target {
+    pub dependencies: Option<HashMap<String, String>>,
+}
+
+/// A `[pins]` entry restricting a dependency to some versions for a while, e.g.
+/// `"_/sqlite" = { version = "=1.4.2", until = "2025-01-01", reason = "regression in 2.x" }`
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Pin {
+    /// The version requirement used instead of the one in `[dependencies]`
+    pub version: String,
+    /// The date the pin should be revisited, as `YYYY-MM-DD`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Pin {
+    /// The date the pin expires, if it has one
+    pub fn expiry(&self) -> Option<NaiveDate> {
+        self.until
+            .as_ref()
+            .and_then(|until| NaiveDate::parse_from_str(until, "%Y-%m-%d").ok())
+    }
+
+    pub fn is_expired(&self, today: NaiveDate) -> bool {
+        self.expiry().iter().any(|expiry| *expiry <= today)
+    }
+
+    /// Describe the pin of a dependency for humans
+    pub fn describe(&self, name: &str) -> String {
+        let mut description = format!("{} is pinned to {}", name, self.version);
+        if let Some(until) = &self.until {
+            description.push_str(&format!(" until {}", until));
+        }
+        if let Some(reason) = &self.reason {
+            description.push_str(&format!(" ({})", reason));
+        }
+        description
+    }
+}
+
+/// A condition for using the dependencies of a `[target]` section, e.g. `abi = "wasi"`
+#[derive(Clone, Debug, PartialEq)]
+pub enum TargetCondition {
+    /// Holds when a module of the package uses the ABI
+    Abi(Abi),
+}
+
+impl TargetCondition {
+    pub fn parse(condition: &str) -> Result<Self, ManifestError> {
+        let invalid_condition = || ManifestError::InvalidTargetCondition(condition.to_string());
+        let mut split = condition.splitn(2, '=');
+        let (key, value) = match (split.next(), split.next()) {
+            (Some(key), Some(value)) => (key.trim(), value.trim()),
+            _ => return Err(invalid_condition()),
+        };
+        match (key, value.trim_matches('"')) {
+            ("abi", "wasi") => Ok(TargetCondition::Abi(Abi::Wasi)),
+            ("abi", "emscripten") => Ok(TargetCondition::Abi(Abi::Emscripten)),
+            ("abi", "none") => Ok(TargetCondition::Abi(Abi::None)),
+            ("feature", _) => Err(ManifestError::UnsupportedTargetCondition(
+                condition.to_string(),
+            )),
+            _ => Err(invalid_condition()),
+        }
+    }
 }
 
 /// Describes a command for a wapm module
@@ -49,6 +222,58 @@ pub struct Command {
     pub module: String,
     pub main_args: Option<String>,
     pub package: Option<String>,
+    /// What the command does, in one line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// How the command is called, like `calc [--precision N] <EXPRESSION>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<String>,
+    /// Example calls of the command, like `calc "1 + 2"`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<String>,
+}
+
+impl Command {
+    /// A call of the command with `wapm run`, from a usage or an example that may start with the
+    /// name of the command
+    pub fn run_line(&self, call: &str) -> String {
+        let call = call.trim();
+        let starts_with_name = call
+            .split_whitespace()
+            .next()
+            .is_some_and(|first| first == self.name);
+        if starts_with_name {
+            format!("wapm run {}", call)
+        } else {
+            format!("wapm run {} {}", self.name, call)
+        }
+    }
+
+    /// The help of the command, when the manifest describes it
+    pub fn help(&self) -> Option<String> {
+        if self.description.is_none() && self.usage.is_none() && self.examples.is_empty() {
+            return None;
+        }
+        let mut help = String::new();
+        match &self.description {
+            Some(description) => help.push_str(&format!("{} - {}\n", self.name, description)),
+            None => help.push_str(&format!("{}\n", self.name)),
+        }
+        if let Some(usage) = &self.usage {
+            help.push_str(&format!("\nUSAGE:\n    {}\n", self.run_line(usage)));
+        }
+        if !self.examples.is_empty() {
+            help.push_str("\nEXAMPLES:\n");
+            for example in self.examples.iter() {
+                help.push_str(&format!("    {}\n", self.run_line(example)));
+            }
+        }
+        help.push_str(&format!(
+            "\nTo pass --help to the command itself, run `wapm run {} -- --help`\n",
+            self.name
+        ));
+        Some(help)
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -61,6 +286,47 @@ pub struct Module {
     pub fs: Option<Table>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub interfaces: Option<HashMap<String, String>>,
+    /// The interfaces this module implements, for use by other packages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exports: Option<Vec<ExportedInterface>>,
+}
+
+/// An interface implemented by a module, described by a `.wai` definition file
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ExportedInterface {
+    pub name: String,
+    pub version: Version,
+    /// The location of the interface definition, relative to the manifest
+    pub path: PathBuf,
+}
+
+/// An interface definition published by an interface package, in the `.wasm_interface` format
+/// of the registry's interface catalog. It is published with the version of the package.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InterfaceDefinition {
+    pub name: String,
+    /// The location of the definition, relative to the manifest
+    pub path: PathBuf,
+}
+
+/// Whether a package is an application (it exposes commands that can be run), a library
+/// that only provides modules for other packages to use, or an interface package that only
+/// publishes interface definitions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PackageKind {
+    Application,
+    Library,
+    Interface,
+}
+
+impl fmt::Display for PackageKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PackageKind::Application => write!(f, "application"),
+            PackageKind::Library => write!(f, "library"),
+            PackageKind::Interface => write!(f, "interface"),
+        }
+    }
 }
 
 /// The manifest represents the file used to describe a Wasm package.
@@ -76,14 +342,33 @@ pub struct Module {
 pub struct Manifest {
     pub package: Package,
     pub dependencies: Option<HashMap<String, String>>,
+    /// Dependencies that are only used when a condition holds, keyed by the condition
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<HashMap<String, Target>>,
+    /// Temporary restrictions of dependencies to some versions, keyed by the dependency
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pins: Option<HashMap<String, Pin>>,
+    /// Short names for long wapm commands, like `t = "run test -- --verbose"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<BTreeMap<String, String>>,
+    /// The levels of the categories of warnings, like `missing-readme = "allow"`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warnings: Option<BTreeMap<String, Level>>,
     pub module: Option<Vec<Module>>,
     pub command: Option<Vec<Command>>,
+    /// The interface definitions of an interface package, which has no modules
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interface: Option<Vec<InterfaceDefinition>>,
     /// Of the form Guest -> Host path
     pub fs: Option<HashMap<String, PathBuf>>,
     /// private data
     /// store the directory path of the manifest file for use later accessing relative path fields
     #[serde(skip)]
     pub base_directory_path: PathBuf,
+    /// The `[package]` fields inherited from the workspace, which are written back as
+    /// `{ workspace = true }`
+    #[serde(skip)]
+    pub inherited_fields: Vec<String>,
 }
 
 impl Manifest {
@@ -99,12 +384,24 @@ impl Manifest {
         let contents = fs::read_to_string(&manifest_path_buf).map_err(|_e| {
             ManifestError::MissingManifest(manifest_path_buf.to_string_lossy().to_string())
         })?;
-        let manifest: Self = toml::from_str(contents.as_str())
-            .map_err(|e| ManifestError::TomlParseError(e.to_string()))?;
+        let manifest = Self::from_source(path.as_ref(), &contents)?;
         manifest.validate()?;
         Ok(manifest)
     }
 
+    /// Parse the manifest of the package in the specified directory, inheriting fields from
+    /// its workspace
+    pub fn from_source(directory: &Path, source: &str) -> Result<Self, ManifestError> {
+        let (manifest_value, inherited_fields) = inherit_workspace_package(directory, source)
+            .map_err(|e| ManifestError::WorkspaceInheritanceError(e.to_string()))?;
+        let mut manifest: Self = manifest_value
+            .try_into()
+            .map_err(|e: toml::de::Error| ManifestError::TomlParseError(e.to_string()))?;
+        manifest.base_directory_path = directory.to_owned();
+        manifest.inherited_fields = inherited_fields;
+        Ok(manifest)
+    }
+
     pub fn validate(&self) -> Result<(), ManifestError> {
         let module_map = self
             .module
@@ -117,6 +414,41 @@ impl Manifest {
             })
             .unwrap_or_default();
 
+        for condition in self.target.iter().flat_map(HashMap::keys) {
+            TargetCondition::parse(condition)?;
+        }
+
+        for (name, pin) in self.pins.iter().flatten() {
+            if VersionReq::parse(&pin.version).is_err() {
+                return Err(ManifestError::ValidationError(ValidationError::InvalidPin(
+                    name.clone(),
+                    format!("invalid version requirement {}", pin.version),
+                )));
+            }
+            if pin.until.is_some() && pin.expiry().is_none() {
+                return Err(ManifestError::ValidationError(ValidationError::InvalidPin(
+                    name.clone(),
+                    "`until` must be a date like 2025-01-01".to_string(),
+                )));
+            }
+        }
+
+        if let Some(ref definitions) = self.interface {
+            if !module_map.is_empty() || self.command.iter().flatten().next().is_some() {
+                return Err(ManifestError::ValidationError(
+                    ValidationError::InterfacePackageWithModules,
+                ));
+            }
+            let mut names = std::collections::HashSet::new();
+            for definition in definitions {
+                if !names.insert(&definition.name) {
+                    return Err(ManifestError::ValidationError(
+                        ValidationError::DuplicateInterface(definition.name.clone()),
+                    ));
+                }
+            }
+        }
+
         if let Some(ref commands) = self.command {
             for command in commands {
                 if let Some(ref module) = module_map.get(&command.module) {
@@ -139,12 +471,92 @@ impl Manifest {
         Ok(())
     }
 
+    /// Packages with interface definitions are interface packages, packages without any commands
+    /// are libraries and everything else is an application
+    pub fn package_kind(&self) -> PackageKind {
+        if self.interface.iter().flatten().next().is_some() {
+            return PackageKind::Interface;
+        }
+        match self.command {
+            Some(ref commands) if !commands.is_empty() => PackageKind::Application,
+            _ => PackageKind::Library,
+        }
+    }
+
+    /// Check if a condition of a `[target]` section holds for this package
+    pub fn target_condition_holds(&self, condition: &TargetCondition) -> bool {
+        match condition {
+            TargetCondition::Abi(abi) => self
+                .module
+                .iter()
+                .flatten()
+                .any(|module| &module.abi == abi),
+        }
+    }
+
+    /// The dependencies of the `[target]` sections whose conditions hold, as
+    /// (condition, name, version)
+    pub fn target_dependencies(&self) -> Result<Vec<(&str, &str, &str)>, ManifestError> {
+        let mut target_dependencies = vec![];
+        for (condition, target) in self.target.iter().flatten() {
+            if !self.target_condition_holds(&TargetCondition::parse(condition)?) {
+                continue;
+            }
+            for (name, version) in target.dependencies.iter().flatten() {
+                target_dependencies.push((condition.as_str(), name.as_str(), version.as_str()));
+            }
+        }
+        Ok(target_dependencies)
+    }
+
+    /// The version requirement of a dependency, from its pin if it is pinned
+    pub fn dependency_requirement<'a>(&'a self, name: &str, requirement: &'a str) -> &'a str {
+        self.pins
+            .as_ref()
+            .and_then(|pins| pins.get(name))
+            .map(|pin| pin.version.as_str())
+            .unwrap_or(requirement)
+    }
+
+    /// The pins that expired on or before `today`, ordered by dependency
+    pub fn expired_pins(&self, today: NaiveDate) -> Vec<(&str, &Pin)> {
+        let mut expired: Vec<(&str, &Pin)> = self
+            .pins
+            .iter()
+            .flatten()
+            .filter(|(_, pin)| pin.is_expired(today))
+            .map(|(name, pin)| (name.as_str(), pin))
+            .collect();
+        expired.sort_by(|a, b| a.0.cmp(b.0));
+        expired
+    }
+
     /// add a dependency
     pub fn add_dependency(&mut self, dependency_name: String, dependency_version: String) {
         let dependencies = self.dependencies.get_or_insert(Default::default());
         dependencies.insert(dependency_name, dependency_version);
     }
 
+    /// Change the version of a dependency and save the manifest, keeping the formatting and
+    /// comments of the manifest file when the dependency is in its `[dependencies]` table
+    pub fn update_dependency_version(
+        &mut self,
+        dependency_name: &str,
+        dependency_version: String,
+    ) -> Result<(), failure::Error> {
+        self.add_dependency(dependency_name.to_string(), dependency_version.clone());
+        let manifest_path = self.manifest_path();
+        let edited = fs::read_to_string(&manifest_path).ok().and_then(|source| {
+            set_dependency_version_in_source(&source, dependency_name, &dependency_version)
+        });
+        match edited {
+            Some(source) => fs::write(manifest_path, source)
+                .map_err(|e| ManifestError::CannotSaveManifest(e.to_string()))?,
+            None => self.save()?,
+        }
+        Ok(())
+    }
+
     /// remove dependency by package name
     pub fn remove_dependency(&mut self, dependency_name: &str) -> Option<String> {
         let dependencies = self.dependencies.get_or_insert(Default::default());
@@ -152,7 +564,19 @@ impl Manifest {
     }
 
     pub fn to_string(&self) -> Result<String, failure::Error> {
-        Ok(toml::to_string(self)?)
+        if self.inherited_fields.is_empty() {
+            return Ok(toml::to_string(self)?);
+        }
+        let mut manifest_value = toml::Value::try_from(self)?;
+        if let Some(package) = manifest_value
+            .get_mut("package")
+            .and_then(toml::Value::as_table_mut)
+        {
+            for field in self.inherited_fields.iter() {
+                package.insert(field.clone(), inherited_field_marker());
+            }
+        }
+        Ok(toml::to_string(&manifest_value)?)
     }
 
     pub fn manifest_path(&self) -> PathBuf {
@@ -215,6 +639,18 @@ pub enum ManifestError {
     SemVerError(String),
     #[fail(display = "There was an error validating the manifest: {}", _0)]
     ValidationError(ValidationError),
+    #[fail(display = "Could not inherit fields from the workspace: {}", _0)]
+    WorkspaceInheritanceError(String),
+    #[fail(
+        display = "Invalid target condition `{}`, expected a condition like `abi = \"wasi\"`",
+        _0
+    )]
+    InvalidTargetCondition(String),
+    #[fail(
+        display = "Unsupported target condition `{}`: packages don't have features, only `abi` conditions are supported",
+        _0
+    )]
+    UnsupportedTargetCondition(String),
 }
 
 #[derive(Debug, Fail)]
@@ -226,6 +662,12 @@ pub enum ValidationError {
     MissingABI(String, String),
     #[fail(display = "missing module {} in manifest used by command {}", _1, _0)]
     MissingModuleForCommand(String, String),
+    #[fail(display = "invalid pin of {}: {}", _0, _1)]
+    InvalidPin(String, String),
+    #[fail(display = "interface packages can't have modules or commands")]
+    InterfacePackageWithModules,
+    #[fail(display = "the interface {} is defined more than once", _0)]
+    DuplicateInterface(String),
 }
 
 #[cfg(test)]
@@ -280,9 +722,103 @@ mod command_tests {
     }
 }
 
+#[cfg(test)]
+mod package_kind_tests {
+    use crate::data::manifest::{Manifest, PackageKind};
+
+    #[test]
+    fn package_without_commands_is_a_library() {
+        let wapm_toml = toml! {
+            [package]
+            name = "test"
+            version = "1.0.0"
+            description = "The best package."
+            [[module]]
+            name = "test-lib"
+            source = "lib.wasm"
+        };
+        let manifest: Manifest = wapm_toml.try_into().unwrap();
+        assert_eq!(PackageKind::Library, manifest.package_kind());
+    }
+
+    #[test]
+    fn package_with_commands_is_an_application() {
+        let wapm_toml = toml! {
+            [package]
+            name = "test"
+            version = "1.0.0"
+            description = "The best package."
+            [[module]]
+            name = "test-app"
+            source = "app.wasm"
+            abi = "wasi"
+            [[command]]
+            name = "test-app"
+            module = "test-app"
+        };
+        let manifest: Manifest = wapm_toml.try_into().unwrap();
+        assert_eq!(PackageKind::Application, manifest.package_kind());
+    }
+}
+
+/// Change the version of a dependency in the source of a manifest, only touching the version
+/// string. Returns `None` if the dependency isn't in the `[dependencies]` table.
+pub fn set_dependency_version_in_source(
+    source: &str,
+    dependency_name: &str,
+    dependency_version: &str,
+) -> Option<String> {
+    let mut in_dependencies = false;
+    let mut edited_lines = vec![];
+    let mut found = false;
+    for line in source.split('\n') {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            in_dependencies =
+                trimmed.trim_matches(|c| c == '[' || c == ']').trim() == "dependencies";
+        } else if in_dependencies && !found {
+            if let Some(edited) = set_version_in_line(line, dependency_name, dependency_version) {
+                edited_lines.push(edited);
+                found = true;
+                continue;
+            }
+        }
+        edited_lines.push(line.to_string());
+    }
+    if found {
+        Some(edited_lines.join("\n"))
+    } else {
+        None
+    }
+}
+
+/// Change the version of a `name = "version"` line if it's about the dependency
+fn set_version_in_line(
+    line: &str,
+    dependency_name: &str,
+    dependency_version: &str,
+) -> Option<String> {
+    let equals = line.find('=')?;
+    let (key, value) = (&line[..equals], &line[equals + 1..]);
+    if key.trim().trim_matches(&['"', '\''][..]) != dependency_name {
+        return None;
+    }
+    let value_start = value.find(&['"', '\''][..])?;
+    let quote = value[value_start..].chars().next()?;
+    let value_end = value_start + 1 + value[value_start + 1..].find(quote)?;
+    Some(format!(
+        "{}={}{}{}{}",
+        key,
+        &value[..value_start + 1],
+        dependency_version,
+        quote,
+        &value[value_end + 1..]
+    ))
+}
+
 #[cfg(test)]
 mod dependency_tests {
-    use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
+    use crate::data::manifest::{set_dependency_version_in_source, Manifest, MANIFEST_FILE_NAME};
     use std::fs::File;
     use std::io::Write;
 
@@ -324,6 +860,26 @@ mod dependency_tests {
         );
         assert_eq!(2, manifest.dependencies.as_ref().unwrap().len());
     }
+
+    #[test]
+    fn update_dependency_version_keeping_the_format() {
+        let source = r#"[package]
+name = "_/test"
+version = "1.0.0" # the version of the package, not a dependency
+
+[dependencies]
+# databases
+"_/sqlite" =   "0.1.0"  # pinned for now
+"_/other-sqlite" = "0.2.0"
+
+[target.'abi = "wasi"'.dependencies]
+"_/wasi-only" = "1.0.0"
+"#;
+        let edited = set_dependency_version_in_source(source, "_/sqlite", "0.2.1").unwrap();
+        assert_eq!(edited, source.replace(r#"=   "0.1.0""#, r#"=   "0.2.1""#));
+        assert!(set_dependency_version_in_source(source, "_/wasi-only", "2.0.0").is_none());
+        assert!(set_dependency_version_in_source(source, "version", "2.0.0").is_none());
+    }
 }
 
 #[cfg(test)]
@@ -358,4 +914,233 @@ module = "mod"
             Some(&"0.0.0-unstable".to_string())
         )
     }
+
+    #[test]
+    fn exports_test() {
+        let manifest_str = r#"
+[package]
+name = "test"
+version = "0.0.0"
+description = "This is a test library"
+
+[[module]]
+name = "mod"
+source = "mod.wasm"
+
+[[module.exports]]
+name = "calculator"
+version = "0.1.0"
+path = "calculator.wai"
+"#;
+        let manifest: Manifest = toml::from_str(manifest_str).unwrap();
+        let exports = manifest.module.as_ref().unwrap()[0]
+            .exports
+            .as_ref()
+            .unwrap();
+        assert_eq!(exports[0].name, "calculator");
+        assert_eq!(exports[0].path, PathBuf::from("calculator.wai"));
+
+        // the manifest can be written back out
+        let manifest: Manifest = toml::from_str(&manifest.to_string().unwrap()).unwrap();
+        assert_eq!(
+            manifest.module.unwrap()[0].exports.as_ref().unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn interface_package_test() {
+        let manifest_str = r#"
+[package]
+name = "test"
+version = "0.2.0"
+description = "This is a test interface"
+
+[[interface]]
+name = "calculator"
+path = "calculator.wasm_interface"
+"#;
+        let manifest: Manifest = toml::from_str(manifest_str).unwrap();
+        assert!(manifest.validate().is_ok());
+        assert_eq!(manifest.package_kind(), PackageKind::Interface);
+
+        let with_module = format!(
+            "{}\n[[module]]\nname = \"mod\"\nsource = \"mod.wasm\"\n",
+            manifest_str
+        );
+        let manifest: Manifest = toml::from_str(&with_module).unwrap();
+        assert!(manifest.validate().is_err());
+
+        let duplicated = format!(
+            "{}\n[[interface]]\nname = \"calculator\"\npath = \"other.wasm_interface\"\n",
+            manifest_str
+        );
+        let manifest: Manifest = toml::from_str(&duplicated).unwrap();
+        assert!(manifest.validate().is_err());
+    }
+}
+
+#[cfg(test)]
+mod target_tests {
+    use crate::abi::Abi;
+    use crate::data::manifest::{Manifest, ManifestError, TargetCondition};
+
+    #[test]
+    fn target_dependencies_for_abi() {
+        let manifest_str = r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+
+[[module]]
+name = "test"
+source = "test.wasm"
+abi = "emscripten"
+
+[target.'abi = "emscripten"'.dependencies]
+"_/emscripten-polyfill" = "1.0.0"
+
+[target.'abi = "wasi"'.dependencies]
+"_/wasi-polyfill" = "1.0.0"
+"#;
+        let manifest: Manifest = toml::from_str(manifest_str).unwrap();
+        manifest.validate().unwrap();
+        assert_eq!(
+            manifest.target_dependencies().unwrap(),
+            vec![("abi = \"emscripten\"", "_/emscripten-polyfill", "1.0.0")]
+        );
+        assert_eq!(
+            TargetCondition::parse("abi = \"wasi\"").unwrap(),
+            TargetCondition::Abi(Abi::Wasi)
+        );
+        assert!(TargetCondition::parse("os = \"linux\"").is_err());
+        match TargetCondition::parse("feature = \"simd\"") {
+            Err(ManifestError::UnsupportedTargetCondition(_)) => {}
+            other => panic!("expected an unsupported condition, got {:?}", other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod pin_tests {
+    use crate::data::manifest::Manifest;
+    use chrono::NaiveDate;
+    use std::path::Path;
+
+    #[test]
+    fn pins_replace_requirements_and_expire() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+
+[dependencies]
+"_/sqlite" = "^2.0"
+
+[pins]
+"_/sqlite" = { version = "=1.4.2", until = "2025-01-01", reason = "regression in 2.x" }
+"#,
+        )
+        .unwrap();
+        manifest.validate().unwrap();
+        assert_eq!(
+            manifest.dependency_requirement("_/sqlite", "^2.0"),
+            "=1.4.2"
+        );
+        assert_eq!(manifest.dependency_requirement("_/other", "^2.0"), "^2.0");
+        assert!(manifest
+            .expired_pins(NaiveDate::from_ymd_opt(2024, 12, 31).unwrap())
+            .is_empty());
+        let expired = manifest.expired_pins(NaiveDate::from_ymd_opt(2025, 1, 1).unwrap());
+        assert_eq!(expired.len(), 1);
+        assert_eq!(
+            expired[0].1.describe(expired[0].0),
+            "_/sqlite is pinned to =1.4.2 until 2025-01-01 (regression in 2.x)"
+        );
+
+        let mut invalid = manifest.clone();
+        invalid
+            .pins
+            .as_mut()
+            .unwrap()
+            .get_mut("_/sqlite")
+            .unwrap()
+            .until = Some("next year".to_string());
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn commands_describe_themselves() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+[package]
+name = "user/calc"
+version = "1.0.0"
+description = "A calculator"
+
+[[command]]
+name = "calc"
+module = "calc"
+description = "Evaluate an expression"
+usage = "[--precision N] <EXPRESSION>"
+examples = ["calc \"1 + 2\"", "--precision 2 \"1 / 3\""]
+
+[[command]]
+name = "repl"
+module = "calc"
+"#,
+        )
+        .unwrap();
+        let commands = manifest.command.unwrap();
+        assert_eq!(
+            commands[0].help().unwrap(),
+            "calc - Evaluate an expression
+
+USAGE:
+    wapm run calc [--precision N] <EXPRESSION>
+
+EXAMPLES:
+    wapm run calc \"1 + 2\"
+    wapm run calc --precision 2 \"1 / 3\"
+
+To pass --help to the command itself, run `wapm run calc -- --help`
+"
+        );
+        assert_eq!(commands[1].help(), None);
+    }
+
+    #[test]
+    fn translations_fall_back_to_english() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+[package]
+name = "user/calc"
+version = "1.0.0"
+description = "A calculator"
+readme = "README.md"
+
+[package.localized.es]
+description = "Una calculadora"
+
+[package.localized.fr]
+readme = "README.fr.md"
+"#,
+        )
+        .unwrap();
+        let package = &manifest.package;
+        assert_eq!(package.description_in("es"), "Una calculadora");
+        assert_eq!(package.description_in("fr"), "A calculator");
+        assert_eq!(package.description_in("de"), "A calculator");
+        assert_eq!(package.readme_in("es"), Some(Path::new("README.md")));
+        assert_eq!(package.readme_in("fr"), Some(Path::new("README.fr.md")));
+        assert_eq!(
+            package.all_readmes().collect::<Vec<_>>(),
+            [Path::new("README.md"), Path::new("README.fr.md")]
+        );
+        let published = toml::to_string(&manifest).unwrap();
+        assert!(published.contains("[package.localized.es]"));
+    }
 }
@@ -0,0 +1,323 @@
+//! The keys `wapm.toml` understands. Keys the manifest doesn't know are silently ignored when it
+//! is parsed, so typos like `licence` or `[comand]` go unnoticed; `wapm validate --strict` and
+//! `wapm publish` check the keys against this list instead.
+
+use std::fmt;
+
+/// What a key of the manifest holds
+#[derive(Clone, Copy)]
+enum Shape {
+    /// A value whose contents aren't checked
+    Value,
+    /// A table with the given keys
+    Table(&'static [(&'static str, Shape)]),
+    /// An array of tables with the given keys
+    Tables(&'static [(&'static str, Shape)]),
+    /// A table with arbitrary keys, like package names, whose values have the given shape
+    Map(&'static Shape),
+}
+
+const ROOT: &[(&str, Shape)] = &[
+    ("package", Shape::Table(PACKAGE)),
+    ("dependencies", Shape::Map(&Shape::Value)),
+    ("target", Shape::Map(&Shape::Table(TARGET))),
+    ("pins", Shape::Map(&Shape::Table(PIN))),
+    ("alias", Shape::Map(&Shape::Value)),
+    ("warnings", Shape::Table(WARNINGS)),
+    ("module", Shape::Tables(MODULE)),
+    ("command", Shape::Tables(COMMAND)),
+    ("interface", Shape::Tables(INTERFACE)),
+    ("fs", Shape::Map(&Shape::Value)),
+    ("workspace", Shape::Table(WORKSPACE)),
+];
+
+const PACKAGE: &[(&str, Shape)] = &[
+    ("name", Shape::Value),
+    ("version", Shape::Value),
+    ("description", Shape::Value),
+    ("authors", Shape::Value),
+    ("license", Shape::Value),
+    ("license-file", Shape::Value),
+    ("readme", Shape::Value),
+    ("repository", Shape::Value),
+    ("homepage", Shape::Value),
+    ("documentation", Shape::Value),
+    ("keywords", Shape::Value),
+    ("categories", Shape::Value),
+    ("moved-to", Shape::Value),
+    ("wasmer-extra-flags", Shape::Value),
+    ("packages-dir", Shape::Value),
+    ("disable-command-rename", Shape::Value),
+    ("rename-commands-to-raw-command-name", Shape::Value),
+    ("build", Shape::Table(&[("command", Shape::Value)])),
+    (
+        "ipfs",
+        Shape::Table(&[("cid", Shape::Value), ("sha256", Shape::Value)]),
+    ),
+    (
+        "localized",
+        Shape::Map(&Shape::Table(&[
+            ("description", Shape::Value),
+            ("readme", Shape::Value),
+        ])),
+    ),
+    ("symlinks", Shape::Value),
+];
+
+const TARGET: &[(&str, Shape)] = &[("dependencies", Shape::Map(&Shape::Value))];
+
+const PIN: &[(&str, Shape)] = &[
+    ("version", Shape::Value),
+    ("until", Shape::Value),
+    ("reason", Shape::Value),
+];
+
+/// The categories of `diagnostics::Warning`
+const WARNINGS: &[(&str, Shape)] = &[
+    ("deprecated-field", Shape::Value),
+    ("missing-readme", Shape::Value),
+    ("large-package", Shape::Value),
+    ("command-collision", Shape::Value),
+    ("expired-pin", Shape::Value),
+    ("abi-mismatch", Shape::Value),
+    ("non-portable-path", Shape::Value),
+];
+
+const MODULE: &[(&str, Shape)] = &[
+    ("name", Shape::Value),
+    ("source", Shape::Value),
+    ("abi", Shape::Value),
+    ("fs", Shape::Value),
+    ("interfaces", Shape::Map(&Shape::Value)),
+    (
+        "exports",
+        Shape::Tables(&[
+            ("name", Shape::Value),
+            ("version", Shape::Value),
+            ("path", Shape::Value),
+        ]),
+    ),
+];
+
+const COMMAND: &[(&str, Shape)] = &[
+    ("name", Shape::Value),
+    ("module", Shape::Value),
+    ("main_args", Shape::Value),
+    ("package", Shape::Value),
+    ("description", Shape::Value),
+    ("usage", Shape::Value),
+    ("examples", Shape::Value),
+];
+
+const INTERFACE: &[(&str, Shape)] = &[("name", Shape::Value), ("path", Shape::Value)];
+
+const WORKSPACE: &[(&str, Shape)] = &[
+    ("members", Shape::Value),
+    (
+        "package",
+        Shape::Table(&[
+            ("version", Shape::Value),
+            ("license", Shape::Value),
+            ("authors", Shape::Value),
+            ("repository", Shape::Value),
+        ]),
+    ),
+];
+
+/// Keys that older versions of wapm read and that are now ignored, with what to do about them
+const DEPRECATED: &[(&str, &str)] = &[
+    ("module.module", "the module file is set with `source`"),
+    (
+        "module.description",
+        "modules are described by the package description",
+    ),
+];
+
+/// A key of the manifest that wapm doesn't use
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyProblem {
+    Unknown {
+        key: String,
+        suggestion: Option<String>,
+    },
+    Deprecated {
+        key: String,
+        note: &'static str,
+    },
+}
+
+impl KeyProblem {
+    pub fn is_unknown(&self) -> bool {
+        match self {
+            KeyProblem::Unknown { .. } => true,
+            KeyProblem::Deprecated { .. } => false,
+        }
+    }
+}
+
+impl fmt::Display for KeyProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyProblem::Unknown {
+                key,
+                suggestion: Some(suggestion),
+            } => write!(f, "unknown key `{}`, did you mean `{}`?", key, suggestion),
+            KeyProblem::Unknown { key, .. } => write!(f, "unknown key `{}`", key),
+            KeyProblem::Deprecated { key, note } => {
+                write!(f, "deprecated key `{}`, {}", key, note)
+            }
+        }
+    }
+}
+
+/// The keys of a manifest that are unknown or deprecated, sorted by key
+pub fn check_keys(manifest: &toml::Value) -> Vec<KeyProblem> {
+    let mut problems = vec![];
+    check_table(manifest, ROOT, "", "", &mut problems);
+    problems
+}
+
+/// Check the keys of a table. `path` is the location shown to the user, like `command[1]`,
+/// and `schema_path` the one deprecated keys are listed under, like `command`.
+fn check_table(
+    value: &toml::Value,
+    keys: &[(&str, Shape)],
+    path: &str,
+    schema_path: &str,
+    problems: &mut Vec<KeyProblem>,
+) {
+    let table = match value.as_table() {
+        Some(table) => table,
+        // values of the wrong type are reported when the manifest is parsed
+        None => return,
+    };
+    for (key, value) in table {
+        let key_path = join_key(path, key);
+        let schema_key_path = join_key(schema_path, key);
+        match keys.iter().find(|(name, _)| name == key) {
+            Some((_, shape)) => check_value(value, *shape, &key_path, &schema_key_path, problems),
+            None => match DEPRECATED.iter().find(|(name, _)| *name == schema_key_path) {
+                Some((_, note)) => problems.push(KeyProblem::Deprecated {
+                    key: key_path,
+                    note,
+                }),
+                None => problems.push(KeyProblem::Unknown {
+                    key: key_path,
+                    suggestion: suggest(key, keys),
+                }),
+            },
+        }
+    }
+}
+
+fn check_value(
+    value: &toml::Value,
+    shape: Shape,
+    path: &str,
+    schema_path: &str,
+    problems: &mut Vec<KeyProblem>,
+) {
+    match shape {
+        Shape::Value => {}
+        Shape::Table(keys) => check_table(value, keys, path, schema_path, problems),
+        Shape::Tables(keys) => {
+            for (index, table) in value.as_array().into_iter().flatten().enumerate() {
+                let table_path = format!("{}[{}]", path, index);
+                check_table(table, keys, &table_path, schema_path, problems);
+            }
+        }
+        Shape::Map(shape) => {
+            for (key, value) in value.as_table().into_iter().flatten() {
+                let entry_path = format!("{}.{}", path, quote_key(key));
+                check_value(value, *shape, &entry_path, schema_path, problems);
+            }
+        }
+    }
+}
+
+fn join_key(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Keys like package names need quotes to be a valid key path
+fn quote_key(key: &str) -> String {
+    let is_bare = key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if is_bare {
+        key.to_string()
+    } else {
+        format!("{:?}", key)
+    }
+}
+
+/// The known key closest to a misspelled one, if it is close enough to be a typo
+fn suggest(key: &str, keys: &[(&str, Shape)]) -> Option<String> {
+    let max_distance = (key.chars().count() / 3).max(1);
+    keys.iter()
+        .map(|(name, _)| (strsim::damerau_levenshtein(key, name), *name))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, name)| name.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn typos_and_deprecated_keys_are_found() {
+        let manifest: toml::Value = toml::from_str(
+            r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "test"
+licence = "MIT"
+
+[dependencies]
+"_/sqlite" = "0.1.0"
+
+[[module]]
+name = "test"
+source = "test.wasm"
+module = "test.wasm"
+interfaces = { "wasi" = "0.0.0-unstable" }
+
+[[command]]
+name = "test"
+module = "test"
+main-args = "--verbose"
+
+[comand]
+name = "other"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            check_keys(&manifest),
+            vec![
+                KeyProblem::Unknown {
+                    key: "comand".to_string(),
+                    suggestion: Some("command".to_string()),
+                },
+                KeyProblem::Unknown {
+                    key: "command[0].main-args".to_string(),
+                    suggestion: Some("main_args".to_string()),
+                },
+                KeyProblem::Deprecated {
+                    key: "module[0].module".to_string(),
+                    note: "the module file is set with `source`",
+                },
+                KeyProblem::Unknown {
+                    key: "package.licence".to_string(),
+                    suggestion: Some("license".to_string()),
+                },
+            ]
+        );
+    }
+}
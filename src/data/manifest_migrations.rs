@@ -0,0 +1,157 @@
+//! Rewriting manifests written for older versions of wapm to the current schema: renamed keys
+//! get their new names, keys that are no longer read are taken out and required keys that were
+//! once optional are added. The manifest is edited in place, so its comments, key order and
+//! formatting are kept.
+
+use toml_edit::{DocumentMut, Item, Key, Table};
+
+/// Keys of `[package]` that used to be written in snake case, with their current names
+const RENAMED_PACKAGE_KEYS: &[(&str, &str)] = &[
+    ("license_file", "license-file"),
+    ("moved_to", "moved-to"),
+    ("wasmer_extra_flags", "wasmer-extra-flags"),
+    ("packages_dir", "packages-dir"),
+    ("disable_command_rename", "disable-command-rename"),
+    (
+        "rename_commands_to_raw_command_name",
+        "rename-commands-to-raw-command-name",
+    ),
+];
+
+#[derive(Debug, Fail)]
+pub enum MigrationError {
+    #[fail(display = "Could not parse the manifest: {}", _0)]
+    InvalidToml(String),
+}
+
+/// The manifest rewritten to the current schema and a description of each change, which is
+/// empty when the manifest is up to date
+pub fn migrate(source: &str) -> Result<(String, Vec<String>), MigrationError> {
+    let mut document: DocumentMut = source
+        .parse()
+        .map_err(|e: toml_edit::TomlError| MigrationError::InvalidToml(e.to_string()))?;
+    let mut changes = vec![];
+    if let Some(package) = document.get_mut("package").and_then(Item::as_table_mut) {
+        migrate_package(package, &mut changes);
+    }
+    if let Some(modules) = document
+        .get_mut("module")
+        .and_then(Item::as_array_of_tables_mut)
+    {
+        for (index, module) in modules.iter_mut().enumerate() {
+            migrate_module(module, index, &mut changes);
+        }
+    }
+    if changes.is_empty() {
+        return Ok((source.to_string(), changes));
+    }
+    Ok((document.to_string(), changes))
+}
+
+fn migrate_package(package: &mut Table, changes: &mut Vec<String>) {
+    for (old, new) in RENAMED_PACKAGE_KEYS {
+        if !package.contains_key(old) {
+            continue;
+        }
+        if package.contains_key(new) {
+            package.remove(old);
+            changes.push(format!(
+                "removed `package.{}`, `package.{}` is already set",
+                old, new
+            ));
+        } else {
+            rename_key(package, old, new);
+            changes.push(format!("renamed `package.{}` to `package.{}`", old, new));
+        }
+    }
+    if !package.contains_key("description") {
+        package.insert("description", toml_edit::value(""));
+        changes.push("added the now required `package.description`".to_string());
+    }
+}
+
+fn migrate_module(module: &mut Table, index: usize, changes: &mut Vec<String>) {
+    if module.contains_key("module") {
+        if module.contains_key("source") {
+            module.remove("module");
+            changes.push(format!(
+                "removed `module[{}].module`, the module file is set with `source`",
+                index
+            ));
+        } else {
+            rename_key(module, "module", "source");
+            changes.push(format!(
+                "renamed `module[{}].module` to `module[{}].source`",
+                index, index
+            ));
+        }
+    }
+    if module.remove("description").is_some() {
+        changes.push(format!(
+            "removed `module[{}].description`, modules are described by the package description",
+            index
+        ));
+    }
+}
+
+/// Rename a key of a table, keeping its place, its value and their formatting
+fn rename_key(table: &mut Table, old: &str, new: &str) {
+    let order: Vec<String> = table
+        .iter()
+        .map(|(key, _)| if key == old { new } else { key }.to_string())
+        .collect();
+    let (key, item) = match table.remove_entry(old) {
+        Some(entry) => entry,
+        None => return,
+    };
+    let renamed = Key::new(new)
+        .with_leaf_decor(key.leaf_decor().clone())
+        .with_dotted_decor(key.dotted_decor().clone());
+    table.insert_formatted(&renamed, item);
+    let position = |key: &Key| order.iter().position(|name| name == key.get());
+    table.sort_values_by(|key1, _, key2, _| position(key1).cmp(&position(key2)));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn old_manifests_are_migrated_in_place() {
+        let source = r#"# my package
+[package]
+name = "user/pkg"   # the name
+version = "0.1.0"
+license_file = "LICENSE.txt"
+repository = "https://example.com"
+
+[[module]]
+name = "pkg"
+module = "pkg.wasm"
+description = "the module"
+abi = "wasi"
+"#;
+        let (migrated, changes) = migrate(source).unwrap();
+        assert_eq!(
+            migrated,
+            r#"# my package
+[package]
+name = "user/pkg"   # the name
+version = "0.1.0"
+license-file = "LICENSE.txt"
+repository = "https://example.com"
+description = ""
+
+[[module]]
+name = "pkg"
+source = "pkg.wasm"
+abi = "wasi"
+"#
+        );
+        assert_eq!(changes.len(), 4);
+
+        let (again, changes) = migrate(&migrated).unwrap();
+        assert_eq!(again, migrated);
+        assert!(changes.is_empty());
+    }
+}
@@ -3,4 +3,8 @@
 //! respectively.
 pub mod lock;
 pub mod manifest;
+pub mod manifest_keys;
+pub mod manifest_migrations;
+pub mod toolchain;
 pub mod wax_index;
+pub mod workspace;
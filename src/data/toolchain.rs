@@ -0,0 +1,189 @@
+//! A `wapm-toolchain.toml` pins the versions of wapm and of the runtime that a project needs, so
+//! everyone working on it, and CI, uses compatible versions.
+//!
+//! ```toml
+//! wapm = "^0.5"
+//! runtime = ">=0.16.0"
+//! # refuse to run commands instead of warning when the pins are not satisfied
+//! strict = true
+//! ```
+
+use crate::util::get_runtime_with_args;
+use semver::{Version, VersionReq};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const TOOLCHAIN_FILE_NAME: &str = "wapm-toolchain.toml";
+
+/// The version requirements of a `wapm-toolchain.toml`
+#[derive(Clone, Debug, Deserialize)]
+pub struct ToolchainPins {
+    /// The required version of wapm
+    pub wapm: Option<VersionReq>,
+    /// The required version of the runtime that runs commands, `wasmer` unless `WAPM_RUNTIME`
+    /// says otherwise
+    pub runtime: Option<VersionReq>,
+    /// Refuse to run commands when the pins are not satisfied, instead of warning
+    #[serde(default)]
+    pub strict: bool,
+    /// The location of the file
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+/// A pinned tool whose version does not satisfy the pin
+#[derive(Clone, Debug, PartialEq)]
+pub struct PinMismatch {
+    pub tool: String,
+    pub required: VersionReq,
+    /// The version found, or `None` if the tool is not installed or its version is unknown
+    pub found: Option<Version>,
+}
+
+#[derive(Debug, Fail)]
+pub enum ToolchainError {
+    #[fail(display = "Could not parse \"{}\": {}", _0, _1)]
+    TomlParseError(String, String),
+    #[fail(
+        display = "The toolchain pinned in \"{}\" is not installed: {}. Run `wapm doctor` for how to fix it",
+        _0, _1
+    )]
+    PinsNotSatisfied(String, String),
+}
+
+impl fmt::Display for PinMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.found {
+            Some(found) => write!(
+                f,
+                "{} {} does not match {}",
+                self.tool, found, self.required
+            ),
+            None => write!(f, "{} {} was not found", self.tool, self.required),
+        }
+    }
+}
+
+impl ToolchainPins {
+    /// Find the `wapm-toolchain.toml` of the directory or of its closest parent directory
+    pub fn find_for_directory(directory: &Path) -> Result<Option<Self>, ToolchainError> {
+        let path = match directory
+            .ancestors()
+            .map(|dir| dir.join(TOOLCHAIN_FILE_NAME))
+            .find(|path| path.is_file())
+        {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let source = fs::read_to_string(&path).map_err(|e| {
+            ToolchainError::TomlParseError(path.to_string_lossy().to_string(), e.to_string())
+        })?;
+        let mut pins: Self = toml::from_str(&source).map_err(|e| {
+            ToolchainError::TomlParseError(path.to_string_lossy().to_string(), e.to_string())
+        })?;
+        pins.path = path;
+        Ok(Some(pins))
+    }
+
+    /// The pins that the installed versions don't satisfy
+    pub fn mismatches(&self, wapm_version: &Version, runtime: &str) -> Vec<PinMismatch> {
+        let mut mismatches = vec![];
+        if let Some(required) = &self.wapm {
+            if !required.matches(wapm_version) {
+                mismatches.push(PinMismatch {
+                    tool: "wapm".to_owned(),
+                    required: required.clone(),
+                    found: Some(wapm_version.clone()),
+                });
+            }
+        }
+        if let Some(required) = &self.runtime {
+            let found = installed_version(runtime);
+            if !found.as_ref().map(|v| required.matches(v)).unwrap_or(false) {
+                mismatches.push(PinMismatch {
+                    tool: runtime.to_owned(),
+                    required: required.clone(),
+                    found,
+                });
+            }
+        }
+        mismatches
+    }
+}
+
+/// Check the pins of the directory, warning about mismatches or, for strict pins, refusing
+pub fn check_toolchain_pins(directory: &Path) -> Result<(), ToolchainError> {
+    let pins = match ToolchainPins::find_for_directory(directory)? {
+        Some(pins) => pins,
+        None => return Ok(()),
+    };
+    let (runtime, _) = get_runtime_with_args();
+    let mismatches = pins.mismatches(&wapm_version(), &runtime);
+    if mismatches.is_empty() {
+        return Ok(());
+    }
+    let description = mismatches
+        .iter()
+        .map(PinMismatch::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    if pins.strict {
+        return Err(ToolchainError::PinsNotSatisfied(
+            pins.path.to_string_lossy().to_string(),
+            description,
+        ));
+    }
+    warn!(
+        "The toolchain pinned in \"{}\" is not installed: {}. Run `wapm doctor` for how to fix it",
+        pins.path.display(),
+        description
+    );
+    Ok(())
+}
+
+/// The version of this wapm
+pub fn wapm_version() -> Version {
+    Version::parse(env!("CARGO_PKG_VERSION")).expect("the crate version is a valid version")
+}
+
+/// The version that a program reports with `--version`, e.g. `wasmer 0.16.2`
+pub fn installed_version(program: &str) -> Option<Version> {
+    let output = Command::new(program).arg("--version").output().ok()?;
+    parse_version_output(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_version_output(output: &str) -> Option<Version> {
+    output
+        .split_whitespace()
+        .map(|word| word.trim_start_matches('v'))
+        .find_map(|word| Version::parse(word).ok())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_versions() {
+        assert_eq!(
+            parse_version_output("wasmer 0.16.2\n"),
+            Some(Version::new(0, 16, 2))
+        );
+        assert_eq!(
+            parse_version_output("tool v1.2.3"),
+            Some(Version::new(1, 2, 3))
+        );
+        assert_eq!(parse_version_output("unknown"), None);
+    }
+
+    #[test]
+    fn find_wapm_mismatch() {
+        let pins: ToolchainPins = toml::from_str("wapm = \"^0.6\"").unwrap();
+        let mismatches = pins.mismatches(&Version::new(0, 5, 0), "wasmer");
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].to_string(), "wapm 0.5.0 does not match ^0.6");
+        assert!(pins.mismatches(&Version::new(0, 6, 1), "wasmer").is_empty());
+    }
+}
@@ -0,0 +1,274 @@
+//! A workspace is a set of packages in member directories that are managed together from a
+//! `wapm.toml` with a `[workspace]` section in the root directory.
+use crate::data::manifest::MANIFEST_FILE_NAME;
+use semver::Version;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The fields of `[package]` that members can inherit from `[workspace.package]` by setting
+/// them to `{ workspace = true }`
+pub const INHERITABLE_FIELDS: &[&str] = &["version", "license", "authors", "repository"];
+
+/// The `[workspace]` section of a root manifest
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Workspace {
+    /// The directories of the member packages, relative to the workspace root
+    pub members: Vec<PathBuf>,
+    /// Metadata shared by the members
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<WorkspacePackage>,
+    /// private data
+    /// store the directory path of the root manifest
+    #[serde(skip)]
+    pub root: PathBuf,
+}
+
+/// The `[workspace.package]` section of a root manifest
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct WorkspacePackage {
+    pub version: Option<Version>,
+    pub license: Option<String>,
+    pub authors: Option<Vec<String>>,
+    pub repository: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkspaceManifest {
+    workspace: Option<Workspace>,
+}
+
+impl Workspace {
+    /// Find a workspace defined by the manifest in the specified directory, if there is one
+    pub fn find_in_directory<P: AsRef<Path>>(directory: P) -> Result<Option<Self>, WorkspaceError> {
+        let directory = directory.as_ref();
+        let manifest_path = directory.join(MANIFEST_FILE_NAME);
+        let contents = match fs::read_to_string(&manifest_path) {
+            Ok(contents) => contents,
+            Err(_) => return Ok(None),
+        };
+        let workspace_manifest: WorkspaceManifest =
+            toml::from_str(&contents).map_err(|e| WorkspaceError::TomlParseError(e.to_string()))?;
+        match workspace_manifest.workspace {
+            Some(mut workspace) => {
+                workspace.root = directory.to_owned();
+                workspace.validate()?;
+                Ok(Some(workspace))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), WorkspaceError> {
+        for member_directory in self.member_directories() {
+            if !member_directory.join(MANIFEST_FILE_NAME).is_file() {
+                return Err(WorkspaceError::MissingMemberManifest(
+                    member_directory.to_string_lossy().to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The full paths of the member directories
+    pub fn member_directories(&self) -> Vec<PathBuf> {
+        self.members
+            .iter()
+            .map(|member| self.root.join(member))
+            .collect()
+    }
+
+    /// Find the workspace that a directory is a member of by searching its parent directories.
+    /// The root of a workspace counts as a member.
+    pub fn find_for_member(directory: &Path) -> Result<Option<Self>, WorkspaceError> {
+        let directory = canonicalize(directory);
+        for ancestor in directory.ancestors() {
+            if let Some(workspace) = Self::find_in_directory(ancestor)? {
+                let is_member = ancestor == directory
+                    || workspace
+                        .member_directories()
+                        .iter()
+                        .any(|member_directory| canonicalize(member_directory) == directory);
+                if is_member {
+                    return Ok(Some(workspace));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// The value of a field in `[workspace.package]`
+    fn package_field(&self, field: &str) -> Option<toml::Value> {
+        let package = self.package.as_ref()?;
+        let value = match field {
+            "version" => toml::Value::try_from(package.version.as_ref()?),
+            "license" => toml::Value::try_from(package.license.as_ref()?),
+            "authors" => toml::Value::try_from(package.authors.as_ref()?),
+            "repository" => toml::Value::try_from(package.repository.as_ref()?),
+            _ => return None,
+        };
+        value.ok()
+    }
+}
+
+fn canonicalize(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())
+}
+
+/// The value that marks a field as inherited from the workspace
+pub fn inherited_field_marker() -> toml::Value {
+    let mut marker = toml::value::Table::new();
+    marker.insert("workspace".to_owned(), toml::Value::Boolean(true));
+    toml::Value::Table(marker)
+}
+
+fn is_inherited_field_marker(value: &toml::Value) -> bool {
+    value
+        .get("workspace")
+        .and_then(toml::Value::as_bool)
+        .unwrap_or(false)
+}
+
+/// Parse the manifest of a package in the specified directory, replacing the `[package]` fields
+/// marked with `workspace = true` with the values from the workspace the package is a member
+/// of. The names of the inherited fields are returned with the manifest.
+pub fn inherit_workspace_package(
+    directory: &Path,
+    source: &str,
+) -> Result<(toml::Value, Vec<String>), WorkspaceError> {
+    let mut manifest: toml::Value =
+        toml::from_str(source).map_err(|e| WorkspaceError::TomlParseError(e.to_string()))?;
+    let package = match manifest
+        .get_mut("package")
+        .and_then(toml::Value::as_table_mut)
+    {
+        Some(package) => package,
+        None => return Ok((manifest, vec![])),
+    };
+    let inherited_fields: Vec<String> = package
+        .iter()
+        .filter(|(_, value)| is_inherited_field_marker(value))
+        .map(|(field, _)| field.clone())
+        .collect();
+    if inherited_fields.is_empty() {
+        return Ok((manifest, inherited_fields));
+    }
+    let workspace = Workspace::find_for_member(directory)?.ok_or_else(|| {
+        WorkspaceError::NotAWorkspaceMember(directory.to_string_lossy().to_string())
+    })?;
+    for field in inherited_fields.iter() {
+        if !INHERITABLE_FIELDS.contains(&field.as_str()) {
+            return Err(WorkspaceError::FieldNotInheritable(
+                field.clone(),
+                INHERITABLE_FIELDS.join(", "),
+            ));
+        }
+        let value = workspace
+            .package_field(field)
+            .ok_or_else(|| WorkspaceError::MissingInheritedField(field.clone()))?;
+        package.insert(field.clone(), value);
+    }
+    Ok((manifest, inherited_fields))
+}
+
+/// A manifest with a `[workspace]` section and no `[package]` section only describes a
+/// workspace, it is not a package itself.
+pub fn is_workspace_only_manifest(source: &str) -> bool {
+    match toml::from_str::<toml::Value>(source) {
+        Ok(value) => value.get("workspace").is_some() && value.get("package").is_none(),
+        Err(_) => false,
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum WorkspaceError {
+    #[fail(display = "Could not parse workspace manifest because {}.", _0)]
+    TomlParseError(String),
+    #[fail(display = "Workspace member \"{}\" does not have a manifest", _0)]
+    MissingMemberManifest(String),
+    #[fail(
+        display = "The package in \"{}\" inherits fields from a workspace but is not a member of one",
+        _0
+    )]
+    NotAWorkspaceMember(String),
+    #[fail(
+        display = "The field \"{}\" can not be inherited from the workspace, only {} can",
+        _0, _1
+    )]
+    FieldNotInheritable(String, String),
+    #[fail(
+        display = "The field \"{}\" is inherited but it is missing from `[workspace.package]`",
+        _0
+    )]
+    MissingInheritedField(String),
+}
+
+#[cfg(test)]
+mod test {
+    use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
+    use crate::data::workspace::{inherit_workspace_package, is_workspace_only_manifest};
+    use semver::Version;
+
+    #[test]
+    fn detect_workspace_only_manifest() {
+        let workspace_only = r#"
+[workspace]
+members = ["app", "lib"]
+"#;
+        assert!(is_workspace_only_manifest(workspace_only));
+
+        let package_and_workspace = r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "description"
+
+[workspace]
+members = ["lib"]
+"#;
+        assert!(!is_workspace_only_manifest(package_and_workspace));
+    }
+
+    #[test]
+    fn inherit_fields_from_workspace() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let root = tmp_dir.path();
+        let member = root.join("member");
+        std::fs::create_dir(&member).unwrap();
+        std::fs::write(
+            root.join(MANIFEST_FILE_NAME),
+            r#"
+[workspace]
+members = ["member"]
+
+[workspace.package]
+version = "1.2.3"
+license = "MIT"
+"#,
+        )
+        .unwrap();
+        let member_manifest = r#"
+[package]
+name = "member"
+description = "description"
+version = { workspace = true }
+license = { workspace = true }
+"#;
+        std::fs::write(member.join(MANIFEST_FILE_NAME), member_manifest).unwrap();
+
+        let manifest = Manifest::from_source(&member, member_manifest).unwrap();
+        assert_eq!(manifest.package.version, Version::parse("1.2.3").unwrap());
+        assert_eq!(manifest.package.license, Some("MIT".to_owned()));
+        assert_eq!(manifest.inherited_fields, vec!["license", "version"]);
+
+        // the inherited fields still refer to the workspace when the manifest is saved
+        let saved: toml::Value = toml::from_str(&manifest.to_string().unwrap()).unwrap();
+        assert_eq!(
+            saved["package"]["license"]["workspace"].as_bool(),
+            Some(true)
+        );
+        assert_eq!(saved["package"]["name"].as_str(), Some("member"));
+
+        let not_inheritable = member_manifest.replace("license =", "homepage =");
+        assert!(inherit_workspace_package(&member, &not_inheritable).is_err());
+    }
+}
@@ -1,7 +1,7 @@
-use crate::data::manifest::PACKAGES_DIR_NAME;
+use crate::util::get_packages_dir;
 use std::fs;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub const BIN_DIR_NAME: &str = ".bin";
 
@@ -13,34 +13,65 @@ pub enum Error {
     FileCreationError(String, String),
 }
 
+/// The `wapm run` options for running a command with a sandbox profile
+fn sandbox_flag(sandbox: Option<&str>) -> String {
+    sandbox
+        .map(|profile| format!("--sandbox {} ", profile))
+        .unwrap_or_default()
+}
+
+/// The name of the bin script file of a command
 #[cfg(not(target_os = "windows"))]
-pub fn save_bin_script<P: AsRef<Path>>(directory: P, command_name: String) -> Result<(), Error> {
-    let data = format!("#!/bin/bash\nwapm run {} \"$@\"\n", command_name);
-    save(data, directory, command_name)
+pub fn bin_script_file_name(command_name: &str) -> String {
+    command_name.to_string()
 }
 
 #[cfg(target_os = "windows")]
-pub fn save_bin_script<P: AsRef<Path>>(directory: P, command_name: String) -> Result<(), Error> {
-    let data = format!("wapm run {} %*\n", command_name);
-    let file_name = format!("{}.cmd", command_name);
-    save(data, directory, file_name)
+pub fn bin_script_file_name(command_name: &str) -> String {
+    format!("{}.cmd", command_name)
 }
 
+/// The contents of the bin script of a command
 #[cfg(not(target_os = "windows"))]
-pub fn delete_bin_script<P: AsRef<Path>>(directory: P, command_name: String) -> Result<(), Error> {
-    delete(directory, command_name)
+pub fn bin_script_contents(command_name: &str, sandbox: Option<&str>) -> String {
+    format!(
+        "#!/bin/bash\nwapm run {}{} \"$@\"\n",
+        sandbox_flag(sandbox),
+        command_name
+    )
 }
 
 #[cfg(target_os = "windows")]
+pub fn bin_script_contents(command_name: &str, sandbox: Option<&str>) -> String {
+    format!("wapm run {}{} %*\n", sandbox_flag(sandbox), command_name)
+}
+
+/// Whether a file of the bin directory was written by wapm
+pub fn is_bin_script(contents: &str) -> bool {
+    contents.lines().any(|line| line.starts_with("wapm run "))
+}
+
+/// The directory with the bin scripts of the commands installed in `directory`
+pub fn bin_directory(directory: &Path) -> PathBuf {
+    get_packages_dir(directory).join(BIN_DIR_NAME)
+}
+
+pub fn save_bin_script<P: AsRef<Path>>(
+    directory: P,
+    command_name: String,
+    sandbox: Option<&str>,
+) -> Result<(), Error> {
+    let data = bin_script_contents(&command_name, sandbox);
+    save(data, directory, bin_script_file_name(&command_name))
+}
+
 pub fn delete_bin_script<P: AsRef<Path>>(directory: P, command_name: String) -> Result<(), Error> {
-    let file_name = format!("{}.cmd", command_name);
-    delete(directory, file_name)
+    delete(directory, bin_script_file_name(&command_name))
 }
 
 /// save the bin script for a command into the .bin directory
 fn save<P: AsRef<Path>>(data: String, directory: P, command_name: String) -> Result<(), Error> {
-    let mut dir = directory.as_ref().join(PACKAGES_DIR_NAME);
-    dir.push(BIN_DIR_NAME);
+    let dir = bin_directory(directory.as_ref());
     if !dir.exists() {
         fs::create_dir_all(&dir)
             .map_err(|e| Error::SaveError(command_name.clone(), e.to_string()))?;
@@ -75,8 +106,7 @@ fn save<P: AsRef<Path>>(data: String, directory: P, command_name: String) -> Res
 
 /// delete the bin script for a command - for cleanup during uninstall
 fn delete<P: AsRef<Path>>(directory: P, command_name: String) -> Result<(), Error> {
-    let mut dir = directory.as_ref().join(PACKAGES_DIR_NAME);
-    dir.push(BIN_DIR_NAME);
+    let dir = bin_directory(directory.as_ref());
     if !dir.exists() {
         Ok(())
     } else {
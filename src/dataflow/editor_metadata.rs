@@ -0,0 +1,206 @@
+//! Metadata about the installed packages for editors and language servers. It is written to
+//! `wapm_packages/.metadata.json` so tools can offer completion for commands and interfaces
+//! without re-implementing the resolver.
+
+use crate::abi::Abi;
+use crate::data::lock::lockfile::Lockfile;
+use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::util::get_packages_dir;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const METADATA_FILE_NAME: &str = ".metadata.json";
+/// Bumped when the format of the metadata changes in a way that breaks readers
+const METADATA_FORMAT_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Could not read the lockfile. {}", _0)]
+    LockfileError(String),
+    #[fail(display = "Could not write \"{}\". {}", _0, _1)]
+    WriteError(String, String),
+}
+
+#[derive(Debug, Serialize)]
+struct EditorMetadata {
+    format_version: u32,
+    packages: Vec<PackageMetadata>,
+    commands: Vec<CommandMetadata>,
+}
+
+#[derive(Debug, Serialize)]
+struct PackageMetadata {
+    name: String,
+    version: String,
+    /// The directory of the package
+    path: PathBuf,
+    /// Whether this is the package of the manifest rather than an installed dependency
+    local: bool,
+    modules: Vec<ModuleMetadata>,
+}
+
+#[derive(Debug, Serialize)]
+struct ModuleMetadata {
+    name: String,
+    abi: Abi,
+    /// The wasm file of the module
+    path: PathBuf,
+    interfaces: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CommandMetadata {
+    name: String,
+    package: String,
+    version: String,
+    module: String,
+    is_top_level_dependency: bool,
+}
+
+/// Write the metadata of the packages in the lockfile of the directory
+pub fn write_editor_metadata(directory: &Path) -> Result<(), Error> {
+    let lockfile = match LockfileResult::find_in_directory(directory) {
+        LockfileResult::Lockfile(lockfile) => lockfile,
+        LockfileResult::NoLockfile => return Ok(()),
+        LockfileResult::LockfileError(e) => return Err(Error::LockfileError(e.to_string())),
+    };
+    let metadata = metadata_from_lockfile(directory, &lockfile);
+    let packages_dir = get_packages_dir(directory);
+    let metadata_path = packages_dir.join(METADATA_FILE_NAME);
+    let write = || -> Result<(), failure::Error> {
+        fs::create_dir_all(&packages_dir)?;
+        fs::write(&metadata_path, serde_json::to_string_pretty(&metadata)?)?;
+        Ok(())
+    };
+    write()
+        .map_err(|e| Error::WriteError(metadata_path.to_string_lossy().to_string(), e.to_string()))
+}
+
+fn metadata_from_lockfile(directory: &Path, lockfile: &Lockfile) -> EditorMetadata {
+    let packages_dir = get_packages_dir(directory);
+    let mut packages = vec![];
+    for (package_name, versions) in lockfile.modules.iter() {
+        for (version, modules) in versions.iter() {
+            let local = modules.values().any(|module| module.resolved == "local");
+            let package_path = match modules.values().next() {
+                Some(_) if local => directory.to_path_buf(),
+                Some(module) => packages_dir.join(&module.package_path),
+                None => continue,
+            };
+            let interfaces = module_interfaces(&package_path);
+            packages.push(PackageMetadata {
+                name: package_name.clone(),
+                version: version.to_string(),
+                local,
+                modules: modules
+                    .values()
+                    .map(|module| ModuleMetadata {
+                        name: module.name.clone(),
+                        abi: module.abi,
+                        path: package_path.join(&module.source),
+                        interfaces: interfaces.get(&module.name).cloned().unwrap_or_default(),
+                    })
+                    .collect(),
+                path: package_path,
+            });
+        }
+    }
+    let commands = lockfile
+        .commands
+        .values()
+        .map(|command| CommandMetadata {
+            name: command.name.clone(),
+            package: command.package_name.clone(),
+            version: command.package_version.to_string(),
+            module: command.module.clone(),
+            is_top_level_dependency: command.is_top_level_dependency,
+        })
+        .collect();
+    EditorMetadata {
+        format_version: METADATA_FORMAT_VERSION,
+        packages,
+        commands,
+    }
+}
+
+/// The interfaces of the modules in the manifest of a package, by module name
+fn module_interfaces(package_path: &Path) -> HashMap<String, BTreeMap<String, String>> {
+    let manifest: Option<Manifest> = fs::read_to_string(package_path.join(MANIFEST_FILE_NAME))
+        .ok()
+        .and_then(|source| toml::from_str(&source).ok());
+    manifest
+        .and_then(|manifest| manifest.module)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|module| {
+            let interfaces = module.interfaces.unwrap_or_default().into_iter().collect();
+            (module.name, interfaces)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn metadata_of_local_package() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let manifest = r#"
+[package]
+name = "_/test"
+version = "0.1.0"
+description = ""
+
+[[module]]
+name = "test"
+source = "test.wasm"
+abi = "wasi"
+
+[module.interfaces]
+wasi = "0.0.0-unstable"
+
+[[command]]
+name = "test"
+module = "test"
+"#;
+        fs::write(tmp_dir.path().join(MANIFEST_FILE_NAME), manifest).unwrap();
+        let lockfile: Lockfile = toml::from_str(
+            r#"
+[modules."_/test"."0.1.0".test]
+name = "test"
+package_version = "0.1.0"
+package_name = "_/test"
+package_path = "_/test@0.1.0"
+resolved = "local"
+resolved_source = "local"
+abi = "wasi"
+source = "test.wasm"
+
+[commands.test]
+name = "test"
+package_name = "_/test"
+package_version = "0.1.0"
+module = "test"
+is_top_level_dependency = true
+"#,
+        )
+        .unwrap();
+        let metadata = metadata_from_lockfile(tmp_dir.path(), &lockfile);
+        assert_eq!(metadata.packages.len(), 1);
+        let package = &metadata.packages[0];
+        assert!(package.local);
+        assert_eq!(package.path, tmp_dir.path());
+        assert_eq!(package.modules[0].path, tmp_dir.path().join("test.wasm"));
+        assert_eq!(
+            package.modules[0]
+                .interfaces
+                .get("wasi")
+                .map(String::as_str),
+            Some("0.0.0-unstable")
+        );
+        assert_eq!(metadata.commands[0].name, "test");
+    }
+}
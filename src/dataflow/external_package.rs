@@ -0,0 +1,215 @@
+//! Packages installed from outside of the registry: the assets of GitHub releases and packages
+//! pulled from OCI registries.
+//!
+//! Their archives are checked like the archives of registry packages before anything is
+//! extracted: against the allowlist, the install policy and its integrity exceptions, the size
+//! limit and the archive scanner. The registry doesn't know anything about them, so policy
+//! rules needing a signature, a license or a publication date are broken unless an integrity
+//! exception lets the package through.
+
+use crate::allowlist::Allowlist;
+use crate::archive;
+use crate::archive_scan;
+use crate::data::manifest::Manifest;
+use crate::dataflow::manifest_packages::ManifestResult;
+use crate::dataflow::WapmPackageKey;
+use crate::integrity_exceptions::IntegrityExceptions;
+use crate::package_size;
+use crate::policy::{self, Policy};
+use crate::registry::PackageVersion;
+use crate::util::{
+    create_package_dir, fully_qualified_package_display_name, get_package_namespace_and_name,
+    sha256_hex,
+};
+use chrono::Utc;
+use std::fs;
+use std::path::Path;
+
+#[derive(Clone, Debug, Fail)]
+pub enum Error {
+    #[fail(display = "Could not check the package {}. {}", _0, _1)]
+    CouldNotCheck(String, String),
+    #[fail(display = "{} is not in the allowlist", _0)]
+    NotInAllowlist(String),
+    #[fail(
+        display = "The archive of {} does not match the allowlist: expected the checksum {}, found {}",
+        _0, _1, _2
+    )]
+    ChecksumMismatch(String, String, String),
+    #[fail(display = "The package is not allowed by the install policy.\n{}", _0)]
+    PolicyViolation(String),
+    #[fail(display = "{}", _0)]
+    PackageTooLarge(String),
+    #[fail(display = "The archive of {} was rejected by the scan. {}", _0, _1)]
+    ScanFailed(String, String),
+    #[fail(display = "Could not install {}. {}", _0, _1)]
+    CouldNotInstall(String, String),
+}
+
+/// The archive of a package from outside of the registry, downloaded and verified against the
+/// checksum published with it, but not extracted yet
+pub struct ExternalArchive {
+    pub key: WapmPackageKey<'static>,
+    pub data: Vec<u8>,
+    /// Where the archive was downloaded from, which the lockfile records
+    pub url: String,
+}
+
+impl ExternalArchive {
+    fn label(&self) -> String {
+        format!("{}@{}", self.key.name, self.key.version)
+    }
+}
+
+/// Check an archive against the allowlist, the install policy, the size limit and the scanner
+/// set in the environment and the config
+pub fn check(archive: &ExternalArchive, exceptions: &IntegrityExceptions) -> Result<(), Error> {
+    let could_not_check = |e: String| Error::CouldNotCheck(archive.label(), e);
+    let allowlist = Allowlist::load().map_err(|e| could_not_check(e.to_string()))?;
+    let policy = Policy::load().map_err(|e| could_not_check(e.to_string()))?;
+    check_with(archive, allowlist.as_ref(), policy.as_ref(), exceptions)?;
+
+    let size_limit = package_size::size_limit();
+    size_limit
+        .check(&archive.label(), "archive", archive.data.len() as u64)
+        .map_err(Error::PackageTooLarge)?;
+    let temp_dir = tempfile::TempDir::new().map_err(|e| could_not_check(e.to_string()))?;
+    let archive_path = temp_dir.path().join("package.tar.gz");
+    fs::write(&archive_path, &archive.data).map_err(|e| could_not_check(e.to_string()))?;
+    archive_scan::scan(&archive_path, &archive.label())
+        .map_err(|e| Error::ScanFailed(archive.label(), e.to_string()))
+}
+
+fn check_with(
+    archive: &ExternalArchive,
+    allowlist: Option<&Allowlist>,
+    policy: Option<&Policy>,
+    exceptions: &IntegrityExceptions,
+) -> Result<(), Error> {
+    let name = archive.key.name.as_ref();
+    let version = archive.key.version.to_string();
+    if let Some(allowlist) = allowlist {
+        let expected = allowlist
+            .sha256(name, &version)
+            .ok_or_else(|| Error::NotInAllowlist(archive.label()))?;
+        let sha256 = sha256_hex(&archive.data);
+        if expected != sha256 {
+            return Err(Error::ChecksumMismatch(
+                archive.label(),
+                expected.to_string(),
+                sha256,
+            ));
+        }
+    }
+    if let Some(policy) = policy {
+        let package_version = PackageVersion {
+            name: name.to_string(),
+            version,
+            manifest: None,
+            download_url: archive.url.clone(),
+            signature: None,
+            license: None,
+            size: Some(archive.data.len() as u64),
+            published_at: None,
+            commands: None,
+        };
+        if let Some(violation) =
+            policy.check_with_exceptions(&package_version, Utc::now(), exceptions)
+        {
+            return Err(Error::PolicyViolation(policy::violation_report(&[
+                violation,
+            ])));
+        }
+    }
+    Ok(())
+}
+
+/// Extract a checked archive into the packages directory of `directory`, returning the
+/// manifest of the package. The manifest must be for the version the package is installed as.
+pub fn unpack(directory: &Path, archive: &ExternalArchive) -> Result<Manifest, Error> {
+    let install_error = |e: String| Error::CouldNotInstall(archive.label(), e);
+    let (namespace, name) = get_package_namespace_and_name(&archive.key.name)
+        .map_err(|e| install_error(e.to_string()))?;
+    let package_dir = create_package_dir(
+        directory,
+        namespace,
+        &fully_qualified_package_display_name(name, &archive.key.version),
+    )
+    .map_err(|e| install_error(e.to_string()))?;
+    archive::unpack(
+        &archive.data[..],
+        &package_dir,
+        &package_size::size_limit().extraction_limits(),
+    )
+    .map_err(|e| install_error(e.to_string()))?;
+    let manifest = match ManifestResult::find_in_directory(&package_dir) {
+        ManifestResult::Manifest(manifest) => *manifest,
+        ManifestResult::ManifestError(e) => return Err(install_error(e.to_string())),
+        ManifestResult::NoManifest => {
+            return Err(install_error(
+                "The package archive does not contain a wapm.toml".to_string(),
+            ))
+        }
+    };
+    if manifest.package.version != archive.key.version {
+        return Err(install_error(format!(
+            "The package is version {} in its wapm.toml",
+            manifest.package.version
+        )));
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dataflow::github_release::GithubRelease;
+    use std::borrow::Cow;
+
+    #[test]
+    fn github_releases_must_be_in_the_allowlist() {
+        let release = GithubRelease::parse("gh:wasmerio/cowsay@v0.2.0").unwrap();
+        let archive = ExternalArchive {
+            key: WapmPackageKey {
+                name: Cow::Owned(release.package_name()),
+                version: release.version().unwrap(),
+            },
+            data: b"archive".to_vec(),
+            url: "https://example.com/cowsay-0.2.0.tar.gz".to_string(),
+        };
+        let exceptions = IntegrityExceptions::default();
+        assert!(check_with(&archive, None, None, &exceptions).is_ok());
+
+        let other_package = Allowlist::parse(
+            "[[package]]\nname = \"_/sqlite\"\nversion = \"0.1.1\"\nsha256 = \"abc\"\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            check_with(&archive, Some(&other_package), None, &exceptions),
+            Err(Error::NotInAllowlist(package)) if package == "wasmerio/cowsay@0.2.0"
+        ));
+
+        let allowlist = |sha256: &str| {
+            Allowlist::parse(&format!(
+                "[[package]]\nname = \"wasmerio/cowsay\"\nversion = \"0.2.0\"\nsha256 = \"{}\"\n",
+                sha256
+            ))
+            .unwrap()
+        };
+        assert!(matches!(
+            check_with(&archive, Some(&allowlist("abc")), None, &exceptions),
+            Err(Error::ChecksumMismatch(..))
+        ));
+        let vetted = allowlist(&sha256_hex(&archive.data));
+        assert!(check_with(&archive, Some(&vetted), None, &exceptions).is_ok());
+
+        let policy = Policy {
+            require_signatures: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            check_with(&archive, Some(&vetted), Some(&policy), &exceptions),
+            Err(Error::PolicyViolation(_))
+        ));
+    }
+}
@@ -1,8 +1,10 @@
 use crate::config::Config;
 use crate::data::lock::lockfile::{Lockfile, LockfileError};
+use crate::data::lock::LOCKFILE_NAME;
 use crate::data::manifest::Manifest;
 use crate::dataflow::lockfile_packages::LockfileResult;
 use crate::dataflow::manifest_packages::ManifestResult;
+use crate::global_versions;
 use std::env;
 use std::path::{Path, PathBuf};
 
@@ -74,6 +76,11 @@ pub enum Error {
         _0, _1
     )]
     CouldNotOpenGlobalsDirectory(String, String),
+    #[fail(
+        display = "Command \"{}\" of package {} was not found in the local directory, its parent directories or the global install directory.",
+        _0, _1
+    )]
+    CommandNotFoundInPackage(String, String),
 }
 
 #[derive(Debug)]
@@ -221,7 +228,7 @@ impl FindCommandResult {
             }
             (ManifestResult::Manifest(m), LockfileResult::Lockfile(l)) => {
                 debug!("Looking for local command in the manifest and lockfile");
-                return Self::find_command_in_manifest_and_lockfile(command_name, m, l, directory);
+                return Self::find_command_in_manifest_and_lockfile(command_name, *m, l, directory);
             }
         };
         FindCommandResult::CommandNotFound(command_name.as_ref().to_string())
@@ -235,14 +242,15 @@ pub struct Command {
     pub manifest_dir: PathBuf,
     pub args: Option<String>,
     pub module_name: String,
-    /// whether the command was found in the global context
-    pub is_global: bool,
+    /// the directory of the lockfile providing the command, which the paths are relative to
+    pub directory: PathBuf,
     /// the prehashed module key
     pub prehashed_cache_key: Option<String>,
 }
 
-/// Get a command from anywhere, where anywhere is the set of packages in the local lockfile and the global lockfile.
-/// A flag indicating global run is also returned. Commands are found in local lockfile first.
+/// Get a command from anywhere, where anywhere is the set of packages in the local lockfile, the
+/// lockfiles of the parent directories and the global lockfile, in that order. The directory of
+/// the lockfile providing the command is also returned.
 pub fn get_command_from_anywhere<S: AsRef<str>>(command_name: S) -> Result<Command, Error> {
     // look in the local directory, update if necessary
     let current_directory = env::current_dir().unwrap();
@@ -263,7 +271,7 @@ pub fn get_command_from_anywhere<S: AsRef<str>>(command_name: S) -> Result<Comma
                 manifest_dir,
                 args,
                 module_name,
-                is_global: false,
+                directory: current_directory,
                 prehashed_cache_key,
             });
         }
@@ -276,6 +284,12 @@ pub fn get_command_from_anywhere<S: AsRef<str>>(command_name: S) -> Result<Comma
     };
     trace!("Local command not found");
 
+    // look in the closest parent directory that pins the command, like version managers do
+    if let Some(command) = find_command_in_parent_directories(&current_directory, &command_name) {
+        return Ok(command);
+    }
+    trace!("Command not pinned in a parent directory");
+
     // look in the global directory
     let global_directory = Config::get_globals_directory().map_err(|e| {
         Error::CouldNotOpenGlobalsDirectory(command_name.as_ref().to_string(), e.to_string())
@@ -297,7 +311,7 @@ pub fn get_command_from_anywhere<S: AsRef<str>>(command_name: S) -> Result<Comma
                 manifest_dir,
                 args,
                 module_name,
-                is_global: true,
+                directory: global_directory,
                 prehashed_cache_key,
             });
         }
@@ -312,5 +326,129 @@ pub fn get_command_from_anywhere<S: AsRef<str>>(command_name: S) -> Result<Comma
     };
     trace!("Global command not found");
 
+    // versioned commands like `tool@1.2.3` of packages installed side by side
+    if let Some((directory, command)) =
+        global_versions::find_versioned_command(command_name.as_ref())
+    {
+        if let FindCommandResult::CommandFound {
+            source,
+            manifest_dir,
+            args,
+            module_name,
+            prehashed_cache_key,
+        } = FindCommandResult::find_command_in_directory(&directory, &command)
+        {
+            return Ok(Command {
+                source,
+                manifest_dir,
+                args,
+                module_name,
+                directory,
+                prehashed_cache_key,
+            });
+        }
+    }
+
     return Err(Error::CommandNotFound(command_name.as_ref().to_string()));
 }
+
+/// Find a command in the lockfile of the closest parent directory providing it. Parent
+/// directories that can't be read are skipped, so they don't break global commands.
+fn find_command_in_parent_directories<S: AsRef<str>>(
+    directory: &Path,
+    command_name: S,
+) -> Option<Command> {
+    directory
+        .ancestors()
+        .skip(1)
+        .filter(|dir| dir.join(LOCKFILE_NAME).is_file())
+        .find_map(
+            |dir| match FindCommandResult::find_command_in_directory(dir, &command_name) {
+                FindCommandResult::CommandFound {
+                    source,
+                    manifest_dir,
+                    args,
+                    module_name,
+                    prehashed_cache_key,
+                } => Some(Command {
+                    source,
+                    manifest_dir,
+                    args,
+                    module_name,
+                    directory: dir.to_path_buf(),
+                    prehashed_cache_key,
+                }),
+                FindCommandResult::CommandNotFound(_) => None,
+                FindCommandResult::Error(e) => {
+                    debug!("Skipping {}: {}", dir.display(), e);
+                    None
+                }
+            },
+        )
+}
+
+/// Get a command provided by a specific package, for when several packages provide commands with
+/// the same name. The package can have a version like `pkg@1.2.3`. The local directory and its
+/// parent directories are searched first, then the global install directory and the versions
+/// installed side by side with it.
+pub fn get_command_from_package(package: &str, command_name: &str) -> Result<Command, Error> {
+    let mut parts = package.splitn(2, '@');
+    let package_name = parts.next().unwrap_or_default();
+    let version = parts.next();
+    let not_found =
+        || Error::CommandNotFoundInPackage(command_name.to_string(), package.to_string());
+
+    let current_directory = env::current_dir().unwrap();
+    let global_directory = Config::get_globals_directory().map_err(|e| {
+        Error::CouldNotOpenGlobalsDirectory(command_name.to_string(), e.to_string())
+    })?;
+    let side_by_side = global_versions::side_by_side_versions(&global_directory, package_name)
+        .into_iter()
+        .map(|v| global_versions::version_directory(&global_directory, package_name, &v));
+    let directories: Vec<PathBuf> = current_directory
+        .ancestors()
+        .map(Path::to_path_buf)
+        .chain(std::iter::once(global_directory.clone()))
+        .chain(side_by_side)
+        .collect();
+
+    let provided_by_package = |directory: &Path| match LockfileResult::find_in_directory(directory)
+    {
+        LockfileResult::Lockfile(lockfile) => lockfile
+            .commands
+            .get(command_name)
+            .map(|command| {
+                command.package_name == package_name
+                    && version
+                        .iter()
+                        .all(|v| command.package_version.to_string() == *v)
+            })
+            .unwrap_or(false),
+        _ => false,
+    };
+    let directory = directories
+        .into_iter()
+        .find(|directory| provided_by_package(directory))
+        .ok_or_else(not_found)?;
+    match FindCommandResult::find_command_in_directory(&directory, command_name) {
+        FindCommandResult::CommandFound {
+            source,
+            manifest_dir,
+            args,
+            module_name,
+            prehashed_cache_key,
+        } => Ok(Command {
+            source,
+            manifest_dir,
+            args,
+            module_name,
+            directory,
+            prehashed_cache_key,
+        }),
+        FindCommandResult::CommandNotFound(_) => Err(not_found()),
+        FindCommandResult::Error(e) => Err(Error::ErrorReadingLocalDirectory(
+            command_name.to_string(),
+            e.to_string(),
+        )),
+    }
+}
@@ -0,0 +1,309 @@
+//! Installing packages from the assets of GitHub releases, with `wapm install gh:owner/repo@tag`.
+//!
+//! The package archive is the release asset ending in `.wapm.tar.gz`, or else the asset named
+//! `<repo>-<version>.tar.gz`. Its SHA-256 checksum is looked up in a `<asset>.sha256` asset or in
+//! a `SHA256SUMS` or `checksums.txt` asset listing `<checksum>  <file name>` lines.
+//! The package is installed as `owner/repo` with the version of the tag, and its lockfile
+//! modules have a `github+owner/repo@tag` resolved source.
+
+use crate::dataflow::external_package::ExternalArchive;
+use crate::dataflow::WapmPackageKey;
+use crate::graphql::VERSION;
+use crate::proxy;
+use crate::util::{self, sha256_hex};
+use reqwest::blocking::{Client, ClientBuilder};
+use semver::Version;
+use std::borrow::Cow;
+use std::env;
+
+/// The prefix of package identifiers that refer to GitHub releases
+pub const GITHUB_SOURCE_PREFIX: &str = "gh:";
+/// The prefix of the resolved source of lockfile modules installed from GitHub releases
+pub const GITHUB_RESOLVED_SOURCE_PREFIX: &str = "github+";
+/// Used to authenticate requests to the GitHub API, to get a higher rate limit
+const GITHUB_TOKEN_ENV_VAR: &str = "GITHUB_TOKEN";
+const CHECKSUM_FILE_NAMES: &[&str] = &["SHA256SUMS", "sha256sums.txt", "checksums.txt"];
+
+#[derive(Clone, Debug, Fail)]
+pub enum Error {
+    #[fail(
+        display = "Invalid GitHub release \"{}\", expected gh:<owner>/<repo>@<tag>",
+        _0
+    )]
+    InvalidReleaseIdentifier(String),
+    #[fail(
+        display = "The tag \"{}\" of the GitHub release is not a version like v1.2.3",
+        _0
+    )]
+    TagIsNotAVersion(String),
+    #[fail(display = "Could not get the GitHub release {}. {}", _0, _1)]
+    CouldNotDownload(String, String),
+    #[fail(
+        display = "The GitHub release {} has no package archive, expected an asset ending in .wapm.tar.gz",
+        _0
+    )]
+    NoPackageAsset(String),
+    #[fail(
+        display = "The checksum of {} does not match the checksum published with the release",
+        _0
+    )]
+    ChecksumMismatch(String),
+    #[fail(display = "Install aborted: {}", _0)]
+    InstallAborted(String),
+}
+
+/// A release of a GitHub repository
+#[derive(Clone, Debug, PartialEq)]
+pub struct GithubRelease {
+    pub owner: String,
+    pub repo: String,
+    pub tag: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseResponse {
+    assets: Vec<ReleaseAsset>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct ReleaseAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl GithubRelease {
+    /// Parse an identifier like `gh:owner/repo@v1.2.3`
+    pub fn parse(identifier: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidReleaseIdentifier(identifier.to_owned());
+        if !identifier.starts_with(GITHUB_SOURCE_PREFIX) {
+            return Err(invalid());
+        }
+        let release = &identifier[GITHUB_SOURCE_PREFIX.len()..];
+        let mut parts = release.splitn(2, '@');
+        let repository = parts.next().ok_or_else(invalid)?;
+        let tag = parts
+            .next()
+            .filter(|tag| !tag.is_empty())
+            .ok_or_else(invalid)?;
+        let mut repository_parts = repository.splitn(2, '/');
+        let owner = repository_parts.next().filter(|owner| !owner.is_empty());
+        let repo = repository_parts
+            .next()
+            .filter(|repo| !repo.is_empty() && !repo.contains('/'));
+        match (owner, repo) {
+            (Some(owner), Some(repo)) => Ok(Self {
+                owner: owner.to_owned(),
+                repo: repo.to_owned(),
+                tag: tag.to_owned(),
+            }),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// The name the package is installed as
+    pub fn package_name(&self) -> String {
+        format!("{}/{}", self.owner, self.repo)
+    }
+
+    /// The version of the package, from the tag of the release
+    pub fn version(&self) -> Result<Version, Error> {
+        Version::parse(self.tag.trim_start_matches('v'))
+            .map_err(|_| Error::TagIsNotAVersion(self.tag.clone()))
+    }
+
+    /// How the source of the package is recorded in the lockfile
+    pub fn resolved_source(&self) -> String {
+        format!("{}{}", GITHUB_RESOLVED_SOURCE_PREFIX, self)
+    }
+}
+
+impl std::fmt::Display for GithubRelease {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}@{}", self.owner, self.repo, self.tag)
+    }
+}
+
+/// Download the package archive of a release and verify it against its published checksum
+pub fn fetch_github_release(release: &GithubRelease) -> Result<ExternalArchive, Error> {
+    let download_error = |e: String| Error::CouldNotDownload(release.to_string(), e);
+    let version = release.version()?;
+    let client = client().map_err(|e| download_error(e.to_string()))?;
+
+    let release_url = format!(
+        "https://api.github.com/repos/{}/{}/releases/tags/{}",
+        release.owner, release.repo, release.tag
+    );
+    let response: ReleaseResponse = get(&client, &release_url)
+        .and_then(|response| response.json().map_err(failure::Error::from))
+        .map_err(|e| download_error(e.to_string()))?;
+    let asset = select_package_asset(&response.assets, release, &version)
+        .ok_or_else(|| Error::NoPackageAsset(release.to_string()))?;
+    let archive = get(&client, &asset.browser_download_url)
+        .and_then(|response| Ok(response.bytes()?))
+        .map_err(|e| download_error(e.to_string()))?;
+
+    match published_checksum(&client, &response.assets, &asset.name)
+        .map_err(|e| download_error(e.to_string()))?
+    {
+        Some(checksum) => {
+            if !checksum.eq_ignore_ascii_case(&sha256_hex(&archive)) {
+                return Err(Error::ChecksumMismatch(asset.name.clone()));
+            }
+            info!("Checksum of {} verified!", asset.name);
+        }
+        None => {
+            warn!(
+                "The GitHub release {} does not publish a checksum for {}",
+                release, asset.name
+            );
+            let install_anyway = util::prompt_user_for_yes(
+                "Would you like to proceed with an unverified installation?",
+            )
+            .map_err(|e| Error::InstallAborted(e.to_string()))?;
+            if !install_anyway {
+                return Err(Error::InstallAborted(format!(
+                    "User did not trust the unverified release {}",
+                    release
+                )));
+            }
+        }
+    }
+
+    let key = WapmPackageKey {
+        name: Cow::Owned(release.package_name()),
+        version,
+    };
+    Ok(ExternalArchive {
+        key,
+        data: archive.to_vec(),
+        url: asset.browser_download_url.clone(),
+    })
+}
+
+fn client() -> Result<Client, failure::Error> {
+    let builder = ClientBuilder::new();
+    let builder = match proxy::maybe_set_up_proxy()? {
+        Some(proxy) => builder.proxy(proxy),
+        None => builder,
+    };
+    Ok(builder.build()?)
+}
+
+fn get(client: &Client, url: &str) -> Result<reqwest::blocking::Response, failure::Error> {
+    let user_agent = format!(
+        "wapm/{} {} {}",
+        VERSION,
+        whoami::platform(),
+        whoami::os().to_lowercase(),
+    );
+    let mut request = client
+        .get(url)
+        .header(reqwest::header::USER_AGENT, user_agent);
+    if let Some(token) = env::var(GITHUB_TOKEN_ENV_VAR)
+        .ok()
+        .filter(|token| !token.is_empty())
+    {
+        request = request.header(reqwest::header::AUTHORIZATION, format!("token {}", token));
+    }
+    Ok(request.send()?.error_for_status()?)
+}
+
+/// The asset that contains the package
+fn select_package_asset<'a>(
+    assets: &'a [ReleaseAsset],
+    release: &GithubRelease,
+    version: &Version,
+) -> Option<&'a ReleaseAsset> {
+    let conventional_names = [
+        format!("{}-{}.tar.gz", release.repo, version),
+        format!("{}-{}.tar.gz", release.repo, release.tag),
+    ];
+    assets
+        .iter()
+        .find(|asset| asset.name.ends_with(".wapm.tar.gz"))
+        .or_else(|| {
+            assets
+                .iter()
+                .find(|asset| conventional_names.contains(&asset.name))
+        })
+}
+
+/// The checksum of an asset published in the checksum assets of the release
+fn published_checksum(
+    client: &Client,
+    assets: &[ReleaseAsset],
+    asset_name: &str,
+) -> Result<Option<String>, failure::Error> {
+    let checksum_asset_name = format!("{}.sha256", asset_name);
+    for asset in assets {
+        if asset.name == checksum_asset_name {
+            let checksums = get(client, &asset.browser_download_url)?.text()?;
+            return Ok(checksums.split_whitespace().next().map(str::to_owned));
+        }
+    }
+    for asset in assets {
+        if CHECKSUM_FILE_NAMES.contains(&asset.name.as_str()) {
+            let checksums = get(client, &asset.browser_download_url)?.text()?;
+            if let Some(checksum) = find_checksum(&checksums, asset_name) {
+                return Ok(Some(checksum));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Find the checksum of a file in the output of `sha256sum`
+fn find_checksum(checksums: &str, file_name: &str) -> Option<String> {
+    checksums.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let checksum = parts.next()?;
+        // `sha256sum` marks files read in binary mode with a `*`
+        let name = parts.next()?.trim_start_matches('*');
+        if name == file_name {
+            Some(checksum.to_owned())
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_release_identifiers() {
+        let release = GithubRelease::parse("gh:wasmerio/cowsay@v0.2.0").unwrap();
+        assert_eq!(release.package_name(), "wasmerio/cowsay");
+        assert_eq!(release.version().unwrap(), Version::new(0, 2, 0));
+        assert_eq!(release.resolved_source(), "github+wasmerio/cowsay@v0.2.0");
+        assert!(GithubRelease::parse("gh:wasmerio/cowsay").is_err());
+        assert!(GithubRelease::parse("gh:cowsay@v0.2.0").is_err());
+        assert!(GithubRelease::parse("wasmerio/cowsay@v0.2.0").is_err());
+    }
+
+    #[test]
+    fn select_assets_and_checksums() {
+        let release = GithubRelease::parse("gh:wasmerio/cowsay@v0.2.0").unwrap();
+        let asset = |name: &str| ReleaseAsset {
+            name: name.to_owned(),
+            browser_download_url: format!("https://example.com/{}", name),
+        };
+        let assets = vec![asset("cowsay-linux.tar.gz"), asset("cowsay-0.2.0.tar.gz")];
+        assert_eq!(
+            select_package_asset(&assets, &release, &Version::new(0, 2, 0))
+                .unwrap()
+                .name,
+            "cowsay-0.2.0.tar.gz"
+        );
+        let checksums = "abc123  cowsay-linux.tar.gz\ndef456 *cowsay-0.2.0.tar.gz\n";
+        assert_eq!(
+            find_checksum(checksums, "cowsay-0.2.0.tar.gz").as_deref(),
+            Some("def456")
+        );
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use crate::data::workspace::Workspace;
+use crate::dataflow::manifest_packages::ManifestResult;
+use crate::dataflow::normalize_global_namespace_package_name;
+use crate::util::{
+    fully_qualified_package_display_name, get_package_namespace_and_name, get_packages_dir,
+};
+use rayon::prelude::*;
+use semver::Version;
+use std::collections::btree_map::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Fail)]
+pub enum Error {
+    #[fail(
+        display = "Could not read the manifest of workspace member \"{}\". {}",
+        _0, _1
+    )]
+    InvalidMemberManifest(String, String),
+    #[fail(display = "Could not parse the package name \"{}\". {}", _0, _1)]
+    FailedToParsePackageName(String, String),
+    #[fail(
+        display = "Could not link package \"{}\" into workspace member \"{}\". {}",
+        _0, _1, _2
+    )]
+    FailedToLinkPackage(String, String, String),
+}
+
+/// Dependencies that are shared by more than one member of a workspace. These are installed
+/// once into the workspace root and linked into the packages directory of each member that
+/// uses them.
+#[derive(Clone, Debug, Default)]
+pub struct HoistedPackages {
+    /// package name and version -> directories of the members using the package
+    pub packages: BTreeMap<(String, Version), Vec<PathBuf>>,
+}
+
+impl HoistedPackages {
+    /// Find the dependencies that multiple members depend on with exactly the same version.
+    /// Dependencies on version ranges are never hoisted.
+    pub fn from_workspace(workspace: &Workspace) -> Result<Self, Error> {
+        let mut all_packages: BTreeMap<(String, Version), Vec<PathBuf>> = BTreeMap::new();
+        // the manifests of big workspaces are parsed in parallel
+        let manifests: Vec<(PathBuf, ManifestResult)> = workspace
+            .member_directories()
+            .into_par_iter()
+            .map(|directory| {
+                let manifest = ManifestResult::find_in_directory(&directory);
+                (directory, manifest)
+            })
+            .collect();
+        for (member_directory, manifest) in manifests {
+            let manifest = match manifest {
+                ManifestResult::Manifest(manifest) => manifest,
+                ManifestResult::NoManifest => continue,
+                ManifestResult::ManifestError(e) => {
+                    return Err(Error::InvalidMemberManifest(
+                        member_directory.to_string_lossy().to_string(),
+                        e.to_string(),
+                    ));
+                }
+            };
+            for (name, version) in manifest.dependencies.unwrap_or_default() {
+                if let Ok(version) = Version::parse(&version) {
+                    let name = normalize_global_namespace_package_name(name.into()).to_string();
+                    all_packages
+                        .entry((name, version))
+                        .or_default()
+                        .push(member_directory.clone());
+                }
+            }
+        }
+        let packages = all_packages
+            .into_iter()
+            .filter(|(_, members)| members.len() > 1)
+            .collect();
+        Ok(Self { packages })
+    }
+
+    /// The hoisted packages as name and version pairs
+    pub fn name_and_version_pairs(&self) -> Vec<(String, String)> {
+        self.packages
+            .keys()
+            .map(|(name, version)| (name.clone(), version.to_string()))
+            .collect()
+    }
+
+    /// Link the packages installed in the workspace root into the members that use them. Any
+    /// copy of a hoisted package that a member has of its own is replaced by the link.
+    pub fn link_into_members(&self, root_directory: &Path) -> Result<(), Error> {
+        let root_packages_dir = get_packages_dir(root_directory);
+        for ((name, version), members) in self.packages.iter() {
+            let package_path = package_path(name, version)?;
+            let hoisted_package_dir = root_packages_dir.join(&package_path);
+            for member_directory in members {
+                let link_error = |e: io::Error| {
+                    Error::FailedToLinkPackage(
+                        name.clone(),
+                        member_directory.to_string_lossy().to_string(),
+                        e.to_string(),
+                    )
+                };
+                let member_package_dir = get_packages_dir(member_directory).join(&package_path);
+                if is_linked_package_dir(&member_package_dir) {
+                    continue;
+                }
+                if member_package_dir.is_dir() {
+                    fs::remove_dir_all(&member_package_dir).map_err(link_error)?;
+                }
+                if let Some(parent) = member_package_dir.parent() {
+                    fs::create_dir_all(parent).map_err(link_error)?;
+                }
+                link_package_dir(&hoisted_package_dir, &member_package_dir).map_err(link_error)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Remove all links to hoisted packages from a member, so that the member gets its own copies
+/// of its dependencies on the next install.
+pub fn unlink_hoisted_packages(member_directory: &Path) -> Result<(), Error> {
+    let packages_dir = get_packages_dir(member_directory);
+    let link_error = |e: io::Error| {
+        Error::FailedToLinkPackage(
+            packages_dir.to_string_lossy().to_string(),
+            member_directory.to_string_lossy().to_string(),
+            e.to_string(),
+        )
+    };
+    let namespace_dirs = match fs::read_dir(&packages_dir) {
+        Ok(namespace_dirs) => namespace_dirs,
+        Err(_) => return Ok(()),
+    };
+    for namespace_dir in namespace_dirs {
+        let namespace_dir = namespace_dir.map_err(link_error)?.path();
+        if !namespace_dir.is_dir() {
+            continue;
+        }
+        for package_dir in fs::read_dir(&namespace_dir).map_err(link_error)? {
+            let package_dir = package_dir.map_err(link_error)?.path();
+            if is_linked_package_dir(&package_dir) {
+                unlink_package_dir(&package_dir).map_err(link_error)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Packages hoisted into a workspace root are symlinked into the members
+pub fn is_linked_package_dir(package_dir: &Path) -> bool {
+    fs::symlink_metadata(package_dir)
+        .map(|metadata| metadata.file_type().is_symlink())
+        .unwrap_or(false)
+}
+
+/// The path of a package relative to a packages directory e.g. `_/sqlite@0.1.1`
+fn package_path(name: &str, version: &Version) -> Result<PathBuf, Error> {
+    let (namespace, package_name) = get_package_namespace_and_name(name)
+        .map_err(|e| Error::FailedToParsePackageName(name.to_string(), e.to_string()))?;
+    Ok(PathBuf::from(namespace).join(fully_qualified_package_display_name(package_name, version)))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn link_package_dir(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(original, link)
+}
+
+#[cfg(target_os = "windows")]
+fn link_package_dir(original: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_dir(original, link)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn unlink_package_dir(link: &Path) -> io::Result<()> {
+    fs::remove_file(link)
+}
+
+#[cfg(target_os = "windows")]
+fn unlink_package_dir(link: &Path) -> io::Result<()> {
+    fs::remove_dir(link)
+}
@@ -0,0 +1,562 @@
+//! What an install is about to do, worked out before anything is downloaded or removed: the new,
+//! updated and removed packages, how much will be downloaded and the commands that will be added.
+//! `wapm install` shows it and asks to go ahead when run interactively.
+//!
+//! `wapm install --plan plan.json` writes the plan to a file instead of installing, with the
+//! download url and SHA-256 checksum of every archive, so it can be reviewed and approved.
+//! `wapm apply plan.json` then runs the same install, failing if the project changed since, if
+//! the install resolves to other packages than those of the plan or if an archive changed.
+
+use crate::data::lock::LOCKFILE_NAME;
+use crate::data::manifest::MANIFEST_FILE_NAME;
+use crate::dataflow::installed_packages;
+use crate::dataflow::lockfile_packages::LockfilePackages;
+use crate::dataflow::removed_lockfile_packages::RemovedLockfilePackages;
+use crate::dataflow::resolved_packages::ResolvedPackages;
+use crate::dataflow::{PackageKey, WapmPackageKey};
+use crate::i18n::{format_message, message};
+use crate::install_report::PackageUpdate;
+use crate::util::{self, format_size, sha256_hex};
+use chrono::Utc;
+use lazy_static::lazy_static;
+use semver::Version;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The version of the format of plan files
+pub const PLAN_FORMAT_VERSION: u32 = 1;
+
+lazy_static! {
+    /// The packages of the plan being applied
+    static ref APPLIED_PLAN: Mutex<Option<Vec<PlannedPackage>>> = Mutex::new(None);
+}
+
+#[derive(Debug, Fail)]
+pub enum PlanError {
+    #[fail(display = "Could not read the plan {}: {}", _0, _1)]
+    CouldNotRead(String, String),
+    #[fail(
+        display = "The plan has format version {}, this version of wapm applies version {}",
+        _0, _1
+    )]
+    UnsupportedFormatVersion(u32, u32),
+    #[fail(
+        display = "{} changed since the plan was made, make a new plan with `wapm install --plan`",
+        _0
+    )]
+    ProjectChanged(String),
+    #[fail(
+        display = "The install no longer resolves to the packages of the plan, make a new plan with `wapm install --plan`: {}",
+        _0
+    )]
+    ResolvedDifferently(String),
+    #[fail(
+        display = "The plan has no checksum for the archive of {}, make a new plan with `wapm install --plan`",
+        _0
+    )]
+    MissingChecksum(String),
+}
+
+/// A package an install downloads
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PlannedPackage {
+    pub name: String,
+    pub version: String,
+    pub download_url: String,
+    /// The checksum of the archive, known once the plan is written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct InstallPlan {
+    /// The packages that aren't installed yet, as `name@version`
+    pub added: Vec<String>,
+    pub updated: Vec<PackageUpdate>,
+    /// The packages installed again in the same version, e.g. because their files were deleted
+    pub reinstalled: Vec<String>,
+    /// The packages that are uninstalled, as `name@version`
+    pub removed: Vec<String>,
+    /// The size of the archives to download, for the packages whose size the registry knows
+    pub download_size: u64,
+    /// The number of packages whose size the registry doesn't know
+    pub unknown_sizes: usize,
+    /// The commands that no installed package has yet
+    pub new_commands: Vec<String>,
+    pub packages: Vec<PlannedPackage>,
+}
+
+/// The `wapm install` arguments a plan was made with, for `wapm apply` to run the same install
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct InstallRequest {
+    pub packages: Vec<String>,
+    pub global: bool,
+    pub no_bin: bool,
+    pub bin_only: bool,
+}
+
+/// Where `wapm install --plan` writes the plan of an install
+#[derive(Clone, Debug)]
+pub struct PlanExport {
+    pub path: PathBuf,
+    pub request: InstallRequest,
+}
+
+/// A plan written by `wapm install --plan`
+#[derive(Debug, Deserialize, Serialize)]
+pub struct PlanFile {
+    pub format_version: u32,
+    pub created_at: String,
+    /// The directory the packages are installed into
+    pub directory: PathBuf,
+    pub request: InstallRequest,
+    /// The checksums of the manifest and the lockfile of the directory, which must not change
+    /// before the plan is applied
+    pub manifest_sha256: Option<String>,
+    pub lockfile_sha256: Option<String>,
+    #[serde(flatten)]
+    pub plan: InstallPlan,
+}
+
+impl InstallPlan {
+    /// The plan for installing the resolved packages into a directory with the packages of its
+    /// lockfile, removing the packages no longer needed
+    pub fn new(
+        installed: &LockfilePackages,
+        resolved: &ResolvedPackages,
+        removed: &RemovedLockfilePackages,
+    ) -> Self {
+        let mut installed_versions = HashMap::new();
+        let mut installed_commands = BTreeSet::new();
+        for (key, package) in installed.packages.iter() {
+            if let PackageKey::WapmPackage(WapmPackageKey { name, version }) = key {
+                installed_versions.insert(name.to_string(), version.clone());
+            }
+            for command in package.commands.iter() {
+                installed_commands.insert(command.name.clone());
+            }
+        }
+
+        let mut plan = Self::default();
+        let mut new_commands = BTreeSet::new();
+        for (key, (download_url, _)) in resolved.packages.iter() {
+            let package = format!("{}@{}", key.name, key.version);
+            plan.packages.push(PlannedPackage {
+                name: key.name.to_string(),
+                version: key.version.to_string(),
+                download_url: download_url.clone(),
+                sha256: None,
+            });
+            match installed_versions.get(key.name.as_ref()) {
+                None => plan.added.push(package),
+                Some(version) if *version == key.version => plan.reinstalled.push(package),
+                Some(version) => plan.updated.push(PackageUpdate {
+                    name: key.name.to_string(),
+                    from: version.to_string(),
+                    to: key.version.to_string(),
+                }),
+            }
+            let details = resolved.details.get(key).cloned().unwrap_or_default();
+            match details.size {
+                Some(size) => plan.download_size += size,
+                None => plan.unknown_sizes += 1,
+            }
+            for command in details.commands.unwrap_or_default() {
+                if !installed_commands.contains(&command) {
+                    new_commands.insert(command);
+                }
+            }
+        }
+        let resolved_names: HashSet<&str> = resolved
+            .packages
+            .iter()
+            .map(|(key, _)| key.name.as_ref())
+            .collect();
+        for key in removed.packages.keys() {
+            if let PackageKey::WapmPackage(WapmPackageKey { name, version }) = key {
+                // the old versions of updated packages are replaced rather than removed
+                if !resolved_names.contains(name.as_ref()) {
+                    plan.removed.push(format!("{}@{}", name, version));
+                }
+            }
+        }
+        plan.added.sort();
+        plan.updated.sort_by(|a, b| a.name.cmp(&b.name));
+        plan.reinstalled.sort();
+        plan.removed.sort();
+        plan.packages
+            .sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        plan.new_commands = new_commands.into_iter().collect();
+        plan
+    }
+
+    /// Whether the install changes nothing
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty() && self.removed.is_empty()
+    }
+
+    /// Show the plan and ask whether to go ahead with it. Nothing is asked when prompts are
+    /// accepted with `--force-yes` or stdin isn't a terminal.
+    pub fn confirm(&self) -> Result<bool, failure::Error> {
+        if self.is_empty() {
+            return Ok(true);
+        }
+        if util::wapm_should_accept_all_prompts() || !atty::is(atty::Stream::Stdin) {
+            println!("{}", self);
+            return Ok(true);
+        }
+        util::prompt_user_for_yes(&format!("{}\n{}", self, message("install.confirm_plan")))
+    }
+}
+
+impl PlanFile {
+    pub fn load(path: &Path) -> Result<Self, PlanError> {
+        let read_error = |e: String| PlanError::CouldNotRead(path.display().to_string(), e);
+        let source = fs::read_to_string(path).map_err(|e| read_error(e.to_string()))?;
+        let plan: Self = serde_json::from_str(&source).map_err(|e| read_error(e.to_string()))?;
+        if plan.format_version != PLAN_FORMAT_VERSION {
+            return Err(PlanError::UnsupportedFormatVersion(
+                plan.format_version,
+                PLAN_FORMAT_VERSION,
+            ));
+        }
+        // the checksums are what makes applying a plan install the reviewed archives
+        if let Some(package) = plan.plan.packages.iter().find(|package| {
+            package
+                .sha256
+                .as_deref()
+                .is_none_or(|sha256| sha256.is_empty())
+        }) {
+            return Err(PlanError::MissingChecksum(format!(
+                "{}@{}",
+                package.name, package.version
+            )));
+        }
+        Ok(plan)
+    }
+
+    /// Check that the manifest and the lockfile are still those the plan was made for
+    pub fn check_project(&self) -> Result<(), PlanError> {
+        let files = [
+            (MANIFEST_FILE_NAME, &self.manifest_sha256),
+            (LOCKFILE_NAME, &self.lockfile_sha256),
+        ];
+        for (file_name, planned) in files.iter() {
+            let path = self.directory.join(file_name);
+            if file_sha256(&path) != **planned {
+                return Err(PlanError::ProjectChanged(path.display().to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write the plan of an install to a file, downloading the archives to record their checksums
+pub fn export(
+    export: &PlanExport,
+    directory: &Path,
+    mut plan: InstallPlan,
+) -> Result<(), failure::Error> {
+    for package in plan.packages.iter_mut() {
+        let key = WapmPackageKey {
+            name: Cow::Borrowed(&package.name),
+            version: Version::parse(&package.version)?,
+        };
+        package.sha256 = Some(installed_packages::archive_sha256(
+            &key,
+            &package.download_url,
+        )?);
+    }
+    let directory = directory.canonicalize()?;
+    let file = PlanFile {
+        format_version: PLAN_FORMAT_VERSION,
+        created_at: Utc::now().to_rfc3339(),
+        manifest_sha256: file_sha256(&directory.join(MANIFEST_FILE_NAME)),
+        lockfile_sha256: file_sha256(&directory.join(LOCKFILE_NAME)),
+        directory,
+        request: export.request.clone(),
+        plan,
+    };
+    fs::write(&export.path, serde_json::to_string_pretty(&file)? + "\n")?;
+    Ok(())
+}
+
+fn file_sha256(path: &Path) -> Option<String> {
+    fs::read(path).ok().map(|data| sha256_hex(&data))
+}
+
+/// Only install the packages of a reviewed plan, with the archives it recorded
+pub fn set_applied_plan(packages: Vec<PlannedPackage>) {
+    *APPLIED_PLAN.lock().unwrap() = Some(packages);
+}
+
+/// Whether a plan made by `wapm install --plan` is being applied
+pub fn is_applying() -> bool {
+    APPLIED_PLAN.lock().unwrap().is_some()
+}
+
+/// Check that an install is going to download exactly the packages of the applied plan
+pub fn check_applied_plan(plan: &InstallPlan) -> Result<(), PlanError> {
+    match APPLIED_PLAN.lock().unwrap().as_ref() {
+        Some(applied) => compare_packages(applied, &plan.packages),
+        None => Ok(()),
+    }
+}
+
+fn compare_packages(
+    applied: &[PlannedPackage],
+    resolved: &[PlannedPackage],
+) -> Result<(), PlanError> {
+    // download urls can change between runs, like the presigned urls of S3 registries, so
+    // only names and versions are compared. The checksums of the plan are checked when the
+    // archives are downloaded.
+    let describe = |package: &PlannedPackage| format!("{}@{}", package.name, package.version);
+    let planned: BTreeSet<String> = applied.iter().map(describe).collect();
+    let resolved: BTreeSet<String> = resolved.iter().map(describe).collect();
+    let mut differences: Vec<String> = planned
+        .difference(&resolved)
+        .map(|package| format!("{} is no longer installed", package))
+        .collect();
+    differences.extend(
+        resolved
+            .difference(&planned)
+            .map(|package| format!("{} is not in the plan", package)),
+    );
+    if differences.is_empty() {
+        Ok(())
+    } else {
+        Err(PlanError::ResolvedDifferently(differences.join(", ")))
+    }
+}
+
+/// The checksum the applied plan recorded for the archive of a package, none when no plan is
+/// being applied. A package of the applied plan without a checksum is an error.
+pub fn planned_sha256(key: &WapmPackageKey) -> Result<Option<String>, PlanError> {
+    let applied = APPLIED_PLAN.lock().unwrap();
+    let applied = match applied.as_ref() {
+        Some(applied) => applied,
+        None => return Ok(None),
+    };
+    applied
+        .iter()
+        .find(|package| package.name == key.name && package.version == key.version.to_string())
+        .and_then(|package| package.sha256.clone())
+        .filter(|sha256| !sha256.is_empty())
+        .map(Some)
+        .ok_or_else(|| PlanError::MissingChecksum(key.to_string()))
+}
+
+impl fmt::Display for InstallPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counts: Vec<String> = [
+            ("install.plan_new", self.added.len()),
+            ("install.plan_updated", self.updated.len()),
+            ("install.plan_reinstalled", self.reinstalled.len()),
+            ("install.plan_removed", self.removed.len()),
+        ]
+        .iter()
+        .filter(|(_, count)| *count > 0)
+        .map(|(key, count)| format_message(key, &[("count", count)]))
+        .collect();
+        write!(
+            f,
+            "{}",
+            format_message(
+                "install.plan_summary",
+                &[
+                    ("counts", &counts.join(", ")),
+                    ("size", &format_size(self.download_size)),
+                ]
+            )
+        )?;
+        if self.unknown_sizes > 0 {
+            write!(
+                f,
+                " {}",
+                format_message(
+                    "install.plan_unknown_sizes",
+                    &[("count", &self.unknown_sizes)]
+                )
+            )?;
+        }
+        for package in self.added.iter() {
+            write!(f, "\n  + {}", package)?;
+        }
+        for update in self.updated.iter() {
+            write!(f, "\n  ~ {} {} -> {}", update.name, update.from, update.to)?;
+        }
+        for package in self.reinstalled.iter() {
+            write!(f, "\n  = {}", package)?;
+        }
+        for package in self.removed.iter() {
+            write!(f, "\n  - {}", package)?;
+        }
+        if !self.new_commands.is_empty() {
+            write!(
+                f,
+                "\n{}",
+                format_message(
+                    "install.plan_new_commands",
+                    &[("commands", &self.new_commands.join(", "))]
+                )
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::lock::lockfile_command::LockfileCommand;
+    use crate::dataflow::lockfile_packages::LockfilePackage;
+    use crate::dataflow::resolved_packages::PackageDetails;
+
+    fn key(name: &'static str, version: &str) -> WapmPackageKey<'static> {
+        WapmPackageKey {
+            name: Cow::Borrowed(name),
+            version: Version::parse(version).unwrap(),
+        }
+    }
+
+    #[test]
+    fn plans_list_new_and_updated_packages() {
+        let mut installed = LockfilePackages::default();
+        installed.packages.insert(
+            PackageKey::WapmPackage(key("_/sqlite", "0.1.0")),
+            LockfilePackage {
+                modules: vec![],
+                commands: vec![LockfileCommand {
+                    name: "sqlite".to_string(),
+                    package_name: "_/sqlite".to_string(),
+                    package_version: Version::new(0, 1, 0),
+                    module: "sqlite".to_string(),
+                    main_args: None,
+                    is_top_level_dependency: true,
+                    sandbox: None,
+                    description: None,
+                }],
+            },
+        );
+        let mut resolved = ResolvedPackages::default();
+        for (package, size, commands) in [
+            (key("_/sqlite", "0.2.0"), Some(2048), vec!["sqlite"]),
+            (
+                key("_/cowsay", "0.1.0"),
+                Some(1024),
+                vec!["cowsay", "cowthink"],
+            ),
+            (key("_/lua", "1.0.0"), None, vec![]),
+        ]
+        .iter()
+        .cloned()
+        {
+            resolved
+                .packages
+                .push((package.clone(), (String::new(), None)));
+            resolved.details.insert(
+                package,
+                PackageDetails {
+                    size,
+                    commands: Some(commands.into_iter().map(String::from).collect()),
+                },
+            );
+        }
+
+        let removed = RemovedLockfilePackages {
+            packages: vec![
+                (
+                    PackageKey::WapmPackage(key("_/sqlite", "0.1.0")),
+                    installed.packages[&PackageKey::WapmPackage(key("_/sqlite", "0.1.0"))].clone(),
+                ),
+                (
+                    PackageKey::WapmPackage(key("_/python", "0.1.0")),
+                    installed.packages[&PackageKey::WapmPackage(key("_/sqlite", "0.1.0"))].clone(),
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        };
+
+        let plan = InstallPlan::new(&installed, &resolved, &removed);
+        assert_eq!(plan.added, vec!["_/cowsay@0.1.0", "_/lua@1.0.0"]);
+        assert_eq!(
+            plan.updated,
+            vec![PackageUpdate {
+                name: "_/sqlite".to_string(),
+                from: "0.1.0".to_string(),
+                to: "0.2.0".to_string(),
+            }]
+        );
+        assert_eq!(plan.removed, vec!["_/python@0.1.0"]);
+        assert_eq!(plan.new_commands, vec!["cowsay", "cowthink"]);
+        assert_eq!(
+            plan.to_string(),
+            "2 new, 1 updated, 1 removed package(s), 3.0 KB to download (and 1 of unknown size)
+  + _/cowsay@0.1.0
+  + _/lua@1.0.0
+  ~ _/sqlite 0.1.0 -> 0.2.0
+  - _/python@0.1.0
+New commands: cowsay, cowthink"
+        );
+    }
+
+    #[test]
+    fn applied_plans_must_match_the_install() {
+        let package = |name: &str, version: &str| PlannedPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            download_url: format!("https://registry/{}-{}.tar.gz", name, version),
+            sha256: Some("abc".to_string()),
+        };
+        let plan = InstallPlan {
+            packages: vec![package("_/cowsay", "0.1.0"), package("_/lua", "1.0.0")],
+            ..Default::default()
+        };
+        let mut file = PlanFile {
+            format_version: PLAN_FORMAT_VERSION,
+            created_at: "2020-01-01T00:00:00+00:00".to_string(),
+            directory: PathBuf::from("/project"),
+            request: InstallRequest {
+                packages: vec!["_/cowsay".to_string()],
+                ..Default::default()
+            },
+            manifest_sha256: None,
+            lockfile_sha256: None,
+            plan: plan.clone(),
+        };
+        let parsed: PlanFile =
+            serde_json::from_str(&serde_json::to_string(&file).unwrap()).unwrap();
+        assert_eq!(parsed.plan, plan);
+        assert_eq!(parsed.request, file.request);
+
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("plan.json");
+        fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+        assert!(PlanFile::load(&path).is_ok());
+        file.plan.packages[1].sha256 = None;
+        fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+        assert!(matches!(
+            PlanFile::load(&path),
+            Err(PlanError::MissingChecksum(package)) if package == "_/lua@1.0.0"
+        ));
+
+        assert!(compare_packages(&plan.packages, &plan.packages).is_ok());
+        let mut moved = plan.packages.clone();
+        moved[0].download_url = "s3://bucket/archives/_/cowsay-0.1.0.tar.gz".to_string();
+        assert!(compare_packages(&plan.packages, &moved).is_ok());
+        let other = vec![package("_/cowsay", "0.1.0"), package("_/lua", "2.0.0")];
+        assert_eq!(
+            compare_packages(&plan.packages, &other)
+                .unwrap_err()
+                .to_string(),
+            "The install no longer resolves to the packages of the plan, make a new plan with \
+             `wapm install --plan`: _/lua@1.0.0 is no longer installed, _/lua@2.0.0 is not in \
+             the plan"
+        );
+    }
+}
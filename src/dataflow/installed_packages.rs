@@ -1,21 +1,33 @@
-use crate::data::manifest::Manifest;
+use crate::allowlist::Allowlist;
+use crate::archive::{self, ExtractionLimits, ExtractionSummary};
+use crate::archive_scan;
+use crate::config::Config;
+use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
 use crate::database;
+use crate::dataflow::hoisted_packages::is_linked_package_dir;
+use crate::dataflow::install_plan;
 use crate::dataflow::manifest_packages::ManifestResult;
 use crate::dataflow::resolved_packages::ResolvedPackages;
 use crate::dataflow::WapmPackageKey;
 use crate::graphql::VERSION;
+use crate::http_trace;
+use crate::integrity_exceptions::{Check, IntegrityExceptions};
+use crate::ipfs;
 use crate::keys;
+use crate::package_size;
+use crate::progress::{self, ProgressEvent};
 use crate::proxy;
+use crate::registry;
 use crate::util::{
     self, create_package_dir, fully_qualified_package_display_name, get_package_namespace_and_name,
 };
-use flate2::read::GzDecoder;
+use crate::wasm_store;
+use crate::webc;
 use reqwest::blocking::ClientBuilder;
 use std::fs::{self, OpenOptions};
 use std::io;
-use std::io::{Seek, SeekFrom};
+use std::io::{Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
-use tar::Archive;
 
 #[derive(Clone, Debug, Fail)]
 pub enum Error {
@@ -60,6 +72,17 @@ pub enum Error {
     IoConnectionError(String),
     #[fail(display = "Failed to validate package {} with key {}: {}", _0, _1, _2)]
     FailedToValidateSignature(String, String, String),
+    #[fail(display = "{}", _0)]
+    PackageTooLarge(String),
+    #[fail(
+        display = "The archive of package \"{}\" has the SHA-256 checksum {} instead of the {} of the {}",
+        _0, _3, _2, _1
+    )]
+    ChecksumMismatch(String, String, String, String),
+    #[fail(display = "{}", _0)]
+    NotAllowed(String),
+    #[fail(display = "The install of package \"{}\" was blocked. {}", _0, _1)]
+    ScanFailed(String, String),
 }
 
 /// A structure containing installed packages. Currently contains the key, the deserialized
@@ -75,6 +98,7 @@ impl<'a> InstalledPackages<'a> {
         directory: &Path,
         resolve_packages: ResolvedPackages<'a>,
         force_insecure_install: bool,
+        exceptions: &IntegrityExceptions,
     ) -> Result<Self, Error> {
         let packages_result: Result<Vec<(WapmPackageKey, PathBuf, String)>, Error> =
             resolve_packages
@@ -88,6 +112,7 @@ impl<'a> InstalledPackages<'a> {
                         &download_url,
                         signature,
                         force_insecure_install,
+                        exceptions,
                     )
                 })
                 .collect();
@@ -102,7 +127,7 @@ impl<'a> InstalledPackages<'a> {
                                 e.to_string(),
                             ));
                         }
-                        ManifestResult::Manifest(m) => m,
+                        ManifestResult::Manifest(m) => *m,
                         ManifestResult::NoManifest => {
                             return Err(Error::InstalledDependencyIsMissingManifest(
                                 key.clone().to_string(),
@@ -126,6 +151,7 @@ pub trait Install<'a> {
         download_url: &str,
         signature: Option<keys::WapmPackageSignature>,
         force_insecure_install: bool,
+        exceptions: &IntegrityExceptions,
     ) -> Result<(WapmPackageKey<'a>, PathBuf, String), Error>;
 }
 
@@ -136,14 +162,25 @@ impl RegistryInstaller {
         mut compressed_archive: F,
         pkg_name: P,
         key: &WapmPackageKey,
-    ) -> Result<(), failure::Error> {
+        limits: &ExtractionLimits,
+    ) -> Result<ExtractionSummary, failure::Error> {
         compressed_archive.seek(SeekFrom::Start(0))?;
-        let gz = GzDecoder::new(compressed_archive);
-        let mut archive = Archive::new(gz);
-        archive
-            .unpack(&pkg_name)
-            .map_err(|err| Error::DecompressionError(key.to_string(), format!("{}", err)))?;
-        Ok(())
+        let mut magic = [0; 5];
+        let is_webc = compressed_archive.read_exact(&mut magic).is_ok() && webc::is_webc(&magic);
+        compressed_archive.seek(SeekFrom::Start(0))?;
+        let decompression_error = |err: String| Error::DecompressionError(key.to_string(), err);
+        let summary = if is_webc {
+            // registries serving webc containers are installed from the same layout as archives
+            let mut container = vec![];
+            compressed_archive.read_to_end(&mut container)?;
+            let converted = webc::archive_from_download(container)
+                .map_err(|err| decompression_error(err.to_string()))?;
+            archive::unpack(&converted[..], pkg_name.as_ref(), limits)
+        } else {
+            archive::unpack(compressed_archive, pkg_name.as_ref(), limits)
+        }
+        .map_err(|err| decompression_error(err.to_string()))?;
+        Ok(summary)
     }
 }
 
@@ -156,9 +193,14 @@ struct PackageSignatureVerificationData {
 
 fn verify_integrity_of_package(
     namespace: &str,
+    key: &WapmPackageKey,
     fully_qualified_package_name: String,
     signature: Option<keys::WapmPackageSignature>,
+    exceptions: &IntegrityExceptions,
 ) -> Result<PackageSignatureVerificationData, Error> {
+    let version = key.version.to_string();
+    let excused =
+        |failure: &str| exceptions.excuses(&key.name, &version, Check::Signature, failure);
     let mut keys_db = database::open_db().map_err(|e| {
         Error::KeyManagementError(fully_qualified_package_name.clone(), e.to_string())
     })?;
@@ -220,6 +262,12 @@ fn verify_integrity_of_package(
                 ));
 
                 signature_to_use = Some(signature_data);
+            } else if excused(&format!(
+                "is signed with the key {} instead of the trusted key {}",
+                &public_key_id, &latest_local_key.public_key_id
+            )) {
+                // the new key is not trusted, so the package can't be verified with it
+                insecure_install = true;
             } else {
                 // mismatch, prompt user
                 let user_trusts_new_key =
@@ -265,6 +313,16 @@ Would you like to trust this key?",
         if let Some(latest_local_key) = latest_public_key {
             // Case 0-1: server does not have key and client has key
             // server error or scary things happening
+            if excused(&format!(
+                "is not signed although a key of {} is known",
+                &namespace
+            )) {
+                return Ok(PackageSignatureVerificationData {
+                    insecure_install: true,
+                    key_to_verify_package_with: None,
+                    signature_to_use: None,
+                });
+            }
             warn!(
                     "The server does not have a public key for {} for the package {} and the package is not signed but a public key for {} is known locally ({}).\nThis could mean that the wapm registry has been compromised, that the package was created before the publisher started signing their packages, or that the publisher decided not to sign this package.",
                     &namespace, &fully_qualified_package_name, &namespace, &latest_local_key.public_key_id
@@ -304,6 +362,7 @@ impl<'a> Install<'a> for RegistryInstaller {
         download_url: &str,
         signature: Option<keys::WapmPackageSignature>,
         force_insecure_install: bool,
+        exceptions: &IntegrityExceptions,
     ) -> Result<(WapmPackageKey<'a>, PathBuf, String), Error> {
         let (namespace, pkg_name) = get_package_namespace_and_name(&key.name)
             .map_err(|e| Error::FailedToParsePackageName(key.to_string(), e.to_string()))?;
@@ -311,41 +370,22 @@ impl<'a> Install<'a> for RegistryInstaller {
             fully_qualified_package_display_name(pkg_name, &key.version);
         let package_dir = create_package_dir(&directory, namespace, &fully_qualified_package_name)
             .map_err(|err| Error::IoErrorCreatingDirectory(key.to_string(), err.to_string()))?;
-        let client = {
-            let builder = ClientBuilder::new().gzip(false);
-            let builder = if let Some(proxy) = proxy::maybe_set_up_proxy()
-                .map_err(|e| Error::IoConnectionError(format!("{}", e)))?
-            {
-                builder.proxy(proxy)
-            } else {
-                builder
-            };
-
-            builder.build().unwrap()
-        };
-        let user_agent = format!(
-            "wapm/{} {} {}",
-            VERSION,
-            whoami::platform(),
-            whoami::os().to_lowercase(),
-        );
-        let mut response = client
-            .get(download_url)
-            .header(reqwest::header::USER_AGENT, user_agent)
-            .send()
-            .map_err(|e| {
-                let error_message = e.to_string();
-                #[cfg(feature = "telemetry")]
-                {
-                    let e = e.into();
-                    sentry::integrations::failure::capture_error(&e);
-                }
-                Error::DownloadError(key.to_string(), error_message)
-            })?;
+        // packages hoisted into the root of a workspace are already linked into the members
+        if is_linked_package_dir(&package_dir) && package_dir.join(MANIFEST_FILE_NAME).is_file() {
+            debug!("Using package {} hoisted into the workspace root", key);
+            progress::emit(ProgressEvent::PackageReused {
+                name: key.name.to_string(),
+                version: key.version.to_string(),
+            });
+            return Ok((key, package_dir, download_url.to_string()));
+        }
+        let mut response = open_archive(&key, download_url)?;
+        let package_version = key.version.to_string();
+        let package_name = key.name.to_string();
 
         // step to perform after package is decompressed: may be a no-op or may
         // execute side effects such as logging to the user.
-        let mut key_sign_end_step: Box<dyn FnMut(&mut fs::File) -> Result<(), Error>> =
+        let mut key_sign_end_step: Box<dyn FnMut(&mut fs::File) -> Result<(), Error> + '_> =
             if !force_insecure_install {
                 let PackageSignatureVerificationData {
                     insecure_install,
@@ -353,8 +393,10 @@ impl<'a> Install<'a> for RegistryInstaller {
                     signature_to_use,
                 } = verify_integrity_of_package(
                     namespace,
+                    &key,
                     fully_qualified_package_name.clone(),
                     signature,
+                    exceptions,
                 )?;
 
                 if insecure_install {
@@ -367,15 +409,24 @@ impl<'a> Install<'a> for RegistryInstaller {
                         let signature_to_use = signature_to_use
                             .clone()
                             .expect("Critical internal logic error");
-                        verify_signature_on_package(&pkv, &signature_to_use, &mut dest).map_err(
-                            |e| {
-                                Error::FailedToValidateSignature(
-                                    fully_qualified_package_name.clone(),
-                                    pk_id,
-                                    e.to_string(),
-                                )
-                            },
-                        )?;
+                        if let Err(e) =
+                            verify_signature_on_package(&pkv, &signature_to_use, &mut dest)
+                        {
+                            let failure = format!("failed the check of its signature: {}", e);
+                            if exceptions.excuses(
+                                &package_name,
+                                &package_version,
+                                Check::Signature,
+                                &failure,
+                            ) {
+                                return Ok(());
+                            }
+                            return Err(Error::FailedToValidateSignature(
+                                fully_qualified_package_name.clone(),
+                                pk_id,
+                                e.to_string(),
+                            ));
+                        }
                         info!(
                             "Signature of package {} verified!",
                             &fully_qualified_package_name
@@ -401,22 +452,186 @@ impl<'a> Install<'a> for RegistryInstaller {
             .create(true)
             .open(&temp_tar_gz_path)
             .map_err(|e| Error::IoCopyError(key.to_string(), e.to_string()))?;
-        io::copy(&mut response, &mut dest)
+        let bytes = io::copy(&mut response, &mut dest)
             .map_err(|e| Error::DownloadError(key.to_string(), e.to_string()))?;
+        progress::emit(ProgressEvent::PackageDownloaded {
+            name: key.name.to_string(),
+            version: key.version.to_string(),
+            bytes,
+        });
 
         key_sign_end_step(&mut dest)?;
 
-        Self::decompress_and_extract_archive(dest, &package_dir, &key)
-            .map_err(|e| Error::DecompressionError(key.to_string(), e.to_string()))?;
+        // an applied plan pins the exact archive that was reviewed, and an allowlist the
+        // archives that were vetted
+        let planned_sha256 =
+            install_plan::planned_sha256(&key).map_err(|e| Error::InstallAborted(e.to_string()))?;
+        let allowlist = Allowlist::load().map_err(|e| Error::NotAllowed(e.to_string()))?;
+        let allowed_sha256 = allowlist
+            .as_ref()
+            .map(|allowlist| {
+                allowlist
+                    .sha256(&key.name, &key.version.to_string())
+                    .map(str::to_string)
+                    .ok_or_else(|| Error::NotAllowed(format!("{} is not in the allowlist", key)))
+            })
+            .transpose()?;
+        if planned_sha256.is_some() || allowed_sha256.is_some() {
+            let mut data = vec![];
+            dest.seek(SeekFrom::Start(0))
+                .and_then(|_| dest.read_to_end(&mut data))
+                .map_err(|e| Error::IoCopyError(key.to_string(), e.to_string()))?;
+            let sha256 = util::sha256_hex(&data);
+            if let Some(expected) = planned_sha256.filter(|expected| *expected != sha256) {
+                return Err(Error::ChecksumMismatch(
+                    key.to_string(),
+                    "plan".to_string(),
+                    expected,
+                    sha256,
+                ));
+            }
+            if let Some(expected) = allowed_sha256.filter(|expected| *expected != sha256) {
+                return Err(Error::ChecksumMismatch(
+                    key.to_string(),
+                    "allowlist".to_string(),
+                    expected,
+                    sha256,
+                ));
+            }
+        }
+
+        let size_limit = package_size::size_limit();
+        let package = format!("{}@{}", key.name, key.version);
+        // the archive sizes the registry reported were already warned about when resolving
+        if size_limit.enforced {
+            size_limit
+                .check(&package, "archive", bytes)
+                .map_err(Error::PackageTooLarge)?;
+        }
+        archive_scan::scan(&temp_tar_gz_path, &package)
+            .map_err(|e| Error::ScanFailed(key.to_string(), e.to_string()))?;
+        let extracted = Self::decompress_and_extract_archive(
+            dest,
+            &package_dir,
+            &key,
+            &size_limit.extraction_limits(),
+        )
+        .map_err(|e| Error::DecompressionError(key.to_string(), e.to_string()))?;
+        size_limit
+            .check(&package, "extracted files", extracted.bytes)
+            .map_err(Error::PackageTooLarge)?;
+        progress::emit(ProgressEvent::PackageExtracted {
+            name: key.name.to_string(),
+            version: key.version.to_string(),
+        });
+        dedup_modules(&key, &package_dir);
         Ok((key, package_dir, download_url.to_string()))
     }
 }
 
+/// Open the archive of a package: from an IPFS gateway when enabled, from the directory of a
+/// local static registry, or else from its download url
+fn open_archive(key: &WapmPackageKey, download_url: &str) -> Result<Box<dyn io::Read>, Error> {
+    let client = {
+        let builder = ClientBuilder::new().gzip(false);
+        let builder = if let Some(proxy) =
+            proxy::maybe_set_up_proxy().map_err(|e| Error::IoConnectionError(format!("{}", e)))?
+        {
+            builder.proxy(proxy)
+        } else {
+            builder
+        };
+
+        builder.build().unwrap()
+    };
+    let user_agent = format!(
+        "wapm/{} {} {}",
+        VERSION,
+        whoami::platform(),
+        whoami::os().to_lowercase(),
+    );
+    let ipfs_archive = if ipfs::is_enabled() {
+        ipfs::fetch_package(&client, &key.name, &key.version.to_string(), &user_agent)
+    } else {
+        None
+    };
+    // archives of static registries in a local directory are read directly
+    let local_archive = registry::file_url_path(download_url);
+    let download_url = registry::fetch_url(download_url)
+        .map_err(|e| Error::DownloadError(key.to_string(), e.to_string()))?;
+    let response: Box<dyn io::Read> = match (ipfs_archive, local_archive) {
+        (Some(archive), _) => Box::new(io::Cursor::new(archive)),
+        (None, Some(path)) => Box::new(
+            fs::File::open(path)
+                .map_err(|e| Error::DownloadError(key.to_string(), e.to_string()))?,
+        ),
+        (None, None) => Box::new(
+            http_trace::send(
+                &client,
+                client
+                    .get(&download_url)
+                    .header(reqwest::header::USER_AGENT, user_agent),
+                None,
+            )
+            .map_err(|e| {
+                let error_message = e.to_string();
+                #[cfg(feature = "telemetry")]
+                {
+                    let e = e.into();
+                    sentry::integrations::failure::capture_error(&e);
+                }
+                Error::DownloadError(key.to_string(), error_message)
+            })?,
+        ),
+    };
+    Ok(response)
+}
+
+/// Download the archive of a package without extracting it
+pub fn download_archive(key: &WapmPackageKey, download_url: &str) -> Result<Vec<u8>, Error> {
+    let mut data = vec![];
+    open_archive(key, download_url)?
+        .read_to_end(&mut data)
+        .map_err(|e| Error::DownloadError(key.to_string(), e.to_string()))?;
+    Ok(data)
+}
+
+/// The SHA-256 checksum of the archive of a package, downloading it
+pub fn archive_sha256(key: &WapmPackageKey, download_url: &str) -> Result<String, Error> {
+    Ok(util::sha256_hex(&download_archive(key, download_url)?))
+}
+
+/// Link the modules of a freshly extracted package that other packages have too to the module
+/// store. This only saves space, so failing to is not an error.
+fn dedup_modules(key: &WapmPackageKey, package_dir: &Path) {
+    let enabled = Config::from_file()
+        .map(|config| config.install.dedup_modules())
+        .unwrap_or(false);
+    if !enabled {
+        return;
+    }
+    let result = Config::get_wasm_store_directory()
+        .map_err(|e| e.to_string())
+        .and_then(|store| {
+            wasm_store::dedup_modules(&store, package_dir).map_err(|e| e.to_string())
+        });
+    match result {
+        Ok(summary) if summary.modules > 0 => progress::emit(ProgressEvent::ModulesDeduplicated {
+            name: key.name.to_string(),
+            version: key.version.to_string(),
+            modules: summary.modules,
+            bytes_saved: summary.bytes,
+        }),
+        Ok(_) => {}
+        Err(e) => debug!("Could not deduplicate the modules of {}: {}", key, e),
+    }
+}
+
 /// Verifies the signature of a downloaded package archive
-fn verify_signature_on_package(
+pub(crate) fn verify_signature_on_package<R: Read + Seek>(
     pkv: &str,
     signature_to_use: &str,
-    dest: &mut fs::File,
+    dest: &mut R,
 ) -> Result<(), failure::Error> {
     dest.seek(SeekFrom::Start(0))?;
     // TODO: refactor to remove extra bit of info here
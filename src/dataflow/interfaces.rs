@@ -1,4 +1,4 @@
-use crate::graphql::execute_query;
+use crate::graphql::{execute_query, DateTime};
 use graphql_client::*;
 
 #[derive(GraphQLQuery)]
@@ -40,3 +40,42 @@ impl InterfaceFromServer {
         })
     }
 }
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/queries/get_interface.graphql",
+    response_derives = "Debug"
+)]
+struct GetInterfaceQuery;
+
+/// An interface in the registry's catalog and its published versions
+#[derive(Debug)]
+pub struct InterfaceListing {
+    pub name: String,
+    pub description: String,
+    pub last_version: Option<String>,
+    /// The versions and when they were published
+    pub versions: Vec<(String, String)>,
+}
+
+impl InterfaceListing {
+    /// The interface with the given name, if the registry has it
+    pub fn get(name: String) -> Result<Option<Self>, failure::Error> {
+        let q = GetInterfaceQuery::build_query(get_interface_query::Variables { name });
+        let response: get_interface_query::ResponseData = execute_query(&q)?;
+        Ok(response.interface.map(|interface| Self {
+            name: interface.name,
+            description: interface.description,
+            last_version: interface.last_version.map(|version| version.version),
+            versions: interface
+                .versions
+                .edges
+                .into_iter()
+                .flatten()
+                .filter_map(|edge| edge.node)
+                .map(|node| (node.version, node.created_at))
+                .collect(),
+        }))
+    }
+}
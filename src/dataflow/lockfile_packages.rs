@@ -5,9 +5,11 @@ use crate::data::lock::migrate::{
     convert_lockfilev2_to_v3, convert_lockfilev3_to_v4, fix_up_v1_package_names, LockfileVersion,
 };
 use crate::data::lock::LOCKFILE_NAME;
+use crate::dataflow::github_release;
 use crate::dataflow::installed_packages::InstalledPackages;
 use crate::dataflow::removed_packages::RemovedPackages;
 use crate::dataflow::{PackageKey, WapmPackageKey};
+use crate::oci;
 use std::collections::hash_map::HashMap;
 use std::collections::hash_set::HashSet;
 use std::fs;
@@ -58,7 +60,12 @@ impl LockfileResult {
             Ok(s) => s,
             Err(_) => return LockfileResult::NoLockfile,
         };
-        let mut lockfile_version = match LockfileVersion::from_lockfile_string(&source) {
+        Self::from_source(&source, directory)
+    }
+
+    /// Parse the source of a lockfile of the directory, migrating it to the latest version
+    pub fn from_source(source: &str, directory: &Path) -> Self {
+        let mut lockfile_version = match LockfileVersion::from_lockfile_string(source) {
             Ok(lv) => lv,
             Err(e) => return LockfileResult::LockfileError(e),
         };
@@ -93,6 +100,21 @@ pub struct LockfilePackage {
     pub commands: Vec<LockfileCommand>,
 }
 
+impl LockfilePackage {
+    /// Packages installed from GitHub releases or OCI registries are not listed in the manifest
+    /// and can't be resolved with the registry
+    pub fn is_from_external_source(&self) -> bool {
+        self.modules.iter().any(|module| {
+            module
+                .resolved_source
+                .starts_with(github_release::GITHUB_RESOLVED_SOURCE_PREFIX)
+                || module
+                    .resolved_source
+                    .starts_with(oci::OCI_RESOLVED_SOURCE_PREFIX)
+        })
+    }
+}
+
 /// A wrapper around a map of key -> lockfile package.
 #[derive(Clone, Debug, Default)]
 pub struct LockfilePackages<'a> {
@@ -198,6 +220,7 @@ impl<'a> LockfilePackages<'a> {
         let missing_packages: HashSet<PackageKey<'a>> = self
             .packages
             .iter()
+            .filter(|(_, data)| !data.is_from_external_source())
             .filter_map(|(key, data)| {
                 if data.modules.iter().any(|module| {
                     let path = module.get_canonical_source_path_from_lockfile_dir(directory.into());
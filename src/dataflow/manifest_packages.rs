@@ -1,4 +1,5 @@
 use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
+use crate::data::workspace::is_workspace_only_manifest;
 use crate::dataflow::added_packages::AddedPackages;
 use crate::dataflow::removed_packages::RemovedPackages;
 use crate::dataflow::{normalize_global_namespace, PackageKey, WapmPackageKey};
@@ -23,7 +24,7 @@ pub enum Error {
 /// A ternary for a manifest: Some, None, Error.
 #[derive(Debug)]
 pub enum ManifestResult {
-    Manifest(Manifest),
+    Manifest(Box<Manifest>),
     NoManifest,
     ManifestError(Error),
 }
@@ -46,11 +47,11 @@ impl ManifestResult {
             Ok(s) => s,
             Err(_) => return ManifestResult::NoManifest,
         };
-        match toml::from_str::<Manifest>(&source) {
-            Ok(mut m) => {
-                m.base_directory_path = directory.to_owned();
-                ManifestResult::Manifest(m)
-            }
+        if is_workspace_only_manifest(&source) {
+            return ManifestResult::NoManifest;
+        }
+        match Manifest::from_source(directory, &source) {
+            Ok(m) => ManifestResult::Manifest(Box::new(m)),
             Err(e) => ManifestResult::ManifestError(Error::ManifestTomlParseError(e.to_string())),
         }
     }
@@ -106,19 +107,24 @@ impl<'a> ManifestPackages<'a> {
         }
     }
 
-    /// Extract package keys from the manifest
+    /// Extract package keys from the manifest, including the dependencies of the `[target]`
+    /// sections whose conditions hold
     fn extract_package_keys(manifest: &'a Manifest) -> Result<Vec<PackageKey<'a>>, Error> {
-        match manifest.dependencies {
-            Some(ref dependencies) => {
-                let result = dependencies
-                    .iter()
-                    .map(|(name, value)| (name.as_str(), value.as_str()))
-                    .map(Self::parse_wapm_package_key)
-                    .collect::<Result<Vec<_>, Error>>()?;
-                Ok(result)
-            }
-            None => Ok(vec![]),
-        }
+        let target_dependencies = manifest
+            .target_dependencies()
+            .map_err(|e| Error::ManifestTomlParseError(e.to_string()))?
+            .into_iter()
+            .map(|(_, name, version)| (name, version));
+        manifest
+            .dependencies
+            .iter()
+            .flatten()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .chain(target_dependencies)
+            // pinned dependencies use the version requirement of their pin
+            .map(|(name, version)| (name, manifest.dependency_requirement(name, version)))
+            .map(Self::parse_wapm_package_key)
+            .collect()
     }
 
     /// Parse a raw pair of strings as an exact wapm package or a range. May fail with a semver
@@ -1,8 +1,9 @@
 use crate::data::lock::lockfile::{CommandMap, Lockfile, ModuleMap};
 use crate::dataflow::bin_script::save_bin_script;
-use crate::dataflow::lockfile_packages::{LockfilePackage, LockfilePackages};
+use crate::dataflow::lockfile_packages::{LockfilePackage, LockfilePackages, LockfileResult};
 use crate::dataflow::retained_lockfile_packages::RetainedLockfilePackages;
 use crate::dataflow::{PackageKey, WapmPackageKey};
+use crate::diagnostics::{self, Warning};
 use std::collections::btree_map::BTreeMap;
 use std::collections::hash_map::HashMap;
 use std::path::Path;
@@ -48,9 +49,33 @@ impl<'a> MergedLockfilePackages<'a> {
         Self { packages }
     }
 
-    pub fn generate_lockfile(self, directory: &'a Path) -> Result<(), Error> {
+    /// Record the `[target]` condition that packages were installed for in their lockfile
+    /// modules. `conditions` maps package names to conditions.
+    pub fn annotate_conditions(&mut self, conditions: &HashMap<String, String>) {
+        for (key, package) in self.packages.iter_mut() {
+            if let PackageKey::WapmPackage(WapmPackageKey { name, .. }) = key {
+                let condition = conditions.get(name.as_ref());
+                for module in package.modules.iter_mut() {
+                    module.condition = condition.cloned();
+                }
+            }
+        }
+    }
+
+    /// Save the lockfile. Bin scripts are only created for packages that have commands, and
+    /// not at all if `create_bin_scripts` is false.
+    pub fn generate_lockfile(
+        self,
+        directory: &'a Path,
+        create_bin_scripts: bool,
+    ) -> Result<(), Error> {
         let mut modules: ModuleMap = BTreeMap::new();
         let mut commands: CommandMap = BTreeMap::new();
+        // new versions of packages keep the sandbox profiles of their commands
+        let previous_commands = match LockfileResult::find_in_directory(directory) {
+            LockfileResult::Lockfile(lockfile) => lockfile.commands,
+            _ => CommandMap::new(),
+        };
         for (key, package) in self.packages {
             match key {
                 PackageKey::WapmPackage(WapmPackageKey { name, version }) => {
@@ -60,13 +85,32 @@ impl<'a> MergedLockfilePackages<'a> {
                         let name = module.name.clone();
                         modules.insert(name, module);
                     }
-                    for command in package.commands {
+                    for mut command in package.commands {
+                        if command.sandbox.is_none() {
+                            command.sandbox = previous_commands
+                                .get(&command.name)
+                                .filter(|previous| previous.package_name == command.package_name)
+                                .and_then(|previous| previous.sandbox.clone());
+                        }
                         let name = command.name.clone();
                         let script_name = command.name.clone();
-                        commands.insert(name, command);
-                        // save the bin script to execute this command from the terminal
-                        save_bin_script(directory, script_name)
-                            .map_err(|e| Error::FailedToSaveLockfile(e.to_string()))?;
+                        if create_bin_scripts {
+                            // save the bin script to execute this command from the terminal
+                            save_bin_script(directory, script_name, command.sandbox.as_deref())
+                                .map_err(|e| Error::FailedToSaveLockfile(e.to_string()))?;
+                        }
+                        let package_name = command.package_name.clone();
+                        if let Some(previous) = commands.insert(name, command) {
+                            if previous.package_name != package_name {
+                                diagnostics::warn(
+                                    Warning::CommandCollision,
+                                    format_args!(
+                                        "The command {} of {} replaces the one of {}",
+                                        previous.name, package_name, previous.package_name
+                                    ),
+                                );
+                            }
+                        }
                     }
                 }
                 PackageKey::WapmPackageRange(_) => {
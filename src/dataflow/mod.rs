@@ -1,6 +1,11 @@
 use crate::data::manifest::Manifest;
+use crate::data::workspace::Workspace;
 use crate::dataflow::added_packages::AddedPackages;
 use crate::dataflow::changed_manifest_packages::ChangedManifestPackages;
+use crate::dataflow::external_package::ExternalArchive;
+use crate::dataflow::github_release::GithubRelease;
+use crate::dataflow::hoisted_packages::{unlink_hoisted_packages, HoistedPackages};
+use crate::dataflow::install_plan::{InstallPlan, PlanExport};
 use crate::dataflow::installed_packages::{InstalledPackages, RegistryInstaller};
 use crate::dataflow::local_package::LocalPackage;
 use crate::dataflow::lockfile_packages::{LockfileError, LockfilePackages, LockfileResult};
@@ -8,8 +13,13 @@ use crate::dataflow::manifest_packages::{ManifestPackages, ManifestResult};
 use crate::dataflow::merged_lockfile_packages::MergedLockfilePackages;
 use crate::dataflow::removed_lockfile_packages::RemovedLockfilePackages;
 use crate::dataflow::removed_packages::RemovedPackages;
-use crate::dataflow::resolved_packages::{RegistryResolver, ResolvedPackages};
+use crate::dataflow::resolved_packages::{PackageDetails, RegistryResolver, ResolvedPackages};
 use crate::dataflow::retained_lockfile_packages::RetainedLockfilePackages;
+use crate::diagnostics::{self, Warning};
+use crate::integrity_exceptions::{IntegrityExceptionError, IntegrityExceptions};
+use crate::oci::{self, OciReference};
+use crate::progress::{self, ProgressEvent};
+use chrono::Local;
 use semver::{Version, VersionReq};
 use std::borrow::{Borrow, Cow};
 use std::collections::{HashMap, HashSet};
@@ -19,17 +29,24 @@ use std::path::Path;
 pub mod added_packages;
 pub mod bin_script;
 pub mod changed_manifest_packages;
+pub mod editor_metadata;
+pub mod external_package;
 pub mod find_command_result;
+pub mod github_release;
+pub mod hoisted_packages;
+pub mod install_plan;
 pub mod installed_packages;
 pub mod interfaces;
 pub mod local_package;
 pub mod lockfile_packages;
 pub mod manifest_packages;
 pub mod merged_lockfile_packages;
+pub mod oci_package;
 pub mod removed_lockfile_packages;
 pub mod removed_packages;
 pub mod resolved_packages;
 pub mod retained_lockfile_packages;
+pub mod workspace_lockfiles;
 
 #[derive(Clone, Debug, Fail)]
 pub enum Error {
@@ -56,6 +73,46 @@ pub enum Error {
         _0, _1, _2
     )]
     DuplicatePackage(String, String, String),
+    #[fail(display = "Could not hoist workspace dependencies. {}", _0)]
+    HoistError(hoisted_packages::Error),
+    #[fail(display = "Could not write the editor metadata. {}", _0)]
+    EditorMetadataError(editor_metadata::Error),
+    #[fail(display = "Could not install from GitHub. {}", _0)]
+    GithubReleaseError(github_release::Error),
+    #[fail(display = "Could not install the package. {}", _0)]
+    ExternalPackageError(external_package::Error),
+    #[fail(display = "Could not install from the OCI registry. {}", _0)]
+    OciError(oci::OciError),
+    #[fail(display = "Could not show what the install will do. {}", _0)]
+    PlanError(String),
+    #[fail(display = "Could not load the integrity exceptions. {}", _0)]
+    IntegrityExceptionsError(IntegrityExceptionError),
+    #[fail(display = "The install was cancelled")]
+    Cancelled,
+}
+
+/// Options controlling how packages are installed by `update`.
+#[derive(Clone, Debug)]
+pub struct UpdateOptions {
+    /// Create scripts in `wapm_packages/.bin` for the commands of installed packages
+    pub create_bin_scripts: bool,
+    /// Describe the installed packages for editors in `wapm_packages/.metadata.json`
+    pub write_editor_metadata: bool,
+    /// Show what will be installed and ask to go ahead before changing anything
+    pub confirm_plan: bool,
+    /// Write what would be installed to a file instead of installing it
+    pub export_plan: Option<PlanExport>,
+}
+
+impl Default for UpdateOptions {
+    fn default() -> Self {
+        Self {
+            create_bin_scripts: true,
+            write_editor_metadata: false,
+            confirm_plan: false,
+            export_plan: None,
+        }
+    }
 }
 
 /// A package key for a package in the wapm.io registry.
@@ -183,6 +240,47 @@ pub fn normalize_global_namespace(key: PackageKey) -> PackageKey {
     new_key
 }
 
+/// Work out what installing the resolved packages will do, then export the plan, check it
+/// against the plan being applied or show it and stop if the user doesn't want to go ahead.
+/// Returns whether to go ahead with the install.
+fn review_plan(
+    directory: &Path,
+    installed: &LockfilePackages,
+    resolved: &ResolvedPackages,
+    removed: &RemovedLockfilePackages,
+    options: &UpdateOptions,
+) -> Result<bool, Error> {
+    let plan = InstallPlan::new(installed, resolved, removed);
+    if let Some(export) = &options.export_plan {
+        install_plan::export(export, directory, plan)
+            .map_err(|e| Error::PlanError(e.to_string()))?;
+        return Ok(false);
+    }
+    if install_plan::is_applying() {
+        install_plan::check_applied_plan(&plan).map_err(|e| Error::PlanError(e.to_string()))?;
+        if !plan.is_empty() {
+            println!("{}", plan);
+        }
+    } else if options.confirm_plan
+        && !plan
+            .confirm()
+            .map_err(|e| Error::PlanError(e.to_string()))?
+    {
+        return Err(Error::Cancelled);
+    }
+    Ok(true)
+}
+
+/// Report the installed packages as linked once they are in the lockfile
+fn report_linked_packages(installed_packages: &InstalledPackages) {
+    for (key, _, _) in installed_packages.packages.iter() {
+        progress::emit(ProgressEvent::PackageLinked {
+            name: key.name.to_string(),
+            version: key.version.to_string(),
+        });
+    }
+}
+
 /// If there is no mainfest, then this is a non-manifest project. All installations are retained
 /// in the lockfile, and installs are additive.
 /// This function returns a bool on success indicating if any changes were applied
@@ -190,6 +288,7 @@ pub fn update_with_no_manifest<P: AsRef<Path>>(
     directory: P,
     added_packages: AddedPackages,
     removed_packages: RemovedPackages,
+    options: &UpdateOptions,
 ) -> Result<bool, Error> {
     let directory = directory.as_ref();
     // get lockfile data
@@ -206,11 +305,6 @@ pub fn update_with_no_manifest<P: AsRef<Path>>(
         &lockfile_packages,
     );
 
-    // cleanup any old artifacts
-    removed_lockfile_packages
-        .cleanup_old_packages(&directory)
-        .map_err(Error::CleanupError)?;
-
     // remove/uninstall packages
     lockfile_packages.remove_packages(removed_packages);
 
@@ -222,12 +316,33 @@ pub fn update_with_no_manifest<P: AsRef<Path>>(
     let missing_packages = lockfile_packages.find_missing_packages(&directory);
     let added_packages = added_packages.add_missing_packages(missing_packages);
 
+    let exceptions =
+        IntegrityExceptions::load(directory).map_err(Error::IntegrityExceptionsError)?;
     let resolved_packages =
-        ResolvedPackages::new_from_added_packages::<RegistryResolver>(added_packages)
+        ResolvedPackages::new_from_added_packages::<RegistryResolver>(added_packages, &exceptions)
             .map_err(Error::ResolveError)?;
-    let installed_packages =
-        InstalledPackages::install::<RegistryInstaller>(&directory, resolved_packages, false)
-            .map_err(Error::InstallError)?;
+    if !review_plan(
+        directory,
+        &lockfile_packages,
+        &resolved_packages,
+        &removed_lockfile_packages,
+        options,
+    )? {
+        return Ok(false);
+    }
+
+    // cleanup any old artifacts
+    removed_lockfile_packages
+        .cleanup_old_packages(&directory)
+        .map_err(Error::CleanupError)?;
+
+    let installed_packages = InstalledPackages::install::<RegistryInstaller>(
+        &directory,
+        resolved_packages,
+        false,
+        &exceptions,
+    )
+    .map_err(Error::InstallError)?;
     let added_lockfile_data = LockfilePackages::from_installed_packages(&installed_packages)
         .map_err(Error::LockfileError)?;
 
@@ -238,14 +353,17 @@ pub fn update_with_no_manifest<P: AsRef<Path>>(
     let final_lockfile_data =
         MergedLockfilePackages::merge(added_lockfile_data, retained_lockfile_packages);
     let final_package_keys: HashSet<_> = final_lockfile_data.packages.keys().cloned().collect();
-    if final_package_keys != initial_package_keys {
+    let changes_applied = final_package_keys != initial_package_keys;
+    if changes_applied {
         final_lockfile_data
-            .generate_lockfile(&directory)
+            .generate_lockfile(&directory, options.create_bin_scripts)
             .map_err(Error::GenerateLockfileError)?;
-        Ok(true)
-    } else {
-        Ok(false)
     }
+    report_linked_packages(&installed_packages);
+    if options.write_editor_metadata {
+        editor_metadata::write_editor_metadata(directory).map_err(Error::EditorMetadataError)?;
+    }
+    Ok(changes_applied)
 }
 
 /// If there is a manifest, then we construct lockfile data from manifest dependencies, and merge
@@ -256,9 +374,21 @@ pub fn update_with_manifest<P: AsRef<Path>>(
     manifest: Manifest,
     added_packages: AddedPackages,
     removed_packages: RemovedPackages,
+    options: &UpdateOptions,
 ) -> Result<bool, Error> {
     let directory = directory.as_ref();
 
+    for (name, pin) in manifest.expired_pins(Local::now().naive_local().date()) {
+        diagnostics::warn(
+            Warning::ExpiredPin,
+            format_args!(
+                "The pin of {} has expired, check whether it is still needed: {}",
+                name,
+                pin.describe(name)
+            ),
+        );
+    }
+
     let mut manifest_packages =
         ManifestPackages::new_from_manifest_and_added_packages(&manifest, &added_packages)
             .map_err(Error::ManifestError)?;
@@ -275,6 +405,22 @@ pub fn update_with_manifest<P: AsRef<Path>>(
     // store lockfile package keys before updating it
     let initial_package_keys = lockfile_packages.package_keys();
 
+    // packages installed from GitHub releases or OCI registries are not in the manifest, but are
+    // kept until removed
+    let external_package_keys: Vec<_> = lockfile_packages
+        .packages
+        .iter()
+        .filter(|(_, data)| data.is_from_external_source())
+        .map(|(key, _)| key.clone())
+        .filter(|key| match key {
+            PackageKey::WapmPackage(WapmPackageKey { name, .. }) => {
+                !removed_packages.packages.contains(name)
+            }
+            _ => true,
+        })
+        .collect();
+    manifest_packages.packages.extend(external_package_keys);
+
     // get the local package modules and commands from the manifest
     let local_package = LocalPackage::new_from_local_package_in_manifest(&manifest)
         .map_err(Error::LocalPackageError)?;
@@ -295,6 +441,23 @@ pub fn update_with_manifest<P: AsRef<Path>>(
     let removed_lockfile_packages =
         RemovedLockfilePackages::from_manifest_and_lockfile(&manifest_packages, &lockfile_packages);
 
+    let exceptions =
+        IntegrityExceptions::load(directory).map_err(Error::IntegrityExceptionsError)?;
+    let resolved_manifest_packages = ResolvedPackages::new_from_added_packages::<RegistryResolver>(
+        new_added_packages,
+        &exceptions,
+    )
+    .map_err(Error::ResolveError)?;
+    if !review_plan(
+        directory,
+        &lockfile_packages,
+        &resolved_manifest_packages,
+        &removed_lockfile_packages,
+        options,
+    )? {
+        return Ok(false);
+    }
+
     // cleanup any old artifacts
     removed_lockfile_packages
         .cleanup_old_packages(&directory)
@@ -302,14 +465,11 @@ pub fn update_with_manifest<P: AsRef<Path>>(
 
     let retained_lockfile_packages =
         RetainedLockfilePackages::from_manifest_and_lockfile(&manifest_packages, lockfile_packages);
-
-    let resolved_manifest_packages =
-        ResolvedPackages::new_from_added_packages::<RegistryResolver>(new_added_packages)
-            .map_err(Error::ResolveError)?;
     let installed_manifest_packages = InstalledPackages::install::<RegistryInstaller>(
         &directory,
         resolved_manifest_packages,
         false,
+        &exceptions,
     )
     .map_err(Error::InstallError)?;
     let mut manifest_lockfile_data =
@@ -319,13 +479,35 @@ pub fn update_with_manifest<P: AsRef<Path>>(
     manifest_lockfile_data.extend(local_package.into());
 
     // merge the lockfile data, and generate the new lockfile
-    let final_lockfile_data =
+    let mut final_lockfile_data =
         MergedLockfilePackages::merge(manifest_lockfile_data, retained_lockfile_packages);
     let final_package_keys: HashSet<_> = final_lockfile_data.packages.keys().cloned().collect();
 
+    // annotate the packages that were installed for a `[target]` section
+    let target_conditions: HashMap<String, String> = manifest
+        .target_dependencies()
+        .map_err(|e| {
+            Error::ManifestError(manifest_packages::Error::ManifestTomlParseError(
+                e.to_string(),
+            ))
+        })?
+        .into_iter()
+        .map(|(condition, name, _)| {
+            (
+                normalize_global_namespace_package_name(name.into()).to_string(),
+                condition.to_string(),
+            )
+        })
+        .collect();
+    final_lockfile_data.annotate_conditions(&target_conditions);
+
     final_lockfile_data
-        .generate_lockfile(&directory)
+        .generate_lockfile(&directory, options.create_bin_scripts)
         .map_err(Error::GenerateLockfileError)?;
+    report_linked_packages(&installed_manifest_packages);
+    if options.write_editor_metadata {
+        editor_metadata::write_editor_metadata(directory).map_err(Error::EditorMetadataError)?;
+    }
 
     // update the manifest, if applicable
     if final_package_keys != initial_package_keys {
@@ -342,6 +524,21 @@ pub fn update<P: AsRef<Path>>(
     added_packages: Vec<(&str, &str)>,
     removed_packages: Vec<&str>,
     directory: P,
+) -> Result<bool, Error> {
+    update_with_options(
+        added_packages,
+        removed_packages,
+        directory,
+        &UpdateOptions::default(),
+    )
+}
+
+/// Like `update`, but with control over how packages are installed.
+pub fn update_with_options<P: AsRef<Path>>(
+    added_packages: Vec<(&str, &str)>,
+    removed_packages: Vec<&str>,
+    directory: P,
+    options: &UpdateOptions,
 ) -> Result<bool, Error> {
     let directory = directory.as_ref();
     let added_packages =
@@ -350,15 +547,174 @@ pub fn update<P: AsRef<Path>>(
     let manifest_result = ManifestResult::find_in_directory(&directory);
     match manifest_result {
         ManifestResult::NoManifest => {
-            update_with_no_manifest(directory, added_packages, removed_packages)
-        }
-        ManifestResult::Manifest(manifest) => {
-            update_with_manifest(directory, manifest, added_packages, removed_packages)
+            update_with_no_manifest(directory, added_packages, removed_packages, options)
         }
+        ManifestResult::Manifest(manifest) => update_with_manifest(
+            directory,
+            *manifest,
+            added_packages,
+            removed_packages,
+            options,
+        ),
         ManifestResult::ManifestError(e) => return Err(Error::ManifestError(e)),
     }
 }
 
+/// Install a package from a GitHub release. The package replaces any other version of it in the
+/// lockfile, and is kept when other packages are installed even though the manifest does not
+/// list it.
+/// This function returns a bool on success indicating if any changes were applied
+pub fn update_with_github_release<P: AsRef<Path>>(
+    directory: P,
+    release: &GithubRelease,
+    options: &UpdateOptions,
+) -> Result<bool, Error> {
+    update_with_external_package(
+        directory.as_ref(),
+        || github_release::fetch_github_release(release).map_err(Error::GithubReleaseError),
+        release.resolved_source(),
+        options,
+    )
+}
+
+/// Install a package from an OCI registry, which is kept in the lockfile like the packages of
+/// GitHub releases.
+/// This function returns a bool on success indicating if any changes were applied
+pub fn update_with_oci_package<P: AsRef<Path>>(
+    directory: P,
+    reference: &OciReference,
+    options: &UpdateOptions,
+) -> Result<bool, Error> {
+    update_with_external_package(
+        directory.as_ref(),
+        || oci_package::fetch_oci_package(reference).map_err(Error::OciError),
+        reference.resolved_source(),
+        options,
+    )
+}
+
+/// Install a package from outside of the registry once its archive passes the checks of
+/// registry packages and the install is confirmed, in place of any other version of it
+fn update_with_external_package<F>(
+    directory: &Path,
+    fetch: F,
+    resolved_source: String,
+    options: &UpdateOptions,
+) -> Result<bool, Error>
+where
+    F: FnOnce() -> Result<ExternalArchive, Error>,
+{
+    let lockfile_result = LockfileResult::find_in_directory(&directory);
+    let mut lockfile_packages =
+        LockfilePackages::new_from_result(lockfile_result).map_err(Error::LockfileError)?;
+    let initial_package_keys = lockfile_packages.package_keys();
+    let exceptions =
+        IntegrityExceptions::load(directory).map_err(Error::IntegrityExceptionsError)?;
+
+    let archive = fetch()?;
+    external_package::check(&archive, &exceptions).map_err(Error::ExternalPackageError)?;
+    if options.confirm_plan {
+        let mut resolved = ResolvedPackages::default();
+        resolved
+            .packages
+            .push((archive.key.clone(), (archive.url.clone(), None)));
+        resolved.details.insert(
+            archive.key.clone(),
+            PackageDetails {
+                size: Some(archive.data.len() as u64),
+                commands: None,
+            },
+        );
+        let removed = RemovedLockfilePackages {
+            packages: HashMap::new(),
+        };
+        let confirmed = InstallPlan::new(&lockfile_packages, &resolved, &removed)
+            .confirm()
+            .map_err(|e| Error::PlanError(e.to_string()))?;
+        if !confirmed {
+            return Err(Error::Cancelled);
+        }
+    }
+    let manifest =
+        external_package::unpack(directory, &archive).map_err(Error::ExternalPackageError)?;
+    let installed_package = (archive.key, manifest, archive.url);
+    let package_name = installed_package.0.name.to_string();
+    let installed_packages = InstalledPackages {
+        packages: vec![installed_package],
+    };
+    let mut added_lockfile_data = LockfilePackages::from_installed_packages(&installed_packages)
+        .map_err(Error::LockfileError)?;
+    for package in added_lockfile_data.packages.values_mut() {
+        for module in package.modules.iter_mut() {
+            module.resolved_source = resolved_source.clone();
+        }
+    }
+
+    lockfile_packages.remove_packages(RemovedPackages::new_from_package_names(vec![
+        package_name.as_str()
+    ]));
+    let retained_lockfile_packages =
+        RetainedLockfilePackages::from_lockfile_packages(lockfile_packages);
+    let final_lockfile_data =
+        MergedLockfilePackages::merge(added_lockfile_data, retained_lockfile_packages);
+    let final_package_keys: HashSet<_> = final_lockfile_data.packages.keys().cloned().collect();
+    final_lockfile_data
+        .generate_lockfile(&directory, options.create_bin_scripts)
+        .map_err(Error::GenerateLockfileError)?;
+    report_linked_packages(&installed_packages);
+    if options.write_editor_metadata {
+        editor_metadata::write_editor_metadata(directory).map_err(Error::EditorMetadataError)?;
+    }
+    Ok(final_package_keys != initial_package_keys)
+}
+
+/// Install the dependencies of every member of a workspace. When `hoist` is true, dependencies
+/// shared by multiple members are installed once into the workspace root and linked into the
+/// members, otherwise every member gets its own copy of every dependency.
+/// This function returns a bool on success indicating if any changes were applied
+pub fn update_workspace(
+    workspace: &Workspace,
+    hoist: bool,
+    options: &UpdateOptions,
+) -> Result<bool, Error> {
+    let mut changes_applied = false;
+    let member_directories = workspace.member_directories();
+    if hoist {
+        let hoisted_packages =
+            HoistedPackages::from_workspace(workspace).map_err(Error::HoistError)?;
+        if !hoisted_packages.packages.is_empty() {
+            let hoisted_pairs = hoisted_packages.name_and_version_pairs();
+            let added_packages = AddedPackages::new_from_str_pairs(
+                hoisted_pairs
+                    .iter()
+                    .map(|(name, version)| (name.as_str(), version.as_str()))
+                    .collect(),
+            )
+            .map_err(Error::AddError)?;
+            // the members create the bin scripts for their own commands
+            let mut root_options = options.clone();
+            root_options.create_bin_scripts = false;
+            changes_applied |= update_with_no_manifest(
+                &workspace.root,
+                added_packages,
+                RemovedPackages::default(),
+                &root_options,
+            )?;
+            hoisted_packages
+                .link_into_members(&workspace.root)
+                .map_err(Error::HoistError)?;
+        }
+    } else {
+        for member_directory in member_directories.iter() {
+            unlink_hoisted_packages(member_directory).map_err(Error::HoistError)?;
+        }
+    }
+    for member_directory in member_directories.iter() {
+        changes_applied |= update_with_options(vec![], vec![], member_directory, options)?;
+    }
+    Ok(changes_applied)
+}
+
 /// Updates the manifest and saves it
 pub fn update_manifest(
     manifest: Manifest,
@@ -0,0 +1,50 @@
+//! Installing packages pushed to OCI registries, with `wapm install oci://ghcr.io/user/pkg:1.0.0`.
+//!
+//! The package is installed with the name and version of the annotations of its OCI manifest,
+//! which must match its `wapm.toml`. Its lockfile modules have an `oci+<registry>/<repository>`
+//! resolved source and are resolved to the blob of the archive, so reinstalls get the same bytes
+//! even if the tag is moved.
+
+use crate::dataflow::external_package::ExternalArchive;
+use crate::dataflow::WapmPackageKey;
+use crate::oci::{self, OciError, OciReference};
+use semver::Version;
+use std::borrow::Cow;
+
+/// Pull the archive of a package and verify it against the digest of the OCI manifest
+pub fn fetch_oci_package(reference: &OciReference) -> Result<ExternalArchive, OciError> {
+    let pulled = oci::pull(reference)?;
+    let annotation = |annotation: &'static str| {
+        pulled
+            .manifest
+            .annotations
+            .get(annotation)
+            .cloned()
+            .ok_or_else(|| OciError::InvalidAnnotation(reference.to_string(), annotation))
+    };
+    let name = annotation(oci::NAME_ANNOTATION)?;
+    let version = Version::parse(&annotation(oci::VERSION_ANNOTATION)?)
+        .map_err(|_| OciError::InvalidAnnotation(reference.to_string(), oci::VERSION_ANNOTATION))?;
+    let fully_qualified_name = if name.contains('/') {
+        name
+    } else {
+        format!("_/{}", name)
+    };
+    info!(
+        "Pulled {}@{} from {} ({})",
+        fully_qualified_name, version, reference, pulled.manifest_digest
+    );
+    let url = pulled
+        .manifest
+        .package_layer()
+        .map(|layer| reference.blob_url(&layer.digest))
+        .unwrap_or_default();
+    Ok(ExternalArchive {
+        key: WapmPackageKey {
+            name: Cow::Owned(fully_qualified_name),
+            version,
+        },
+        data: pulled.archive,
+        url,
+    })
+}
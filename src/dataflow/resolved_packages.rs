@@ -1,26 +1,29 @@
-use crate::constants::*;
+use crate::allowlist::Allowlist;
 use crate::dataflow::added_packages::AddedPackages;
 use crate::dataflow::{PackageKey, WapmPackageKey, WapmPackageRange};
-use crate::graphql::{execute_query, DateTime};
+use crate::integrity_exceptions::IntegrityExceptions;
 use crate::keys;
-use graphql_client::*;
+use crate::min_age;
+use crate::package_size;
+use crate::policy::{self, Policy};
+use crate::progress::{self, ProgressEvent};
+use crate::registry;
+use chrono::Utc;
 use semver::Version;
 use std::borrow::Cow::Owned;
 use std::collections::hash_map::HashMap;
 use std::collections::hash_set::HashSet;
 
-#[derive(GraphQLQuery)]
-#[graphql(
-    schema_path = "graphql/schema.graphql",
-    query_path = "graphql/queries/get_packages.graphql",
-    response_derives = "Debug"
-)]
-struct GetPackagesQuery;
-
 #[derive(Clone, Debug, Fail)]
 pub enum Error {
     #[fail(display = "There was a problem resolve dependencies. {}", _0)]
     CouldNotResolvePackages(String),
+    #[fail(display = "The install policy does not allow these packages:\n{}", _0)]
+    PolicyViolation(String),
+    #[fail(display = "{}", _0)]
+    PackageTooLarge(String),
+    #[fail(display = "The allowlist does not allow these packages: {}", _0)]
+    NotInAllowlist(String),
 }
 
 /// Struct containing wapm registry resolved packages. This is realized as a pairing of wapm.io keys
@@ -31,12 +34,26 @@ pub struct ResolvedPackages<'a> {
         WapmPackageKey<'a>,
         (String, Option<keys::WapmPackageSignature>),
     )>,
+    /// What the registry said about the resolved packages, for showing what an install will do
+    pub details: HashMap<WapmPackageKey<'a>, PackageDetails>,
+}
+
+/// What the registry knows about a resolved package besides where to download it from
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PackageDetails {
+    /// The size of the archive in bytes
+    pub size: Option<u64>,
+    /// The names of the commands of the package
+    pub commands: Option<Vec<String>>,
 }
 
 impl<'a> ResolvedPackages<'a> {
     /// Consume changed manifest packages and produce keys with download urls. Will query the registry
     /// for the download urls.
-    fn new<Resolver>(packages: HashSet<PackageKey<'a>>) -> Result<Self, Error>
+    fn new<Resolver>(
+        packages: HashSet<PackageKey<'a>>,
+        exceptions: &IntegrityExceptions,
+    ) -> Result<Self, Error>
     where
         Resolver: Resolve<'a>,
     {
@@ -45,18 +62,31 @@ impl<'a> ResolvedPackages<'a> {
         if wapm_pkgs.is_empty() {
             return Ok(Self::default());
         }
-        let packages = Resolver::sync_packages(wapm_pkgs)
+        progress::emit(ProgressEvent::ResolveStarted {
+            packages: wapm_pkgs
+                .iter()
+                .map(|key| match key {
+                    PackageKey::WapmPackage(key) => format!("{}@{}", key.name, key.version),
+                    PackageKey::WapmPackageRange(range) => {
+                        format!("{}@{}", range.name, range.version_req)
+                    }
+                })
+                .collect(),
+        });
+        let resolved = Resolver::sync_packages(wapm_pkgs, exceptions)
             .map_err(|e| Error::CouldNotResolvePackages(e.to_string()))?;
-        Ok(Self { packages })
+        progress::emit(ProgressEvent::ResolveFinished);
+        Ok(resolved)
     }
 
     pub fn new_from_added_packages<Resolver>(
         added_packages: AddedPackages<'a>,
+        exceptions: &IntegrityExceptions,
     ) -> Result<Self, Error>
     where
         Resolver: Resolve<'a>,
     {
-        Self::new::<Resolver>(added_packages.packages)
+        Self::new::<Resolver>(added_packages.packages, exceptions)
     }
 }
 
@@ -64,89 +94,48 @@ impl<'a> ResolvedPackages<'a> {
 pub trait Resolve<'a> {
     fn sync_packages(
         added_packages: Vec<PackageKey<'a>>,
-    ) -> Result<
-        Vec<(
-            WapmPackageKey<'a>,
-            (String, Option<keys::WapmPackageSignature>),
-        )>,
-        Error,
-    >;
+        exceptions: &IntegrityExceptions,
+    ) -> Result<ResolvedPackages<'a>, Error>;
 }
 
 pub struct RegistryResolver;
 
-impl<'a> RegistryResolver {
-    fn get_response(added_pkgs: Vec<PackageKey<'a>>) -> get_packages_query::ResponseData {
-        let names = added_pkgs
-            .into_iter()
+/// The Registry Resolver will resolve dependencies on the configured registry
+impl<'a> Resolve<'a> for RegistryResolver {
+    fn sync_packages(
+        added_packages: Vec<PackageKey<'a>>,
+        exceptions: &IntegrityExceptions,
+    ) -> Result<ResolvedPackages<'a>, Error> {
+        let names: Vec<String> = added_packages
+            .iter()
             .map(|key| match key {
                 PackageKey::WapmPackageRange(WapmPackageRange { name, .. }) => name.to_string(),
                 PackageKey::WapmPackage(WapmPackageKey { name, .. }) => name.to_string(),
             })
             .collect();
-        let q = GetPackagesQuery::build_query(get_packages_query::Variables { names });
-        execute_query(&q).unwrap()
-    }
-}
-
-/// The Registry Resolver will resolve dependencies on a wapm.io server
-impl<'a> Resolve<'a> for RegistryResolver {
-    /// This gross function queries the GraphQL server. See the schema in `/graphql/queries/get_packages.graphql`
-    fn sync_packages(
-        added_packages: Vec<PackageKey<'a>>,
-    ) -> Result<
-        Vec<(
-            WapmPackageKey<'a>,
-            (String, Option<keys::WapmPackageSignature>),
-        )>,
-        Error,
-    > {
-        // fetch data from graphql server
-        let response = Self::get_response(added_packages.clone());
+        let package_versions = registry::backend()
+            .and_then(|backend| backend.package_versions(&names))
+            .map_err(|e| Error::CouldNotResolvePackages(e.to_string()))?;
+        let policy = Policy::load().map_err(|e| Error::CouldNotResolvePackages(e.to_string()))?;
+        let allowlist =
+            Allowlist::load().map_err(|e| Error::CouldNotResolvePackages(e.to_string()))?;
         let all_packages_and_download_urls: Vec<(
             String,
             Version,
             String,
             Option<keys::WapmPackageSignature>,
-        )> = response
-            .package
-            .into_iter()
-            .filter_map(|p| p)
-            .map(|p| {
-                let versions = p.versions.unwrap_or_default();
-                let name = p.name;
-                (name, versions)
-            })
-            .flat_map(|(n, vs)| {
-                vs.into_iter()
-                    .filter_map(|o| o)
-                    .map(|v| {
-                        let version = v.version;
-                        let download_url = v.distribution.download_url;
-                        let signature = v.signature.map(|gq_sig| keys::WapmPackageSignature {
-                            public_key_id: gq_sig.public_key.key_id,
-                            public_key: gq_sig.public_key.key,
-                            signature_data: gq_sig.data,
-                            date_created: {
-                                time::strptime(
-                                    &gq_sig.created_at,
-                                    RFC3339_FORMAT_STRING_WITH_TIMEZONE,
-                                )
-                                .unwrap_or_else(|err| {
-                                    panic!("Failed to parse time string: {}", err)
-                                })
-                                .to_timespec()
-                            },
-                            revoked: gq_sig.public_key.revoked,
-                            owner: gq_sig.public_key.owner.username,
-                        });
-                        (n.clone(), version, download_url, signature)
+        )> = package_versions
+            .iter()
+            .map(|pv| {
+                Version::parse(&pv.version)
+                    .map(|version| {
+                        (
+                            pv.name.clone(),
+                            version,
+                            pv.download_url.clone(),
+                            pv.signature.clone(),
+                        )
                     })
-                    .collect::<Vec<_>>()
-            })
-            .map(|(name, version, download_url, signature)| {
-                Version::parse(&version)
-                    .map(|version| (name, version, download_url, signature))
                     .map_err(|e| Error::CouldNotResolvePackages(e.to_string()))
             })
             .collect::<Result<Vec<(_, _, _, _)>, Error>>()?;
@@ -173,6 +162,15 @@ impl<'a> Resolve<'a> for RegistryResolver {
             versions.push(version);
         }
 
+        // versions published more recently than the minimum age are not picked for ranges
+        let min_age = min_age::min_age();
+        let now = Utc::now();
+        let held_back: HashSet<(&str, &str)> = package_versions
+            .iter()
+            .filter(|pv| min_age::is_held_back(pv.published_at, min_age, now))
+            .map(|pv| (pv.name.as_str(), pv.version.as_str()))
+            .collect();
+
         // filter all the package-versions + download_urls by exact version or version range
         let packages_and_download_urls: Vec<(
             WapmPackageKey,
@@ -186,16 +184,28 @@ impl<'a> Resolve<'a> for RegistryResolver {
                     .map(|(d, s)| (wapm_package_key, (d.clone(), s.clone()))),
                 // if a range, then filter by the requirements, and find the max version
                 PackageKey::WapmPackageRange(range) => {
-                    let matching_version: Option<Version> = package_versions_lookup
+                    let matching_versions: Vec<&Version> = package_versions_lookup
                         .get(range.name.as_ref())
-                        .and_then(|versions| {
-                            let max_version: Option<Version> = versions
-                                .iter()
-                                .cloned()
-                                .filter(|v| range.version_req.matches(v))
-                                .max(); // get the max version number after filtering by version requirement
-                            max_version
-                        });
+                        .into_iter()
+                        .flatten()
+                        .filter(|v| range.version_req.matches(v))
+                        .collect();
+                    // get the max version number after filtering by version requirement
+                    let matching_version: Option<Version> = matching_versions
+                        .iter()
+                        .filter(|v| {
+                            !held_back.contains(&(range.name.as_ref(), v.to_string().as_str()))
+                        })
+                        .max()
+                        .map(|v| (*v).clone());
+                    if let Some(newest) = matching_versions.iter().max() {
+                        if Some(*newest) != matching_version.as_ref() {
+                            info!(
+                                "{}@{} was published too recently for the minimum package age",
+                                range.name, newest
+                            );
+                        }
+                    }
                     // join the key with the download url by using the package-key lookup table
                     let key_and_data: Option<(
                         WapmPackageKey,
@@ -213,7 +223,64 @@ impl<'a> Resolve<'a> for RegistryResolver {
                 }
             })
             .collect();
-        Ok(packages_and_download_urls)
+
+        let chosen_versions = || {
+            package_versions.iter().filter(|pv| {
+                packages_and_download_urls.iter().any(|(key, _)| {
+                    key.name == pv.name.as_str() && key.version.to_string() == pv.version
+                })
+            })
+        };
+
+        // check the chosen versions against the install policy before anything is downloaded
+        if let Some(policy) = policy {
+            let violations = policy.check_all(chosen_versions(), exceptions);
+            if !violations.is_empty() {
+                return Err(Error::PolicyViolation(policy::violation_report(
+                    &violations,
+                )));
+            }
+        }
+
+        // and the allowlist, if there is one
+        if let Some(allowlist) = allowlist {
+            let not_allowed: Vec<String> = packages_and_download_urls
+                .iter()
+                .map(|(key, _)| (key.name.to_string(), key.version.to_string()))
+                .filter(|(name, version)| !allowlist.allows(name, version))
+                .map(|(name, version)| format!("{}@{}", name, version))
+                .collect();
+            if !not_allowed.is_empty() {
+                return Err(Error::NotInAllowlist(not_allowed.join(", ")));
+            }
+        }
+
+        // and their sizes, as far as the registry knows them
+        let size_limit = package_size::size_limit();
+        let mut details = HashMap::new();
+        for pv in chosen_versions() {
+            if let Some(size) = pv.size {
+                let package = format!("{}@{}", pv.name, pv.version);
+                size_limit
+                    .check(&package, "archive", size)
+                    .map_err(Error::PackageTooLarge)?;
+            }
+            if let Ok(version) = Version::parse(&pv.version) {
+                let key = WapmPackageKey {
+                    name: Owned(pv.name.clone()),
+                    version,
+                };
+                let package_details = PackageDetails {
+                    size: pv.size,
+                    commands: pv.command_names(),
+                };
+                details.insert(key, package_details);
+            }
+        }
+        Ok(ResolvedPackages {
+            packages: packages_and_download_urls,
+            details,
+        })
     }
 }
 
@@ -222,8 +289,8 @@ mod test {
     use crate::dataflow::added_packages::AddedPackages;
     use crate::dataflow::resolved_packages::{Error, Resolve, ResolvedPackages};
     use crate::dataflow::{PackageKey, WapmPackageKey, WapmPackageRange};
-    use crate::keys;
-    use std::collections::HashSet;
+    use crate::integrity_exceptions::IntegrityExceptions;
+    use std::collections::{HashMap, HashSet};
 
     struct TestResolver;
 
@@ -231,14 +298,9 @@ mod test {
     impl<'a> Resolve<'a> for TestResolver {
         fn sync_packages(
             added_packages: Vec<PackageKey<'a>>,
-        ) -> Result<
-            Vec<(
-                WapmPackageKey<'a>,
-                (String, Option<keys::WapmPackageSignature>),
-            )>,
-            Error,
-        > {
-            Ok(added_packages
+            _exceptions: &IntegrityExceptions,
+        ) -> Result<ResolvedPackages<'a>, Error> {
+            let packages = added_packages
                 .into_iter()
                 .filter(|k| {
                     match k {
@@ -268,7 +330,11 @@ mod test {
                         ("url".to_string(), None),
                     ),
                 })
-                .collect())
+                .collect();
+            Ok(ResolvedPackages {
+                packages,
+                details: HashMap::new(),
+            })
         }
     }
 
@@ -281,8 +347,11 @@ mod test {
         let added_packages = AddedPackages {
             packages: packages_set,
         };
-        let resolve_packages =
-            ResolvedPackages::new_from_added_packages::<TestResolver>(added_packages).unwrap();
+        let resolve_packages = ResolvedPackages::new_from_added_packages::<TestResolver>(
+            added_packages,
+            &IntegrityExceptions::default(),
+        )
+        .unwrap();
         assert_eq!(1, resolve_packages.packages.len());
     }
 
@@ -301,8 +370,11 @@ mod test {
         let added_packages = AddedPackages {
             packages: packages_set,
         };
-        let resolve_packages =
-            ResolvedPackages::new_from_added_packages::<TestResolver>(added_packages).unwrap();
+        let resolve_packages = ResolvedPackages::new_from_added_packages::<TestResolver>(
+            added_packages,
+            &IntegrityExceptions::default(),
+        )
+        .unwrap();
         assert_eq!(1, resolve_packages.packages.len());
     }
 
@@ -322,8 +394,11 @@ mod test {
         let added_packages = AddedPackages {
             packages: packages_set,
         };
-        let resolve_packages =
-            ResolvedPackages::new_from_added_packages::<TestResolver>(added_packages).unwrap();
+        let resolve_packages = ResolvedPackages::new_from_added_packages::<TestResolver>(
+            added_packages,
+            &IntegrityExceptions::default(),
+        )
+        .unwrap();
         assert_eq!(1, resolve_packages.packages.len());
         resolve_packages
             .packages
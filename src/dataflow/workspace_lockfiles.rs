@@ -0,0 +1,180 @@
+//! Loading the lockfiles of every member of a workspace. Workspaces can have hundreds of members,
+//! so the lockfiles are parsed in parallel and the parsed lockfiles are kept in a cache in the
+//! packages directory of the workspace root. Only the lockfiles changed since the last load,
+//! going by their size and modification time, are parsed again.
+
+use crate::data::lock::lockfile::Lockfile;
+use crate::data::lock::LOCKFILE_NAME;
+use crate::data::workspace::Workspace;
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::util::get_packages_dir;
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const CACHE_FILE_NAME: &str = ".workspace-lockfiles.cbor";
+
+/// What tells whether a lockfile changed since it was cached
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+struct FileStamp {
+    len: u64,
+    /// Nanoseconds since the epoch
+    modified: u64,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(FileStamp {
+            len: metadata.len(),
+            modified: u64::try_from(modified.as_nanos()).ok()?,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedLockfile {
+    stamp: FileStamp,
+    lockfile: Lockfile,
+}
+
+/// The parsed lockfiles of the members, by member directory
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct LockfileCache {
+    members: BTreeMap<PathBuf, CachedLockfile>,
+}
+
+impl LockfileCache {
+    fn load(path: &Path) -> Self {
+        fs::read(path)
+            .ok()
+            .and_then(|data| serde_cbor::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    /// Save the cache if the packages directory exists, listing packages does not create it
+    fn save(&self, path: &Path) {
+        if !path.parent().is_some_and(Path::is_dir) {
+            return;
+        }
+        let saved = serde_cbor::to_vec(self)
+            .map_err(|e| e.to_string())
+            .and_then(|data| fs::write(path, data).map_err(|e| e.to_string()));
+        if let Err(e) = saved {
+            debug!("Could not save the workspace lockfile cache: {}", e);
+        }
+    }
+}
+
+/// The lockfiles of the members of the workspace, in the order of the members
+pub fn member_lockfiles(workspace: &Workspace) -> Vec<(PathBuf, LockfileResult)> {
+    let cache_path = get_packages_dir(&workspace.root).join(CACHE_FILE_NAME);
+    let mut cache = LockfileCache::load(&cache_path);
+    let members: Vec<(PathBuf, Option<FileStamp>, Option<CachedLockfile>)> = workspace
+        .member_directories()
+        .into_iter()
+        .map(|directory| {
+            let stamp = FileStamp::of(&directory.join(LOCKFILE_NAME));
+            let cached = cache
+                .members
+                .remove(&directory)
+                .filter(|cached| Some(cached.stamp) == stamp);
+            (directory, stamp, cached)
+        })
+        .collect();
+    let parsed: Vec<(PathBuf, Option<FileStamp>, LockfileResult, bool)> = members
+        .into_par_iter()
+        .map(|(directory, stamp, cached)| match cached {
+            Some(cached) => (
+                directory,
+                stamp,
+                LockfileResult::Lockfile(cached.lockfile),
+                false,
+            ),
+            None => {
+                let result = LockfileResult::find_in_directory(&directory);
+                (directory, stamp, result, true)
+            }
+        })
+        .collect();
+
+    let changed = parsed.iter().any(|(_, _, _, parsed)| *parsed);
+    let mut fresh = LockfileCache::default();
+    let lockfiles = parsed
+        .into_iter()
+        .map(|(directory, stamp, result, _)| {
+            if let (LockfileResult::Lockfile(lockfile), Some(stamp)) = (&result, stamp) {
+                let cached = CachedLockfile {
+                    stamp,
+                    lockfile: lockfile.clone(),
+                };
+                fresh.members.insert(directory.clone(), cached);
+            }
+            (directory, result)
+        })
+        .collect();
+    // members that left the workspace are dropped from the cache too
+    if changed || !cache.members.is_empty() {
+        fresh.save(&cache_path);
+    }
+    lockfiles
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::lock::lockfile_module::LockfileModule;
+
+    #[test]
+    fn unchanged_lockfiles_come_from_the_cache() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let root = tmp_dir.path();
+        for member in &["app", "lib"] {
+            fs::create_dir(root.join(member)).unwrap();
+        }
+        let lockfile = Lockfile {
+            modules: BTreeMap::new(),
+            commands: BTreeMap::new(),
+        };
+        lockfile.save(root.join("app")).unwrap();
+        fs::create_dir(get_packages_dir(root)).unwrap();
+        let workspace = Workspace {
+            members: vec!["app".into(), "lib".into()],
+            package: None,
+            root: root.to_path_buf(),
+        };
+
+        let lockfiles = member_lockfiles(&workspace);
+        assert!(matches!(lockfiles[0].1, LockfileResult::Lockfile(_)));
+        assert!(matches!(lockfiles[1].1, LockfileResult::NoLockfile));
+
+        // a module only in the cache shows the lockfile was not parsed again
+        let cache_path = get_packages_dir(root).join(CACHE_FILE_NAME);
+        let mut cache = LockfileCache::load(&cache_path);
+        let cached = cache.members.get_mut(&root.join("app")).unwrap();
+        cached
+            .lockfile
+            .modules
+            .entry("_/sqlite".to_string())
+            .or_default()
+            .entry(semver::Version::parse("0.1.0").unwrap())
+            .or_default()
+            .insert("sqlite".to_string(), LockfileModule::default());
+        cache.save(&cache_path);
+        match &member_lockfiles(&workspace)[0].1 {
+            LockfileResult::Lockfile(lockfile) => assert_eq!(lockfile.modules.len(), 1),
+            other => panic!("unexpected lockfile result {:?}", other),
+        }
+
+        // changed lockfiles are parsed again
+        fs::write(root.join("app").join(LOCKFILE_NAME), "not a lockfile").unwrap();
+        assert!(matches!(
+            member_lockfiles(&workspace)[0].1,
+            LockfileResult::LockfileError(_)
+        ));
+    }
+}
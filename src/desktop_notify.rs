@@ -0,0 +1,130 @@
+//! Desktop notifications when installs and publishes that took longer than `notify.threshold`
+//! finish, so the terminal doesn't have to be watched. The terminal bell is rung as well, for
+//! terminals that flash or badge their tab on it.
+//!
+//! Notifications are shown with `osascript` on macOS, PowerShell on Windows and `notify-send`
+//! elsewhere. Failing to show one is never an error.
+
+use crate::config::Config;
+use crate::min_age::parse_min_age;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// How long an operation must take to be notified, if notifications are enabled
+fn threshold() -> Option<Duration> {
+    Config::from_file()
+        .ok()?
+        .notify
+        .threshold
+        .and_then(|threshold| parse_min_age(&threshold).ok())
+        .and_then(|threshold| threshold.to_std().ok())
+}
+
+/// Notify the end of an operation if it took longer than the threshold
+pub fn notify_if_slow(operation: &str, elapsed: Duration, result: &Result<(), failure::Error>) {
+    match threshold() {
+        Some(threshold) if elapsed >= threshold => {}
+        _ => return,
+    }
+    let (title, body) = message(operation, elapsed, result);
+    if atty::is(atty::Stream::Stderr) {
+        let _ = io::stderr().write_all(b"\x07");
+    }
+    if let Err(e) = show(&title, &body) {
+        debug!("Could not show a desktop notification: {}", e);
+    }
+}
+
+fn message(
+    operation: &str,
+    elapsed: Duration,
+    result: &Result<(), failure::Error>,
+) -> (String, String) {
+    let seconds = elapsed.as_secs();
+    let duration = if seconds >= 60 {
+        format!("{}m {}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}s", seconds)
+    };
+    match result {
+        Ok(()) => (
+            format!("wapm {} finished", operation),
+            format!("Done in {}", duration),
+        ),
+        Err(e) => (
+            format!("wapm {} failed", operation),
+            format!("After {}: {}", duration, e),
+        ),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn show(title: &str, body: &str) -> io::Result<()> {
+    let quote = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+    let script = format!(
+        "display notification \"{}\" with title \"{}\"",
+        quote(body),
+        quote(title)
+    );
+    spawn(Command::new("osascript").arg("-e").arg(script))
+}
+
+#[cfg(windows)]
+fn show(title: &str, body: &str) -> io::Result<()> {
+    let quote = |text: &str| text.replace('\'', "''");
+    let script = format!(
+        "Add-Type -AssemblyName System.Windows.Forms; \
+         $n = New-Object System.Windows.Forms.NotifyIcon; \
+         $n.Icon = [System.Drawing.SystemIcons]::Information; \
+         $n.BalloonTipTitle = '{}'; $n.BalloonTipText = '{}'; \
+         $n.Visible = $true; $n.ShowBalloonTip(5000); Start-Sleep -Seconds 6; $n.Dispose()",
+        quote(title),
+        quote(body)
+    );
+    spawn(
+        Command::new("powershell")
+            .arg("-NoProfile")
+            .arg("-Command")
+            .arg(script),
+    )
+}
+
+#[cfg(not(any(target_os = "macos", windows)))]
+fn show(title: &str, body: &str) -> io::Result<()> {
+    spawn(
+        Command::new("notify-send")
+            .arg("--app-name=wapm")
+            .arg(title)
+            .arg(body),
+    )
+}
+
+/// Start the notifier without waiting for it, so exiting isn't delayed
+fn spawn(command: &mut Command) -> io::Result<()> {
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn messages_tell_the_outcome() {
+        let (title, body) = message("install", Duration::from_secs(95), &Ok(()));
+        assert_eq!(title, "wapm install finished");
+        assert_eq!(body, "Done in 1m 35s");
+        let (title, body) = message(
+            "publish",
+            Duration::from_secs(31),
+            &Err(format_err!("network down")),
+        );
+        assert_eq!(title, "wapm publish failed");
+        assert_eq!(body, "After 31s: network down");
+    }
+}
@@ -0,0 +1,223 @@
+//! Warnings in categories that projects can silence or turn into errors.
+//!
+//! A project sets the level of each category in the `[warnings]` section of its manifest, like
+//! `missing-readme = "allow"`. The global `--deny` option, e.g. `wapm --deny warnings install`
+//! for CI, denies all categories (or the comma separated ones given) whatever the manifest says.
+//! Denied warnings are logged as errors and make the command fail once it is done.
+
+use crate::data::manifest::MANIFEST_FILE_NAME;
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// The environment variable set by the global `--deny` option
+pub const DENY_ENV_VAR: &str = "WAPM_DENY";
+/// The value of `--deny` that denies every category
+const ALL_WARNINGS: &str = "warnings";
+
+lazy_static! {
+    /// The levels set in the manifest of the project in the current directory, read once
+    static ref PROJECT_LEVELS: BTreeMap<String, Level> = project_levels();
+    /// The messages of the denied warnings given so far
+    static ref DENIED: Mutex<Vec<String>> = Mutex::new(vec![]);
+}
+
+/// The categories of warnings
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Warning {
+    /// A manifest key that is deprecated
+    DeprecatedField,
+    /// A package published without a readme
+    MissingReadme,
+    /// A package larger than `install.max-package-size`
+    LargePackage,
+    /// Two installed packages with a command of the same name
+    CommandCollision,
+    /// A pin of a dependency past its `until` date
+    ExpiredPin,
+    /// A module whose imports don't match its ABI
+    AbiMismatch,
+    /// A published file that can't be created on some platforms
+    NonPortablePath,
+}
+
+impl Warning {
+    pub const ALL: &'static [Warning] = &[
+        Warning::DeprecatedField,
+        Warning::MissingReadme,
+        Warning::LargePackage,
+        Warning::CommandCollision,
+        Warning::ExpiredPin,
+        Warning::AbiMismatch,
+        Warning::NonPortablePath,
+    ];
+
+    /// The name of the category in `[warnings]` and `--deny`
+    pub fn name(self) -> &'static str {
+        match self {
+            Warning::DeprecatedField => "deprecated-field",
+            Warning::MissingReadme => "missing-readme",
+            Warning::LargePackage => "large-package",
+            Warning::CommandCollision => "command-collision",
+            Warning::ExpiredPin => "expired-pin",
+            Warning::AbiMismatch => "abi-mismatch",
+            Warning::NonPortablePath => "non-portable-path",
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Warning {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Warning::ALL
+            .iter()
+            .copied()
+            .find(|warning| warning.name() == s)
+            .ok_or_else(|| format!("unknown warning category {}", s))
+    }
+}
+
+/// What is done about the warnings of a category
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    /// Not shown, except in the debug log
+    Allow,
+    /// Shown as a warning, the default
+    Warn,
+    /// Shown as an error, failing the command
+    Deny,
+}
+
+#[derive(Debug, Fail)]
+pub enum DiagnosticsError {
+    #[fail(display = "{} denied warning(s), see above", _0)]
+    DeniedWarnings(usize),
+    #[fail(
+        display = "Unknown warning category \"{}\" for --deny, expected `warnings` or some of: {}",
+        _0, _1
+    )]
+    UnknownCategory(String, String),
+}
+
+fn project_levels() -> BTreeMap<String, Level> {
+    #[derive(Deserialize)]
+    struct WarningsSection {
+        #[serde(default)]
+        warnings: BTreeMap<String, Level>,
+    }
+    env::current_dir()
+        .ok()
+        .and_then(|dir| fs::read_to_string(dir.join(MANIFEST_FILE_NAME)).ok())
+        .and_then(|source| toml::from_str::<WarningsSection>(&source).ok())
+        .map(|section| section.warnings)
+        .unwrap_or_default()
+}
+
+/// The level of a category given the value of `--deny` and the levels of the project
+fn level_of(warning: Warning, deny: Option<&str>, project: &BTreeMap<String, Level>) -> Level {
+    let denied = deny.is_some_and(|deny| {
+        deny.split(',')
+            .map(str::trim)
+            .any(|name| name == ALL_WARNINGS || name == warning.name())
+    });
+    if denied {
+        return Level::Deny;
+    }
+    project.get(warning.name()).copied().unwrap_or(Level::Warn)
+}
+
+/// Check that `--deny` only names known categories
+pub fn validate_deny_option() -> Result<(), DiagnosticsError> {
+    let deny = match env::var(DENY_ENV_VAR) {
+        Ok(deny) => deny,
+        Err(_) => return Ok(()),
+    };
+    for name in deny.split(',').map(str::trim) {
+        if name != ALL_WARNINGS && name.parse::<Warning>().is_err() {
+            let categories: Vec<&str> = Warning::ALL.iter().map(|warning| warning.name()).collect();
+            return Err(DiagnosticsError::UnknownCategory(
+                name.to_string(),
+                categories.join(", "),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Give a warning of a category, at the level the project and `--deny` set for it
+pub fn warn(warning: Warning, message: impl fmt::Display) {
+    let deny = env::var(DENY_ENV_VAR).ok();
+    match level_of(warning, deny.as_deref(), &PROJECT_LEVELS) {
+        Level::Allow => debug!("{} [{}]", message, warning),
+        Level::Warn => warn!("{} [{}]", message, warning),
+        Level::Deny => {
+            let message = format!("{} [{}]", message, warning);
+            error!("{}", message);
+            DENIED.lock().unwrap().push(message);
+        }
+    }
+}
+
+/// Fail if any denied warning was given
+pub fn check_denied() -> Result<(), DiagnosticsError> {
+    match DENIED.lock().unwrap().len() {
+        0 => Ok(()),
+        denied => Err(DiagnosticsError::DeniedWarnings(denied)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data::manifest_keys::check_keys;
+
+    #[test]
+    fn deny_overrides_the_project_levels() {
+        let mut project = BTreeMap::new();
+        project.insert("missing-readme".to_string(), Level::Allow);
+        assert_eq!(
+            level_of(Warning::MissingReadme, None, &project),
+            Level::Allow
+        );
+        assert_eq!(level_of(Warning::LargePackage, None, &project), Level::Warn);
+        assert_eq!(
+            level_of(Warning::MissingReadme, Some("warnings"), &project),
+            Level::Deny
+        );
+        assert_eq!(
+            level_of(
+                Warning::LargePackage,
+                Some("expired-pin, large-package"),
+                &project
+            ),
+            Level::Deny
+        );
+        assert_eq!(
+            level_of(Warning::MissingReadme, Some("large-package"), &project),
+            Level::Allow
+        );
+    }
+
+    #[test]
+    fn every_category_is_a_manifest_key() {
+        let mut warnings = toml::value::Table::new();
+        for warning in Warning::ALL {
+            warnings.insert(warning.name().to_string(), "allow".into());
+        }
+        let mut manifest = toml::value::Table::new();
+        manifest.insert("warnings".to_string(), toml::Value::Table(warnings));
+        assert!(check_keys(&toml::Value::Table(manifest)).is_empty());
+    }
+}
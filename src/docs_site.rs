@@ -0,0 +1,349 @@
+//! Static HTML documentation of a package, for publishing to GitHub Pages or any static host.
+//!
+//! The site is one `index.html` with the readme, the commands of the package, its interface
+//! definitions and the versions published to the registry, next to a stylesheet and copies of the
+//! interface definitions. Everything links relatively, so the site works from any base path.
+
+use crate::data::manifest::Manifest;
+use crate::registry::PackageVersion;
+use pulldown_cmark::{html, Options, Parser};
+use semver::Version;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+pub const STYLESHEET_NAME: &str = "style.css";
+/// The directory of the site the interface definitions are copied to
+pub const INTERFACES_DIR: &str = "interfaces";
+
+const STYLESHEET: &str = "body { font-family: system-ui, sans-serif; max-width: 56rem; margin: 0 auto; padding: 1rem 2rem; color: #1f2328; line-height: 1.5; }
+header { border-bottom: 1px solid #d0d7de; margin-bottom: 1.5rem; }
+nav a { margin-right: 1rem; }
+code, pre { font-family: ui-monospace, monospace; background: #f6f8fa; border-radius: 4px; }
+pre { padding: 0.75rem; overflow-x: auto; }
+table { border-collapse: collapse; width: 100%; }
+th, td { text-align: left; padding: 0.4rem 0.6rem; border-bottom: 1px solid #d0d7de; }
+.muted { color: #656d76; }
+";
+
+/// A file of the site, relative to its root
+#[derive(Debug)]
+pub struct SiteFile {
+    pub path: PathBuf,
+    pub contents: Vec<u8>,
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn markdown_to_html(markdown: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TASKLISTS);
+    let mut out = String::new();
+    html::push_html(&mut out, Parser::new_ext(markdown, options));
+    out
+}
+
+/// An interface definition of the package, with where it is in the package
+struct Interface {
+    name: String,
+    version: Option<String>,
+    path: PathBuf,
+}
+
+fn interfaces(manifest: &Manifest) -> Vec<Interface> {
+    let definitions = manifest.interface.iter().flatten().map(|d| Interface {
+        name: d.name.clone(),
+        version: None,
+        path: d.path.clone(),
+    });
+    let exports = manifest
+        .module
+        .iter()
+        .flatten()
+        .flat_map(|module| module.exports.iter().flatten())
+        .map(|e| Interface {
+            name: e.name.clone(),
+            version: Some(e.version.to_string()),
+            path: e.path.clone(),
+        });
+    definitions.chain(exports).collect()
+}
+
+fn write_header(out: &mut String, manifest: &Manifest) -> std::fmt::Result {
+    let package = &manifest.package;
+    writeln!(out, "<header>")?;
+    writeln!(
+        out,
+        "<h1>{} <span class=\"muted\">{}</span></h1>",
+        escape(&package.name),
+        package.version
+    )?;
+    writeln!(out, "<p>{}</p>", escape(&package.description))?;
+    let links = [
+        ("Repository", &package.repository),
+        ("Homepage", &package.homepage),
+        ("Documentation", &package.documentation),
+    ];
+    write!(out, "<nav>")?;
+    for (title, link) in links.iter() {
+        if let Some(link) = link {
+            write!(out, "<a href=\"{}\">{}</a>", escape(link), title)?;
+        }
+    }
+    writeln!(out, "</nav>")?;
+    if let Some(license) = &package.license {
+        writeln!(out, "<p class=\"muted\">License: {}</p>", escape(license))?;
+    }
+    writeln!(out, "</header>")
+}
+
+fn write_commands(out: &mut String, manifest: &Manifest) -> std::fmt::Result {
+    let commands = manifest.command.as_deref().unwrap_or_default();
+    if commands.is_empty() {
+        return Ok(());
+    }
+    writeln!(out, "<section id=\"commands\">\n<h2>Commands</h2>")?;
+    writeln!(
+        out,
+        "<table>\n<tr><th>Command</th><th>Description</th><th>Usage</th><th>Module</th></tr>"
+    )?;
+    for command in commands {
+        let mut usage = match &command.usage {
+            Some(usage) => command.run_line(usage),
+            None => format!("wapm run {} [ARGS]...", command.name),
+        };
+        if let Some(main_args) = &command.main_args {
+            write!(usage, "\n# runs with {} before ARGS", main_args)?;
+        }
+        if !command.examples.is_empty() {
+            usage.push_str("\n\n# examples");
+            for example in command.examples.iter() {
+                write!(usage, "\n{}", command.run_line(example))?;
+            }
+        }
+        let module = match &command.package {
+            Some(package) => format!("{} from {}", command.module, package),
+            None => command.module.clone(),
+        };
+        writeln!(
+            out,
+            "<tr><td><code>{}</code></td><td>{}</td><td><pre>{}</pre></td><td>{}</td></tr>",
+            escape(&command.name),
+            escape(command.description.as_deref().unwrap_or_default()),
+            escape(&usage),
+            escape(&module)
+        )?;
+    }
+    writeln!(out, "</table>\n</section>")
+}
+
+fn write_versions(out: &mut String, versions: &[PackageVersion]) -> std::fmt::Result {
+    let mut versions: Vec<&PackageVersion> = versions.iter().collect();
+    versions.sort_by(|a, b| {
+        let a = Version::parse(&a.version).ok();
+        let b = Version::parse(&b.version).ok();
+        b.cmp(&a)
+    });
+    writeln!(out, "<section id=\"versions\">\n<h2>Versions</h2>")?;
+    writeln!(
+        out,
+        "<table>\n<tr><th>Version</th><th>Published</th><th>License</th></tr>"
+    )?;
+    for version in versions {
+        let published = version
+            .published_at
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        writeln!(
+            out,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&version.version),
+            published,
+            escape(version.license.as_deref().unwrap_or_default())
+        )?;
+    }
+    writeln!(out, "</table>\n</section>")
+}
+
+/// The files of the documentation site of a package. The version history is left out when
+/// `versions` is `None`.
+pub fn build_site(
+    manifest: &Manifest,
+    versions: Option<&[PackageVersion]>,
+) -> Result<Vec<SiteFile>, failure::Error> {
+    let package = &manifest.package;
+    let base = &manifest.base_directory_path;
+    let mut files = vec![SiteFile {
+        path: PathBuf::from(STYLESHEET_NAME),
+        contents: STYLESHEET.as_bytes().to_vec(),
+    }];
+    let mut out = String::new();
+    writeln!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n<title>{} {}</title>\n<link rel=\"stylesheet\" href=\"{}\">\n</head>\n<body>",
+        escape(&package.name),
+        package.version,
+        STYLESHEET_NAME
+    )?;
+    write_header(&mut out, manifest)?;
+    writeln!(
+        out,
+        "<section id=\"install\">\n<h2>Install</h2>\n<pre>wapm install {}</pre>\n</section>",
+        escape(&package.name)
+    )?;
+    write_commands(&mut out, manifest)?;
+
+    let interfaces = interfaces(manifest);
+    if !interfaces.is_empty() {
+        writeln!(out, "<section id=\"interfaces\">\n<h2>Interfaces</h2>")?;
+        for interface in interfaces {
+            let definition = fs::read_to_string(base.join(&interface.path))?;
+            let file_name = interface
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| interface.name.clone());
+            let site_path = format!("{}/{}", INTERFACES_DIR, file_name);
+            let title = match &interface.version {
+                Some(version) => format!("{} {}", interface.name, version),
+                None => interface.name.clone(),
+            };
+            writeln!(
+                out,
+                "<h3>{}</h3>\n<p><a href=\"{}\">{}</a></p>\n<pre>{}</pre>",
+                escape(&title),
+                escape(&site_path),
+                escape(&file_name),
+                escape(&definition)
+            )?;
+            files.push(SiteFile {
+                path: PathBuf::from(site_path),
+                contents: definition.into_bytes(),
+            });
+        }
+        writeln!(out, "</section>")?;
+    }
+
+    if let Some(versions) = versions.filter(|versions| !versions.is_empty()) {
+        write_versions(&mut out, versions)?;
+    }
+
+    if let Some(readme) = &package.readme {
+        let readme = fs::read_to_string(base.join(readme))?;
+        writeln!(
+            out,
+            "<section id=\"readme\">\n{}</section>",
+            markdown_to_html(&readme)
+        )?;
+    }
+    writeln!(out, "</body>\n</html>")?;
+    files.push(SiteFile {
+        path: PathBuf::from("index.html"),
+        contents: out.into_bytes(),
+    });
+    // GitHub Pages would otherwise run the site through Jekyll
+    files.push(SiteFile {
+        path: PathBuf::from(".nojekyll"),
+        contents: vec![],
+    });
+    Ok(files)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sites_document_commands_interfaces_and_versions() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let dir = tmp_dir.path();
+        fs::write(
+            dir.join("README.md"),
+            "# Usage\n\n| a | b |\n|---|---|\n| 1 | 2 |\n",
+        )
+        .unwrap();
+        fs::write(dir.join("math.wai"), "add: func(a: u32, b: u32) -> u32\n").unwrap();
+        let mut manifest: Manifest = toml::from_str(
+            r#"
+[package]
+name = "user/calc"
+version = "1.1.0"
+description = "Adds <numbers>"
+readme = "README.md"
+
+[[module]]
+name = "calc"
+source = "calc.wasm"
+exports = [{ name = "math", version = "0.1.0", path = "math.wai" }]
+
+[[command]]
+name = "calc"
+module = "calc"
+main_args = "--verbose"
+
+[[command]]
+name = "sum"
+module = "calc"
+description = "Add <numbers>"
+usage = "<NUMBER>..."
+examples = ["1 2 3"]
+"#,
+        )
+        .unwrap();
+        manifest.base_directory_path = dir.to_path_buf();
+        let versions: Vec<PackageVersion> = ["1.0.0", "1.1.0", "0.9.0"]
+            .iter()
+            .map(|version| PackageVersion {
+                name: "user/calc".to_string(),
+                version: version.to_string(),
+                manifest: None,
+                download_url: String::new(),
+                signature: None,
+                license: None,
+                size: None,
+                published_at: None,
+                commands: None,
+            })
+            .collect();
+
+        let files = build_site(&manifest, Some(&versions)).unwrap();
+        let paths: Vec<String> = files
+            .iter()
+            .map(|file| file.path.to_string_lossy().replace('\\', "/"))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "style.css",
+                "interfaces/math.wai",
+                "index.html",
+                ".nojekyll"
+            ]
+        );
+        let index = String::from_utf8(files[2].contents.clone()).unwrap();
+        assert!(index.contains("<p>Adds &lt;numbers&gt;</p>"));
+        assert!(index.contains("wapm run calc [ARGS]...\n# runs with --verbose before ARGS"));
+        assert!(index.contains(
+            "<td>Add &lt;numbers&gt;</td><td><pre>wapm run sum &lt;NUMBER&gt;...\n\n# examples\nwapm run sum 1 2 3</pre>"
+        ));
+        assert!(index.contains("<h3>math 0.1.0</h3>"));
+        assert!(index.contains("<th>a</th>"));
+        let newest = index.find("<td>1.1.0</td>").unwrap();
+        assert!(newest < index.find("<td>0.9.0</td>").unwrap());
+    }
+}
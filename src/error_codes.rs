@@ -0,0 +1,287 @@
+//! Stable codes for the major classes of errors, printed with the errors and explained in more
+//! detail by `wapm explain <code>`
+
+use crate::config::{ConfigError, GlobalConfigError};
+use crate::data::lock::lockfile::LockfileError;
+use crate::data::manifest::ManifestError;
+use crate::data::toolchain::ToolchainError;
+use crate::data::workspace::WorkspaceError;
+use crate::dataflow;
+use crate::dataflow::lockfile_packages;
+use crate::dataflow::manifest_packages;
+use crate::graphql::{GraphQLError, SessionError, SESSION_EXPIRED_EXIT_CODE};
+use crate::validate::ValidationError;
+use failure::Fail;
+
+/// An error code with the guidance shown by `wapm explain`
+#[derive(Debug)]
+pub struct ErrorCode {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub explanation: &'static str,
+}
+
+pub static ERROR_CODES: &[ErrorCode] = &[
+    ErrorCode {
+        code: "W0001",
+        title: "The manifest could not be parsed",
+        explanation: "The `wapm.toml` in the current directory is not valid TOML, or a field has the wrong type.
+
+Common fixes:
+ - check the line and column in the error message for typos such as missing quotes
+ - `version` must be a semantic version like \"1.0.0\"
+ - dependency versions must be strings, e.g. `\"_/sqlite\" = \"0.1.1\"`
+ - run `wapm validate` to check the manifest and its modules",
+    },
+    ErrorCode {
+        code: "W0002",
+        title: "The manifest is invalid",
+        explanation: "The `wapm.toml` could be parsed but its contents are inconsistent.
+
+Common fixes:
+ - every command must refer to a module that is listed in a `[[module]]` section
+ - modules used by commands need an ABI, e.g. `abi = \"wasi\"`
+ - conditions of `[target]` sections must look like `abi = \"wasi\"`; `feature` conditions are not supported",
+    },
+    ErrorCode {
+        code: "W0003",
+        title: "The workspace is invalid",
+        explanation: "The `[workspace]` section of the root manifest, or a member inheriting from it, is invalid.
+
+Common fixes:
+ - every directory in `members` must contain a `wapm.toml`
+ - fields set to `{ workspace = true }` must be present in `[workspace.package]`
+ - only `version`, `license`, `authors` and `repository` can be inherited",
+    },
+    ErrorCode {
+        code: "W0101",
+        title: "The registry could not be reached",
+        explanation: "A request to the registry failed before it returned a response.
+
+Common fixes:
+ - check your internet connection
+ - check the registry url with `wapm config get registry.url`
+ - if you are behind a proxy, set it with `wapm config set proxy.url <url>`",
+    },
+    ErrorCode {
+        code: "W0102",
+        title: "The registry rejected the credentials",
+        explanation: "The registry requires you to be logged in for this operation, or the saved token is no longer valid.
+
+Common fixes:
+ - run `wapm login` and try again
+ - check who you are logged in as with `wapm whoami`
+ - tokens are saved per registry, log in again after changing `registry.url`",
+    },
+    ErrorCode {
+        code: "W0103",
+        title: "The configuration is invalid",
+        explanation: "The wapm configuration file could not be read, or a config key or value is invalid.
+
+Common fixes:
+ - list the valid keys with `wapm config --help`
+ - the config file is `wapm.toml` in the WASMER_DIR directory, check it for typos",
+    },
+    ErrorCode {
+        code: "W0104",
+        title: "The login expired",
+        explanation: "The registry no longer accepts the saved token, and it could not be renewed: the registry does not support refresh tokens, or wapm was not run in a terminal where it could ask you to log in again.
+
+Outside of a terminal, commands failing for this reason exit with code 77 so scripts can tell them apart from other failures.
+
+Common fixes:
+ - run `wapm login` and try again
+ - in CI, log in again or set a fresh token with `wapm config set registry.token <token>`
+ - publishes that failed this way are kept, retry them with `wapm publish --resume` after logging in",
+    },
+    ErrorCode {
+        code: "W0201",
+        title: "The lockfile is invalid",
+        explanation: "The `wapm.lock` could not be read or was written by a newer version of wapm.
+
+Common fixes:
+ - delete `wapm.lock` and run `wapm install` to generate it again
+ - update wapm if the lockfile was generated by a newer version",
+    },
+    ErrorCode {
+        code: "W0202",
+        title: "Dependencies could not be resolved",
+        explanation: "The registry does not have a version of a package that matches the requested version.
+
+Common fixes:
+ - check the spelling of the package name, with its namespace e.g. `_/sqlite`
+ - search for the package with `wapm search <name>`
+ - relax the version requirement in `wapm.toml`",
+    },
+    ErrorCode {
+        code: "W0203",
+        title: "Conflicting package versions",
+        explanation: "Two different versions of the same package were requested, and only one version of a package can be installed in a directory.
+
+Common fixes:
+ - make the versions in `wapm.toml` and on the command line agree
+ - remove one of the versions with `wapm remove <package>`",
+    },
+    ErrorCode {
+        code: "W0204",
+        title: "A package could not be installed",
+        explanation: "A package was resolved but downloading or unpacking it failed.
+
+Common fixes:
+ - try again, the download may have been interrupted
+ - remove the installed packages with `wapm clean` and install again
+ - check that you can write to the packages directory",
+    },
+    ErrorCode {
+        code: "W0301",
+        title: "A wasm module is invalid",
+        explanation: "A module of the package is missing or is not valid WebAssembly, or it does not match the interfaces it declares.
+
+Common fixes:
+ - build the module before publishing and check its `source` path in `wapm.toml`
+ - check the ABI of the module with `wapm detect-abi <file.wasm>`
+ - remove interfaces from `interfaces` that the module does not implement",
+    },
+    ErrorCode {
+        code: "W0401",
+        title: "The pinned toolchain is not installed",
+        explanation: "The `wapm-toolchain.toml` of the project requires versions of wapm or of the runtime that are not installed, and sets `strict = true`.
+
+Common fixes:
+ - run `wapm doctor` to see which versions are required and how to install them
+ - set `WAPM_RUNTIME` to a runtime that matches the `runtime` pin",
+    },
+];
+
+/// Find an error code, ignoring case
+pub fn find_error_code(code: &str) -> Option<&'static ErrorCode> {
+    ERROR_CODES
+        .iter()
+        .find(|error_code| error_code.code.eq_ignore_ascii_case(code))
+}
+
+/// The code of an error, found from the first error in its chain of causes that has one
+pub fn code_for_error(error: &failure::Error) -> Option<&'static str> {
+    error.iter_chain().filter_map(code_for_fail).next()
+}
+
+/// The exit code of a failed command. Commands failing because the login expired get their own,
+/// so scripts can log in again and retry.
+pub fn exit_code_for_error(error: &failure::Error) -> i32 {
+    let session_expired = error
+        .iter_chain()
+        .any(|fail| fail.downcast_ref::<SessionError>().is_some());
+    if session_expired {
+        SESSION_EXPIRED_EXIT_CODE
+    } else {
+        -1
+    }
+}
+
+fn code_for_fail(fail: &dyn Fail) -> Option<&'static str> {
+    if let Some(error) = fail.downcast_ref::<ManifestError>() {
+        return Some(match error {
+            ManifestError::ValidationError(_)
+            | ManifestError::InvalidTargetCondition(_)
+            | ManifestError::UnsupportedTargetCondition(_) => "W0002",
+            ManifestError::WorkspaceInheritanceError(_) => "W0003",
+            _ => "W0001",
+        });
+    }
+    if fail.downcast_ref::<manifest_packages::Error>().is_some() {
+        return Some("W0001");
+    }
+    if fail.downcast_ref::<WorkspaceError>().is_some() {
+        return Some("W0003");
+    }
+    if fail.downcast_ref::<reqwest::Error>().is_some() {
+        return Some("W0101");
+    }
+    if fail.downcast_ref::<SessionError>().is_some() {
+        return Some("W0104");
+    }
+    if let Some(error) = fail.downcast_ref::<GraphQLError>() {
+        if error.is_authentication_error() {
+            return Some("W0102");
+        }
+    }
+    if fail.downcast_ref::<GlobalConfigError>().is_some()
+        || fail.downcast_ref::<ConfigError>().is_some()
+    {
+        return Some("W0103");
+    }
+    if fail.downcast_ref::<LockfileError>().is_some()
+        || fail
+            .downcast_ref::<lockfile_packages::LockfileError>()
+            .is_some()
+    {
+        return Some("W0201");
+    }
+    if let Some(error) = fail.downcast_ref::<dataflow::Error>() {
+        return match error {
+            dataflow::Error::ManifestError(_) => Some("W0001"),
+            dataflow::Error::LockfileError(_) => Some("W0201"),
+            dataflow::Error::ResolveError(_) => Some("W0202"),
+            dataflow::Error::DuplicatePackage(..) => Some("W0203"),
+            dataflow::Error::InstallError(_) => Some("W0204"),
+            _ => None,
+        };
+    }
+    if fail.downcast_ref::<ValidationError>().is_some() {
+        return Some("W0301");
+    }
+    if let Some(ToolchainError::PinsNotSatisfied(..)) = fail.downcast_ref::<ToolchainError>() {
+        return Some("W0401");
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn error_codes_are_unique() {
+        let codes: HashSet<&str> = ERROR_CODES
+            .iter()
+            .map(|error_code| error_code.code)
+            .collect();
+        assert_eq!(codes.len(), ERROR_CODES.len());
+        assert_eq!(find_error_code("w0001").unwrap().code, "W0001");
+    }
+
+    #[test]
+    fn find_code_for_error() {
+        let error: failure::Error = dataflow::Error::DuplicatePackage(
+            "_/sqlite".to_string(),
+            "0.1.0".to_string(),
+            "0.2.0".to_string(),
+        )
+        .into();
+        assert_eq!(code_for_error(&error), Some("W0203"));
+        assert_eq!(code_for_error(&format_err!("unknown")), None);
+
+        let error: failure::Error = SessionError::Expired("https://registry.wapm.io".into()).into();
+        assert_eq!(code_for_error(&error), Some("W0104"));
+        assert_eq!(exit_code_for_error(&error), SESSION_EXPIRED_EXIT_CODE);
+
+        let graphql_error = |message: &str, codes: &[&str], status: u16| -> failure::Error {
+            GraphQLError::Error {
+                message: message.to_string(),
+                codes: codes.iter().map(|code| code.to_string()).collect(),
+                status,
+            }
+            .into()
+        };
+        let error = graphql_error("You must be logged in", &["UNAUTHENTICATED"], 200);
+        assert_eq!(code_for_error(&error), Some("W0102"));
+        let error = graphql_error("Unexpected token in the manifest", &["BAD_USER_INPUT"], 200);
+        assert_eq!(code_for_error(&error), None);
+        let error = graphql_error("Not allowed", &[], 403);
+        assert_eq!(code_for_error(&error), Some("W0102"));
+        // registries without error codes
+        let error = graphql_error("Please login first", &[], 200);
+        assert_eq!(code_for_error(&error), Some("W0102"));
+    }
+}
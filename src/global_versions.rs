@@ -0,0 +1,277 @@
+//! Several versions of a globally installed package can be kept side by side, like toolchain
+//! managers do.
+//!
+//! The default version is installed in the globals directory as usual and its commands get the
+//! plain shims. Every other version is installed in its own directory under `globals/versions`.
+//! All versions get versioned shims, e.g. `tool@1.2.3`, which run `wapm run tool@1.2.3`.
+//! `wapm default pkg@version` swaps the default version.
+
+use crate::config::Config;
+use crate::data::lock::lockfile::Lockfile;
+use crate::dataflow::bin_script::{delete_bin_script, save_bin_script};
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::dataflow::{self, UpdateOptions};
+use crate::registry;
+use semver::{Version, VersionReq};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const VERSIONS_DIR_NAME: &str = "versions";
+
+#[derive(Debug, Fail)]
+pub enum GlobalVersionsError {
+    #[fail(display = "No version of package {} matches {}", _0, _1)]
+    NoMatchingVersion(String, String),
+    #[fail(
+        display = "Version {} of package {} is not installed globally, install it with `wapm install -g {}@{}`",
+        _1, _0, _0, _1
+    )]
+    VersionNotInstalled(String, String),
+    #[fail(display = "Could not install package {}@{}. {}", _0, _1, _2)]
+    CouldNotInstall(String, String, String),
+}
+
+/// The directory of a version installed side by side with the default one
+pub fn version_directory(globals_directory: &Path, name: &str, version: &Version) -> PathBuf {
+    globals_directory
+        .join(VERSIONS_DIR_NAME)
+        .join(format!("{}@{}", name, version))
+}
+
+fn read_lockfile(directory: &Path) -> Option<Lockfile> {
+    match LockfileResult::find_in_directory(directory) {
+        LockfileResult::Lockfile(lockfile) => Some(lockfile),
+        _ => None,
+    }
+}
+
+/// The version of a package installed in the globals directory itself
+pub fn default_version(globals_directory: &Path, name: &str) -> Option<Version> {
+    read_lockfile(globals_directory)?
+        .modules
+        .get(name)?
+        .keys()
+        .next()
+        .cloned()
+}
+
+/// The versions of a package installed side by side with the default one, in ascending order
+pub fn side_by_side_versions(globals_directory: &Path, name: &str) -> Vec<Version> {
+    let versions_directory = globals_directory.join(VERSIONS_DIR_NAME);
+    // the package name can contain a namespace, which is a directory of its own
+    let (parent, package) = match name.rfind('/') {
+        Some(index) => (versions_directory.join(&name[..index]), &name[index + 1..]),
+        None => (versions_directory, name),
+    };
+    let prefix = format!("{}@", package);
+    let mut versions: Vec<Version> = fs::read_dir(parent)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter_map(|entry| {
+                    let file_name = entry.file_name().to_string_lossy().to_string();
+                    if file_name.starts_with(&prefix) {
+                        Version::parse(&file_name[prefix.len()..]).ok()
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    versions.sort();
+    versions
+}
+
+/// The exact version to install for a version or version requirement
+pub fn resolve_version(name: &str, version: &str) -> Result<Version, failure::Error> {
+    if let Ok(version) = Version::parse(version) {
+        return Ok(version);
+    }
+    let requirement = VersionReq::parse(version)?;
+    registry::backend()?
+        .package_versions(&[name.to_string()])?
+        .into_iter()
+        .filter_map(|package_version| Version::parse(&package_version.version).ok())
+        .filter(|v| requirement.matches(v))
+        .max()
+        .ok_or_else(|| {
+            GlobalVersionsError::NoMatchingVersion(name.to_string(), version.to_string()).into()
+        })
+}
+
+/// Install a version of a package next to the default one
+pub fn install_side_by_side(
+    globals_directory: &Path,
+    name: &str,
+    version: &Version,
+    create_shims: bool,
+) -> Result<(), failure::Error> {
+    let directory = version_directory(globals_directory, name, version);
+    fs::create_dir_all(&directory)?;
+    let options = UpdateOptions {
+        create_bin_scripts: false,
+        write_editor_metadata: false,
+        confirm_plan: false,
+        export_plan: None,
+    };
+    dataflow::update_with_options(
+        vec![(name, &version.to_string())],
+        vec![],
+        &directory,
+        &options,
+    )
+    .map_err(|e| {
+        GlobalVersionsError::CouldNotInstall(name.to_string(), version.to_string(), e.to_string())
+    })?;
+    if create_shims {
+        save_versioned_shims(globals_directory, &directory, name)?;
+    }
+    Ok(())
+}
+
+/// Create the versioned shims, like `tool@1.2.3`, for the commands of a package installed in
+/// `directory`
+pub fn save_versioned_shims(
+    globals_directory: &Path,
+    directory: &Path,
+    name: &str,
+) -> Result<(), failure::Error> {
+    if let Some(lockfile) = read_lockfile(directory) {
+        for command in lockfile.commands.values() {
+            if command.package_name == name {
+                let shim_name = format!("{}@{}", command.name, command.package_version);
+                save_bin_script(globals_directory, shim_name, command.sandbox.as_deref())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Remove a version installed side by side and its versioned shims. Returns false if that
+/// version was not installed side by side.
+pub fn uninstall_side_by_side(
+    globals_directory: &Path,
+    name: &str,
+    version: &Version,
+) -> Result<bool, failure::Error> {
+    let directory = version_directory(globals_directory, name, version);
+    if !directory.is_dir() {
+        return Ok(false);
+    }
+    if let Some(lockfile) = read_lockfile(&directory) {
+        for command in lockfile.commands.values() {
+            let shim_name = format!("{}@{}", command.name, command.package_version);
+            delete_bin_script(globals_directory, shim_name)?;
+        }
+    }
+    fs::remove_dir_all(&directory)?;
+    Ok(true)
+}
+
+/// Make an installed version the default one. The previous default version is kept side by side.
+pub fn set_default_version(
+    globals_directory: &Path,
+    name: &str,
+    version: &Version,
+) -> Result<(), failure::Error> {
+    let directory = version_directory(globals_directory, name, version);
+    if !directory.is_dir() {
+        return Err(GlobalVersionsError::VersionNotInstalled(
+            name.to_string(),
+            version.to_string(),
+        )
+        .into());
+    }
+    if let Some(current) = default_version(globals_directory, name) {
+        if !version_directory(globals_directory, name, &current).is_dir() {
+            install_side_by_side(globals_directory, name, &current, false)?;
+        }
+    }
+    dataflow::update_with_options(
+        vec![(name, &version.to_string())],
+        vec![],
+        globals_directory,
+        &UpdateOptions::default(),
+    )
+    .map_err(|e| {
+        GlobalVersionsError::CouldNotInstall(name.to_string(), version.to_string(), e.to_string())
+    })?;
+    fs::remove_dir_all(&directory)?;
+    Ok(())
+}
+
+/// The directory with the package of a versioned command like `tool@1.2.3` and the plain name of
+/// the command in it
+pub fn find_versioned_command(command_name: &str) -> Option<(PathBuf, String)> {
+    let index = command_name.rfind('@')?;
+    let (command, version) = (&command_name[..index], &command_name[index + 1..]);
+    let version = Version::parse(version).ok()?;
+    let globals_directory = Config::get_globals_directory().ok()?;
+    let provides_command = |directory: &Path| {
+        read_lockfile(directory)
+            .and_then(|lockfile| lockfile.commands.get(command).cloned())
+            .map(|found| found.package_version == version)
+            .unwrap_or(false)
+    };
+    if provides_command(&globals_directory) {
+        return Some((globals_directory, command.to_string()));
+    }
+    let suffix = format!("@{}", version);
+    side_by_side_directories(&globals_directory)
+        .into_iter()
+        .filter(|directory| directory.to_string_lossy().ends_with(&suffix))
+        .find(|directory| provides_command(directory))
+        .map(|directory| (directory, command.to_string()))
+}
+
+/// The directories of every version installed side by side, which are
+/// `versions/<name>@<version>` or `versions/<namespace>/<name>@<version>`
+pub fn side_by_side_directories(globals_directory: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(globals_directory.join(VERSIONS_DIR_NAME)) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut directories = vec![];
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().contains('@') {
+            directories.push(path);
+        } else if let Ok(namespace_entries) = fs::read_dir(&path) {
+            directories.extend(
+                namespace_entries
+                    .filter_map(Result::ok)
+                    .filter(|e| e.file_name().to_string_lossy().contains('@'))
+                    .map(|e| e.path()),
+            );
+        }
+    }
+    directories.sort();
+    directories
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn list_side_by_side_versions() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let globals = dir.path();
+        for version in &["1.10.0", "1.2.0"] {
+            fs::create_dir_all(version_directory(
+                globals,
+                "_/tool",
+                &Version::parse(version).unwrap(),
+            ))
+            .unwrap();
+        }
+        fs::create_dir_all(globals.join(VERSIONS_DIR_NAME).join("_/toolbox@2.0.0")).unwrap();
+        assert_eq!(
+            side_by_side_versions(globals, "_/tool"),
+            vec![Version::new(1, 2, 0), Version::new(1, 10, 0)]
+        );
+        assert!(side_by_side_versions(globals, "_/other").is_empty());
+        assert!(default_version(globals, "_/tool").is_none());
+    }
+}
@@ -1,27 +1,304 @@
+use crate::credentials;
+use crate::http_trace;
 use crate::proxy;
+use crate::util;
 use failure;
-use graphql_client::{QueryBody, Response};
+use graphql_client::{GraphQLQuery, QueryBody, Response};
 use reqwest::blocking::multipart;
 use reqwest::blocking::Client;
 use reqwest::header::USER_AGENT;
+use reqwest::StatusCode;
 use serde;
+use serde_json::json;
 use std::string::ToString;
 
 use super::config::Config;
 
+/// The exit code of commands that failed because the login expired and could not be renewed
+pub const SESSION_EXPIRED_EXIT_CODE: i32 = 77;
+
+/// The `extensions.code` of GraphQL errors about a missing token or missing permissions
+const AUTHENTICATION_ERROR_CODES: &[&str] = &[
+    "UNAUTHENTICATED",
+    "UNAUTHORIZED",
+    "FORBIDDEN",
+    "PERMISSION_DENIED",
+];
+
 #[derive(Debug, Fail)]
-enum GraphQLError {
+pub enum GraphQLError {
     #[fail(display = "{}", message)]
-    Error { message: String },
+    Error {
+        message: String,
+        /// The `extensions.code` of the errors, for registries that set one
+        codes: Vec<String>,
+        /// The HTTP status of the response
+        status: u16,
+    },
+    #[fail(display = "The registry did not accept the token (HTTP 401)")]
+    Unauthorized,
 }
 
+impl GraphQLError {
+    /// Errors returned by the registry when the token is missing or not allowed to do something.
+    /// The message is only looked at when the registry gives neither an error code nor a 403.
+    pub fn is_authentication_error(&self) -> bool {
+        match self {
+            GraphQLError::Error {
+                message,
+                codes,
+                status,
+            } => {
+                if *status == StatusCode::FORBIDDEN.as_u16() {
+                    return true;
+                }
+                if !codes.is_empty() {
+                    return codes.iter().any(|code| {
+                        AUTHENTICATION_ERROR_CODES
+                            .iter()
+                            .any(|auth_code| code.eq_ignore_ascii_case(auth_code))
+                    });
+                }
+                let message = message.to_lowercase();
+                ["authenticat", "login", "permission", "token"]
+                    .iter()
+                    .any(|word| message.contains(word))
+            }
+            GraphQLError::Unauthorized => true,
+        }
+    }
+
+    /// Errors returned by the registry for a token that expired or that it can't decode anymore,
+    /// as opposed to a valid token that is not allowed to do something
+    pub fn is_session_expired(&self) -> bool {
+        match self {
+            GraphQLError::Error { message, .. } => {
+                let message = message.to_lowercase();
+                [
+                    "signature has expired",
+                    "error decoding signature",
+                    "token is expired",
+                    "invalid token",
+                    "invalid refresh token",
+                ]
+                .iter()
+                .any(|phrase| message.contains(phrase))
+            }
+            GraphQLError::Unauthorized => true,
+        }
+    }
+
+    /// The error of registries asked for a persisted query they don't know yet
+    pub fn is_persisted_query_not_found(&self) -> bool {
+        match self {
+            GraphQLError::Error { message, .. } => message
+                .to_lowercase()
+                .replace(' ', "")
+                .contains("persistedquerynotfound"),
+            GraphQLError::Unauthorized => false,
+        }
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum SessionError {
+    #[fail(
+        display = "The login to {} expired and could not be renewed, run `wapm login` to log in again",
+        _0
+    )]
+    Expired(String),
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/queries/refresh_token.graphql",
+    response_derives = "Debug"
+)]
+struct RefreshTokenMutation;
+
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub type DateTime = String;
 
+/// How the text of a query is sent to the registry
+#[derive(Clone, Copy, PartialEq)]
+enum QueryText {
+    /// The query itself
+    Full,
+    /// Only the hash of a persisted query
+    Hash,
+    /// The query and its hash, so the registry persists it
+    FullAndHash,
+}
+
 pub fn execute_query_modifier<R, V, F>(
     query: &QueryBody<V>,
     form_modifier: F,
 ) -> Result<R, failure::Error>
+where
+    for<'de> R: serde::Deserialize<'de>,
+    V: serde::Serialize,
+    F: Fn(multipart::Form) -> multipart::Form,
+{
+    with_session_renewal(|config| {
+        send_query(
+            config,
+            config.registry.token.as_deref(),
+            query.query,
+            query.operation_name,
+            &query.variables,
+            QueryText::Full,
+            &form_modifier,
+        )
+    })
+}
+
+pub fn execute_query<R, V>(query: &QueryBody<V>) -> Result<R, failure::Error>
+where
+    for<'de> R: serde::Deserialize<'de>,
+    V: serde::Serialize,
+{
+    execute_raw_query(query.query, query.operation_name, &query.variables)
+}
+
+/// Execute a query that was built at runtime, like a batch of lookups. Registries with
+/// `registry.persisted-queries` set are sent the hash of the query, and the query itself only
+/// when they don't know it yet.
+pub fn execute_raw_query<R, V>(
+    query: &str,
+    operation_name: &str,
+    variables: &V,
+) -> Result<R, failure::Error>
+where
+    for<'de> R: serde::Deserialize<'de>,
+    V: serde::Serialize,
+{
+    with_session_renewal(|config| {
+        let token = config.registry.token.as_deref();
+        if !config.registry.persisted_queries {
+            return send_query(
+                config,
+                token,
+                query,
+                operation_name,
+                variables,
+                QueryText::Full,
+                |f| f,
+            );
+        }
+        match send_query(
+            config,
+            token,
+            query,
+            operation_name,
+            variables,
+            QueryText::Hash,
+            |f| f,
+        ) {
+            Err(e)
+                if e.downcast_ref::<GraphQLError>()
+                    .map(GraphQLError::is_persisted_query_not_found)
+                    .unwrap_or(false) => {}
+            result => return result,
+        }
+        send_query(
+            config,
+            token,
+            query,
+            operation_name,
+            variables,
+            QueryText::FullAndHash,
+            |f| f,
+        )
+    })
+}
+
+/// Run the requests of an authenticated call, and when the registry says the saved token expired,
+/// renew the login and run them again. The token is refreshed if the registry supports refresh
+/// tokens. Otherwise the user is asked to log in again, and outside of a terminal the call fails
+/// with `SessionError::Expired`.
+fn with_session_renewal<R, S>(send: S) -> Result<R, failure::Error>
+where
+    S: Fn(&Config) -> Result<R, failure::Error>,
+{
+    // the saved token may be encrypted, the requests are sent with the plain one
+    let config = credentials::with_revealed_token(Config::from_file()?)?;
+    let token = match (send(&config), config.registry.token.clone()) {
+        (Err(e), Some(token)) if is_session_expired(&e) => token,
+        (result, _) => return result,
+    };
+    match refresh_token(&config, &token) {
+        Ok(refreshed) => {
+            info!("Renewed the login to {}", config.registry.url);
+            let mut saved_config = Config::from_file()?;
+            saved_config.registry.token = Some(credentials::protect(&saved_config, &refreshed)?);
+            saved_config.save()?;
+            let mut config = config;
+            config.registry.token = Some(refreshed);
+            return send(&config);
+        }
+        Err(e) => debug!("Could not refresh the token: {}", e),
+    }
+
+    // the expired token is of no use anymore, and logging in must not send it
+    let mut config = Config::from_file()?;
+    let registry_url = config.registry.url.clone();
+    config.registry.token = None;
+    config.save()?;
+    let interactive = atty::is(atty::Stream::Stdin) && !util::wapm_should_accept_all_prompts();
+    if !interactive
+        || !util::prompt_user_for_yes(&format!(
+            "The login to {} expired. Log in again?",
+            registry_url
+        ))?
+    {
+        return Err(SessionError::Expired(registry_url).into());
+    }
+    crate::commands::login()?;
+    send(&credentials::with_revealed_token(Config::from_file()?)?)
+}
+
+fn is_session_expired(error: &failure::Error) -> bool {
+    error
+        .downcast_ref::<GraphQLError>()
+        .map(GraphQLError::is_session_expired)
+        .unwrap_or(false)
+}
+
+/// Exchange the saved token for a new one, for registries that support refresh tokens
+fn refresh_token(config: &Config, token: &str) -> Result<String, failure::Error> {
+    let q = RefreshTokenMutation::build_query(refresh_token_mutation::Variables {
+        refresh_token: token.to_string(),
+    });
+    let response: refresh_token_mutation::ResponseData = send_query(
+        config,
+        None,
+        q.query,
+        q.operation_name,
+        &q.variables,
+        QueryText::Full,
+        |f| f,
+    )?;
+    response
+        .refresh_token
+        .and_then(|payload| payload.refresh_token)
+        .ok_or_else(|| format_err!("the registry returned no token"))
+}
+
+/// The hash that registries supporting persisted queries know a query by
+pub fn persisted_query_hash(query: &str) -> String {
+    util::sha256_hex(query.as_bytes())
+}
+
+fn send_query<R, V, F>(
+    config: &Config,
+    token: Option<&str>,
+    query: &str,
+    operation_name: &str,
+    variables: &V,
+    query_text: QueryText,
+    form_modifier: F,
+) -> Result<R, failure::Error>
 where
     for<'de> R: serde::Deserialize<'de>,
     V: serde::Serialize,
@@ -37,15 +314,25 @@ where
         };
         builder.build()?
     };
-    let config = Config::from_file()?;
 
     let registry_url = &config.registry.get_graphql_url();
-    let vars = serde_json::to_string(&query.variables).unwrap();
+    let vars = serde_json::to_string(variables).unwrap();
 
-    let form = multipart::Form::new()
-        .text("query", query.query.to_string())
-        .text("operationName", query.operation_name.to_string())
+    let mut form = multipart::Form::new()
+        .text("operationName", operation_name.to_string())
         .text("variables", vars);
+    if query_text != QueryText::Hash {
+        form = form.text("query", query.to_string());
+    }
+    if query_text != QueryText::Full {
+        let extensions = json!({
+            "persistedQuery": {
+                "version": 1,
+                "sha256Hash": persisted_query_hash(query),
+            }
+        });
+        form = form.text("extensions", extensions.to_string());
+    }
 
     let form = form_modifier(form);
 
@@ -56,28 +343,31 @@ where
         whoami::os().to_lowercase(),
     );
 
-    let res = client
+    let request = client
         .post(registry_url)
         .multipart(form)
-        .bearer_auth(&config.registry.token.unwrap_or_else(|| "".to_string()))
-        .header(USER_AGENT, user_agent)
-        .send()?;
+        .bearer_auth(token.unwrap_or(""))
+        .header(USER_AGENT, user_agent);
+    let res = http_trace::send(&client, request, Some(operation_name))?;
+    if res.status() == StatusCode::UNAUTHORIZED {
+        return Err(GraphQLError::Unauthorized.into());
+    }
+    let status = res.status().as_u16();
 
     let response_body: Response<R> = res.json()?;
     if let Some(errors) = response_body.errors {
+        let codes: Vec<String> = errors
+            .iter()
+            .filter_map(|err| err.extensions.as_ref()?.get("code")?.as_str())
+            .map(str::to_string)
+            .collect();
         let error_messages: Vec<String> = errors.into_iter().map(|err| err.message).collect();
         return Err(GraphQLError::Error {
             message: error_messages.join(", "),
+            codes,
+            status,
         }
         .into());
     }
     Ok(response_body.data.expect("missing response data"))
 }
-
-pub fn execute_query<R, V>(query: &QueryBody<V>) -> Result<R, failure::Error>
-where
-    for<'de> R: serde::Deserialize<'de>,
-    V: serde::Serialize,
-{
-    execute_query_modifier(query, |f| f)
-}
@@ -0,0 +1,219 @@
+//! A journal of the commands that changed the manifest or the lockfile of a project, kept in
+//! the packages directory. Every entry holds the files before and after the command, so
+//! `wapm history` can list what changed and `wapm undo` can put back what was there before.
+
+use crate::data::lock::LOCKFILE_NAME;
+use crate::data::manifest::MANIFEST_FILE_NAME;
+use crate::util::get_packages_dir;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const HISTORY_DIR_NAME: &str = ".history";
+/// Older entries are dropped once the journal holds this many
+const MAX_ENTRIES: usize = 100;
+
+/// The contents of the manifest and the lockfile of a project at some point, `None` for a file
+/// that did not exist
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct Snapshot {
+    pub manifest: Option<String>,
+    pub lockfile: Option<String>,
+}
+
+impl Snapshot {
+    pub fn take(directory: &Path) -> Self {
+        Snapshot {
+            manifest: fs::read_to_string(directory.join(MANIFEST_FILE_NAME)).ok(),
+            lockfile: fs::read_to_string(directory.join(LOCKFILE_NAME)).ok(),
+        }
+    }
+
+    /// Write the files back, removing those that did not exist
+    pub fn restore(&self, directory: &Path) -> io::Result<()> {
+        restore_file(&directory.join(MANIFEST_FILE_NAME), &self.manifest)?;
+        restore_file(&directory.join(LOCKFILE_NAME), &self.lockfile)
+    }
+
+    /// The versions of the packages in the lockfile
+    fn locked_packages(&self) -> BTreeMap<String, Vec<String>> {
+        let modules = self
+            .lockfile
+            .as_ref()
+            .and_then(|lockfile| lockfile.parse::<toml::Value>().ok())
+            .and_then(|lockfile| lockfile.get("modules").and_then(|m| m.as_table()).cloned())
+            .unwrap_or_default();
+        modules
+            .into_iter()
+            .map(|(name, versions)| {
+                let versions = versions
+                    .as_table()
+                    .map(|versions| versions.keys().cloned().collect())
+                    .unwrap_or_default();
+                (name, versions)
+            })
+            .collect()
+    }
+}
+
+fn restore_file(path: &Path, contents: &Option<String>) -> io::Result<()> {
+    match contents {
+        Some(contents) => fs::write(path, contents),
+        None if path.exists() => fs::remove_file(path),
+        None => Ok(()),
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct HistoryEntry {
+    pub id: u64,
+    /// The command line that made the change, like `wapm add _/sqlite`
+    pub operation: String,
+    pub recorded_at: DateTime<Utc>,
+    pub before: Snapshot,
+    pub after: Snapshot,
+}
+
+impl HistoryEntry {
+    /// The packages the operation added, removed or changed the version of, like `+ _/sqlite 0.1.1`
+    pub fn changes(&self) -> Vec<String> {
+        let before = self.before.locked_packages();
+        let after = self.after.locked_packages();
+        let mut changes = vec![];
+        for (name, versions) in &after {
+            match before.get(name) {
+                None => changes.push(format!("+ {} {}", name, versions.join(", "))),
+                Some(old_versions) if old_versions != versions => changes.push(format!(
+                    "~ {} {} -> {}",
+                    name,
+                    old_versions.join(", "),
+                    versions.join(", ")
+                )),
+                Some(_) => {}
+            }
+        }
+        for (name, versions) in &before {
+            if !after.contains_key(name) {
+                changes.push(format!("- {} {}", name, versions.join(", ")));
+            }
+        }
+        if changes.is_empty() && self.before.manifest != self.after.manifest {
+            changes.push(format!("~ {}", MANIFEST_FILE_NAME));
+        }
+        changes
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum HistoryError {
+    #[fail(display = "Could not read the history entry {}: {}", _0, _1)]
+    InvalidEntry(String, String),
+}
+
+fn history_dir(directory: &Path) -> PathBuf {
+    get_packages_dir(directory).join(HISTORY_DIR_NAME)
+}
+
+/// The entries of the journal of the project, oldest first
+pub fn entries(directory: &Path) -> Result<Vec<HistoryEntry>, failure::Error> {
+    let history_dir = history_dir(directory);
+    if !history_dir.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut entries = vec![];
+    for file in fs::read_dir(&history_dir)? {
+        let path = file?.path();
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        let entry: HistoryEntry = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| HistoryError::InvalidEntry(path.display().to_string(), e.to_string()))?;
+        entries.push(entry);
+    }
+    entries.sort_by_key(|entry| entry.id);
+    Ok(entries)
+}
+
+/// Journal the operation if it changed the manifest or the lockfile since `before` was taken
+pub fn record(
+    directory: &Path,
+    operation: &str,
+    before: Snapshot,
+) -> Result<Option<HistoryEntry>, failure::Error> {
+    let after = Snapshot::take(directory);
+    if after == before {
+        return Ok(None);
+    }
+    let mut entries = entries(directory)?;
+    let entry = HistoryEntry {
+        id: entries.last().map_or(1, |last| last.id + 1),
+        operation: operation.to_string(),
+        recorded_at: Utc::now(),
+        before,
+        after,
+    };
+    let history_dir = history_dir(directory);
+    fs::create_dir_all(&history_dir)?;
+    fs::write(
+        history_dir.join(format!("{}.json", entry.id)),
+        serde_json::to_string_pretty(&entry)?,
+    )?;
+    entries.push(entry.clone());
+    if entries.len() > MAX_ENTRIES {
+        for old in &entries[..entries.len() - MAX_ENTRIES] {
+            fs::remove_file(history_dir.join(format!("{}.json", old.id)))?;
+        }
+    }
+    Ok(Some(entry))
+}
+
+/// Take an entry out of the journal once it is undone
+pub fn remove(directory: &Path, entry: &HistoryEntry) -> io::Result<()> {
+    fs::remove_file(history_dir(directory).join(format!("{}.json", entry.id)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LOCKFILE_BEFORE: &str = r#"
+[modules."_/sqlite"."0.1.1".sqlite]
+name = "sqlite"
+"#;
+    const LOCKFILE_AFTER: &str = r#"
+[modules."_/sqlite"."0.2.0".sqlite]
+name = "sqlite"
+[modules."_/cowsay"."0.1.0".cowsay]
+name = "cowsay"
+"#;
+
+    #[test]
+    fn operations_are_journaled_and_undone() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join(LOCKFILE_NAME), LOCKFILE_BEFORE).unwrap();
+        let before = Snapshot::take(dir.path());
+        assert!(record(dir.path(), "wapm install", before.clone())
+            .unwrap()
+            .is_none());
+
+        fs::write(dir.path().join(MANIFEST_FILE_NAME), "[dependencies]\n").unwrap();
+        fs::write(dir.path().join(LOCKFILE_NAME), LOCKFILE_AFTER).unwrap();
+        let entry = record(dir.path(), "wapm add _/cowsay", before.clone())
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.id, 1);
+        assert_eq!(
+            entry.changes(),
+            vec!["+ _/cowsay 0.1.0", "~ _/sqlite 0.1.1 -> 0.2.0"]
+        );
+        assert_eq!(entries(dir.path()).unwrap(), vec![entry.clone()]);
+
+        entry.before.restore(dir.path()).unwrap();
+        assert_eq!(Snapshot::take(dir.path()), before);
+        assert!(!dir.path().join(MANIFEST_FILE_NAME).exists());
+        remove(dir.path(), &entry).unwrap();
+        assert!(entries(dir.path()).unwrap().is_empty());
+    }
+}
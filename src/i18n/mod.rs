@@ -0,0 +1,167 @@
+//! The catalog of user facing messages, so they can be shown in the language of the user.
+//!
+//! Messages are looked up by keys like `init.package_name` in the catalog of the locale set by
+//! the `locale` config key, or else by `LC_ALL`, `LC_MESSAGES` or `LANG`. Messages missing from
+//! a catalog are shown in English. New languages only need a catalog added to `CATALOGS`.
+
+use crate::config::Config;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+
+/// The language every message is available in
+const FALLBACK_LANGUAGE: &str = "en";
+
+/// The catalogs of messages, by language
+const CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("en.toml")),
+    ("es", include_str!("es.toml")),
+];
+
+lazy_static! {
+    static ref LANGUAGE: String = current_language();
+    static ref MESSAGES: Messages = Messages::for_language(&LANGUAGE);
+}
+
+struct Messages {
+    messages: HashMap<String, String>,
+    fallback: HashMap<String, String>,
+}
+
+impl Messages {
+    fn for_language(language: &str) -> Self {
+        Self {
+            messages: load_catalog(language),
+            fallback: load_catalog(FALLBACK_LANGUAGE),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.messages
+            .get(key)
+            .or_else(|| self.fallback.get(key))
+            .map(String::as_str)
+    }
+}
+
+/// The language of the configured locale, e.g. `es` for `es_ES.UTF-8`
+fn current_language() -> String {
+    let config_locale = Config::from_file().ok().and_then(|config| config.locale);
+    let locale = config_locale.or_else(|| {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+    });
+    match locale {
+        Some(locale) => language_of_locale(&locale),
+        None => FALLBACK_LANGUAGE.to_owned(),
+    }
+}
+
+fn language_of_locale(locale: &str) -> String {
+    locale
+        .split(&['_', '-', '.', '@'][..])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/// Flatten a catalog into a map from keys like `init.package_name` to messages
+fn load_catalog(language: &str) -> HashMap<String, String> {
+    let mut messages = HashMap::new();
+    let source = match CATALOGS.iter().find(|(name, _)| *name == language) {
+        Some((_, source)) => source,
+        None => return messages,
+    };
+    let catalog: toml::value::Table = match toml::from_str(source) {
+        Ok(catalog) => catalog,
+        Err(e) => {
+            debug!(
+                "Could not parse the \"{}\" message catalog: {}",
+                language, e
+            );
+            return messages;
+        }
+    };
+    for (section, section_messages) in catalog {
+        for (name, message) in section_messages.as_table().into_iter().flatten() {
+            if let Some(message) = message.as_str() {
+                messages.insert(format!("{}.{}", section, name), message.to_owned());
+            }
+        }
+    }
+    messages
+}
+
+/// The language of the user, like `es`, which package descriptions and readmes are shown in when
+/// they are translated
+pub fn language() -> &'static str {
+    &LANGUAGE
+}
+
+/// Look up a message. The key itself is returned if no catalog has the message.
+pub fn message(key: &str) -> String {
+    MESSAGES.get(key).unwrap_or(key).to_owned()
+}
+
+/// Look up a message and fill in its `{name}` placeholders
+pub fn format_message(key: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    fill_placeholders(&message(key), args)
+}
+
+fn fill_placeholders(message: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    args.iter()
+        .fold(message.to_owned(), |message, (name, value)| {
+            message.replace(&format!("{{{}}}", name), &value.to_string())
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn catalogs_match_the_fallback() {
+        let fallback = load_catalog(FALLBACK_LANGUAGE);
+        assert!(!fallback.is_empty());
+        for (language, _) in CATALOGS {
+            for (key, message) in load_catalog(language) {
+                let fallback_message = fallback
+                    .get(&key)
+                    .unwrap_or_else(|| panic!("\"{}\" is missing from the fallback", key));
+                let placeholders = |message: &str| {
+                    let mut placeholders: Vec<String> = message
+                        .split('{')
+                        .skip(1)
+                        .filter_map(|part| part.split('}').next())
+                        .map(str::to_owned)
+                        .collect();
+                    placeholders.sort();
+                    placeholders
+                };
+                assert_eq!(
+                    placeholders(&message),
+                    placeholders(fallback_message),
+                    "placeholders of \"{}\" in \"{}\"",
+                    key,
+                    language
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fill_in_placeholders() {
+        assert_eq!(
+            fill_placeholders(
+                "Installed {package} {version}",
+                &[("package", &"_/sqlite"), ("version", &"0.1.1")]
+            ),
+            "Installed _/sqlite 0.1.1"
+        );
+        assert_eq!(language_of_locale("es_ES.UTF-8"), "es");
+        assert_eq!(language_of_locale("C"), "c");
+    }
+}
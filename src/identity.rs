@@ -0,0 +1,160 @@
+//! The user each registry last said the saved token belongs to, and when it said so.
+//!
+//! `wapm whoami` and the checks before a publish fall back to it when the registry can't be
+//! reached, saying how old it is. A verified identity is used for an hour before the registry is
+//! asked again, unless a refresh is asked for.
+
+use crate::config::Config;
+use crate::util;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+const IDENTITIES_FILE_NAME: &str = "identities.json";
+
+/// How long a verified identity is used without asking the registry again
+const FRESH_FOR_MINUTES: i64 = 60;
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+struct CachedIdentity {
+    username: String,
+    verified_at: DateTime<Utc>,
+}
+
+/// Who the user is on the registry of the config
+#[derive(Debug, PartialEq)]
+pub enum Identity {
+    /// The registry verified the identity at `verified_at`, recently enough to be trusted
+    Verified {
+        username: String,
+        verified_at: DateTime<Utc>,
+    },
+    /// The registry could not be reached, and last verified the identity at `verified_at`
+    Stale {
+        username: String,
+        verified_at: DateTime<Utc>,
+        error: String,
+    },
+    LoggedOut,
+}
+
+fn identities_path() -> Result<PathBuf, failure::Error> {
+    Ok(Config::get_folder()?.join(IDENTITIES_FILE_NAME))
+}
+
+fn load() -> BTreeMap<String, CachedIdentity> {
+    identities_path()
+        .ok()
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .unwrap_or_default()
+}
+
+fn save(identities: &BTreeMap<String, CachedIdentity>) -> Result<(), failure::Error> {
+    let path = identities_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_vec_pretty(identities)?)?;
+    Ok(())
+}
+
+/// Remember that the registry of the config verified `username` just now
+pub fn remember(username: &str) -> Result<(), failure::Error> {
+    let registry = Config::from_file()?.registry.url;
+    let mut identities = load();
+    identities.insert(
+        registry,
+        CachedIdentity {
+            username: username.to_string(),
+            verified_at: Utc::now(),
+        },
+    );
+    save(&identities)
+}
+
+/// Forget the identity on the registry of the config, after a logout
+pub fn forget() -> Result<(), failure::Error> {
+    let registry = Config::from_file()?.registry.url;
+    let mut identities = load();
+    if identities.remove(&registry).is_some() {
+        save(&identities)?;
+    }
+    Ok(())
+}
+
+fn is_fresh(identity: &CachedIdentity, now: DateTime<Utc>) -> bool {
+    now.signed_duration_since(identity.verified_at) < Duration::minutes(FRESH_FOR_MINUTES)
+}
+
+/// The identity of the user on the registry of the config. The registry is asked when the last
+/// verification is more than an hour old or `refresh` is set, and the last verified identity is
+/// returned as stale when the registry can't be reached.
+pub fn current(refresh: bool) -> Result<Identity, failure::Error> {
+    let config = Config::from_file()?;
+    if config.registry.token.is_none() {
+        return Ok(Identity::LoggedOut);
+    }
+    let cached = load().remove(&config.registry.url);
+    if let Some(cached) = cached.as_ref().filter(|_| !refresh) {
+        if is_fresh(cached, Utc::now()) {
+            return Ok(Identity::Verified {
+                username: cached.username.clone(),
+                verified_at: cached.verified_at,
+            });
+        }
+    }
+    match util::get_username() {
+        Ok(Some(username)) => {
+            if let Err(e) = remember(&username) {
+                debug!("Could not save the identity: {}", e);
+            }
+            Ok(Identity::Verified {
+                username,
+                verified_at: Utc::now(),
+            })
+        }
+        Ok(None) => {
+            if let Err(e) = forget() {
+                debug!("Could not forget the identity: {}", e);
+            }
+            Ok(Identity::LoggedOut)
+        }
+        Err(e) => match cached {
+            Some(cached) => Ok(Identity::Stale {
+                username: cached.username,
+                verified_at: cached.verified_at,
+                error: e.to_string(),
+            }),
+            None => Err(e),
+        },
+    }
+}
+
+/// A verification time, like `2020-03-09 14:02 UTC`
+pub fn format_verified_at(verified_at: DateTime<Utc>) -> String {
+    verified_at.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn identities_are_trusted_for_an_hour() {
+        let now = Utc::now();
+        let identity = CachedIdentity {
+            username: "alice".to_string(),
+            verified_at: now - Duration::minutes(59),
+        };
+        assert!(is_fresh(&identity, now));
+        assert!(!is_fresh(&identity, now + Duration::minutes(2)));
+
+        let identities: BTreeMap<String, CachedIdentity> =
+            std::iter::once(("https://registry.wapm.io/graphql".to_string(), identity)).collect();
+        let saved = serde_json::to_vec(&identities).unwrap();
+        let loaded: BTreeMap<String, CachedIdentity> = serde_json::from_slice(&saved).unwrap();
+        assert_eq!(loaded, identities);
+    }
+}
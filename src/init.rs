@@ -1,21 +1,76 @@
 //! logic to init a directory for use with wapm
 
+use crate::abi::detect::{detect_abi, Confidence};
+use crate::abi::emscripten::{self, EMSCRIPTEN_INTERFACE_NAME};
 use crate::abi::Abi;
+use crate::commands::{search_packages, SearchResult};
 use crate::data::manifest::MANIFEST_FILE_NAME;
-use crate::data::manifest::{Command, Manifest, Module, Package};
+use crate::data::manifest::{
+    Build, Command, ExportedInterface, InterfaceDefinition, Manifest, Module, Package,
+};
+use crate::database;
+use crate::dataflow;
+use crate::i18n::{format_message, message};
+use crate::interfaces;
+use crate::templates::{self, Template};
 use crate::util;
 
-use dialoguer::{Confirmation, Input, Select};
+mod answers;
+pub(crate) mod presets;
+pub use answers::FlagAnswers;
+use answers::{validate_answer, CommandAnswer, InitAnswers};
+use presets::Preset;
+
+use dialoguer::{Checkboxes, Confirmation, Input, Select};
 use semver::Version;
 use std::{
     any::Any,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs,
     io::{Read, Write},
     path::{Path, PathBuf},
 };
 
-const WASI_LAST_VERSION: &str = "0.0.0-unstable";
+pub(crate) const WASI_LAST_VERSION: &str = "0.0.0-unstable";
+/// Where `wapm init --lib` puts an example package using the library
+const EXAMPLE_CONSUMER_DIR: &str = "examples/consumer";
+/// How many packages `wapm init` suggests as starter dependencies
+const STARTER_DEPENDENCY_COUNT: usize = 10;
+
+/// Options for setting up a new package
+#[derive(Debug, Default)]
+pub struct InitOptions {
+    /// Use the defaults instead of asking the user
+    pub force_yes: bool,
+    /// Set up a library that exports interfaces instead of a package with commands
+    pub lib: bool,
+    /// Set up an interface package, which only publishes interface definitions
+    pub interface: bool,
+    /// The name of a language preset that fills in the defaults for its toolchain
+    pub lang: Option<String>,
+    /// The name of a project template that scaffolds the sources, module and command
+    pub template: Option<String>,
+    /// Ask every question on its own line instead of using interactive widgets
+    pub no_fancy_prompts: bool,
+    /// A file with the answers to the questions, to set up a package without asking the user
+    pub answers: Option<PathBuf>,
+    /// Answers given with flags, which take precedence over the answers file
+    pub flags: FlagAnswers,
+}
+
+#[derive(Debug, Fail)]
+pub enum InitError {
+    #[fail(
+        display = "Unknown language \"{}\", the supported languages are: {}",
+        _0, _1
+    )]
+    UnknownLanguage(String, String),
+    #[fail(
+        display = "Unknown template \"{}\", the available templates are:{}",
+        _0, _1
+    )]
+    UnknownTemplate(String, String),
+}
 
 pub fn ask(prompt: &str, default: Option<String>) -> Result<Option<String>, std::io::Error> {
     let value = Input::<String>::new()
@@ -52,23 +107,249 @@ where
     }
 }
 
+/// Interactive widgets need a terminal that supports moving the cursor, which dumb terminals
+/// and screen readers don't
+fn use_plain_prompts(no_fancy_prompts: bool) -> bool {
+    if no_fancy_prompts {
+        return true;
+    }
+    match std::env::var("TERM") {
+        Ok(term) => term.is_empty() || term == "dumb",
+        Err(_) => true,
+    }
+}
+
+/// Read a line from stdin, without the line ending
+fn read_line() -> Result<String, std::io::Error> {
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    Ok(line.trim().to_owned())
+}
+
+/// Ask the user to pick one of the items, returning its index
+fn select(
+    prompt: &str,
+    items: &[&str],
+    default: usize,
+    plain: bool,
+) -> Result<usize, std::io::Error> {
+    if !plain {
+        let mut select = Select::new();
+        select.with_prompt(prompt).default(default);
+        for item in items {
+            select.item(item);
+        }
+        return select.interact();
+    }
+    println!("{}", prompt);
+    for (index, item) in items.iter().enumerate() {
+        println!("   {}) {}", index + 1, item);
+    }
+    loop {
+        print!(
+            "{} ",
+            format_message(
+                "init.select_number",
+                &[("count", &items.len()), ("default", &(default + 1))]
+            )
+        );
+        std::io::stdout().flush()?;
+        let answer = read_line()?;
+        if answer.is_empty() {
+            return Ok(default);
+        }
+        match answer.parse::<usize>() {
+            Ok(number) if number >= 1 && number <= items.len() => return Ok(number - 1),
+            _ => println!("{}", message("init.invalid_selection")),
+        }
+    }
+}
+
+/// Ask the user to pick any number of the items, returning their indices
+fn select_many(prompt: &str, items: &[String], plain: bool) -> Result<Vec<usize>, std::io::Error> {
+    if !plain {
+        let items_checked: Vec<(&str, bool)> =
+            items.iter().map(|item| (item.as_str(), false)).collect();
+        return Checkboxes::new()
+            .with_prompt(prompt)
+            .items_checked(&items_checked)
+            .interact();
+    }
+    println!("{}", prompt);
+    for (index, item) in items.iter().enumerate() {
+        println!("   {}) {}", index + 1, item);
+    }
+    loop {
+        print!("{} ", message("init.select_numbers"));
+        std::io::stdout().flush()?;
+        let answer = read_line()?;
+        let numbers: Result<Vec<usize>, _> = answer
+            .split(',')
+            .map(str::trim)
+            .filter(|number| !number.is_empty())
+            .map(str::parse::<usize>)
+            .collect();
+        match numbers {
+            Ok(numbers) if numbers.iter().all(|n| *n >= 1 && *n <= items.len()) => {
+                return Ok(numbers.into_iter().map(|n| n - 1).collect())
+            }
+            _ => println!("{}", message("init.invalid_selection")),
+        }
+    }
+}
+
+/// Ask the user a yes or no question
+fn confirm(text: &str, default: bool, plain: bool) -> Result<bool, std::io::Error> {
+    if !plain {
+        return Confirmation::new()
+            .with_text(text)
+            .default(default)
+            .interact();
+    }
+    loop {
+        print!(
+            "{} [{}] ",
+            text,
+            message(if default {
+                "init.yes_no"
+            } else {
+                "init.no_yes"
+            })
+        );
+        std::io::stdout().flush()?;
+        let answer = read_line()?.to_lowercase();
+        if answer.is_empty() {
+            return Ok(default);
+        }
+        if message("init.yes_answers")
+            .split(',')
+            .any(|yes| yes == answer)
+        {
+            return Ok(true);
+        }
+        if message("init.no_answers").split(',').any(|no| no == answer) {
+            return Ok(false);
+        }
+    }
+}
+
 pub fn validate_wasm_source(source: &str) -> Result<PathBuf, String> {
     trace!("Validating wasm source: {:?}", source);
     if source == "none" || source.ends_with(".wasm") {
         return Ok(PathBuf::from(source));
     }
-    return Err("The module source path must have a .wasm extension".to_owned());
+    return Err(message("init.invalid_wasm_source"));
+}
+
+pub fn validate_interface_definition(path: &str) -> Result<PathBuf, String> {
+    trace!("Validating interface definition path: {:?}", path);
+    if path.ends_with(".wai") {
+        return Ok(PathBuf::from(path));
+    }
+    return Err(message("init.invalid_interface_definition"));
+}
+
+pub fn validate_definition_path(path: &str) -> Result<PathBuf, String> {
+    trace!("Validating interface definition path: {:?}", path);
+    if path.ends_with(".wasm_interface") {
+        return Ok(PathBuf::from(path));
+    }
+    return Err(message("init.invalid_definition_path"));
+}
+
+/// Validate the package providing the module of a command, given as `namespace/name version`
+pub fn validate_command_package(package: &str) -> Result<Option<String>, String> {
+    trace!("Validating command package: {:?}", package);
+    if package.is_empty() {
+        return Ok(None);
+    }
+    match package.split(' ').collect::<Vec<_>>()[..] {
+        [name, version] if util::validate_name(name).is_ok() && Version::parse(version).is_ok() => {
+            Ok(Some(package.to_owned()))
+        }
+        _ => Err(message("init.invalid_command_package")),
+    }
 }
 
-pub fn validate_commands(command_names: &str) -> Result<Vec<String>, util::NameError> {
-    trace!("Validating command names: {:?}", command_names);
-    command_names
-        .split_whitespace()
-        .map(util::validate_name)
-        .collect()
+fn new_package(name: String, version: Version, description: String) -> Package {
+    Package {
+        name,
+        description,
+        version,
+        authors: None,
+        repository: None,
+        license: Some("ISC".to_owned()),
+        license_file: None,
+        homepage: None,
+        documentation: None,
+        keywords: vec![],
+        categories: vec![],
+        moved_to: None,
+        wasmer_extra_flags: None,
+        packages_dir: None,
+        readme: None,
+        disable_command_rename: false,
+        rename_commands_to_raw_command_name: false,
+        build: None,
+        ipfs: None,
+        localized: BTreeMap::new(),
+        symlinks: None,
+    }
 }
 
-pub fn init(dir: PathBuf, force_yes: bool) -> Result<(), failure::Error> {
+/// A module built by the toolchain of a preset
+fn preset_module(preset: &Preset, package_name: &str) -> Module {
+    let source = PathBuf::from(preset.module_source_for(package_name));
+    let name = source
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| package_name.to_owned());
+    let interfaces = if preset.abi == Abi::Wasi {
+        Some(
+            [("wasi".to_owned(), WASI_LAST_VERSION.to_owned())]
+                .iter()
+                .cloned()
+                .collect(),
+        )
+    } else {
+        None
+    };
+    Module {
+        name,
+        source,
+        abi: preset.abi,
+        interfaces,
+        exports: None,
+    }
+}
+
+pub fn init(dir: PathBuf, options: InitOptions) -> Result<(), failure::Error> {
+    let force_yes = options.force_yes;
+    let plain_prompts = use_plain_prompts(options.no_fancy_prompts);
+    let preset = match options.lang.as_ref() {
+        Some(lang) => Some(presets::get_preset(lang).ok_or_else(|| {
+            InitError::UnknownLanguage(lang.clone(), presets::preset_names().join(", "))
+        })?),
+        None => None,
+    };
+    let template =
+        match options.template.as_ref() {
+            Some(name) => Some(templates::get_template(name).ok_or_else(|| {
+                InitError::UnknownTemplate(name.clone(), templates::template_list())
+            })?),
+            None => None,
+        };
+    let mut answers = match options.answers.as_ref() {
+        Some(path) => Some(InitAnswers::from_file(path)?),
+        None => None,
+    };
+    // answering with flags doesn't need a terminal, the other questions get their defaults
+    if !options.flags.is_empty() {
+        answers
+            .get_or_insert_with(InitAnswers::default)
+            .merge_flags(options.flags)?;
+    }
+    let answered = answers.is_some();
     let manifest_location = {
         let mut dir = dir.clone();
         dir.push(MANIFEST_FILE_NAME);
@@ -77,74 +358,134 @@ pub fn init(dir: PathBuf, force_yes: bool) -> Result<(), failure::Error> {
     let mut manifest = if manifest_location.exists() {
         Manifest::find_in_directory(dir)?
     } else {
+        let package_name = dir
+            .as_path()
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        let (version, module, interface) = if options.interface {
+            // interfaces start out unstable, so breaking changes only need a minor bump
+            ("0.1.0", None, Some(vec![default_definition(&package_name)]))
+        } else {
+            (
+                "1.0.0",
+                Some(vec![Module {
+                    name: "entry".to_owned(),
+                    source: "entry.wasm".into(),
+                    abi: Abi::default(),
+                    interfaces: None,
+                    exports: None,
+                }]),
+                None,
+            )
+        };
         Manifest {
             base_directory_path: dir.clone(),
             fs: None,
-            package: Package {
-                name: dir
-                    .clone()
-                    .as_path()
-                    .file_name()
-                    .unwrap()
-                    .to_string_lossy()
-                    .to_string(),
-                description: "".to_owned(),
-                version: Version::parse("1.0.0").unwrap(),
-                repository: None,
-                license: Some("ISC".to_owned()),
-                license_file: None,
-                homepage: None,
-                wasmer_extra_flags: None,
-                readme: None,
-                disable_command_rename: false,
-                rename_commands_to_raw_command_name: false,
-            },
+            package: new_package(
+                package_name,
+                Version::parse(version).unwrap(),
+                "".to_owned(),
+            ),
             dependencies: None,
-            module: Some(vec![Module {
-                name: "entry".to_owned(),
-                source: "entry.wasm".into(),
-                abi: Abi::default(),
-                interfaces: None,
-            }]),
+            target: None,
+            pins: None,
+            alias: None,
+            warnings: None,
+            module,
             command: None,
+            interface,
+            inherited_fields: vec![],
         }
     };
+    if let Some(preset) = preset {
+        let package_name = manifest.package.name.clone();
+        if !manifest_location.exists() {
+            manifest.module = Some(vec![preset_module(preset, &package_name)]);
+        }
+        if manifest.package.build.is_none() {
+            manifest.package.build = Some(Build {
+                command: preset.build_command_for(&package_name),
+            });
+        }
+    }
+    if let Some(template) = template {
+        if !manifest_location.exists() {
+            manifest.module = Some(vec![template.module()]);
+            manifest.command = Some(vec![template.command(&manifest.package.name)]);
+        }
+        if manifest.package.build.is_none() {
+            manifest.package.build = Some(Build {
+                command: template.build_command_for(&manifest.package.name),
+            });
+        }
+    }
 
-    if !force_yes {
-        println!(
-            "This utility will walk you through creating a wapm.toml file.
-It only covers the most common items, and tries to guess sensible defaults.
-
-Use `wapm add <pkg>` afterwards to add a package and
-save it as a dependency in the wapm.toml file.
-
-Press ^C at any time to quit."
-        );
+    if let Some(answers) = answers {
+        apply_answers(&mut manifest, answers, options.lib)?;
+        if options.interface {
+            manifest.module = None;
+            manifest.command = None;
+            if manifest.interface.is_none() {
+                manifest.interface = Some(vec![default_definition(&manifest.package.name)]);
+            }
+        }
+    } else if !force_yes {
+        println!("{}", message("init.intro"));
         manifest.package.name = ask_until_valid(
-            "Package name",
+            &message("init.package_name"),
             Some(manifest.package.name),
             util::validate_name,
         )?;
         manifest.package.version = ask_until_valid(
-            "Version",
+            &message("init.version"),
             Some(manifest.package.version.to_string()),
             Version::parse,
         )?;
-        manifest.package.description =
-            ask("Description", Some(manifest.package.description))?.unwrap_or_default();
-        manifest.package.repository = ask("Repository", manifest.package.repository)?;
+        manifest.package.description = ask(
+            &message("init.description"),
+            Some(manifest.package.description),
+        )?
+        .unwrap_or_default();
+        manifest.package.repository =
+            ask(&message("init.repository"), manifest.package.repository)?;
         manifest.package.license = Some(ask_until_valid(
-            "License",
+            &message("init.license"),
             manifest.package.license,
             util::validate_license,
         )?);
+        if preset.is_some() || template.is_some() || manifest.package.build.is_some() {
+            manifest.package.build = ask(
+                &message("init.build_command"),
+                manifest.package.build.map(|build| build.command),
+            )?
+            .map(|command| Build { command });
+        }
+        if options.interface {
+            let existing_definitions = manifest.interface.take().unwrap_or_default();
+            let definitions = ask_interface_definitions(existing_definitions)?;
+            manifest.interface = if definitions.is_empty() {
+                None
+            } else {
+                Some(definitions)
+            };
+        }
         // Let's reset the modules
         let mut all_modules: Vec<Module> = vec![];
         let mut all_commands: Vec<Command> = vec![];
         let manifest_modules = manifest.module.unwrap_or_default();
+        let manifest_commands = manifest.command.take().unwrap_or_default();
         loop {
+            // interface packages have no modules
+            if options.interface {
+                break;
+            }
             let current_index = all_modules.len();
-            println!("Enter the data for the Module ({})", current_index + 1);
+            println!(
+                "{}",
+                format_message("init.module_header", &[("index", &(current_index + 1))])
+            );
             let mut module = {
                 // We take the data from the current manifest modules
                 if manifest_modules.len() > current_index {
@@ -155,17 +496,22 @@ Press ^C at any time to quit."
                         source: PathBuf::from("none"),
                         abi: Abi::default(),
                         interfaces: None,
+                        exports: None,
                     }
                 }
             };
             module.source = ask_until_valid(
-                " - Source (path)",
+                &format!(" - {}", message("init.module_source")),
                 Some(module.source.to_string_lossy().to_string()),
                 validate_wasm_source,
             )?;
             if module.source.to_string_lossy() == "none" {
                 break;
             }
+            // an existing module is inspected right away, to suggest the ABI its imports need
+            let detection = fs::read(manifest.base_directory_path.join(&module.source))
+                .ok()
+                .and_then(|wasm| detect_abi(&wasm).ok());
             // Let's try to guess the name based on the file path
             let default_module_name = Path::new(&module.source)
                 .file_stem()
@@ -173,56 +519,97 @@ Press ^C at any time to quit."
                 .to_string_lossy()
                 .to_string();
             module.name = ask_until_valid(
-                " - Name",
+                &format!(" - {}", message("init.module_name")),
                 Some(default_module_name.clone()),
                 util::validate_name,
             )?;
-            let default_module_abi = match module.abi {
+            if let Some(detection) = &detection {
+                println!(
+                    "   {}",
+                    format_message(
+                        "init.module_detected_abi",
+                        &[
+                            ("abi", &detection.abi),
+                            ("confidence", &detection.confidence)
+                        ]
+                    )
+                );
+            }
+            // the ABI chosen in the manifest wins over the detected one
+            let suggested_abi = match &detection {
+                Some(detection) if module.abi.is_none() => detection.abi,
+                _ => module.abi,
+            };
+            let default_module_abi = match suggested_abi {
                 Abi::None => 0,
                 Abi::Wasi => 1,
                 Abi::Emscripten => 2,
             };
-            let (abi, interfaces): (Abi, Option<HashMap<String, String>>) = match Select::new()
-                .with_prompt(" - ABI")
-                .item("None")
-                .item("WASI")
-                .item("Emscripten")
-                .default(default_module_abi)
-                .interact()?
-            {
-                1 => (
-                    Abi::Wasi,
-                    Some(
-                        [("wasi".to_owned(), WASI_LAST_VERSION.to_owned())]
-                            .iter()
-                            .cloned()
-                            .collect(),
-                    ),
-                ),
-                2 => (Abi::Emscripten, None),
-                0 | _ => (Abi::None, None),
+            module.abi = match select(
+                &format!(" - {}", message("init.module_abi")),
+                &["None", "WASI", "Emscripten"],
+                default_module_abi,
+                plain_prompts,
+            )? {
+                1 => Abi::Wasi,
+                2 => Abi::Emscripten,
+                0 | _ => Abi::None,
             };
-            module.abi = abi;
-            module.interfaces = interfaces;
-            // We ask for commands if it has an Abi
-            if !module.abi.is_none() {
-                let module_command_strings = ask_until_valid(
-                    " - Commmands (space separated)",
-                    Some(default_module_name.clone()),
-                    validate_commands,
-                )?;
-                if !module_command_strings.is_empty() {
-                    let module_commands =
-                        module_command_strings
-                            .into_iter()
-                            .map(|command_string| Command {
-                                name: command_string,
-                                module: module.name.clone(),
-                                main_args: None,
-                                package: None,
-                            });
-                    all_commands.extend(module_commands);
+            match &detection {
+                Some(detection)
+                    if detection.abi != module.abi && detection.confidence == Confidence::High =>
+                {
+                    println!(
+                        "   {}",
+                        format_message(
+                            "init.module_abi_mismatch",
+                            &[("abi", &module.abi), ("detected", &detection.abi)]
+                        )
+                    )
                 }
+                _ => {}
+            }
+            let abi_interfaces = abi_interfaces(
+                module.abi,
+                &manifest.base_directory_path.join(&module.source),
+            )
+            .unwrap_or_default();
+            // the interfaces of the ABI are set above, only the others are asked for
+            let mut existing_interfaces: Vec<(String, String)> = module
+                .interfaces
+                .take()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|(name, _)| {
+                    name != "wasi"
+                        && name != EMSCRIPTEN_INTERFACE_NAME
+                        && !abi_interfaces.contains_key(name)
+                })
+                .collect();
+            existing_interfaces.sort();
+            let mut interfaces = abi_interfaces;
+            interfaces.extend(ask_interfaces(existing_interfaces, &interfaces)?);
+            module.interfaces = if interfaces.is_empty() {
+                None
+            } else {
+                Some(interfaces)
+            };
+            if options.lib {
+                // Libraries export interfaces instead of commands
+                let exports = ask_exported_interfaces(module.exports.take().unwrap_or_default())?;
+                module.exports = if exports.is_empty() {
+                    None
+                } else {
+                    Some(exports)
+                };
+            } else if !module.abi.is_none() {
+                // We ask for commands if it has an Abi
+                let existing_commands = manifest_commands
+                    .iter()
+                    .filter(|command| command.module == module.name)
+                    .cloned()
+                    .collect();
+                all_commands.extend(ask_commands(&module.name, existing_commands)?);
             }
             all_modules.push(module);
         }
@@ -238,36 +625,626 @@ Press ^C at any time to quit."
         };
     }
 
-    let print_text = if force_yes {
-        "Wrote to"
+    let print_text_key = if force_yes || answered {
+        "init.wrote_to"
     } else {
-        "About to write to"
+        "init.about_to_write_to"
     };
 
     println!(
-        "\n{} {}:\n\n{}\n",
-        print_text,
-        manifest.manifest_path().to_string_lossy(),
+        "\n{}\n\n{}\n",
+        format_message(
+            print_text_key,
+            &[("path", &manifest.manifest_path().to_string_lossy())]
+        ),
         manifest.to_string()?
     );
 
-    if force_yes
-        || Confirmation::new()
-            .with_text("Is this OK? (yes)")
-            .default(true)
-            .interact()?
-    {
+    if force_yes || answered || confirm(&message("init.confirm"), true, plain_prompts)? {
         manifest.save()?;
+        if options.lib {
+            init_example_consumer(&manifest)?;
+        }
+        if options.interface {
+            init_interface_definitions(&manifest)?;
+        }
+        if let Some(template) = template {
+            init_template_files(&manifest, template)?;
+            if !template.toolchain_is_installed() {
+                warn!(
+                    "{}",
+                    format_message(
+                        "init.missing_toolchain",
+                        &[
+                            ("toolchain", &template.toolchain),
+                            ("lang", &template.name),
+                            ("instructions", &template.install_instructions),
+                        ]
+                    )
+                );
+            }
+        }
         #[allow(unused_must_use)]
         {
-            init_gitignore(manifest.base_directory_path);
+            init_gitignore(manifest.base_directory_path.clone());
+        }
+        if !force_yes && !answered && !options.interface {
+            suggest_starter_dependencies(&mut manifest, preset, plain_prompts)?;
         }
     } else {
-        println!("Aborted.")
+        println!("{}", message("init.aborted"))
+    }
+    if let Some(preset) = preset {
+        if !preset.toolchain_is_installed() {
+            warn!(
+                "{}",
+                format_message(
+                    "init.missing_toolchain",
+                    &[
+                        ("toolchain", &preset.toolchain),
+                        ("lang", &preset.name),
+                        ("instructions", &preset.install_instructions),
+                    ]
+                )
+            );
+        }
     }
     Ok(())
 }
 
+/// Fill in the manifest from the answers file, validating the answers like the questions would
+fn apply_answers(
+    manifest: &mut Manifest,
+    answers: InitAnswers,
+    lib: bool,
+) -> Result<(), failure::Error> {
+    let package = answers.package;
+    if let Some(name) = package.name {
+        manifest.package.name = validate_answer("package.name", &name, util::validate_name)?;
+    }
+    if let Some(version) = package.version {
+        manifest.package.version = validate_answer("package.version", &version, Version::parse)?;
+    }
+    if let Some(description) = package.description {
+        manifest.package.description = description;
+    }
+    if let Some(repository) = package.repository {
+        manifest.package.repository = Some(repository);
+    }
+    if let Some(license) = package.license {
+        manifest.package.license = Some(validate_answer(
+            "package.license",
+            &license,
+            util::validate_license,
+        )?);
+    }
+    if let Some(command) = package.build_command {
+        manifest.package.build = Some(Build { command });
+    }
+    let module_answers = match answers.module {
+        Some(module_answers) => module_answers,
+        None => return Ok(()),
+    };
+    let mut all_modules: Vec<Module> = vec![];
+    let mut all_commands: Vec<Command> = vec![];
+    for module_answer in module_answers {
+        let source = validate_answer(
+            "module.source",
+            &module_answer.source.to_string_lossy(),
+            validate_wasm_source,
+        )?;
+        let default_module_name = source
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let name = validate_answer(
+            "module.name",
+            &module_answer
+                .name
+                .unwrap_or_else(|| default_module_name.clone()),
+            util::validate_name,
+        )?;
+        // Libraries export interfaces instead of commands
+        let command_names = match module_answer.commands {
+            _ if lib => vec![],
+            Some(command_names) => command_names,
+            None if module_answer.abi.is_none() => vec![],
+            None => vec![CommandAnswer::Name(default_module_name)],
+        };
+        for command_answer in command_names {
+            let (command_name, main_args, package) = match command_answer {
+                CommandAnswer::Name(command_name) => (command_name, None, None),
+                CommandAnswer::Command {
+                    name,
+                    main_args,
+                    package,
+                } => (name, main_args, package),
+            };
+            all_commands.push(Command {
+                name: validate_answer("module.commands", &command_name, util::validate_name)?,
+                module: name.clone(),
+                main_args,
+                package: match package {
+                    Some(package) => {
+                        validate_answer("module.commands", &package, validate_command_package)?
+                    }
+                    None => None,
+                },
+                description: None,
+                usage: None,
+                examples: vec![],
+            });
+        }
+        let mut interfaces = abi_interfaces(
+            module_answer.abi,
+            &manifest.base_directory_path.join(&source),
+        )
+        .unwrap_or_default();
+        for (interface_name, version) in module_answer.interfaces.unwrap_or_default() {
+            validate_answer("module.interfaces", &version, |version| {
+                validate_interface(&interface_name, version)
+            })?;
+            interfaces.insert(interface_name, version);
+        }
+        all_modules.push(Module {
+            interfaces: if interfaces.is_empty() {
+                None
+            } else {
+                Some(interfaces)
+            },
+            name,
+            source,
+            abi: module_answer.abi,
+            exports: module_answer.exports.filter(|exports| !exports.is_empty()),
+        });
+    }
+    manifest.module = if all_modules.is_empty() {
+        None
+    } else {
+        Some(all_modules)
+    };
+    manifest.command = if all_commands.is_empty() {
+        None
+    } else {
+        Some(all_commands)
+    };
+    Ok(())
+}
+
+/// Offer to add popular packages from the registry as dependencies of the new package, and
+/// install the chosen ones. The packages are those the registry ranks first when searching for
+/// the ABIs of the modules and the language of the preset.
+fn suggest_starter_dependencies(
+    manifest: &mut Manifest,
+    preset: Option<&Preset>,
+    plain: bool,
+) -> Result<(), failure::Error> {
+    let mut terms: Vec<&str> = vec![];
+    let abi_terms = manifest
+        .module
+        .iter()
+        .flatten()
+        .filter_map(|module| match module.abi {
+            Abi::Wasi => Some("wasi"),
+            Abi::Emscripten => Some("emscripten"),
+            Abi::None => None,
+        });
+    for term in abi_terms.chain(preset.map(|preset| preset.name)) {
+        if !terms.contains(&term) {
+            terms.push(term);
+        }
+    }
+    if terms.is_empty() || !confirm(&message("init.starter_confirm"), false, plain)? {
+        return Ok(());
+    }
+
+    let mut suggestions: Vec<SearchResult> = vec![];
+    for term in terms {
+        let results = match search_packages(term) {
+            Ok(results) => results,
+            Err(e) => {
+                warn!(
+                    "{}",
+                    format_message("init.starter_search_failed", &[("error", &e)])
+                );
+                return Ok(());
+            }
+        };
+        for result in results {
+            let known = result.name == manifest.package.name
+                || suggestions.iter().any(|s| s.name == result.name)
+                || manifest
+                    .dependencies
+                    .as_ref()
+                    .is_some_and(|dependencies| dependencies.contains_key(&result.name));
+            if !known {
+                suggestions.push(result);
+            }
+        }
+    }
+    suggestions.truncate(STARTER_DEPENDENCY_COUNT);
+    if suggestions.is_empty() {
+        println!("{}", message("init.starter_none"));
+        return Ok(());
+    }
+
+    let items: Vec<String> = suggestions
+        .iter()
+        .map(|s| format!("{}@{}  {}", s.name, s.version, s.description))
+        .collect();
+    let chosen = select_many(&message("init.starter_select"), &items, plain)?;
+    if chosen.is_empty() {
+        return Ok(());
+    }
+    for index in &chosen {
+        let suggestion = &suggestions[*index];
+        manifest.add_dependency(suggestion.name.clone(), suggestion.version.clone());
+    }
+    manifest.save()?;
+    dataflow::update(vec![], vec![], &manifest.base_directory_path)?;
+    println!(
+        "{}",
+        format_message("init.starter_added", &[("count", &chosen.len())])
+    );
+    Ok(())
+}
+
+/// The interfaces implied by the ABI of a module
+fn abi_interfaces(abi: Abi, source: &Path) -> Option<HashMap<String, String>> {
+    match abi {
+        Abi::Wasi => Some(
+            [("wasi".to_owned(), WASI_LAST_VERSION.to_owned())]
+                .iter()
+                .cloned()
+                .collect(),
+        ),
+        Abi::Emscripten => emscripten_interfaces(source),
+        Abi::None => None,
+    }
+}
+
+/// Record the version of the Emscripten ABI used by a module, if the module has been built
+fn emscripten_interfaces(source: &Path) -> Option<HashMap<String, String>> {
+    let wasm = fs::read(source).ok()?;
+    let version = emscripten::inspect_module(&wasm).ok()??.version?;
+    Some(
+        [(EMSCRIPTEN_INTERFACE_NAME.to_owned(), version)]
+            .iter()
+            .cloned()
+            .collect(),
+    )
+}
+
+/// Check that an interface is in the registry's interface catalog
+pub fn validate_interface(name: &str, version: &str) -> Result<(), String> {
+    trace!("Validating interface: {:?} {:?}", name, version);
+    if Version::parse(version).is_err() {
+        return Err(format_message(
+            "init.invalid_interface_version",
+            &[("version", &version)],
+        ));
+    }
+    database::open_db()
+        .and_then(|mut conn| interfaces::ensure_interface(&mut conn, name, version))
+        .map_err(|e| {
+            format_message(
+                "init.unknown_interface",
+                &[("name", &name), ("version", &version), ("error", &e)],
+            )
+        })
+}
+
+/// Ask for the interfaces used by a module, besides the ones of its ABI, until an empty name is
+/// given
+fn ask_interfaces(
+    existing_interfaces: Vec<(String, String)>,
+    abi_interfaces: &HashMap<String, String>,
+) -> Result<HashMap<String, String>, failure::Error> {
+    let mut interfaces: HashMap<String, String> = HashMap::new();
+    loop {
+        let existing_interface = existing_interfaces.get(interfaces.len());
+        println!(
+            " - {}",
+            format_message(
+                "init.interface_header",
+                &[("index", &(interfaces.len() + 1))]
+            )
+        );
+        let name = match ask_until_valid(
+            &format!("   - {}", message("init.interface_name")),
+            existing_interface.map(|(name, _)| name.clone()),
+            |name| {
+                if name.is_empty() {
+                    Ok(None)
+                } else if interfaces.contains_key(name) || abi_interfaces.contains_key(name) {
+                    Err(format_message(
+                        "init.duplicate_interface",
+                        &[("name", &name)],
+                    ))
+                } else {
+                    util::validate_name(name)
+                        .map(Some)
+                        .map_err(|e| e.to_string())
+                }
+            },
+        )? {
+            Some(name) => name,
+            None => break,
+        };
+        let version = ask_until_valid(
+            &format!("   - {}", message("init.interface_version")),
+            existing_interface.map(|(_, version)| version.clone()),
+            |version| validate_interface(&name, version).map(|()| version.to_owned()),
+        )?;
+        interfaces.insert(name, version);
+    }
+    Ok(interfaces)
+}
+
+/// Ask for the commands of a module until an empty name is given. The first command is named
+/// after the module unless the module already has commands.
+fn ask_commands(
+    module_name: &str,
+    existing_commands: Vec<Command>,
+) -> Result<Vec<Command>, failure::Error> {
+    let mut commands: Vec<Command> = vec![];
+    loop {
+        let existing_command = existing_commands.get(commands.len());
+        println!(
+            " - {}",
+            format_message("init.command_header", &[("index", &(commands.len() + 1))])
+        );
+        let default_name = match existing_command {
+            Some(command) => Some(command.name.clone()),
+            None if commands.is_empty() && existing_commands.is_empty() => {
+                Some(module_name.to_owned())
+            }
+            None => None,
+        };
+        let name = match ask_until_valid(
+            &format!("   - {}", message("init.command_name")),
+            default_name,
+            |name| {
+                if name.is_empty() {
+                    Ok(None)
+                } else if commands.iter().any(|command| command.name == name) {
+                    Err(format_message("init.duplicate_command", &[("name", &name)]))
+                } else {
+                    util::validate_name(name)
+                        .map(Some)
+                        .map_err(|e| e.to_string())
+                }
+            },
+        )? {
+            Some(name) => name,
+            None => break,
+        };
+        let main_args = ask(
+            &format!("   - {}", message("init.command_main_args")),
+            existing_command.and_then(|command| command.main_args.clone()),
+        )?;
+        let package = ask_until_valid(
+            &format!("   - {}", message("init.command_package")),
+            existing_command.and_then(|command| command.package.clone()),
+            validate_command_package,
+        )?;
+        commands.push(Command {
+            name,
+            module: module_name.to_owned(),
+            main_args,
+            package,
+            description: existing_command.and_then(|command| command.description.clone()),
+            usage: existing_command.and_then(|command| command.usage.clone()),
+            examples: existing_command
+                .map(|command| command.examples.clone())
+                .unwrap_or_default(),
+        });
+    }
+    Ok(commands)
+}
+
+/// Ask for the interfaces exported by a module until an empty name is given
+fn ask_exported_interfaces(
+    existing_exports: Vec<ExportedInterface>,
+) -> Result<Vec<ExportedInterface>, failure::Error> {
+    let mut exports = vec![];
+    loop {
+        let existing_export = existing_exports.get(exports.len());
+        println!(
+            " - {}",
+            format_message("init.export_header", &[("index", &(exports.len() + 1))])
+        );
+        let name = match ask_until_valid(
+            &format!("   - {}", message("init.export_name")),
+            existing_export.map(|export| export.name.clone()),
+            |name| {
+                if name.is_empty() {
+                    Ok(None)
+                } else {
+                    util::validate_name(name).map(Some)
+                }
+            },
+        )? {
+            Some(name) => name,
+            None => break,
+        };
+        let version = ask_until_valid(
+            &format!("   - {}", message("init.export_version")),
+            Some(
+                existing_export
+                    .map(|export| export.version.to_string())
+                    .unwrap_or_else(|| "0.1.0".to_owned()),
+            ),
+            Version::parse,
+        )?;
+        let path = ask_until_valid(
+            &format!("   - {}", message("init.export_path")),
+            Some(
+                existing_export
+                    .map(|export| export.path.to_string_lossy().to_string())
+                    .unwrap_or_else(|| format!("{}.wai", name)),
+            ),
+            validate_interface_definition,
+        )?;
+        exports.push(ExportedInterface {
+            name,
+            version,
+            path,
+        });
+    }
+    Ok(exports)
+}
+
+/// The interface definition of a new interface package, named after the package
+fn default_definition(package_name: &str) -> InterfaceDefinition {
+    let name = package_name
+        .rsplit('/')
+        .next()
+        .unwrap_or(package_name)
+        .to_owned();
+    InterfaceDefinition {
+        path: PathBuf::from(format!("{}.wasm_interface", name)),
+        name,
+    }
+}
+
+/// Ask for the interfaces defined by an interface package until an empty name is given
+fn ask_interface_definitions(
+    existing_definitions: Vec<InterfaceDefinition>,
+) -> Result<Vec<InterfaceDefinition>, failure::Error> {
+    let mut definitions: Vec<InterfaceDefinition> = vec![];
+    loop {
+        let existing_definition = existing_definitions.get(definitions.len());
+        println!(
+            " - {}",
+            format_message(
+                "init.definition_header",
+                &[("index", &(definitions.len() + 1))]
+            )
+        );
+        let name = match ask_until_valid(
+            &format!("   - {}", message("init.definition_name")),
+            existing_definition.map(|definition| definition.name.clone()),
+            |name| {
+                if name.is_empty() {
+                    Ok(None)
+                } else if definitions.iter().any(|definition| definition.name == name) {
+                    Err(format_message(
+                        "init.duplicate_definition",
+                        &[("name", &name)],
+                    ))
+                } else {
+                    util::validate_name(name)
+                        .map(Some)
+                        .map_err(|e| e.to_string())
+                }
+            },
+        )? {
+            Some(name) => name,
+            None => break,
+        };
+        let path = ask_until_valid(
+            &format!("   - {}", message("init.definition_path")),
+            Some(
+                existing_definition
+                    .map(|definition| definition.path.to_string_lossy().to_string())
+                    .unwrap_or_else(|| format!("{}.wasm_interface", name)),
+            ),
+            validate_definition_path,
+        )?;
+        definitions.push(InterfaceDefinition { name, path });
+    }
+    Ok(definitions)
+}
+
+/// Write an empty definition for the interfaces of an interface package that don't have one yet
+fn init_interface_definitions(manifest: &Manifest) -> Result<(), failure::Error> {
+    for definition in manifest.interface.iter().flatten() {
+        let path = manifest.base_directory_path.join(&definition.path);
+        if path.exists() {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, format!("(interface \"{}\")\n", definition.name))?;
+        println!(
+            "{}",
+            format_message(
+                "init.wrote_definition",
+                &[
+                    ("name", &definition.name),
+                    ("path", &path.to_string_lossy()),
+                ]
+            )
+        );
+    }
+    Ok(())
+}
+
+/// Write the sources and placeholder module of a template next to the manifest
+fn init_template_files(manifest: &Manifest, template: &Template) -> Result<(), failure::Error> {
+    let written = template.write_files(&manifest.base_directory_path, &manifest.package.name)?;
+    for path in written {
+        println!(
+            "{}",
+            format_message(
+                "init.wrote_template_file",
+                &[
+                    ("template", &template.name),
+                    ("path", &path.to_string_lossy()),
+                ]
+            )
+        );
+    }
+    Ok(())
+}
+
+/// Create a minimal package that depends on the library in `examples/`
+fn init_example_consumer(library_manifest: &Manifest) -> Result<(), failure::Error> {
+    let consumer_dir = library_manifest
+        .base_directory_path
+        .join(EXAMPLE_CONSUMER_DIR);
+    let consumer_manifest_path = consumer_dir.join(MANIFEST_FILE_NAME);
+    if consumer_manifest_path.exists() {
+        return Ok(());
+    }
+    let library = &library_manifest.package;
+    let mut consumer_manifest = Manifest {
+        base_directory_path: consumer_dir.clone(),
+        fs: None,
+        package: new_package(
+            format!("{}-example", library.name),
+            Version::parse("0.1.0").unwrap(),
+            // the manifest may be published, so it's in English whatever the locale
+            format!("An example of using {}", library.name),
+        ),
+        dependencies: None,
+        target: None,
+        pins: None,
+        alias: None,
+        warnings: None,
+        module: None,
+        command: None,
+        interface: None,
+        inherited_fields: vec![],
+    };
+    consumer_manifest.add_dependency(library.name.clone(), library.version.to_string());
+    fs::create_dir_all(&consumer_dir)?;
+    fs::write(&consumer_manifest_path, consumer_manifest.to_string()?)?;
+    println!(
+        "{}",
+        format_message(
+            "init.wrote_example",
+            &[
+                ("library", &library.name),
+                ("path", &consumer_manifest_path.to_string_lossy()),
+            ]
+        )
+    );
+    Ok(())
+}
+
 pub fn init_gitignore(mut dir: PathBuf) -> Result<(), failure::Error> {
     let gitignore = {
         dir.push(".gitignore");
@@ -0,0 +1,350 @@
+//! Answers to the questions of `wapm init`, read from a file with `wapm init --answers`
+//!
+//! ```toml
+//! [package]
+//! name = "my-package"
+//! version = "0.1.0"
+//!
+//! [[module]]
+//! source = "target/wasm32-wasi/release/my-package.wasm"
+//! abi = "wasi"
+//! commands = ["my-package"]
+//! ```
+//!
+//! Commands are names, or tables for commands with arguments or from another package:
+//!
+//! ```toml
+//! commands = ["my-package", { name = "verbose", main-args = "--verbose" }]
+//! ```
+//!
+//! Questions without an answer get the same default as with `--force-yes`.
+//!
+//! The flags of `wapm init`, like `--name` or `--module <name>:<path>:<abi>`, are answers too, and
+//! take precedence over the answers file.
+
+use super::validate_wasm_source;
+use crate::abi::Abi;
+use crate::data::manifest::ExportedInterface;
+use crate::util;
+use semver::Version;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct InitAnswers {
+    #[serde(default)]
+    pub package: PackageAnswers,
+    /// The modules of the package, replacing the modules of an existing manifest when present
+    pub module: Option<Vec<ModuleAnswers>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PackageAnswers {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub description: Option<String>,
+    pub repository: Option<String>,
+    pub license: Option<String>,
+    #[serde(rename = "build-command")]
+    pub build_command: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModuleAnswers {
+    pub source: PathBuf,
+    /// Defaults to the file name of the source
+    pub name: Option<String>,
+    #[serde(default = "Abi::default")]
+    pub abi: Abi,
+    /// Defaults to a command named after the module for modules with an ABI
+    pub commands: Option<Vec<CommandAnswer>>,
+    /// Interfaces used by the module besides the ones of its ABI, by name and version
+    pub interfaces: Option<HashMap<String, String>>,
+    /// The interfaces exported by the module of a library
+    pub exports: Option<Vec<ExportedInterface>>,
+}
+
+impl ModuleAnswers {
+    /// The name of the module, the file name of its source when it has none
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| {
+            self.source
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default()
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum CommandAnswer {
+    Name(String),
+    Command {
+        name: String,
+        #[serde(rename = "main-args")]
+        main_args: Option<String>,
+        /// The package providing the module, as `namespace/name version`
+        package: Option<String>,
+    },
+}
+
+#[derive(Debug, Fail)]
+pub enum AnswersError {
+    #[fail(display = "Could not read the answers file \"{}\": {}", _0, _1)]
+    CouldNotRead(String, String),
+    #[fail(display = "Could not parse the answers file \"{}\": {}", _0, _1)]
+    CouldNotParse(String, String),
+    #[fail(display = "Invalid answer for \"{}\" in the answers file: {}", _0, _1)]
+    InvalidAnswer(String, String),
+    #[fail(display = "Invalid value \"{}\" for --{}: {}", _1, _0, _2)]
+    InvalidFlag(&'static str, String, String),
+}
+
+/// The answers given with the flags of `wapm init`
+#[derive(Debug, Default)]
+pub struct FlagAnswers {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub description: Option<String>,
+    pub license: Option<String>,
+    /// Modules as `<name>:<path>` or `<name>:<path>:<abi>`
+    pub modules: Vec<String>,
+    /// Commands as `<name>:<module>`
+    pub commands: Vec<String>,
+}
+
+impl FlagAnswers {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.version.is_none()
+            && self.description.is_none()
+            && self.license.is_none()
+            && self.modules.is_empty()
+            && self.commands.is_empty()
+    }
+}
+
+fn parse_abi(abi: &str) -> Result<Abi, String> {
+    match abi.to_lowercase().as_str() {
+        "wasi" => Ok(Abi::Wasi),
+        "emscripten" => Ok(Abi::Emscripten),
+        "none" | "generic" => Ok(Abi::None),
+        _ => Err(format!(
+            "unknown ABI \"{}\", the ABIs are wasi, emscripten and none",
+            abi
+        )),
+    }
+}
+
+/// A module from `<name>:<path>` or `<name>:<path>:<abi>`, the path may contain `:` on Windows
+fn parse_module_flag(value: &str) -> Result<ModuleAnswers, String> {
+    let (name, rest) = value
+        .split_once(':')
+        .ok_or_else(|| "expected <name>:<path>:<abi>".to_string())?;
+    let (source, abi) = match rest.rsplit_once(':') {
+        Some((source, abi)) if validate_wasm_source(rest).is_err() => (source, parse_abi(abi)?),
+        _ => (rest, Abi::default()),
+    };
+    Ok(ModuleAnswers {
+        source: validate_wasm_source(source)?,
+        name: Some(util::validate_name(name).map_err(|e| e.to_string())?),
+        abi,
+        commands: None,
+        interfaces: None,
+        exports: None,
+    })
+}
+
+/// A command from `<name>:<module>`
+fn parse_command_flag(value: &str) -> Result<(String, String), String> {
+    let (name, module) = value
+        .split_once(':')
+        .ok_or_else(|| "expected <name>:<module>".to_string())?;
+    let name = util::validate_name(name).map_err(|e| e.to_string())?;
+    Ok((name, module.to_string()))
+}
+
+impl InitAnswers {
+    pub fn from_file(path: &Path) -> Result<Self, AnswersError> {
+        let source = fs::read_to_string(path).map_err(|e| {
+            AnswersError::CouldNotRead(path.to_string_lossy().to_string(), e.to_string())
+        })?;
+        toml::from_str(&source).map_err(|e| {
+            AnswersError::CouldNotParse(path.to_string_lossy().to_string(), e.to_string())
+        })
+    }
+
+    /// Override the answers with the flags, checked with the validators of the questions. Modules
+    /// given with `--module` replace the other modules, and commands given with `--command`
+    /// replace the commands of every module.
+    pub fn merge_flags(&mut self, flags: FlagAnswers) -> Result<(), AnswersError> {
+        fn check<V, E: std::fmt::Display>(
+            flag: &'static str,
+            value: &str,
+            validator: impl Fn(&str) -> Result<V, E>,
+        ) -> Result<V, AnswersError> {
+            validator(value)
+                .map_err(|e| AnswersError::InvalidFlag(flag, value.to_string(), e.to_string()))
+        }
+
+        if let Some(name) = flags.name {
+            check("name", &name, util::validate_name)?;
+            self.package.name = Some(name);
+        }
+        if let Some(version) = flags.version {
+            check("version", &version, Version::parse)?;
+            self.package.version = Some(version);
+        }
+        if flags.description.is_some() {
+            self.package.description = flags.description;
+        }
+        if let Some(license) = flags.license {
+            check("license", &license, util::validate_license)?;
+            self.package.license = Some(license);
+        }
+        if !flags.modules.is_empty() {
+            let modules = flags
+                .modules
+                .iter()
+                .map(|module| check("module", module, parse_module_flag))
+                .collect::<Result<Vec<_>, _>>()?;
+            self.module = Some(modules);
+        }
+        if !flags.commands.is_empty() {
+            let commands = flags
+                .commands
+                .iter()
+                .map(|command| check("command", command, parse_command_flag))
+                .collect::<Result<Vec<_>, _>>()?;
+            let modules = self.module.get_or_insert_with(Vec::new);
+            let module_names: Vec<String> = modules.iter().map(ModuleAnswers::name).collect();
+            for (command, (_, module)) in flags.commands.iter().zip(commands.iter()) {
+                if !module_names.contains(module) {
+                    return Err(AnswersError::InvalidFlag(
+                        "command",
+                        command.clone(),
+                        format!(
+                            "there is no module named \"{}\", add it with --module",
+                            module
+                        ),
+                    ));
+                }
+            }
+            for (answer, module_name) in modules.iter_mut().zip(module_names) {
+                answer.commands = Some(
+                    commands
+                        .iter()
+                        .filter(|(_, module)| *module == module_name)
+                        .map(|(name, _)| CommandAnswer::Name(name.clone()))
+                        .collect(),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check an answer with the validator used for the interactive question
+pub fn validate_answer<F, V, E>(key: &str, answer: &str, validator: F) -> Result<V, AnswersError>
+where
+    F: Fn(&str) -> Result<V, E>,
+    E: std::fmt::Display,
+{
+    validator(answer).map_err(|e| AnswersError::InvalidAnswer(key.to_owned(), e.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_answers() {
+        let answers: InitAnswers = toml::from_str(
+            r#"
+[package]
+name = "hello"
+build-command = "cargo build --target wasm32-wasi"
+
+[[module]]
+source = "hello.wasm"
+abi = "wasi"
+commands = ["hello", { name = "hi", main-args = "--greeting hi" }]
+interfaces = { "wasi-http" = "0.1.0" }
+
+[[module]]
+source = "helper.wasm"
+"#,
+        )
+        .unwrap();
+        assert_eq!(answers.package.name.as_deref(), Some("hello"));
+        assert!(answers.package.version.is_none());
+        let modules = answers.module.unwrap();
+        assert_eq!(modules[0].abi, Abi::Wasi);
+        let commands = modules[0].commands.as_ref().unwrap();
+        assert_eq!(commands.len(), 2);
+        match &commands[1] {
+            CommandAnswer::Command {
+                name, main_args, ..
+            } => {
+                assert_eq!(name, "hi");
+                assert_eq!(main_args.as_deref(), Some("--greeting hi"));
+            }
+            CommandAnswer::Name(_) => panic!("expected a command table"),
+        }
+        assert_eq!(
+            modules[0].interfaces.as_ref().unwrap()["wasi-http"],
+            "0.1.0"
+        );
+        assert_eq!(modules[1].abi, Abi::None);
+        assert!(modules[1].name.is_none());
+    }
+
+    #[test]
+    fn flags_override_answers() {
+        let mut answers: InitAnswers = toml::from_str(
+            r#"
+[package]
+name = "hello"
+license = "MIT"
+"#,
+        )
+        .unwrap();
+        answers
+            .merge_flags(FlagAnswers {
+                name: Some("greeter".to_string()),
+                version: Some("0.2.0".to_string()),
+                modules: vec![
+                    "greeter:target/greeter.wasm:wasi".to_string(),
+                    "helper:helper.wasm".to_string(),
+                ],
+                commands: vec!["greet:greeter".to_string(), "hi:greeter".to_string()],
+                ..FlagAnswers::default()
+            })
+            .unwrap();
+        assert_eq!(answers.package.name.as_deref(), Some("greeter"));
+        assert_eq!(answers.package.license.as_deref(), Some("MIT"));
+        let modules = answers.module.as_ref().unwrap();
+        assert_eq!(modules[0].source, PathBuf::from("target/greeter.wasm"));
+        assert_eq!(modules[0].abi, Abi::Wasi);
+        assert_eq!(modules[0].commands.as_ref().unwrap().len(), 2);
+        assert_eq!(modules[1].abi, Abi::None);
+        assert!(modules[1].commands.as_ref().unwrap().is_empty());
+
+        let invalid = |flags: FlagAnswers| InitAnswers::default().merge_flags(flags).is_err();
+        assert!(invalid(FlagAnswers {
+            version: Some("one".to_string()),
+            ..FlagAnswers::default()
+        }));
+        assert!(invalid(FlagAnswers {
+            modules: vec!["app:app.wasm:wasm64".to_string()],
+            ..FlagAnswers::default()
+        }));
+        assert!(invalid(FlagAnswers {
+            modules: vec!["app:app.wasm".to_string()],
+            commands: vec!["run:other".to_string()],
+            ..FlagAnswers::default()
+        }));
+    }
+}
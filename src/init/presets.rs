@@ -0,0 +1,131 @@
+//! Presets fill in the defaults of `wapm init --lang <name>` for the toolchain of a language.
+//!
+//! New presets only need to be added to `PRESETS`.
+
+use crate::abi::Abi;
+use std::process::{Command, Stdio};
+
+/// The defaults for packages built with a language's toolchain
+#[derive(Debug)]
+pub struct Preset {
+    /// The name used with `wapm init --lang`
+    pub name: &'static str,
+    pub abi: Abi,
+    /// Where the toolchain writes the module, `{name}` is replaced with the package name
+    pub module_source: &'static str,
+    /// The command that builds the module, `{name}` is replaced with the package name
+    pub build_command: &'static str,
+    /// The program that must be installed to build the module
+    pub toolchain: &'static str,
+    /// The arguments that make the toolchain print its version, to check that it is installed
+    pub version_args: &'static [&'static str],
+    /// What to do if the toolchain is not installed
+    pub install_instructions: &'static str,
+}
+
+pub static PRESETS: &[Preset] = &[
+    Preset {
+        name: "rust",
+        abi: Abi::Wasi,
+        module_source: "target/wasm32-wasi/release/{name}.wasm",
+        build_command: "cargo build --release --target wasm32-wasi",
+        toolchain: "cargo",
+        version_args: &["--version"],
+        install_instructions:
+            "Install Rust from https://rustup.rs and run `rustup target add wasm32-wasi`",
+    },
+    Preset {
+        name: "c",
+        abi: Abi::Wasi,
+        module_source: "{name}.wasm",
+        build_command: "clang --target=wasm32-wasi -O2 -o {name}.wasm main.c",
+        toolchain: "clang",
+        version_args: &["--version"],
+        install_instructions:
+            "Install the WASI SDK from https://github.com/WebAssembly/wasi-sdk and add its `bin` directory to your PATH",
+    },
+    Preset {
+        name: "go",
+        abi: Abi::Wasi,
+        module_source: "{name}.wasm",
+        build_command: "tinygo build -target=wasi -o {name}.wasm .",
+        toolchain: "tinygo",
+        version_args: &["--version"],
+        install_instructions: "Install TinyGo from https://tinygo.org/getting-started/",
+    },
+    Preset {
+        name: "zig",
+        abi: Abi::Wasi,
+        module_source: "{name}.wasm",
+        build_command: "zig build-exe src/main.zig -target wasm32-wasi -O ReleaseSmall --name {name}",
+        toolchain: "zig",
+        version_args: &["version"],
+        install_instructions: "Install Zig from https://ziglang.org/download/",
+    },
+    Preset {
+        name: "assemblyscript",
+        abi: Abi::None,
+        module_source: "build/optimized.wasm",
+        build_command: "npm run asbuild",
+        toolchain: "npm",
+        version_args: &["--version"],
+        install_instructions:
+            "Install Node.js from https://nodejs.org and run `npm install --save-dev assemblyscript`",
+    },
+];
+
+/// Find a preset by name
+pub fn get_preset(name: &str) -> Option<&'static Preset> {
+    PRESETS
+        .iter()
+        .find(|preset| preset.name.eq_ignore_ascii_case(name))
+}
+
+/// The names of all the presets, for error messages
+pub fn preset_names() -> Vec<&'static str> {
+    PRESETS.iter().map(|preset| preset.name).collect()
+}
+
+impl Preset {
+    pub fn module_source_for(&self, package_name: &str) -> String {
+        self.module_source.replace("{name}", package_name)
+    }
+
+    pub fn build_command_for(&self, package_name: &str) -> String {
+        self.build_command.replace("{name}", package_name)
+    }
+
+    /// Check if the toolchain can be run
+    pub fn toolchain_is_installed(&self) -> bool {
+        toolchain_is_installed(self.toolchain, self.version_args)
+    }
+}
+
+/// Check if a toolchain program can be run by asking it for its version
+pub fn toolchain_is_installed(toolchain: &str, version_args: &[&str]) -> bool {
+    Command::new(toolchain)
+        .args(version_args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_presets() {
+        assert_eq!(get_preset("rust").unwrap().abi, Abi::Wasi);
+        assert_eq!(get_preset("Rust").unwrap().name, "rust");
+        assert!(get_preset("cobol").is_none());
+        assert_eq!(
+            get_preset("rust").unwrap().module_source_for("hello"),
+            "target/wasm32-wasi/release/hello.wasm"
+        );
+        // zig has no `--version` flag
+        assert_eq!(get_preset("zig").unwrap().version_args, ["version"]);
+    }
+}
@@ -0,0 +1,230 @@
+//! A report of what an install did, written with `wapm install --report <path>`, e.g. as an
+//! artifact of CI builds or to track install performance over time. Reports with a `.json`
+//! extension are JSON, other reports are text for humans.
+
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::progress::ProgressStats;
+use semver::Version;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// The versions of the packages in the lockfile of a directory
+pub fn installed_versions(directory: &Path) -> BTreeMap<String, Version> {
+    match LockfileResult::find_in_directory(directory) {
+        LockfileResult::Lockfile(lockfile) => lockfile
+            .modules
+            .into_iter()
+            .filter_map(|(name, versions)| {
+                versions
+                    .into_iter()
+                    .next()
+                    .map(|(version, _)| (name, version))
+            })
+            .collect(),
+        _ => BTreeMap::new(),
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PackageUpdate {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InstallReport {
+    pub success: bool,
+    pub error: Option<String>,
+    /// The added packages, as `name@version`
+    pub added: Vec<String>,
+    pub updated: Vec<PackageUpdate>,
+    /// The removed packages, as `name@version`
+    pub removed: Vec<String>,
+    pub duration_seconds: f64,
+    pub resolution_seconds: f64,
+    pub bytes_downloaded: u64,
+    pub packages_downloaded: usize,
+    pub packages_reused: usize,
+    /// The bytes saved by linking modules identical to those of other packages
+    pub bytes_deduplicated: u64,
+    /// The share of the installed packages that were already on disk and not downloaded
+    pub cache_hit_rate: Option<f64>,
+    pub warnings: Vec<String>,
+}
+
+impl InstallReport {
+    pub fn new(
+        before: &BTreeMap<String, Version>,
+        after: &BTreeMap<String, Version>,
+        stats: &ProgressStats,
+        duration: Duration,
+        warnings: Vec<String>,
+        error: Option<String>,
+    ) -> Self {
+        let mut added = vec![];
+        let mut updated = vec![];
+        for (name, version) in after.iter() {
+            match before.get(name) {
+                None => added.push(format!("{}@{}", name, version)),
+                Some(previous) if previous != version => updated.push(PackageUpdate {
+                    name: name.clone(),
+                    from: previous.to_string(),
+                    to: version.to_string(),
+                }),
+                Some(_) => {}
+            }
+        }
+        let removed = before
+            .iter()
+            .filter(|(name, _)| !after.contains_key(*name))
+            .map(|(name, version)| format!("{}@{}", name, version))
+            .collect();
+        let installed = stats.packages_downloaded + stats.packages_reused;
+        let cache_hit_rate = if installed == 0 {
+            None
+        } else {
+            Some(stats.packages_reused as f64 / installed as f64)
+        };
+        Self {
+            success: error.is_none(),
+            error,
+            added,
+            updated,
+            removed,
+            duration_seconds: duration.as_secs_f64(),
+            resolution_seconds: stats.resolve_time.as_secs_f64(),
+            bytes_downloaded: stats.bytes_downloaded,
+            packages_downloaded: stats.packages_downloaded,
+            packages_reused: stats.packages_reused,
+            bytes_deduplicated: stats.bytes_deduplicated,
+            cache_hit_rate,
+            warnings,
+        }
+    }
+
+    /// Write the report, as JSON if the path has a `.json` extension
+    pub fn save(&self, path: &Path) -> Result<(), failure::Error> {
+        let is_json = path
+            .extension()
+            .map(|extension| extension == "json")
+            .unwrap_or(false);
+        let report = if is_json {
+            serde_json::to_string_pretty(self)?
+        } else {
+            self.to_string()
+        };
+        fs::write(path, report)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for InstallReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.error {
+            None => writeln!(f, "Install succeeded in {:.2}s", self.duration_seconds)?,
+            Some(error) => writeln!(
+                f,
+                "Install failed after {:.2}s: {}",
+                self.duration_seconds, error
+            )?,
+        }
+        writeln!(f)?;
+        for package in self.added.iter() {
+            writeln!(f, "+ {}", package)?;
+        }
+        for update in self.updated.iter() {
+            writeln!(f, "~ {} {} -> {}", update.name, update.from, update.to)?;
+        }
+        for package in self.removed.iter() {
+            writeln!(f, "- {}", package)?;
+        }
+        if self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty() {
+            writeln!(f, "No packages changed")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Resolution time: {:.2}s", self.resolution_seconds)?;
+        writeln!(
+            f,
+            "Downloaded: {} bytes in {} packages",
+            self.bytes_downloaded, self.packages_downloaded
+        )?;
+        match self.cache_hit_rate {
+            Some(rate) => writeln!(f, "Cache hit rate: {:.0}%", rate * 100.0)?,
+            None => writeln!(f, "Cache hit rate: -")?,
+        }
+        if self.bytes_deduplicated > 0 {
+            writeln!(
+                f,
+                "Deduplicated: {} bytes of identical modules",
+                self.bytes_deduplicated
+            )?;
+        }
+        if !self.warnings.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Warnings:")?;
+            for warning in self.warnings.iter() {
+                writeln!(f, "  {}", warning)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn report_changed_packages() {
+        let version = |v: &str| Version::parse(v).unwrap();
+        let before: BTreeMap<String, Version> = vec![
+            ("_/kept".to_string(), version("1.0.0")),
+            ("_/updated".to_string(), version("1.0.0")),
+            ("_/removed".to_string(), version("0.1.0")),
+        ]
+        .into_iter()
+        .collect();
+        let after: BTreeMap<String, Version> = vec![
+            ("_/kept".to_string(), version("1.0.0")),
+            ("_/updated".to_string(), version("1.1.0")),
+            ("_/added".to_string(), version("2.0.0")),
+        ]
+        .into_iter()
+        .collect();
+        let stats = ProgressStats {
+            resolve_time: Duration::from_millis(250),
+            bytes_downloaded: 1024,
+            packages_downloaded: 3,
+            packages_reused: 1,
+            ..ProgressStats::default()
+        };
+        let report = InstallReport::new(
+            &before,
+            &after,
+            &stats,
+            Duration::from_secs(2),
+            vec![],
+            None,
+        );
+        assert!(report.success);
+        assert_eq!(report.added, vec!["_/added@2.0.0"]);
+        assert_eq!(
+            report.updated,
+            vec![PackageUpdate {
+                name: "_/updated".to_string(),
+                from: "1.0.0".to_string(),
+                to: "1.1.0".to_string(),
+            }]
+        );
+        assert_eq!(report.removed, vec!["_/removed@0.1.0"]);
+        assert_eq!(report.cache_hit_rate, Some(0.25));
+
+        let text = report.to_string();
+        assert!(text.contains("~ _/updated 1.0.0 -> 1.1.0"));
+        assert!(text.contains("Cache hit rate: 25%"));
+    }
+}
@@ -2,7 +2,9 @@
 
 use crate::commands::*;
 use crate::data::manifest::{Manifest, ManifestError};
+use crate::mock_registry::MockRegistry;
 use failure;
+use std::path::Path;
 
 /// Runs `wapm config set registry.url https://registry.wapm.dev`
 pub fn set_registry_to_dev() -> Result<(), failure::Error> {
@@ -12,6 +14,17 @@ pub fn set_registry_to_dev() -> Result<(), failure::Error> {
     ))
 }
 
+/// Starts a mock registry serving the fixtures in a directory and runs
+/// `wapm config set registry.url` with its url. The registry stops when it is dropped.
+pub fn use_mock_registry(fixtures: &Path) -> Result<MockRegistry, failure::Error> {
+    let registry = MockRegistry::from_fixtures(fixtures)?;
+    config(ConfigOpt::set(
+        "registry.url".to_string(),
+        registry.url().to_string(),
+    ))?;
+    Ok(registry)
+}
+
 pub fn set_test_dir_to_new_temp_dir() -> tempfile::TempDir {
     let new_dir = tempfile::TempDir::new().expect("Could not create temp dir");
     let new_cur_dir = new_dir.path().join("integration_test");
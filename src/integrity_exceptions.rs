@@ -0,0 +1,344 @@
+//! Integrity exceptions let single dependencies through a signature or policy check they fail,
+//! with a recorded justification, instead of turning verification off for every package with
+//! `--force-insecure-install`.
+//!
+//! Exceptions live in `integrity-exceptions.toml` next to the manifest, so they are reviewed
+//! like the rest of the project, in the globals directory for global installs, or in the file
+//! the `WAPM_INTEGRITY_EXCEPTIONS` environment variable or the `install.integrity-exceptions`
+//! config key points at:
+//!
+//! ```toml
+//! [[exception]]
+//! package = "_/sqlite"
+//! version = "0.1.1"
+//! # `signature` or `policy`
+//! check = "signature"
+//! reason = "Published before the author started signing, the archive was compared with upstream"
+//! approver = "security@example.com"
+//! # optional, the exception is ignored from this day on
+//! expires = "2021-06-30"
+//! ```
+//!
+//! An exception only covers the exact version it names. Every package it lets through is
+//! reported with its reason and approver during the install.
+
+use crate::config::Config;
+use crate::data::manifest::Manifest;
+use chrono::{Local, NaiveDate};
+use semver::Version;
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const EXCEPTIONS_ENV_VAR: &str = "WAPM_INTEGRITY_EXCEPTIONS";
+
+/// The file of exceptions next to the manifest, when none is configured
+pub const EXCEPTIONS_FILE_NAME: &str = "integrity-exceptions.toml";
+
+/// The check an exception lets a package through
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Check {
+    /// Packages that are unsigned while their publisher has a known key, are signed with
+    /// another key than the trusted one, or whose signature doesn't match their archive
+    Signature,
+    /// Packages breaking the rules of the install policy
+    Policy,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Check::Signature => write!(f, "signature"),
+            Check::Policy => write!(f, "policy"),
+        }
+    }
+}
+
+impl std::str::FromStr for Check {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "signature" => Ok(Check::Signature),
+            "policy" => Ok(Check::Policy),
+            _ => Err(format!(
+                "\"{}\" is not a check, use `signature` or `policy`",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct IntegrityException {
+    pub package: String,
+    pub version: String,
+    pub check: Check,
+    /// Why the package can be trusted anyway
+    pub reason: String,
+    /// Who approved the exception
+    pub approver: String,
+    /// The day the exception stops applying
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<NaiveDate>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct IntegrityExceptions {
+    #[serde(rename = "exception", default)]
+    pub exceptions: Vec<IntegrityException>,
+}
+
+#[derive(Clone, Debug, Fail)]
+pub enum IntegrityExceptionError {
+    #[fail(display = "Could not read the integrity exceptions {}: {}", _0, _1)]
+    CouldNotRead(String, String),
+    #[fail(display = "Could not parse the integrity exceptions {}: {}", _0, _1)]
+    CouldNotParse(String, String),
+    #[fail(
+        display = "The integrity exception for {}@{} in {} needs a reason and an approver",
+        _0, _1, _2
+    )]
+    MissingJustification(String, String, String),
+    #[fail(
+        display = "The integrity exception for {}@{} in {} must name an exact version",
+        _0, _1, _2
+    )]
+    InexactVersion(String, String, String),
+}
+
+/// Where the exceptions of installs into `directory` are read from: the environment, the config,
+/// or else the file next to the manifest `directory` belongs to
+pub fn exceptions_path(directory: &Path) -> PathBuf {
+    env::var(EXCEPTIONS_ENV_VAR)
+        .ok()
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            Config::from_file()
+                .ok()
+                .and_then(|config| config.install.integrity_exceptions)
+        })
+        .unwrap_or_else(|| project_directory(directory).join(EXCEPTIONS_FILE_NAME))
+}
+
+/// The globals directory for global installs, else the directory of the closest manifest, so
+/// installs from a subdirectory of the project use its exceptions too
+fn project_directory(directory: &Path) -> PathBuf {
+    if Config::get_globals_directory().is_ok_and(|globals| globals == directory) {
+        return directory.to_path_buf();
+    }
+    directory
+        .ancestors()
+        .find_map(|ancestor| Manifest::find_in_directory(ancestor).ok())
+        .map(|manifest| manifest.base_directory_path)
+        .unwrap_or_else(|| directory.to_path_buf())
+}
+
+impl IntegrityExceptions {
+    /// The exceptions of installs into `directory`, none when there is no file
+    pub fn load(directory: &Path) -> Result<Self, IntegrityExceptionError> {
+        let path = exceptions_path(directory);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::from_file(&path)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, IntegrityExceptionError> {
+        let display = path.display().to_string();
+        let source = fs::read_to_string(path)
+            .map_err(|e| IntegrityExceptionError::CouldNotRead(display.clone(), e.to_string()))?;
+        Self::parse(&source, &display)
+    }
+
+    fn parse(source: &str, display: &str) -> Result<Self, IntegrityExceptionError> {
+        let exceptions: Self = toml::from_str(source).map_err(|e| {
+            IntegrityExceptionError::CouldNotParse(display.to_string(), e.to_string())
+        })?;
+        for exception in exceptions.exceptions.iter() {
+            exception.validate(display)?;
+        }
+        Ok(exceptions)
+    }
+
+    /// The exception that lets a version of a package through a check, if one applies today
+    pub fn find(&self, package: &str, version: &str, check: Check) -> Option<&IntegrityException> {
+        let today = Local::now().naive_local().date();
+        self.exceptions.iter().find(|exception| {
+            exception.package == package
+                && exception.version == version
+                && exception.check == check
+                && !exception.is_expired(today)
+        })
+    }
+
+    /// Whether a version of a package may skip a check, reporting the exception it uses
+    pub fn excuses(&self, package: &str, version: &str, check: Check, failure: &str) -> bool {
+        match self.find(package, version, check) {
+            Some(exception) => {
+                warn!(
+                    "{}@{} {}, allowed by the integrity exception approved by {}: {}",
+                    package, version, failure, exception.approver, exception.reason
+                );
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl IntegrityException {
+    pub fn is_expired(&self, today: NaiveDate) -> bool {
+        self.expires.is_some_and(|expires| today >= expires)
+    }
+
+    fn validate(&self, display: &str) -> Result<(), IntegrityExceptionError> {
+        if self.reason.trim().is_empty() || self.approver.trim().is_empty() {
+            return Err(IntegrityExceptionError::MissingJustification(
+                self.package.clone(),
+                self.version.clone(),
+                display.to_string(),
+            ));
+        }
+        if Version::parse(&self.version).is_err() {
+            return Err(IntegrityExceptionError::InexactVersion(
+                self.package.clone(),
+                self.version.clone(),
+                display.to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The exception as a TOML table, appended to the file so its comments are kept
+    fn to_toml(&self) -> String {
+        let quote = |value: &str| toml::Value::String(value.to_string()).to_string();
+        let mut table = format!(
+            "[[exception]]\npackage = {}\nversion = {}\ncheck = \"{}\"\nreason = {}\napprover = {}\n",
+            quote(&self.package),
+            quote(&self.version),
+            self.check,
+            quote(&self.reason),
+            quote(&self.approver)
+        );
+        if let Some(expires) = self.expires {
+            table.push_str(&format!("expires = \"{}\"\n", expires));
+        }
+        table
+    }
+}
+
+/// Record an exception in the file of exceptions
+pub fn add(path: &Path, exception: &IntegrityException) -> Result<(), failure::Error> {
+    let display = path.display().to_string();
+    exception.validate(&display)?;
+    if path.exists() {
+        let existing = IntegrityExceptions::from_file(path)?;
+        if existing.exceptions.iter().any(|other| {
+            other.package == exception.package
+                && other.version == exception.version
+                && other.check == exception.check
+        }) {
+            return Err(format_err!(
+                "{} already has a {} exception for {}@{}",
+                display,
+                exception.check,
+                exception.package,
+                exception.version
+            ));
+        }
+    }
+    let mut table = String::new();
+    if fs::metadata(path).is_ok_and(|metadata| metadata.len() > 0) {
+        table.push('\n');
+    }
+    table.push_str(&exception.to_toml());
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(table.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exceptions_cover_one_version_and_check() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join(EXCEPTIONS_FILE_NAME);
+        fs::write(&path, "# reviewed by the security team\n").unwrap();
+        let exception = IntegrityException {
+            package: "_/sqlite".to_string(),
+            version: "0.1.1".to_string(),
+            check: Check::Signature,
+            reason: "Signed before the \"key rotation\"".to_string(),
+            approver: "security@example.com".to_string(),
+            expires: None,
+        };
+        add(&path, &exception).unwrap();
+        add(
+            &path,
+            &IntegrityException {
+                check: Check::Policy,
+                expires: NaiveDate::from_ymd_opt(2020, 1, 1),
+                ..exception.clone()
+            },
+        )
+        .unwrap();
+        assert!(add(&path, &exception).is_err());
+        assert!(fs::read_to_string(&path)
+            .unwrap()
+            .starts_with("# reviewed by the security team\n"));
+
+        let exceptions = IntegrityExceptions::from_file(&path).unwrap();
+        assert_eq!(exceptions.exceptions.len(), 2);
+        assert_eq!(
+            exceptions.find("_/sqlite", "0.1.1", Check::Signature),
+            Some(&exception)
+        );
+        assert!(exceptions
+            .find("_/sqlite", "0.1.2", Check::Signature)
+            .is_none());
+        // expired
+        assert!(exceptions
+            .find("_/sqlite", "0.1.1", Check::Policy)
+            .is_none());
+
+        let unjustified = "[[exception]]\npackage = \"_/sqlite\"\nversion = \"0.1.1\"\ncheck = \"policy\"\nreason = \"\"\napprover = \"me\"\n";
+        assert!(matches!(
+            IntegrityExceptions::parse(unjustified, "test"),
+            Err(IntegrityExceptionError::MissingJustification(..))
+        ));
+        let range = unjustified
+            .replace("\"0.1.1\"", "\"^0.1\"")
+            .replace("reason = \"\"", "reason = \"ok\"");
+        assert!(matches!(
+            IntegrityExceptions::parse(&range, "test"),
+            Err(IntegrityExceptionError::InexactVersion(..))
+        ));
+    }
+
+    #[test]
+    fn exceptions_are_next_to_the_manifest() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let project = tmp_dir.path();
+        fs::write(
+            project.join("wapm.toml"),
+            "[package]\nname = \"_/project\"\nversion = \"0.1.0\"\ndescription = \"\"\n",
+        )
+        .unwrap();
+        let subdirectory = project.join("src/bin");
+        fs::create_dir_all(&subdirectory).unwrap();
+        assert_eq!(project_directory(&subdirectory), project);
+        assert_eq!(project_directory(project), project);
+    }
+}
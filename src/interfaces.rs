@@ -1,4 +1,5 @@
 use crate::database::*;
+use crate::dataflow::interfaces::InterfaceFromServer;
 use crate::sql;
 
 use rusqlite::{params, Connection, TransactionBehavior};
@@ -12,6 +13,26 @@ pub fn interface_exists(
     Ok(stmt.exists(params![interface_name, version])?)
 }
 
+/// Make sure an interface is in the local database, downloading it from the registry's interface
+/// catalog if it isn't. Fails if the registry doesn't know the interface.
+pub fn ensure_interface(
+    conn: &mut Connection,
+    interface_name: &str,
+    version: &str,
+) -> Result<(), failure::Error> {
+    if !interface_exists(conn, interface_name, version)? {
+        let interface_from_server =
+            InterfaceFromServer::get(interface_name.to_string(), version.to_string())?;
+        import_interface(
+            conn,
+            interface_name,
+            version,
+            &interface_from_server.content,
+        )?;
+    }
+    Ok(())
+}
+
 pub fn load_interface_from_db(
     conn: &mut Connection,
     interface_name: &str,
@@ -0,0 +1,167 @@
+//! An experimental distribution backend that fetches package archives by content from IPFS
+//! gateways, falling back to the registry.
+//!
+//! `wapm publish --ipfs-pin` adds the archive to an IPFS node and records its CID and checksum in
+//! the `[package.ipfs]` section of the manifest sent to the registry. When `ipfs.enabled` is set,
+//! installs look up that section and try the configured gateways first. Content from a gateway
+//! is only used if it matches the recorded checksum.
+
+use crate::config::Config;
+use crate::data::manifest::{IpfsDistribution, Manifest};
+use crate::registry;
+use crate::util::sha256_hex;
+use reqwest::blocking::{multipart, Client};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Fail)]
+pub enum IpfsError {
+    #[fail(
+        display = "Could not pin the package archive with the IPFS node at {}: {}",
+        _0, _1
+    )]
+    CouldNotPin(String, String),
+}
+
+#[derive(Debug, Deserialize)]
+struct AddResponse {
+    #[serde(rename = "Hash")]
+    hash: String,
+}
+
+/// Whether packages should be fetched from IPFS gateways
+pub fn is_enabled() -> bool {
+    Config::from_file()
+        .map(|config| config.ipfs.enabled)
+        .unwrap_or(false)
+}
+
+/// Fetch the archive of a package version from the configured gateways. Returns `None` when the
+/// version was not published to IPFS or no gateway returned the right content, in which case the
+/// registry should be used.
+pub fn fetch_package(
+    client: &Client,
+    name: &str,
+    version: &str,
+    user_agent: &str,
+) -> Option<Vec<u8>> {
+    let distribution = match registry_distribution(name, version) {
+        Ok(distribution) => distribution?,
+        Err(e) => {
+            debug!(
+                "Could not look up the IPFS CID of {}@{}: {}",
+                name, version, e
+            );
+            return None;
+        }
+    };
+    let gateways = Config::from_file().ok()?.ipfs.gateways;
+    gateways.iter().find_map(|gateway| {
+        let url = gateway_url(gateway, &distribution.cid);
+        match fetch_verified(client, &url, &distribution, user_agent) {
+            Ok(archive) => {
+                info!("Fetched {}@{} from {}", name, version, gateway);
+                Some(archive)
+            }
+            Err(e) => {
+                debug!("Could not fetch {}@{} from {}: {}", name, version, url, e);
+                None
+            }
+        }
+    })
+}
+
+/// The `[package.ipfs]` section of the manifest published for a package version
+fn registry_distribution(
+    name: &str,
+    version: &str,
+) -> Result<Option<IpfsDistribution>, failure::Error> {
+    Ok(registry::backend()?
+        .package_version(name, Some(version))?
+        .and_then(|package_version| package_version.manifest)
+        .and_then(|manifest| toml::from_str::<Manifest>(&manifest).ok())
+        .and_then(|manifest| manifest.package.ipfs))
+}
+
+fn gateway_url(gateway: &str, cid: &str) -> String {
+    format!("{}/ipfs/{}", gateway.trim_end_matches('/'), cid)
+}
+
+fn fetch_verified(
+    client: &Client,
+    url: &str,
+    distribution: &IpfsDistribution,
+    user_agent: &str,
+) -> Result<Vec<u8>, failure::Error> {
+    let archive = client
+        .get(url)
+        .header(reqwest::header::USER_AGENT, user_agent)
+        .send()?
+        .error_for_status()?
+        .bytes()?
+        .to_vec();
+    let checksum = sha256_hex(&archive);
+    if !checksum.eq_ignore_ascii_case(&distribution.sha256) {
+        return Err(format_err!(
+            "the checksum {} does not match the published checksum {}",
+            checksum,
+            distribution.sha256
+        ));
+    }
+    Ok(archive)
+}
+
+/// Add a package archive to the configured IPFS node, pinning it so it stays available
+pub fn pin_archive(archive_path: &Path) -> Result<IpfsDistribution, IpfsError> {
+    let config =
+        Config::from_file().map_err(|e| IpfsError::CouldNotPin(String::new(), e.to_string()))?;
+    let api_url = config.ipfs.api_url;
+    let pin = || -> Result<IpfsDistribution, failure::Error> {
+        let archive = fs::read(archive_path)?;
+        let form = multipart::Form::new().part(
+            "file",
+            multipart::Part::bytes(archive.clone()).file_name("package.tar.gz"),
+        );
+        let response: AddResponse = Client::new()
+            .post(&format!(
+                "{}/api/v0/add?pin=true",
+                api_url.trim_end_matches('/')
+            ))
+            .multipart(form)
+            .send()?
+            .error_for_status()?
+            .json()?;
+        Ok(IpfsDistribution {
+            cid: response.hash,
+            sha256: sha256_hex(&archive),
+        })
+    };
+    pin().map_err(|e| IpfsError::CouldNotPin(api_url.clone(), e.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_distribution_from_manifest() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+[package]
+name = "_/test"
+version = "1.0.0"
+description = ""
+
+[package.ipfs]
+cid = "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG"
+sha256 = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+"#,
+        )
+        .unwrap();
+        let distribution = manifest.package.ipfs.unwrap();
+        assert_eq!(
+            gateway_url("https://ipfs.io/", &distribution.cid),
+            "https://ipfs.io/ipfs/QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG"
+        );
+    }
+}
@@ -17,21 +17,67 @@ extern crate toml;
 #[cfg(feature = "integration_tests")]
 pub mod integration_tests;
 
-mod abi;
+pub mod abi;
+pub mod alias;
+mod allowlist;
+mod api;
+mod api_tokens;
+mod archive;
+mod archive_scan;
+mod audit_log;
+mod backup;
+mod bundle;
+mod categories;
 pub mod commands;
 mod config;
 mod constants;
+mod container_image;
+mod credentials;
 pub mod data;
 mod database;
 mod dataflow;
+pub mod desktop_notify;
+pub mod diagnostics;
+mod docs_site;
+pub mod error_codes;
+mod global_versions;
 mod graphql;
+pub mod history;
+mod http_trace;
+mod i18n;
+mod identity;
 mod init;
+mod install_report;
+mod integrity_exceptions;
 mod interfaces;
+mod ipfs;
 mod keys;
 pub mod logging;
+mod markdown;
+mod min_age;
+#[cfg(any(test, feature = "mock-registry"))]
+pub mod mock_registry;
+mod moved_packages;
+mod oci;
+mod outdated;
+mod package_size;
+mod package_symlinks;
+mod policy;
+mod portable_paths;
+mod progress;
 mod proxy;
+mod publish_outbox;
+mod registry;
+mod sandbox;
+mod serverless;
+mod shims;
 mod sql;
+mod templates;
 #[cfg(feature = "update-notifications")]
 pub mod update_notifier;
+mod update_summary;
 pub mod util;
 mod validate;
+mod wasm_store;
+mod webc;
+mod webhook;
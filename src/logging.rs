@@ -3,12 +3,23 @@
 use crate::config::Config;
 use crate::util;
 use fern::colors::{Color, ColoredLevelConfig};
+use lazy_static::lazy_static;
 use std::fs;
 use std::io;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 
 static STDOUT_LINE_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
+lazy_static! {
+    static ref LOGGED_WARNINGS: Mutex<Vec<String>> = Mutex::new(vec![]);
+}
+
+/// The warnings logged so far, e.g. for reports of what a command did
+pub(crate) fn logged_warnings() -> Vec<String> {
+    LOGGED_WARNINGS.lock().unwrap().clone()
+}
+
 fn get_num_stdout_lines_logged() -> usize {
     STDOUT_LINE_COUNTER.load(Ordering::Acquire)
 }
@@ -102,7 +113,19 @@ pub fn set_up_logging(count_lines: bool) -> Result<(), failure::Error> {
                         })
                         .chain(std::io::stderr()),
                 )
-        });
+        })
+        // remember the warnings
+        .chain(
+            fern::Dispatch::new()
+                .level(log::LevelFilter::Warn)
+                .filter(|metadata| metadata.target().starts_with("wapm_cli"))
+                .chain(fern::Output::call(|record| {
+                    LOGGED_WARNINGS
+                        .lock()
+                        .unwrap()
+                        .push(record.args().to_string());
+                })),
+        );
 
     // verbose logging to file
     let dispatch = if let Ok(wasmer_dir) = Config::get_folder() {
@@ -0,0 +1,273 @@
+//! Rendering markdown, like the readmes of packages, for reading in a terminal. Headings,
+//! emphasis and code are styled with ANSI escape codes when the output is a terminal; lists,
+//! quotes, code blocks and links are laid out as text either way.
+
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const ITALIC: &str = "\x1b[3m";
+const UNDERLINE: &str = "\x1b[4m";
+const CYAN: &str = "\x1b[36m";
+
+const BULLET: &str = "•";
+const RULE: &str = "────────────────────────────────────────";
+
+struct Renderer {
+    out: String,
+    styled: bool,
+    /// The styles in effect, innermost last
+    styles: Vec<&'static str>,
+    /// The next number of each open list, `None` for bulleted lists
+    lists: Vec<Option<u64>>,
+    quote_depth: usize,
+    code_block: bool,
+    /// The destinations of the open links, shown after their text
+    links: Vec<String>,
+    at_line_start: bool,
+}
+
+impl Renderer {
+    fn new(styled: bool) -> Self {
+        Renderer {
+            out: String::new(),
+            styled,
+            styles: vec![],
+            lists: vec![],
+            quote_depth: 0,
+            code_block: false,
+            links: vec![],
+            at_line_start: true,
+        }
+    }
+
+    fn push_style(&mut self, style: &'static str) {
+        self.styles.push(style);
+        if self.styled {
+            self.out.push_str(style);
+        }
+    }
+
+    fn pop_style(&mut self) {
+        self.styles.pop();
+        if self.styled {
+            self.out.push_str(RESET);
+            for style in &self.styles {
+                self.out.push_str(style);
+            }
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        if self.at_line_start {
+            for _ in 0..self.quote_depth {
+                self.out.push_str("│ ");
+            }
+            if self.code_block {
+                self.out.push_str("    ");
+            }
+            self.at_line_start = false;
+        }
+        // the text comes from packages, which could move the cursor, retitle the terminal or
+        // worse with escape sequences of their own
+        self.out.extend(
+            text.chars()
+                .filter(|c| !c.is_control() || *c == '\n' || *c == '\t'),
+        );
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.at_line_start = true;
+    }
+
+    /// End the block, leaving one empty line before the next one
+    fn end_block(&mut self) {
+        if !self.at_line_start {
+            self.newline();
+        }
+        if self.lists.is_empty() && !self.out.ends_with("\n\n") {
+            self.newline();
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(Tag::Heading(level, _, _)) => {
+                self.push_style(BOLD);
+                if level == HeadingLevel::H1 {
+                    self.push_style(UNDERLINE);
+                }
+            }
+            Event::End(Tag::Heading(level, _, _)) => {
+                if level == HeadingLevel::H1 {
+                    self.pop_style();
+                }
+                self.pop_style();
+                self.end_block();
+            }
+            Event::End(Tag::Paragraph) => self.end_block(),
+            Event::Start(Tag::Emphasis) => self.push_style(ITALIC),
+            Event::Start(Tag::Strong) => self.push_style(BOLD),
+            Event::End(Tag::Emphasis) | Event::End(Tag::Strong) => self.pop_style(),
+            Event::Start(Tag::BlockQuote) => self.quote_depth += 1,
+            Event::End(Tag::BlockQuote) => self.quote_depth -= 1,
+            Event::Start(Tag::CodeBlock(kind)) => {
+                if !self.at_line_start {
+                    self.newline();
+                }
+                if let CodeBlockKind::Fenced(language) = kind {
+                    if !language.is_empty() {
+                        self.push_style(DIM);
+                        self.write(&format!("[{}]", language));
+                        self.pop_style();
+                        self.newline();
+                    }
+                }
+                self.code_block = true;
+                self.push_style(CYAN);
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                self.pop_style();
+                self.code_block = false;
+                self.end_block();
+            }
+            Event::Start(Tag::List(first_number)) => {
+                if !self.at_line_start {
+                    self.newline();
+                }
+                self.lists.push(first_number);
+            }
+            Event::End(Tag::List(_)) => {
+                self.lists.pop();
+                self.end_block();
+            }
+            Event::Start(Tag::Item) => {
+                let indent = "  ".repeat(self.lists.len().saturating_sub(1));
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}.", *number - 1)
+                    }
+                    _ => BULLET.to_string(),
+                };
+                self.write(&format!("{}{} ", indent, marker));
+            }
+            Event::End(Tag::Item) if !self.at_line_start => self.newline(),
+            Event::Start(Tag::Link(_, destination, _)) => {
+                self.links.push(destination.to_string());
+                self.push_style(UNDERLINE);
+            }
+            Event::End(Tag::Link(..)) => {
+                self.pop_style();
+                if let Some(destination) = self.links.pop() {
+                    if !destination.starts_with('#') {
+                        self.push_style(DIM);
+                        self.write(&format!(" ({})", destination));
+                        self.pop_style();
+                    }
+                }
+            }
+            Event::Start(Tag::Image(..)) => self.write("[image: "),
+            Event::End(Tag::Image(..)) => self.write("]"),
+            Event::Text(text) if self.code_block => {
+                for (index, line) in text.trim_end_matches('\n').split('\n').enumerate() {
+                    if index > 0 {
+                        self.newline();
+                    }
+                    self.write(line);
+                }
+            }
+            Event::Text(text) => self.write(&text),
+            Event::Code(code) => {
+                self.push_style(CYAN);
+                self.write(&code);
+                self.pop_style();
+            }
+            Event::SoftBreak => self.write(" "),
+            Event::HardBreak => self.newline(),
+            Event::Rule => {
+                self.write(RULE);
+                self.end_block();
+            }
+            Event::End(Tag::TableCell) => self.write("  "),
+            Event::End(Tag::TableHead) | Event::End(Tag::TableRow) => self.newline(),
+            Event::End(Tag::Table(_)) => self.end_block(),
+            Event::TaskListMarker(done) => self.write(if done { "[x] " } else { "[ ] " }),
+            _ => {}
+        }
+    }
+}
+
+/// Render markdown as text, styled with ANSI escape codes when `styled` is set
+pub fn render(markdown: &str, styled: bool) -> String {
+    let mut renderer = Renderer::new(styled);
+    let options = Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS;
+    for event in Parser::new_ext(markdown, options) {
+        renderer.event(event);
+    }
+    renderer.out.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const README: &str = "# sqlite
+
+A **fast** database, see [the docs](https://sqlite.org).
+
+- one
+- two `code`
+
+1. first
+2. second
+
+> quoted
+
+```sh
+wapm run sqlite
+```
+";
+
+    #[test]
+    fn markdown_is_laid_out_as_text() {
+        assert_eq!(
+            render(README, false),
+            "sqlite
+
+A fast database, see the docs (https://sqlite.org).
+
+• one
+• two code
+
+1. first
+2. second
+
+│ quoted
+
+[sh]
+    wapm run sqlite"
+        );
+    }
+
+    #[test]
+    fn styles_are_restored_after_nested_ones() {
+        assert_eq!(
+            render("**bold *both* bold**", true),
+            "\x1b[1mbold \x1b[3mboth\x1b[0m\x1b[1m bold\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn control_characters_are_stripped() {
+        assert_eq!(
+            render(
+                "a \x1b]0;title\x07b \x1b[2Jc\u{9b}d\te `\x1b]8;;x\x1b\\`",
+                false
+            ),
+            "a ]0;titleb [2Jcd\te ]8;;x\\"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+//! The minimum age of the versions that resolving picks for version ranges, so a freshly
+//! published malicious version isn't installed before anyone had a chance to notice it.
+//!
+//! It is set with `wapm install --min-age 3d` or the `install.min-age` config key. Ages are a
+//! number followed by `m` (minutes), `h` (hours), `d` (days) or `w` (weeks).
+
+use crate::config::Config;
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+
+lazy_static! {
+    static ref MIN_AGE_OVERRIDE: Mutex<Option<Duration>> = Mutex::new(None);
+}
+
+/// Parse an age like `3d` or `12h`
+pub fn parse_min_age(age: &str) -> Result<Duration, String> {
+    let age = age.trim();
+    let invalid = || {
+        format!(
+            "invalid age \"{}\", expected a number followed by s, m, h, d or w, e.g. 3d",
+            age
+        )
+    };
+    if age.len() < 2 {
+        return Err(invalid());
+    }
+    let (amount, unit) = age.split_at(age.len() - 1);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    match unit {
+        "s" => Ok(Duration::seconds(amount)),
+        "m" => Ok(Duration::minutes(amount)),
+        "h" => Ok(Duration::hours(amount)),
+        "d" => Ok(Duration::days(amount)),
+        "w" => Ok(Duration::weeks(amount)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Describe an age for humans e.g. `3 days`
+pub fn format_min_age(age: Duration) -> String {
+    let (amount, unit) = if age.num_days() > 0 && age == Duration::days(age.num_days()) {
+        (age.num_days(), "day")
+    } else if age.num_hours() > 0 && age == Duration::hours(age.num_hours()) {
+        (age.num_hours(), "hour")
+    } else {
+        (age.num_minutes(), "minute")
+    };
+    format!("{} {}{}", amount, unit, if amount == 1 { "" } else { "s" })
+}
+
+/// Use this minimum age instead of the one in the config
+pub fn set_min_age(min_age: Duration) {
+    *MIN_AGE_OVERRIDE.lock().unwrap() = Some(min_age);
+}
+
+/// The minimum age from `--min-age` or the config, if any
+pub fn min_age() -> Option<Duration> {
+    if let Some(min_age) = *MIN_AGE_OVERRIDE.lock().unwrap() {
+        return Some(min_age);
+    }
+    let config_min_age = Config::from_file().ok()?.install.min_age?;
+    match parse_min_age(&config_min_age) {
+        Ok(min_age) => Some(min_age),
+        Err(e) => {
+            warn!("Ignoring install.min-age in the config: {}", e);
+            None
+        }
+    }
+}
+
+/// Whether a version published at `published_at` is too new to be picked. Versions without a
+/// publication date are never held back.
+pub fn is_held_back(
+    published_at: Option<DateTime<Utc>>,
+    min_age: Option<Duration>,
+    now: DateTime<Utc>,
+) -> bool {
+    match (published_at, min_age) {
+        (Some(published_at), Some(min_age)) => now - published_at < min_age,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ages_are_parsed() {
+        assert_eq!(parse_min_age("3d"), Ok(Duration::days(3)));
+        assert_eq!(parse_min_age("12h"), Ok(Duration::hours(12)));
+        assert_eq!(parse_min_age("2w"), Ok(Duration::weeks(2)));
+        assert_eq!(parse_min_age("30s"), Ok(Duration::seconds(30)));
+        assert!(parse_min_age("3").is_err());
+        assert!(parse_min_age("d").is_err());
+        assert!(parse_min_age("3y").is_err());
+        assert_eq!(format_min_age(Duration::weeks(1)), "7 days");
+        assert_eq!(format_min_age(Duration::hours(1)), "1 hour");
+    }
+}
@@ -0,0 +1,358 @@
+//! A registry for tests that serves canned GraphQL responses and files over localhost, so
+//! installing, publishing and searching can be tested end to end without a real registry.
+//! Enabled with the `mock-registry` feature.
+//!
+//! Responses are set up in code with `respond_to` and `serve_file`, or loaded from a directory of
+//! fixtures:
+//!
+//! ```text
+//! fixtures/
+//!   graphql/GetPackageVersionQuery.json   the `data` of the response to the operation
+//!   files/_/hello-0.1.0.tar.gz            served at <url>/files/_/hello-0.1.0.tar.gz
+//! ```
+//!
+//! `{{registry}}` in the GraphQL fixtures is replaced by the url of the registry, for the
+//! download urls of packages.
+
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// The url placeholder in GraphQL fixtures
+const REGISTRY_PLACEHOLDER: &str = "{{registry}}";
+
+/// A GraphQL request the registry received
+#[derive(Clone, Debug, PartialEq)]
+pub struct MockRequest {
+    pub operation: String,
+    pub variables: serde_json::Value,
+}
+
+#[derive(Default)]
+struct State {
+    /// The responses to GraphQL operations
+    responses: HashMap<String, serde_json::Value>,
+    /// The files served under `/files/`
+    files: HashMap<String, Vec<u8>>,
+    requests: Vec<MockRequest>,
+}
+
+/// A registry running on a local port until it is dropped
+pub struct MockRegistry {
+    url: String,
+    state: Arc<Mutex<State>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl MockRegistry {
+    /// Start a registry without any responses
+    pub fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let state = Arc::new(Mutex::new(State::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        {
+            let state = state.clone();
+            let stopped = stopped.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let state = state.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = handle_connection(stream, &state) {
+                                debug!("The mock registry could not answer a request: {}", e);
+                            }
+                        });
+                    }
+                }
+            });
+        }
+        Ok(Self {
+            url,
+            state,
+            stopped,
+        })
+    }
+
+    /// Start a registry with the responses and files from a fixtures directory
+    pub fn from_fixtures(directory: &Path) -> io::Result<Self> {
+        let registry = Self::start()?;
+        let graphql_dir = directory.join("graphql");
+        if graphql_dir.is_dir() {
+            for entry in fs::read_dir(&graphql_dir)? {
+                let path = entry?.path();
+                let operation = match path.file_stem() {
+                    Some(stem) if path.extension().and_then(|e| e.to_str()) == Some("json") => {
+                        stem.to_string_lossy().to_string()
+                    }
+                    _ => continue,
+                };
+                let source =
+                    fs::read_to_string(&path)?.replace(REGISTRY_PLACEHOLDER, &registry.url);
+                let data = serde_json::from_str(&source)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                registry.respond_to(&operation, data);
+            }
+        }
+        registry.serve_directory(&directory.join("files"), "")?;
+        Ok(registry)
+    }
+
+    fn serve_directory(&self, directory: &Path, prefix: &str) -> io::Result<()> {
+        if !directory.is_dir() {
+            return Ok(());
+        }
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            let name = format!(
+                "{}{}",
+                prefix,
+                path.file_name().unwrap_or_default().to_string_lossy()
+            );
+            if path.is_dir() {
+                self.serve_directory(&path, &format!("{}/", name))?;
+            } else {
+                self.serve_file(&name, fs::read(&path)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// The url to set as `registry.url`
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Answer a GraphQL operation with `data`
+    pub fn respond_to(&self, operation: &str, data: serde_json::Value) {
+        self.state
+            .lock()
+            .unwrap()
+            .responses
+            .insert(operation.to_string(), data);
+    }
+
+    /// Serve a file at `<url>/files/<path>`, returning its url
+    pub fn serve_file(&self, path: &str, contents: Vec<u8>) -> String {
+        let path = path.trim_start_matches('/').to_string();
+        let url = format!("{}/files/{}", self.url, path);
+        self.state.lock().unwrap().files.insert(path, contents);
+        url
+    }
+
+    /// The GraphQL requests received so far, in order
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for MockRegistry {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // wake the listener up so it sees it was stopped
+        let address = self.url.trim_start_matches("http://");
+        TcpStream::connect(address).ok();
+    }
+}
+
+fn handle_connection(stream: TcpStream, state: &Mutex<State>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(colon) = line.find(':') {
+            headers.insert(
+                line[..colon].trim().to_lowercase(),
+                line[colon + 1..].trim().to_string(),
+            );
+        }
+    }
+    let body = read_body(&mut reader, &headers)?;
+
+    let (status, content_type, response) = if method == "POST" && path.starts_with("/graphql") {
+        let content_type = headers.get("content-type").cloned().unwrap_or_default();
+        let request = parse_graphql_request(&content_type, &body);
+        let mut state = state.lock().unwrap();
+        let response = match state.responses.get(&request.operation) {
+            Some(data) if data.get("data").is_some() || data.get("errors").is_some() => {
+                data.clone()
+            }
+            Some(data) => json!({ "data": data }),
+            None => json!({
+                "data": null,
+                "errors": [{ "message": format!("The mock registry has no response to {}", request.operation) }],
+            }),
+        };
+        state.requests.push(request);
+        (
+            "200 OK",
+            "application/json",
+            response.to_string().into_bytes(),
+        )
+    } else if path.starts_with("/files/") {
+        let file_path = path.trim_start_matches("/files/");
+        match state.lock().unwrap().files.get(file_path) {
+            Some(contents) => ("200 OK", "application/octet-stream", contents.clone()),
+            None => ("404 Not Found", "text/plain", b"not found".to_vec()),
+        }
+    } else {
+        ("404 Not Found", "text/plain", b"not found".to_vec())
+    };
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        response.len()
+    )?;
+    stream.write_all(&response)?;
+    stream.flush()
+}
+
+fn read_body<R: BufRead>(reader: &mut R, headers: &HashMap<String, String>) -> io::Result<Vec<u8>> {
+    let mut body = vec![];
+    if headers
+        .get("transfer-encoding")
+        .map(|encoding| encoding.contains("chunked"))
+        .unwrap_or(false)
+    {
+        loop {
+            let mut size_line = String::new();
+            reader.read_line(&mut size_line)?;
+            let size = usize::from_str_radix(size_line.trim(), 16)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk)?;
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..size]);
+        }
+    } else if let Some(length) = headers.get("content-length") {
+        let length = length
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        body.resize(length, 0);
+        reader.read_exact(&mut body)?;
+    }
+    Ok(body)
+}
+
+/// The operation and variables of a GraphQL request, sent as a multipart form like wapm does or
+/// as JSON
+fn parse_graphql_request(content_type: &str, body: &[u8]) -> MockRequest {
+    let body = String::from_utf8_lossy(body);
+    let (operation, variables) = if content_type.starts_with("multipart/form-data") {
+        (
+            form_field(&body, "operationName").unwrap_or_default(),
+            form_field(&body, "variables")
+                .and_then(|variables| serde_json::from_str(&variables).ok())
+                .unwrap_or(serde_json::Value::Null),
+        )
+    } else {
+        let request: serde_json::Value =
+            serde_json::from_str(&body).unwrap_or(serde_json::Value::Null);
+        (
+            request["operationName"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            request["variables"].clone(),
+        )
+    };
+    MockRequest {
+        operation,
+        variables,
+    }
+}
+
+/// The value of a text field of a multipart form
+fn form_field(body: &str, name: &str) -> Option<String> {
+    let header = format!("name=\"{}\"", name);
+    let start = body.find(&header)?;
+    let value_start = start + body[start..].find("\r\n\r\n")? + 4;
+    let value_end = value_start + body[value_start..].find("\r\n--")?;
+    Some(body[value_start..value_end].to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use reqwest::blocking::{multipart, Client};
+
+    #[test]
+    fn canned_responses_and_files_are_served() {
+        let fixtures = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(fixtures.path().join("graphql")).unwrap();
+        fs::create_dir_all(fixtures.path().join("files/_")).unwrap();
+        fs::write(
+            fixtures.path().join("graphql/GetPackageVersionQuery.json"),
+            r#"{ "packageVersion": { "version": "0.1.0", "distribution": { "downloadUrl": "{{registry}}/files/_/hello-0.1.0.tar.gz" } } }"#,
+        )
+        .unwrap();
+        fs::write(
+            fixtures.path().join("files/_/hello-0.1.0.tar.gz"),
+            b"archive",
+        )
+        .unwrap();
+        let registry = MockRegistry::from_fixtures(fixtures.path()).unwrap();
+        let client = Client::builder().no_proxy().build().unwrap();
+
+        let form = multipart::Form::new()
+            .text("operationName", "GetPackageVersionQuery")
+            .text("variables", r#"{"name":"_/hello"}"#)
+            .text("query", "query GetPackageVersionQuery { }");
+        let response: serde_json::Value = client
+            .post(&format!("{}/graphql", registry.url()))
+            .multipart(form)
+            .send()
+            .unwrap()
+            .json()
+            .unwrap();
+        let download_url = response["data"]["packageVersion"]["distribution"]["downloadUrl"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            download_url,
+            format!("{}/files/_/hello-0.1.0.tar.gz", registry.url())
+        );
+        assert_eq!(
+            registry.requests(),
+            vec![MockRequest {
+                operation: "GetPackageVersionQuery".to_string(),
+                variables: json!({ "name": "_/hello" }),
+            }]
+        );
+
+        let archive = client.get(&download_url).send().unwrap().bytes().unwrap();
+        assert_eq!(&archive[..], b"archive");
+        let missing = client
+            .get(&format!("{}/files/missing", registry.url()))
+            .send()
+            .unwrap();
+        assert_eq!(missing.status().as_u16(), 404);
+    }
+}
@@ -0,0 +1,158 @@
+//! Packages that were renamed declare their new name with `moved-to` in the manifest of their
+//! last version. Installing or adding a package by its old name warns about the new name and
+//! offers to use it instead.
+//!
+//! The manifest of the last version comes with the versions the registry lists for a package,
+//! which are looked up for all the requested packages at once, so checking for renames doesn't
+//! cost a request per package.
+
+use crate::data::lock::lockfile::Lockfile;
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::i18n::format_message;
+use crate::registry::{self, PackageVersion};
+use crate::util;
+use semver::Version;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// The new name of a package, read from the `moved-to` field of a manifest
+pub fn moved_to(manifest: &str) -> Option<String> {
+    let manifest: toml::Value = toml::from_str(manifest).ok()?;
+    manifest
+        .get("package")?
+        .get("moved-to")?
+        .as_str()
+        .map(str::to_owned)
+}
+
+/// The new name of a package if the last of its `versions` in the registry says it was renamed.
+/// The last version is only looked up on its own for registries that list versions without
+/// their manifest.
+fn registry_moved_to(
+    name: &str,
+    versions: &[PackageVersion],
+) -> Result<Option<String>, failure::Error> {
+    let last_version = versions
+        .iter()
+        .filter(|version| version.name == name)
+        .max_by_key(|version| Version::parse(&version.version).ok());
+    let manifest = match last_version {
+        Some(PackageVersion {
+            manifest: Some(manifest),
+            ..
+        }) => Some(manifest.clone()),
+        Some(_) => registry::backend()?
+            .package_version(name, None)?
+            .and_then(|last_version| last_version.manifest),
+        None => None,
+    };
+    Ok(manifest.and_then(|manifest| moved_to(&manifest)))
+}
+
+/// The name to use for a requested package, given the `versions` of the requested packages in
+/// the registry. When the package was renamed, the user is warned and asked whether to use the
+/// new name instead.
+pub fn follow_moved_package(
+    name: &str,
+    versions: &[PackageVersion],
+) -> Result<String, failure::Error> {
+    let mut current_name = name.to_owned();
+    let mut current_versions = Cow::Borrowed(versions);
+    let mut seen_names = HashSet::new();
+    while let Some(new_name) = registry_moved_to(&current_name, &current_versions)? {
+        // packages that point at each other would redirect forever
+        if !seen_names.insert(current_name.clone()) || new_name == current_name {
+            break;
+        }
+        warn!(
+            "{}",
+            format_message(
+                "install.package_moved",
+                &[("package", &current_name), ("new_package", &new_name)]
+            )
+        );
+        if !util::prompt_user_for_yes(&format_message(
+            "install.use_moved_package",
+            &[("new_package", &new_name)],
+        ))? {
+            break;
+        }
+        current_name = new_name;
+        // the new name wasn't among the requested packages
+        current_versions =
+            Cow::Owned(registry::backend()?.package_versions(std::slice::from_ref(&current_name))?);
+    }
+    Ok(current_name)
+}
+
+/// Record in the lockfile of the directory which installed packages were requested by an old
+/// name. `moves` are pairs of the old and new names.
+pub fn record_moved_packages(
+    directory: &Path,
+    moves: &[(String, String)],
+) -> Result<(), failure::Error> {
+    if moves.is_empty() {
+        return Ok(());
+    }
+    let mut lockfile: Lockfile = match LockfileResult::find_in_directory(directory) {
+        LockfileResult::Lockfile(lockfile) => lockfile,
+        _ => return Ok(()),
+    };
+    for (old_name, new_name) in moves {
+        let modules = lockfile
+            .modules
+            .get_mut(new_name)
+            .into_iter()
+            .flat_map(|versions| versions.values_mut())
+            .flat_map(|modules| modules.values_mut());
+        for module in modules {
+            module.moved_from = Some(old_name.clone());
+        }
+    }
+    lockfile.save(directory)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read_moved_to() {
+        let manifest = r#"
+[package]
+name = "_/old-name"
+version = "1.0.0"
+description = ""
+moved-to = "_/new-name"
+"#;
+        assert_eq!(moved_to(manifest).as_deref(), Some("_/new-name"));
+        assert_eq!(moved_to("[package]\nname = \"_/old-name\""), None);
+        assert_eq!(moved_to("not toml ["), None);
+
+        let version = |version: &str, manifest: Option<&str>| PackageVersion {
+            name: "_/old-name".to_string(),
+            version: version.to_string(),
+            manifest: manifest.map(str::to_string),
+            download_url: String::new(),
+            signature: None,
+            license: None,
+            size: None,
+            published_at: None,
+            commands: None,
+        };
+        // only the last version comes with its manifest
+        let versions = vec![
+            version("1.0.0", Some(manifest)),
+            version("0.9.0", None),
+            version("0.10.0", None),
+        ];
+        assert_eq!(
+            registry_moved_to("_/old-name", &versions)
+                .unwrap()
+                .as_deref(),
+            Some("_/new-name")
+        );
+        assert_eq!(registry_moved_to("_/other", &versions).unwrap(), None);
+    }
+}
@@ -0,0 +1,755 @@
+//! Pushing and pulling packages as OCI artifacts, so container registries like the GitHub
+//! Container Registry or Harbor can distribute them, with `wapm push oci://ghcr.io/user/pkg:1.0.0`
+//! and `wapm install oci://ghcr.io/user/pkg:1.0.0`.
+//!
+//! A package is an artifact of type `application/vnd.wapm.package.v1` with a single layer, the
+//! archive the package would be published to the wapm registry with, and its manifest as JSON in
+//! the config blob. The name, version, description, license and links of the package are also
+//! recorded in the standard `org.opencontainers.image.*` annotations of the OCI manifest.
+//!
+//! Registries are authenticated with the token flow of the distribution spec, using the
+//! credentials of the `WAPM_OCI_USERNAME` and `WAPM_OCI_PASSWORD` environment variables when they
+//! are set. Registries on `localhost` are reached over plain HTTP.
+
+use crate::data::manifest::Manifest;
+use crate::graphql::VERSION;
+use crate::proxy;
+use crate::util::sha256_hex;
+use chrono::Utc;
+use reqwest::blocking::{Client, ClientBuilder, RequestBuilder, Response};
+use reqwest::header::{ACCEPT, CONTENT_TYPE, LOCATION, USER_AGENT, WWW_AUTHENTICATE};
+use reqwest::{StatusCode, Url};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+
+/// The prefix of package identifiers that refer to OCI registries
+pub const OCI_SOURCE_PREFIX: &str = "oci://";
+/// The prefix of the resolved source of lockfile modules installed from OCI registries
+pub const OCI_RESOLVED_SOURCE_PREFIX: &str = "oci+";
+
+pub const ARTIFACT_TYPE: &str = "application/vnd.wapm.package.v1";
+pub const CONFIG_MEDIA_TYPE: &str = "application/vnd.wapm.package.config.v1+json";
+pub const LAYER_MEDIA_TYPE: &str = "application/vnd.wapm.package.layer.v1.tar+gzip";
+pub const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+pub const INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+const DOCKER_MANIFEST_MEDIA_TYPE: &str = "application/vnd.docker.distribution.manifest.v2+json";
+const DOCKER_MANIFEST_LIST_MEDIA_TYPE: &str =
+    "application/vnd.docker.distribution.manifest.list.v2+json";
+
+pub const NAME_ANNOTATION: &str = "io.wapm.package.name";
+pub const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+pub const VERSION_ANNOTATION: &str = "org.opencontainers.image.version";
+pub const DESCRIPTION_ANNOTATION: &str = "org.opencontainers.image.description";
+const LICENSES_ANNOTATION: &str = "org.opencontainers.image.licenses";
+const SOURCE_ANNOTATION: &str = "org.opencontainers.image.source";
+const URL_ANNOTATION: &str = "org.opencontainers.image.url";
+pub const CREATED_ANNOTATION: &str = "org.opencontainers.image.created";
+
+const USERNAME_ENV_VAR: &str = "WAPM_OCI_USERNAME";
+const PASSWORD_ENV_VAR: &str = "WAPM_OCI_PASSWORD";
+const DEFAULT_TAG: &str = "latest";
+
+#[derive(Clone, Debug, Fail)]
+pub enum OciError {
+    #[fail(
+        display = "Invalid OCI reference \"{}\", expected oci://<registry>/<repository>[:<tag>]",
+        _0
+    )]
+    InvalidReference(String),
+    #[fail(display = "Request to the OCI registry for {} failed: {}", _0, _1)]
+    RequestFailed(String, String),
+    #[fail(
+        display = "Could not authenticate with the OCI registry for {}: {}. Set the WAPM_OCI_USERNAME and WAPM_OCI_PASSWORD environment variables to log in",
+        _0, _1
+    )]
+    AuthenticationFailed(String, String),
+    #[fail(
+        display = "{} is not a wapm package, it has no layer of type application/vnd.wapm.package.layer.v1.tar+gzip",
+        _0
+    )]
+    NotAPackage(String),
+    #[fail(display = "The {} of {} does not match its digest", _0, _1)]
+    DigestMismatch(&'static str, String),
+    #[fail(
+        display = "The OCI manifest of {} has no \"{}\" annotation or it is invalid",
+        _0, _1
+    )]
+    InvalidAnnotation(String, &'static str),
+    #[fail(display = "{} has no image for linux/{}", _0, _1)]
+    NoImageForPlatform(String, String),
+}
+
+/// A package in a repository of an OCI registry, like `oci://ghcr.io/user/pkg:1.0.0`
+#[derive(Clone, Debug, PartialEq)]
+pub struct OciReference {
+    pub registry: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    /// The digest of the OCI manifest, given with `@sha256:...`
+    pub digest: Option<String>,
+}
+
+impl OciReference {
+    pub fn parse(identifier: &str) -> Result<Self, OciError> {
+        let invalid = || OciError::InvalidReference(identifier.to_owned());
+        if !identifier.starts_with(OCI_SOURCE_PREFIX) {
+            return Err(invalid());
+        }
+        let reference = &identifier[OCI_SOURCE_PREFIX.len()..];
+        let (reference, digest) = match reference.find('@') {
+            Some(index) => (&reference[..index], Some(&reference[index + 1..])),
+            None => (reference, None),
+        };
+        let slash = reference.find('/').ok_or_else(invalid)?;
+        let registry = &reference[..slash];
+        let repository_and_tag = &reference[slash + 1..];
+        // the registry may have a port, but a colon after the last slash starts the tag
+        let last_segment_start = repository_and_tag.rfind('/').map_or(0, |index| index + 1);
+        let (repository, tag) = match repository_and_tag[last_segment_start..].find(':') {
+            Some(index) => {
+                let index = last_segment_start + index;
+                (
+                    &repository_and_tag[..index],
+                    Some(&repository_and_tag[index + 1..]),
+                )
+            }
+            None => (repository_and_tag, None),
+        };
+        let is_valid_repository = !repository.is_empty()
+            && repository.split('/').all(|segment| {
+                !segment.is_empty()
+                    && segment
+                        .chars()
+                        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "._-".contains(c))
+            });
+        let is_valid_tag = tag.is_none_or(|tag| {
+            !tag.is_empty()
+                && tag.len() <= 128
+                && tag
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+        });
+        let is_valid_digest =
+            digest.is_none_or(|digest| digest.starts_with("sha256:") && digest.len() > 7);
+        if registry.is_empty() || !is_valid_repository || !is_valid_tag || !is_valid_digest {
+            return Err(invalid());
+        }
+        Ok(Self {
+            registry: registry.to_owned(),
+            repository: repository.to_owned(),
+            tag: tag.map(str::to_owned),
+            digest: digest.map(str::to_owned),
+        })
+    }
+
+    /// The same repository, with a tag instead of the tag or digest of this reference
+    pub fn with_tag(&self, tag: &str) -> Self {
+        Self {
+            tag: Some(tag.to_owned()),
+            digest: None,
+            ..self.clone()
+        }
+    }
+
+    /// The tag or digest the manifest is fetched with
+    fn manifest_reference(&self) -> &str {
+        self.digest
+            .as_deref()
+            .or(self.tag.as_deref())
+            .unwrap_or(DEFAULT_TAG)
+    }
+
+    fn base_url(&self) -> String {
+        let is_local = self.registry.starts_with("localhost")
+            || self.registry.starts_with("127.0.0.1")
+            || self.registry.starts_with("[::1]");
+        let scheme = if is_local { "http" } else { "https" };
+        format!("{}://{}/v2/{}", scheme, self.registry, self.repository)
+    }
+
+    /// The url of a blob of the repository
+    pub fn blob_url(&self, digest: &str) -> String {
+        format!("{}/blobs/{}", self.base_url(), digest)
+    }
+
+    fn manifest_url(&self, reference: &str) -> String {
+        format!("{}/manifests/{}", self.base_url(), reference)
+    }
+
+    /// How the source of the package is recorded in the lockfile
+    pub fn resolved_source(&self) -> String {
+        format!(
+            "{}{}",
+            OCI_RESOLVED_SOURCE_PREFIX,
+            &self.to_string()[OCI_SOURCE_PREFIX.len()..]
+        )
+    }
+}
+
+impl fmt::Display for OciReference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{}/{}",
+            OCI_SOURCE_PREFIX, self.registry, self.repository
+        )?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{}", tag)?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
+
+/// An OCI image manifest, which describes the blobs of an artifact
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageManifest {
+    pub schema_version: u32,
+    #[serde(default)]
+    pub media_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    pub config: Descriptor,
+    pub layers: Vec<Descriptor>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// A reference to a blob, by its digest
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    pub media_type: String,
+    pub digest: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+impl Descriptor {
+    pub fn new(media_type: &str, data: &[u8]) -> Self {
+        Self {
+            media_type: media_type.to_owned(),
+            digest: digest(data),
+            size: data.len() as u64,
+            annotations: BTreeMap::new(),
+        }
+    }
+}
+
+impl ImageManifest {
+    /// The OCI manifest of a package, given its manifest as JSON and its archive
+    pub fn for_package(manifest: &Manifest, config: &[u8], archive: &[u8]) -> Self {
+        let package = &manifest.package;
+        let mut layer = Descriptor::new(LAYER_MEDIA_TYPE, archive);
+        layer.annotations.insert(
+            TITLE_ANNOTATION.to_owned(),
+            format!(
+                "{}-{}.tar.gz",
+                package.name.replace('/', "-"),
+                package.version
+            ),
+        );
+        let mut annotations = BTreeMap::new();
+        annotations.insert(NAME_ANNOTATION.to_owned(), package.name.clone());
+        annotations.insert(TITLE_ANNOTATION.to_owned(), package.name.clone());
+        annotations.insert(VERSION_ANNOTATION.to_owned(), package.version.to_string());
+        annotations.insert(
+            DESCRIPTION_ANNOTATION.to_owned(),
+            package.description.clone(),
+        );
+        annotations.insert(CREATED_ANNOTATION.to_owned(), Utc::now().to_rfc3339());
+        let optional_annotations = [
+            (LICENSES_ANNOTATION, &package.license),
+            (SOURCE_ANNOTATION, &package.repository),
+            (URL_ANNOTATION, &package.homepage),
+        ];
+        for (annotation, value) in optional_annotations.iter() {
+            if let Some(value) = value {
+                annotations.insert((*annotation).to_owned(), value.clone());
+            }
+        }
+        Self {
+            schema_version: 2,
+            media_type: MANIFEST_MEDIA_TYPE.to_owned(),
+            artifact_type: Some(ARTIFACT_TYPE.to_owned()),
+            config: Descriptor::new(CONFIG_MEDIA_TYPE, config),
+            layers: vec![layer],
+            annotations,
+        }
+    }
+
+    /// The layer with the archive of the package
+    pub fn package_layer(&self) -> Option<&Descriptor> {
+        self.layers
+            .iter()
+            .find(|layer| layer.media_type == LAYER_MEDIA_TYPE)
+    }
+}
+
+/// A package pulled from an OCI registry
+pub struct PulledPackage {
+    pub manifest: ImageManifest,
+    /// The digest of the OCI manifest, which pins the package
+    pub manifest_digest: String,
+    /// The gzipped archive of the package
+    pub archive: Vec<u8>,
+}
+
+/// Push the archive of a package to a repository, tagged with the tag of the reference.
+/// Returns the digest of the OCI manifest.
+pub fn push(
+    reference: &OciReference,
+    manifest: &Manifest,
+    archive: &[u8],
+) -> Result<String, OciError> {
+    let mut client = RegistryClient::new(reference, "pull,push")?;
+    let config = serde_json::to_vec(manifest)
+        .map_err(|e| OciError::RequestFailed(reference.to_string(), e.to_string()))?;
+    client.upload_blob(&config)?;
+    client.upload_blob(archive)?;
+
+    let image_manifest = ImageManifest::for_package(manifest, &config, archive);
+    let body = serde_json::to_vec(&image_manifest)
+        .map_err(|e| OciError::RequestFailed(reference.to_string(), e.to_string()))?;
+    let manifest_digest = digest(&body);
+    let url = reference.manifest_url(reference.manifest_reference());
+    client.send(|client| {
+        client
+            .put(&url)
+            .header(CONTENT_TYPE, MANIFEST_MEDIA_TYPE)
+            .body(body.clone())
+    })?;
+    Ok(manifest_digest)
+}
+
+/// Pull the archive of a package, checking the digests of everything that is downloaded
+pub fn pull(reference: &OciReference) -> Result<PulledPackage, OciError> {
+    let mut client = RegistryClient::new(reference, "pull")?;
+    let url = reference.manifest_url(reference.manifest_reference());
+    let body = client
+        .send(|client| client.get(&url).header(ACCEPT, MANIFEST_MEDIA_TYPE))?
+        .bytes()
+        .map_err(|e| OciError::RequestFailed(reference.to_string(), e.to_string()))?;
+    let manifest_digest = digest(&body);
+    if let Some(expected) = &reference.digest {
+        if expected != &manifest_digest {
+            return Err(OciError::DigestMismatch("manifest", reference.to_string()));
+        }
+    }
+    let manifest: ImageManifest = serde_json::from_slice(&body)
+        .map_err(|e| OciError::RequestFailed(reference.to_string(), e.to_string()))?;
+    let layer = manifest
+        .package_layer()
+        .ok_or_else(|| OciError::NotAPackage(reference.to_string()))?;
+    let archive = client.fetch_blob(&layer.digest, "package archive")?;
+    Ok(PulledPackage {
+        manifest,
+        manifest_digest,
+        archive,
+    })
+}
+
+/// A container image pulled from an OCI registry, to build other images on
+pub struct PulledImage {
+    pub manifest: ImageManifest,
+    /// The image config, as JSON
+    pub config: Vec<u8>,
+    /// The blobs of the layers, in the order of the manifest
+    pub layers: Vec<Vec<u8>>,
+}
+
+#[derive(Deserialize)]
+struct ImageIndex {
+    manifests: Vec<IndexEntry>,
+}
+
+#[derive(Deserialize)]
+struct IndexEntry {
+    digest: String,
+    platform: Option<Platform>,
+}
+
+#[derive(Deserialize)]
+struct Platform {
+    architecture: String,
+    os: String,
+}
+
+/// Pull a container image. When the reference is a multi-platform image, the linux image of the
+/// architecture is pulled.
+pub fn pull_image(reference: &OciReference, architecture: &str) -> Result<PulledImage, OciError> {
+    let mut client = RegistryClient::new(reference, "pull")?;
+    let accept = [
+        MANIFEST_MEDIA_TYPE,
+        INDEX_MEDIA_TYPE,
+        DOCKER_MANIFEST_MEDIA_TYPE,
+        DOCKER_MANIFEST_LIST_MEDIA_TYPE,
+    ]
+    .join(", ");
+    let mut body = client.fetch_manifest(reference.manifest_reference(), &accept)?;
+    if let Some(expected) = &reference.digest {
+        if expected != &digest(&body) {
+            return Err(OciError::DigestMismatch("manifest", reference.to_string()));
+        }
+    }
+    let parse_error =
+        |e: serde_json::Error| OciError::RequestFailed(reference.to_string(), e.to_string());
+    if let Ok(index) = serde_json::from_slice::<ImageIndex>(&body) {
+        let entry = index
+            .manifests
+            .iter()
+            .find(|entry| {
+                entry.platform.as_ref().is_some_and(|platform| {
+                    platform.os == "linux" && platform.architecture == architecture
+                })
+            })
+            .ok_or_else(|| {
+                OciError::NoImageForPlatform(reference.to_string(), architecture.to_owned())
+            })?;
+        body = client.fetch_manifest(&entry.digest, &accept)?;
+        if digest(&body) != entry.digest {
+            return Err(OciError::DigestMismatch("manifest", reference.to_string()));
+        }
+    }
+    let manifest: ImageManifest = serde_json::from_slice(&body).map_err(parse_error)?;
+    let config = client.fetch_blob(&manifest.config.digest, "image config")?;
+    let layers = manifest
+        .layers
+        .iter()
+        .map(|layer| client.fetch_blob(&layer.digest, "image layer"))
+        .collect::<Result<_, _>>()?;
+    Ok(PulledImage {
+        manifest,
+        config,
+        layers,
+    })
+}
+
+/// The digest of a blob, as the distribution spec writes it
+pub fn digest(data: &[u8]) -> String {
+    format!("sha256:{}", sha256_hex(data))
+}
+
+enum Authorization {
+    Basic(String, String),
+    Bearer(String),
+}
+
+/// Sends requests to a repository, authenticating when the registry asks for it
+struct RegistryClient<'a> {
+    client: Client,
+    reference: &'a OciReference,
+    /// The actions the token is requested for, like `pull,push`
+    actions: &'static str,
+    credentials: Option<(String, String)>,
+    authorization: Option<Authorization>,
+}
+
+impl<'a> RegistryClient<'a> {
+    fn new(reference: &'a OciReference, actions: &'static str) -> Result<Self, OciError> {
+        let builder = ClientBuilder::new();
+        let client = proxy::maybe_set_up_proxy()
+            .map(|proxy| match proxy {
+                Some(proxy) => builder.proxy(proxy),
+                None => builder,
+            })
+            .and_then(|builder| Ok(builder.build()?))
+            .map_err(|e| OciError::RequestFailed(reference.to_string(), e.to_string()))?;
+        let credentials = match (env::var(USERNAME_ENV_VAR), env::var(PASSWORD_ENV_VAR)) {
+            (Ok(username), Ok(password)) if !username.is_empty() => Some((username, password)),
+            _ => None,
+        };
+        Ok(Self {
+            client,
+            reference,
+            actions,
+            credentials,
+            authorization: None,
+        })
+    }
+
+    fn request_error(&self, e: impl ToString) -> OciError {
+        OciError::RequestFailed(self.reference.to_string(), e.to_string())
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        let request = request.header(
+            USER_AGENT,
+            format!(
+                "wapm/{} {} {}",
+                VERSION,
+                whoami::platform(),
+                whoami::os().to_lowercase()
+            ),
+        );
+        match &self.authorization {
+            Some(Authorization::Basic(username, password)) => {
+                request.basic_auth(username, Some(password))
+            }
+            Some(Authorization::Bearer(token)) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Send a request, authenticating and sending it again if the registry asks for credentials.
+    /// Fails on error statuses.
+    fn send<F: Fn(&Client) -> RequestBuilder>(&mut self, build: F) -> Result<Response, OciError> {
+        let response = self
+            .authorize(build(&self.client))
+            .send()
+            .map_err(|e| self.request_error(e))?;
+        let response =
+            if response.status() == StatusCode::UNAUTHORIZED && self.authorization.is_none() {
+                let challenge = response
+                    .headers()
+                    .get(WWW_AUTHENTICATE)
+                    .and_then(|header| header.to_str().ok())
+                    .unwrap_or_default()
+                    .to_owned();
+                self.authorization = Some(self.authenticate(&challenge)?);
+                self.authorize(build(&self.client))
+                    .send()
+                    .map_err(|e| self.request_error(e))?
+            } else {
+                response
+            };
+        if response.status() == StatusCode::UNAUTHORIZED
+            || response.status() == StatusCode::FORBIDDEN
+        {
+            return Err(OciError::AuthenticationFailed(
+                self.reference.to_string(),
+                response.status().to_string(),
+            ));
+        }
+        response
+            .error_for_status()
+            .map_err(|e| self.request_error(e))
+    }
+
+    /// Get the authorization a `WWW-Authenticate` challenge asks for
+    fn authenticate(&self, challenge: &str) -> Result<Authorization, OciError> {
+        let auth_error =
+            |e: &str| OciError::AuthenticationFailed(self.reference.to_string(), e.to_owned());
+        let (scheme, parameters) = parse_challenge(challenge)
+            .ok_or_else(|| auth_error("the registry sent no challenge"))?;
+        if scheme.eq_ignore_ascii_case("basic") {
+            let (username, password) = self
+                .credentials
+                .clone()
+                .ok_or_else(|| auth_error("the registry requires credentials"))?;
+            return Ok(Authorization::Basic(username, password));
+        }
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            return Err(auth_error(&format!(
+                "unsupported authentication scheme {}",
+                scheme
+            )));
+        }
+        let realm = parameters
+            .get("realm")
+            .ok_or_else(|| auth_error("the challenge of the registry has no realm"))?;
+        let mut url = Url::parse(realm).map_err(|e| auth_error(&e.to_string()))?;
+        {
+            let mut query = url.query_pairs_mut();
+            if let Some(service) = parameters.get("service") {
+                query.append_pair("service", service);
+            }
+            query.append_pair(
+                "scope",
+                &format!("repository:{}:{}", self.reference.repository, self.actions),
+            );
+        }
+        let mut request = self.client.get(url);
+        if let Some((username, password)) = &self.credentials {
+            request = request.basic_auth(username, Some(password));
+        }
+        let response: TokenResponse = request
+            .send()
+            .and_then(|response| response.error_for_status())
+            .and_then(|response| response.json())
+            .map_err(|e| auth_error(&e.to_string()))?;
+        response
+            .token
+            .or(response.access_token)
+            .map(Authorization::Bearer)
+            .ok_or_else(|| auth_error("the registry sent no token"))
+    }
+
+    fn fetch_manifest(&mut self, reference: &str, accept: &str) -> Result<Vec<u8>, OciError> {
+        let url = self.reference.manifest_url(reference);
+        let body = self
+            .send(|client| client.get(&url).header(ACCEPT, accept))?
+            .bytes()
+            .map_err(|e| self.request_error(e))?;
+        Ok(body.to_vec())
+    }
+
+    /// Download a blob of the repository, checking its digest
+    fn fetch_blob(&mut self, blob_digest: &str, what: &'static str) -> Result<Vec<u8>, OciError> {
+        let blob_url = self.reference.blob_url(blob_digest);
+        let blob = self
+            .send(|client| client.get(&blob_url))?
+            .bytes()
+            .map_err(|e| self.request_error(e))?
+            .to_vec();
+        if digest(&blob) != blob_digest {
+            return Err(OciError::DigestMismatch(what, self.reference.to_string()));
+        }
+        Ok(blob)
+    }
+
+    /// Upload a blob to the repository, unless the repository has it already
+    fn upload_blob(&mut self, data: &[u8]) -> Result<(), OciError> {
+        let digest = digest(data);
+        let blob_url = self.reference.blob_url(&digest);
+        let exists = self.send(|client| client.head(&blob_url)).is_ok();
+        if exists {
+            return Ok(());
+        }
+        let uploads_url = format!("{}/blobs/uploads/", self.reference.base_url());
+        let response = self.send(|client| client.post(&uploads_url))?;
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|header| header.to_str().ok())
+            .ok_or_else(|| self.request_error("the registry did not start the upload"))?;
+        // the location of the upload may be relative to the registry
+        let mut upload_url = Url::parse(&uploads_url)
+            .and_then(|base| base.join(location))
+            .map_err(|e| self.request_error(e))?;
+        upload_url.query_pairs_mut().append_pair("digest", &digest);
+        self.send(|client| {
+            client
+                .put(upload_url.clone())
+                .header(CONTENT_TYPE, "application/octet-stream")
+                .body(data.to_vec())
+        })?;
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+/// Parse a `WWW-Authenticate` header like `Bearer realm="https://ghcr.io/token",service="ghcr.io"`
+fn parse_challenge(challenge: &str) -> Option<(String, BTreeMap<String, String>)> {
+    let challenge = challenge.trim();
+    let (scheme, rest) = match challenge.find(' ') {
+        Some(index) => (&challenge[..index], &challenge[index + 1..]),
+        None => (challenge, ""),
+    };
+    if scheme.is_empty() {
+        return None;
+    }
+    let mut parameters = BTreeMap::new();
+    let mut rest = rest.trim_start();
+    while let Some(equals) = rest.find('=') {
+        let key = rest[..equals].trim().to_lowercase();
+        let value_start = &rest[equals + 1..];
+        let (value, remainder) = if let Some(value_start) = value_start.strip_prefix('"') {
+            let end = value_start.find('"').unwrap_or(value_start.len());
+            (
+                &value_start[..end],
+                value_start.get(end + 1..).unwrap_or_default(),
+            )
+        } else {
+            let end = value_start.find(',').unwrap_or(value_start.len());
+            (&value_start[..end], &value_start[end..])
+        };
+        parameters.insert(key, value.to_owned());
+        rest = remainder.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+    }
+    Some((scheme.to_owned(), parameters))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_references() {
+        let reference = OciReference::parse("oci://ghcr.io/user/pkg:1.0.0").unwrap();
+        assert_eq!(reference.registry, "ghcr.io");
+        assert_eq!(reference.repository, "user/pkg");
+        assert_eq!(reference.tag.as_deref(), Some("1.0.0"));
+        assert_eq!(reference.to_string(), "oci://ghcr.io/user/pkg:1.0.0");
+        assert_eq!(reference.resolved_source(), "oci+ghcr.io/user/pkg:1.0.0");
+        assert_eq!(
+            reference.manifest_url("1.0.0"),
+            "https://ghcr.io/v2/user/pkg/manifests/1.0.0"
+        );
+
+        let reference = OciReference::parse("oci://localhost:5000/pkg@sha256:abc").unwrap();
+        assert_eq!(reference.registry, "localhost:5000");
+        assert_eq!(reference.tag, None);
+        assert_eq!(reference.manifest_reference(), "sha256:abc");
+        assert_eq!(
+            reference.blob_url("sha256:def"),
+            "http://localhost:5000/v2/pkg/blobs/sha256:def"
+        );
+        assert_eq!(
+            OciReference::parse("oci://ghcr.io/user/pkg")
+                .unwrap()
+                .manifest_reference(),
+            "latest"
+        );
+
+        assert!(OciReference::parse("oci://ghcr.io").is_err());
+        assert!(OciReference::parse("oci://ghcr.io/User/pkg").is_err());
+        assert!(OciReference::parse("oci://ghcr.io/user/pkg:").is_err());
+        assert!(OciReference::parse("ghcr.io/user/pkg:1.0.0").is_err());
+    }
+
+    #[test]
+    fn package_manifests_are_annotated() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+[package]
+name = "user/pkg"
+version = "1.0.0"
+description = "A package"
+license = "MIT"
+"#,
+        )
+        .unwrap();
+        let image_manifest = ImageManifest::for_package(&manifest, b"{}", b"archive");
+        assert_eq!(image_manifest.config.media_type, CONFIG_MEDIA_TYPE);
+        assert_eq!(image_manifest.config.digest, digest(b"{}"));
+        let layer = image_manifest.package_layer().unwrap();
+        assert_eq!(layer.size, 7);
+        assert_eq!(layer.annotations[TITLE_ANNOTATION], "user-pkg-1.0.0.tar.gz");
+        assert_eq!(image_manifest.annotations[NAME_ANNOTATION], "user/pkg");
+        assert_eq!(image_manifest.annotations[VERSION_ANNOTATION], "1.0.0");
+        assert_eq!(image_manifest.annotations[LICENSES_ANNOTATION], "MIT");
+        assert!(!image_manifest.annotations.contains_key(SOURCE_ANNOTATION));
+
+        let json = serde_json::to_value(&image_manifest).unwrap();
+        assert_eq!(json["schemaVersion"], 2);
+        assert_eq!(json["artifactType"], ARTIFACT_TYPE);
+        assert_eq!(json["layers"][0]["mediaType"], LAYER_MEDIA_TYPE);
+    }
+
+    #[test]
+    fn parse_authentication_challenges() {
+        let (scheme, parameters) = parse_challenge(
+            r#"Bearer realm="https://ghcr.io/token",service="ghcr.io",scope="repository:user/pkg:pull""#,
+        )
+        .unwrap();
+        assert_eq!(scheme, "Bearer");
+        assert_eq!(parameters["realm"], "https://ghcr.io/token");
+        assert_eq!(parameters["service"], "ghcr.io");
+        assert_eq!(parameters["scope"], "repository:user/pkg:pull");
+        let (scheme, parameters) = parse_challenge(r#"Basic realm="Registry""#).unwrap();
+        assert_eq!(scheme, "Basic");
+        assert_eq!(parameters["realm"], "Registry");
+        assert!(parse_challenge("").is_none());
+    }
+}
@@ -0,0 +1,254 @@
+//! Finding the dependencies of a project that have newer versions in the registry
+
+use crate::data::lock::lockfile::Lockfile;
+use crate::data::manifest::Manifest;
+use crate::min_age;
+use crate::registry::PackageVersion;
+use chrono::{Duration, Utc};
+use semver::{Version, VersionReq};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutdatedDependency {
+    pub name: String,
+    /// The version requirement in the manifest, or of the pin of the dependency
+    pub requirement: String,
+    /// The version in the lockfile
+    pub current: Option<Version>,
+    /// The greatest version matching the requirement
+    pub wanted: Option<Version>,
+    pub latest: Version,
+    /// A version newer than `latest` that was published too recently for the minimum package age
+    pub held_back: Option<Version>,
+}
+
+impl OutdatedDependency {
+    /// Whether the latest version is newer than the version in the lockfile
+    pub fn is_upgradable(&self) -> bool {
+        self.current.iter().all(|current| *current < self.latest)
+    }
+
+    /// Whether the dependency is behind the latest version or has newer versions held back
+    pub fn is_outdated(&self) -> bool {
+        self.is_upgradable() || self.held_back.is_some()
+    }
+}
+
+/// The newest version, prereleases are only newest if there is nothing else
+fn newest<'a>(versions: impl Iterator<Item = &'a Version> + Clone) -> Option<&'a Version> {
+    versions
+        .clone()
+        .filter(|version| !version.is_prerelease())
+        .max()
+        .or_else(|| versions.max())
+}
+
+/// The dependencies of the manifest whose latest version in the registry is newer than the
+/// version in the lockfile, or that have newer versions held back by `min_age`.
+/// `registry_versions` are the versions of the dependencies in the registry.
+pub fn outdated_dependencies(
+    manifest: &Manifest,
+    lockfile: Option<&Lockfile>,
+    registry_versions: &[PackageVersion],
+    min_age: Option<Duration>,
+) -> Vec<OutdatedDependency> {
+    dependency_versions(manifest, lockfile, registry_versions, min_age)
+        .into_iter()
+        .filter(OutdatedDependency::is_outdated)
+        .collect()
+}
+
+/// The current, wanted and latest versions of every dependency of the manifest that has versions
+/// in the registry, whether it is outdated or not
+pub fn dependency_versions(
+    manifest: &Manifest,
+    lockfile: Option<&Lockfile>,
+    registry_versions: &[PackageVersion],
+    min_age: Option<Duration>,
+) -> Vec<OutdatedDependency> {
+    let now = Utc::now();
+    let mut outdated = vec![];
+    for (name, requirement) in manifest.dependencies.iter().flatten() {
+        let requirement = manifest.dependency_requirement(name, requirement);
+        // the versions and whether they are held back
+        let versions: Vec<(Version, bool)> = registry_versions
+            .iter()
+            .filter(|package_version| package_version.name == *name)
+            .filter_map(|package_version| {
+                Version::parse(&package_version.version)
+                    .ok()
+                    .map(|version| {
+                        let held_back =
+                            min_age::is_held_back(package_version.published_at, min_age, now);
+                        (version, held_back)
+                    })
+            })
+            .collect();
+        let allowed = versions
+            .iter()
+            .filter(|(_, held_back)| !held_back)
+            .map(|(version, _)| version);
+        let latest = match newest(allowed.clone()) {
+            Some(latest) => latest.clone(),
+            None => continue,
+        };
+        let held_back = newest(versions.iter().map(|(version, _)| version))
+            .filter(|newest| **newest > latest)
+            .cloned();
+        let current = lockfile
+            .and_then(|lockfile| lockfile.modules.get(name))
+            .and_then(|versions| versions.keys().next())
+            .cloned();
+        let wanted = VersionReq::parse(requirement).ok().and_then(|requirement| {
+            allowed
+                .filter(|version| requirement.matches(version))
+                .max()
+                .cloned()
+        });
+        outdated.push(OutdatedDependency {
+            name: name.clone(),
+            requirement: requirement.to_string(),
+            current,
+            wanted,
+            latest,
+            held_back,
+        });
+    }
+    outdated.sort_by(|a, b| a.name.cmp(&b.name));
+    outdated
+}
+
+/// The header and a row per dependency with the current, wanted and latest versions, padded into
+/// columns. Versions held back by the minimum package age get a column if there are any.
+pub fn format_rows(outdated: &[OutdatedDependency]) -> Vec<String> {
+    let version_or_dash = |version: &Option<Version>| {
+        version
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_else(|| "-".to_string())
+    };
+    let with_held_back = outdated
+        .iter()
+        .any(|dependency| dependency.held_back.is_some());
+    let mut rows = vec![vec![
+        "PACKAGE".to_string(),
+        "CURRENT".to_string(),
+        "WANTED".to_string(),
+        "LATEST".to_string(),
+    ]];
+    rows.extend(outdated.iter().map(|dependency| {
+        vec![
+            dependency.name.clone(),
+            version_or_dash(&dependency.current),
+            version_or_dash(&dependency.wanted),
+            dependency.latest.to_string(),
+        ]
+    }));
+    if with_held_back {
+        rows[0].push("HELD BACK".to_string());
+        for (row, dependency) in rows[1..].iter_mut().zip(outdated) {
+            row.push(version_or_dash(&dependency.held_back));
+        }
+    }
+    let mut widths = vec![0; rows[0].len()];
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect();
+            cells.join("  ").trim_end().to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_outdated_dependencies() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+[package]
+name = "_/app"
+version = "1.0.0"
+description = ""
+
+[dependencies]
+"_/outdated" = "1.0"
+"_/up-to-date" = "2.0.0"
+"#,
+        )
+        .unwrap();
+        let registry_version = |name: &str, version: &str| PackageVersion {
+            name: name.to_string(),
+            version: version.to_string(),
+            manifest: None,
+            download_url: String::new(),
+            signature: None,
+            license: None,
+            size: None,
+            published_at: None,
+            commands: None,
+        };
+        let registry_versions = vec![
+            registry_version("_/outdated", "1.0.0"),
+            registry_version("_/outdated", "1.2.0"),
+            registry_version("_/outdated", "2.0.0"),
+            registry_version("_/outdated", "3.0.0-beta"),
+            registry_version("_/up-to-date", "2.0.0"),
+        ];
+        let outdated = outdated_dependencies(&manifest, None, &registry_versions, None);
+        assert_eq!(outdated.len(), 2);
+        assert_eq!(
+            outdated[0],
+            OutdatedDependency {
+                name: "_/outdated".to_string(),
+                requirement: "1.0".to_string(),
+                current: None,
+                wanted: Some(Version::new(1, 2, 0)),
+                latest: Version::new(2, 0, 0),
+                held_back: None,
+            }
+        );
+    }
+
+    #[test]
+    fn newer_versions_are_held_back_by_the_minimum_age() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+[package]
+name = "_/app"
+version = "1.0.0"
+description = ""
+
+[dependencies]
+"_/fresh" = "1.0"
+"#,
+        )
+        .unwrap();
+        let registry_version = |version: &str, days_ago: i64| PackageVersion {
+            name: "_/fresh".to_string(),
+            version: version.to_string(),
+            manifest: None,
+            download_url: String::new(),
+            signature: None,
+            license: None,
+            size: None,
+            published_at: Some(Utc::now() - Duration::days(days_ago)),
+            commands: None,
+        };
+        let registry_versions = vec![registry_version("1.0.0", 30), registry_version("1.1.0", 1)];
+        let outdated =
+            outdated_dependencies(&manifest, None, &registry_versions, Some(Duration::days(3)));
+        assert_eq!(outdated[0].wanted, Some(Version::new(1, 0, 0)));
+        assert_eq!(outdated[0].latest, Version::new(1, 0, 0));
+        assert_eq!(outdated[0].held_back, Some(Version::new(1, 1, 0)));
+    }
+}
@@ -0,0 +1,145 @@
+//! Warning about, or refusing, packages that are much larger than expected, so an accidental
+//! dependency of hundreds of megabytes is noticed before it fills the disk.
+//!
+//! Packages larger than the `install.max-package-size` config key, 100MB by default, are
+//! installed with a warning. `wapm install --max-package-size 50MB` fails on them instead. Both
+//! the archive and what it extracts to are checked. Sizes are a number optionally followed by
+//! `B`, `KB`, `MB` or `GB`.
+
+use crate::archive::ExtractionLimits;
+use crate::config::Config;
+use crate::diagnostics::{self, Warning};
+use crate::util::format_size;
+use lazy_static::lazy_static;
+use std::sync::Mutex;
+
+const DEFAULT_MAX_PACKAGE_SIZE: u64 = 100 * 1024 * 1024;
+
+lazy_static! {
+    static ref MAX_PACKAGE_SIZE_OVERRIDE: Mutex<Option<u64>> = Mutex::new(None);
+}
+
+/// The largest size a package is expected to have
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SizeLimit {
+    pub bytes: u64,
+    /// Fail on larger packages rather than warn about them
+    pub enforced: bool,
+}
+
+/// Parse a size like `100MB` or `512KB`
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let invalid = || {
+        format!(
+            "invalid size \"{}\", expected a number optionally followed by B, KB, MB or GB, e.g. 100MB",
+            size
+        )
+    };
+    let split = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (amount, unit) = size.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| invalid())?;
+    let multiplier: u64 = match unit.trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "KB" => 1024,
+        "MB" => 1024 * 1024,
+        "GB" => 1024 * 1024 * 1024,
+        _ => return Err(invalid()),
+    };
+    amount.checked_mul(multiplier).ok_or_else(invalid)
+}
+
+/// Fail on packages larger than this instead of warning about those larger than the config
+pub fn set_max_package_size(bytes: u64) {
+    *MAX_PACKAGE_SIZE_OVERRIDE.lock().unwrap() = Some(bytes);
+}
+
+/// The limit from `--max-package-size`, or else the warning threshold from the config
+pub fn size_limit() -> SizeLimit {
+    if let Some(bytes) = *MAX_PACKAGE_SIZE_OVERRIDE.lock().unwrap() {
+        return SizeLimit {
+            bytes,
+            enforced: true,
+        };
+    }
+    let config_size = Config::from_file()
+        .ok()
+        .and_then(|config| config.install.max_package_size);
+    let bytes = match config_size.as_deref().map(parse_size) {
+        Some(Ok(bytes)) => bytes,
+        Some(Err(e)) => {
+            warn!("Ignoring install.max-package-size in the config: {}", e);
+            DEFAULT_MAX_PACKAGE_SIZE
+        }
+        None => DEFAULT_MAX_PACKAGE_SIZE,
+    };
+    SizeLimit {
+        bytes,
+        enforced: false,
+    }
+}
+
+impl SizeLimit {
+    /// Check the size of the archive or the extracted files (`what`) of a package, warning
+    /// about it or returning an error if it is too large
+    pub fn check(&self, package: &str, what: &str, bytes: u64) -> Result<(), String> {
+        if bytes <= self.bytes {
+            return Ok(());
+        }
+        let message = format!(
+            "The {} of {} is {}, more than the limit of {}",
+            what,
+            package,
+            format_size(bytes),
+            format_size(self.bytes)
+        );
+        if self.enforced {
+            Err(message)
+        } else {
+            diagnostics::warn(Warning::LargePackage, message);
+            Ok(())
+        }
+    }
+
+    /// The limits to extract a package with, stopping early when the limit is enforced
+    pub fn extraction_limits(&self) -> ExtractionLimits {
+        let limits = ExtractionLimits::default();
+        if self.enforced {
+            ExtractionLimits {
+                max_total_size: self.bytes.min(limits.max_total_size),
+                ..limits
+            }
+        } else {
+            limits
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sizes_are_parsed() {
+        assert_eq!(parse_size("100MB"), Ok(100 * 1024 * 1024));
+        assert_eq!(parse_size("512 kb"), Ok(512 * 1024));
+        assert_eq!(parse_size("2GB"), Ok(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size("1000"), Ok(1000));
+        assert!(parse_size("MB").is_err());
+        assert!(parse_size("10TB").is_err());
+        assert!(parse_size("-1MB").is_err());
+
+        let limit = SizeLimit {
+            bytes: 1024,
+            enforced: true,
+        };
+        assert!(limit.check("_/big@1.0.0", "archive", 1024).is_ok());
+        assert_eq!(
+            limit.check("_/big@1.0.0", "archive", 2048),
+            Err("The archive of _/big@1.0.0 is 2.0 KB, more than the limit of 1.0 KB".to_string())
+        );
+        assert_eq!(limit.extraction_limits().max_total_size, 1024);
+    }
+}
@@ -0,0 +1,227 @@
+//! What happens to the symlinks in a package when it is published, set with `symlinks` in the
+//! `[package]` section of the manifest.
+//!
+//! The files the manifest names, like module sources and readmes, are always published with the
+//! content they link to, so they only fail when their link is broken or links are rejected. Links
+//! in the directories of the package filesystem depend on the policy:
+//!
+//! - `rewrite`, the default: the link is replaced with the file it points to, which must be in the
+//!   package directory so files from elsewhere on the machine are not published by accident
+//! - `preserve`: the link is kept, so it must be relative and stay in the directory it is in
+//! - `reject`: links fail the publish
+//!
+//! Extracting a package never follows a link out of the package directory, see `archive`.
+
+use crate::data::manifest::{Manifest, SymlinkPolicy};
+use std::fs;
+use std::path::{Component, Path};
+
+#[derive(Debug, Fail)]
+pub enum SymlinkError {
+    #[fail(
+        display = "`{}` is a symlink, which `symlinks = \"reject\"` in the manifest doesn't allow",
+        _0
+    )]
+    Rejected(String),
+    #[fail(display = "`{}` is a broken symlink to `{}`", _0, _1)]
+    Dangling(String, String),
+    #[fail(
+        display = "`{}` links to `{}`, outside of the package directory. Copy the file into the package instead",
+        _0, _1
+    )]
+    LeavesPackage(String, String),
+    #[fail(
+        display = "`{}` links to `{}`, outside of the directory it is published in, so the link can't be kept. Use `symlinks = \"rewrite\"` to publish the file it links to",
+        _0, _1
+    )]
+    NotPreserved(String, String),
+}
+
+fn display(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Whether reaching `path` from `root` goes through a symlink, the file itself included
+fn goes_through_link(root: &Path, path: &Path) -> bool {
+    let relative = path.strip_prefix(root).unwrap_or(path);
+    let mut current = root.to_path_buf();
+    relative.components().any(|component| {
+        current.push(component);
+        fs::symlink_metadata(&current)
+            .map(|metadata| metadata.file_type().is_symlink())
+            .unwrap_or(false)
+    })
+}
+
+/// Check a file the manifest names, which is published with the content it links to
+fn check_file(root: &Path, path: &Path, policy: SymlinkPolicy) -> Result<(), SymlinkError> {
+    if !goes_through_link(root, path) {
+        return Ok(());
+    }
+    if policy == SymlinkPolicy::Reject {
+        return Err(SymlinkError::Rejected(display(root, path)));
+    }
+    if path.canonicalize().is_err() {
+        let link = fs::read_link(path).unwrap_or_default();
+        return Err(SymlinkError::Dangling(
+            display(root, path),
+            link.to_string_lossy().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether a relative link in `directory` stays inside `top`, without resolving other links
+fn stays_inside(top: &Path, directory: &Path, link: &Path) -> bool {
+    let mut resolved = directory.to_path_buf();
+    for component in link.components() {
+        match component {
+            Component::Normal(part) => resolved.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    return false;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    resolved.starts_with(top) && resolved != top
+}
+
+/// Check the links in a directory of the package filesystem, published as `top`
+fn check_directory(
+    root: &Path,
+    top: &Path,
+    directory: &Path,
+    policy: SymlinkPolicy,
+) -> Result<(), failure::Error> {
+    let canonical_root = root.canonicalize()?;
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_dir() {
+            check_directory(root, top, &path, policy)?;
+            continue;
+        }
+        if !metadata.file_type().is_symlink() {
+            continue;
+        }
+        let link = fs::read_link(&path)?;
+        let link_display = link.to_string_lossy().to_string();
+        match policy {
+            SymlinkPolicy::Reject => {
+                return Err(SymlinkError::Rejected(display(root, &path)).into())
+            }
+            SymlinkPolicy::Rewrite => match path.canonicalize() {
+                Ok(target) if target.starts_with(&canonical_root) => {}
+                Ok(_) => {
+                    return Err(
+                        SymlinkError::LeavesPackage(display(root, &path), link_display).into(),
+                    )
+                }
+                Err(_) => {
+                    return Err(SymlinkError::Dangling(display(root, &path), link_display).into())
+                }
+            },
+            SymlinkPolicy::Preserve => {
+                if !stays_inside(top, directory, &link) {
+                    return Err(
+                        SymlinkError::NotPreserved(display(root, &path), link_display).into(),
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that the symlinks of the package in the directory of `manifest` can be published with
+/// its policy
+pub fn check_package(manifest: &Manifest) -> Result<(), failure::Error> {
+    let root = &manifest.base_directory_path;
+    let policy = manifest.package.symlinks.unwrap_or_default();
+    let named_files = manifest
+        .module
+        .iter()
+        .flatten()
+        .map(|module| module.source.as_path())
+        .chain(manifest.package.all_readmes())
+        .chain(manifest.package.license_file.as_deref())
+        .chain(
+            manifest
+                .interface
+                .iter()
+                .flatten()
+                .map(|definition| definition.path.as_path()),
+        );
+    for file in named_files {
+        let path = root.join(file);
+        // missing files are reported by the checks of each kind of file
+        if fs::symlink_metadata(&path).is_ok() {
+            check_file(root, &path, policy)?;
+        }
+    }
+    for directory in manifest.fs.iter().flat_map(|fs| fs.values()) {
+        let top = root.join(directory);
+        if goes_through_link(root, &top) {
+            check_file(root, &top, policy)?;
+        }
+        if top.is_dir() {
+            check_directory(root, &top, &top, policy)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn links_are_checked_with_the_policy() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let root = tmp_dir.path().join("package");
+        let assets = root.join("assets");
+        fs::create_dir_all(assets.join("images")).unwrap();
+        fs::write(root.join("module.wasm"), b"").unwrap();
+        fs::write(assets.join("images/logo.png"), b"").unwrap();
+        fs::write(tmp_dir.path().join("secret.txt"), b"").unwrap();
+        symlink("module.wasm", root.join("linked.wasm")).unwrap();
+        symlink("missing.wasm", root.join("broken.wasm")).unwrap();
+
+        assert!(check_file(&root, &root.join("module.wasm"), SymlinkPolicy::Reject).is_ok());
+        assert!(check_file(&root, &root.join("linked.wasm"), SymlinkPolicy::Rewrite).is_ok());
+        assert!(matches!(
+            check_file(&root, &root.join("linked.wasm"), SymlinkPolicy::Reject),
+            Err(SymlinkError::Rejected(_))
+        ));
+        assert!(matches!(
+            check_file(&root, &root.join("broken.wasm"), SymlinkPolicy::Rewrite),
+            Err(SymlinkError::Dangling(..))
+        ));
+
+        let check = |policy| check_directory(&root, &assets, &assets, policy);
+        symlink("images/logo.png", assets.join("logo.png")).unwrap();
+        assert!(check(SymlinkPolicy::Rewrite).is_ok());
+        assert!(check(SymlinkPolicy::Preserve).is_ok());
+        assert!(check(SymlinkPolicy::Reject).is_err());
+
+        symlink("../module.wasm", assets.join("module.wasm")).unwrap();
+        assert!(check(SymlinkPolicy::Rewrite).is_ok());
+        assert!(check(SymlinkPolicy::Preserve)
+            .unwrap_err()
+            .downcast_ref::<SymlinkError>()
+            .is_some_and(|e| matches!(e, SymlinkError::NotPreserved(..))));
+
+        symlink("../../../secret.txt", assets.join("images/secret.txt")).unwrap();
+        assert!(check(SymlinkPolicy::Rewrite)
+            .unwrap_err()
+            .downcast_ref::<SymlinkError>()
+            .is_some_and(|e| matches!(e, SymlinkError::LeavesPackage(..))));
+    }
+}
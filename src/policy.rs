@@ -0,0 +1,280 @@
+//! Supply-chain policies restricting the packages that installs may fetch.
+//!
+//! An organization points `install.policy` in the wapm config, the `policy` of a config profile,
+//! or the `WAPM_POLICY` environment variable, at a policy file like:
+//!
+//! ```toml
+//! allowed-namespaces = ["_", "my-org"]
+//! require-signatures = true
+//! # in bytes
+//! max-package-size = 52428800
+//! denied-licenses = ["GPL-3.0", "AGPL-3.0"]
+//! minimum-package-age-days = 7
+//!
+//! # notified after publishing, unless the wapm config has a webhook
+//! [webhook]
+//! url = "https://hooks.slack.com/services/${SLACK_WEBHOOK_TOKEN}"
+//! format = "slack"
+//! ```
+//!
+//! Packages are checked against the metadata from the registry after resolving and before
+//! anything is downloaded. A package missing metadata that a rule needs violates that rule.
+
+use crate::config::{Config, Webhook};
+use crate::integrity_exceptions::{Check, IntegrityExceptions};
+use crate::registry::PackageVersion;
+use crate::util::{format_size, get_package_namespace_and_name};
+use chrono::{DateTime, Duration, Utc};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const POLICY_ENV_VAR: &str = "WAPM_POLICY";
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Policy {
+    /// The namespaces packages may come from, `_` for the global namespace. Any when not set.
+    #[serde(default)]
+    pub allowed_namespaces: Option<Vec<String>>,
+    #[serde(default)]
+    pub require_signatures: bool,
+    /// The greatest size of a package archive in bytes
+    #[serde(default)]
+    pub max_package_size: Option<u64>,
+    /// SPDX identifiers of licenses that packages may not use
+    #[serde(default)]
+    pub denied_licenses: Vec<String>,
+    /// How many days ago a version must have been published
+    #[serde(default)]
+    pub minimum_package_age_days: Option<u32>,
+    /// The webhook notified after publishing, used when the config has none
+    #[serde(default)]
+    pub webhook: Option<Webhook>,
+}
+
+#[derive(Debug, Fail)]
+pub enum PolicyError {
+    #[fail(display = "Could not read the install policy {}: {}", _0, _1)]
+    CouldNotRead(String, String),
+    #[fail(display = "Could not parse the install policy {}: {}", _0, _1)]
+    CouldNotParse(String, String),
+}
+
+/// A package version that a policy does not allow, and why
+#[derive(Debug, PartialEq)]
+pub struct PolicyViolation {
+    pub package: String,
+    pub reasons: Vec<String>,
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:", self.package)?;
+        for reason in self.reasons.iter() {
+            write!(f, "\n  - {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+impl Policy {
+    /// The policy from the environment or the config, if there is one
+    pub fn load() -> Result<Option<Self>, PolicyError> {
+        let path = env::var(POLICY_ENV_VAR)
+            .ok()
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                Config::from_file()
+                    .ok()
+                    .and_then(|config| config.policy_path())
+            });
+        match path {
+            Some(path) => Self::from_file(&path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, PolicyError> {
+        let display = path.display().to_string();
+        let source = fs::read_to_string(path)
+            .map_err(|e| PolicyError::CouldNotRead(display.clone(), e.to_string()))?;
+        toml::from_str(&source).map_err(|e| PolicyError::CouldNotParse(display, e.to_string()))
+    }
+
+    /// The rules of the policy that a package version breaks
+    pub fn check(&self, package: &PackageVersion, now: DateTime<Utc>) -> Option<PolicyViolation> {
+        let mut reasons = vec![];
+        if let Some(allowed_namespaces) = &self.allowed_namespaces {
+            let namespace = get_package_namespace_and_name(&package.name)
+                .map(|(namespace, _)| namespace)
+                .unwrap_or("_");
+            if !allowed_namespaces
+                .iter()
+                .any(|allowed| allowed == namespace)
+            {
+                reasons.push(format!("the namespace {} is not allowed", namespace));
+            }
+        }
+        if self.require_signatures && package.signature.is_none() {
+            reasons.push("it is not signed".to_string());
+        }
+        if let Some(max_package_size) = self.max_package_size {
+            match package.size {
+                Some(size) if size > max_package_size => reasons.push(format!(
+                    "its size of {} is over the limit of {}",
+                    format_size(size),
+                    format_size(max_package_size)
+                )),
+                Some(_) => {}
+                None => reasons.push("the registry did not report its size".to_string()),
+            }
+        }
+        if !self.denied_licenses.is_empty() {
+            match &package.license {
+                Some(license) => {
+                    if self
+                        .denied_licenses
+                        .iter()
+                        .any(|denied| license_mentions(license, denied))
+                    {
+                        reasons.push(format!("its license {} is denied", license));
+                    }
+                }
+                None => reasons.push("the registry did not report its license".to_string()),
+            }
+        }
+        if let Some(days) = self.minimum_package_age_days {
+            match package.published_at {
+                Some(published_at) if now - published_at < Duration::days(days.into()) => reasons
+                    .push(format!(
+                        "it was published on {}, less than {} days ago",
+                        published_at.format("%Y-%m-%d"),
+                        days
+                    )),
+                Some(_) => {}
+                None => {
+                    reasons.push("the registry did not report when it was published".to_string())
+                }
+            }
+        }
+        if reasons.is_empty() {
+            None
+        } else {
+            Some(PolicyViolation {
+                package: format!("{}@{}", package.name, package.version),
+                reasons,
+            })
+        }
+    }
+
+    /// The rules of the policy that a package version breaks, unless an integrity exception lets
+    /// it through
+    pub fn check_with_exceptions(
+        &self,
+        package: &PackageVersion,
+        now: DateTime<Utc>,
+        exceptions: &IntegrityExceptions,
+    ) -> Option<PolicyViolation> {
+        self.check(package, now).filter(|violation| {
+            !exceptions.excuses(
+                &package.name,
+                &package.version,
+                Check::Policy,
+                &format!(
+                    "breaks the install policy ({})",
+                    violation.reasons.join(", ")
+                ),
+            )
+        })
+    }
+
+    /// The rules of the policy that the package versions break, except for the versions that
+    /// integrity exceptions let through
+    pub fn check_all<'a, I>(
+        &self,
+        packages: I,
+        exceptions: &IntegrityExceptions,
+    ) -> Vec<PolicyViolation>
+    where
+        I: IntoIterator<Item = &'a PackageVersion>,
+    {
+        let now = Utc::now();
+        packages
+            .into_iter()
+            .filter_map(|package| self.check_with_exceptions(package, now, exceptions))
+            .collect()
+    }
+}
+
+/// Whether a license expression like `MIT OR Apache-2.0` mentions a license
+fn license_mentions(expression: &str, license: &str) -> bool {
+    expression
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+        .any(|part| part.eq_ignore_ascii_case(license))
+}
+
+/// Describe the packages that a policy does not allow
+pub fn violation_report(violations: &[PolicyViolation]) -> String {
+    violations
+        .iter()
+        .map(|violation| violation.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn date(date: &str) -> DateTime<Utc> {
+        format!("{}T00:00:00Z", date).parse().unwrap()
+    }
+
+    #[test]
+    fn packages_are_checked_against_the_policy() {
+        let policy: Policy = toml::from_str(
+            r#"
+allowed-namespaces = ["_"]
+max-package-size = 1000
+denied-licenses = ["GPL-3.0"]
+minimum-package-age-days = 7
+"#,
+        )
+        .unwrap();
+        let now = date("2020-03-10");
+        let package = PackageVersion {
+            name: "_/sqlite".to_string(),
+            version: "0.1.1".to_string(),
+            manifest: None,
+            download_url: String::new(),
+            signature: None,
+            license: Some("MIT OR Apache-2.0".to_string()),
+            size: Some(1000),
+            published_at: Some(date("2020-03-01")),
+            commands: None,
+        };
+        assert_eq!(policy.check(&package, now), None);
+
+        let package = PackageVersion {
+            name: "someone/sqlite".to_string(),
+            license: Some("(GPL-3.0)".to_string()),
+            size: None,
+            published_at: Some(date("2020-03-09")),
+            ..package
+        };
+        let violation = policy.check(&package, now).unwrap();
+        assert_eq!(violation.package, "someone/sqlite@0.1.1");
+        assert_eq!(
+            violation.reasons,
+            vec![
+                "the namespace someone is not allowed",
+                "the registry did not report its size",
+                "its license (GPL-3.0) is denied",
+                "it was published on 2020-03-09, less than 7 days ago",
+            ]
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Checks that the files of a package can be extracted on every platform, before it is published.
+//!
+//! Files whose paths differ only by case or unicode normalization, like `Readme.md` and
+//! `README.md`, overwrite each other on the filesystems of macOS and Windows, so they fail the
+//! publish. Names Windows can't create, like `CON`, `nul.txt` or `a?.txt`, paths too long for
+//! Windows and names that are not in the NFC unicode form are reported as `non-portable-path`
+//! warnings.
+//!
+//! Packages are extracted with NFC names whatever form they were published in, macOS giving the
+//! decomposed NFD form to the files it lists, and on Windows with the long path prefix so paths
+//! over 260 characters can be written.
+
+use crate::diagnostics::{self, Warning};
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+/// The names Windows reserves for devices, whatever their case and extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// The characters Windows doesn't allow in file names, besides control characters
+const FORBIDDEN_CHARACTERS: &[char] = &['<', '>', ':', '"', '|', '?', '*', '\\'];
+
+/// The longest path in a package that fits in the 260 characters of MAX_PATH on Windows once it
+/// is in a packages directory
+const MAX_PORTABLE_PATH_LENGTH: usize = 160;
+
+#[derive(Debug, Fail)]
+pub enum PortablePathError {
+    #[fail(
+        display = "The package has both `{}` and `{}`, which are the same file on the filesystems of macOS and Windows. Rename one of them",
+        _0, _1
+    )]
+    CaseCollision(String, String),
+}
+
+/// Whether a file or directory name is reserved on Windows
+fn is_reserved_name(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+}
+
+/// Why a path can't be created as it is on some platforms, if it can't
+fn path_problem(path: &str) -> Option<String> {
+    if path.chars().count() > MAX_PORTABLE_PATH_LENGTH {
+        return Some(format!(
+            "is longer than {} characters, which Windows tools without long path support can't open once installed",
+            MAX_PORTABLE_PATH_LENGTH
+        ));
+    }
+    for name in path.split('/') {
+        if is_reserved_name(name) {
+            return Some("has a name Windows reserves for devices".to_string());
+        }
+        if name
+            .chars()
+            .any(|c| c.is_control() || FORBIDDEN_CHARACTERS.contains(&c))
+        {
+            return Some("has a character Windows doesn't allow in file names".to_string());
+        }
+        if name.ends_with('.') || name.ends_with(' ') {
+            return Some(
+                "has a name ending with a dot or a space, which Windows drops".to_string(),
+            );
+        }
+    }
+    if !is_nfc(path) {
+        return Some(format!(
+            "is not in the NFC unicode form, it is installed as `{}`",
+            path.nfc().collect::<String>()
+        ));
+    }
+    None
+}
+
+/// The pairs of paths that only differ by case or unicode normalization
+fn case_collisions(paths: &[String]) -> Vec<(String, String)> {
+    let mut seen: BTreeMap<String, &String> = BTreeMap::new();
+    let mut collisions = vec![];
+    for path in paths {
+        let folded = path.nfc().collect::<String>().to_lowercase();
+        if let Some(other) = seen.insert(folded, path) {
+            if other != path {
+                collisions.push((other.clone(), path.clone()));
+            }
+        }
+    }
+    collisions
+}
+
+/// A file name in the NFC unicode form, names that are not unicode are kept as they are
+pub fn nfc_name(name: &OsStr) -> OsString {
+    match name.to_str() {
+        Some(name) if !is_nfc(name) => name.nfc().collect::<String>().into(),
+        _ => name.to_os_string(),
+    }
+}
+
+/// An absolute path with the `\\?\` prefix that lifts the 260 characters limit of Windows
+#[cfg(windows)]
+pub fn long_path(path: &Path) -> PathBuf {
+    let text = path.to_string_lossy();
+    if !path.is_absolute() || text.starts_with(r"\\?\") {
+        return path.to_path_buf();
+    }
+    // the prefix turns off the parsing of `/`, so the path must only use `\`
+    let text = text.replace('/', r"\");
+    match text.strip_prefix(r"\\") {
+        Some(share) => PathBuf::from(format!(r"\\?\UNC\{}", share)),
+        None => PathBuf::from(format!(r"\\?\{}", text)),
+    }
+}
+
+/// An absolute path with the `\\?\` prefix that lifts the 260 characters limit of Windows
+#[cfg(not(windows))]
+pub fn long_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
+
+/// The paths of the files in an uncompressed package archive
+fn archive_files(tar_data: &[u8]) -> Result<Vec<String>, failure::Error> {
+    let mut archive = tar::Archive::new(tar_data);
+    let mut files = vec![];
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_dir() {
+            continue;
+        }
+        files.push(entry.path()?.to_string_lossy().replace('\\', "/"));
+    }
+    Ok(files)
+}
+
+/// Check that the files of a package archive can be extracted on every platform
+pub fn check_archive(tar_data: &[u8]) -> Result<(), failure::Error> {
+    let files = archive_files(tar_data)?;
+    if let Some((first, second)) = case_collisions(&files).into_iter().next() {
+        return Err(PortablePathError::CaseCollision(first, second).into());
+    }
+    for path in files.iter() {
+        if let Some(problem) = path_problem(path) {
+            diagnostics::warn(
+                Warning::NonPortablePath,
+                format_args!("`{}` {}", path, problem),
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn paths_that_break_other_platforms_are_found() {
+        let paths: Vec<String> = [
+            "wapm.toml",
+            "README.md",
+            "docs/Readme.md",
+            "Readme.md",
+            "static/con.txt",
+            "aux/index.html",
+            "console.log",
+            "nul",
+            "caf\u{e9}.txt",
+            "cafe\u{301}.txt",
+            "what?.txt",
+            "notes.",
+        ]
+        .iter()
+        .map(|path| path.to_string())
+        .collect();
+        assert_eq!(
+            case_collisions(&paths),
+            [
+                ("README.md".to_string(), "Readme.md".to_string()),
+                ("caf\u{e9}.txt".to_string(), "cafe\u{301}.txt".to_string())
+            ]
+        );
+        let with_problems: Vec<&str> = paths
+            .iter()
+            .filter(|path| path_problem(path).is_some())
+            .map(String::as_str)
+            .collect();
+        assert_eq!(
+            with_problems,
+            [
+                "static/con.txt",
+                "aux/index.html",
+                "nul",
+                "cafe\u{301}.txt",
+                "what?.txt",
+                "notes."
+            ]
+        );
+        assert!(path_problem(&"a/".repeat(100)).is_some());
+        assert!(case_collisions(&["README.md".to_string(), "README.md".to_string()]).is_empty());
+        assert_eq!(
+            nfc_name(OsStr::new("cafe\u{301}.txt")),
+            OsString::from("caf\u{e9}.txt")
+        );
+    }
+}
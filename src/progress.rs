@@ -0,0 +1,174 @@
+//! Machine readable progress of long running operations like installs, so GUIs and editor
+//! extensions can render their own progress.
+//!
+//! With `--progress json` every event is written as a line of JSON, to stderr or to the file or
+//! named pipe given with `--progress-output`:
+//!
+//! ```json
+//! {"event":"package_downloaded","name":"_/sqlite","version":"0.1.1","bytes":1234567}
+//! ```
+
+use lazy_static::lazy_static;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProgressFormat {
+    Json,
+}
+
+impl FromStr for ProgressFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ProgressFormat::Json),
+            _ => Err(format!(
+                "unsupported progress format \"{}\", use \"json\"",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    /// The registry is being queried for the versions of the packages
+    ResolveStarted {
+        packages: Vec<String>,
+    },
+    ResolveFinished,
+    PackageDownloaded {
+        name: String,
+        version: String,
+        bytes: u64,
+    },
+    PackageExtracted {
+        name: String,
+        version: String,
+    },
+    /// The package was already on disk, e.g. hoisted into the root of a workspace
+    PackageReused {
+        name: String,
+        version: String,
+    },
+    /// Modules of the package were identical to modules of other packages and are stored once
+    ModulesDeduplicated {
+        name: String,
+        version: String,
+        modules: usize,
+        bytes_saved: u64,
+    },
+    /// The package was added to the lockfile and its commands are available
+    PackageLinked {
+        name: String,
+        version: String,
+    },
+    Done,
+    Failed {
+        error: String,
+    },
+}
+
+/// Totals of the events reported so far, whether or not progress reporting is enabled
+#[derive(Clone, Debug, Default)]
+pub struct ProgressStats {
+    pub resolve_time: Duration,
+    pub bytes_downloaded: u64,
+    pub packages_downloaded: usize,
+    pub packages_reused: usize,
+    pub modules_deduplicated: usize,
+    pub bytes_deduplicated: u64,
+}
+
+lazy_static! {
+    static ref PROGRESS_OUTPUT: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+    static ref PROGRESS_STATS: Mutex<(ProgressStats, Option<Instant>)> =
+        Mutex::new((ProgressStats::default(), None));
+}
+
+/// Start reporting progress events to stderr, or to a file or named pipe
+pub fn enable(output: Option<&Path>) -> io::Result<()> {
+    let writer: Box<dyn Write + Send> = match output {
+        Some(path) => Box::new(OpenOptions::new().create(true).append(true).open(path)?),
+        None => Box::new(io::stderr()),
+    };
+    *PROGRESS_OUTPUT.lock().unwrap() = Some(writer);
+    Ok(())
+}
+
+/// The totals of the events reported so far
+pub fn stats() -> ProgressStats {
+    PROGRESS_STATS.lock().unwrap().0.clone()
+}
+
+fn record(event: &ProgressEvent) {
+    let mut guard = PROGRESS_STATS.lock().unwrap();
+    let (stats, resolve_started) = &mut *guard;
+    match event {
+        ProgressEvent::ResolveStarted { .. } => *resolve_started = Some(Instant::now()),
+        ProgressEvent::ResolveFinished => {
+            if let Some(started) = resolve_started.take() {
+                stats.resolve_time += started.elapsed();
+            }
+        }
+        ProgressEvent::PackageDownloaded { bytes, .. } => {
+            stats.bytes_downloaded += bytes;
+            stats.packages_downloaded += 1;
+        }
+        ProgressEvent::PackageReused { .. } => stats.packages_reused += 1,
+        ProgressEvent::ModulesDeduplicated {
+            modules,
+            bytes_saved,
+            ..
+        } => {
+            stats.modules_deduplicated += modules;
+            stats.bytes_deduplicated += bytes_saved;
+        }
+        _ => {}
+    }
+}
+
+/// Report an event, if progress reporting is enabled
+pub fn emit(event: ProgressEvent) {
+    record(&event);
+    let mut guard = PROGRESS_OUTPUT.lock().unwrap();
+    if let Some(output) = guard.as_mut() {
+        let line = serde_json::to_string(&event).expect("progress events serialize to JSON");
+        // progress is best effort, e.g. the reader of a named pipe may have gone away
+        if writeln!(output, "{}", line)
+            .and_then(|_| output.flush())
+            .is_err()
+        {
+            debug!("Could not report progress event {}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn events_are_tagged_json() {
+        let event = ProgressEvent::PackageDownloaded {
+            name: "_/sqlite".to_string(),
+            version: "0.1.1".to_string(),
+            bytes: 42,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"package_downloaded","name":"_/sqlite","version":"0.1.1","bytes":42}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&ProgressEvent::Done).unwrap(),
+            r#"{"event":"done"}"#
+        );
+        assert!("xml".parse::<ProgressFormat>().is_err());
+    }
+}
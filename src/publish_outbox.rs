@@ -0,0 +1,149 @@
+//! Publishes that fail after the package archive was built, e.g. during a network outage, are
+//! kept in an outbox in the wapm directory. `wapm publish --resume` retries their uploads without
+//! building the packages again.
+
+use crate::util::sha256_hex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const ARCHIVE_FILE_NAME: &str = "package.tar.gz";
+const METADATA_FILE_NAME: &str = "publish.json";
+
+/// Everything sent to the registry when publishing, apart from the archive
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct PreparedPublish {
+    /// The url of the registry the package is published to
+    pub registry: String,
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    pub manifest: String,
+    pub license: Option<String>,
+    pub license_file: Option<String>,
+    pub readme: Option<String>,
+    pub repository: Option<String>,
+    pub homepage: Option<String>,
+    pub signature: Option<PreparedSignature>,
+    /// Identifies the publish to the registry, so submitting it twice publishes it once
+    pub idempotency_key: String,
+    /// The file name the archive is uploaded with, which tells the registry its format
+    #[serde(default = "default_archive_name")]
+    pub archive_name: String,
+}
+
+fn default_archive_name() -> String {
+    ARCHIVE_FILE_NAME.to_string()
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct PreparedSignature {
+    pub public_key_id: String,
+    pub data: String,
+}
+
+/// A publish waiting in the outbox
+#[derive(Debug)]
+pub struct OutboxEntry {
+    directory: PathBuf,
+    pub publish: PreparedPublish,
+}
+
+impl OutboxEntry {
+    pub fn archive_path(&self) -> PathBuf {
+        self.directory.join(ARCHIVE_FILE_NAME)
+    }
+
+    /// Take the publish out of the outbox once it is done
+    pub fn remove(self) -> Result<(), failure::Error> {
+        fs::remove_dir_all(&self.directory)?;
+        Ok(())
+    }
+}
+
+/// The idempotency key of publishing an archive as a package version
+pub fn idempotency_key(name: &str, version: &str, archive: &[u8]) -> String {
+    let mut data = format!("{}@{}\n", name, version).into_bytes();
+    data.extend_from_slice(archive);
+    sha256_hex(&data)
+}
+
+/// Keep a publish and a copy of its archive in the outbox
+pub fn save(
+    outbox_directory: &Path,
+    publish: &PreparedPublish,
+    archive_path: &Path,
+) -> Result<(), failure::Error> {
+    let directory = outbox_directory.join(&publish.idempotency_key);
+    fs::create_dir_all(&directory)?;
+    fs::copy(archive_path, directory.join(ARCHIVE_FILE_NAME))?;
+    fs::write(
+        directory.join(METADATA_FILE_NAME),
+        serde_json::to_string_pretty(publish)?,
+    )?;
+    Ok(())
+}
+
+/// The publishes in the outbox, oldest first
+pub fn entries(outbox_directory: &Path) -> Result<Vec<OutboxEntry>, failure::Error> {
+    if !outbox_directory.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut entries = vec![];
+    for dir_entry in fs::read_dir(outbox_directory)? {
+        let directory = dir_entry?.path();
+        let metadata_path = directory.join(METADATA_FILE_NAME);
+        // skip publishes that were interrupted while being saved
+        if !metadata_path.is_file() || !directory.join(ARCHIVE_FILE_NAME).is_file() {
+            continue;
+        }
+        let publish: PreparedPublish = serde_json::from_str(&fs::read_to_string(&metadata_path)?)?;
+        let saved_at = fs::metadata(&metadata_path)?.modified()?;
+        entries.push((saved_at, OutboxEntry { directory, publish }));
+    }
+    entries.sort_by_key(|(saved_at, _)| *saved_at);
+    Ok(entries.into_iter().map(|(_, entry)| entry).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn save_and_resume_publishes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let archive_path = dir.path().join("archive.tar.gz");
+        fs::write(&archive_path, b"archive").unwrap();
+        let outbox = dir.path().join("outbox");
+        let publish = PreparedPublish {
+            registry: "https://registry.wapm.io".to_string(),
+            name: "_/hello".to_string(),
+            version: "1.0.0".to_string(),
+            description: "Hello".to_string(),
+            manifest: "[package]".to_string(),
+            license: None,
+            license_file: None,
+            readme: None,
+            repository: None,
+            homepage: None,
+            signature: None,
+            idempotency_key: idempotency_key("_/hello", "1.0.0", b"archive"),
+            archive_name: default_archive_name(),
+        };
+        assert_ne!(
+            publish.idempotency_key,
+            idempotency_key("_/hello", "1.0.1", b"archive")
+        );
+
+        assert!(entries(&outbox).unwrap().is_empty());
+        save(&outbox, &publish, &archive_path).unwrap();
+        // saving the same publish again doesn't queue it twice
+        save(&outbox, &publish, &archive_path).unwrap();
+        let mut saved = entries(&outbox).unwrap();
+        assert_eq!(saved.len(), 1);
+        let entry = saved.remove(0);
+        assert_eq!(entry.publish, publish);
+        assert_eq!(fs::read(entry.archive_path()).unwrap(), b"archive");
+        entry.remove().unwrap();
+        assert!(entries(&outbox).unwrap().is_empty());
+    }
+}
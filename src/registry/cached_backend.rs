@@ -0,0 +1,302 @@
+//! A cache of the versions the registry has for the dependencies of a project, so the commands
+//! run one after another on a project, like `wapm install` and then `wapm outdated`, do not ask
+//! the registry for the same packages again.
+//!
+//! The cache is kept in the packages directory and is keyed by the hash of the manifest and of
+//! the registry settings: editing `wapm.toml` or switching registries drops it. It is also dropped
+//! once it is older than a few minutes, so new releases still show up within a session.
+
+use crate::config::Config;
+use crate::data::manifest::MANIFEST_FILE_NAME;
+use crate::keys::WapmPackageSignature;
+use crate::registry::{PackageVersion, RegistryBackend};
+use crate::util::{get_packages_dir, sha256_hex};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CACHE_FILE_NAME: &str = ".resolution-cache.json";
+/// Cached versions older than this are looked up again
+const MAX_AGE_IN_MINUTES: i64 = 10;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct ResolutionCache {
+    key: String,
+    created_at: Option<DateTime<Utc>>,
+    /// The versions of every package looked up, empty for packages the registry does not have
+    packages: BTreeMap<String, Vec<CachedVersion>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedVersion {
+    version: String,
+    manifest: Option<String>,
+    download_url: String,
+    signature: Option<CachedSignature>,
+    license: Option<String>,
+    size: Option<u64>,
+    published_at: Option<DateTime<Utc>>,
+    commands: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedSignature {
+    public_key_id: String,
+    public_key: String,
+    signature_data: String,
+    /// Seconds and nanoseconds since the epoch
+    date_created: (i64, i32),
+    revoked: bool,
+    owner: String,
+}
+
+impl CachedVersion {
+    fn new(package_version: &PackageVersion) -> Self {
+        CachedVersion {
+            version: package_version.version.clone(),
+            manifest: package_version.manifest.clone(),
+            download_url: package_version.download_url.clone(),
+            signature: package_version
+                .signature
+                .as_ref()
+                .map(|signature| CachedSignature {
+                    public_key_id: signature.public_key_id.clone(),
+                    public_key: signature.public_key.clone(),
+                    signature_data: signature.signature_data.clone(),
+                    date_created: (signature.date_created.sec, signature.date_created.nsec),
+                    revoked: signature.revoked,
+                    owner: signature.owner.clone(),
+                }),
+            license: package_version.license.clone(),
+            size: package_version.size,
+            published_at: package_version.published_at,
+            commands: package_version.commands.clone(),
+        }
+    }
+
+    fn package_version(&self, name: &str) -> PackageVersion {
+        PackageVersion {
+            name: name.to_string(),
+            version: self.version.clone(),
+            manifest: self.manifest.clone(),
+            download_url: self.download_url.clone(),
+            signature: self
+                .signature
+                .as_ref()
+                .map(|signature| WapmPackageSignature {
+                    public_key_id: signature.public_key_id.clone(),
+                    public_key: signature.public_key.clone(),
+                    signature_data: signature.signature_data.clone(),
+                    date_created: time::Timespec::new(
+                        signature.date_created.0,
+                        signature.date_created.1,
+                    ),
+                    revoked: signature.revoked,
+                    owner: signature.owner.clone(),
+                }),
+            license: self.license.clone(),
+            size: self.size,
+            published_at: self.published_at,
+            commands: self.commands.clone(),
+        }
+    }
+}
+
+impl ResolutionCache {
+    /// The cache at the path if it has the key and is fresh, otherwise an empty cache for the key
+    fn load(path: &Path, key: &str, now: DateTime<Utc>) -> Self {
+        let cache = fs::read(path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<ResolutionCache>(&data).ok());
+        match cache {
+            Some(cache)
+                if cache.key == key
+                    && cache.created_at.is_some_and(|created_at| {
+                        now - created_at < Duration::minutes(MAX_AGE_IN_MINUTES)
+                    }) =>
+            {
+                cache
+            }
+            _ => ResolutionCache {
+                key: key.to_string(),
+                created_at: Some(now),
+                packages: BTreeMap::new(),
+            },
+        }
+    }
+
+    /// Save the cache if the packages directory exists, looking up versions does not create it
+    fn save(&self, path: &Path) {
+        if !path.parent().is_some_and(Path::is_dir) {
+            return;
+        }
+        if let Err(e) = fs::write(path, serde_json::to_vec(self).unwrap_or_default()) {
+            debug!("Could not save the resolution cache: {}", e);
+        }
+    }
+}
+
+/// Looks up the versions of packages in the cache of the project before asking the registry
+pub struct CachedBackend {
+    inner: Box<dyn RegistryBackend>,
+    path: PathBuf,
+    key: String,
+}
+
+impl CachedBackend {
+    /// Wrap the backend with the cache of the project in the directory. Directories without a
+    /// manifest, like for global installs, get the backend as it is.
+    pub fn wrap(
+        inner: Box<dyn RegistryBackend>,
+        directory: &Path,
+        config: &Config,
+    ) -> Box<dyn RegistryBackend> {
+        match fs::read(directory.join(MANIFEST_FILE_NAME)) {
+            Ok(manifest) => Box::new(CachedBackend {
+                inner,
+                path: get_packages_dir(directory).join(CACHE_FILE_NAME),
+                key: cache_key(&manifest, config),
+            }),
+            Err(_) => inner,
+        }
+    }
+}
+
+/// The hash of the manifest and of the registry settings that change what lookups return
+fn cache_key(manifest: &[u8], config: &Config) -> String {
+    let mut data = manifest.to_vec();
+    data.extend(
+        format!(
+            "\n{}\n{:?}\n{}",
+            config.registry.url,
+            config.registry.backend_kind(),
+            config.index.enabled
+        )
+        .bytes(),
+    );
+    sha256_hex(&data)
+}
+
+impl RegistryBackend for CachedBackend {
+    fn package_versions(&self, names: &[String]) -> Result<Vec<PackageVersion>, failure::Error> {
+        let mut cache = ResolutionCache::load(&self.path, &self.key, Utc::now());
+        let missing: Vec<String> = names
+            .iter()
+            .filter(|name| !cache.packages.contains_key(*name))
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            let fetched = self.inner.package_versions(&missing)?;
+            for name in &missing {
+                cache.packages.insert(name.clone(), vec![]);
+            }
+            for package_version in &fetched {
+                cache
+                    .packages
+                    .entry(package_version.name.clone())
+                    .or_default()
+                    .push(CachedVersion::new(package_version));
+            }
+            cache.save(&self.path);
+        } else {
+            debug!(
+                "Resolved {} packages from the resolution cache",
+                names.len()
+            );
+        }
+        Ok(names
+            .iter()
+            .flat_map(|name| {
+                cache
+                    .packages
+                    .get(name)
+                    .into_iter()
+                    .flatten()
+                    .map(move |cached| cached.package_version(name))
+            })
+            .collect())
+    }
+
+    fn package_version(
+        &self,
+        name: &str,
+        version: Option<&str>,
+    ) -> Result<Option<PackageVersion>, failure::Error> {
+        self.inner.package_version(name, version)
+    }
+
+    fn prefetch_package_versions(
+        &self,
+        lookups: &[(String, Option<String>)],
+    ) -> Result<(), failure::Error> {
+        self.inner.prefetch_package_versions(lookups)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// Records the names looked up in the registry
+    struct CountingBackend(Rc<RefCell<Vec<String>>>);
+
+    impl RegistryBackend for CountingBackend {
+        fn package_versions(
+            &self,
+            names: &[String],
+        ) -> Result<Vec<PackageVersion>, failure::Error> {
+            self.0.borrow_mut().extend(names.iter().cloned());
+            Ok(names
+                .iter()
+                .filter(|name| *name != "_/missing")
+                .map(|name| PackageVersion {
+                    name: name.clone(),
+                    version: "1.0.0".to_string(),
+                    manifest: None,
+                    download_url: format!("https://example.com/{}.tar.gz", name),
+                    signature: None,
+                    license: None,
+                    size: Some(10),
+                    published_at: None,
+                    commands: None,
+                })
+                .collect())
+        }
+
+        fn package_version(
+            &self,
+            _name: &str,
+            _version: Option<&str>,
+        ) -> Result<Option<PackageVersion>, failure::Error> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn lookups_are_cached_until_the_key_changes() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let lookups = Rc::new(RefCell::new(vec![]));
+        let backend = |key: &str| CachedBackend {
+            inner: Box::new(CountingBackend(lookups.clone())),
+            path: tmp_dir.path().join(CACHE_FILE_NAME),
+            key: key.to_string(),
+        };
+        let names = vec!["_/sqlite".to_string(), "_/missing".to_string()];
+
+        assert_eq!(backend("a").package_versions(&names).unwrap().len(), 1);
+        let versions = backend("a").package_versions(&names).unwrap();
+        assert_eq!(versions[0].size, Some(10));
+        assert_eq!(lookups.borrow().len(), 2);
+
+        // only the packages not looked up before reach the registry
+        let more = vec!["_/sqlite".to_string(), "_/lua".to_string()];
+        assert_eq!(backend("a").package_versions(&more).unwrap().len(), 2);
+        assert_eq!(lookups.borrow()[2..], ["_/lua".to_string()]);
+
+        backend("b").package_versions(&names).unwrap();
+        assert_eq!(lookups.borrow().len(), 5);
+    }
+}
@@ -0,0 +1,304 @@
+use crate::constants::RFC3339_FORMAT_STRING_WITH_TIMEZONE;
+use crate::graphql::{execute_query, execute_raw_query, DateTime};
+use crate::keys;
+use crate::registry::{PackageVersion, RegistryBackend};
+use chrono::Utc;
+use graphql_client::*;
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// How many lookups are sent in one batched query
+const BATCH_SIZE: usize = 50;
+
+type Lookup = (String, Option<String>);
+
+lazy_static! {
+    /// Lookups already answered by the registry, a package is often looked up more than once
+    /// while installing
+    static ref PACKAGE_VERSION_CACHE: Mutex<HashMap<Lookup, Option<PackageVersion>>> =
+        Mutex::new(HashMap::new());
+}
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/queries/get_packages.graphql",
+    response_derives = "Debug"
+)]
+struct GetPackagesQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/queries/get_package.graphql",
+    response_derives = "Debug"
+)]
+struct GetPackageQuery;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/queries/get_package_version.graphql",
+    response_derives = "Debug"
+)]
+struct GetPackageVersionQuery;
+
+/// The GraphQL API of a wapm registry server
+pub struct GraphQLBackend;
+
+impl RegistryBackend for GraphQLBackend {
+    fn package_versions(&self, names: &[String]) -> Result<Vec<PackageVersion>, failure::Error> {
+        let q = GetPackagesQuery::build_query(get_packages_query::Variables {
+            names: names.to_vec(),
+        });
+        let response: get_packages_query::ResponseData = execute_query(&q)?;
+        Ok(response
+            .package
+            .into_iter()
+            .flatten()
+            .flat_map(|p| {
+                let name = p.name;
+                // only the last version comes with its manifest, it says whether the package moved
+                let last_version = p.last_version;
+                p.versions
+                    .unwrap_or_default()
+                    .into_iter()
+                    .flatten()
+                    .map(|v| PackageVersion {
+                        name: name.clone(),
+                        manifest: last_version
+                            .as_ref()
+                            .filter(|last_version| last_version.version == v.version)
+                            .map(|last_version| last_version.manifest.clone()),
+                        version: v.version,
+                        download_url: v.distribution.download_url,
+                        license: v.license,
+                        size: Some(v.file_size as u64),
+                        commands: Some(
+                            v.commands
+                                .into_iter()
+                                .map(|command| command.command)
+                                .collect(),
+                        ),
+                        published_at: chrono::DateTime::parse_from_rfc3339(&v.created_at)
+                            .ok()
+                            .map(|date| date.with_timezone(&Utc)),
+                        signature: v.signature.map(|gq_sig| keys::WapmPackageSignature {
+                            public_key_id: gq_sig.public_key.key_id,
+                            public_key: gq_sig.public_key.key,
+                            signature_data: gq_sig.data,
+                            date_created: {
+                                time::strptime(
+                                    &gq_sig.created_at,
+                                    RFC3339_FORMAT_STRING_WITH_TIMEZONE,
+                                )
+                                .unwrap_or_else(|err| {
+                                    panic!("Failed to parse time string: {}", err)
+                                })
+                                .to_timespec()
+                            },
+                            revoked: gq_sig.public_key.revoked,
+                            owner: gq_sig.public_key.owner.username,
+                        }),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect())
+    }
+
+    fn package_version(
+        &self,
+        name: &str,
+        version: Option<&str>,
+    ) -> Result<Option<PackageVersion>, failure::Error> {
+        let lookup = (name.to_string(), version.map(str::to_owned));
+        if let Some(cached) = PACKAGE_VERSION_CACHE.lock().unwrap().get(&lookup) {
+            return Ok(cached.clone());
+        }
+        let package_version = Self::fetch_package_version(name, version)?;
+        PACKAGE_VERSION_CACHE
+            .lock()
+            .unwrap()
+            .insert(lookup, package_version.clone());
+        Ok(package_version)
+    }
+
+    fn prefetch_package_versions(&self, lookups: &[Lookup]) -> Result<(), failure::Error> {
+        let missing: Vec<Lookup> = {
+            let cache = PACKAGE_VERSION_CACHE.lock().unwrap();
+            let mut missing: Vec<Lookup> = lookups
+                .iter()
+                .filter(|lookup| !cache.contains_key(lookup))
+                .cloned()
+                .collect();
+            missing.sort();
+            missing.dedup();
+            missing
+        };
+        for batch in missing.chunks(BATCH_SIZE) {
+            let (query, variables) = batched_query(batch);
+            let response: HashMap<String, serde_json::Value> =
+                execute_raw_query(&query, BATCHED_OPERATION_NAME, &variables)?;
+            let mut cache = PACKAGE_VERSION_CACHE.lock().unwrap();
+            for (index, (name, version)) in batch.iter().enumerate() {
+                let value = response
+                    .get(&format!("lookup{}", index))
+                    .cloned()
+                    .unwrap_or(serde_json::Value::Null);
+                let package_version = if version.is_some() {
+                    serde_json::from_value::<Option<BatchedVersion>>(value)?
+                        .map(|found| found.into_package_version(name.clone()))
+                } else {
+                    serde_json::from_value::<Option<BatchedPackage>>(value)?.and_then(|package| {
+                        let name = package.name;
+                        package
+                            .last_version
+                            .map(|last_version| last_version.into_package_version(name))
+                    })
+                };
+                cache.insert((name.clone(), version.clone()), package_version);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl GraphQLBackend {
+    fn fetch_package_version(
+        name: &str,
+        version: Option<&str>,
+    ) -> Result<Option<PackageVersion>, failure::Error> {
+        if let Some(version) = version {
+            let q = GetPackageVersionQuery::build_query(get_package_version_query::Variables {
+                name: name.to_string(),
+                version: Some(version.to_string()),
+            });
+            let response: get_package_version_query::ResponseData = execute_query(&q)?;
+            Ok(response.package_version.map(|pv| PackageVersion {
+                name: name.to_string(),
+                version: pv.version,
+                manifest: Some(pv.manifest),
+                download_url: pv.distribution.download_url,
+                signature: None,
+                license: None,
+                size: None,
+                published_at: None,
+                commands: None,
+            }))
+        } else {
+            let q = GetPackageQuery::build_query(get_package_query::Variables {
+                name: name.to_string(),
+            });
+            let response: get_package_query::ResponseData = execute_query(&q)?;
+            Ok(response.package.and_then(|package| {
+                let name = package.name;
+                package.last_version.map(|last_version| PackageVersion {
+                    name,
+                    version: last_version.version,
+                    manifest: Some(last_version.manifest),
+                    download_url: last_version.distribution.download_url,
+                    signature: None,
+                    license: None,
+                    size: None,
+                    published_at: None,
+                    commands: None,
+                })
+            }))
+        }
+    }
+}
+
+const BATCHED_OPERATION_NAME: &str = "BatchedPackageVersionsQuery";
+
+const BATCHED_VERSION_FIELDS: &str = "version manifest distribution { downloadUrl }";
+
+#[derive(Deserialize)]
+struct BatchedVersion {
+    version: String,
+    manifest: String,
+    distribution: BatchedDistribution,
+}
+
+#[derive(Deserialize)]
+struct BatchedDistribution {
+    #[serde(rename = "downloadUrl")]
+    download_url: String,
+}
+
+#[derive(Deserialize)]
+struct BatchedPackage {
+    name: String,
+    #[serde(rename = "lastVersion")]
+    last_version: Option<BatchedVersion>,
+}
+
+impl BatchedVersion {
+    fn into_package_version(self, name: String) -> PackageVersion {
+        PackageVersion {
+            name,
+            version: self.version,
+            manifest: Some(self.manifest),
+            download_url: self.distribution.download_url,
+            signature: None,
+            license: None,
+            size: None,
+            published_at: None,
+            commands: None,
+        }
+    }
+}
+
+/// One query with an aliased field per lookup, answered as `lookup0`, `lookup1`, ...
+fn batched_query(lookups: &[Lookup]) -> (String, serde_json::Map<String, serde_json::Value>) {
+    let mut parameters = vec![];
+    let mut fields = vec![];
+    let mut variables = serde_json::Map::new();
+    for (index, (name, version)) in lookups.iter().enumerate() {
+        parameters.push(format!("$name{}: String!", index));
+        variables.insert(format!("name{}", index), name.clone().into());
+        if let Some(version) = version {
+            parameters.push(format!("$version{}: String", index));
+            variables.insert(format!("version{}", index), version.clone().into());
+            fields.push(format!(
+                "lookup{0}: getPackageVersion(name: $name{0}, version: $version{0}) {{ {1} }}",
+                index, BATCHED_VERSION_FIELDS
+            ));
+        } else {
+            fields.push(format!(
+                "lookup{0}: getPackage(name: $name{0}) {{ name lastVersion {{ {1} }} }}",
+                index, BATCHED_VERSION_FIELDS
+            ));
+        }
+    }
+    let query = format!(
+        "query {}({}) {{ {} }}",
+        BATCHED_OPERATION_NAME,
+        parameters.join(", "),
+        fields.join(" ")
+    );
+    (query, variables)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lookups_are_batched_into_one_query() {
+        let (query, variables) = batched_query(&[
+            ("foo".to_string(), Some("1.0.0".to_string())),
+            ("bar".to_string(), None),
+        ]);
+        assert_eq!(
+            query,
+            "query BatchedPackageVersionsQuery($name0: String!, $version0: String, $name1: String!) { \
+             lookup0: getPackageVersion(name: $name0, version: $version0) { version manifest distribution { downloadUrl } } \
+             lookup1: getPackage(name: $name1) { name lastVersion { version manifest distribution { downloadUrl } } } }"
+        );
+        assert_eq!(variables["name0"], "foo");
+        assert_eq!(variables["version0"], "1.0.0");
+        assert_eq!(variables["name1"], "bar");
+        assert!(variables.get("version1").is_none());
+    }
+}
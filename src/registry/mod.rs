@@ -0,0 +1,164 @@
+//! Looking up packages in the configured registry.
+//!
+//! A registry is either a wapm registry server spoken to over GraphQL, a static registry (an
+//! `index.json` and package archives served from any web server or a `file://` directory) or a
+//! static registry kept in an S3 bucket. Which one is used is set by the `registry.backend`
+//! config key. GraphQL registries can also be resolved against a local snapshot of their index.
+
+mod cached_backend;
+mod graphql_backend;
+mod s3_backend;
+mod snapshot_backend;
+mod static_backend;
+
+pub use self::cached_backend::CachedBackend;
+pub use self::graphql_backend::GraphQLBackend;
+pub use self::s3_backend::S3Backend;
+pub use self::snapshot_backend::{snapshot_path, snapshot_url, update_snapshot, SnapshotBackend};
+pub use self::static_backend::StaticBackend;
+
+use crate::config::{Config, RegistryBackendKind};
+use crate::data::manifest::Manifest;
+use crate::keys;
+use crate::proxy;
+use chrono::{DateTime, Utc};
+use reqwest::blocking::Client;
+use std::env;
+use std::path::PathBuf;
+
+const FILE_URL_PREFIX: &str = "file://";
+
+/// A published version of a package
+#[derive(Clone, Debug)]
+pub struct PackageVersion {
+    pub name: String,
+    pub version: String,
+    /// The published manifest, when the registry returned it
+    pub manifest: Option<String>,
+    pub download_url: String,
+    pub signature: Option<keys::WapmPackageSignature>,
+    /// The license of the package, when the registry returned it
+    pub license: Option<String>,
+    /// The size of the package archive in bytes, when the registry returned it
+    pub size: Option<u64>,
+    /// When the version was published, when the registry returned it
+    pub published_at: Option<DateTime<Utc>>,
+    /// The names of the commands of the package, when the registry returned them
+    pub commands: Option<Vec<String>>,
+}
+
+impl PackageVersion {
+    /// The names of the commands of the package, from the registry or else from its manifest
+    pub fn command_names(&self) -> Option<Vec<String>> {
+        if let Some(commands) = &self.commands {
+            return Some(commands.clone());
+        }
+        let manifest: Manifest = toml::from_str(self.manifest.as_ref()?).ok()?;
+        Some(
+            manifest
+                .command
+                .unwrap_or_default()
+                .into_iter()
+                .map(|command| command.name)
+                .collect(),
+        )
+    }
+}
+
+/// The ways of looking up packages that installing needs from a registry
+pub trait RegistryBackend {
+    /// All the published versions of the packages with the given names. Unknown names are skipped.
+    fn package_versions(&self, names: &[String]) -> Result<Vec<PackageVersion>, failure::Error>;
+
+    /// A published version of a package, or its last version when no version is given
+    fn package_version(
+        &self,
+        name: &str,
+        version: Option<&str>,
+    ) -> Result<Option<PackageVersion>, failure::Error>;
+
+    /// Look up many versions at once ahead of the `package_version` calls for them, so they are
+    /// answered without another request each. Lookups are pairs of a name and an optional
+    /// version, like the arguments of `package_version`.
+    fn prefetch_package_versions(
+        &self,
+        _lookups: &[(String, Option<String>)],
+    ) -> Result<(), failure::Error> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Fail)]
+pub enum RegistryError {
+    #[fail(
+        display = "Could not fetch the index of the registry at {}: {}",
+        _0, _1
+    )]
+    CouldNotFetchIndex(String, String),
+    #[fail(
+        display = "Could not parse the index of the registry at {}: {}",
+        _0, _1
+    )]
+    CouldNotParseIndex(String, String),
+    #[fail(display = "Could not upload {}: {}", _0, _1)]
+    CouldNotUpload(String, String),
+    #[fail(
+        display = "Invalid S3 registry url {}, expected s3://<bucket>/<prefix>",
+        _0
+    )]
+    InvalidS3Url(String),
+    #[fail(
+        display = "No AWS credentials found for the S3 registry. Set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY or add them to ~/.aws/credentials"
+    )]
+    NoAwsCredentials,
+    #[fail(display = "Version {} of package {} was already published", _1, _0)]
+    VersionAlreadyPublished(String, String),
+}
+
+/// The backend of the registry in the config, with the resolution cache of the project in the
+/// current directory in front of it
+pub fn backend() -> Result<Box<dyn RegistryBackend>, failure::Error> {
+    let config = Config::from_file()?;
+    let backend: Box<dyn RegistryBackend> = match config.registry.backend_kind() {
+        RegistryBackendKind::Graphql => match SnapshotBackend::from_config(&config) {
+            Some(snapshot) => Box::new(snapshot),
+            None => Box::new(GraphQLBackend),
+        },
+        RegistryBackendKind::Static => Box::new(StaticBackend::new(config.registry.url.clone())),
+        RegistryBackendKind::S3 => Box::new(S3Backend::from_registry(&config.registry)?),
+    };
+    Ok(match env::current_dir() {
+        Ok(current_dir) => CachedBackend::wrap(backend, &current_dir, &config),
+        Err(_) => backend,
+    })
+}
+
+/// The path of a `file://` url, which is used for static registries in a local directory
+pub fn file_url_path(url: &str) -> Option<PathBuf> {
+    if url.starts_with(FILE_URL_PREFIX) {
+        Some(PathBuf::from(url.trim_start_matches(FILE_URL_PREFIX)))
+    } else {
+        None
+    }
+}
+
+/// The url to fetch an archive from. The `s3://` urls of archives in S3 registries are
+/// presigned with the current credentials, other urls are fetched as they are.
+pub fn fetch_url(url: &str) -> Result<String, failure::Error> {
+    if url.starts_with(s3_backend::S3_URL_PREFIX) {
+        let endpoint = Config::from_file()?.registry.s3_endpoint;
+        return Ok(S3Backend::download_url(url, endpoint)?);
+    }
+    Ok(url.to_string())
+}
+
+/// A client for fetching from registries, through the configured proxy
+pub fn http_client() -> Result<Client, failure::Error> {
+    let builder = Client::builder();
+    let builder = if let Some(proxy) = proxy::maybe_set_up_proxy()? {
+        builder.proxy(proxy)
+    } else {
+        builder
+    };
+    Ok(builder.build()?)
+}
@@ -0,0 +1,293 @@
+//! A local snapshot of a registry's package index, so resolving versions needs no requests and
+//! only the package archives are downloaded.
+//!
+//! The snapshot is a gzipped static registry index (see `static_backend`) that the registry
+//! serves as `index.json.gz`, or at the `index.url` config key. It is enabled with
+//! `wapm config set index.enabled true`, downloaded again once it is older than `index.max-age`
+//! (a day by default) and can be refreshed with `wapm index update`.
+//!
+//! Packages missing from the snapshot, like ones published since it was taken, and signed
+//! versions, whose signatures only the registry has, are still looked up in the registry.
+
+use crate::config::Config;
+use crate::http_trace;
+use crate::min_age;
+use crate::registry::static_backend::StaticIndex;
+use crate::registry::{
+    file_url_path, http_client, GraphQLBackend, PackageVersion, RegistryBackend,
+};
+use crate::util::sha256_hex;
+use chrono::{DateTime, Duration, Utc};
+use flate2::read::GzDecoder;
+use lazy_static::lazy_static;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub const SNAPSHOT_FILE_NAME: &str = "index.json.gz";
+
+lazy_static! {
+    /// The snapshot read by this process and the url it came from, or None if it could not be
+    /// read. The registry backend is created many times while installing and the snapshot is
+    /// only read once.
+    static ref LOADED_SNAPSHOT: Mutex<Option<(String, Option<Arc<StaticIndex>>)>> =
+        Mutex::new(None);
+}
+
+#[derive(Debug, Fail)]
+pub enum SnapshotError {
+    #[fail(display = "Could not download the index snapshot from {}: {}", _0, _1)]
+    CouldNotDownload(String, String),
+    #[fail(display = "Could not read the index snapshot from {}: {}", _0, _1)]
+    CouldNotParse(String, String),
+}
+
+/// Resolves against the snapshot, falling back to the registry's GraphQL API
+pub struct SnapshotBackend {
+    index: Arc<StaticIndex>,
+    /// The url of the snapshot, which relative archive paths are resolved against
+    url: String,
+}
+
+impl SnapshotBackend {
+    /// The backend for the snapshot of the registry in the config, downloading the snapshot if it
+    /// is missing or stale. None if snapshots are disabled or no snapshot could be downloaded.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        if !config.index.enabled {
+            return None;
+        }
+        let url = snapshot_url(config);
+        if let Some((loaded_url, index)) = &*LOADED_SNAPSHOT.lock().unwrap() {
+            if *loaded_url == url {
+                return index.clone().map(|index| Self { index, url });
+            }
+        }
+        let path = snapshot_path(config).ok()?;
+        let max_age = config
+            .index
+            .max_age
+            .as_deref()
+            .and_then(|max_age| min_age::parse_min_age(max_age).ok())
+            .unwrap_or_else(|| Duration::days(1));
+        let modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .map(DateTime::<Utc>::from);
+        let is_fresh = modified
+            .map(|modified| !is_stale(modified, max_age, Utc::now()))
+            .unwrap_or(false);
+        let index = if is_fresh {
+            read_snapshot(&path)
+        } else {
+            info!("Updating the package index snapshot");
+            update_snapshot(&url, &path).or_else(|e| {
+                if modified.is_some() {
+                    warn!("{}, using the snapshot from before", e);
+                    read_snapshot(&path)
+                } else {
+                    Err(e)
+                }
+            })
+        };
+        let index = match index {
+            Ok(index) => Some(Arc::new(index)),
+            Err(e) => {
+                warn!("{}, looking packages up in the registry instead", e);
+                None
+            }
+        };
+        *LOADED_SNAPSHOT.lock().unwrap() = Some((url.clone(), index.clone()));
+        index.map(|index| Self { index, url })
+    }
+
+    fn archive_url(&self, archive: &str) -> String {
+        if archive.contains("://") {
+            return archive.to_string();
+        }
+        let base = match self.url.rfind('/') {
+            Some(position) => &self.url[..position],
+            None => &self.url,
+        };
+        format!("{}/{}", base, archive.trim_start_matches('/'))
+    }
+
+    /// Whether the snapshot can answer for a package on its own
+    fn knows(&self, name: &str) -> bool {
+        self.index
+            .packages
+            .iter()
+            .find(|package| package.name == name)
+            .map(|package| package.versions.iter().all(|version| !version.signed))
+            .unwrap_or(false)
+    }
+}
+
+impl RegistryBackend for SnapshotBackend {
+    fn package_versions(&self, names: &[String]) -> Result<Vec<PackageVersion>, failure::Error> {
+        let (local, remote): (Vec<String>, Vec<String>) =
+            names.iter().cloned().partition(|name| self.knows(name));
+        let mut package_versions = self
+            .index
+            .package_versions(&local, |archive| self.archive_url(archive));
+        if !remote.is_empty() {
+            package_versions.extend(GraphQLBackend.package_versions(&remote)?);
+        }
+        Ok(package_versions)
+    }
+
+    fn package_version(
+        &self,
+        name: &str,
+        version: Option<&str>,
+    ) -> Result<Option<PackageVersion>, failure::Error> {
+        if self.knows(name) {
+            let found = self
+                .index
+                .package_version(name, version, |archive| self.archive_url(archive));
+            // the manifests of packages are left out of slimmer snapshots
+            if let Some(found) = found.filter(|found| found.manifest.is_some()) {
+                return Ok(Some(found));
+            }
+        }
+        GraphQLBackend.package_version(name, version)
+    }
+
+    fn prefetch_package_versions(
+        &self,
+        lookups: &[(String, Option<String>)],
+    ) -> Result<(), failure::Error> {
+        GraphQLBackend.prefetch_package_versions(lookups)
+    }
+}
+
+/// Where the snapshot of the registry in the config is downloaded from
+pub fn snapshot_url(config: &Config) -> String {
+    config.index.url.clone().unwrap_or_else(|| {
+        format!(
+            "{}/{}",
+            config.registry.url.trim_end_matches('/'),
+            SNAPSHOT_FILE_NAME
+        )
+    })
+}
+
+/// Where the snapshot of the registry in the config is kept, one per registry
+pub fn snapshot_path(config: &Config) -> Result<PathBuf, failure::Error> {
+    let registry_hash = sha256_hex(config.registry.url.as_bytes());
+    Ok(Config::get_cache_directory()?.join("index").join(format!(
+        "{}-{}",
+        &registry_hash[..16],
+        SNAPSHOT_FILE_NAME
+    )))
+}
+
+/// Download the snapshot at `url` to `path`
+pub fn update_snapshot(url: &str, path: &Path) -> Result<StaticIndex, failure::Error> {
+    let data = fetch(url).map_err(|e| SnapshotError::CouldNotDownload(url.to_string(), e))?;
+    // only snapshots that can be read replace the previous one
+    let index =
+        parse_snapshot(&data).map_err(|e| SnapshotError::CouldNotParse(url.to_string(), e))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial_path = path.with_extension("partial");
+    fs::write(&partial_path, &data)?;
+    fs::rename(&partial_path, path)?;
+    Ok(index)
+}
+
+fn fetch(url: &str) -> Result<Vec<u8>, String> {
+    if let Some(path) = file_url_path(url) {
+        return fs::read(path).map_err(|e| e.to_string());
+    }
+    let client = http_client().map_err(|e| e.to_string())?;
+    let mut response = http_trace::send(&client, client.get(url), None)
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| e.to_string())?;
+    let mut data = vec![];
+    response.copy_to(&mut data).map_err(|e| e.to_string())?;
+    Ok(data)
+}
+
+fn read_snapshot(path: &Path) -> Result<StaticIndex, failure::Error> {
+    let data = fs::read(path)?;
+    Ok(parse_snapshot(&data)
+        .map_err(|e| SnapshotError::CouldNotParse(path.display().to_string(), e))?)
+}
+
+fn parse_snapshot(data: &[u8]) -> Result<StaticIndex, String> {
+    let mut json = String::new();
+    GzDecoder::new(data)
+        .read_to_string(&mut json)
+        .map_err(|e| e.to_string())?;
+    serde_json::from_str(&json).map_err(|e| e.to_string())
+}
+
+/// Whether a snapshot downloaded at `modified` needs to be downloaded again
+pub fn is_stale(modified: DateTime<Utc>, max_age: Duration, now: DateTime<Utc>) -> bool {
+    now - modified > max_age
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    #[test]
+    fn resolve_against_a_downloaded_snapshot() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder
+            .write_all(
+                br#"{
+  "packages": [
+    {
+      "name": "_/hello",
+      "versions": [
+        { "version": "0.1.0", "archive": "archives/hello-0.1.0.tar.gz", "manifest": "" },
+        { "version": "0.2.0", "archive": "archives/hello-0.2.0.tar.gz", "manifest": "" }
+      ]
+    },
+    {
+      "name": "_/signed",
+      "versions": [{ "version": "1.0.0", "archive": "signed.tar.gz", "signed": true }]
+    }
+  ]
+}"#,
+            )
+            .unwrap();
+        fs::write(dir.path().join("remote.json.gz"), encoder.finish().unwrap()).unwrap();
+        let url = format!("file://{}/remote.json.gz", dir.path().display());
+        let path = dir.path().join("snapshots").join(SNAPSHOT_FILE_NAME);
+
+        let index = update_snapshot(&url, &path).unwrap();
+        assert!(path.exists());
+        let backend = SnapshotBackend {
+            index: Arc::new(index),
+            url: "https://registry.example.com/index.json.gz".to_string(),
+        };
+        assert!(backend.knows("_/hello"));
+        assert!(!backend.knows("_/signed"));
+        assert!(!backend.knows("_/missing"));
+        let last = backend.package_version("_/hello", None).unwrap().unwrap();
+        assert_eq!(last.version, "0.2.0");
+        assert_eq!(
+            last.download_url,
+            "https://registry.example.com/archives/hello-0.2.0.tar.gz"
+        );
+
+        let modified = "2020-03-10T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        assert!(!is_stale(
+            modified,
+            Duration::days(1),
+            modified + Duration::hours(12)
+        ));
+        assert!(is_stale(
+            modified,
+            Duration::days(1),
+            modified + Duration::hours(25)
+        ));
+    }
+}
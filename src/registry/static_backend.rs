@@ -0,0 +1,252 @@
+//! A static registry is a directory with an `index.json` listing the packages:
+//!
+//! ```json
+//! {
+//!   "packages": [
+//!     {
+//!       "name": "namespace/package",
+//!       "versions": [
+//!         {
+//!           "version": "1.0.0",
+//!           "archive": "archives/namespace/package-1.0.0.tar.gz",
+//!           "manifest": "[package]\nname = \"namespace/package\"\n..."
+//!         }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Archive paths are relative to the registry url unless they are urls themselves. The directory
+//! can be served by any web server or used directly with a `file://` url. Versions can also list
+//! their `license`, the `size` of the archive in bytes and when they were `published_at`, which
+//! install policies check, and whether they are `signed`.
+
+use crate::http_trace;
+use crate::registry::{file_url_path, http_client, PackageVersion, RegistryBackend, RegistryError};
+use chrono::{DateTime, Utc};
+use semver::Version;
+use std::fs;
+
+pub const INDEX_FILE_NAME: &str = "index.json";
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct StaticIndex {
+    #[serde(default)]
+    pub packages: Vec<StaticPackage>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StaticPackage {
+    pub name: String,
+    #[serde(default)]
+    pub versions: Vec<StaticPackageVersion>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct StaticPackageVersion {
+    pub version: String,
+    pub archive: String,
+    pub manifest: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    /// The size of the archive in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published_at: Option<DateTime<Utc>>,
+    /// Whether the version was signed, the signature itself is only known to the registry
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub signed: bool,
+}
+
+impl StaticIndex {
+    /// All the versions of the packages with the given names. `archive_url` turns the archive
+    /// paths of the index into download urls.
+    pub fn package_versions<F>(&self, names: &[String], archive_url: F) -> Vec<PackageVersion>
+    where
+        F: Fn(&str) -> String,
+    {
+        self.packages
+            .iter()
+            .filter(|package| names.contains(&package.name))
+            .flat_map(|package| {
+                package
+                    .versions
+                    .iter()
+                    .map(|version| to_package_version(&package.name, version, &archive_url))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// A version of a package, or its greatest version when no version is given
+    pub fn package_version<F>(
+        &self,
+        name: &str,
+        version: Option<&str>,
+        archive_url: F,
+    ) -> Option<PackageVersion>
+    where
+        F: Fn(&str) -> String,
+    {
+        let package = self.packages.iter().find(|package| package.name == name)?;
+        let found = match version {
+            Some(version) => package.versions.iter().find(|v| v.version == version),
+            None => package
+                .versions
+                .iter()
+                .filter_map(|v| Version::parse(&v.version).ok().map(|parsed| (parsed, v)))
+                .max_by(|(a, _), (b, _)| a.cmp(b))
+                .map(|(_, v)| v),
+        };
+        found.map(|version| to_package_version(name, version, &archive_url))
+    }
+
+    /// Add a published version of a package. Returns false if the version was already published.
+    pub fn add_version(&mut self, name: &str, version: StaticPackageVersion) -> bool {
+        let position = match self.packages.iter().position(|p| p.name == name) {
+            Some(position) => position,
+            None => {
+                self.packages.push(StaticPackage {
+                    name: name.to_string(),
+                    versions: vec![],
+                });
+                self.packages.len() - 1
+            }
+        };
+        let package = &mut self.packages[position];
+        if package
+            .versions
+            .iter()
+            .any(|v| v.version == version.version)
+        {
+            return false;
+        }
+        package.versions.push(version);
+        true
+    }
+}
+
+fn to_package_version<F>(
+    name: &str,
+    version: &StaticPackageVersion,
+    archive_url: F,
+) -> PackageVersion
+where
+    F: Fn(&str) -> String,
+{
+    PackageVersion {
+        name: name.to_string(),
+        version: version.version.clone(),
+        manifest: version.manifest.clone(),
+        download_url: archive_url(&version.archive),
+        signature: None,
+        license: version.license.clone(),
+        size: version.size,
+        published_at: version.published_at,
+        commands: None,
+    }
+}
+
+/// A registry made of an `index.json` and package archives
+pub struct StaticBackend {
+    url: String,
+}
+
+impl StaticBackend {
+    pub fn new(url: String) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn index(&self) -> Result<StaticIndex, RegistryError> {
+        let index_url = format!("{}/{}", self.url, INDEX_FILE_NAME);
+        let source = fetch_index(&index_url)
+            .map_err(|e| RegistryError::CouldNotFetchIndex(self.url.clone(), e.to_string()))?;
+        serde_json::from_str(&source)
+            .map_err(|e| RegistryError::CouldNotParseIndex(self.url.clone(), e.to_string()))
+    }
+
+    fn archive_url(&self, archive: &str) -> String {
+        if archive.contains("://") {
+            archive.to_string()
+        } else {
+            format!("{}/{}", self.url, archive.trim_start_matches('/'))
+        }
+    }
+}
+
+fn fetch_index(index_url: &str) -> Result<String, failure::Error> {
+    if let Some(path) = file_url_path(index_url) {
+        return Ok(fs::read_to_string(path)?);
+    }
+    let client = http_client()?;
+    Ok(http_trace::send(&client, client.get(index_url), None)?
+        .error_for_status()?
+        .text()?)
+}
+
+impl RegistryBackend for StaticBackend {
+    fn package_versions(&self, names: &[String]) -> Result<Vec<PackageVersion>, failure::Error> {
+        Ok(self
+            .index()?
+            .package_versions(names, |archive| self.archive_url(archive)))
+    }
+
+    fn package_version(
+        &self,
+        name: &str,
+        version: Option<&str>,
+    ) -> Result<Option<PackageVersion>, failure::Error> {
+        Ok(self
+            .index()?
+            .package_version(name, version, |archive| self.archive_url(archive)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn look_up_packages_in_a_directory() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(
+            dir.path().join(INDEX_FILE_NAME),
+            r#"{
+  "packages": [
+    {
+      "name": "_/hello",
+      "versions": [
+        { "version": "0.2.0", "archive": "archives/hello-0.2.0.tar.gz" },
+        { "version": "0.10.0", "archive": "https://example.com/hello-0.10.0.tar.gz" }
+      ]
+    },
+    { "name": "_/other", "versions": [] }
+  ]
+}"#,
+        )
+        .unwrap();
+        let url = format!("file://{}/", dir.path().display());
+        let backend = StaticBackend::new(url.clone());
+
+        let versions = backend
+            .package_versions(&["_/hello".to_string(), "_/missing".to_string()])
+            .unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(
+            versions[0].download_url,
+            format!("{}archives/hello-0.2.0.tar.gz", url)
+        );
+
+        let last = backend.package_version("_/hello", None).unwrap().unwrap();
+        assert_eq!(last.version, "0.10.0");
+        assert_eq!(last.download_url, "https://example.com/hello-0.10.0.tar.gz");
+        assert!(backend
+            .package_version("_/hello", Some("1.0.0"))
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,159 @@
+//! Sandbox profiles for the commands of installed packages.
+//!
+//! `wapm install pkg --sandbox strict` records the profile for the commands of the package in
+//! the lockfile, and the scripts in `wapm_packages/.bin` run them with
+//! `wapm run --sandbox strict <command>`. A sandboxed command gets only the runtime flags of its
+//! profile: the `[fs]` mappings and `wasmer-extra-flags` of its manifest are ignored.
+//!
+//! - `strict`: the current directory, no environment variables
+//! - `standard`: the current directory and the locale and terminal environment variables
+//! - `none`: whatever the package asks for, like commands without a profile
+//!
+//! The runtime can't map directories read-only, so no profile gives access to the home
+//! directory.
+
+use crate::data::lock::lockfile::Lockfile;
+use crate::dataflow::bin_script::save_bin_script;
+use crate::dataflow::lockfile_packages::LockfileResult;
+use std::env;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The environment variables commands with the `standard` profile can read
+const STANDARD_ENV_ALLOWLIST: &[&str] = &["LANG", "LC_ALL", "TERM", "TZ", "NO_COLOR"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SandboxProfile {
+    /// No restrictions beyond those of the runtime
+    Unrestricted,
+    Strict,
+    Standard,
+}
+
+impl FromStr for SandboxProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(SandboxProfile::Unrestricted),
+            "strict" => Ok(SandboxProfile::Strict),
+            "standard" => Ok(SandboxProfile::Standard),
+            _ => Err(format!(
+                "unknown sandbox profile \"{}\", use \"strict\", \"standard\" or \"none\"",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for SandboxProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            SandboxProfile::Unrestricted => "none",
+            SandboxProfile::Strict => "strict",
+            SandboxProfile::Standard => "standard",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl SandboxProfile {
+    pub fn description(self) -> &'static str {
+        match self {
+            SandboxProfile::Unrestricted => {
+                "the directories and flags the package asks for in its manifest"
+            }
+            SandboxProfile::Strict => "only the current directory, no environment variables",
+            SandboxProfile::Standard => {
+                "only the current directory, and the locale and terminal environment variables"
+            }
+        }
+    }
+
+    /// Whether the directories and flags of the manifest of the package are used
+    pub fn uses_manifest_flags(self) -> bool {
+        self == SandboxProfile::Unrestricted
+    }
+
+    /// The runtime flags commands are run with, given how to read environment variables
+    pub fn runtime_flags<F: Fn(&str) -> Option<String>>(self, read_env: F) -> Vec<String> {
+        let env_allowlist: &[&str] = match self {
+            SandboxProfile::Unrestricted => return vec![],
+            SandboxProfile::Strict => &[],
+            SandboxProfile::Standard => STANDARD_ENV_ALLOWLIST,
+        };
+        let mut flags = vec!["--dir=.".to_string()];
+        for name in env_allowlist {
+            if let Some(value) = read_env(name) {
+                flags.push(format!("--env={}={}", name, value));
+            }
+        }
+        flags
+    }
+
+    /// The runtime flags, with the environment variables of this process
+    pub fn current_runtime_flags(self) -> Vec<String> {
+        self.runtime_flags(|name| env::var(name).ok())
+    }
+
+    /// How the profile is recorded in the lockfile
+    pub fn lockfile_value(self) -> Option<String> {
+        match self {
+            SandboxProfile::Unrestricted => None,
+            profile => Some(profile.to_string()),
+        }
+    }
+}
+
+/// Attach a sandbox profile to the commands of packages installed in a directory, updating their
+/// scripts in `wapm_packages/.bin` if `update_bin_scripts` is set
+pub fn set_sandbox_profile(
+    directory: &Path,
+    packages: &[String],
+    profile: SandboxProfile,
+    update_bin_scripts: bool,
+) -> Result<(), failure::Error> {
+    let mut lockfile: Lockfile = match LockfileResult::find_in_directory(directory) {
+        LockfileResult::Lockfile(lockfile) => lockfile,
+        _ => return Ok(()),
+    };
+    for command in lockfile.commands.values_mut() {
+        if !packages.contains(&command.package_name) {
+            continue;
+        }
+        command.sandbox = profile.lockfile_value();
+        if update_bin_scripts {
+            save_bin_script(directory, command.name.clone(), command.sandbox.as_deref())?;
+        }
+    }
+    lockfile.save(directory)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn profiles_restrict_the_runtime_flags() {
+        let read_env = |name: &str| match name {
+            "LANG" => Some("en_US.UTF-8".to_string()),
+            "HOME" => Some("/home/user".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            SandboxProfile::Strict.runtime_flags(read_env),
+            vec!["--dir=."]
+        );
+        assert_eq!(
+            SandboxProfile::Standard.runtime_flags(read_env),
+            vec!["--dir=.", "--env=LANG=en_US.UTF-8"]
+        );
+        assert!(SandboxProfile::Unrestricted
+            .runtime_flags(read_env)
+            .is_empty());
+        assert_eq!("strict".parse(), Ok(SandboxProfile::Strict));
+        assert_eq!(SandboxProfile::Unrestricted.lockfile_value(), None);
+        assert!("home".parse::<SandboxProfile>().is_err());
+    }
+}
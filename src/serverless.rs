@@ -0,0 +1,405 @@
+//! Exporting a command into the layout a wasm serverless platform deploys: a Spin application, a
+//! Fastly Compute package or a Cloudflare Worker.
+//!
+//! The module is copied next to the config of the platform, with the `fs` directories of the
+//! manifest and the environment variables of `wasmer-extra-flags` translated to what the platform
+//! has for them. What a platform has no equivalent for is left out with a warning.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// The version of `@cloudflare/workers-wasi` the generated workers depend on
+const WORKERS_WASI_VERSION: &str = "^0.0.5";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Platform {
+    Spin,
+    Fastly,
+    Workers,
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spin" => Ok(Platform::Spin),
+            "fastly" => Ok(Platform::Fastly),
+            "workers" => Ok(Platform::Workers),
+            _ => Err(format!(
+                "unknown target \"{}\", expected spin, fastly or workers",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Platform::Spin => write!(f, "spin"),
+            Platform::Fastly => write!(f, "fastly"),
+            Platform::Workers => write!(f, "workers"),
+        }
+    }
+}
+
+/// A command resolved to its module, with what the manifest of its package declares for it
+#[derive(Debug)]
+pub struct ExportedCommand {
+    pub name: String,
+    pub package_name: String,
+    pub version: String,
+    pub description: String,
+    /// The path of the module
+    pub module: PathBuf,
+    /// The `main_args` of the command
+    pub args: Vec<String>,
+    /// The `fs` directories, from the guest path to the path of the directory
+    pub fs: BTreeMap<String, PathBuf>,
+    /// The `KEY=VALUE` environment variables of the command
+    pub env_vars: Vec<String>,
+}
+
+impl ExportedCommand {
+    /// A name the platforms accept for applications and components: lowercase letters, digits
+    /// and dashes
+    fn app_name(&self) -> String {
+        let name: String = self
+            .name
+            .to_lowercase()
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect();
+        name.trim_matches('-').to_string()
+    }
+
+    fn env_pairs(&self) -> BTreeMap<&str, &str> {
+        self.env_vars
+            .iter()
+            .filter_map(|env_var| env_var.split_once('='))
+            .collect()
+    }
+}
+
+/// The environment variables given with `--env` in `wasmer-extra-flags`
+pub fn env_vars_of_flags(flags: &str) -> Vec<String> {
+    let mut env_vars = vec![];
+    let mut words = flags.split_whitespace();
+    while let Some(word) = words.next() {
+        if let Some(env_var) = word.strip_prefix("--env=") {
+            env_vars.push(env_var.to_string());
+        } else if word == "--env" {
+            env_vars.extend(words.next().map(str::to_string));
+        }
+    }
+    env_vars
+}
+
+/// Where the `fs` directory mapped to a guest path is copied in the export
+fn asset_dir(guest: &str) -> String {
+    let name: Vec<&str> = guest
+        .split(['/', '\\'])
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect();
+    if name.is_empty() {
+        "assets/root".to_string()
+    } else {
+        format!("assets/{}", name.join("-"))
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+fn toml_string(value: &str) -> String {
+    toml::Value::String(value.to_string()).to_string()
+}
+
+fn spin_toml(command: &ExportedCommand) -> String {
+    let id = command.app_name();
+    let mut out = String::from("spin_manifest_version = 2\n\n[application]\n");
+    out.push_str(&format!("name = {}\n", toml_string(&id)));
+    out.push_str(&format!("version = {}\n", toml_string(&command.version)));
+    out.push_str(&format!(
+        "description = {}\n\n",
+        toml_string(&command.description)
+    ));
+    // the wagi executor runs WASI commands, with the request on stdin and the response on stdout
+    out.push_str("[[trigger.http]]\nroute = \"/...\"\n");
+    out.push_str(&format!("component = {}\n", toml_string(&id)));
+    let mut argv = vec!["${SCRIPT_NAME}".to_string()];
+    argv.extend(command.args.iter().cloned());
+    argv.push("${ARGS}".to_string());
+    out.push_str(&format!(
+        "executor = {{ type = \"wagi\", argv = {} }}\n\n",
+        toml_string(&argv.join(" "))
+    ));
+    out.push_str(&format!("[component.{}]\n", id));
+    out.push_str(&format!(
+        "source = {}\n",
+        toml_string(&format!("{}.wasm", id))
+    ));
+    if !command.fs.is_empty() {
+        let files: Vec<String> = command
+            .fs
+            .keys()
+            .map(|guest| {
+                format!(
+                    "{{ source = {}, destination = {} }}",
+                    toml_string(&asset_dir(guest)),
+                    toml_string(guest)
+                )
+            })
+            .collect();
+        out.push_str(&format!("files = [{}]\n", files.join(", ")));
+    }
+    let env = command.env_pairs();
+    if !env.is_empty() {
+        let pairs: Vec<String> = env
+            .iter()
+            .map(|(key, value)| format!("{} = {}", toml_string(key), toml_string(value)))
+            .collect();
+        out.push_str(&format!("environment = {{ {} }}\n", pairs.join(", ")));
+    }
+    out
+}
+
+fn fastly_toml(command: &ExportedCommand) -> String {
+    let name = command.app_name();
+    let mut out = String::from("manifest_version = 3\n");
+    out.push_str(&format!("name = {}\n", toml_string(&name)));
+    out.push_str(&format!(
+        "description = {}\n",
+        toml_string(&command.description)
+    ));
+    out.push_str("authors = []\nlanguage = \"other\"\n");
+    // Compute has no environment variables, they are read from a config store
+    let env = command.env_pairs();
+    if !env.is_empty() {
+        out.push_str("\n[setup.config_stores.env]\n");
+        out.push_str("description = \"The environment variables of the command\"\n");
+        for (key, value) in env.iter() {
+            out.push_str(&format!(
+                "\n[setup.config_stores.env.items.{}]\nvalue = {}\n",
+                toml_string(key),
+                toml_string(value)
+            ));
+        }
+        out.push_str(
+            "\n[local_server.config_stores.env]\nformat = \"inline-toml\"\n\n[local_server.config_stores.env.contents]\n",
+        );
+        for (key, value) in env.iter() {
+            out.push_str(&format!("{} = {}\n", toml_string(key), toml_string(value)));
+        }
+    }
+    out
+}
+
+fn wrangler_toml(command: &ExportedCommand, compatibility_date: &str) -> String {
+    let mut out = format!("name = {}\n", toml_string(&command.app_name()));
+    out.push_str("main = \"src/index.mjs\"\n");
+    out.push_str(&format!(
+        "compatibility_date = {}\n",
+        toml_string(compatibility_date)
+    ));
+    let env = command.env_pairs();
+    if !env.is_empty() {
+        out.push_str("\n[vars]\n");
+        for (key, value) in env.iter() {
+            out.push_str(&format!("{} = {}\n", toml_string(key), toml_string(value)));
+        }
+    }
+    out
+}
+
+fn worker_script(command: &ExportedCommand) -> Result<String, serde_json::Error> {
+    let mut args = vec![command.name.clone()];
+    args.extend(command.args.iter().cloned());
+    let env_keys: Vec<&str> = command.env_pairs().keys().cloned().collect();
+    Ok(format!(
+        r#"// Generated by `wapm export --target workers`, runs the `{name}` command of {package}
+// with the request body on stdin and the response body from stdout
+import {{ WASI }} from "@cloudflare/workers-wasi";
+import module from "./{name}.wasm";
+
+const ARGS = {args};
+const ENV_KEYS = {env_keys};
+
+export default {{
+  async fetch(request, env, ctx) {{
+    const stdout = new TransformStream();
+    const wasi = new WASI({{
+      args: ARGS,
+      env: Object.fromEntries(ENV_KEYS.map((key) => [key, env[key]])),
+      stdin: request.body,
+      stdout: stdout.writable,
+    }});
+    const instance = new WebAssembly.Instance(module, {{
+      wasi_snapshot_preview1: wasi.wasiImport,
+    }});
+    ctx.waitUntil(wasi.start(instance));
+    return new Response(stdout.readable);
+  }},
+}};
+"#,
+        name = command.name,
+        package = command.package_name,
+        args = serde_json::to_string(&args)?,
+        env_keys = serde_json::to_string(&env_keys)?,
+    ))
+}
+
+/// Write a tar.gz with the files of the Fastly package under a directory named like it, the
+/// layout `fastly compute deploy --package` takes
+fn fastly_package(output: &Path, name: &str) -> Result<(), failure::Error> {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    fs::create_dir_all(output.join("pkg"))?;
+    let archive = fs::File::create(output.join("pkg").join(format!("{}.tar.gz", name)))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(archive, Compression::default()));
+    builder.append_path_with_name(
+        output.join("fastly.toml"),
+        Path::new(name).join("fastly.toml"),
+    )?;
+    builder.append_path_with_name(
+        output.join("bin").join("main.wasm"),
+        Path::new(name).join("bin").join("main.wasm"),
+    )?;
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Write the export of the command to the output directory, returning the files that a user
+/// would look at first
+pub fn export(
+    command: &ExportedCommand,
+    platform: Platform,
+    output: &Path,
+) -> Result<Vec<PathBuf>, failure::Error> {
+    fs::create_dir_all(output)?;
+    let name = command.app_name();
+    match platform {
+        Platform::Spin => {
+            fs::copy(&command.module, output.join(format!("{}.wasm", name)))?;
+            for (guest, host) in command.fs.iter() {
+                copy_dir(host, &output.join(asset_dir(guest)))?;
+            }
+            fs::write(output.join("spin.toml"), spin_toml(command))?;
+            Ok(vec![output.join("spin.toml")])
+        }
+        Platform::Fastly => {
+            for guest in command.fs.keys() {
+                warn!(
+                    "Fastly Compute has no filesystem, the `{}` directory is left out",
+                    guest
+                );
+            }
+            fs::create_dir_all(output.join("bin"))?;
+            fs::copy(&command.module, output.join("bin").join("main.wasm"))?;
+            fs::write(output.join("fastly.toml"), fastly_toml(command))?;
+            fastly_package(output, &name)?;
+            Ok(vec![
+                output.join("fastly.toml"),
+                output.join("pkg").join(format!("{}.tar.gz", name)),
+            ])
+        }
+        Platform::Workers => {
+            for guest in command.fs.keys() {
+                warn!(
+                    "Cloudflare Workers have no filesystem, the `{}` directory is left out",
+                    guest
+                );
+            }
+            let src = output.join("src");
+            fs::create_dir_all(&src)?;
+            fs::copy(&command.module, src.join(format!("{}.wasm", command.name)))?;
+            fs::write(src.join("index.mjs"), worker_script(command)?)?;
+            let compatibility_date = chrono::Utc::now().format("%Y-%m-%d").to_string();
+            fs::write(
+                output.join("wrangler.toml"),
+                wrangler_toml(command, &compatibility_date),
+            )?;
+            let package_json = serde_json::json!({
+                "name": name,
+                "private": true,
+                "dependencies": { "@cloudflare/workers-wasi": WORKERS_WASI_VERSION },
+            });
+            fs::write(
+                output.join("package.json"),
+                serde_json::to_string_pretty(&package_json)? + "\n",
+            )?;
+            Ok(vec![output.join("wrangler.toml"), src.join("index.mjs")])
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn command() -> ExportedCommand {
+        let mut fs = BTreeMap::new();
+        fs.insert("/static".to_string(), PathBuf::from("public"));
+        ExportedCommand {
+            name: "serve".to_string(),
+            package_name: "user/server".to_string(),
+            version: "1.0.0".to_string(),
+            description: "A \"server\"".to_string(),
+            module: PathBuf::from("server.wasm"),
+            args: vec!["--quiet".to_string()],
+            fs,
+            env_vars: env_vars_of_flags("--env=LOG=info --dir . --env MODE=prod"),
+        }
+    }
+
+    #[test]
+    fn manifests_declare_files_and_environment() {
+        let command = command();
+        assert_eq!(command.env_vars, ["LOG=info", "MODE=prod"]);
+
+        let spin: toml::Value = toml::from_str(&spin_toml(&command)).unwrap();
+        let component = &spin["component"]["serve"];
+        assert_eq!(component["source"].as_str(), Some("serve.wasm"));
+        assert_eq!(
+            component["files"][0]["source"].as_str(),
+            Some("assets/static")
+        );
+        assert_eq!(component["environment"]["MODE"].as_str(), Some("prod"));
+        assert_eq!(
+            spin["trigger"]["http"][0]["executor"]["argv"].as_str(),
+            Some("${SCRIPT_NAME} --quiet ${ARGS}")
+        );
+        assert_eq!(
+            spin["application"]["description"].as_str(),
+            Some("A \"server\"")
+        );
+
+        let fastly: toml::Value = toml::from_str(&fastly_toml(&command)).unwrap();
+        assert_eq!(
+            fastly["setup"]["config_stores"]["env"]["items"]["LOG"]["value"].as_str(),
+            Some("info")
+        );
+        let wrangler: toml::Value = toml::from_str(&wrangler_toml(&command, "2024-01-01")).unwrap();
+        assert_eq!(wrangler["vars"]["LOG"].as_str(), Some("info"));
+        assert!(worker_script(&command)
+            .unwrap()
+            .contains(r#"const ARGS = ["serve","--quiet"];"#));
+    }
+}
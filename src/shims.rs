@@ -0,0 +1,189 @@
+//! The shims of the globally installed commands, the bin scripts in the globals directory that run
+//! `wapm run <command>` and that the `PATH` points to.
+//!
+//! Every command of the globals lockfile has a plain shim and a versioned one like `tool@1.2.3`,
+//! and every command of a version installed side by side has a versioned one. Shims go missing or
+//! stale when the bin directory is edited by hand or packages are removed without wapm, and
+//! `wapm rehash` writes them again from the lockfiles.
+
+use crate::data::lock::lockfile::Lockfile;
+use crate::dataflow::bin_script::{
+    bin_directory, bin_script_contents, bin_script_file_name, is_bin_script, save_bin_script,
+};
+use crate::dataflow::lockfile_packages::LockfileResult;
+use crate::global_versions;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// How the shims in the bin directory differ from the installed commands
+#[derive(Debug, Default, PartialEq)]
+pub struct ShimDrift {
+    /// Commands without a shim
+    pub missing: Vec<String>,
+    /// Shims that don't run their command the way the lockfile says, or can't be executed
+    pub outdated: Vec<String>,
+    /// Shim files of commands that are not installed anymore
+    pub stale: Vec<String>,
+}
+
+impl ShimDrift {
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty() && self.outdated.is_empty() && self.stale.is_empty()
+    }
+}
+
+fn read_lockfile(directory: &Path) -> Option<Lockfile> {
+    match LockfileResult::find_in_directory(directory) {
+        LockfileResult::Lockfile(lockfile) => Some(lockfile),
+        _ => None,
+    }
+}
+
+/// The shims the installed global commands should have, with the sandbox profile they run with
+fn expected_shims(globals_directory: &Path) -> BTreeMap<String, Option<String>> {
+    let mut shims = BTreeMap::new();
+    if let Some(lockfile) = read_lockfile(globals_directory) {
+        for command in lockfile.commands.values() {
+            shims.insert(command.name.clone(), command.sandbox.clone());
+            shims.insert(
+                format!("{}@{}", command.name, command.package_version),
+                command.sandbox.clone(),
+            );
+        }
+    }
+    for directory in global_versions::side_by_side_directories(globals_directory) {
+        for command in read_lockfile(&directory)
+            .map(|lockfile| lockfile.commands)
+            .unwrap_or_default()
+            .values()
+        {
+            shims
+                .entry(format!("{}@{}", command.name, command.package_version))
+                .or_insert_with(|| command.sandbox.clone());
+        }
+    }
+    shims
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .map(|metadata| metadata.permissions().mode() & 0o100 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    true
+}
+
+/// Compare the shims in the bin directory with the installed global commands
+pub fn check(globals_directory: &Path) -> ShimDrift {
+    let bin_directory = bin_directory(globals_directory);
+    let expected = expected_shims(globals_directory);
+    let mut drift = ShimDrift::default();
+    for (name, sandbox) in expected.iter() {
+        let path = bin_directory.join(bin_script_file_name(name));
+        match fs::read_to_string(&path) {
+            Ok(contents)
+                if contents == bin_script_contents(name, sandbox.as_deref())
+                    && is_executable(&path) => {}
+            Ok(_) => drift.outdated.push(name.clone()),
+            Err(_) => drift.missing.push(name.clone()),
+        }
+    }
+    let expected_files: Vec<String> = expected
+        .keys()
+        .map(|name| bin_script_file_name(name))
+        .collect();
+    for entry in fs::read_dir(&bin_directory)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+    {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if expected_files.contains(&file_name) {
+            continue;
+        }
+        let written_by_wapm = fs::read_to_string(entry.path())
+            .map(|contents| is_bin_script(&contents))
+            .unwrap_or(false);
+        if written_by_wapm {
+            drift.stale.push(file_name);
+        }
+    }
+    drift
+}
+
+/// Write the shims of every installed global command and remove the shims of commands that are
+/// not installed anymore. Returns how the shims differed before.
+pub fn rehash(globals_directory: &Path) -> Result<ShimDrift, failure::Error> {
+    let drift = check(globals_directory);
+    let bin_directory = bin_directory(globals_directory);
+    // existing files keep their permissions when they are written again
+    for name in drift.outdated.iter() {
+        fs::remove_file(bin_directory.join(bin_script_file_name(name)))?;
+    }
+    for (name, sandbox) in expected_shims(globals_directory) {
+        save_bin_script(globals_directory, name, sandbox.as_deref())?;
+    }
+    for file_name in drift.stale.iter() {
+        fs::remove_file(bin_directory.join(file_name))?;
+    }
+    Ok(drift)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rehash_restores_the_shims_of_installed_commands() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let globals = tmp_dir.path();
+        let lockfile: Lockfile = toml::from_str(
+            r#"
+[modules."_/tool"."1.0.0".tool]
+name = "tool"
+package_version = "1.0.0"
+package_name = "_/tool"
+package_path = "_/tool@1.0.0"
+resolved = ""
+resolved_source = ""
+abi = "wasi"
+source = "tool.wasm"
+
+[commands.tool]
+name = "tool"
+package_name = "_/tool"
+package_version = "1.0.0"
+module = "tool"
+is_top_level_dependency = true
+"#,
+        )
+        .unwrap();
+        lockfile.save(globals).unwrap();
+        let bin = bin_directory(globals);
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(
+            bin.join(bin_script_file_name("removed")),
+            bin_script_contents("removed", None),
+        )
+        .unwrap();
+        fs::write(bin.join("notes.txt"), "not a shim").unwrap();
+
+        let drift = check(globals);
+        assert_eq!(drift.missing, ["tool", "tool@1.0.0"]);
+        assert_eq!(drift.stale, [bin_script_file_name("removed")]);
+
+        fs::write(bin.join(bin_script_file_name("tool")), "#!/bin/bash\n").unwrap();
+        assert_eq!(check(globals).outdated, ["tool"]);
+
+        rehash(globals).unwrap();
+        assert_eq!(check(globals), ShimDrift::default());
+        assert!(bin.join("notes.txt").exists());
+        assert!(!bin.join(bin_script_file_name("removed")).exists());
+    }
+}
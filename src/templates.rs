@@ -0,0 +1,181 @@
+//! Project templates scaffold a whole package with `wapm init --template <name>`: the source
+//! stubs of a language, a build command writing the module to `entry.wasm`, a placeholder
+//! `entry.wasm` until the first build, and the `[[module]]` and `[[command]]` sections.
+//!
+//! Every template has its own module under `templates/`, and new templates only need to be
+//! registered in `TEMPLATES`. In the files, the build command and the command name, `{name}` is
+//! replaced with the package name without its namespace.
+
+mod emscripten;
+mod wasi_c;
+mod wasi_rust;
+
+use crate::abi::Abi;
+use crate::data::manifest::{Command, Module};
+use crate::init::presets;
+use crate::init::WASI_LAST_VERSION;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where the modules of templates are built
+const MODULE_SOURCE: &str = "entry.wasm";
+/// The name of the module of templates in the manifest
+const MODULE_NAME: &str = "entry";
+/// An empty module, so the package validates before its first build
+const PLACEHOLDER_MODULE: &[u8] = &[0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+
+/// A source file of a template, `{name}` in the path and contents is replaced
+#[derive(Debug)]
+pub struct TemplateFile {
+    pub path: &'static str,
+    pub contents: &'static str,
+}
+
+#[derive(Debug)]
+pub struct Template {
+    /// The name used with `wapm init --template`
+    pub name: &'static str,
+    /// What the template sets up, in one line
+    pub description: &'static str,
+    pub abi: Abi,
+    /// The command that builds the module into `entry.wasm`
+    pub build_command: &'static str,
+    pub files: &'static [TemplateFile],
+    /// The program that must be installed to build the module
+    pub toolchain: &'static str,
+    /// The arguments that make the toolchain print its version, to check that it is installed
+    pub version_args: &'static [&'static str],
+    /// What to do if the toolchain is not installed
+    pub install_instructions: &'static str,
+}
+
+/// The templates of `wapm init --template`
+pub static TEMPLATES: &[&Template] = &[
+    &wasi_rust::TEMPLATE,
+    &wasi_c::TEMPLATE,
+    &emscripten::TEMPLATE,
+];
+
+/// Find a template by name
+pub fn get_template(name: &str) -> Option<&'static Template> {
+    TEMPLATES
+        .iter()
+        .copied()
+        .find(|template| template.name.eq_ignore_ascii_case(name))
+}
+
+/// The names and descriptions of all the templates, one per line for error messages
+pub fn template_list() -> String {
+    TEMPLATES
+        .iter()
+        .map(|template| format!("\n - {}: {}", template.name, template.description))
+        .collect()
+}
+
+/// The package name without its namespace, which is what the files of templates use
+fn short_name(package_name: &str) -> &str {
+    package_name.rsplit('/').next().unwrap_or(package_name)
+}
+
+impl Template {
+    pub fn build_command_for(&self, package_name: &str) -> String {
+        self.build_command
+            .replace("{name}", short_name(package_name))
+    }
+
+    /// Check if the toolchain can be run
+    pub fn toolchain_is_installed(&self) -> bool {
+        presets::toolchain_is_installed(self.toolchain, self.version_args)
+    }
+
+    pub fn module(&self) -> Module {
+        let interfaces = match self.abi {
+            Abi::Wasi => Some(
+                [("wasi".to_owned(), WASI_LAST_VERSION.to_owned())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+            ),
+            _ => None,
+        };
+        Module {
+            name: MODULE_NAME.to_owned(),
+            source: PathBuf::from(MODULE_SOURCE),
+            abi: self.abi,
+            interfaces,
+            exports: None,
+        }
+    }
+
+    pub fn command(&self, package_name: &str) -> Command {
+        Command {
+            name: short_name(package_name).to_owned(),
+            module: MODULE_NAME.to_owned(),
+            main_args: None,
+            package: None,
+            description: None,
+            usage: None,
+            examples: vec![],
+        }
+    }
+
+    /// Write the files of the template and the placeholder module in `dir`, keeping the files
+    /// that already exist. Returns the paths of the written files.
+    pub fn write_files(&self, dir: &Path, package_name: &str) -> Result<Vec<PathBuf>, io::Error> {
+        let name = short_name(package_name);
+        let mut written = vec![];
+        for file in self.files {
+            let path = dir.join(file.path.replace("{name}", name));
+            if path.exists() {
+                continue;
+            }
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&path, file.contents.replace("{name}", name))?;
+            written.push(path);
+        }
+        let module = dir.join(MODULE_SOURCE);
+        if !module.exists() {
+            fs::write(&module, PLACEHOLDER_MODULE)?;
+            written.push(module);
+        }
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn templates_write_their_files() {
+        assert!(get_template("cobol").is_none());
+        let template = get_template("WASI-Rust").unwrap();
+        assert_eq!(
+            template.build_command_for("user/hello"),
+            "cargo build --release --target wasm32-wasi && cp target/wasm32-wasi/release/hello.wasm entry.wasm"
+        );
+        assert_eq!(template.command("user/hello").name, "hello");
+
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir(tmp_dir.path().join("src")).unwrap();
+        fs::write(tmp_dir.path().join("src/main.rs"), "// mine").unwrap();
+        let written = template.write_files(tmp_dir.path(), "hello").unwrap();
+        assert_eq!(
+            written,
+            [
+                tmp_dir.path().join("Cargo.toml"),
+                tmp_dir.path().join("entry.wasm")
+            ]
+        );
+        assert!(fs::read_to_string(tmp_dir.path().join("Cargo.toml"))
+            .unwrap()
+            .contains("name = \"hello\""));
+        assert_eq!(
+            fs::read_to_string(tmp_dir.path().join("src/main.rs")).unwrap(),
+            "// mine"
+        );
+    }
+}
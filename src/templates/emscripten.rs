@@ -0,0 +1,17 @@
+//! A C command built with Emscripten
+
+use super::wasi_c::HELLO_C;
+use super::Template;
+use crate::abi::Abi;
+
+pub const TEMPLATE: Template = Template {
+    name: "emscripten",
+    description: "A C command built with Emscripten",
+    abi: Abi::Emscripten,
+    build_command: "emcc -O2 -o entry.wasm main.c",
+    files: &[HELLO_C],
+    toolchain: "emcc",
+    version_args: &["--version"],
+    install_instructions:
+        "Install Emscripten from https://emscripten.org/docs/getting_started/downloads.html",
+};
@@ -0,0 +1,28 @@
+//! A C command built for WASI with the WASI SDK
+
+use super::{Template, TemplateFile};
+use crate::abi::Abi;
+
+/// The source of the C templates, shared with Emscripten
+pub(super) const HELLO_C: TemplateFile = TemplateFile {
+    path: "main.c",
+    contents: r#"#include <stdio.h>
+
+int main(int argc, char **argv) {
+    printf("Hello from {name}!\n");
+    return 0;
+}
+"#,
+};
+
+pub const TEMPLATE: Template = Template {
+    name: "wasi-c",
+    description: "A C command built for WASI with the WASI SDK",
+    abi: Abi::Wasi,
+    build_command: "clang --target=wasm32-wasi -O2 -o entry.wasm main.c",
+    files: &[HELLO_C],
+    toolchain: "clang",
+    version_args: &["--version"],
+    install_instructions:
+        "Install the WASI SDK from https://github.com/WebAssembly/wasi-sdk and add its `bin` directory to your PATH",
+};
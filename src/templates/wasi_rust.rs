@@ -0,0 +1,34 @@
+//! A Rust command built for WASI with cargo
+
+use super::{Template, TemplateFile};
+use crate::abi::Abi;
+
+pub const TEMPLATE: Template = Template {
+    name: "wasi-rust",
+    description: "A Rust command built for WASI with cargo",
+    abi: Abi::Wasi,
+    build_command: "cargo build --release --target wasm32-wasi && cp target/wasm32-wasi/release/{name}.wasm entry.wasm",
+    files: &[
+        TemplateFile {
+            path: "Cargo.toml",
+            contents: r#"[package]
+name = "{name}"
+version = "0.1.0"
+edition = "2018"
+
+[dependencies]
+"#,
+        },
+        TemplateFile {
+            path: "src/main.rs",
+            contents: r#"fn main() {
+    println!("Hello from {name}!");
+}
+"#,
+        },
+    ],
+    toolchain: "cargo",
+    version_args: &["--version"],
+    install_instructions:
+        "Install Rust from https://rustup.rs and run `rustup target add wasm32-wasi`",
+};
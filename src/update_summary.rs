@@ -0,0 +1,148 @@
+//! Markdown summaries of the dependencies bumped by `wapm upgrade --write-summary`, meant for the
+//! description of a pull request opened by a CI job. The summary links to the registry page of
+//! every package and quotes the changelog entries of the new versions when the packages ship a
+//! changelog.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use semver::Version;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+/// Files with these names, in any case, are taken for the changelog of a package
+const CHANGELOG_FILE_NAMES: &[&str] = &["changelog.md", "changelog", "changes.md", "history.md"];
+/// Longer changelog excerpts are cut to this many lines
+const MAX_EXCERPT_LINES: usize = 40;
+
+lazy_static! {
+    static ref VERSION_RE: Regex = Regex::new(r"\d+\.\d+\.\d+(-[0-9A-Za-z.-]+)?").unwrap();
+}
+
+/// A dependency moved to a newer version
+#[derive(Clone, Debug)]
+pub struct VersionBump {
+    pub name: String,
+    /// The version in the lockfile before the upgrade, if it was locked
+    pub from: Option<Version>,
+    pub to: Version,
+    /// The page of the package on the website of the registry
+    pub page: Option<String>,
+    /// The changelog entries of the versions after `from` up to `to`
+    pub changelog: Option<String>,
+}
+
+/// Read the changelog an installed package ships, if any
+pub fn read_changelog(package_dir: &Path) -> Option<String> {
+    let entries = fs::read_dir(package_dir).ok()?;
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_lowercase();
+        if CHANGELOG_FILE_NAMES.contains(&name.as_str()) {
+            return fs::read_to_string(entry.path()).ok();
+        }
+    }
+    None
+}
+
+/// The sections of a markdown changelog whose headings name a version after `from` up to `to`
+pub fn changelog_excerpt(changelog: &str, from: Option<&Version>, to: &Version) -> Option<String> {
+    let mut excerpt: Vec<&str> = vec![];
+    let mut in_section = false;
+    for line in changelog.lines() {
+        if line.starts_with('#') {
+            let version = VERSION_RE
+                .find(line)
+                .and_then(|found| Version::parse(found.as_str()).ok());
+            if let Some(version) = version {
+                in_section = from.is_none_or(|from| version > *from) && version <= *to;
+            }
+        }
+        if in_section {
+            excerpt.push(line);
+        }
+    }
+    while excerpt.last().is_some_and(|line| line.trim().is_empty()) {
+        excerpt.pop();
+    }
+    if excerpt.is_empty() {
+        return None;
+    }
+    if excerpt.len() > MAX_EXCERPT_LINES {
+        excerpt.truncate(MAX_EXCERPT_LINES);
+        excerpt.push("...");
+    }
+    Some(excerpt.join("\n"))
+}
+
+/// The markdown summary of the bumps
+pub fn render(bumps: &[VersionBump]) -> String {
+    let mut summary = String::new();
+    let _ = writeln!(summary, "## Dependency updates\n");
+    let _ = writeln!(summary, "| Package | From | To |");
+    let _ = writeln!(summary, "| --- | --- | --- |");
+    for bump in bumps {
+        let name = match &bump.page {
+            Some(page) => format!("[{}]({})", bump.name, page),
+            None => format!("`{}`", bump.name),
+        };
+        let from = bump
+            .from
+            .as_ref()
+            .map(|from| from.to_string())
+            .unwrap_or_else(|| "-".to_string());
+        let _ = writeln!(summary, "| {} | {} | {} |", name, from, bump.to);
+    }
+    for bump in bumps {
+        if let Some(changelog) = &bump.changelog {
+            let _ = writeln!(
+                summary,
+                "\n<details>\n<summary>Changelog of {} {}</summary>\n\n{}\n\n</details>",
+                bump.name, bump.to, changelog
+            );
+        }
+    }
+    summary
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CHANGELOG: &str = "# Changelog
+
+## [0.3.0] - 2020-05-01
+- Faster queries
+
+## 0.2.0
+- New API
+
+## 0.1.0
+- First release
+";
+
+    #[test]
+    fn changelog_excerpts_cover_the_new_versions() {
+        let from = Version::parse("0.1.0").unwrap();
+        let to = Version::parse("0.3.0").unwrap();
+        assert_eq!(
+            changelog_excerpt(CHANGELOG, Some(&from), &to).unwrap(),
+            "## [0.3.0] - 2020-05-01\n- Faster queries\n\n## 0.2.0\n- New API"
+        );
+        assert!(changelog_excerpt(CHANGELOG, Some(&to), &to).is_none());
+    }
+
+    #[test]
+    fn summaries_link_to_package_pages() {
+        let summary = render(&[VersionBump {
+            name: "_/sqlite".to_string(),
+            from: Some(Version::parse("0.1.0").unwrap()),
+            to: Version::parse("0.2.0").unwrap(),
+            page: Some("https://wapm.io/package/_/sqlite".to_string()),
+            changelog: Some("## 0.2.0\n- New API".to_string()),
+        }]);
+        assert!(
+            summary.contains("| [_/sqlite](https://wapm.io/package/_/sqlite) | 0.1.0 | 0.2.0 |")
+        );
+        assert!(summary.contains("<summary>Changelog of _/sqlite 0.2.0</summary>"));
+    }
+}
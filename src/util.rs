@@ -1,5 +1,6 @@
+use crate::config::Config;
 use crate::constants::{DEFAULT_RUNTIME, WAPM_RUNTIME_ENV_KEY};
-use crate::data::manifest::PACKAGES_DIR_NAME;
+use crate::data::manifest::{Manifest, PACKAGES_DIR_NAME};
 use crate::graphql::execute_query;
 use graphql_client::*;
 use license_exprs;
@@ -114,18 +115,80 @@ pub fn fully_qualified_package_display_name(
     format!("{}@{}", package_name, package_version)
 }
 
+/// Get the directory that packages are installed into for the project in `project_dir`.
+///
+/// This is `wapm_packages` unless it's changed with `packages-dir` in the project's manifest or
+/// `install.packages-dir` in the wapm config. Relative paths are resolved from `project_dir`.
+pub fn get_packages_dir(project_dir: &Path) -> PathBuf {
+    let manifest_packages_dir = Manifest::find_in_directory(project_dir)
+        .ok()
+        .and_then(|manifest| manifest.package.packages_dir);
+    let packages_dir = manifest_packages_dir.or_else(|| {
+        Config::from_file()
+            .ok()
+            .and_then(|config| config.install.packages_dir)
+    });
+    match packages_dir {
+        Some(packages_dir) => project_dir.join(packages_dir),
+        None => project_dir.join(PACKAGES_DIR_NAME),
+    }
+}
+
 pub fn create_package_dir(
     project_dir: &Path,
     namespace_dir: &str,
     fully_qualified_package_name: &str,
 ) -> Result<PathBuf, io::Error> {
-    let mut package_dir = project_dir.join(PACKAGES_DIR_NAME);
+    let mut package_dir = get_packages_dir(project_dir);
     package_dir.push(namespace_dir);
     package_dir.push(&fully_qualified_package_name);
     fs::create_dir_all(&package_dir)?;
     Ok(package_dir)
 }
 
+/// The total size in bytes of the files in a directory. Symlinks are not followed.
+pub fn get_dir_size(path: &Path) -> u64 {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| get_dir_size(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Format a number of bytes for humans e.g. `1.5 MB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// The SHA-256 checksum of some data, as lowercase hex
+pub fn sha256_hex(data: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
 pub fn wapm_should_print_color() -> bool {
     std::env::var("WAPM_DISABLE_COLOR")
         .map(|_| false)
@@ -344,4 +407,36 @@ mod test {
             ("wasmer".to_owned(), vec!["run".to_owned()])
         );
     }
+
+    #[test]
+    pub fn test_packages_dir_from_manifest() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let manifest = r#"
+[package]
+name = "test"
+version = "1.0.0"
+description = "description"
+packages-dir = "target/wapm_packages"
+"#;
+        fs::write(
+            tmp_dir
+                .path()
+                .join(crate::data::manifest::MANIFEST_FILE_NAME),
+            manifest,
+        )
+        .unwrap();
+        assert_eq!(
+            tmp_dir.path().join("target").join("wapm_packages"),
+            get_packages_dir(tmp_dir.path())
+        );
+    }
+
+    #[test]
+    pub fn test_format_size() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.0 KB");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MB");
+    }
 }
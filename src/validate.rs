@@ -1,8 +1,22 @@
+use crate::abi::detect::{detect_abi, Confidence};
+use crate::abi::emscripten::{self, EMSCRIPTEN_INTERFACE_NAME};
+use crate::abi::Abi;
+use crate::data::manifest::{Manifest, MANIFEST_FILE_NAME};
+use crate::data::manifest_keys::{self, KeyProblem};
 use crate::database;
-use crate::dataflow::{interfaces::InterfaceFromServer, manifest_packages::ManifestResult};
+use crate::dataflow::interfaces::{InterfaceFromServer, InterfaceListing};
+use crate::dataflow::manifest_packages::ManifestResult;
+use crate::diagnostics::{self, Warning};
 use crate::interfaces;
-use std::{fs, io::Read, path::PathBuf};
-use wasm_interface::{validate, Interface};
+use crate::package_symlinks;
+use semver::Version;
+use std::{
+    collections::HashMap,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+use wasm_interface::{parser, validate, Interface};
 
 pub fn validate_directory(pkg_path: PathBuf) -> Result<(), failure::Error> {
     // validate as dir
@@ -11,6 +25,11 @@ pub fn validate_directory(pkg_path: PathBuf) -> Result<(), failure::Error> {
         ManifestResult::ManifestError(e) => return Err(e.into()),
         ManifestResult::Manifest(manifest) => manifest,
     };
+    // links that would not survive packaging fail before the sources are read through them
+    package_symlinks::check_package(&manifest)?;
+    for definition in manifest.interface.iter().flatten() {
+        read_interface_definition(&manifest.base_directory_path.join(&definition.path))?;
+    }
     if let Some(modules) = manifest.module {
         for module in modules.into_iter() {
             let source_path = if module.source.is_relative() {
@@ -31,8 +50,18 @@ pub fn validate_directory(pkg_path: PathBuf) -> Result<(), failure::Error> {
                 }
             })?;
 
+            check_module_abi(&module.name, module.abi, &wasm_buffer, &source_path_string)?;
+
+            // the version of the Emscripten ABI is not an interface that can be validated
+            let interfaces: Option<HashMap<String, String>> = module.interfaces.map(|interfaces| {
+                interfaces
+                    .into_iter()
+                    .filter(|(name, _)| name != EMSCRIPTEN_INTERFACE_NAME)
+                    .collect()
+            });
+
             // hack, short circuit if no interface for now
-            if module.interfaces.is_none() {
+            if interfaces.as_ref().map(HashMap::is_empty).unwrap_or(true) {
                 return validate_wasm_and_report_errors_old(
                     &wasm_buffer[..],
                     source_path_string.clone(),
@@ -41,22 +70,9 @@ pub fn validate_directory(pkg_path: PathBuf) -> Result<(), failure::Error> {
 
             let mut conn = database::open_db()?;
             let mut interface: Interface = Default::default();
-            for (interface_name, interface_version) in
-                module.interfaces.unwrap_or_default().into_iter()
-            {
-                if !interfaces::interface_exists(&mut conn, &interface_name, &interface_version)? {
-                    // download interface and store it if we don't have it locally
-                    let interface_data_from_server = InterfaceFromServer::get(
-                        interface_name.clone(),
-                        interface_version.clone(),
-                    )?;
-                    interfaces::import_interface(
-                        &mut conn,
-                        &interface_name,
-                        &interface_version,
-                        &interface_data_from_server.content,
-                    )?;
-                }
+            for (interface_name, interface_version) in interfaces.unwrap_or_default().into_iter() {
+                // download interface and store it if we don't have it locally
+                interfaces::ensure_interface(&mut conn, &interface_name, &interface_version)?;
                 let sub_interface = interfaces::load_interface_from_db(
                     &mut conn,
                     &interface_name,
@@ -79,6 +95,155 @@ pub fn validate_directory(pkg_path: PathBuf) -> Result<(), failure::Error> {
     Ok(())
 }
 
+/// The keys of the manifest in a package directory that wapm doesn't use, if it has a manifest
+pub fn manifest_key_problems(pkg_path: &Path) -> Result<Vec<KeyProblem>, failure::Error> {
+    let source = match fs::read_to_string(pkg_path.join(MANIFEST_FILE_NAME)) {
+        Ok(source) => source,
+        Err(_) => return Ok(vec![]),
+    };
+    let manifest: toml::Value = toml::from_str(&source)?;
+    Ok(manifest_keys::check_keys(&manifest))
+}
+
+/// Fail if the manifest has keys that wapm doesn't use: unknown keys, or deprecated ones too if
+/// `strict`. Deprecated keys are otherwise only warned about.
+pub fn validate_manifest_keys(pkg_path: &Path, strict: bool) -> Result<(), failure::Error> {
+    let problems = manifest_key_problems(pkg_path)?;
+    let (errors, warnings): (Vec<_>, Vec<_>) = problems
+        .into_iter()
+        .partition(|problem| strict || problem.is_unknown());
+    for warning in warnings {
+        diagnostics::warn(
+            Warning::DeprecatedField,
+            format_args!("{}: {}", MANIFEST_FILE_NAME, warning),
+        );
+    }
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let problems: Vec<String> = errors.iter().map(ToString::to_string).collect();
+    Err(ValidationError::UnusedManifestKeys {
+        problems: problems.join("\n  "),
+    }
+    .into())
+}
+
+/// Read and parse an interface definition
+fn read_interface_definition(path: &Path) -> Result<Interface, ValidationError> {
+    let file = path.to_string_lossy().to_string();
+    let source = fs::read_to_string(path)
+        .map_err(|_| ValidationError::MissingFile { file: file.clone() })?;
+    parser::parse_interface(&source)
+        .map_err(|error| ValidationError::InvalidInterface { file, error })
+}
+
+/// Check the interface definitions of an interface package against the versions already in the
+/// registry: the package version must be newer than the last published version of each
+/// interface, and removing or changing imports or exports needs a major version bump, or a
+/// minor one before 1.0.0.
+pub fn check_interface_versions(manifest: &Manifest) -> Result<(), failure::Error> {
+    let version = &manifest.package.version;
+    for definition in manifest.interface.iter().flatten() {
+        let listing = match InterfaceListing::get(definition.name.clone())? {
+            Some(listing) => listing,
+            None => continue,
+        };
+        let last_version = match listing.last_version {
+            Some(last_version) => last_version,
+            None => continue,
+        };
+        let interface_error = |error: String| ValidationError::InvalidInterface {
+            file: format!("{}@{}", definition.name, last_version),
+            error,
+        };
+        let last = Version::parse(&last_version).map_err(|e| interface_error(e.to_string()))?;
+        if *version <= last {
+            return Err(ValidationError::InterfaceVersionNotNewer {
+                name: definition.name.clone(),
+                version: version.to_string(),
+                last: last_version,
+            }
+            .into());
+        }
+        let published =
+            InterfaceFromServer::get(definition.name.clone(), last_version.clone())?.content;
+        let published = parser::parse_interface(&published).map_err(interface_error)?;
+        let interface =
+            read_interface_definition(&manifest.base_directory_path.join(&definition.path))?;
+        let changes = breaking_changes(&published, &interface);
+        let is_breaking_bump =
+            version.major > last.major || (last.major == 0 && version.minor > last.minor);
+        if !changes.is_empty() && !is_breaking_bump {
+            return Err(ValidationError::BreakingInterfaceChange {
+                name: definition.name.clone(),
+                version: version.to_string(),
+                last: last_version,
+                changes: changes.join(", "),
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// The imports and exports of `old` that `new` removed or changed
+fn breaking_changes(old: &Interface, new: &Interface) -> Vec<String> {
+    let mut changes: Vec<String> = old
+        .imports
+        .iter()
+        .filter(|(key, import)| new.imports.get(key) != Some(import))
+        .map(|((namespace, name), _)| format!("import \"{}\" \"{}\"", namespace, name))
+        .chain(
+            old.exports
+                .iter()
+                .filter(|(key, export)| new.exports.get(*key) != Some(export))
+                .map(|(name, _)| format!("export \"{}\"", name)),
+        )
+        .collect();
+    changes.sort();
+    changes
+}
+
+/// Warn if the imports of a module don't match its ABI, and about Emscripten modules that need
+/// the JavaScript glue code generated by Emscripten, as they can't run standalone.
+fn check_module_abi(
+    module_name: &str,
+    abi: Abi,
+    wasm: &[u8],
+    file: &str,
+) -> Result<(), ValidationError> {
+    let invalid_wasm = |error| ValidationError::InvalidWasm {
+        file: file.to_string(),
+        error,
+    };
+    let detection = detect_abi(wasm).map_err(invalid_wasm)?;
+    if detection.abi != abi && detection.confidence == Confidence::High {
+        diagnostics::warn(
+            Warning::AbiMismatch,
+            format_args!(
+                "\"{}\" looks like a {} module but module \"{}\" uses the {} ABI: it {}",
+                file,
+                detection.abi,
+                module_name,
+                abi,
+                detection.notes.join(", ")
+            ),
+        );
+    }
+    if abi == Abi::Emscripten {
+        if let Some(emscripten_module) = emscripten::inspect_module(wasm).map_err(invalid_wasm)? {
+            if !emscripten_module.runs_standalone() {
+                warn!(
+                    "Module \"{}\" needs the JavaScript glue code generated by Emscripten for the imports {} and can't run standalone. Build it with `-s STANDALONE_WASM` to run it with Wasmer",
+                    module_name,
+                    emscripten_module.js_glue_imports.join(", ")
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, Fail)]
 pub enum ValidationError {
     #[fail(
@@ -92,6 +257,32 @@ pub enum ValidationError {
     MiscCannotRead { file: String, error: String },
     #[fail(display = "Failed to unpack archive \"{}\"! {}", file, error)]
     CannotUnpackArchive { file: String, error: String },
+    #[fail(
+        display = "The manifest has keys that wapm doesn't use:\n  {}",
+        problems
+    )]
+    UnusedManifestKeys { problems: String },
+    #[fail(display = "Interface definition \"{}\" is invalid: {}", file, error)]
+    InvalidInterface { file: String, error: String },
+    #[fail(
+        display = "Interface {} can't be published as {}, its last version is {}",
+        name, version, last
+    )]
+    InterfaceVersionNotNewer {
+        name: String,
+        version: String,
+        last: String,
+    },
+    #[fail(
+        display = "Interface {} {} removes or changes {} of {}, which needs a major version bump",
+        name, version, changes, last
+    )]
+    BreakingInterfaceChange {
+        name: String,
+        version: String,
+        last: String,
+        changes: String,
+    },
 }
 
 // legacy function, validates wasm.  TODO: clean up
@@ -116,3 +307,39 @@ pub fn validate_wasm_and_report_errors_old(
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn removed_and_changed_entries_are_breaking() {
+        let old = parser::parse_interface(
+            r#"(interface "calculator"
+                (func (import "env" "log") (param i32))
+                (func (export "add") (param i32 i32) (result i32))
+                (func (export "sub") (param i32 i32) (result i32)))"#,
+        )
+        .unwrap();
+        let added = parser::parse_interface(
+            r#"(interface "calculator"
+                (func (import "env" "log") (param i32))
+                (func (export "add") (param i32 i32) (result i32))
+                (func (export "sub") (param i32 i32) (result i32))
+                (func (export "mul") (param i32 i32) (result i32)))"#,
+        )
+        .unwrap();
+        assert!(breaking_changes(&old, &added).is_empty());
+
+        let changed = parser::parse_interface(
+            r#"(interface "calculator"
+                (func (export "add") (param i64 i64) (result i64))
+                (func (export "sub") (param i32 i32) (result i32)))"#,
+        )
+        .unwrap();
+        assert_eq!(
+            breaking_changes(&old, &changed),
+            vec!["export \"add\"", "import \"env\" \"log\""]
+        );
+    }
+}
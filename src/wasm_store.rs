@@ -0,0 +1,194 @@
+//! A content-addressed store of the `.wasm` modules of installed packages. Packages often ship
+//! byte-identical modules, e.g. the same version of a library in many packages, and those are
+//! kept on disk once: the store has one copy named after its sha256 hash and the modules of the
+//! packages are hard links to it.
+//!
+//! A blob in the store is shared by every package linking to it, so a damaged blob damages all
+//! of them. `wapm doctor` checks that blobs still match their hashes and `wapm clean` removes
+//! the blobs no package links to anymore. Deduplicating is turned off with
+//! `wapm config set install.dedup-modules false`.
+
+use crate::util::sha256_hex;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const MODULE_EXTENSION: &str = "wasm";
+
+/// What deduplicating or pruning did
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StoreSummary {
+    pub modules: usize,
+    pub bytes: u64,
+}
+
+/// Replace the modules in `package_dir` that the store already has by links to the store, and
+/// add the others to the store. Returns the modules that were replaced and the bytes saved.
+pub fn dedup_modules(store: &Path, package_dir: &Path) -> io::Result<StoreSummary> {
+    let mut summary = StoreSummary::default();
+    for module in find_modules(package_dir) {
+        if link_count(&module)? > 1 {
+            // already linked to the store
+            continue;
+        }
+        let data = fs::read(&module)?;
+        let blob = blob_path(store, &data);
+        if blob.exists() {
+            if fs::read(&blob)? != data {
+                warn!(
+                    "The module store has a damaged copy of {}, run `wapm doctor`",
+                    module.display()
+                );
+                continue;
+            }
+            // link next to the module first, so the module is replaced in one step
+            let linked = module.with_extension("wasm.linking");
+            if let Err(e) = fs::hard_link(&blob, &linked) {
+                debug!("Could not link {} to the store: {}", module.display(), e);
+                continue;
+            }
+            fs::rename(&linked, &module)?;
+            summary.modules += 1;
+            summary.bytes += data.len() as u64;
+        } else {
+            fs::create_dir_all(store)?;
+            // links can't cross file systems, the module is then kept as it is
+            if let Err(e) = fs::hard_link(&module, &blob) {
+                debug!("Could not add {} to the store: {}", module.display(), e);
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Remove the blobs no installed module links to anymore, or only list them with `dry_run`.
+/// Links are only counted on unix, elsewhere nothing is removed.
+pub fn prune(store: &Path, dry_run: bool) -> io::Result<StoreSummary> {
+    let mut summary = StoreSummary::default();
+    for blob in blobs(store)? {
+        if !cfg!(unix) || link_count(&blob)? > 1 {
+            continue;
+        }
+        summary.modules += 1;
+        summary.bytes += fs::metadata(&blob)?.len();
+        if !dry_run {
+            fs::remove_file(&blob)?;
+        }
+    }
+    Ok(summary)
+}
+
+/// The blobs whose contents no longer match the hash they are named after
+pub fn damaged_blobs(store: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut damaged = vec![];
+    for blob in blobs(store)? {
+        if blob_path(store, &fs::read(&blob)?) != blob {
+            damaged.push(blob);
+        }
+    }
+    Ok(damaged)
+}
+
+fn blob_path(store: &Path, data: &[u8]) -> PathBuf {
+    store.join(format!("{}.{}", sha256_hex(data), MODULE_EXTENSION))
+}
+
+fn blobs(store: &Path) -> io::Result<Vec<PathBuf>> {
+    if !store.is_dir() {
+        return Ok(vec![]);
+    }
+    let mut blobs = vec![];
+    for entry in fs::read_dir(store)? {
+        let path = entry?.path();
+        if is_module(&path) {
+            blobs.push(path);
+        }
+    }
+    blobs.sort();
+    Ok(blobs)
+}
+
+/// The modules in a directory and its subdirectories, not following symlinks
+fn find_modules(directory: &Path) -> Vec<PathBuf> {
+    let mut modules = vec![];
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return modules,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => modules.extend(find_modules(&path)),
+            Ok(metadata) if metadata.is_file() && is_module(&path) => modules.push(path),
+            _ => {}
+        }
+    }
+    modules
+}
+
+fn is_module(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension == MODULE_EXTENSION)
+        .unwrap_or(false)
+}
+
+#[cfg(unix)]
+fn link_count(path: &Path) -> io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(fs::metadata(path)?.nlink())
+}
+
+#[cfg(not(unix))]
+fn link_count(_path: &Path) -> io::Result<u64> {
+    Ok(1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn identical_modules_are_stored_once() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let store = dir.path().join("store");
+        let first = dir.path().join("first");
+        let second = dir.path().join("second");
+        for package in [&first, &second].iter() {
+            fs::create_dir_all(package.join("lib")).unwrap();
+            fs::write(package.join("lib/shared.wasm"), b"\0asm shared").unwrap();
+        }
+        fs::write(second.join("own.wasm"), b"\0asm own").unwrap();
+
+        assert_eq!(
+            dedup_modules(&store, &first).unwrap(),
+            StoreSummary::default()
+        );
+        assert_eq!(
+            dedup_modules(&store, &second).unwrap(),
+            StoreSummary {
+                modules: 1,
+                bytes: 11
+            }
+        );
+        assert_eq!(blobs(&store).unwrap().len(), 2);
+        assert_eq!(
+            fs::read(second.join("lib/shared.wasm")).unwrap(),
+            b"\0asm shared"
+        );
+        assert!(damaged_blobs(&store).unwrap().is_empty());
+
+        if cfg!(unix) {
+            assert_eq!(prune(&store, false).unwrap().modules, 0);
+            fs::remove_dir_all(&second).unwrap();
+            // the shared module is still used by the first package
+            assert_eq!(
+                prune(&store, false).unwrap(),
+                StoreSummary {
+                    modules: 1,
+                    bytes: 8
+                }
+            );
+            assert_eq!(blobs(&store).unwrap().len(), 1);
+        }
+    }
+}
@@ -0,0 +1,789 @@
+//! Converting packages between the classic `.tar.gz` archives and wasmer's webc containers.
+//!
+//! Registries may serve webc containers instead of archives: installs turn them back into the
+//! archive layout before extracting them, so they get the same checks as any other archive.
+//! `wapm publish --webc` uploads a container and `wapm convert` converts by hand.
+//!
+//! A v1 container is laid out as:
+//!
+//! - the magic `\0webc` and the version `001`
+//! - a 16 byte checksum type (`sha256` padded with `-`, or only `-` without a checksum) and a
+//!   256 byte field with the SHA-256 checksum of everything after the signature
+//! - a little-endian `u32` signature length and a 1024 byte signature field
+//! - the CBOR manifest, with the package metadata, atoms and commands
+//! - the atoms volume, with the modules of the package
+//! - the named volumes: `metadata` with the `wapm.toml`, readme and license, and `atom` with the
+//!   mapped directories of the package
+//!
+//! Sections and volumes are prefixed with their little-endian `u64` length, and the volumes are
+//! prefixed with their length-prefixed name.
+//!
+//! A volume is a length-prefixed header followed by the contents of its files. The header is a
+//! list of directory levels, starting with the root. A level is its `u64` length followed by an
+//! entry for every file and directory in it, sorted by name: a `u64` with the entry type in its
+//! top byte (`0` for a directory, `1` for a file) and the length of the name in the rest, the
+//! `u64` start and end offsets, and the name. The offsets of a directory are those of its level
+//! in the header, the offsets of a file those of its contents after the header.
+
+use crate::abi::Abi;
+use crate::data::manifest::{Command, Manifest, Module, MANIFEST_FILE_NAME};
+use crate::util::sha256_hex;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use semver::Version;
+use serde_cbor::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+pub const MAGIC: &[u8] = b"\0webc";
+const VERSION: &[u8] = b"001";
+const CHECKSUM_TYPE_SHA256: &[u8; 16] = b"sha256----------";
+const CHECKSUM_LENGTH: usize = 256;
+const SIGNATURE_LENGTH: usize = 1024;
+/// The size of the magic, version, checksum and signature
+const HEADER_LENGTH: usize = 8 + 16 + CHECKSUM_LENGTH + 4 + SIGNATURE_LENGTH;
+
+const ATOM_KIND_WASM: &str = "https://webc.org/kind/wasm";
+const RUNNER_WASI: &str = "https://webc.org/runner/wasi";
+const RUNNER_EMSCRIPTEN: &str = "https://webc.org/runner/emscripten";
+/// The volume with the manifest, readme and license of the package
+const METADATA_VOLUME: &str = "metadata";
+/// The volume with the mapped directories of the package
+const ATOM_VOLUME: &str = "atom";
+/// The size of a volume header entry without its name
+const ENTRY_LENGTH: usize = 24;
+const ENTRY_DIRECTORY: u64 = 0;
+const ENTRY_FILE: u64 = 1;
+
+#[derive(Debug, Fail)]
+pub enum WebcError {
+    #[fail(display = "The file is not a webc container")]
+    NotWebc,
+    #[fail(display = "Version {} of the webc format is not supported", _0)]
+    UnsupportedVersion(String),
+    #[fail(display = "The webc container is truncated")]
+    Truncated,
+    #[fail(display = "The checksum of the webc container does not match its contents")]
+    ChecksumMismatch,
+    #[fail(display = "The manifest of the webc container is invalid: {}", _0)]
+    InvalidManifest(String),
+    #[fail(display = "The webc container has no atom for the module {}", _0)]
+    MissingAtom(String),
+    #[fail(display = "The package has no file {} for the module {}", _0, _1)]
+    MissingModule(String, String),
+    #[fail(display = "The package has an invalid path: {}", _0)]
+    InvalidPath(String),
+    #[fail(display = "Could not convert the package: {}", _0)]
+    Io(String),
+}
+
+/// The CBOR manifest of a webc container
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct WebcManifest {
+    /// Package metadata, keyed by the tool that wrote it. wapm writes its `[package]` table
+    /// under `wapm`.
+    #[serde(default)]
+    pub package: BTreeMap<String, Value>,
+    #[serde(default)]
+    pub atoms: BTreeMap<String, Atom>,
+    #[serde(default)]
+    pub commands: BTreeMap<String, WebcCommand>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entrypoint: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Atom {
+    pub kind: String,
+    /// The checksum of the atom, like `sha256:<hex>`
+    pub signature: String,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct WebcCommand {
+    pub runner: String,
+    /// Settings for the runner, keyed by the runner: wapm writes the atom, arguments and package
+    /// of the command under `wasi` or `emscripten`
+    #[serde(default)]
+    pub annotations: BTreeMap<String, Value>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+struct CommandAnnotation {
+    atom: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    main_args: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    package: Option<String>,
+}
+
+/// A package in the webc format
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WebcPackage {
+    pub manifest: WebcManifest,
+    /// The modules of the package, by name
+    pub atoms: BTreeMap<String, Vec<u8>>,
+    /// The files of the package, by volume and path
+    pub volumes: BTreeMap<String, BTreeMap<String, Vec<u8>>>,
+}
+
+/// Whether the data starts like a webc container
+pub fn is_webc(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+impl WebcPackage {
+    /// Read a webc container, checking its checksum
+    pub fn parse(data: &[u8]) -> Result<Self, WebcError> {
+        if !is_webc(data) {
+            return Err(WebcError::NotWebc);
+        }
+        if data.len() < HEADER_LENGTH {
+            return Err(WebcError::Truncated);
+        }
+        let version = &data[MAGIC.len()..8];
+        if version != VERSION {
+            return Err(WebcError::UnsupportedVersion(
+                String::from_utf8_lossy(version).to_string(),
+            ));
+        }
+        let body = &data[HEADER_LENGTH..];
+        if &data[8..24] == CHECKSUM_TYPE_SHA256 {
+            let checksum = &data[24..24 + 32];
+            if checksum != &Sha256::digest(body)[..] {
+                return Err(WebcError::ChecksumMismatch);
+            }
+        }
+
+        let mut reader = SectionReader { data: body };
+        let manifest = serde_cbor::from_slice(reader.section()?)
+            .map_err(|e| WebcError::InvalidManifest(e.to_string()))?;
+        let atoms = parse_volume(reader.section()?)?;
+        let mut volumes = BTreeMap::new();
+        while !reader.data.is_empty() {
+            let name = String::from_utf8(reader.section()?.to_vec())
+                .map_err(|e| WebcError::InvalidManifest(e.to_string()))?;
+            volumes.insert(name, parse_volume(reader.section()?)?);
+        }
+        Ok(Self {
+            manifest,
+            atoms,
+            volumes,
+        })
+    }
+
+    /// Write the package as a webc container
+    pub fn to_bytes(&self) -> Result<Vec<u8>, WebcError> {
+        let mut body = vec![];
+        let manifest = serde_cbor::to_vec(&self.manifest)
+            .map_err(|e| WebcError::InvalidManifest(e.to_string()))?;
+        write_section(&mut body, &manifest);
+        write_section(&mut body, &volume_bytes(&self.atoms)?);
+        for (name, files) in self.volumes.iter() {
+            write_section(&mut body, name.as_bytes());
+            write_section(&mut body, &volume_bytes(files)?);
+        }
+
+        let mut data = Vec::with_capacity(HEADER_LENGTH + body.len());
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(VERSION);
+        data.extend_from_slice(CHECKSUM_TYPE_SHA256);
+        let mut checksum = Sha256::digest(&body).to_vec();
+        checksum.resize(CHECKSUM_LENGTH, 0);
+        data.extend_from_slice(&checksum);
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&[0; SIGNATURE_LENGTH]);
+        data.extend_from_slice(&body);
+        Ok(data)
+    }
+
+    /// Convert the uncompressed tarball of a package, as it is published, to the webc format
+    pub fn from_tar(tar_data: &[u8]) -> Result<Self, WebcError> {
+        let mut files = BTreeMap::new();
+        let mut archive = tar::Archive::new(tar_data);
+        for entry in archive.entries().map_err(io_error)? {
+            let mut entry = entry.map_err(io_error)?;
+            if entry.header().entry_type() != tar::EntryType::Regular {
+                continue;
+            }
+            let path = volume_path(&entry.path().map_err(io_error)?)?;
+            let mut contents = vec![];
+            entry.read_to_end(&mut contents).map_err(io_error)?;
+            files.insert(path, contents);
+        }
+        let manifest_source = files.get(MANIFEST_FILE_NAME).ok_or_else(|| {
+            WebcError::InvalidManifest("the package has no wapm.toml".to_string())
+        })?;
+        let manifest: Manifest = toml::from_slice(manifest_source)
+            .map_err(|e| WebcError::InvalidManifest(e.to_string()))?;
+
+        let mut package = WebcPackage::default();
+        let wapm_package = serde_cbor::value::to_value(&manifest.package)
+            .map_err(|e| WebcError::InvalidManifest(e.to_string()))?;
+        package
+            .manifest
+            .package
+            .insert("wapm".to_string(), wapm_package);
+        let mut module_paths = vec![];
+        for module in manifest.module.iter().flatten() {
+            let source = volume_path(&module.source)?;
+            let (path, contents) = files
+                .iter()
+                .find(|(path, _)| **path == source || path.ends_with(&format!("/{}", source)))
+                .ok_or_else(|| WebcError::MissingModule(source.clone(), module.name.clone()))?;
+            module_paths.push(path.clone());
+            package.manifest.atoms.insert(
+                module.name.clone(),
+                Atom {
+                    kind: ATOM_KIND_WASM.to_string(),
+                    signature: format!("sha256:{}", sha256_hex(contents)),
+                },
+            );
+            package.atoms.insert(module.name.clone(), contents.clone());
+        }
+        for command in manifest.command.iter().flatten() {
+            let abi = manifest
+                .module
+                .iter()
+                .flatten()
+                .find(|module| module.name == command.module)
+                .map(|module| module.abi)
+                .unwrap_or(Abi::None);
+            let (runner, annotation_key) = match abi {
+                Abi::Emscripten => (RUNNER_EMSCRIPTEN, "emscripten"),
+                _ => (RUNNER_WASI, "wasi"),
+            };
+            let annotation = CommandAnnotation {
+                atom: command.module.clone(),
+                main_args: command.main_args.clone(),
+                package: command.package.clone(),
+            };
+            let annotation = serde_cbor::value::to_value(&annotation)
+                .map_err(|e| WebcError::InvalidManifest(e.to_string()))?;
+            let mut annotations = BTreeMap::new();
+            annotations.insert(annotation_key.to_string(), annotation);
+            package.manifest.commands.insert(
+                command.name.clone(),
+                WebcCommand {
+                    runner: runner.to_string(),
+                    annotations,
+                },
+            );
+        }
+        if package.manifest.commands.len() == 1 {
+            package.manifest.entrypoint = package.manifest.commands.keys().next().cloned();
+        }
+
+        let metadata_paths: Vec<String> =
+            [&manifest.package.readme, &manifest.package.license_file]
+                .iter()
+                .filter_map(|path| path.as_ref())
+                .map(|path| volume_path(path))
+                .collect::<Result<_, _>>()?;
+        for (path, contents) in files {
+            if module_paths.contains(&path) {
+                continue;
+            }
+            let volume = if path == MANIFEST_FILE_NAME
+                || path == "LICENSE"
+                || metadata_paths.contains(&path)
+            {
+                METADATA_VOLUME
+            } else {
+                ATOM_VOLUME
+            };
+            package
+                .volumes
+                .entry(volume.to_string())
+                .or_default()
+                .insert(path, contents);
+        }
+        Ok(package)
+    }
+
+    /// Convert the package to the uncompressed tarball of the classic layout. Containers written
+    /// by other tools get a `wapm.toml` generated from their manifest.
+    pub fn to_tar(&self) -> Result<Vec<u8>, WebcError> {
+        let mut files: BTreeMap<String, &[u8]> = BTreeMap::new();
+        for volume in self.volumes.values() {
+            for (path, contents) in volume {
+                files.insert(path.clone(), contents);
+            }
+        }
+        let generated_manifest;
+        let manifest = match files.get(MANIFEST_FILE_NAME) {
+            Some(source) => {
+                toml::from_slice(source).map_err(|e| WebcError::InvalidManifest(e.to_string()))?
+            }
+            None => {
+                let manifest = self.generate_manifest()?;
+                generated_manifest = toml::to_string(&manifest)
+                    .map_err(|e| WebcError::InvalidManifest(e.to_string()))?;
+                files.insert(
+                    MANIFEST_FILE_NAME.to_string(),
+                    generated_manifest.as_bytes(),
+                );
+                manifest
+            }
+        };
+        let manifest: Manifest = manifest;
+        for module in manifest.module.iter().flatten() {
+            let atom = self
+                .atoms
+                .get(&module.name)
+                .ok_or_else(|| WebcError::MissingAtom(module.name.clone()))?;
+            files.insert(volume_path(&module.source)?, atom);
+        }
+
+        let mut builder = tar::Builder::new(vec![]);
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, &path, contents)
+                .map_err(io_error)?;
+        }
+        builder.into_inner().map_err(io_error)
+    }
+
+    /// A `wapm.toml` for a container without one, from the metadata and commands of its manifest
+    fn generate_manifest(&self) -> Result<Manifest, WebcError> {
+        let metadata: BTreeMap<String, Value> = match self.manifest.package.get("wapm") {
+            Some(value) => serde_cbor::value::from_value(value.clone())
+                .map_err(|e| WebcError::InvalidManifest(e.to_string()))?,
+            None => BTreeMap::new(),
+        };
+        let text = |key: &str| match metadata.get(key) {
+            Some(Value::Text(text)) => Some(text.clone()),
+            _ => None,
+        };
+        let name = text("name")
+            .ok_or_else(|| WebcError::InvalidManifest("the package has no name".to_string()))?;
+        let version = text("version")
+            .and_then(|version| Version::parse(&version).ok())
+            .ok_or_else(|| WebcError::InvalidManifest("the package has no version".to_string()))?;
+
+        let mut abis = BTreeMap::new();
+        let mut commands = vec![];
+        for (command_name, command) in self.manifest.commands.iter() {
+            let (abi, key) = match command.runner.as_str() {
+                RUNNER_EMSCRIPTEN => (Abi::Emscripten, "emscripten"),
+                _ => (Abi::Wasi, "wasi"),
+            };
+            let annotation: CommandAnnotation = match command.annotations.get(key) {
+                Some(value) => serde_cbor::value::from_value(value.clone())
+                    .map_err(|e| WebcError::InvalidManifest(e.to_string()))?,
+                None => continue,
+            };
+            abis.insert(annotation.atom.clone(), abi);
+            commands.push(Command {
+                name: command_name.clone(),
+                module: annotation.atom,
+                main_args: annotation.main_args,
+                package: annotation.package,
+                description: None,
+                usage: None,
+                examples: vec![],
+            });
+        }
+        let modules = self
+            .atoms
+            .keys()
+            .map(|atom| {
+                let mut module: Module = toml::from_str(&format!(
+                    "name = {:?}\nsource = {:?}",
+                    atom,
+                    format!("{}.wasm", atom)
+                ))
+                .map_err(|e| WebcError::InvalidManifest(e.to_string()))?;
+                module.abi = abis.get(atom).copied().unwrap_or(Abi::None);
+                Ok(module)
+            })
+            .collect::<Result<Vec<_>, WebcError>>()?;
+        let mut manifest: Manifest = toml::from_str(&format!(
+            "[package]\nname = {:?}\nversion = {:?}\ndescription = {:?}",
+            name,
+            version.to_string(),
+            text("description").unwrap_or_default()
+        ))
+        .map_err(|e| WebcError::InvalidManifest(e.to_string()))?;
+        manifest.package.license = text("license");
+        manifest.package.repository = text("repository");
+        manifest.package.homepage = text("homepage");
+        manifest.module = Some(modules).filter(|modules| !modules.is_empty());
+        manifest.command = Some(commands).filter(|commands| !commands.is_empty());
+        Ok(manifest)
+    }
+}
+
+/// Turn a webc container into a gzipped archive of the classic layout, and leave anything else
+/// as it is
+pub fn archive_from_download(data: Vec<u8>) -> Result<Vec<u8>, WebcError> {
+    if !is_webc(&data) {
+        return Ok(data);
+    }
+    gzip(&WebcPackage::parse(&data)?.to_tar()?)
+}
+
+pub fn gzip(data: &[u8]) -> Result<Vec<u8>, WebcError> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(data).map_err(io_error)?;
+    encoder.finish().map_err(io_error)
+}
+
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>, WebcError> {
+    let mut decompressed = vec![];
+    GzDecoder::new(data)
+        .read_to_end(&mut decompressed)
+        .map_err(io_error)?;
+    Ok(decompressed)
+}
+
+fn io_error(e: std::io::Error) -> WebcError {
+    WebcError::Io(e.to_string())
+}
+
+/// A relative path with `/` separators, rejecting paths leaving the package
+fn volume_path(path: &Path) -> Result<String, WebcError> {
+    let mut parts = vec![];
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_string_lossy().to_string()),
+            Component::CurDir => {}
+            _ => return Err(WebcError::InvalidPath(path.to_string_lossy().to_string())),
+        }
+    }
+    if parts.is_empty() {
+        return Err(WebcError::InvalidPath(path.to_string_lossy().to_string()));
+    }
+    Ok(parts.join("/"))
+}
+
+fn write_section(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+/// A directory of a volume being written
+#[derive(Default)]
+struct Directory<'a> {
+    entries: BTreeMap<&'a str, Node<'a>>,
+}
+
+enum Node<'a> {
+    Directory(Directory<'a>),
+    File(&'a [u8]),
+}
+
+impl<'a> Directory<'a> {
+    fn insert(&mut self, path: &'a str, contents: &'a [u8]) -> Result<(), WebcError> {
+        let invalid_path = || WebcError::InvalidPath(path.to_string());
+        let mut directory = self;
+        let mut parts = path.split('/').peekable();
+        while let Some(part) = parts.next() {
+            if parts.peek().is_none() {
+                if directory.entries.contains_key(part) {
+                    return Err(invalid_path());
+                }
+                directory.entries.insert(part, Node::File(contents));
+                break;
+            }
+            let node = directory
+                .entries
+                .entry(part)
+                .or_insert_with(|| Node::Directory(Directory::default()));
+            directory = match node {
+                Node::Directory(child) => child,
+                Node::File(_) => return Err(invalid_path()),
+            };
+        }
+        Ok(())
+    }
+
+    fn level_length(&self) -> usize {
+        8 + self
+            .entries
+            .keys()
+            .map(|name| ENTRY_LENGTH + name.len())
+            .sum::<usize>()
+    }
+}
+
+fn volume_bytes(files: &BTreeMap<String, Vec<u8>>) -> Result<Vec<u8>, WebcError> {
+    let mut root = Directory::default();
+    for (path, contents) in files {
+        root.insert(path, contents)?;
+    }
+
+    // the levels go breadth first, so every directory is before the levels of its children
+    let mut levels = vec![&root];
+    let mut next = 0;
+    while next < levels.len() {
+        for node in levels[next].entries.values() {
+            if let Node::Directory(child) = node {
+                levels.push(child);
+            }
+        }
+        next += 1;
+    }
+    let mut level_offsets = Vec::with_capacity(levels.len());
+    let mut header_length = 0;
+    for level in levels.iter() {
+        level_offsets.push(header_length);
+        header_length += level.level_length();
+    }
+
+    let mut header = Vec::with_capacity(header_length);
+    let mut data = vec![];
+    let mut next_level = 1;
+    for level in levels.iter() {
+        header.extend_from_slice(&((level.level_length() - 8) as u64).to_le_bytes());
+        for (name, node) in level.entries.iter() {
+            let (kind, start, end) = match node {
+                Node::Directory(child) => {
+                    let start = level_offsets[next_level];
+                    next_level += 1;
+                    (ENTRY_DIRECTORY, start, start + child.level_length())
+                }
+                Node::File(contents) => {
+                    let start = data.len();
+                    data.extend_from_slice(contents);
+                    (ENTRY_FILE, start, data.len())
+                }
+            };
+            header.extend_from_slice(&(kind << 56 | name.len() as u64).to_le_bytes());
+            header.extend_from_slice(&(start as u64).to_le_bytes());
+            header.extend_from_slice(&(end as u64).to_le_bytes());
+            header.extend_from_slice(name.as_bytes());
+        }
+    }
+
+    let mut volume = Vec::with_capacity(8 + header.len() + data.len());
+    write_section(&mut volume, &header);
+    volume.extend_from_slice(&data);
+    Ok(volume)
+}
+
+fn parse_volume(data: &[u8]) -> Result<BTreeMap<String, Vec<u8>>, WebcError> {
+    let mut reader = SectionReader { data };
+    let header = reader.section()?;
+    let mut files = BTreeMap::new();
+    parse_level(header, 0, header.len(), "", reader.data, &mut files)?;
+    Ok(files)
+}
+
+/// Read the directory level between `start` and `end` of the header, and the levels of its
+/// directories
+fn parse_level(
+    header: &[u8],
+    start: usize,
+    end: usize,
+    prefix: &str,
+    data: &[u8],
+    files: &mut BTreeMap<String, Vec<u8>>,
+) -> Result<(), WebcError> {
+    let level = header.get(start..end).ok_or(WebcError::Truncated)?;
+    let mut reader = SectionReader { data: level };
+    let mut entries = reader.section()?;
+    let level_end = start + 8 + entries.len();
+    while !entries.is_empty() {
+        let mut fields = SectionReader { data: entries };
+        let kind_and_length = fields.length()?;
+        let entry_start = fields.length()? as usize;
+        let entry_end = fields.length()? as usize;
+        let name_length = (kind_and_length & 0x00ff_ffff_ffff_ffff) as usize;
+        if name_length > fields.data.len() {
+            return Err(WebcError::Truncated);
+        }
+        let (name, rest) = fields.data.split_at(name_length);
+        entries = rest;
+
+        let name = std::str::from_utf8(name).map_err(|e| WebcError::InvalidPath(e.to_string()))?;
+        let path = volume_path(&PathBuf::from(format!("{}{}", prefix, name)))?;
+        match kind_and_length >> 56 {
+            // a level is always after the level of its parent, which also rules out loops
+            ENTRY_DIRECTORY if entry_start >= level_end => {
+                parse_level(
+                    header,
+                    entry_start,
+                    entry_end,
+                    &format!("{}/", path),
+                    data,
+                    files,
+                )?;
+            }
+            ENTRY_FILE => {
+                let contents = data
+                    .get(entry_start..entry_end)
+                    .ok_or(WebcError::Truncated)?;
+                files.insert(path, contents.to_vec());
+            }
+            _ => return Err(WebcError::InvalidPath(path)),
+        }
+    }
+    Ok(())
+}
+
+/// Reads length-prefixed sections, failing instead of reading past the end
+struct SectionReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> SectionReader<'a> {
+    fn length(&mut self) -> Result<u64, WebcError> {
+        if self.data.len() < 8 {
+            return Err(WebcError::Truncated);
+        }
+        let mut length = [0; 8];
+        length.copy_from_slice(&self.data[..8]);
+        self.data = &self.data[8..];
+        Ok(u64::from_le_bytes(length))
+    }
+
+    fn section(&mut self) -> Result<&'a [u8], WebcError> {
+        let length = self.length()?;
+        if length > self.data.len() as u64 {
+            return Err(WebcError::Truncated);
+        }
+        let (section, rest) = self.data.split_at(length as usize);
+        self.data = rest;
+        Ok(section)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn package_tar() -> Vec<u8> {
+        let files: &[(&str, &[u8])] = &[
+            (
+                "wapm.toml",
+                br#"[package]
+name = "_/hello"
+version = "1.0.0"
+description = "Says hello"
+readme = "README.md"
+
+[[module]]
+name = "hello"
+source = "target/hello.wasm"
+abi = "wasi"
+
+[[command]]
+name = "hello"
+module = "hello"
+
+[fs]
+data = "data"
+"#,
+            ),
+            ("README.md", b"# hello"),
+            ("target/hello.wasm", b"\0asm\x01\0\0\0"),
+            ("data/greeting.txt", b"hello"),
+        ];
+        let mut builder = tar::Builder::new(vec![]);
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *contents).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn packages_round_trip_through_webc() {
+        let package = WebcPackage::from_tar(&package_tar()).unwrap();
+        assert_eq!(package.atoms["hello"], b"\0asm\x01\0\0\0");
+        assert_eq!(package.manifest.commands["hello"].runner, RUNNER_WASI);
+        assert_eq!(package.manifest.entrypoint.as_deref(), Some("hello"));
+        assert!(package.volumes[METADATA_VOLUME].contains_key("README.md"));
+        assert!(package.volumes[ATOM_VOLUME].contains_key("data/greeting.txt"));
+
+        let data = package.to_bytes().unwrap();
+        assert!(is_webc(&data));
+        let parsed = WebcPackage::parse(&data).unwrap();
+        assert_eq!(parsed, package);
+
+        let mut corrupted = data.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 1;
+        assert!(matches!(
+            WebcPackage::parse(&corrupted),
+            Err(WebcError::ChecksumMismatch)
+        ));
+        assert!(matches!(
+            WebcPackage::parse(&data[..HEADER_LENGTH - 1]),
+            Err(WebcError::Truncated)
+        ));
+
+        let mut paths = vec![];
+        let tar = parsed.to_tar().unwrap();
+        for entry in tar::Archive::new(&tar[..]).entries().unwrap() {
+            paths.push(entry.unwrap().path().unwrap().to_string_lossy().to_string());
+        }
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                "README.md",
+                "data/greeting.txt",
+                "target/hello.wasm",
+                "wapm.toml"
+            ]
+        );
+    }
+
+    #[test]
+    fn volumes_have_a_header_of_directory_levels() {
+        fn entry(kind: u64, name: &str, start: u64, end: u64) -> Vec<u8> {
+            let mut entry = (kind << 56 | name.len() as u64).to_le_bytes().to_vec();
+            entry.extend_from_slice(&start.to_le_bytes());
+            entry.extend_from_slice(&end.to_le_bytes());
+            entry.extend_from_slice(name.as_bytes());
+            entry
+        }
+        // the root level is 8 + 29 + 28 bytes long, and the level of `data` 8 + 29 bytes
+        let mut header = 57u64.to_le_bytes().to_vec();
+        header.extend(entry(ENTRY_FILE, "a.txt", 0, 2));
+        header.extend(entry(ENTRY_DIRECTORY, "data", 65, 102));
+        header.extend_from_slice(&29u64.to_le_bytes());
+        header.extend(entry(ENTRY_FILE, "b.txt", 2, 4));
+        let mut volume = (header.len() as u64).to_le_bytes().to_vec();
+        volume.extend_from_slice(&header);
+        volume.extend_from_slice(b"hiyo");
+
+        let mut files = BTreeMap::new();
+        files.insert("a.txt".to_string(), b"hi".to_vec());
+        files.insert("data/b.txt".to_string(), b"yo".to_vec());
+        assert_eq!(parse_volume(&volume).unwrap(), files);
+        assert_eq!(volume_bytes(&files).unwrap(), volume);
+
+        // a directory pointing back at the root level
+        let mut looping = volume.clone();
+        looping[8 + 8 + 29 + 8..8 + 8 + 29 + 16].copy_from_slice(&0u64.to_le_bytes());
+        assert!(parse_volume(&looping).is_err());
+        assert!(matches!(
+            parse_volume(&volume[..volume.len() - 1]),
+            Err(WebcError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn containers_without_a_wapm_toml_get_one() {
+        let mut package = WebcPackage::from_tar(&package_tar()).unwrap();
+        package.volumes.remove(METADATA_VOLUME);
+        let manifest = package.generate_manifest().unwrap();
+        assert_eq!(manifest.package.name, "_/hello");
+        assert_eq!(manifest.package.version, Version::new(1, 0, 0));
+        let modules = manifest.module.unwrap();
+        assert_eq!(modules[0].source, PathBuf::from("hello.wasm"));
+        assert_eq!(modules[0].abi, Abi::Wasi);
+        assert_eq!(manifest.command.unwrap()[0].module, "hello");
+    }
+}
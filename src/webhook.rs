@@ -0,0 +1,178 @@
+//! Notifying a webhook after a package is published, e.g. to announce releases in a Slack or
+//! Discord channel.
+//!
+//! The webhook is set with the `webhook.url` and `webhook.format` config keys, or in the
+//! `[webhook]` table of the install policy. Secrets such as tokens are kept out of the config by
+//! referring to environment variables in the url, like `${SLACK_WEBHOOK_TOKEN}`.
+
+use crate::config::{Config, Webhook, WebhookFormat};
+use crate::policy::Policy;
+use crate::registry;
+use regex::{Captures, Regex};
+use serde_json::json;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// The changelog excerpt is cut off after this many characters
+const MAX_CHANGELOG_EXCERPT_LENGTH: usize = 1000;
+
+#[derive(Debug, Fail)]
+pub enum WebhookError {
+    #[fail(
+        display = "The webhook url refers to the environment variable {}, which is not set",
+        _0
+    )]
+    EnvVarNotSet(String),
+    #[fail(display = "The webhook at {} failed: {}", _0, _1)]
+    RequestFailed(String, String),
+}
+
+/// A published package version to announce
+#[derive(Debug)]
+pub struct Notification {
+    pub package: String,
+    pub version: String,
+    pub changelog: Option<String>,
+}
+
+/// The webhook from the config, or else from the install policy
+pub fn configured_webhook() -> Option<Webhook> {
+    let from_config = Config::from_file()
+        .ok()
+        .map(|config| config.webhook)
+        .filter(|webhook| webhook.url.is_some());
+    from_config.or_else(|| {
+        Policy::load()
+            .ok()
+            .flatten()
+            .and_then(|policy| policy.webhook)
+            .filter(|webhook| webhook.url.is_some())
+    })
+}
+
+/// Send the notification to the configured webhook. Returns false if there is no webhook.
+pub fn notify(notification: &Notification) -> Result<bool, failure::Error> {
+    let webhook = match configured_webhook() {
+        Some(webhook) => webhook,
+        None => return Ok(false),
+    };
+    let url = expand_env_vars(webhook.url.as_deref().unwrap_or_default())?;
+    let payload = payload(webhook.format.unwrap_or(WebhookFormat::Json), notification);
+    // the url may contain secrets, so errors only mention where it goes
+    let host = url::Url::parse(&url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+        .unwrap_or_else(|| "the configured url".to_string());
+    registry::http_client()?
+        .post(&url)
+        .json(&payload)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| WebhookError::RequestFailed(host, e.to_string()))?;
+    Ok(true)
+}
+
+/// Replace `$NAME` and `${NAME}` with the values of environment variables
+fn expand_env_vars(url: &str) -> Result<String, WebhookError> {
+    let env_var = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}|\$([A-Za-z_][A-Za-z0-9_]*)").unwrap();
+    let mut missing = None;
+    let expanded = env_var.replace_all(url, |captures: &Captures| {
+        let name = captures
+            .get(1)
+            .or_else(|| captures.get(2))
+            .map(|name| name.as_str())
+            .unwrap_or_default();
+        env::var(name).unwrap_or_else(|_| {
+            missing = Some(name.to_string());
+            String::new()
+        })
+    });
+    match missing {
+        Some(name) => Err(WebhookError::EnvVarNotSet(name)),
+        None => Ok(expanded.to_string()),
+    }
+}
+
+fn payload(format: WebhookFormat, notification: &Notification) -> serde_json::Value {
+    let mut message = format!(
+        "Published {}@{}",
+        notification.package, notification.version
+    );
+    if let Some(changelog) = &notification.changelog {
+        message.push_str("\n\n");
+        message.push_str(changelog);
+    }
+    match format {
+        WebhookFormat::Slack => json!({ "text": message }),
+        WebhookFormat::Discord => json!({ "content": message }),
+        WebhookFormat::Json => json!({
+            "event": "package_published",
+            "package": notification.package,
+            "version": notification.version,
+            "changelog": notification.changelog,
+        }),
+    }
+}
+
+/// The section of the `CHANGELOG.md` in `directory` whose heading mentions `version`
+pub fn changelog_excerpt(directory: &Path, version: &str) -> Option<String> {
+    let changelog = fs::read_to_string(directory.join("CHANGELOG.md")).ok()?;
+    let mut lines = changelog.lines();
+    let heading = lines
+        .by_ref()
+        .find(|line| line.starts_with('#') && line.contains(version))?;
+    let level = heading.chars().take_while(|c| *c == '#').count();
+    let section: Vec<&str> = lines
+        .take_while(|line| {
+            let line_level = line.chars().take_while(|c| *c == '#').count();
+            line_level == 0 || line_level > level
+        })
+        .collect();
+    let mut excerpt = section.join("\n").trim().to_string();
+    if excerpt.is_empty() {
+        return None;
+    }
+    if excerpt.chars().count() > MAX_CHANGELOG_EXCERPT_LENGTH {
+        excerpt = excerpt
+            .chars()
+            .take(MAX_CHANGELOG_EXCERPT_LENGTH)
+            .collect::<String>()
+            + "…";
+    }
+    Some(excerpt)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn changelog_sections_are_excerpted() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        fs::write(
+            tmp_dir.path().join("CHANGELOG.md"),
+            "# Changelog\n\n## 1.1.0\n\n### Added\n- Faster queries\n\n## 1.0.0\n- First release\n",
+        )
+        .unwrap();
+        assert_eq!(
+            changelog_excerpt(tmp_dir.path(), "1.1.0").as_deref(),
+            Some("### Added\n- Faster queries")
+        );
+        assert_eq!(
+            changelog_excerpt(tmp_dir.path(), "1.0.0").as_deref(),
+            Some("- First release")
+        );
+        assert_eq!(changelog_excerpt(tmp_dir.path(), "2.0.0"), None);
+
+        env::set_var("WAPM_TEST_WEBHOOK_TOKEN", "secret");
+        assert_eq!(
+            expand_env_vars(
+                "https://example.com/${WAPM_TEST_WEBHOOK_TOKEN}/$WAPM_TEST_WEBHOOK_TOKEN"
+            )
+            .unwrap(),
+            "https://example.com/secret/secret"
+        );
+        assert!(expand_env_vars("https://example.com/$WAPM_TEST_WEBHOOK_UNSET").is_err());
+    }
+}